@@ -0,0 +1,59 @@
+//! Insert throughput of the event log, run with `cargo bench --bench storage`.
+//!
+//! `record_batch_100k` loads into an empty log, so it takes the bulk load
+//! path of `EventStorage::record_batch`: multi-row inserts, with the
+//! secondary indexes built once the rows are in.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use graphite::legacy::hlc::HLTimestamp;
+use graphite::legacy::storage::{Action, Datum, Event, EventCreator, EventStorage};
+use uuid::Uuid;
+
+const BATCH: usize = 100_000;
+
+fn events(n: usize) -> Vec<Event> {
+    let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+    (0..n)
+        .map(|i| {
+            creator.create(Action::AddFact {
+                subject: Uuid::new_v4(),
+                predicate: "count".to_string(),
+                datum: Datum::Integer(i as i64),
+            })
+        })
+        .collect()
+}
+
+fn record_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("storage");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("record_batch_100k", |b| {
+        b.iter_batched(
+            || (EventStorage::open(":memory:").unwrap(), events(BATCH)),
+            // Return the storage so closing it is not part of the measurement.
+            |(mut storage, events)| {
+                storage.record_batch(events).unwrap();
+                storage
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.throughput(Throughput::Elements(BATCH as u64 / 10));
+    group.bench_function("record_10k", |b| {
+        b.iter_batched(
+            || (EventStorage::open(":memory:").unwrap(), events(BATCH / 10)),
+            |(storage, events)| {
+                for event in events {
+                    storage.record(event).unwrap();
+                }
+                storage
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, record_batch);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+#![no_main]
+
+use graphite::legacy::storage::fuzz;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| fuzz::replay(data));
@@ -0,0 +1,422 @@
+app-title = Graphite
+language-name = Deutsch
+inspector-title = Graphite Inspektor
+entity-count = { $count ->
+    [one] 1 Entität
+   *[other] { $count } Entitäten
+}
+close = Schließen
+save = Speichern
+cancel = Abbrechen
+read-only = Der Stand { $name } ist schreibgeschützt
+read-only-database = Die Datenbank ist schreibgeschützt geöffnet
+
+# Settings
+settings = Einstellungen
+settings-theme = Design
+settings-language = Sprache
+settings-language-system = System
+settings-window = Fenster
+settings-database = Standarddatenbank
+settings-data-dir = Datenverzeichnis
+settings-data-dir-change = Ändern…
+settings-autosave = Automatisch speichern alle (Sekunden)
+settings-binding-conflict = Auch belegt durch { $commands }
+settings-keybindings = Tastenkürzel
+settings-no-config-dir = Es gibt kein Konfigurationsverzeichnis zum Speichern der Einstellungen
+window-windowed = Fenster
+window-maximized = Maximiert
+window-fullscreen = Vollbild
+command-journal-today = Heutiges Journal öffnen
+command-diagnostics = Diagnose
+command-settings = Einstellungen
+command-checkpoints = Stände
+command-backups = Sicherungen
+command-inspector = Inspektorfenster öffnen
+command-help = Tastenkürzel anzeigen
+command-console = Debug-Konsole anzeigen
+command-hygiene = Graph-Hygiene
+command-trash = Papierkorb
+command-duplicates = Duplikate finden
+command-new-entity = Neue Entität
+command-table = Tabelle
+command-board = Board
+command-calendar = Kalender
+command-canvas = Graph-Leinwand
+command-search = Zu Entität springen
+command-staging = Vormerken
+command-conflicts = Konflikte
+command-nearby = Geräte in der Nähe
+command-scripts = Skripte
+
+# Journal
+journal-today = Heute
+journal-weekdays = Mo Di Mi Do Fr Sa So
+journal-month = { $month ->
+    [1] Januar
+    [2] Februar
+    [3] März
+    [4] April
+    [5] Mai
+    [6] Juni
+    [7] Juli
+    [8] August
+    [9] September
+    [10] Oktober
+    [11] November
+   *[12] Dezember
+} { $year }
+journal-hint = Strg+J öffnet die heutige Notiz
+journal-empty = Noch nichts erwähnt
+journal-skip = Überspringen
+
+# Tasks
+tasks = Meine Aufgaben
+tasks-placeholder = Aufgabe hinzufügen: Sam anrufen due:friday !1
+tasks-empty = Nichts zu tun
+bucket-overdue = Überfällig
+bucket-today = Heute
+bucket-tomorrow = Morgen
+bucket-upcoming = Nächste 7 Tage
+bucket-later = Später
+bucket-no-date = Ohne Datum
+
+# Timer
+timer-tracking = Erfasse { $entity } seit { $since }
+timer-stop = Stopp
+timer-today = { $duration } heute erfasst
+
+# Diagnostics
+diagnostics = Diagnose
+diagnostics-checking = Datenbank wird geprüft…
+diagnostics-report = { $events } Ereignisse geprüft, { $problems } Probleme gefunden
+diagnostics-slow = Langsame Abfragen
+diagnostics-slow-none = Keine Anweisung war langsam.
+diagnostics-slow-entry = { $time }: { $millis } ms, { $rows } Zeilen
+diagnostics-repairs = { $quarantined } Ereignisse in Quarantäne, { $resequenced } neu sortiert und { $unlinked } verwaiste Verknüpfungen entfernt
+diagnostics-repair = Reparieren
+
+# Checkpoints
+checkpoints = Stände
+checkpoints-tag = Jetzt markieren
+checkpoints-empty = Noch keine Stände
+checkpoints-open = Öffnen
+checkpoints-remove = Entfernen
+checkpoints-diff = Vergleichen
+checkpoints-changes = { $count ->
+    [one] 1 Änderung
+   *[other] { $count } Änderungen
+}
+checkpoints-viewing = Stand „{ $name }“, schreibgeschützt
+checkpoints-return = Zurück zu jetzt
+
+# Inspector
+inspector-filter = Entitäten filtern
+inspector-export-pdf = Als PDF exportieren
+inspector-exported = Exportiert nach { $path }
+inspector-chart = Diagramm
+inspector-copy = Kopieren
+inspector-paste = Einfügen
+inspector-source = Quelle
+inspector-batch = Import { $id }
+inspector-edit-text = Bearbeiten
+inspector-close-text = Fertig
+
+# Selection
+selection-count = { $count } ausgewählt
+selection-clear = Auswahl aufheben
+selection-delete = Auswahl löschen
+selection-confirm-delete = { $count ->
+    [one] 1 Entität löschen
+   *[other] { $count } Entitäten löschen
+}
+selection-tag = Schlagwort
+selection-add-tag = Schlagwort hinzufügen
+selection-align-left = Links ausrichten
+selection-align-top = Oben ausrichten
+selection-distribute-horizontally = Horizontal verteilen
+selection-distribute-vertically = Vertikal verteilen
+selection-merge = Zusammenführen
+
+# Merge
+merge-into = „{ $duplicate }“ in „{ $keep }“ zusammenführen
+merge-copied = Übernimmt { $predicate }: { $value }
+merge-dropped = Verwirft { $predicate }: { $value }
+merge-references = { $count ->
+    [one] Verknüpft 1 Verweis neu
+   *[other] Verknüpft { $count } Verweise neu
+}
+merge-confirm = Zusammenführen
+merge-swap = Die andere behalten
+
+# Table
+table = Tabelle
+table-filter = Abfrage, z. B. type=person
+table-column = Prädikat
+table-add-column = Spalte hinzufügen
+
+# Board
+board = Board
+board-filter = Abfrage
+board-predicate = Prädikat
+board-no-predicate = Wähle das Prädikat, dessen Werte die Spalten sind.
+board-column = Wert
+board-add-column = Spalte hinzufügen
+board-none = Kein Wert
+
+# Calendar
+canvas = Graph
+canvas-fit = Alles zeigen
+canvas-fit-selection = Auswahl zeigen
+canvas-focus = Fokussieren
+canvas-unfocus = Alles zeigen
+canvas-collapse = Gruppen einklappen
+canvas-expand = Gruppen ausklappen
+canvas-arrange-circle = Im Kreis anordnen
+canvas-arrange-grid = Im Raster anordnen
+canvas-count = { $count } Entitäten
+canvas-styles = Stile
+canvas-all = Alle Entitäten
+canvas-view-name = Name der Ansicht
+canvas-filter = Filter, eine Abfrage
+canvas-rule-query = Abfrage
+canvas-rule-size = Größe
+canvas-rule-icon = Symbol
+canvas-add-rule = Regel hinzufügen
+canvas-save-view = Ansicht speichern
+canvas-layout-positions = An ihren Positionen
+canvas-layout-down = Hierarchie, von oben nach unten
+canvas-layout-up = Hierarchie, von unten nach oben
+canvas-layout-right = Hierarchie, von links nach rechts
+canvas-layout-left = Hierarchie, von rechts nach links
+canvas-layout-circular = Kreis
+canvas-layout-grid = Raster
+canvas-export = Exportieren
+canvas-export-view = Was zu sehen ist
+canvas-export-all = Der ganze Graph
+canvas-exported = Exportiert nach { $path }
+calendar = Kalender
+calendar-today = Heute
+calendar-predicate = Datumsprädikat
+
+# Search
+search = Zu Entität springen
+search-placeholder = Teil eines Namens eingeben
+search-none = Keine Entitäten gefunden
+search-hint = ↑ und ↓ zum Auswählen, Enter zum Öffnen, Esc zum Schließen, > am Anfang für Skripte
+
+# Staging
+staging-count = { $count ->
+    [one] 1 vorgemerkte Änderung, noch nicht gespeichert
+   *[other] { $count } vorgemerkte Änderungen, noch nicht gespeichert
+}
+staging-commit = Übernehmen
+staging-discard = Verwerfen
+staging-start = Änderungen vormerken
+staging-stop = Änderungen direkt speichern
+
+# Conflicts
+conflicts = Konflikte
+conflicts-count = { $count ->
+    [one] 1 Konflikt zwischen gleichzeitigen Änderungen
+   *[other] { $count } Konflikte zwischen gleichzeitigen Änderungen
+}
+conflicts-found = { $count ->
+    [one] 1 Konflikt mit lokalen Änderungen gefunden.
+   *[other] { $count } Konflikte mit lokalen Änderungen gefunden.
+}
+conflicts-none = Keine Konflikte
+conflicts-local = Meine
+conflicts-synced = Ihre ({ $origin })
+conflicts-no-value = (kein Wert)
+conflicts-keep-local = Meine behalten
+conflicts-keep-synced = Ihre behalten
+conflicts-keep-both = Beide behalten
+
+# Templates
+templates = Neue Entität
+templates-empty = Es gibt noch keine Vorlagen.
+templates-edit = Bearbeiten
+templates-new-template = Neue Vorlage
+templates-new = Neu: { $name }
+templates-create = Erstellen
+templates-name = Name
+templates-type = Typ der erstellten Entitäten
+templates-fields = Felder
+templates-field = Prädikat
+templates-add-field = Feld hinzufügen
+templates-delete = Vorlage löschen
+
+# Duplicates
+dedupe = Mögliche Duplikate
+dedupe-none = Keine möglichen Duplikate.
+dedupe-details = { $score } % ähnlich, { $shared } gemeinsame Verknüpfungen, { $dropped } widersprüchliche Fakten
+dedupe-merge = Zusammenführen
+dedupe-dismiss = Kein Duplikat
+
+# Validation
+validation-count = { $count ->
+    [one] 1 Entität verletzt eine Regel
+   *[other] { $count } Entitäten verletzen eine Regel
+}
+validation-required = { $predicate } fehlt
+validation-min = { $predicate } ist kleiner als { $min }
+validation-max = { $predicate } ist größer als { $max }
+validation-pattern = { $predicate } passt nicht zu { $pattern }
+validation-unique = { $predicate } ist derselbe wie bei { $other }
+validation-invalid-pattern = { $pattern } ist kein regulärer Ausdruck
+
+# Import
+import-drop = Zum Importieren loslassen
+import-progress = { $files } wird importiert…
+
+# Backups
+backups = Sicherungen
+backups-now = Jetzt sichern
+backups-running = Wird gesichert…
+backups-restoring = { $name } wird wiederhergestellt…
+backups-restore = Wiederherstellen
+backups-none = Keine Sicherungsziele eingerichtet
+backups-empty = Noch keine Sicherungen
+backups-copy = Jetzt lokal kopieren
+backups-local = Tägliche Kopien in { $path }
+backups-maintained = Datenbank gewartet
+backups-copied = Nach { $name } kopiert
+backups-done = Auf { $targets ->
+    [one] ein Ziel
+   *[other] { $targets } Ziele
+} gesichert
+backups-restored = { $events ->
+    [one] Ein Ereignis
+   *[other] { $events } Ereignisse
+} aus { $name } wiederhergestellt
+
+# Clock
+clock-behind = Die Systemuhr geht { $minutes ->
+    [one] eine Minute
+   *[other] { $minutes } Minuten
+} hinter der letzten Änderung.
+clock-continuing = Neue Änderungen werden danach eingeordnet, ihre Zeiten stimmen aber erst wieder, wenn die Uhr richtig geht.
+clock-paused = Änderungen sind angehalten, bis die Uhr richtig geht.
+
+# Nearby
+nearby = Geräte in der Nähe
+nearby-unnamed = Unbenanntes Gerät
+nearby-this-device = Dieses Gerät: { $device } ({ $actor })
+nearby-none = Noch keine anderen Geräte im Netzwerk gefunden
+nearby-sync = Synchronisieren
+nearby-syncing = Synchronisierung mit { $device }…
+nearby-waiting = Warte auf die Zustimmung von { $device }. Prüfe, dass dort der Code { $code } angezeigt wird.
+nearby-request = { $device } ({ $actor }) möchte synchronisieren
+nearby-code = Code: { $code }
+nearby-accept = Annehmen
+nearby-refuse = Ablehnen
+nearby-synced = Mit { $device } synchronisiert: { $pulled ->
+    [one] ein Ereignis
+   *[other] { $pulled } Ereignisse
+} empfangen, { $pushed ->
+    [one] ein Ereignis
+   *[other] { $pushed } Ereignisse
+} gesendet
+
+# Presence
+presence-here = { $device } ist da
+presence-viewing = { $device } sieht sich { $entity } an
+presence-pointer = Zeiger bei { $x }, { $y }
+
+# Operations
+operation-loading = Graph wird geladen
+operation-cancelling = Wird abgebrochen…
+
+# Charts
+chart-empty = Noch keine Zahlen erfasst
+chart-loading = Verlauf wird gelesen…
+
+# Dashboards
+dashboard-home = Start
+dashboard-empty = Noch keine Dashboards
+dashboard-create = Dashboard erstellen
+dashboard-unpinned = Die angeheftete Entität wurde gelöscht
+dashboard-unknown-view = Es gibt keine Plugin-Ansicht { $view }
+
+# Stats
+stats-totals = { $events } Ereignisse zu { $entities } Entitäten
+stats-size = { $size } MB auf der Festplatte
+stats-synced = Zuletzt synchronisiert { $time }
+stats-never-synced = Noch nie synchronisiert
+stats-per-actor = Ereignisse pro Akteur
+stats-per-predicate = Ereignisse pro Prädikat
+
+# Data directory
+data-dir = Datenverzeichnis
+data-dir-current = Graphite speichert seine Daten in { $dir }.
+data-dir-none = Auf dieser Plattform gibt es kein Verzeichnis für die Daten.
+data-dir-explain = Einstellungen, Datenbank, Plugins und Shell-Verlauf können auch in einem anderen Verzeichnis liegen, oder neben dem Programm, um es auf einem USB-Stick mitzunehmen. Sie werden kopiert, und ab dem nächsten Start werden die Kopien verwendet.
+data-dir-portable = Neben dem Programm
+data-dir-move = Hierher verschieben
+data-dir-moving = Die Daten werden kopiert…
+data-dir-moved = Die Daten wurden nach { $dir } kopiert. Starte Graphite neu, um sie zu verwenden.
+data-dir-keep = Hier behalten
+
+# Locks
+lock-take = Für eine Stunde sperren
+lock-release = Entsperren
+lock-held = { $entity } ist von { $owner } bis { $until } gesperrt
+lock-warning = Jemand anderes arbeitet an dem, was diese Änderung ändert.
+lock-edit-anyway = Trotzdem ändern
+
+# Locked
+locked = Ein anderes Graphite bearbeitet diese Datenbank.
+locked-by-process = Ein anderes Graphite (Prozess { $pid }) bearbeitet diese Datenbank.
+locked-explain = Sie wurde schreibgeschützt geöffnet, damit sich deine Änderungen nicht mit denen des anderen vermischen. Um sie zu bearbeiten, beende Graphite und schließe zuerst das andere.
+locked-read-only = Schreibgeschützt fortfahren
+locked-quit = Beenden
+
+# Scripts
+scripts = Skripte
+scripts-run = Ausführen
+scripts-done = Das Skript wurde ausgeführt.
+
+# Hygiene
+hygiene = Graph-Hygiene
+hygiene-clean = Nichts wurde zurückgelassen.
+hygiene-unreachable = { $count ->
+    [one] 1 Entität ist von den Wurzeln aus nicht erreichbar
+   *[other] { $count } Entitäten sind von den Wurzeln aus nicht erreichbar
+}
+hygiene-unlinked = { $count ->
+    [one] 1 Entität hat keine Verknüpfungen
+   *[other] { $count } Entitäten haben keine Verknüpfungen
+}
+hygiene-dangling = { $count ->
+    [one] 1 Verknüpfung zu einer gelöschten Entität
+   *[other] { $count } Verknüpfungen zu gelöschten Entitäten
+}
+hygiene-empty = { $count ->
+    [one] 1 Entität hat keine Fakten
+   *[other] { $count } Entitäten haben keine Fakten
+}
+hygiene-more = und { $count } weitere
+hygiene-link = Verknüpfen
+hygiene-delete = Alle löschen
+hygiene-ignore = Alle ignorieren
+hygiene-unlink = Verknüpfungen entfernen
+
+# Trash
+trash = Papierkorb
+trash-empty = Der Papierkorb ist leer.
+trash-window = { $days ->
+    [one] Gelöschte Entitäten können 1 Tag lang wiederhergestellt werden.
+   *[other] Gelöschte Entitäten können { $days } Tage lang wiederhergestellt werden.
+}
+trash-restore = Wiederherstellen
+
+# Console
+console = Debug-Konsole
+console-empty = Noch wurde nichts protokolliert. Mit --log-level debug protokolliert Graphite mehr.
+
+# Shortcuts
+shortcuts = Tastenkürzel
+shortcuts-conflict = { $binding } ist mehreren Befehlen zugewiesen: { $commands }
+shortcuts-script = Skript: { $name }
+shortcuts-remap = Sie lassen sich in den Einstellungen ändern.
@@ -0,0 +1,425 @@
+# The strings of the editor. Keep the message ids in sync with the other
+# locales; missing messages fall back to English.
+
+app-title = Graphite
+language-name = English
+inspector-title = Graphite Inspector
+entity-count = { $count ->
+    [one] 1 entity
+   *[other] { $count } entities
+}
+close = Close
+save = Save
+cancel = Cancel
+read-only = Checkpoint { $name } is read-only
+read-only-database = The database is open read-only
+
+# Settings
+settings = Settings
+settings-theme = Theme
+settings-language = Language
+settings-language-system = System
+settings-window = Window
+settings-database = Default database
+settings-data-dir = Data directory
+settings-data-dir-change = Change…
+settings-autosave = Autosave every (seconds)
+settings-binding-conflict = Also bound to { $commands }
+settings-keybindings = Keybindings
+settings-no-config-dir = There is no config directory to save settings to
+window-windowed = Windowed
+window-maximized = Maximized
+window-fullscreen = Fullscreen
+command-journal-today = Open today's journal
+command-diagnostics = Diagnostics
+command-settings = Settings
+command-checkpoints = Checkpoints
+command-backups = Backups
+command-inspector = Open an inspector window
+command-help = Show the keyboard shortcuts
+command-console = Show the debug console
+command-hygiene = Graph hygiene
+command-trash = Trash
+command-duplicates = Find duplicates
+command-new-entity = New entity
+command-table = Table
+command-board = Board
+command-calendar = Calendar
+command-canvas = Graph canvas
+command-search = Jump to entity
+command-staging = Staging
+command-conflicts = Conflicts
+command-nearby = Nearby devices
+command-scripts = Scripts
+
+# Journal
+journal-today = Today
+journal-weekdays = Mo Tu We Th Fr Sa Su
+journal-month = { $month ->
+    [1] January
+    [2] February
+    [3] March
+    [4] April
+    [5] May
+    [6] June
+    [7] July
+    [8] August
+    [9] September
+    [10] October
+    [11] November
+   *[12] December
+} { $year }
+journal-hint = Press Ctrl+J to open today's note
+journal-empty = Nothing mentioned yet
+journal-skip = Skip
+
+# Tasks
+tasks = My tasks
+tasks-placeholder = Add a task: Call Sam due:friday !1
+tasks-empty = Nothing to do
+bucket-overdue = Overdue
+bucket-today = Today
+bucket-tomorrow = Tomorrow
+bucket-upcoming = Next 7 days
+bucket-later = Later
+bucket-no-date = No date
+
+# Timer
+timer-tracking = Tracking { $entity } since { $since }
+timer-stop = Stop
+timer-today = { $duration } tracked today
+
+# Diagnostics
+diagnostics = Diagnostics
+diagnostics-checking = Checking the database…
+diagnostics-report = Checked { $events } events, found { $problems } problems
+diagnostics-slow = Slow queries
+diagnostics-slow-none = No statement was slow.
+diagnostics-slow-entry = { $time }: { $millis } ms, { $rows } rows
+diagnostics-repairs = Quarantined { $quarantined } events, re-sequenced { $resequenced } and removed { $unlinked } orphan links
+diagnostics-repair = Repair
+
+# Checkpoints
+checkpoints = Checkpoints
+checkpoints-tag = Tag now
+checkpoints-empty = No checkpoints yet
+checkpoints-open = Open
+checkpoints-remove = Remove
+checkpoints-diff = Diff
+checkpoints-changes = { $count ->
+    [one] 1 change
+   *[other] { $count } changes
+}
+checkpoints-viewing = Viewing checkpoint "{ $name }", read-only
+checkpoints-return = Back to now
+
+# Inspector
+inspector-filter = Filter entities
+inspector-export-pdf = Export as PDF
+inspector-exported = Exported to { $path }
+inspector-chart = Chart
+inspector-copy = Copy
+inspector-paste = Paste
+inspector-source = source
+inspector-batch = Import { $id }
+inspector-edit-text = Edit
+inspector-close-text = Done
+
+# Selection
+selection-count = { $count } selected
+selection-clear = Clear selection
+selection-delete = Delete selected
+selection-confirm-delete = { $count ->
+    [one] Delete 1 entity
+   *[other] Delete { $count } entities
+}
+selection-tag = Tag
+selection-add-tag = Add tag
+selection-align-left = Align left
+selection-align-top = Align top
+selection-distribute-horizontally = Distribute horizontally
+selection-distribute-vertically = Distribute vertically
+selection-merge = Merge
+
+# Merge
+merge-into = Merge “{ $duplicate }” into “{ $keep }”
+merge-copied = Copies { $predicate }: { $value }
+merge-dropped = Drops { $predicate }: { $value }
+merge-references = { $count ->
+    [one] Relinks 1 reference
+   *[other] Relinks { $count } references
+}
+merge-confirm = Merge
+merge-swap = Keep the other
+
+# Table
+table = Table
+table-filter = Query, e.g. type=person
+table-column = Predicate
+table-add-column = Add column
+
+# Board
+board = Board
+board-filter = Query
+board-predicate = Predicate
+board-no-predicate = Choose the predicate whose values are the columns.
+board-column = Value
+board-add-column = Add column
+board-none = No value
+
+# Calendar
+canvas = Graph
+canvas-fit = Show all
+canvas-fit-selection = Show selection
+canvas-focus = Focus
+canvas-unfocus = Show everything
+canvas-collapse = Collapse groups
+canvas-expand = Expand groups
+canvas-arrange-circle = Arrange in a circle
+canvas-arrange-grid = Arrange in a grid
+canvas-count = { $count } entities
+canvas-styles = Styles
+canvas-all = All entities
+canvas-view-name = Name of the view
+canvas-filter = Filter, a query
+canvas-rule-query = Query
+canvas-rule-size = Size
+canvas-rule-icon = Icon
+canvas-add-rule = Add rule
+canvas-save-view = Save view
+canvas-layout-positions = At their positions
+canvas-layout-down = Hierarchy, top down
+canvas-layout-up = Hierarchy, bottom up
+canvas-layout-right = Hierarchy, left to right
+canvas-layout-left = Hierarchy, right to left
+canvas-layout-circular = Circle
+canvas-layout-grid = Grid
+canvas-export = Export
+canvas-export-view = What is in view
+canvas-export-all = The whole graph
+canvas-exported = Exported to { $path }
+calendar = Calendar
+calendar-today = Today
+calendar-predicate = Date predicate
+
+# Search
+search = Jump to entity
+search-placeholder = Type part of a name
+search-none = No entities found
+search-hint = ↑ and ↓ to choose, Enter to open, Esc to close, > first for scripts
+
+# Staging
+staging-count = { $count ->
+    [one] 1 staged edit, not recorded yet
+   *[other] { $count } staged edits, not recorded yet
+}
+staging-commit = Commit
+staging-discard = Discard
+staging-start = Stage edits
+staging-stop = Record edits directly
+
+# Conflicts
+conflicts = Conflicts
+conflicts-count = { $count ->
+    [one] 1 conflict between concurrent edits
+   *[other] { $count } conflicts between concurrent edits
+}
+conflicts-found = { $count ->
+    [one] Found 1 conflict with local edits.
+   *[other] Found { $count } conflicts with local edits.
+}
+conflicts-none = No conflicts
+conflicts-local = Mine
+conflicts-synced = Theirs ({ $origin })
+conflicts-no-value = (no value)
+conflicts-keep-local = Keep mine
+conflicts-keep-synced = Keep theirs
+conflicts-keep-both = Keep both
+
+# Templates
+templates = New entity
+templates-empty = There are no templates yet.
+templates-edit = Edit
+templates-new-template = New template
+templates-new = New { $name }
+templates-create = Create
+templates-name = Name
+templates-type = Type of the entities created
+templates-fields = Fields
+templates-field = Predicate
+templates-add-field = Add field
+templates-delete = Delete template
+
+# Duplicates
+dedupe = Likely duplicates
+dedupe-none = No likely duplicates.
+dedupe-details = { $score } % alike, { $shared } shared links, { $dropped } conflicting facts
+dedupe-merge = Merge
+dedupe-dismiss = Not a duplicate
+
+# Validation
+validation-count = { $count ->
+    [one] 1 entity breaks a constraint
+   *[other] { $count } entities break a constraint
+}
+validation-required = { $predicate } is required
+validation-min = { $predicate } is less than { $min }
+validation-max = { $predicate } is more than { $max }
+validation-pattern = { $predicate } doesn't match { $pattern }
+validation-unique = { $predicate } is the same as for { $other }
+validation-invalid-pattern = { $pattern } isn't a regular expression
+
+# Import
+import-drop = Drop to import
+import-progress = Importing { $files }…
+
+# Backups
+backups = Backups
+backups-now = Back up now
+backups-running = Backing up…
+backups-restoring = Restoring { $name }…
+backups-restore = Restore
+backups-none = No backup targets configured
+backups-empty = No backups yet
+backups-copy = Copy locally now
+backups-local = Daily copies in { $path }
+backups-maintained = Maintained the database
+backups-copied = Copied to { $name }
+backups-done = Backed up to { $targets ->
+    [one] one target
+   *[other] { $targets } targets
+}
+backups-restored = Restored { $events ->
+    [one] one event
+   *[other] { $events } events
+} from { $name }
+
+# Clock
+clock-behind = The system clock is { $minutes ->
+    [one] a minute
+   *[other] { $minutes } minutes
+} behind the latest edit.
+clock-continuing = New edits are ordered after it, but their times are off until the clock is corrected.
+clock-paused = Edits are paused until the clock is corrected.
+
+# Nearby
+nearby = Nearby devices
+nearby-unnamed = Unnamed device
+nearby-this-device = This device: { $device } ({ $actor })
+nearby-none = No other devices found on the network yet
+nearby-sync = Sync
+nearby-syncing = Syncing with { $device }…
+nearby-waiting = Waiting for { $device } to accept. Check that it shows the code { $code }.
+nearby-request = { $device } ({ $actor }) asks to sync
+nearby-code = Code: { $code }
+nearby-accept = Accept
+nearby-refuse = Refuse
+nearby-synced = Synced with { $device }: { $pulled ->
+    [one] one event
+   *[other] { $pulled } events
+} received, { $pushed ->
+    [one] one event
+   *[other] { $pushed } events
+} sent
+
+# Presence
+presence-here = { $device } is here
+presence-viewing = { $device } is looking at { $entity }
+presence-pointer = pointer at { $x }, { $y }
+
+# Operations
+operation-loading = Loading the graph
+operation-cancelling = Cancelling…
+
+# Charts
+chart-empty = No numbers recorded yet
+chart-loading = Reading the history…
+
+# Dashboards
+dashboard-home = Home
+dashboard-empty = No dashboards yet
+dashboard-create = Create a dashboard
+dashboard-unpinned = The pinned entity was deleted
+dashboard-unknown-view = There is no plugin view { $view }
+
+# Stats
+stats-totals = { $events } events on { $entities } entities
+stats-size = { $size } MB on disk
+stats-synced = Last synced { $time }
+stats-never-synced = Never synced
+stats-per-actor = Events per actor
+stats-per-predicate = Events per predicate
+
+# Data directory
+data-dir = Data directory
+data-dir-current = Graphite keeps its data in { $dir }.
+data-dir-none = There is no directory for the data on this platform.
+data-dir-explain = Its config, database, plugins and shell history can live in another directory instead, or next to the program to carry it around on a USB stick. They are copied, and the copies are used from the next start on.
+data-dir-portable = Next to the program
+data-dir-move = Move here
+data-dir-moving = Copying the data…
+data-dir-moved = The data was copied to { $dir }. Restart Graphite to use it.
+data-dir-keep = Keep it here
+
+# Locks
+lock-take = Lock for an hour
+lock-release = Unlock
+lock-held = { $entity } is locked by { $owner } until { $until }
+lock-warning = Someone else is working on what this edit changes.
+lock-edit-anyway = Edit anyway
+
+# Locked
+locked = Another Graphite is editing this database.
+locked-by-process = Another Graphite (process { $pid }) is editing this database.
+locked-explain = It was opened read-only, so you can look at it without your edits interleaving with the other one's. To edit it, quit and close the other Graphite first.
+locked-read-only = Continue read-only
+locked-quit = Quit
+
+# Scripts
+scripts = Scripts
+scripts-run = Run
+scripts-done = The script ran.
+
+# Hygiene
+hygiene = Graph hygiene
+hygiene-clean = Nothing was left behind.
+hygiene-unreachable = { $count ->
+    [one] 1 entity can't be reached from the roots
+   *[other] { $count } entities can't be reached from the roots
+}
+hygiene-unlinked = { $count ->
+    [one] 1 entity has no links
+   *[other] { $count } entities have no links
+}
+hygiene-dangling = { $count ->
+    [one] 1 link to a deleted entity
+   *[other] { $count } links to deleted entities
+}
+hygiene-empty = { $count ->
+    [one] 1 entity has no facts
+   *[other] { $count } entities have no facts
+}
+hygiene-more = and { $count } more
+hygiene-link = Link
+hygiene-delete = Delete all
+hygiene-ignore = Ignore all
+hygiene-unlink = Remove the links
+
+# Trash
+trash = Trash
+trash-empty = The trash is empty.
+trash-window = { $days ->
+    [one] Deleted entities can be restored for 1 day.
+   *[other] Deleted entities can be restored for { $days } days.
+}
+trash-restore = Restore
+
+# Console
+console = Debug console
+console-empty = Nothing was logged yet. Start Graphite with --log-level debug to log more.
+
+# Shortcuts
+shortcuts = Keyboard shortcuts
+shortcuts-conflict = { $binding } is bound to several commands: { $commands }
+shortcuts-script = Script: { $name }
+shortcuts-remap = Change them in the settings.
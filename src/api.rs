@@ -0,0 +1,506 @@
+//! An HTTP API for automations outside the editor, such as scripts, phone
+//! shortcuts and browser extensions.
+//!
+//! Every request carries an `Authorization: Bearer <token>` header with the
+//! token of the config. Bodies and answers are JSON:
+//!
+//! - `GET /entities/<id>`: the facts of an entity.
+//! - `GET /query?q=<query>`: the entities matching a query of
+//!   [`crate::query`], with their facts.
+//! - `GET /search?q=<text>&limit=<n>`: the entities found by
+//!   [`crate::search`], best first.
+//! - `POST /actions`: records `{"actions": [...], "source": "..."}` as one
+//!   event, the actions as in the event log, e.g.
+//!   `{"AddFact": {"subject": "…", "predicate": "done", "datum": {"Boolean": true}}}`.
+//! - `POST /clip`: bookmarks a page clipped in a browser, see
+//!   [`crate::clipper`], and answers the id of its bookmark.
+//! - `POST /graphql`: runs `{"query": "...", "variables": {...}}`, see
+//!   [`crate::graphql`]. `GET /graphql?query=<query>` runs one too, and
+//!   without a query answers `{"schema": "..."}`, the schema of the graph.
+//!
+//! Browsers may call the API from any page, for extensions to clip them.
+//!
+//! Reads are answered from a projection replayed once and then kept current
+//! with the events recorded through storage, so requests don't replay the
+//! log. The events of the API are created by the creator of the editor, so
+//! the two never hand out the same timestamp.
+//!
+//! The server is tiny_http on a thread of its own, like the relay and LAN
+//! sync, rather than an async framework the rest of the crate doesn't use.
+//!
+//! The server runs beside the editor while `enabled` in the config, or alone
+//! with `graphite serve`:
+//!
+//! ```toml
+//! [api]
+//! enabled = true
+//! address = "127.0.0.1:7878"
+//! token = "…"
+//! ```
+
+use crate::clipper::{self, constant_time_eq, Clip};
+use crate::graphql;
+use crate::import::rdf::percent_decode;
+use crate::legacy::async_storage::AsyncStorage;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{metadata, Action, Datum, Event, EventCreator};
+use crate::search::{self, Recency};
+use anyhow::{anyhow, bail, Context, Result};
+use futures::executor::block_on;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Request, Response, Server};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+use uuid::Uuid;
+
+/// How many bytes of a request body are read.
+const MAX_BODY: u64 = 16 * 1024 * 1024;
+/// How many search results are answered unless a limit is given.
+const SEARCH_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Whether the editor serves the API while it runs.
+    pub enabled: bool,
+    pub address: String,
+    /// The token requests must carry. Without one nothing is served.
+    pub token: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            enabled: false,
+            address: "127.0.0.1:7878".to_string(),
+            token: None,
+        }
+    }
+}
+
+/// The body of `POST /actions`.
+#[derive(Debug, Deserialize)]
+struct Write {
+    actions: Vec<Action>,
+    /// Who sent the actions, recorded as their import source.
+    source: Option<String>,
+}
+
+/// The body of `POST /graphql`.
+#[derive(Debug, Deserialize)]
+struct GraphQl {
+    query: String,
+    #[serde(default)]
+    variables: Map<String, Value>,
+}
+
+/// Answers the requests of the API, reading from and recording to storage.
+pub struct Api {
+    token: String,
+    storage: AsyncStorage,
+    creator: Arc<Mutex<EventCreator>>,
+    /// Replayed on the first read.
+    live: Mutex<Option<Live>>,
+}
+
+/// The graph as recorded, and the events recorded since it was replayed.
+struct Live {
+    projection: Projection,
+    recency: Recency,
+    feed: broadcast::Receiver<Event>,
+}
+
+impl Live {
+    /// Applies the events recorded since, or returns `false` if some were
+    /// missed.
+    fn catch_up(&mut self) -> bool {
+        loop {
+            match self.feed.try_recv() {
+                Ok(event) => {
+                    self.projection.apply_event(&event);
+                    self.recency.record(&event);
+                }
+                Err(TryRecvError::Lagged(_)) => return false,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return true,
+            }
+        }
+    }
+}
+
+impl Api {
+    pub fn new(token: String, storage: AsyncStorage, creator: Arc<Mutex<EventCreator>>) -> Api {
+        Api {
+            token,
+            storage,
+            creator,
+            live: Mutex::new(None),
+        }
+    }
+
+    /// The API of `settings`, or why it can't be served.
+    pub fn from_settings(
+        settings: &Settings,
+        storage: AsyncStorage,
+        creator: Arc<Mutex<EventCreator>>,
+    ) -> Result<Api> {
+        let Some(token) = settings.token.clone().filter(|t| !t.is_empty()) else {
+            bail!(
+                "Set a token in the [api] table of the config first, e.g. token = \"{}\"",
+                crate::clipper::generate_token()
+            );
+        };
+        Ok(Api::new(token, storage, creator))
+    }
+
+    /// Returns the status code and body to answer a request with.
+    pub fn handle(
+        &self,
+        method: &str,
+        url: &str,
+        authorization: Option<&str>,
+        body: &str,
+    ) -> (u16, String) {
+        // Browsers ask before sending the token from a page.
+        if method == "OPTIONS" {
+            return (204, String::new());
+        }
+        let token = authorization.and_then(|a| a.strip_prefix("Bearer "));
+        if !token.is_some_and(|t| constant_time_eq(t.as_bytes(), self.token.as_bytes())) {
+            return (401, "Invalid token".into());
+        }
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let params: BTreeMap<String, String> = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.to_string(), percent_decode(&value.replace('+', " "))))
+            .collect();
+        let answer = match (method, path) {
+            ("GET", path) if path.starts_with("/entities/") => {
+                self.entity(&path["/entities/".len()..])
+            }
+            ("GET", "/query") => self.query(params.get("q").map_or("", String::as_str)),
+            ("GET", "/search") => self.search(&params),
+            ("POST", "/actions") => self.write(body),
+            ("POST", "/clip") => self.clip(body),
+            ("GET", "/graphql") => match params.get("query") {
+                Some(query) => self.graphql(query, &Map::new()),
+                None => self
+                    .read(|projection, _| (200, json!({ "schema": graphql::schema(projection) }))),
+            },
+            ("POST", "/graphql") => serde_json::from_str::<GraphQl>(body)
+                .context("Invalid GraphQL request")
+                .and_then(|request| self.graphql(&request.query, &request.variables)),
+            (_, "/query" | "/search" | "/actions" | "/clip" | "/graphql") => {
+                return (405, "Method not allowed".into())
+            }
+            (_, path) if path.starts_with("/entities/") => {
+                return (405, "Method not allowed".into())
+            }
+            _ => return (404, "Not found".into()),
+        };
+        match answer {
+            Ok((status, value)) => (status, value.to_string()),
+            Err(e) => (400, format!("{:#}", e)),
+        }
+    }
+
+    /// Calls `read` with the graph as recorded, replaying it only the first
+    /// time and after missing events.
+    fn read<T>(&self, read: impl FnOnce(&Projection, &Recency) -> T) -> Result<T> {
+        let mut guard = self
+            .live
+            .lock()
+            .map_err(|_| anyhow!("The graph is poisoned"))?;
+        let live = match guard.take() {
+            Some(mut live) => match live.catch_up() {
+                true => live,
+                false => self.replay()?,
+            },
+            None => self.replay()?,
+        };
+        let answer = read(&live.projection, &live.recency);
+        *guard = Some(live);
+        Ok(answer)
+    }
+
+    /// Replays the log, subscribing on the storage thread so that no event
+    /// is missed or applied twice.
+    fn replay(&self) -> Result<Live> {
+        let storage = self.storage.clone();
+        block_on(self.storage.call(move |s| -> Result<_> {
+            let feed = storage.subscribe();
+            let (mut projection, mut recency) = (Projection::new(), Recency::default());
+            s.play(|event| {
+                projection.apply_event(&event);
+                recency.record(&event);
+                Ok(())
+            })?;
+            Ok(Live {
+                projection,
+                recency,
+                feed,
+            })
+        }))
+    }
+
+    fn entity(&self, id: &str) -> Result<(u16, Value)> {
+        let id: Uuid = id.parse().with_context(|| format!("Invalid id {}", id))?;
+        self.read(|projection, _| match facts(projection, id) {
+            Some(facts) => (200, json!({ "id": id, "facts": facts })),
+            None => (404, json!(format!("There is no entity {}", id))),
+        })
+    }
+
+    fn query(&self, query: &str) -> Result<(u16, Value)> {
+        let query: crate::query::Query = query.parse()?;
+        self.read(|projection, _| {
+            let mut ids = query.run(projection);
+            ids.sort();
+            let entities: Vec<_> = ids
+                .into_iter()
+                .map(|id| json!({ "id": id, "facts": facts(projection, id) }))
+                .collect();
+            (200, json!(entities))
+        })
+    }
+
+    fn search(&self, params: &BTreeMap<String, String>) -> Result<(u16, Value)> {
+        let text = params.get("q").map_or("", String::as_str);
+        let limit = match params.get("limit") {
+            Some(limit) => limit.parse().context("Invalid limit")?,
+            None => SEARCH_LIMIT,
+        };
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        self.read(|projection, recency| {
+            let hits: Vec<_> = search::search(projection, recency, text, now, limit)
+                .into_iter()
+                .map(|hit| json!({ "id": hit.id, "score": hit.score, "matched": hit.matched }))
+                .collect();
+            (200, json!(hits))
+        })
+    }
+
+    fn write(&self, body: &str) -> Result<(u16, Value)> {
+        let write: Write = serde_json::from_str(body).context("Invalid actions")?;
+        if write.actions.is_empty() {
+            return Ok((200, json!({ "recorded": 0 })));
+        }
+        let count = write.actions.len();
+        let source = write.source.unwrap_or_else(|| "api".to_string());
+        self.record(write.actions, source)?;
+        Ok((201, json!({ "recorded": count })))
+    }
+
+    fn graphql(&self, query: &str, variables: &Map<String, Value>) -> Result<(u16, Value)> {
+        self.read(|projection, _| (200, graphql::execute(projection, query, variables)))
+    }
+
+    fn clip(&self, body: &str) -> Result<(u16, Value)> {
+        let clip: Clip = serde_json::from_str(body).context("Invalid clip")?;
+        let (bookmark, actions) = self.read(|projection, _| {
+            let bookmark = clipper::find_bookmark(projection, &clip.url);
+            clipper::actions(projection, &clip).map(|actions| (bookmark, actions))
+        })??;
+        if !actions.is_empty() {
+            self.record(actions, clip.url)?;
+        }
+        Ok((201, json!({ "bookmark": bookmark })))
+    }
+
+    /// Records `actions` as one event from `source`.
+    fn record(&self, actions: Vec<Action>, source: String) -> Result<()> {
+        let event = self
+            .creator
+            .lock()
+            .map_err(|_| anyhow!("The event creator is poisoned"))?
+            .create(Action::Transaction { actions })
+            .with_metadata(metadata::IMPORT_SOURCE, source);
+        Ok(block_on(self.storage.record(event))?)
+    }
+
+    fn answer(&self, request: &mut Request) -> (u16, String) {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .map(|h| h.value.to_string());
+        let mut body = String::new();
+        if request
+            .as_reader()
+            .take(MAX_BODY)
+            .read_to_string(&mut body)
+            .is_err()
+        {
+            return (400, "Body is not UTF-8".into());
+        }
+        self.handle(
+            request.method().as_str(),
+            request.url(),
+            authorization.as_deref(),
+            &body,
+        )
+    }
+
+    /// Serves requests on `address` from a background thread.
+    pub fn serve(self, address: impl ToSocketAddrs) -> Result<ApiServer> {
+        let server = Server::http(address).map_err(|e| anyhow!("Failed to listen: {}", e))?;
+        let server = Arc::new(server);
+        let address = server
+            .server_addr()
+            .to_ip()
+            .context("The API is not listening on an IP address")?;
+        let listener = server.clone();
+        let thread = thread::Builder::new()
+            .name("graphite-api".into())
+            .spawn(move || {
+                for mut request in listener.incoming_requests() {
+                    let (status, body) = self.answer(&mut request);
+                    let mut response = Response::from_string(body).with_status_code(status);
+                    // Errors are plain text.
+                    let json = ("Content-Type", "application/json");
+                    let headers = [
+                        ("Access-Control-Allow-Origin", "*"),
+                        (
+                            "Access-Control-Allow-Headers",
+                            "Authorization, Content-Type",
+                        ),
+                        ("Access-Control-Allow-Methods", "GET, POST"),
+                    ];
+                    let json = (status < 300).then_some(json);
+                    for (field, value) in headers.into_iter().chain(json) {
+                        if let Ok(header) = Header::from_bytes(field, value) {
+                            response.add_header(header);
+                        }
+                    }
+                    let _ = request.respond(response);
+                }
+            })
+            .context("Failed to spawn the API thread")?;
+        Ok(ApiServer {
+            server,
+            address,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// The facts of the entity `id`, by predicate.
+fn facts(projection: &Projection, id: Uuid) -> Option<BTreeMap<&str, &[Datum]>> {
+    Some(projection.entity(&id)?.facts().collect())
+}
+
+/// A running API server, stopped when dropped.
+pub struct ApiServer {
+    server: Arc<Server>,
+    address: SocketAddr,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ApiServer {
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Blocks until the server stops.
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+
+    #[test]
+    fn reads_and_writes_with_a_token() {
+        let storage = AsyncStorage::open(":memory:").unwrap();
+        let creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let creator = Arc::new(Mutex::new(creator));
+        let server = Api::new("secret".into(), storage.clone(), creator.clone())
+            .serve("127.0.0.1:0")
+            .unwrap();
+        let url = |path: &str| format!("http://{}{}", server.address(), path);
+        let get = |path: &str| {
+            ureq::get(&url(path))
+                .set("Authorization", "Bearer secret")
+                .call()
+                .unwrap()
+                .into_string()
+                .unwrap()
+                .parse::<Value>()
+                .unwrap()
+        };
+
+        let id = Uuid::new_v4();
+        let body = json!({
+            "actions": [
+                { "CreateEntity": { "id": id } },
+                { "AddFact": { "subject": id, "predicate": "name", "datum": { "String": "Big project" } } },
+            ],
+            "source": "shortcut",
+        })
+        .to_string();
+        let unauthorized = ureq::post(&url("/actions")).send_string(&body);
+        assert!(matches!(unauthorized, Err(ureq::Error::Status(401, _))));
+        let response = ureq::post(&url("/actions"))
+            .set("Authorization", "Bearer secret")
+            .send_string(&body)
+            .unwrap();
+        assert_eq!(response.status(), 201);
+
+        let entity = get(&format!("/entities/{}", id));
+        assert_eq!(entity["facts"]["name"][0]["String"], "Big project");
+
+        // What the editor records is read too.
+        let edit = creator.lock().unwrap().create(Action::AddFact {
+            subject: id,
+            predicate: "done".to_string(),
+            datum: Datum::Boolean(true),
+        });
+        block_on(storage.record(edit)).unwrap();
+        let entity = get(&format!("/entities/{}", id));
+        assert_eq!(entity["facts"]["done"][0]["Boolean"], true);
+        let found = get("/query?q=name=%22Big+project%22");
+        assert_eq!(found[0]["id"], id.to_string());
+        let hits = get("/search?q=big&limit=1");
+        assert_eq!(hits[0]["id"], id.to_string());
+
+        let clip = json!({ "url": "https://example.com/a", "tags": ["rust"], "note": "Later" });
+        let clipped = ureq::post(&url("/clip"))
+            .set("Authorization", "Bearer secret")
+            .send_string(&clip.to_string())
+            .unwrap()
+            .into_string()
+            .unwrap();
+        let bookmark: Value = clipped.parse().unwrap();
+        let bookmark = get(&format!(
+            "/entities/{}",
+            bookmark["bookmark"].as_str().unwrap()
+        ));
+        assert_eq!(bookmark["facts"]["note"][0]["String"], "Later");
+
+        let named = get("/graphql?query=%7Bentities(query%3A%22tag%3Drust%22)%7Bnote%7D%7D");
+        assert_eq!(named["data"]["entities"][0]["note"][0], "Later");
+        let missing = ureq::get(&url(&format!("/entities/{}", Uuid::new_v4())))
+            .set("Authorization", "Bearer secret")
+            .call();
+        assert!(matches!(missing, Err(ureq::Error::Status(404, _))));
+    }
+}
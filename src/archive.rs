@@ -0,0 +1,371 @@
+//! `.graphite` archives: a whole graph in one file, for moving or sharing
+//! it.
+//!
+//! An archive is a gzip-compressed sequence of named entries:
+//!
+//! - `manifest.json`, the format version and what the archive holds,
+//! - `snapshot.json`, the facts of every entity, so the graph can be read
+//!   without replaying the log,
+//! - `events.jsonl`, the event log, one event per line, and
+//! - `files/<n>-<name>`, the local files the graph refers to through asset
+//!   predicates such as `image`.
+//!
+//! Each entry is its name and its contents, each preceded by its length as
+//! a big-endian integer.
+//!
+//! Importing into an empty database records the events as they are, keeping
+//! the history and the ids. Importing into a graph that already has events
+//! records the snapshot instead, with every entity given a new id, so two
+//! graphs that share an origin don't get mixed up. The files are written to
+//! a directory of their own in both cases and the facts pointing to them
+//! are updated.
+
+use crate::export::site;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{metadata, Action, Datum, Event, EventStorage};
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::instrument;
+use uuid::Uuid;
+
+pub const EXTENSION: &str = "graphite";
+
+const MAGIC: &[u8] = b"GRAPHITE-ARCHIVE\n";
+const VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const SNAPSHOT: &str = "snapshot.json";
+const EVENTS: &str = "events.jsonl";
+const FILES: &str = "files/";
+
+/// The facts of every entity, by predicate.
+type Snapshot = BTreeMap<Uuid, BTreeMap<String, Vec<Datum>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// When the archive was written, in unix seconds.
+    created: i64,
+    events: usize,
+    entities: usize,
+    files: Vec<ArchivedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ArchivedFile {
+    /// The entry holding it.
+    entry: String,
+    /// The path the graph refers to it by.
+    path: String,
+}
+
+/// What an export or import covered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub events: usize,
+    pub entities: usize,
+    pub files: usize,
+    /// Whether the entities were given new ids, see the module docs.
+    pub remapped: bool,
+}
+
+/// Writes the graph in `storage` and the files it refers to to an archive
+/// at `path`.
+#[instrument(skip(storage))]
+pub fn export_archive(storage: &EventStorage, path: &Path) -> Result<Summary> {
+    let projection = Projection::load(storage)?;
+    let mut events = Vec::new();
+    storage.play(|event| {
+        serde_json::to_writer(&mut events, &event).expect("events serialize to JSON");
+        events.push(b'\n');
+        Ok(())
+    })?;
+    let snapshot: Snapshot = projection
+        .entities()
+        .map(|(id, entity)| {
+            let facts = entity
+                .facts()
+                .map(|(predicate, values)| (predicate.to_string(), values.to_vec()))
+                .collect();
+            (*id, facts)
+        })
+        .collect();
+
+    let mut files = Vec::new();
+    for asset in assets(&snapshot) {
+        if Path::new(asset).is_file() && !files.iter().any(|f: &ArchivedFile| f.path == asset) {
+            let name = Path::new(asset)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            files.push(ArchivedFile {
+                entry: format!("{}{}-{}", FILES, files.len(), name),
+                path: asset.to_string(),
+            });
+        }
+    }
+    let manifest = Manifest {
+        version: VERSION,
+        created: time::OffsetDateTime::now_utc().unix_timestamp(),
+        events: events.iter().filter(|b| **b == b'\n').count(),
+        entities: snapshot.len(),
+        files,
+    };
+
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut out = GzEncoder::new(BufWriter::new(file), flate2::Compression::default());
+    let written = (|| -> Result<()> {
+        out.write_all(MAGIC)?;
+        write_entry(&mut out, MANIFEST, &serde_json::to_vec(&manifest)?)?;
+        write_entry(&mut out, SNAPSHOT, &serde_json::to_vec(&snapshot)?)?;
+        write_entry(&mut out, EVENTS, &events)?;
+        for file in &manifest.files {
+            let contents = std::fs::read(&file.path)
+                .with_context(|| format!("Failed to read {}", file.path))?;
+            write_entry(&mut out, &file.entry, &contents)?;
+        }
+        out.finish()?.flush()?;
+        Ok(())
+    })();
+    written.with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(Summary {
+        events: manifest.events,
+        entities: manifest.entities,
+        files: manifest.files.len(),
+        remapped: false,
+    })
+}
+
+/// Records the graph of the archive at `path` in `storage`, writing its
+/// files to a directory in `files`.
+#[instrument(skip(storage))]
+pub fn import_archive(storage: &mut EventStorage, path: &Path, files: &Path) -> Result<Summary> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut entries = read_entries(BufReader::new(GzDecoder::new(BufReader::new(file))))
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut take = |name: &str| {
+        entries
+            .remove(name)
+            .with_context(|| format!("{} lacks {}", path.display(), name))
+    };
+    let manifest: Manifest = serde_json::from_slice(&take(MANIFEST)?)?;
+    if manifest.version > VERSION {
+        bail!(
+            "{} is an archive of version {}, this build reads up to {}",
+            path.display(),
+            manifest.version,
+            VERSION
+        );
+    }
+    let snapshot: Snapshot = serde_json::from_slice(&take(SNAPSHOT)?)?;
+    let events = take(EVENTS)?
+        .lines()
+        .map(|line| Ok(serde_json::from_str::<Event>(&line?)?))
+        .collect::<Result<Vec<Event>>>()?;
+
+    // A directory per import, so files of the same name don't collide.
+    let dir = files.join(Uuid::new_v4().simple().to_string());
+    let mut paths = HashMap::new();
+    for file in &manifest.files {
+        let contents = take(&file.entry)?;
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        // Only the file name, so an archive can't write outside `dir`.
+        let name = Path::new(&file.entry)
+            .file_name()
+            .with_context(|| format!("{} names a file without a name", path.display()))?;
+        let target: PathBuf = dir.join(name);
+        std::fs::write(&target, contents)
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+        paths.insert(file.path.clone(), target.to_string_lossy().into_owned());
+    }
+
+    let remapped = storage.count()? > 0;
+    let recorded = if remapped { 0 } else { events.len() };
+    let actions = if remapped {
+        let ids: HashMap<Uuid, Uuid> = snapshot.keys().map(|id| (*id, Uuid::new_v4())).collect();
+        let mut actions = Vec::new();
+        for (id, facts) in &snapshot {
+            actions.push(Action::CreateEntity { id: ids[id] });
+            for (predicate, values) in facts {
+                actions.extend(values.iter().map(|datum| Action::AddFact {
+                    subject: ids[id],
+                    predicate: predicate.clone(),
+                    datum: rewrite(datum, &ids, &paths),
+                }));
+            }
+        }
+        actions
+    } else {
+        let origin = path.display().to_string();
+        let events = events
+            .into_iter()
+            .map(|event| event.synced_from(&origin))
+            .collect();
+        storage.merge(events)?;
+        relocate(&snapshot, &paths)
+    };
+    if !actions.is_empty() {
+        let mut creator = storage.creator()?;
+        creator.set_metadata(metadata::IMPORT_SOURCE, path.display().to_string());
+        storage.record_batch(creator.transaction(actions))?;
+    }
+    Ok(Summary {
+        events: recorded,
+        entities: snapshot.len(),
+        files: paths.len(),
+        remapped,
+    })
+}
+
+/// The paths the facts of asset predicates refer to.
+fn assets(snapshot: &Snapshot) -> impl Iterator<Item = &str> {
+    let predicates = site::Options::default().asset_predicates;
+    snapshot
+        .values()
+        .flat_map(|facts| facts.iter())
+        .filter(move |(predicate, _)| predicates.contains(predicate))
+        .flat_map(|(_, values)| values)
+        .filter_map(|datum| match datum {
+            Datum::String(path) => Some(path.as_str()),
+            _ => None,
+        })
+}
+
+/// `datum` with the entity it links to and the file it names replaced by
+/// their imported counterparts.
+fn rewrite(datum: &Datum, ids: &HashMap<Uuid, Uuid>, paths: &HashMap<String, String>) -> Datum {
+    match datum {
+        Datum::Entity(id) => Datum::Entity(*ids.get(id).unwrap_or(id)),
+        Datum::String(path) => Datum::String(paths.get(path).unwrap_or(path).clone()),
+        datum => datum.clone(),
+    }
+}
+
+/// The actions that point the facts naming archived files to where they
+/// were written.
+fn relocate(snapshot: &Snapshot, paths: &HashMap<String, String>) -> Vec<Action> {
+    let mut actions = Vec::new();
+    for (id, facts) in snapshot {
+        for (predicate, values) in facts {
+            let moved = values
+                .iter()
+                .any(|datum| matches!(datum, Datum::String(path) if paths.contains_key(path)));
+            if !moved {
+                continue;
+            }
+            actions.push(Action::RemoveFact {
+                subject: *id,
+                predicate: predicate.clone(),
+            });
+            actions.extend(values.iter().map(|datum| Action::AddFact {
+                subject: *id,
+                predicate: predicate.clone(),
+                datum: rewrite(datum, &HashMap::new(), paths),
+            }));
+        }
+    }
+    actions
+}
+
+fn write_entry(out: &mut impl Write, name: &str, contents: &[u8]) -> Result<()> {
+    out.write_all(&(name.len() as u32).to_be_bytes())?;
+    out.write_all(name.as_bytes())?;
+    out.write_all(&(contents.len() as u64).to_be_bytes())?;
+    out.write_all(contents)?;
+    Ok(())
+}
+
+fn read_entries(mut input: impl BufRead) -> Result<HashMap<String, Vec<u8>>> {
+    let mut magic = vec![0; MAGIC.len()];
+    input.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        bail!("Not a Graphite archive");
+    }
+    let mut entries = HashMap::new();
+    while !input.fill_buf()?.is_empty() {
+        let mut length = [0; 4];
+        input.read_exact(&mut length)?;
+        let mut name = vec![0; u32::from_be_bytes(length) as usize];
+        input.read_exact(&mut name)?;
+        let name = String::from_utf8(name).context("An entry has a name that isn't UTF-8")?;
+        let mut length = [0; 8];
+        input.read_exact(&mut length)?;
+        let mut contents = Vec::new();
+        (&mut input)
+            .take(u64::from_be_bytes(length))
+            .read_to_end(&mut contents)?;
+        entries.insert(name, contents);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(subject: Uuid, predicate: &str, datum: Datum) -> Action {
+        Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        }
+    }
+
+    #[test]
+    fn archives_move_graphs_with_their_files() {
+        let dir = std::env::temp_dir().join(format!("graphite-archive-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let photo = dir.join("photo.png");
+        std::fs::write(&photo, b"not really a png").unwrap();
+        let [page, other] = [Uuid::new_v4(), Uuid::new_v4()];
+        let mut source = EventStorage::open(dir.join("source.db")).unwrap();
+        let mut creator = source.creator().unwrap();
+        let actions = vec![
+            Action::CreateEntity { id: page },
+            Action::CreateEntity { id: other },
+            add(page, "see", Datum::Entity(other)),
+            add(page, "image", Datum::String(photo.display().to_string())),
+        ];
+        source.record_batch(creator.transaction(actions)).unwrap();
+        let archive = dir.join("graph.graphite");
+        let exported = export_archive(&source, &archive).unwrap();
+        assert_eq!((exported.entities, exported.files), (2, 1));
+
+        // Into an empty database, the events are kept as they are.
+        let mut empty = EventStorage::open(dir.join("empty.db")).unwrap();
+        let imported = import_archive(&mut empty, &archive, &dir.join("files")).unwrap();
+        assert!(!imported.remapped);
+        let projection = Projection::load(&empty).unwrap();
+        assert!(projection.contains(&page));
+        let Some(Datum::String(path)) = projection.entity(&page).unwrap().value("image") else {
+            panic!("expected the image to be imported");
+        };
+        assert_ne!(Path::new(path), photo);
+        assert_eq!(std::fs::read(path).unwrap(), b"not really a png");
+
+        // Into the source itself, the entities are copies with new ids.
+        let imported = import_archive(&mut source, &archive, &dir.join("files")).unwrap();
+        assert!(imported.remapped);
+        let projection = Projection::load(&source).unwrap();
+        assert_eq!(projection.len(), 4);
+        let (copy, _) = projection
+            .entities()
+            .find(|(id, e)| **id != page && e.value("image").is_some())
+            .unwrap();
+        let Some(Datum::Entity(linked)) = projection.entity(copy).unwrap().value("see") else {
+            panic!("expected the link to be imported");
+        };
+        assert!(*linked != other && projection.contains(linked));
+
+        drop((source, empty));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,427 @@
+//! Encrypted backups of the database to remote targets: a bucket of S3 or a
+//! compatible service, or a WebDAV collection.
+//!
+//! A backup is a consistent copy of the database, compressed and encrypted
+//! with a passphrase (see [`crypt`]), uploaded as
+//! `graphite-<yyyymmddThhmmssZ>.backup`. Every upload is downloaded again and
+//! compared to what was sent, then the backups the retention policy no
+//! longer keeps are deleted.
+//!
+//! With `end_to_end` on, backups are sealed with the keys of the graph
+//! rather than the passphrase, see [`keyring`].
+//!
+//! Daily copies can also be kept in a local directory, see [`local`].
+//!
+//! Restoring records the events of a backup that are missing from the
+//! database, so nothing recorded since the backup is lost.
+//!
+//! ```toml
+//! [backups]
+//! interval = 24
+//! retention = { keep_last = 7, keep_days = 30 }
+//!
+//! [[backups.targets]]
+//! type = "s3"
+//! name = "Offsite"
+//! endpoint = "https://s3.eu-central-1.amazonaws.com"
+//! region = "eu-central-1"
+//! bucket = "my-backups"
+//! prefix = "graphite/"
+//! ```
+
+pub mod crypt;
+pub mod keyring;
+pub mod local;
+pub mod s3;
+pub mod webdav;
+
+use crate::legacy::storage::{writer, Event, EventStorage};
+use crate::replication::Subscription;
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use keyring::Keyring;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::Path;
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+/// The environment variable that overrides the passphrase in the config.
+pub const PASSPHRASE_VARIABLE: &str = "GRAPHITE_BACKUP_PASSPHRASE";
+
+const PREFIX: &str = "graphite-";
+const EXTENSION: &str = ".backup";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub targets: Vec<Target>,
+    /// Hours between the backups taken while the editor runs. 0 only backs
+    /// up on request.
+    pub interval: u64,
+    /// The passphrase backups are encrypted with, unless
+    /// `GRAPHITE_BACKUP_PASSPHRASE` is set.
+    pub passphrase: Option<String>,
+    pub retention: Retention,
+    /// The part of the graph restored, see [`crate::replication`].
+    pub subscription: Subscription,
+    /// Seal backups with the keys of the graph instead of the passphrase,
+    /// see [`keyring`].
+    pub end_to_end: bool,
+    /// Daily copies in a local directory, see [`local`].
+    pub local: local::Local,
+}
+
+/// What backups are sealed with.
+#[derive(Clone)]
+pub enum Secret {
+    Passphrase(String),
+    Keyring(Keyring),
+}
+
+impl Secret {
+    pub fn seal(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Secret::Passphrase(passphrase) => crypt::seal(data, passphrase),
+            Secret::Keyring(keyring) => keyring.seal(data),
+        }
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Secret::Passphrase(passphrase) => crypt::open(sealed, passphrase),
+            Secret::Keyring(keyring) => keyring.open(sealed),
+        }
+    }
+}
+
+impl Settings {
+    pub fn passphrase(&self) -> Result<String> {
+        std::env::var(PASSPHRASE_VARIABLE)
+            .ok()
+            .or_else(|| self.passphrase.clone())
+            .filter(|passphrase| !passphrase.is_empty())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Set a backup passphrase in the config or in {}",
+                    PASSPHRASE_VARIABLE
+                )
+            })
+    }
+
+    /// The passphrase, or with end-to-end encryption the keyring at
+    /// `keyring`.
+    pub fn secret(&self, keyring: Option<&Path>) -> Result<Secret> {
+        if !self.end_to_end {
+            return Ok(Secret::Passphrase(self.passphrase()?));
+        }
+        let path = keyring.ok_or_else(|| anyhow!("There is no place for a keyring"))?;
+        Ok(Secret::Keyring(Keyring::load(path)?))
+    }
+
+    pub fn target(&self, name: &str) -> Result<&Target> {
+        self.targets
+            .iter()
+            .find(|t| t.name() == name)
+            .ok_or_else(|| anyhow!("There is no backup target named {}", name))
+    }
+}
+
+/// Which backups are kept: the newest `keep_last`, and those taken in the
+/// last `keep_days` days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retention {
+    pub keep_last: usize,
+    pub keep_days: Option<u64>,
+}
+
+impl Default for Retention {
+    fn default() -> Retention {
+        Retention {
+            keep_last: 7,
+            keep_days: Some(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Target {
+    S3(s3::Bucket),
+    Webdav(webdav::Collection),
+}
+
+impl Target {
+    pub fn name(&self) -> &str {
+        match self {
+            Target::S3(bucket) => &bucket.name,
+            Target::Webdav(collection) => &collection.name,
+        }
+    }
+
+    fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        match self {
+            Target::S3(bucket) => bucket.put(name, data),
+            Target::Webdav(collection) => collection.put(name, data),
+        }
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>> {
+        match self {
+            Target::S3(bucket) => bucket.get(name),
+            Target::Webdav(collection) => collection.get(name),
+        }
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        match self {
+            Target::S3(bucket) => bucket.delete(name),
+            Target::Webdav(collection) => collection.delete(name),
+        }
+    }
+
+    /// Every file on the target.
+    fn names(&self) -> Result<Vec<String>> {
+        match self {
+            Target::S3(bucket) => bucket.list(),
+            Target::Webdav(collection) => collection.list(),
+        }
+    }
+
+    /// The backups on the target, newest first.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut backups: Vec<String> = self
+            .names()
+            .with_context(|| format!("Failed to list the backups on {}", self.name()))?
+            .into_iter()
+            .filter(|name| taken(name).is_some())
+            .collect();
+        backups.sort_by(|a, b| b.cmp(a));
+        Ok(backups)
+    }
+}
+
+/// A copy of the database, compressed and sealed, ready to upload.
+#[instrument(skip_all)]
+pub fn snapshot(storage: &EventStorage, secret: &Secret) -> Result<Vec<u8>> {
+    let path = std::env::temp_dir().join(format!("graphite-backup-{}.db", Uuid::new_v4()));
+    let copied = storage.copy_to(&path).map_err(Into::into).and_then(|()| {
+        std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
+    });
+    let _ = std::fs::remove_file(&path);
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&copied?)?;
+    secret.seal(&encoder.finish()?)
+}
+
+/// What an upload to a target did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uploaded {
+    pub name: String,
+    /// The older backups deleted by the retention policy.
+    pub removed: Vec<String>,
+}
+
+/// Uploads `backup` to `target` as taken at `now`, checks it arrived intact
+/// and deletes the backups `retention` doesn't keep.
+#[instrument(skip_all, fields(target = target.name()))]
+pub fn upload(
+    target: &Target,
+    backup: &[u8],
+    now: OffsetDateTime,
+    retention: Retention,
+) -> Result<Uploaded> {
+    let name = file_name(now);
+    let failed = || format!("Failed to back up to {}", target.name());
+    target.put(&name, backup).with_context(failed)?;
+    let uploaded = target.get(&name).with_context(failed)?;
+    if uploaded != backup {
+        bail!(
+            "The backup {} on {} differs from the one uploaded",
+            name,
+            target.name()
+        );
+    }
+    let removed = rotate(target, now, retention)?;
+    info!(backup = %name, removed = removed.len(), "Backed up");
+    Ok(Uploaded { name, removed })
+}
+
+/// Deletes the backups on `target` that `retention` doesn't keep at `now`,
+/// and returns their names.
+pub fn rotate(target: &Target, now: OffsetDateTime, retention: Retention) -> Result<Vec<String>> {
+    let mut removed = Vec::new();
+    for old in expired(&target.list()?, now.unix_timestamp(), retention) {
+        target
+            .delete(&old)
+            .with_context(|| format!("Failed to delete {} on {}", old, target.name()))?;
+        removed.push(old);
+    }
+    Ok(removed)
+}
+
+/// The events in the backup `name` on `target`.
+#[instrument(skip(target, secret), fields(target = target.name()))]
+pub fn download(target: &Target, name: &str, secret: &Secret) -> Result<Vec<Event>> {
+    let sealed = target
+        .get(name)
+        .with_context(|| format!("Failed to download {} from {}", name, target.name()))?;
+    let events = events(&sealed, secret).with_context(|| format!("Failed to restore {}", name))?;
+    let origin = format!("{}/{}", target.name(), name);
+    Ok(events
+        .into_iter()
+        .map(|event| event.synced_from(&origin))
+        .collect())
+}
+
+/// The events in a sealed backup.
+pub fn events(sealed: &[u8], secret: &Secret) -> Result<Vec<Event>> {
+    let mut database = Vec::new();
+    GzDecoder::new(&secret.open(sealed)?[..])
+        .read_to_end(&mut database)
+        .context("The backup is corrupt")?;
+    let path = std::env::temp_dir().join(format!("graphite-restore-{}.db", Uuid::new_v4()));
+    std::fs::write(&path, database)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    let events = EventStorage::open(&path).and_then(|storage| {
+        let mut events = Vec::new();
+        storage.play(|event| {
+            events.push(event);
+            Ok(())
+        })?;
+        Ok(events)
+    });
+    let _ = std::fs::remove_file(writer::lock_path(&path));
+    let _ = std::fs::remove_file(&path);
+    Ok(events?)
+}
+
+/// The name of the backup taken at `time`.
+pub fn file_name(time: OffsetDateTime) -> String {
+    format!(
+        "{}{:04}{:02}{:02}T{:02}{:02}{:02}Z{}",
+        PREFIX,
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+        EXTENSION
+    )
+}
+
+/// When the backup named `name` was taken, as a Unix timestamp.
+pub fn taken(name: &str) -> Option<i64> {
+    let stamp = name.strip_prefix(PREFIX)?.strip_suffix(EXTENSION)?;
+    let (date, time) = stamp.strip_suffix('Z')?.split_once('T')?;
+    if date.len() != 8 || time.len() != 6 {
+        return None;
+    }
+    let number = |text: &str, range: std::ops::Range<usize>| text.get(range)?.parse::<u8>().ok();
+    let date = Date::from_calendar_date(
+        date.get(..4)?.parse().ok()?,
+        Month::try_from(number(date, 4..6)?).ok()?,
+        number(date, 6..8)?,
+    )
+    .ok()?;
+    let time = Time::from_hms(
+        number(time, 0..2)?,
+        number(time, 2..4)?,
+        number(time, 4..6)?,
+    )
+    .ok()?;
+    Some(
+        PrimitiveDateTime::new(date, time)
+            .assume_utc()
+            .unix_timestamp(),
+    )
+}
+
+/// The backups among `names` that `retention` no longer keeps at `now`.
+pub fn expired(names: &[String], now: i64, retention: Retention) -> Vec<String> {
+    let mut backups: Vec<(i64, &String)> = names
+        .iter()
+        .filter_map(|name| Some((taken(name)?, name)))
+        .collect();
+    backups.sort_by(|a, b| b.cmp(a));
+    backups
+        .into_iter()
+        .skip(retention.keep_last)
+        .filter(|(taken, _)| {
+            retention
+                .keep_days
+                .is_none_or(|days| now - taken > days as i64 * 24 * 60 * 60)
+        })
+        .map(|(_, name)| name.clone())
+        .collect()
+}
+
+/// Sends `request`, with `body` unless it is empty, turning error responses
+/// into errors that include what the server said.
+pub(crate) fn send(request: ureq::Request, body: &[u8]) -> Result<ureq::Response> {
+    let response = if body.is_empty() {
+        request.call()
+    } else {
+        request.send_bytes(body)
+    };
+    match response {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(status, response)) => {
+            let text = response.into_string().unwrap_or_default();
+            let text: String = text.chars().take(500).collect();
+            bail!("The server responded with {}: {}", status, text.trim())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+    use time::macros::datetime;
+
+    #[test]
+    fn keeps_the_newest_and_recent_backups() {
+        let now = datetime!(2024-10-15 12:00 UTC);
+        let names: Vec<String> = [1, 2, 10, 40, 41]
+            .into_iter()
+            .map(|days| file_name(now - time::Duration::days(days)))
+            .chain(["notes.txt".to_string()])
+            .collect();
+        assert_eq!(
+            taken(&names[0]),
+            Some((now - time::Duration::days(1)).unix_timestamp())
+        );
+
+        let retention = |keep_last, keep_days| Retention {
+            keep_last,
+            keep_days,
+        };
+        let now = now.unix_timestamp();
+        assert_eq!(expired(&names, now, retention(2, Some(30))), &names[3..5]);
+        assert_eq!(expired(&names, now, retention(4, Some(30))), &names[4..5]);
+        assert_eq!(expired(&names, now, retention(1, None)), &names[1..5]);
+    }
+
+    #[test]
+    fn restores_the_events_of_a_snapshot() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let mut creator = storage.creator().unwrap();
+        let event = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+        storage.record_batch(vec![event.clone()]).unwrap();
+
+        let secret = |passphrase: &str| Secret::Passphrase(passphrase.to_string());
+        let backup = snapshot(&storage, &secret("secret")).unwrap();
+        assert_eq!(events(&backup, &secret("secret")).unwrap(), vec![event]);
+        assert!(events(&backup, &secret("guess")).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Encryption of backups with a key derived from a passphrase.
+//!
+//! A sealed backup is the magic bytes, the salt the key was derived with
+//! (PBKDF2-HMAC-SHA256), a nonce and the data encrypted with AES-256-GCM,
+//! whose tag also covers the magic bytes.
+
+use anyhow::{anyhow, bail, Result};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::num::NonZeroU32;
+
+const MAGIC: &[u8] = b"GRAPHITE-BACKUP\x01";
+const SALT: usize = 16;
+#[cfg(not(test))]
+const ITERATIONS: u32 = 600_000;
+/// Deriving keys is slow on purpose, too slow for unoptimized tests.
+#[cfg(test)]
+const ITERATIONS: u32 = 1_000;
+
+pub fn seal(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let random = SystemRandom::new();
+    let mut salt = [0; SALT];
+    let mut nonce = [0; aead::NONCE_LEN];
+    random
+        .fill(&mut salt)
+        .and_then(|_| random.fill(&mut nonce))
+        .map_err(|_| anyhow!("Failed to generate a salt and nonce"))?;
+
+    let mut sealed = data.to_vec();
+    key(passphrase, &salt)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow!("Failed to encrypt the backup"))?;
+    Ok([MAGIC, &salt, &nonce, &sealed].concat())
+}
+
+/// Decrypts a sealed backup, failing if the passphrase is wrong or the data
+/// was changed.
+pub fn open(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let Some(rest) = sealed.strip_prefix(MAGIC) else {
+        bail!("Not a Graphite backup");
+    };
+    if rest.len() < SALT + aead::NONCE_LEN + aead::AES_256_GCM.tag_len() {
+        bail!("The backup is truncated");
+    }
+    let (salt, rest) = rest.split_at(SALT);
+    let (nonce, data) = rest.split_at(aead::NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("the nonce has its length");
+    let mut data = data.to_vec();
+    let plain = key(passphrase, salt)
+        .open_in_place(nonce, Aad::from(MAGIC), &mut data)
+        .map_err(|_| anyhow!("Wrong passphrase, or the backup is corrupt"))?;
+    Ok(plain.to_vec())
+}
+
+fn key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(ITERATIONS).expect("iterations are not zero"),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&aead::AES_256_GCM, &key).expect("the key has its length"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_only_with_the_passphrase() {
+        let sealed = seal(b"events", "correct horse").unwrap();
+        assert_eq!(open(&sealed, "correct horse").unwrap(), b"events");
+        assert!(open(&sealed, "wrong").is_err());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(&tampered, "correct horse").is_err());
+    }
+}
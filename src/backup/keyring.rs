@@ -0,0 +1,556 @@
+//! End-to-end encryption of what is sent to targets, with keys of the graph
+//! rather than a passphrase, so the service storing it only ever sees
+//! ciphertext.
+//!
+//! Each device keeps a [`Keyring`]: an Ed25519 identity, shown as a
+//! [`fingerprint`] to compare between devices, and the keys of the graph,
+//! one per epoch. Payloads are sealed with AES-256-GCM under the key of the
+//! latest epoch, which they name so older ones still open. Rotating adds an
+//! epoch; a device that left can't open anything sealed afterwards.
+//!
+//! A new device gets the keys through the target: it sends a [`Request`]
+//! with its identity and an ephemeral X25519 key, signed, and waits. A
+//! device that has the keys approves it once the fingerprints match, and
+//! answers with a [`Grant`]: the keys sealed under the secret both
+//! ephemeral keys agree on, signed with its own identity.
+//!
+//! A recovery code, shown once, seals a copy of the keys stored on the
+//! target, to get them back when no device is left.
+
+use super::{crypt, Target};
+use anyhow::{anyhow, bail, Context, Result};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::agreement::{self, EphemeralPrivateKey, X25519};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use ring::{digest, hkdf};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"GRAPHITE-E2E\x01";
+/// The copy of the keys sealed with the recovery code.
+const RECOVERY: &str = "graphite-keyring.recovery";
+const REQUEST: &str = "graphite-join-";
+const GRANT: &str = "graphite-grant-";
+/// Bytes of randomness in a recovery code.
+const CODE: usize = 20;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Keyring {
+    /// The Ed25519 key pair of this device, as PKCS#8.
+    #[serde(with = "bytes")]
+    identity: Vec<u8>,
+    /// The keys of the graph, the last one current.
+    keys: Vec<Key>,
+    /// The recovery code, to seal the keys again when they are rotated.
+    recovery: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Key {
+    epoch: u32,
+    #[serde(with = "bytes")]
+    key: Vec<u8>,
+}
+
+/// A new device asking for the keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    #[serde(with = "bytes")]
+    identity: Vec<u8>,
+    #[serde(with = "bytes")]
+    ephemeral: Vec<u8>,
+    #[serde(with = "bytes")]
+    signature: Vec<u8>,
+}
+
+/// The keys sent to a device that asked for them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    #[serde(with = "bytes")]
+    identity: Vec<u8>,
+    #[serde(with = "bytes")]
+    ephemeral: Vec<u8>,
+    #[serde(with = "bytes")]
+    sealed: Vec<u8>,
+    #[serde(with = "bytes")]
+    signature: Vec<u8>,
+}
+
+/// A request waiting for its grant, holding the ephemeral key that opens it.
+pub struct Joining {
+    private: EphemeralPrivateKey,
+    request: Request,
+}
+
+impl Keyring {
+    /// A keyring with a new identity and no keys, to join a graph.
+    pub fn new() -> Result<Keyring> {
+        let identity = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate an identity"))?;
+        Ok(Keyring {
+            identity: identity.as_ref().to_vec(),
+            keys: Vec::new(),
+            recovery: None,
+        })
+    }
+
+    /// A keyring with a new identity and the first key of a graph.
+    pub fn generate() -> Result<Keyring> {
+        let mut keyring = Keyring::new()?;
+        keyring.rotate()?;
+        Ok(keyring)
+    }
+
+    pub fn load(path: &Path) -> Result<Keyring> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the keyring {}", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse the keyring {}", path.display()))
+    }
+
+    /// Saves the keyring, readable by the user only.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let write = |options: &std::fs::OpenOptions| -> std::io::Result<()> {
+            std::io::Write::write_all(&mut options.open(path)?, json.as_bytes())
+        };
+        write(&options).with_context(|| format!("Failed to save the keyring {}", path.display()))
+    }
+
+    fn key_pair(&self) -> Result<Ed25519KeyPair> {
+        Ed25519KeyPair::from_pkcs8(&self.identity).map_err(|_| anyhow!("The identity is corrupt"))
+    }
+
+    /// The fingerprint of this device's identity.
+    pub fn fingerprint(&self) -> Result<String> {
+        Ok(fingerprint(self.key_pair()?.public_key().as_ref()))
+    }
+
+    /// The current epoch, `None` until the keys were received.
+    pub fn epoch(&self) -> Option<u32> {
+        self.keys.last().map(|k| k.epoch)
+    }
+
+    /// Adds a new key, used from now on, and returns its epoch.
+    pub fn rotate(&mut self) -> Result<u32> {
+        let mut key = vec![0; 32];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| anyhow!("Failed to generate a key"))?;
+        let epoch = self.epoch().map_or(1, |epoch| epoch + 1);
+        self.keys.push(Key { epoch, key });
+        Ok(epoch)
+    }
+
+    /// Seals `data` with the current key.
+    pub fn seal(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let Some(key) = self.keys.last() else {
+            bail!("The keyring has no keys yet, join the graph first");
+        };
+        let header = [MAGIC, &key.epoch.to_be_bytes()].concat();
+        Ok([header.as_slice(), &seal(&key.key, &header, data)?].concat())
+    }
+
+    /// Opens `sealed`, failing if it was sealed with a key this keyring
+    /// lacks or was changed.
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        let Some(rest) = sealed.strip_prefix(MAGIC) else {
+            bail!("Not sealed with the keys of a graph");
+        };
+        if rest.len() < 4 {
+            bail!("The payload is truncated");
+        }
+        let (epoch, data) = rest.split_at(4);
+        let epoch = u32::from_be_bytes(epoch.try_into().expect("the epoch has 4 bytes"));
+        let Some(key) = self.keys.iter().find(|k| k.epoch == epoch) else {
+            bail!("The keyring lacks the key of epoch {}", epoch);
+        };
+        open(&key.key, &sealed[..MAGIC.len() + 4], data)
+    }
+
+    /// Sets a new recovery code and returns it, to be written down.
+    pub fn new_recovery_code(&mut self) -> Result<String> {
+        let mut bytes = [0; CODE];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow!("Failed to generate a recovery code"))?;
+        let code = base32(&bytes)
+            .as_bytes()
+            .chunks(4)
+            .map(|c| String::from_utf8_lossy(c).into_owned())
+            .collect::<Vec<_>>()
+            .join("-");
+        self.recovery = Some(code.clone());
+        Ok(code)
+    }
+
+    /// The keys sealed with the recovery code, if one was set.
+    pub fn recovery(&self) -> Result<Option<Vec<u8>>> {
+        let Some(code) = &self.recovery else {
+            return Ok(None);
+        };
+        let keys = serde_json::to_vec(&self.keys)?;
+        Ok(Some(crypt::seal(&keys, &normalize(code))?))
+    }
+
+    /// Takes in the keys `sealed` with the recovery `code`.
+    pub fn recover(&mut self, sealed: &[u8], code: &str) -> Result<()> {
+        let keys = crypt::open(sealed, &normalize(code))
+            .map_err(|_| anyhow!("Wrong recovery code, or the copy of the keys is corrupt"))?;
+        self.add_keys(serde_json::from_slice(&keys).context("The keys are corrupt")?);
+        self.recovery = Some(code.to_string());
+        Ok(())
+    }
+
+    fn add_keys(&mut self, keys: Vec<Key>) {
+        for key in keys {
+            if !self.keys.iter().any(|k| k.epoch == key.epoch) {
+                self.keys.push(key);
+            }
+        }
+        self.keys.sort_by_key(|k| k.epoch);
+    }
+
+    /// Asks for the keys of the graph.
+    pub fn request(&self) -> Result<Joining> {
+        let private = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate a key"))?;
+        let ephemeral = private
+            .compute_public_key()
+            .map_err(|_| anyhow!("Failed to generate a key"))?
+            .as_ref()
+            .to_vec();
+        let key_pair = self.key_pair()?;
+        let identity = key_pair.public_key().as_ref().to_vec();
+        let signature = key_pair
+            .sign(&[b"request".as_slice(), &identity, &ephemeral].concat())
+            .as_ref()
+            .to_vec();
+        Ok(Joining {
+            private,
+            request: Request {
+                identity,
+                ephemeral,
+                signature,
+            },
+        })
+    }
+
+    /// Sends the keys to the device that made `request`, once its
+    /// fingerprint was checked.
+    pub fn approve(&self, request: &Request) -> Result<Grant> {
+        if self.keys.is_empty() {
+            bail!("The keyring has no keys to share");
+        }
+        verify(
+            &request.identity,
+            &[b"request".as_slice(), &request.identity, &request.ephemeral].concat(),
+            &request.signature,
+        )?;
+        let private = EphemeralPrivateKey::generate(&X25519, &SystemRandom::new())
+            .map_err(|_| anyhow!("Failed to generate a key"))?;
+        let ephemeral = private
+            .compute_public_key()
+            .map_err(|_| anyhow!("Failed to generate a key"))?
+            .as_ref()
+            .to_vec();
+        let secret = agree(private, &request.ephemeral, &request.ephemeral, &ephemeral)?;
+        let sealed = seal(&secret, MAGIC, &serde_json::to_vec(&self.keys)?)?;
+        let key_pair = self.key_pair()?;
+        let identity = key_pair.public_key().as_ref().to_vec();
+        let signed = [
+            b"grant".as_slice(),
+            &identity,
+            &ephemeral,
+            &request.ephemeral,
+            &sealed,
+        ]
+        .concat();
+        Ok(Grant {
+            signature: key_pair.sign(&signed).as_ref().to_vec(),
+            identity,
+            ephemeral,
+            sealed,
+        })
+    }
+}
+
+impl Request {
+    /// The fingerprint of the device asking.
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.identity)
+    }
+}
+
+impl Grant {
+    /// The fingerprint of the device that approved.
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.identity)
+    }
+}
+
+impl Joining {
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+
+    /// Takes the keys in `grant` into `keyring`.
+    pub fn accept(self, keyring: &mut Keyring, grant: &Grant) -> Result<()> {
+        let signed = [
+            b"grant".as_slice(),
+            &grant.identity,
+            &grant.ephemeral,
+            &self.request.ephemeral,
+            &grant.sealed,
+        ]
+        .concat();
+        verify(&grant.identity, &signed, &grant.signature)?;
+        let secret = agree(
+            self.private,
+            &grant.ephemeral,
+            &self.request.ephemeral,
+            &grant.ephemeral,
+        )?;
+        let keys = open(&secret, MAGIC, &grant.sealed)?;
+        keyring.add_keys(serde_json::from_slice(&keys).context("The keys are corrupt")?);
+        Ok(())
+    }
+}
+
+/// A short hash of a public identity key, e.g. `3f2a 9c01 77de 4b10`.
+pub fn fingerprint(identity: &[u8]) -> String {
+    let hash = digest::digest(&digest::SHA256, identity);
+    hash.as_ref()[..8]
+        .chunks(2)
+        .map(super::hex)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Uploads the copy of the keys sealed with the recovery code.
+pub fn upload_recovery(target: &Target, keyring: &Keyring) -> Result<()> {
+    if let Some(sealed) = keyring.recovery()? {
+        target
+            .put(RECOVERY, &sealed)
+            .with_context(|| format!("Failed to upload the recovery keys to {}", target.name()))?;
+    }
+    Ok(())
+}
+
+pub fn download_recovery(target: &Target) -> Result<Vec<u8>> {
+    target.get(RECOVERY).with_context(|| {
+        format!(
+            "Failed to download the recovery keys from {}",
+            target.name()
+        )
+    })
+}
+
+pub fn send_request(target: &Target, request: &Request) -> Result<()> {
+    let name = format!("{}{}.json", REQUEST, file_id(&request.identity));
+    target
+        .put(&name, &serde_json::to_vec(request)?)
+        .with_context(|| format!("Failed to send the request to {}", target.name()))
+}
+
+/// The requests waiting on `target`.
+pub fn requests(target: &Target) -> Result<Vec<Request>> {
+    let mut requests = Vec::new();
+    for name in target.names()? {
+        if name.starts_with(REQUEST) {
+            let json = target.get(&name)?;
+            requests.push(
+                serde_json::from_slice(&json)
+                    .with_context(|| format!("Failed to parse the request {}", name))?,
+            );
+        }
+    }
+    Ok(requests)
+}
+
+/// Answers `request` with `grant`, removing the request.
+pub fn send_grant(target: &Target, request: &Request, grant: &Grant) -> Result<()> {
+    let id = file_id(&request.identity);
+    target
+        .put(
+            &format!("{}{}.json", GRANT, id),
+            &serde_json::to_vec(grant)?,
+        )
+        .with_context(|| format!("Failed to send the keys to {}", target.name()))?;
+    target.delete(&format!("{}{}.json", REQUEST, id))
+}
+
+/// Refuses `request`, removing it.
+pub fn refuse(target: &Target, request: &Request) -> Result<()> {
+    target.delete(&format!("{}{}.json", REQUEST, file_id(&request.identity)))
+}
+
+/// The grant answering `request`, once there is one, which is removed.
+pub fn receive_grant(target: &Target, request: &Request) -> Result<Option<Grant>> {
+    let name = format!("{}{}.json", GRANT, file_id(&request.identity));
+    if !target.names()?.contains(&name) {
+        return Ok(None);
+    }
+    let grant = serde_json::from_slice(&target.get(&name)?)
+        .with_context(|| format!("Failed to parse the grant {}", name))?;
+    target.delete(&name)?;
+    Ok(Some(grant))
+}
+
+fn file_id(identity: &[u8]) -> String {
+    fingerprint(identity).replace(' ', "")
+}
+
+fn verify(identity: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+    signature::UnparsedPublicKey::new(&signature::ED25519, identity)
+        .verify(message, signature)
+        .map_err(|_| anyhow!("The signature of {} is invalid", fingerprint(identity)))
+}
+
+/// The secret `private` agrees on with `peer`, bound to both ephemeral keys.
+fn agree(
+    private: EphemeralPrivateKey,
+    peer: &[u8],
+    requester: &[u8],
+    approver: &[u8],
+) -> Result<Vec<u8>> {
+    let salt = [requester, approver].concat();
+    agreement::agree_ephemeral(
+        private,
+        &agreement::UnparsedPublicKey::new(&X25519, peer),
+        |shared| {
+            let mut secret = vec![0; 32];
+            hkdf::Salt::new(hkdf::HKDF_SHA256, &salt)
+                .extract(shared)
+                .expand(&[b"graphite-grant"], hkdf::HKDF_SHA256)
+                .and_then(|okm| okm.fill(&mut secret))
+                .map(|()| secret)
+        },
+    )
+    .map_err(|_| anyhow!("Failed to agree on a key"))?
+    .map_err(|_| anyhow!("Failed to derive a key"))
+}
+
+/// A nonce and `data` encrypted with `key`, authenticating `aad` too.
+fn seal(key: &[u8], aad: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; aead::NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow!("Failed to generate a nonce"))?;
+    let mut sealed = data.to_vec();
+    aead_key(key)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow!("Failed to encrypt"))?;
+    Ok([nonce.as_slice(), &sealed].concat())
+}
+
+fn open(key: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < aead::NONCE_LEN + aead::AES_256_GCM.tag_len() {
+        bail!("The payload is truncated");
+    }
+    let (nonce, data) = sealed.split_at(aead::NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("the nonce has its length");
+    let mut data = data.to_vec();
+    let plain = aead_key(key)?
+        .open_in_place(nonce, Aad::from(aad), &mut data)
+        .map_err(|_| anyhow!("Failed to decrypt, the payload is corrupt"))?;
+    Ok(plain.to_vec())
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&aead::AES_256_GCM, key).map_err(|_| anyhow!("Invalid key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Crockford's base32, which leaves out letters easily mistaken.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    let (mut buffer, mut bits, mut text) = (0u32, 0, String::new());
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        text.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    text
+}
+
+/// A recovery code as typed, without separators and in capitals.
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Bytes as base64 strings in JSON.
+mod bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_shared_rotated_and_recovered() {
+        let mut laptop = Keyring::generate().unwrap();
+        let sealed = laptop.seal(b"events").unwrap();
+        assert_eq!(laptop.open(&sealed).unwrap(), b"events");
+
+        // A phone joins.
+        let mut phone = Keyring::new().unwrap();
+        assert!(phone.seal(b"events").is_err());
+        let joining = phone.request().unwrap();
+        let request: Request =
+            serde_json::from_slice(&serde_json::to_vec(joining.request()).unwrap()).unwrap();
+        assert_eq!(request.fingerprint(), phone.fingerprint().unwrap());
+        let grant = laptop.approve(&request).unwrap();
+        assert_eq!(grant.fingerprint(), laptop.fingerprint().unwrap());
+        // A grant answers one request only.
+        let other = phone.request().unwrap();
+        assert!(other.accept(&mut phone.clone(), &grant).is_err());
+        joining.accept(&mut phone, &grant).unwrap();
+        assert_eq!(phone.open(&sealed).unwrap(), b"events");
+
+        // Rotated, new payloads need the new key, old ones still open.
+        assert_eq!(laptop.rotate().unwrap(), 2);
+        let rotated = laptop.seal(b"newer").unwrap();
+        assert!(phone.open(&rotated).is_err());
+        assert_eq!(laptop.open(&sealed).unwrap(), b"events");
+
+        let code = laptop.new_recovery_code().unwrap();
+        assert_eq!(code.len(), 39);
+        let copy = laptop.recovery().unwrap().unwrap();
+        let mut restored = Keyring::new().unwrap();
+        assert!(restored.recover(&copy, "0000-0000").is_err());
+        restored.recover(&copy, &code.to_lowercase()).unwrap();
+        assert_eq!(restored.epoch(), Some(2));
+        assert_eq!(restored.open(&rotated).unwrap(), b"newer");
+    }
+}
@@ -0,0 +1,172 @@
+//! Daily copies of the database in a local directory, such as another disk.
+//!
+//! Unlike the backups on targets, these are plain SQLite databases, one a
+//! day named `graphite-<yyyy-mm-dd>.db`, that open as they are. They are
+//! taken with SQLite's online backup, see [`EventStorage::backup_to`], and
+//! only the newest `keep_daily` are kept.
+//!
+//! ```toml
+//! [backups.local]
+//! path = "/mnt/external/graphite"
+//! keep_daily = 14
+//! ```
+
+use crate::legacy::storage::EventStorage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use time::{Date, OffsetDateTime};
+use tracing::{info, instrument};
+
+const PREFIX: &str = "graphite-";
+const EXTENSION: &str = ".db";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Local {
+    /// The directory the copies are kept in, none to take none.
+    pub path: Option<PathBuf>,
+    /// How many daily copies are kept.
+    pub keep_daily: usize,
+}
+
+impl Default for Local {
+    fn default() -> Local {
+        Local {
+            path: None,
+            keep_daily: 7,
+        }
+    }
+}
+
+/// What a local backup did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Copied {
+    pub name: String,
+    /// The older copies deleted to keep `keep_daily`.
+    pub removed: Vec<String>,
+}
+
+/// The name of the copy taken on `date`.
+pub fn file_name(date: Date) -> String {
+    format!("{}{}{}", PREFIX, date, EXTENSION)
+}
+
+/// The copies in `dir`, newest first.
+pub fn list(dir: &Path) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut copies = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let date = name
+            .strip_prefix(PREFIX)
+            .and_then(|n| n.strip_suffix(EXTENSION));
+        // yyyy-mm-dd sorts by date.
+        if date.is_some_and(|d| d.len() == 10 && d.chars().all(|c| c.is_ascii_digit() || c == '-'))
+        {
+            copies.push(name);
+        }
+    }
+    copies.sort_by(|a, b| b.cmp(a));
+    Ok(copies)
+}
+
+/// Whether there is no copy for the day of `now` yet.
+pub fn due(settings: &Local, now: OffsetDateTime) -> bool {
+    settings
+        .path
+        .as_ref()
+        .is_some_and(|dir| !dir.join(file_name(now.date())).exists())
+}
+
+/// Copies the database to the directory of `settings` as the copy of the day
+/// of `now`, replacing one already taken that day, and deletes the copies
+/// past `keep_daily`.
+#[instrument(skip_all)]
+pub fn back_up(storage: &EventStorage, settings: &Local, now: OffsetDateTime) -> Result<Copied> {
+    let dir = settings
+        .path
+        .as_ref()
+        .context("No directory for local backups is configured")?;
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let name = file_name(now.date());
+    // Copied under another name first, so a copy cut short never replaces
+    // the last one of the day.
+    let partial = dir.join(format!(".{}.tmp", name));
+    let _ = std::fs::remove_file(&partial);
+    storage.backup_to(&partial)?;
+    std::fs::rename(&partial, dir.join(&name))
+        .with_context(|| format!("Failed to write {}", name))?;
+
+    let removed = rotate(settings)?;
+    info!(backup = %name, removed = removed.len(), "Backed up locally");
+    Ok(Copied { name, removed })
+}
+
+/// Deletes the copies past `keep_daily` and returns their names.
+pub fn rotate(settings: &Local) -> Result<Vec<String>> {
+    let Some(dir) = &settings.path else {
+        return Ok(Vec::new());
+    };
+    let mut removed = Vec::new();
+    for old in list(dir)?.into_iter().skip(settings.keep_daily.max(1)) {
+        std::fs::remove_file(dir.join(&old))
+            .with_context(|| format!("Failed to delete {}", old))?;
+        removed.push(old);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+    use time::macros::datetime;
+    use uuid::Uuid;
+
+    #[test]
+    fn keeps_the_last_dailies() {
+        let dir = std::env::temp_dir().join(format!("graphite-local-{}", Uuid::new_v4()));
+        let settings = Local {
+            path: Some(dir.clone()),
+            keep_daily: 2,
+        };
+        let storage = EventStorage::open(":memory:").unwrap();
+        let mut creator = storage.creator().unwrap();
+        let id = Uuid::new_v4();
+        storage
+            .record(creator.create(Action::CreateEntity { id }))
+            .unwrap();
+
+        let days = [
+            datetime!(2024-03-01 09:00 UTC),
+            datetime!(2024-03-02 09:00 UTC),
+            datetime!(2024-03-03 09:00 UTC),
+        ];
+        assert!(due(&settings, days[0]));
+        back_up(&storage, &settings, days[0]).unwrap();
+        assert!(!due(&settings, days[0]));
+        back_up(&storage, &settings, days[1]).unwrap();
+        let copied = back_up(&storage, &settings, days[2]).unwrap();
+        assert_eq!(copied.removed, vec!["graphite-2024-03-01.db"]);
+        assert_eq!(
+            list(&dir).unwrap(),
+            vec!["graphite-2024-03-03.db", "graphite-2024-03-02.db"]
+        );
+
+        let copy = EventStorage::open(dir.join(&copied.name)).unwrap();
+        let mut events = 0;
+        copy.play(|_| {
+            events += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(events, 1);
+        drop(copy);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
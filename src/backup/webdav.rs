@@ -0,0 +1,90 @@
+//! A collection on a WebDAV server, such as Nextcloud or a NAS.
+
+use super::send;
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collection {
+    /// The name the target is shown and picked with.
+    pub name: String,
+    /// The URL of the collection, e.g.
+    /// `https://cloud.example.com/remote.php/dav/files/me/backups/`.
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/></prop></propfind>"#;
+
+impl Collection {
+    pub fn put(&self, name: &str, data: &[u8]) -> Result<()> {
+        // The collection is created on the first backup. It failing because
+        // the collection exists is fine, any other problem fails the upload.
+        let _ = send(self.request("MKCOL", None), &[]);
+        send(self.request("PUT", Some(name)), data)
+            .with_context(|| format!("Failed to upload {}", name))?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        send(self.request("GET", Some(name)), &[])?
+            .into_reader()
+            .read_to_end(&mut data)
+            .with_context(|| format!("Failed to download {}", name))?;
+        Ok(data)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        send(self.request("DELETE", Some(name)), &[])
+            .with_context(|| format!("Failed to delete {}", name))?;
+        Ok(())
+    }
+
+    /// The names of the files in the collection.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let request = self
+            .request("PROPFIND", None)
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml");
+        let text = send(request, PROPFIND.as_bytes())
+            .with_context(|| format!("Failed to list {}", self.url))?
+            .into_string()
+            .context("Failed to read the list of backups")?;
+        let document = roxmltree::Document::parse(&text).context("Invalid list of backups")?;
+        Ok(document
+            .descendants()
+            .filter(|n| n.tag_name().name() == "href")
+            .filter_map(|n| n.text())
+            .filter_map(|href| href.trim_end_matches('/').rsplit('/').next())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn request(&self, method: &str, name: Option<&str>) -> ureq::Request {
+        let mut url = self.url.trim_end_matches('/').to_string();
+        url.push('/');
+        if let Some(name) = name {
+            url.push_str(name);
+        }
+        let request = ureq::request(method, &url);
+        match &self.username {
+            Some(username) => {
+                let credentials = format!(
+                    "{}:{}",
+                    username,
+                    self.password.as_deref().unwrap_or_default()
+                );
+                let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+                request.set("Authorization", &format!("Basic {}", encoded))
+            }
+            None => request,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! The relay devices sync through when they are never online at the same
+//! time, see [`graphite::relay`]. Built with `--features relay`.
+
+use clap::Parser;
+use graphite::logging;
+use graphite::relay::{Relay, Store, TOKEN_VARIABLE};
+use std::path::PathBuf;
+use tracing::level_filters::LevelFilter;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Where the batches are kept.
+    #[arg(long, default_value = "graphite-relay.db")]
+    database: PathBuf,
+    /// The address to listen on.
+    #[arg(long, default_value = "0.0.0.0:7878")]
+    listen: String,
+    /// The token devices must send, unless GRAPHITE_RELAY_TOKEN is set.
+    #[arg(long)]
+    token: Option<String>,
+    /// What to log: off, error, warn, info, debug or trace.
+    #[arg(long, default_value_t = LevelFilter::INFO)]
+    log_level: LevelFilter,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    logging::init(args.log_level, None)?;
+    let token = std::env::var(TOKEN_VARIABLE).ok().or(args.token);
+    if token.is_none() {
+        tracing::warn!("Relaying without a token, anyone can read and add batches");
+    }
+    let relay = Relay::new(Store::open(&args.database)?, token).serve(&args.listen)?;
+    println!("Relaying on {}", relay.address());
+    relay.wait();
+    Ok(())
+}
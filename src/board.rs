@@ -0,0 +1,146 @@
+//! A board of the entities matching a query, in a column per value of a
+//! predicate, such as the `status` of tasks.
+//!
+//! An entity is placed by the first value of the predicate, and entities
+//! without one are in a last column of their own. Moving a card to another
+//! column replaces the values of the predicate with that column's, or removes
+//! them if it is the last column.
+
+use crate::import::infer;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use crate::query::Query;
+use crate::table;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    /// The value of the predicate, or `None` for the entities without one.
+    pub value: Option<Datum>,
+    pub cards: Vec<Uuid>,
+}
+
+/// The columns of the values of `predicate` and of `extra`, the value typed
+/// for a column that has no cards yet.
+pub fn board(
+    projection: &Projection,
+    query: &Query,
+    predicate: &str,
+    extra: &[String],
+) -> Vec<Column> {
+    let mut columns: Vec<Column> = extra
+        .iter()
+        .map(|value| Column {
+            value: Some(infer(value)),
+            cards: Vec::new(),
+        })
+        .collect();
+    let mut unset = Vec::new();
+    let mut ids = query.run(projection);
+    ids.sort();
+    for id in ids {
+        let Some(value) = projection.entity(&id).and_then(|e| e.value(predicate)) else {
+            unset.push(id);
+            continue;
+        };
+        match columns.iter_mut().find(|c| c.value.as_ref() == Some(value)) {
+            Some(column) => column.cards.push(id),
+            None => columns.push(Column {
+                value: Some(value.clone()),
+                cards: vec![id],
+            }),
+        }
+    }
+    columns.sort_by(|a, b| match (&a.value, &b.value) {
+        (Some(a), Some(b)) => table::compare(a, b),
+        _ => std::cmp::Ordering::Equal,
+    });
+    columns.push(Column {
+        value: None,
+        cards: unset,
+    });
+    columns
+}
+
+/// The actions that move the card `id` to the column of `value`.
+pub fn move_card(
+    projection: &Projection,
+    id: Uuid,
+    predicate: &str,
+    value: Option<&Datum>,
+) -> Vec<Action> {
+    let Some(entity) = projection.entity(&id) else {
+        return Vec::new();
+    };
+    let values = entity.values(predicate);
+    if values.first() == value && values.len() <= 1 {
+        return Vec::new();
+    }
+    let mut actions = Vec::new();
+    if !values.is_empty() {
+        actions.push(Action::RemoveFact {
+            subject: id,
+            predicate: predicate.to_string(),
+        });
+    }
+    actions.extend(value.map(|datum| Action::AddFact {
+        subject: id,
+        predicate: predicate.to_string(),
+        datum: datum.clone(),
+    }));
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cards_move_between_columns() {
+        let mut projection = Projection::new();
+        let text = |s: &str| Datum::String(s.to_string());
+        let [write, test, plan] = [(); 3].map(|_| Uuid::new_v4());
+        for (id, status) in [(write, Some("doing")), (test, Some("todo")), (plan, None)] {
+            projection.apply(&Action::CreateEntity { id });
+            projection.apply(&Action::AddFact {
+                subject: id,
+                predicate: "type".to_string(),
+                datum: text("task"),
+            });
+            if let Some(status) = status {
+                projection.apply(&Action::AddFact {
+                    subject: id,
+                    predicate: "status".to_string(),
+                    datum: text(status),
+                });
+            }
+        }
+        let query: Query = "type=task".parse().unwrap();
+        let columns = board(&projection, &query, "status", &["done".to_string()]);
+        let shape: Vec<_> = columns
+            .iter()
+            .map(|c| (c.value.clone(), c.cards.clone()))
+            .collect();
+        assert_eq!(
+            shape,
+            [
+                (Some(text("doing")), vec![write]),
+                (Some(text("done")), vec![]),
+                (Some(text("todo")), vec![test]),
+                (None, vec![plan]),
+            ]
+        );
+
+        for action in move_card(&projection, write, "status", Some(&text("done"))) {
+            projection.apply(&action);
+        }
+        for action in move_card(&projection, test, "status", None) {
+            projection.apply(&action);
+        }
+        let columns = board(&projection, &query, "status", &[]);
+        assert_eq!(columns[0].value, Some(text("done")));
+        assert_eq!(columns[0].cards, [write]);
+        assert_eq!(columns[1].cards.len(), 2);
+        assert!(move_card(&projection, write, "status", Some(&text("done"))).is_empty());
+    }
+}
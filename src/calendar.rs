@@ -0,0 +1,153 @@
+//! A month calendar of the entities with a date, such as the `due` date of
+//! tasks.
+//!
+//! Entities are placed on the local day of each value of the predicate.
+//! Moving one to another day shifts that value by whole days, keeping its
+//! time of day.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use std::collections::BTreeMap;
+use time::{Date, Duration, OffsetDateTime, UtcOffset};
+use uuid::Uuid;
+
+/// An entity on a day, with the value placing it there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entry {
+    pub id: Uuid,
+    pub timestamp: i64,
+}
+
+/// The days of the weeks, from Monday, covering the month of `day`.
+pub fn weeks(day: Date) -> Vec<[Date; 7]> {
+    let first = day.replace_day(1).expect("every month has a first day");
+    let mut monday = first - Duration::days(first.weekday().number_days_from_monday().into());
+    let mut weeks = Vec::new();
+    while monday.month() == first.month() || weeks.is_empty() {
+        let mut week = [monday; 7];
+        for (i, day) in week.iter_mut().enumerate() {
+            *day = monday + Duration::days(i as i64);
+        }
+        weeks.push(week);
+        monday += Duration::weeks(1);
+    }
+    weeks
+}
+
+/// The entries on each day from `first` to `last`, by time.
+pub fn entries(
+    projection: &Projection,
+    predicate: &str,
+    offset: UtcOffset,
+    first: Date,
+    last: Date,
+) -> BTreeMap<Date, Vec<Entry>> {
+    let mut days: BTreeMap<Date, Vec<Entry>> = BTreeMap::new();
+    for (id, entity) in projection.entities() {
+        for datum in entity.values(predicate) {
+            let Datum::DateTime(timestamp) = datum else {
+                continue;
+            };
+            let Some(day) = day(*timestamp, offset) else {
+                continue;
+            };
+            if (first..=last).contains(&day) {
+                days.entry(day).or_default().push(Entry {
+                    id: *id,
+                    timestamp: *timestamp,
+                });
+            }
+        }
+    }
+    for entries in days.values_mut() {
+        entries.sort_by_key(|e| (e.timestamp, e.id));
+    }
+    days
+}
+
+/// The actions that move the value of `entry` to `to`, keeping the other
+/// values of the predicate.
+pub fn reschedule(
+    projection: &Projection,
+    entry: Entry,
+    predicate: &str,
+    to: Date,
+    offset: UtcOffset,
+) -> Vec<Action> {
+    let (Some(entity), Some(from)) = (projection.entity(&entry.id), day(entry.timestamp, offset))
+    else {
+        return Vec::new();
+    };
+    let moved = Datum::DateTime(entry.timestamp + (to - from).whole_seconds());
+    let values = entity.values(predicate);
+    if from == to || !values.contains(&Datum::DateTime(entry.timestamp)) {
+        return Vec::new();
+    }
+    let mut actions = vec![Action::RemoveFact {
+        subject: entry.id,
+        predicate: predicate.to_string(),
+    }];
+    for datum in values {
+        let datum = if *datum == Datum::DateTime(entry.timestamp) {
+            moved.clone()
+        } else {
+            datum.clone()
+        };
+        actions.push(Action::AddFact {
+            subject: entry.id,
+            predicate: predicate.to_string(),
+            datum,
+        });
+    }
+    actions
+}
+
+fn day(timestamp: i64, offset: UtcOffset) -> Option<Date> {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .map(|t| t.to_offset(offset).date())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    #[test]
+    fn entities_are_placed_and_rescheduled() {
+        assert_eq!(weeks(date!(2024 - 07 - 15)).len(), 5);
+        assert_eq!(weeks(date!(2024 - 07 - 15))[0][0], date!(2024 - 07 - 01));
+        assert_eq!(weeks(date!(2024 - 09 - 01))[0][0], date!(2024 - 08 - 26));
+
+        let mut projection = Projection::new();
+        let task = Uuid::new_v4();
+        let due = datetime!(2024-07-03 09:30 UTC).unix_timestamp();
+        projection.apply(&Action::CreateEntity { id: task });
+        projection.apply(&Action::AddFact {
+            subject: task,
+            predicate: "due".to_string(),
+            datum: Datum::DateTime(due),
+        });
+        let (first, last) = (date!(2024 - 07 - 01), date!(2024 - 07 - 31));
+        let days = entries(&projection, "due", UtcOffset::UTC, first, last);
+        let entry = Entry {
+            id: task,
+            timestamp: due,
+        };
+        assert_eq!(days[&date!(2024 - 07 - 03)], [entry]);
+
+        let actions = reschedule(
+            &projection,
+            entry,
+            "due",
+            date!(2024 - 07 - 10),
+            UtcOffset::UTC,
+        );
+        actions.iter().for_each(|a| projection.apply(a));
+        let moved = datetime!(2024-07-10 09:30 UTC).unix_timestamp();
+        assert_eq!(
+            projection.entity(&task).unwrap().values("due"),
+            [Datum::DateTime(moved)]
+        );
+    }
+}
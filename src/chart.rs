@@ -0,0 +1,172 @@
+//! Charts of the history of numeric facts, such as a weight logged over
+//! months or expenses over a year.
+//!
+//! A chart has a series per entity: the numbers added to a predicate over
+//! time, see [`EventStorage::history`]. Time is split into equal buckets;
+//! lines show the last value in each bucket and bars their sum, so expenses
+//! logged several times a day add up.
+//!
+//! [`EventStorage::history`]: crate::legacy::storage::EventStorage::history
+
+use crate::legacy::hlc::HLTimestamp;
+use crate::legacy::storage::Datum;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    #[default]
+    Line,
+    Bar,
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Style::Line => "line",
+            Style::Bar => "bar",
+        })
+    }
+}
+
+impl FromStr for Style {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Style> {
+        match s.to_ascii_lowercase().as_str() {
+            "line" => Ok(Style::Line),
+            "bar" => Ok(Style::Bar),
+            _ => Err(anyhow!("Unknown chart style {}, expected line or bar", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub entity: Uuid,
+    /// Unix seconds and values, oldest first.
+    pub points: Vec<(i64, f64)>,
+}
+
+impl Series {
+    /// The numbers in the history of a fact. Other values are left out.
+    pub fn from_history(entity: Uuid, history: &[(HLTimestamp, Datum)]) -> Series {
+        let points = history
+            .iter()
+            .filter_map(|(hlc, datum)| match datum {
+                Datum::Integer(n) => Some((hlc.seconds(), *n as f64)),
+                Datum::Float(n) if n.is_finite() => Some((hlc.seconds(), *n)),
+                _ => None,
+            })
+            .collect();
+        Series { entity, points }
+    }
+}
+
+/// Series resampled into buckets, ready to be drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plot {
+    /// The time of the first and last point, in unix seconds.
+    pub start: i64,
+    pub end: i64,
+    /// The range of the value axis.
+    pub min: f64,
+    pub max: f64,
+    /// The value of each series in each bucket, if it has points there.
+    pub buckets: Vec<Vec<Option<f64>>>,
+}
+
+impl Plot {
+    /// Plots `series` in at most `buckets` buckets, or `None` if they have no
+    /// points.
+    pub fn new(series: &[Series], style: Style, buckets: usize) -> Option<Plot> {
+        let times = series.iter().flat_map(|s| s.points.iter().map(|(t, _)| *t));
+        let start = times.clone().min()?;
+        let end = times.max()?;
+        // A single moment gets a single bucket.
+        let count = if start == end { 1 } else { buckets.max(1) };
+        let bucket = |time: i64| {
+            let offset = (time - start) as f64 / (end - start).max(1) as f64;
+            ((offset * count as f64) as usize).min(count - 1)
+        };
+        let mut plotted = vec![vec![None; series.len()]; count];
+        for (n, s) in series.iter().enumerate() {
+            for (time, value) in &s.points {
+                let slot: &mut Option<f64> = &mut plotted[bucket(*time)][n];
+                *slot = match (style, *slot) {
+                    (Style::Bar, Some(sum)) => Some(sum + value),
+                    _ => Some(*value),
+                };
+            }
+        }
+        let values = plotted.iter().flatten().flatten().copied();
+        let mut min = values.clone().fold(f64::INFINITY, f64::min);
+        let mut max = values.fold(f64::NEG_INFINITY, f64::max);
+        if style == Style::Bar {
+            // Bars grow from zero.
+            min = min.min(0.0);
+            max = max.max(0.0);
+        }
+        Some(Plot {
+            start,
+            end,
+            min,
+            max,
+            buckets: plotted,
+        })
+    }
+
+    /// Where `value` is between the bottom (0) and the top (1) of the plot.
+    pub fn height(&self, value: f64) -> f32 {
+        if self.max == self.min {
+            return 0.5;
+        }
+        ((value - self.min) / (self.max - self.min)) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plots_series_in_buckets() {
+        let history = [
+            (HLTimestamp::new(0, 0), Datum::Integer(10)),
+            (HLTimestamp::new(5, 0), Datum::String("skipped".into())),
+            (HLTimestamp::new(10, 0), Datum::Float(20.0)),
+            (HLTimestamp::new(10, 1), Datum::Float(30.0)),
+            (HLTimestamp::new(100, 0), Datum::Integer(40)),
+        ];
+        let spending = Series::from_history(Uuid::new_v4(), &history);
+        assert_eq!(spending.points.len(), 4);
+        let empty = Series {
+            entity: Uuid::new_v4(),
+            points: Vec::new(),
+        };
+
+        let bars = Plot::new(&[spending.clone(), empty.clone()], Style::Bar, 4).unwrap();
+        assert_eq!((bars.start, bars.end), (0, 100));
+        assert_eq!(
+            bars.buckets,
+            vec![
+                vec![Some(60.0), None],
+                vec![None, None],
+                vec![None, None],
+                vec![Some(40.0), None]
+            ]
+        );
+        assert_eq!((bars.min, bars.max), (0.0, 60.0));
+        assert_eq!(bars.height(30.0), 0.5);
+
+        let line = Plot::new(&[spending], Style::Line, 4).unwrap();
+        assert_eq!(line.buckets[0], vec![Some(30.0)]);
+        assert_eq!((line.min, line.max), (30.0, 40.0));
+        assert_eq!(Plot::new(&[empty], Style::Line, 4), None);
+        assert_eq!("Bar".parse::<Style>().unwrap(), Style::Bar);
+    }
+}
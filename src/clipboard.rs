@@ -0,0 +1,151 @@
+//! Copying entities to the system clipboard and pasting them, into the same
+//! graph or another one.
+//!
+//! A copy holds the facts of the copied entities as JSON. Pasting creates
+//! new entities with fresh ids, so a subgraph can be pasted several times;
+//! links between copied entities point to their pasted counterparts. Links
+//! to entities that were not copied are kept if the target is in the graph
+//! pasted into, and dropped otherwise.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Tells a copy apart from other JSON on the clipboard.
+const FORMAT: &str = "graphite/subgraph";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subgraph {
+    format: String,
+    pub entities: Vec<Copied>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Copied {
+    pub id: Uuid,
+    pub facts: BTreeMap<String, Vec<Datum>>,
+}
+
+impl Subgraph {
+    /// Copies `ids` with all their facts, skipping entities that do not exist.
+    pub fn copy(projection: &Projection, ids: &[Uuid]) -> Subgraph {
+        let entities = ids
+            .iter()
+            .filter_map(|id| {
+                let entity = projection.entity(id)?;
+                let facts = entity
+                    .facts()
+                    .map(|(predicate, values)| (predicate.to_string(), values.to_vec()))
+                    .collect();
+                Some(Copied { id: *id, facts })
+            })
+            .collect();
+        Subgraph {
+            format: FORMAT.to_string(),
+            entities,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("subgraphs serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Subgraph> {
+        let subgraph: Subgraph = serde_json::from_str(json.trim())
+            .context("Failed to read the clipboard as copied entities")?;
+        if subgraph.format != FORMAT {
+            bail!(
+                "The clipboard holds {}, not copied entities",
+                subgraph.format
+            );
+        }
+        Ok(subgraph)
+    }
+
+    /// The actions that paste the subgraph into `projection`, and the ids of
+    /// the pasted entities in the order they were copied.
+    pub fn paste(&self, projection: &Projection) -> (Vec<Uuid>, Vec<Action>) {
+        let fresh: HashMap<Uuid, Uuid> = self
+            .entities
+            .iter()
+            .map(|copied| (copied.id, Uuid::new_v4()))
+            .collect();
+        let mut actions = Vec::new();
+        for copied in &self.entities {
+            let subject = fresh[&copied.id];
+            actions.push(Action::CreateEntity { id: subject });
+            for (predicate, values) in &copied.facts {
+                for datum in values {
+                    let datum = match datum {
+                        Datum::Entity(target) => match fresh.get(target) {
+                            Some(pasted) => Datum::Entity(*pasted),
+                            None if projection.contains(target) => datum.clone(),
+                            None => continue,
+                        },
+                        _ => datum.clone(),
+                    };
+                    actions.push(Action::AddFact {
+                        subject,
+                        predicate: predicate.clone(),
+                        datum,
+                    });
+                }
+            }
+        }
+        let pasted = self
+            .entities
+            .iter()
+            .map(|copied| fresh[&copied.id])
+            .collect();
+        (pasted, actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pastes_with_fresh_ids() {
+        let mut source = Projection::new();
+        let (project, task, person) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let fact = |subject, predicate: &str, datum| Action::AddFact {
+            subject,
+            predicate: predicate.into(),
+            datum,
+        };
+        for action in [
+            Action::CreateEntity { id: project },
+            Action::CreateEntity { id: task },
+            Action::CreateEntity { id: person },
+            fact(project, "name", Datum::String("Garden".into())),
+            fact(task, "parent", Datum::Entity(project)),
+            fact(task, "assignee", Datum::Entity(person)),
+        ] {
+            source.apply(&action);
+        }
+        let json = Subgraph::copy(&source, &[project, task]).to_json();
+        let subgraph = Subgraph::from_json(&json).unwrap();
+
+        // Into the same graph the link to the person who was not copied stays.
+        let (pasted, actions) = subgraph.paste(&source);
+        source.apply(&Action::Transaction { actions });
+        assert_eq!(source.len(), 5);
+        let copy = source.entity(&pasted[1]).unwrap();
+        assert_eq!(copy.value("parent"), Some(&Datum::Entity(pasted[0])));
+        assert_eq!(copy.value("assignee"), Some(&Datum::Entity(person)));
+
+        // Into another graph it is dropped.
+        let mut other = Projection::new();
+        let (pasted, actions) = subgraph.paste(&other);
+        other.apply(&Action::Transaction { actions });
+        assert_eq!(other.len(), 2);
+        assert_eq!(other.entity(&pasted[1]).unwrap().value("assignee"), None);
+
+        assert!(Subgraph::from_json("{\"format\":\"other\",\"entities\":[]}").is_err());
+        assert!(Subgraph::from_json("plain text").is_err());
+    }
+}
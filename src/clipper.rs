@@ -0,0 +1,242 @@
+//! Saving pages from a browser.
+//!
+//! A browser extension or bookmarklet posts a JSON [`Clip`] to `/clip` with an
+//! `Authorization: Bearer <token>` header, served by [`crate::api`]. The page
+//! is recorded as a `bookmark` entity linked to a `source` entity for its
+//! domain, with its tags and a note if given, and the selected text, if any,
+//! as a `highlight` entity linked to the bookmark, citing the page as the
+//! source of its text.
+//!
+//! A page is bookmarked once: urls are compared [`normalize`]d, so clipping
+//! it again from another link updates the bookmark it already has.
+
+use crate::import::{entity_id, upsert};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use crate::provenance::{cite, Source};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Clip {
+    pub url: String,
+    pub title: Option<String>,
+    pub selection: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// What the clipper wrote about the page.
+    pub note: Option<String>,
+}
+
+/// Returns a new random token for authenticating clips.
+pub fn generate_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// The domain of `url`, lower-cased and without a leading `www.`.
+pub fn domain(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map(|(_, rest)| rest)?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// `url` without what doesn't change the page it points to: the case of the
+/// scheme and the host, a leading `www.`, the default port, the fragment,
+/// tracking parameters such as `utm_source`, and a trailing slash.
+pub fn normalize(url: &str) -> String {
+    let url = url.trim();
+    let url = url.split('#').next().unwrap_or(url);
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let scheme = scheme.to_lowercase();
+    let (authority, rest) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+    let mut host = authority.to_lowercase();
+    if let Some(stripped) = host.strip_prefix("www.") {
+        host = stripped.to_string();
+    }
+    for (scheme_, port) in [("http", ":80"), ("https", ":443")] {
+        if scheme == scheme_ {
+            if let Some(stripped) = host.strip_suffix(port) {
+                host = stripped.to_string();
+            }
+        }
+    }
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let path = path.trim_end_matches('/');
+    let query: Vec<&str> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or(pair);
+            !key.starts_with("utm_") && !["fbclid", "gclid", "ref"].contains(&key)
+        })
+        .collect();
+    match query.is_empty() {
+        true => format!("{}://{}{}", scheme, host, path),
+        false => format!("{}://{}{}?{}", scheme, host, path, query.join("&")),
+    }
+}
+
+pub fn bookmark_entity(url: &str) -> Uuid {
+    entity_id(&format!("bookmark:{}", normalize(url)))
+}
+
+/// The bookmark of the page at `url`, whether clipped or added otherwise.
+pub fn find_bookmark(projection: &Projection, url: &str) -> Uuid {
+    let url = normalize(url);
+    let bookmarks = projection.entities().filter(|(_, entity)| {
+        entity.value("type") == Some(&Datum::String("bookmark".to_string()))
+            && matches!(entity.value("url"), Some(Datum::String(u)) if normalize(u) == url)
+    });
+    let mut ids: Vec<Uuid> = bookmarks.map(|(id, _)| *id).collect();
+    ids.sort();
+    let clipped = entity_id(&format!("bookmark:{}", url));
+    match ids.contains(&clipped) {
+        true => clipped,
+        false => ids.first().copied().unwrap_or(clipped),
+    }
+}
+
+pub fn source_entity(domain: &str) -> Uuid {
+    entity_id(&format!("source:{}", domain))
+}
+
+/// Returns the actions that record `clip`.
+///
+/// Clipping the same url again updates its bookmark, and adds another
+/// highlight if text was selected.
+pub fn actions(projection: &Projection, clip: &Clip) -> Result<Vec<Action>> {
+    let domain = domain(&clip.url).ok_or_else(|| anyhow!("Invalid url {}", clip.url))?;
+    let source = source_entity(&domain);
+    let bookmark = find_bookmark(projection, &clip.url);
+    let text = |s: &str| Datum::String(s.to_string());
+
+    let mut source_facts = BTreeMap::new();
+    source_facts.insert("type".to_string(), vec![text("source")]);
+    source_facts.insert("domain".to_string(), vec![text(&domain)]);
+    let mut actions = upsert(projection, source, &source_facts);
+
+    let mut facts = BTreeMap::new();
+    facts.insert("type".to_string(), vec![text("bookmark")]);
+    facts.insert("url".to_string(), vec![text(&clip.url)]);
+    facts.insert("source".to_string(), vec![Datum::Entity(source)]);
+    if let Some(title) = &clip.title {
+        facts.insert("title".to_string(), vec![text(title)]);
+    }
+    if !clip.tags.is_empty() {
+        // Keep tags from earlier clips of the same page.
+        let mut tags = projection
+            .entity(&bookmark)
+            .map(|e| e.values("tag").to_vec())
+            .unwrap_or_default();
+        for tag in &clip.tags {
+            if !tags.contains(&text(tag)) {
+                tags.push(text(tag));
+            }
+        }
+        facts.insert("tag".to_string(), tags);
+    }
+    if let Some(note) = clip.note.as_deref().filter(|n| !n.trim().is_empty()) {
+        facts.insert("note".to_string(), vec![text(note)]);
+    }
+    actions.extend(upsert(projection, bookmark, &facts));
+
+    if let Some(selection) = clip.selection.as_deref().filter(|s| !s.trim().is_empty()) {
+        let highlight = Uuid::new_v4();
+        actions.push(Action::CreateEntity { id: highlight });
+        let quoted = Action::AddFact {
+            subject: highlight,
+            predicate: "text".to_string(),
+            datum: text(selection),
+        };
+        actions.extend(cite(vec![quoted], &Source::Url(clip.url.clone())));
+        for (predicate, datum) in [
+            ("type", text("highlight")),
+            ("bookmark", Datum::Entity(bookmark)),
+        ] {
+            actions.push(Action::AddFact {
+                subject: highlight,
+                predicate: predicate.to_string(),
+                datum,
+            });
+        }
+    }
+
+    Ok(actions)
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(selection: Option<&str>, tags: &[&str]) -> Clip {
+        Clip {
+            url: "https://www.Example.com/post?id=1".into(),
+            title: Some("Post".into()),
+            selection: selection.map(str::to_string),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            note: None,
+        }
+    }
+
+    #[test]
+    fn extracts_domains() {
+        assert_eq!(
+            domain("https://www.Example.com/a").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(domain("http://user@host:8080?q").as_deref(), Some("host"));
+        assert_eq!(domain("not a url"), None);
+    }
+
+    #[test]
+    fn clips_become_bookmarks_and_highlights() {
+        let mut projection = Projection::new();
+        let first = clip(Some("quote"), &["rust"]);
+        actions(&projection, &first)
+            .unwrap()
+            .iter()
+            .for_each(|a| projection.apply(a));
+        let second = clip(Some("another quote"), &["sqlite"]);
+        actions(&projection, &second)
+            .unwrap()
+            .iter()
+            .for_each(|a| projection.apply(a));
+
+        // A source, a bookmark and two highlights.
+        assert_eq!(projection.len(), 4);
+        let bookmark = projection.entity(&bookmark_entity(&first.url)).unwrap();
+        assert_eq!(
+            bookmark.value("source"),
+            Some(&Datum::Entity(source_entity("example.com")))
+        );
+        assert_eq!(bookmark.values("tag").len(), 2);
+
+        // The same page from another link, with a note.
+        let again = Clip {
+            url: "HTTPS://example.com:443/post/?utm_source=feed&id=1#comments".into(),
+            note: Some("Read later".into()),
+            ..clip(None, &[])
+        };
+        actions(&projection, &again)
+            .unwrap()
+            .iter()
+            .for_each(|a| projection.apply(a));
+        assert_eq!(projection.len(), 4);
+        let bookmark = projection.entity(&bookmark_entity(&first.url)).unwrap();
+        assert_eq!(
+            bookmark.value("note"),
+            Some(&Datum::String("Read later".into()))
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Application settings, kept as TOML in the platform's config directory,
+//! e.g. `~/.config/graphite/config.toml` on Linux.
+//!
+//! Missing settings take their default, so the file only needs the ones
+//! that differ.
+
+use crate::backup;
+use crate::export::{jsonld, pdf, rdf};
+use crate::legacy::storage::clock;
+use crate::shortcuts::{self, Binding, Command};
+use crate::{api, crdt, folder, graph, maintenance, relay, rollup, theme, wasm};
+use anyhow::{Context, Result};
+#[cfg(feature = "gui")]
+use iced::keyboard::{Key, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The name of an iced theme, e.g. `Dark` or `Solarized Light`, or of a
+    /// custom palette.
+    pub theme: String,
+    /// The language of the editor, e.g. `de`, or `None` for the system's.
+    pub language: Option<String>,
+    /// Custom palettes by name, see [`crate::theme`].
+    pub palettes: BTreeMap<String, theme::Palette>,
+    /// The database opened when none is given on the command line.
+    pub database: PathBuf,
+    /// Seconds between saves of unsaved edits, such as changed settings. 0
+    /// only saves them on request.
+    pub autosave_interval: u64,
+    pub window_mode: WindowMode,
+    /// The size of the main window in windowed mode, kept up to date when it
+    /// is resized.
+    pub window_width: u32,
+    pub window_height: u32,
+    /// Key combinations such as `Ctrl+Shift+D` by the command they run, see
+    /// [`crate::shortcuts`].
+    pub keybindings: BTreeMap<Command, Binding>,
+    /// The predicate that links an entity to its parent in project trees.
+    pub hierarchy: String,
+    /// The entities everything should be reachable from, see
+    /// [`crate::hygiene`].
+    pub roots: Vec<uuid::Uuid>,
+    /// Days deleted entities can be restored from the trash.
+    pub trash_days: u64,
+    /// The predicates whose edits merge across devices, see [`crate::crdt`].
+    pub crdt: crdt::Types,
+    /// The aggregations of descendants shown on parent entities.
+    pub rollups: Vec<rollup::Definition>,
+    /// The sections of entity pages exported as PDF, in order.
+    pub pdf_sections: Vec<pdf::Section>,
+    /// How entities and predicates map to IRIs in RDF, see
+    /// [`crate::export::rdf`].
+    pub rdf: rdf::Mapping,
+    /// How typed entities map to JSON-LD, see [`crate::export::jsonld`].
+    pub jsonld: jsonld::Mapping,
+    /// Where and how often the database is backed up, see [`crate::backup`].
+    pub backups: backup::Settings,
+    /// The relay the graph syncs through, see [`crate::relay`].
+    pub relay: relay::Settings,
+    /// The shared folder the graph syncs through, see [`crate::folder`].
+    pub folder: folder::Settings,
+    /// What happens to edits while the system clock is behind, see
+    /// [`crate::legacy::storage::clock`].
+    pub clock: clock::Guard,
+    /// How the WebAssembly modules of plugins run, see [`crate::wasm`].
+    pub wasm: wasm::Runtime,
+    /// The HTTP API for automations, see [`crate::api`].
+    pub api: api::Settings,
+    /// How often and how the database is maintained, see
+    /// [`crate::maintenance`].
+    pub maintenance: maintenance::Settings,
+    /// Milliseconds after which SQL statements are written to the
+    /// slow-query log, 0 for none, see
+    /// [`crate::legacy::storage::profile`].
+    pub slow_query_ms: u64,
+    /// How the graph canvas draws it, see [`crate::graph`].
+    pub canvas: graph::Settings,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            theme: "Dark".to_string(),
+            language: None,
+            palettes: BTreeMap::new(),
+            database: PathBuf::from("graphite.db"),
+            autosave_interval: 30,
+            window_mode: WindowMode::Fullscreen,
+            window_width: 1024,
+            window_height: 768,
+            keybindings: shortcuts::defaults(),
+            hierarchy: "parent".to_string(),
+            roots: Vec::new(),
+            trash_days: 30,
+            crdt: crdt::Types::new(),
+            rollups: rollup::Definition::defaults(),
+            pdf_sections: pdf::Section::ALL.to_vec(),
+            rdf: rdf::Mapping::default(),
+            jsonld: jsonld::Mapping::default(),
+            backups: backup::Settings::default(),
+            relay: relay::Settings::default(),
+            folder: folder::Settings::default(),
+            clock: clock::Guard::default(),
+            wasm: wasm::Runtime::default(),
+            api: api::Settings::default(),
+            maintenance: maintenance::Settings::default(),
+            slow_query_ms: 500,
+            canvas: graph::Settings::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Where the config is kept, if the platform has a config directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("graphite").join("config.toml"))
+    }
+
+    /// Reads the config at `path`, or the defaults if there is no file.
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let mut config: Config =
+            toml::from_str(&text).with_context(|| format!("Invalid config {}", path.display()))?;
+        // Commands added since the file was written get their default.
+        for command in Command::ALL {
+            config
+                .keybindings
+                .entry(command)
+                .or_insert_with(|| command.default_binding());
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let text = toml::to_string_pretty(self).context("Failed to serialize the config")?;
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The command bound to a key press, if any.
+    #[cfg(feature = "gui")]
+    pub fn command(&self, key: &Key, modifiers: Modifiers) -> Option<Command> {
+        shortcuts::command(&self.keybindings, key, modifiers)
+    }
+}
+
+/// How the main window opens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    Windowed,
+    Maximized,
+    #[default]
+    Fullscreen,
+}
+
+impl WindowMode {
+    pub const ALL: [WindowMode; 3] = [
+        WindowMode::Windowed,
+        WindowMode::Maximized,
+        WindowMode::Fullscreen,
+    ];
+}
+
+impl WindowMode {
+    /// The id of the mode's label in the translations, see [`crate::i18n`].
+    pub fn message_id(self) -> &'static str {
+        match self {
+            WindowMode::Windowed => "window-windowed",
+            WindowMode::Maximized => "window-maximized",
+            WindowMode::Fullscreen => "window-fullscreen",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_files_fall_back_to_defaults() {
+        let dir = std::env::temp_dir().join(format!("graphite-config-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("config.toml");
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &path,
+            "window_mode = \"windowed\"\n[keybindings]\njournal_today = \"Alt+T\"\n\n\
+             [[rollups]]\nname = \"tasks\"\npredicate = \"status\"\naggregate = \"count\"\n",
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.window_mode, WindowMode::Windowed);
+        assert_eq!(config.theme, "Dark");
+        assert_eq!(config.rollups[0].aggregate, rollup::Aggregate::Count);
+        assert_eq!(
+            config.keybindings[&Command::JournalToday].to_string(),
+            "Alt+T"
+        );
+        assert_eq!(
+            config.keybindings[&Command::Diagnostics],
+            Command::Diagnostics.default_binding()
+        );
+
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
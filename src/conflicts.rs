@@ -0,0 +1,332 @@
+//! Conflicts between concurrent edits brought in by sync.
+//!
+//! Facts are resolved by the last writer: events apply in the order of their
+//! timestamps, so when two replicas change a fact without having seen each
+//! other's edit, the value of the later edit is kept. When events are merged
+//! from elsewhere, such as a restored backup or an imported archive, each
+//! fact changed both by the merged events and by local events they had not
+//! seen, and on which the two sides disagree, is recorded as a conflict
+//! entity: `type=conflict`, with the `subject` and `predicate` of the fact,
+//! the `local` and `synced` values of each side and the `origin` of the
+//! merged events. The losing values are kept there until the conflict is
+//! resolved, which sets the fact and deletes the conflict entity.
+//!
+//! Whether a replica had seen an edit is told by the events it has. Events
+//! recorded with causal metadata, see [`crate::legacy::vector_clock`], also
+//! tell which events they had seen when created, so edits made after
+//! syncing with the other replica aren't taken for concurrent ones.
+
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{metadata, Action, Datum, Event, EventStorage};
+use crate::legacy::vector_clock;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+/// The type of conflict entities.
+pub const CONFLICT: &str = "conflict";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// The conflict entity, or `None` for one not yet recorded.
+    pub id: Option<Uuid>,
+    pub subject: Uuid,
+    pub predicate: String,
+    /// The values of the fact on this replica before the merge.
+    pub local: Vec<Datum>,
+    /// The values of the fact in the merged events.
+    pub synced: Vec<Datum>,
+    pub origin: Option<String>,
+}
+
+/// Which values a conflict is resolved with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Local,
+    Synced,
+    /// Both sides, as a fact with several values.
+    Both,
+}
+
+impl Conflict {
+    fn read(id: Uuid, entity: &Entity) -> Option<Conflict> {
+        if entity.value("type") != Some(&Datum::String(CONFLICT.to_string())) {
+            return None;
+        }
+        let (Some(Datum::Entity(subject)), Some(Datum::String(predicate))) =
+            (entity.value("subject"), entity.value("predicate"))
+        else {
+            return None;
+        };
+        Some(Conflict {
+            id: Some(id),
+            subject: *subject,
+            predicate: predicate.clone(),
+            local: entity.values("local").to_vec(),
+            synced: entity.values("synced").to_vec(),
+            origin: match entity.value("origin") {
+                Some(Datum::String(origin)) => Some(origin.clone()),
+                _ => None,
+            },
+        })
+    }
+
+    /// The actions that record the conflict as a new entity.
+    pub fn save(&self) -> Vec<Action> {
+        let id = Uuid::new_v4();
+        let fact = |predicate: &str, datum: Datum| Action::AddFact {
+            subject: id,
+            predicate: predicate.to_string(),
+            datum,
+        };
+        let mut actions = vec![
+            Action::CreateEntity { id },
+            fact("type", Datum::String(CONFLICT.to_string())),
+            fact("subject", Datum::Entity(self.subject)),
+            fact("predicate", Datum::String(self.predicate.clone())),
+        ];
+        actions.extend(self.local.iter().map(|d| fact("local", d.clone())));
+        actions.extend(self.synced.iter().map(|d| fact("synced", d.clone())));
+        actions.extend(
+            self.origin
+                .iter()
+                .map(|origin| fact("origin", Datum::String(origin.clone()))),
+        );
+        actions
+    }
+
+    /// The actions that set the fact to the values kept and delete the
+    /// conflict entity.
+    pub fn resolve(&self, keep: Keep) -> Vec<Action> {
+        let values: Vec<&Datum> = match keep {
+            Keep::Local => self.local.iter().collect(),
+            Keep::Synced => self.synced.iter().collect(),
+            Keep::Both => {
+                let mut both: Vec<&Datum> = self.local.iter().collect();
+                both.extend(self.synced.iter().filter(|d| !self.local.contains(d)));
+                both
+            }
+        };
+        let mut actions = vec![Action::RemoveFact {
+            subject: self.subject,
+            predicate: self.predicate.clone(),
+        }];
+        actions.extend(values.into_iter().map(|datum| Action::AddFact {
+            subject: self.subject,
+            predicate: self.predicate.clone(),
+            datum: datum.clone(),
+        }));
+        actions.extend(self.id.map(|id| Action::DeleteEntity { id }));
+        actions
+    }
+}
+
+/// The conflicts recorded in the graph and not resolved yet.
+pub fn conflicts(projection: &Projection) -> Vec<Conflict> {
+    let mut conflicts: Vec<Conflict> = projection
+        .entities()
+        .filter_map(|(id, entity)| Conflict::read(*id, entity))
+        .collect();
+    conflicts.sort_by(|a, b| (a.subject, &a.predicate).cmp(&(b.subject, &b.predicate)));
+    conflicts
+}
+
+/// The facts on which the `local` events and the `merged` ones, the events
+/// of another replica, disagree, that both changed without the other having
+/// seen it.
+pub fn detect<'a>(local: &'a [Event], merged: &'a [Event]) -> Vec<Conflict> {
+    let local_ids: HashSet<Uuid> = local.iter().map(Event::id).collect();
+    let merged_ids: HashSet<Uuid> = merged.iter().map(Event::id).collect();
+    let facts = |events: &mut dyn Iterator<Item = &'a Event>| {
+        let mut facts: BTreeMap<(Uuid, String), Vec<&'a Event>> = BTreeMap::new();
+        for event in events {
+            for (subject, predicate) in event.action().touches() {
+                if let Some(predicate) = predicate {
+                    let fact = (subject, predicate.to_string());
+                    facts.entry(fact).or_default().push(event);
+                }
+            }
+        }
+        facts
+    };
+    // Local edits the other replica had not seen, and its edits new here.
+    let unseen = facts(&mut local.iter().filter(|e| !merged_ids.contains(&e.id())));
+    let new = facts(&mut merged.iter().filter(|e| !local_ids.contains(&e.id())));
+    // Edits with vector clocks are concurrent only if neither had seen the
+    // other, e.g. not if the other replica had synced with this one since.
+    let concurrent = |here: &[&Event], there: &[&Event]| {
+        here.iter().any(|a| {
+            there
+                .iter()
+                .any(|b| vector_clock::concurrent(a, b).unwrap_or(true))
+        })
+    };
+
+    let project = |events: &[Event]| {
+        let mut projection = Projection::new();
+        events.iter().for_each(|e| projection.apply_event(e));
+        projection
+    };
+    let (mine, theirs) = (project(local), project(merged));
+    let origin = merged
+        .iter()
+        .find_map(|e| e.metadata().get(metadata::SYNC_ORIGIN).cloned());
+    let is_conflict = |id: &Uuid| {
+        mine.entity(id)
+            .is_some_and(|e| Conflict::read(*id, e).is_some())
+    };
+    let mut conflicts = Vec::new();
+    for ((subject, predicate), here) in &unseen {
+        let Some(there) = new.get(&(*subject, predicate.clone())) else {
+            continue;
+        };
+        if is_conflict(subject) || !concurrent(here, there) {
+            continue;
+        }
+        let values = |projection: &Projection| -> Vec<Datum> {
+            projection
+                .entity(subject)
+                .map(|e| e.values(predicate).to_vec())
+                .unwrap_or_default()
+        };
+        let (local, synced) = (values(&mine), values(&theirs));
+        let same = local.len() == synced.len() && local.iter().all(|d| synced.contains(d));
+        if !same {
+            conflicts.push(Conflict {
+                id: None,
+                subject: *subject,
+                predicate: predicate.clone(),
+                local,
+                synced,
+                origin: origin.clone(),
+            });
+        }
+    }
+    conflicts
+}
+
+/// Merges `events` into `storage` like [`EventStorage::merge`], recording
+/// the conflicts with local edits, and returns how many events were merged
+/// and how many conflicts were found.
+pub fn merge(storage: &mut EventStorage, events: Vec<Event>) -> Result<(usize, usize)> {
+    let mut local = Vec::new();
+    storage.play(|event| {
+        local.push(event);
+        Ok(())
+    })?;
+    let conflicts = detect(&local, &events);
+    let merged = storage.merge(events)?;
+    if !conflicts.is_empty() {
+        let actions = conflicts.iter().flat_map(Conflict::save).collect();
+        let mut creator = storage.creator()?;
+        storage.record_batch(creator.transaction(actions))?;
+    }
+    Ok((merged, conflicts.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::EventCreator;
+    use crate::legacy::vector_clock::VectorClock;
+
+    #[test]
+    fn concurrent_edits_conflict() {
+        let text = |s: &str| Datum::String(s.to_string());
+        let [task, note] = [(); 2].map(|_| Uuid::new_v4());
+        let set = |subject: Uuid, predicate: &str, value: &str| {
+            vec![
+                Action::RemoveFact {
+                    subject,
+                    predicate: predicate.to_string(),
+                },
+                Action::AddFact {
+                    subject,
+                    predicate: predicate.to_string(),
+                    datum: text(value),
+                },
+            ]
+        };
+        let mut here = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(10, 0));
+        let mut there = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(20, 0));
+        // Seen by both replicas.
+        let shared = here.transaction(vec![
+            Action::CreateEntity { id: task },
+            Action::CreateEntity { id: note },
+        ]);
+        let mut local = shared.clone();
+        local.extend(here.transaction(set(task, "status", "done")));
+        local.extend(here.transaction(set(note, "title", "Same")));
+        let mut merged = shared;
+        merged.extend(there.transaction(set(task, "status", "doing")));
+        merged.extend(there.transaction(set(note, "title", "Same")));
+        let merged: Vec<Event> = merged
+            .into_iter()
+            .map(|e| e.synced_from("backup"))
+            .collect();
+
+        let detected = detect(&local, &merged);
+        assert_eq!(detected.len(), 1);
+        let conflict = &detected[0];
+        assert_eq!(
+            (conflict.subject, conflict.predicate.as_str()),
+            (task, "status")
+        );
+        assert_eq!(
+            (&conflict.local[..], &conflict.synced[..]),
+            (&[text("done")][..], &[text("doing")][..])
+        );
+        assert_eq!(conflict.origin.as_deref(), Some("backup"));
+
+        let mut projection = Projection::new();
+        local
+            .iter()
+            .chain(&merged)
+            .for_each(|e| projection.apply_event(e));
+        conflict.save().iter().for_each(|a| projection.apply(a));
+        let recorded = conflicts(&projection);
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].local, conflict.local);
+        // Already merged, nothing new conflicts.
+        assert!(detect(&merged, &merged).is_empty());
+
+        recorded[0]
+            .resolve(Keep::Both)
+            .iter()
+            .for_each(|a| projection.apply(a));
+        assert_eq!(
+            projection.entity(&task).unwrap().values("status"),
+            [text("done"), text("doing")]
+        );
+        assert!(conflicts(&projection).is_empty());
+    }
+
+    #[test]
+    fn sequential_edits_do_not_conflict() {
+        let task = Uuid::new_v4();
+        let status = |value: &str| Action::AddFact {
+            subject: task,
+            predicate: "status".to_string(),
+            datum: Datum::String(value.to_string()),
+        };
+        let detected = |causal: bool| {
+            let mut here = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(10, 0));
+            let mut there = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(20, 0));
+            if causal {
+                here.set_clock(VectorClock::default());
+                there.set_clock(VectorClock::default());
+            }
+            let created = here.create(Action::CreateEntity { id: task });
+            let done = here.create(status("done"));
+            // The other replica had seen the edit, but its events merged
+            // here leave it out, e.g. a partial copy.
+            there.observe(&done);
+            let doing = there.create(status("doing"));
+            detect(&[created.clone(), done], &[created, doing]).len()
+        };
+        assert_eq!(detected(true), 0);
+        // Without clocks, the edits are taken for concurrent ones.
+        assert_eq!(detected(false), 1);
+    }
+}
@@ -0,0 +1,40 @@
+//! The engine, for tools that embed Graphite without the editor.
+//!
+//! Everything is recorded as [`Event`]s, each an [`Action`] by an actor at a
+//! hybrid logical timestamp ([`Hlc`]), in a [`Storage`]. A [`Projection`]
+//! replays them into the current graph: [`Entity`]s with facts, values
+//! ([`Datum`]s) by predicate.
+//!
+//! ```
+//! use graphite::core::{Action, Datum, Projection, Storage};
+//! use uuid::Uuid;
+//!
+//! let storage = Storage::open(":memory:")?;
+//! let mut creator = storage.creator()?;
+//! let ada = Uuid::new_v4();
+//! storage.record(creator.create(Action::CreateEntity { id: ada }))?;
+//! storage.record(creator.create(Action::AddFact {
+//!     subject: ada,
+//!     predicate: "name".to_string(),
+//!     datum: Datum::String("Ada".to_string()),
+//! }))?;
+//!
+//! let projection = Projection::load(&storage)?;
+//! let name = projection.entity(&ada).and_then(|e| e.value("name"));
+//! assert_eq!(name, Some(&Datum::String("Ada".to_string())));
+//! # Ok::<(), graphite::core::Error>(())
+//! ```
+//!
+//! The modules under [`crate::legacy`] hold the rest of the engine, such as
+//! checks of the log and checkpoints, as methods of these types.
+
+pub use crate::legacy::async_storage::AsyncStorage;
+pub use crate::legacy::codec::Codec;
+pub use crate::legacy::error::{GraphiteError as Error, Result};
+pub use crate::legacy::hlc::HLTimestamp as Hlc;
+pub use crate::legacy::projection::{Entity, Projection};
+pub use crate::legacy::storage::metadata::Metadata;
+pub use crate::legacy::storage::{
+    Action, Cursor, Datum, Event, EventBuilder, EventCreator, EventStorage as Storage,
+    StorageConfig,
+};
@@ -0,0 +1,218 @@
+//! Predicates whose edits on different devices merge, instead of the last one
+//! winning.
+//!
+//! A predicate is declared as one of the [`Kind`]s in the config:
+//!
+//! ```toml
+//! [crdt]
+//! likes = "g_counter"
+//! balance = "pn_counter"
+//! tags = "or_set"
+//! title = "mv_register"
+//! body = "text"
+//! ```
+//!
+//! Edits of declared predicates are recorded with their own actions, which
+//! the projection merges, see [`crate::legacy::projection::Projection`]:
+//!
+//! - counters hold one integer that `Increment`s add to, so two devices
+//!   liking something at once count two likes. A G-Counter only grows.
+//! - an OR-Set (observed-remove set) removes only the elements the remover
+//!   had seen, so an element added elsewhere meanwhile stays.
+//! - an MV-Register (multi-value register) keeps every value written at once
+//!   on different devices, until a write that has seen them replaces them.
+//! - a text keeps the characters typed on every device, see
+//!   [`crate::legacy::text`], for notes edited at once.
+//!
+//! Plain facts added to a declared predicate aren't merged, and are dropped
+//! by its next merged edit.
+
+use crate::legacy::projection::Entity;
+use crate::legacy::storage::{Action, Datum};
+use crate::legacy::text::Text;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    GCounter,
+    PnCounter,
+    OrSet,
+    MvRegister,
+    Text,
+}
+
+/// The kinds of the declared predicates, by predicate.
+pub type Types = BTreeMap<String, Kind>;
+
+impl Kind {
+    pub fn is_counter(self) -> bool {
+        matches!(self, Kind::GCounter | Kind::PnCounter)
+    }
+
+    /// The action adding `amount` to the counter `predicate` of `subject`.
+    pub fn increment(self, subject: Uuid, predicate: &str, amount: i64) -> Result<Action> {
+        match self {
+            Kind::GCounter if amount < 0 => bail!("{} can only grow", predicate),
+            Kind::GCounter | Kind::PnCounter => Ok(Action::Increment {
+                subject,
+                predicate: predicate.to_string(),
+                amount,
+            }),
+            Kind::OrSet | Kind::MvRegister | Kind::Text => {
+                bail!("{} isn't a counter", predicate)
+            }
+        }
+    }
+
+    /// The action adding `datum` to `predicate` of `subject`, which is
+    /// `entity` as seen now. Counters add integers to their count, and a
+    /// text is edited into the string.
+    pub fn add(
+        self,
+        entity: Option<&Entity>,
+        subject: Uuid,
+        predicate: &str,
+        datum: Datum,
+    ) -> Result<Action> {
+        let predicate = predicate.to_string();
+        match (self, datum) {
+            (Kind::GCounter | Kind::PnCounter, Datum::Integer(amount)) => {
+                self.increment(subject, &predicate, amount)
+            }
+            (Kind::GCounter | Kind::PnCounter, _) => {
+                bail!("{} counts, so only integers can be added", predicate)
+            }
+            (Kind::OrSet, datum) => Ok(Action::AddElement {
+                subject,
+                predicate,
+                datum,
+                tag: Uuid::new_v4(),
+            }),
+            (Kind::MvRegister, datum) => Ok(Action::Assign {
+                replaces: tags(entity, &predicate, None),
+                subject,
+                predicate,
+                datum,
+                tag: Uuid::new_v4(),
+            }),
+            (Kind::Text, Datum::String(new)) => Ok(Action::Transaction {
+                actions: text(entity, &predicate).edit(subject, &predicate, &new),
+            }),
+            (Kind::Text, _) => bail!("{} is a text, so only strings can be added", predicate),
+        }
+    }
+
+    /// The action removing `datum` from `predicate` of `subject`, which is
+    /// `entity` as seen now, or every value if `None`. A PN-Counter is reset
+    /// to nothing, and a text emptied.
+    pub fn remove(
+        self,
+        entity: Option<&Entity>,
+        subject: Uuid,
+        predicate: &str,
+        datum: Option<&Datum>,
+    ) -> Result<Action> {
+        match (self, datum) {
+            (Kind::GCounter, _) => bail!("{} can only grow", predicate),
+            (Kind::PnCounter, None) => Ok(Action::RemoveFact {
+                subject,
+                predicate: predicate.to_string(),
+            }),
+            (Kind::PnCounter, Some(_)) => bail!("Counters can only be removed as a whole"),
+            (Kind::Text, None) => Ok(Action::Transaction {
+                actions: text(entity, predicate).edit(subject, predicate, ""),
+            }),
+            (Kind::Text, Some(_)) => bail!("Texts can only be removed as a whole"),
+            (Kind::OrSet | Kind::MvRegister, datum) => Ok(Action::RemoveElements {
+                tags: tags(entity, predicate, datum),
+                subject,
+                predicate: predicate.to_string(),
+            }),
+        }
+    }
+}
+
+/// The text `predicate` of `entity` holds, empty if none.
+fn text(entity: Option<&Entity>, predicate: &str) -> Text {
+    entity
+        .and_then(|entity| entity.text(predicate))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// The tags `predicate` of `entity` holds `datum` under, or all of them.
+fn tags(entity: Option<&Entity>, predicate: &str, datum: Option<&Datum>) -> Vec<Uuid> {
+    entity
+        .map(|entity| entity.tagged(predicate))
+        .unwrap_or_default()
+        .iter()
+        .filter(|(_, d)| datum.is_none_or(|datum| d == datum))
+        .map(|(tag, _)| *tag)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::projection::Projection;
+
+    #[test]
+    fn edits_on_two_devices_merge() {
+        let post = Uuid::new_v4();
+        let mut base = Projection::new();
+        base.apply(&Action::CreateEntity { id: post });
+        let like = Kind::GCounter.increment(post, "likes", 1).unwrap();
+        base.apply(&like);
+        let tag = |projection: &Projection, datum: &str| {
+            let entity = projection.entity(&post);
+            Kind::OrSet
+                .add(entity, post, "tags", Datum::String(datum.into()))
+                .unwrap()
+        };
+        base.apply(&tag(&base, "rust"));
+        let title = |projection: &Projection, datum: &str| {
+            let entity = projection.entity(&post);
+            Kind::MvRegister
+                .add(entity, post, "title", Datum::String(datum.into()))
+                .unwrap()
+        };
+        base.apply(&title(&base, "Draft"));
+
+        // Each device edits what it has seen, and then gets the other's.
+        let (mut laptop, mut phone) = (base.clone(), base.clone());
+        let on_laptop = vec![
+            like.clone(),
+            Kind::OrSet
+                .remove(laptop.entity(&post), post, "tags", None)
+                .unwrap(),
+            title(&laptop, "Hello"),
+        ];
+        let on_phone = vec![like.clone(), tag(&phone, "rust"), title(&phone, "Hi")];
+        for action in on_laptop.iter().chain(&on_phone) {
+            laptop.apply(action);
+        }
+        for action in on_phone.iter().chain(&on_laptop) {
+            phone.apply(action);
+        }
+
+        for projection in [&laptop, &phone] {
+            let entity = projection.entity(&post).unwrap();
+            assert_eq!(entity.value("likes"), Some(&Datum::Integer(3)));
+            // The laptop removed only the tag it had seen.
+            assert_eq!(entity.values("tags"), [Datum::String("rust".into())]);
+            assert_eq!(entity.values("title").len(), 2);
+        }
+        let resolved = title(&laptop, "Hello, world");
+        laptop.apply(&resolved);
+        let entity = laptop.entity(&post).unwrap();
+        assert_eq!(
+            entity.values("title"),
+            [Datum::String("Hello, world".into())]
+        );
+        assert!(Kind::GCounter.increment(post, "likes", -1).is_err());
+    }
+}
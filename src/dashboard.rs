@@ -0,0 +1,357 @@
+//! Dashboards: grids of widgets showing the results of queries, such as a
+//! home screen for the graph.
+//!
+//! A dashboard is an entity of type `dashboard` with a `name` and a `widget`
+//! fact linking to each of its widgets. A widget is an entity of type
+//! `widget` with a `kind`:
+//!
+//! - `list`: the entities matching its `query`, at most `limit`
+//! - `counter`: how many entities match its `query`
+//! - `chart`: the entities matching its `query` counted by their value of
+//!   `group_by`
+//! - `pinned`: the facts of the `entity` it links to
+//! - `history`: a chart of the numbers added to `predicate` over time, a
+//!   series per `entity` it links to, drawn as a `line` or `bar` per `style`
+//! - `view`: the `view` of a plugin, named as `plugin/view`, see
+//!   [`crate::plugin`]
+//! - `stats`: the statistics of the database, see
+//!   [`crate::legacy::storage::stats`]
+//!
+//! A widget may have a `title`, and its place in the grid is given by `row`,
+//! `column` and `span`, the number of columns it is wide. Layout and queries
+//! are ordinary facts, so dashboards are edited and synced like any entity.
+//! Outputs are derived from the projection and never recorded.
+
+use crate::chart::Style;
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{Action, Datum};
+use crate::query::Query;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// How many entities a list shows without a `limit`.
+const DEFAULT_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dashboard {
+    pub id: Uuid,
+    pub name: String,
+    /// In reading order: by row, then by column.
+    pub widgets: Vec<Widget>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Widget {
+    pub id: Uuid,
+    pub title: String,
+    pub kind: Kind,
+    pub row: i64,
+    pub column: i64,
+    pub span: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    List {
+        query: Query,
+        limit: usize,
+    },
+    Counter {
+        query: Query,
+    },
+    Chart {
+        query: Query,
+        group_by: String,
+    },
+    Pinned {
+        entity: Option<Uuid>,
+    },
+    History {
+        entities: Vec<Uuid>,
+        predicate: String,
+        style: Style,
+    },
+    /// A view type of a plugin, by plugin and view name.
+    View {
+        plugin: String,
+        view: String,
+    },
+    Stats,
+    /// A widget whose facts can't be understood, and why.
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// The matching entities, sorted by name.
+    List(Vec<Uuid>),
+    Counter(usize),
+    /// The number of matching entities by value, most frequent first.
+    Chart(Vec<(String, usize)>),
+    Pinned(Option<Uuid>),
+    /// Plotted from the history of facts, which the projection doesn't keep,
+    /// so it is read from storage separately.
+    History,
+    /// Rendered by the plugin, which the dashboard doesn't know about.
+    View,
+    /// Counted by the storage, so it is read from storage separately.
+    Stats,
+    Invalid(String),
+}
+
+/// Every dashboard in the graph, sorted by name.
+pub fn dashboards(projection: &Projection) -> Vec<Dashboard> {
+    let mut dashboards: Vec<Dashboard> = projection
+        .entities()
+        .filter(|(_, e)| is(e, "dashboard"))
+        .map(|(id, e)| {
+            let mut widgets: Vec<Widget> = e
+                .values("widget")
+                .iter()
+                .filter_map(|datum| match datum {
+                    Datum::Entity(widget) => Some((widget, projection.entity(widget)?)),
+                    _ => None,
+                })
+                .map(|(id, e)| Widget::from_facts(*id, e))
+                .collect();
+            widgets.sort_by_key(|w| (w.row, w.column, w.id));
+            Dashboard {
+                id: *id,
+                name: string(e, "name").unwrap_or_else(|| id.to_string()),
+                widgets,
+            }
+        })
+        .collect();
+    dashboards.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+    dashboards
+}
+
+impl Widget {
+    fn from_facts(id: Uuid, entity: &Entity) -> Widget {
+        let integer = |predicate| match entity.value(predicate) {
+            Some(Datum::Integer(n)) => Some(*n),
+            _ => None,
+        };
+        let query = || match string(entity, "query").unwrap_or_default().parse() {
+            Ok(query) => Ok(query),
+            Err(e) => Err(Kind::Invalid(format!("{:#}", e))),
+        };
+        let kind = match string(entity, "kind").as_deref() {
+            Some("list") => query().map(|query| Kind::List {
+                query,
+                limit: integer("limit").map_or(DEFAULT_LIMIT, |n| n.max(0) as usize),
+            }),
+            Some("counter") => query().map(|query| Kind::Counter { query }),
+            Some("chart") => match string(entity, "group_by") {
+                Some(group_by) => query().map(|query| Kind::Chart { query, group_by }),
+                None => Err(Kind::Invalid("A chart needs a group_by".to_string())),
+            },
+            Some("pinned") => Ok(Kind::Pinned {
+                entity: match entity.value("entity") {
+                    Some(Datum::Entity(id)) => Some(*id),
+                    _ => None,
+                },
+            }),
+            Some("history") => match (string(entity, "predicate"), string(entity, "style")) {
+                (None, _) => Err(Kind::Invalid("A history needs a predicate".to_string())),
+                (Some(predicate), style) => match style.as_deref().map(str::parse).transpose() {
+                    Ok(style) => Ok(Kind::History {
+                        entities: entity
+                            .values("entity")
+                            .iter()
+                            .filter_map(|datum| match datum {
+                                Datum::Entity(id) => Some(*id),
+                                _ => None,
+                            })
+                            .collect(),
+                        predicate,
+                        style: style.unwrap_or_default(),
+                    }),
+                    Err(e) => Err(Kind::Invalid(format!("{:#}", e))),
+                },
+            },
+            Some("view") => match string(entity, "view").as_deref().map(|v| v.split_once('/')) {
+                Some(Some((plugin, view))) => Ok(Kind::View {
+                    plugin: plugin.to_string(),
+                    view: view.to_string(),
+                }),
+                _ => Err(Kind::Invalid(
+                    "A view needs a view named as plugin/view".to_string(),
+                )),
+            },
+            Some("stats") => Ok(Kind::Stats),
+            Some(kind) => Err(Kind::Invalid(format!("Unknown widget kind {}", kind))),
+            None => Err(Kind::Invalid("The widget has no kind".to_string())),
+        };
+        Widget {
+            id,
+            title: string(entity, "title").unwrap_or_default(),
+            kind: kind.unwrap_or_else(|invalid| invalid),
+            row: integer("row").unwrap_or(0),
+            column: integer("column").unwrap_or(0),
+            span: integer("span").map_or(1, |n| n.clamp(1, 12) as u16),
+        }
+    }
+
+    pub fn evaluate(&self, projection: &Projection) -> Output {
+        match &self.kind {
+            Kind::List { query, limit } => {
+                let mut matches: Vec<(String, Uuid)> = query
+                    .run(projection)
+                    .into_iter()
+                    .map(|id| (name(projection, &id), id))
+                    .collect();
+                matches.sort();
+                Output::List(matches.into_iter().take(*limit).map(|(_, id)| id).collect())
+            }
+            Kind::Counter { query } => Output::Counter(query.run(projection).len()),
+            Kind::Chart { query, group_by } => {
+                let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+                for id in query.run(projection) {
+                    let values = projection
+                        .entity(&id)
+                        .map_or(&[][..], |e| e.values(group_by));
+                    for datum in values {
+                        *counts.entry(describe(projection, datum)).or_default() += 1;
+                    }
+                }
+                let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+                counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                Output::Chart(counts)
+            }
+            Kind::Pinned { entity } => Output::Pinned(entity.filter(|id| projection.contains(id))),
+            Kind::History { .. } => Output::History,
+            Kind::View { .. } => Output::View,
+            Kind::Stats => Output::Stats,
+            Kind::Invalid(error) => Output::Invalid(error.clone()),
+        }
+    }
+
+    /// Whether the output changes with the events that touch `touched`
+    /// entities and predicates, for widgets read from storage.
+    pub fn reads(&self, touched: &[(Uuid, Option<&str>)]) -> bool {
+        let Kind::History {
+            entities,
+            predicate,
+            ..
+        } = &self.kind
+        else {
+            return false;
+        };
+        touched
+            .iter()
+            .any(|(subject, p)| entities.contains(subject) && p.is_none_or(|p| p == predicate))
+    }
+}
+
+/// The actions that create a dashboard named `name` with a few widgets to
+/// start from: open tasks, the number of entities and tasks by status.
+pub fn starter(name: &str) -> (Uuid, Vec<Action>) {
+    let dashboard = Uuid::new_v4();
+    let mut actions = vec![Action::CreateEntity { id: dashboard }];
+    let mut fact = |subject, predicate: &str, datum| {
+        actions.push(Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        })
+    };
+    let text = |s: &str| Datum::String(s.to_string());
+    fact(dashboard, "type", text("dashboard"));
+    fact(dashboard, "name", text(name));
+    let widgets = [
+        ("Open tasks", "list", "type=task status!=done", (0, 0, 2)),
+        ("Entities", "counter", "", (0, 2, 1)),
+        ("Tasks by status", "chart", "type=task", (1, 0, 3)),
+    ];
+    let mut creates = Vec::new();
+    for (title, kind, query, (row, column, span)) in widgets {
+        let widget = Uuid::new_v4();
+        creates.push(Action::CreateEntity { id: widget });
+        fact(dashboard, "widget", Datum::Entity(widget));
+        fact(widget, "type", text("widget"));
+        fact(widget, "title", text(title));
+        fact(widget, "kind", text(kind));
+        fact(widget, "query", text(query));
+        if kind == "chart" {
+            fact(widget, "group_by", text("status"));
+        }
+        fact(widget, "row", Datum::Integer(row));
+        fact(widget, "column", Datum::Integer(column));
+        fact(widget, "span", Datum::Integer(span));
+    }
+    // Widgets exist before the facts that link to them.
+    creates.extend(actions);
+    (dashboard, creates)
+}
+
+fn is(entity: &Entity, kind: &str) -> bool {
+    entity.value("type") == Some(&Datum::String(kind.to_string()))
+}
+
+fn string(entity: &Entity, predicate: &str) -> Option<String> {
+    match entity.value(predicate) {
+        Some(Datum::String(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+pub(crate) fn name(projection: &Projection, id: &Uuid) -> String {
+    projection
+        .entity(id)
+        .and_then(|e| string(e, "name").or_else(|| string(e, "title")))
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// A value as a chart label.
+pub(crate) fn describe(projection: &Projection, datum: &Datum) -> String {
+    match datum {
+        Datum::String(s) => s.clone(),
+        Datum::Integer(n) => n.to_string(),
+        Datum::Float(n) => n.to_string(),
+        Datum::Boolean(b) => b.to_string(),
+        Datum::DateTime(t) => time::OffsetDateTime::from_unix_timestamp(*t)
+            .map(|t| t.date().to_string())
+            .unwrap_or_else(|_| t.to_string()),
+        Datum::Entity(id) => name(projection, id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_the_starter_dashboard() {
+        let mut projection = Projection::new();
+        let (dashboard, actions) = starter("Home");
+        projection.apply(&Action::Transaction { actions });
+        for status in ["todo", "todo", "done"] {
+            let facts = BTreeMap::from([
+                ("type".to_string(), vec![Datum::String("task".into())]),
+                ("status".to_string(), vec![Datum::String(status.into())]),
+            ]);
+            let actions = crate::import::upsert(&projection, Uuid::new_v4(), &facts);
+            projection.apply(&Action::Transaction { actions });
+        }
+
+        let dashboards = dashboards(&projection);
+        assert_eq!(dashboards.len(), 1);
+        assert_eq!(dashboards[0].id, dashboard);
+        let outputs: Vec<Output> = dashboards[0]
+            .widgets
+            .iter()
+            .map(|w| w.evaluate(&projection))
+            .collect();
+        let Output::List(open) = &outputs[0] else {
+            panic!("expected a list, got {:?}", outputs[0]);
+        };
+        assert_eq!(open.len(), 2);
+        // The dashboard, its three widgets and the three tasks.
+        assert_eq!(outputs[1], Output::Counter(7));
+        assert_eq!(
+            outputs[2],
+            Output::Chart(vec![("todo".to_string(), 2), ("done".to_string(), 1)])
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Duplicate detection: pairs of entities that likely describe the same
+//! thing.
+//!
+//! Entities are compared by the first of their [`KEYS`] that is a string,
+//! ignoring case, punctuation and spacing, and scored by how few edits turn
+//! one into the other. Links to the same entities raise the score: a pair
+//! whose names are half alike but that link to all the same entities scores
+//! as high as a pair with equal names. Only entities sharing a word or the
+//! first letters of their name are compared, so large graphs stay quick.
+//!
+//! A pair can be merged, see [`crate::merge`], or dismissed, which links the
+//! entities by [`DISMISSED`] so that later suggestions leave them out.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+/// The predicates compared, in order of preference.
+pub const KEYS: [&str; 2] = ["name", "title"];
+/// The predicate linking entities that were dismissed as duplicates.
+pub const DISMISSED: &str = "not_duplicate_of";
+/// The lowest score suggested.
+pub const THRESHOLD: f64 = 0.8;
+
+/// How many leading characters of a name are compared besides its words.
+const PREFIX: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// The entity with more facts, suggested to be kept.
+    pub keep: Uuid,
+    pub duplicate: Uuid,
+    /// From 0 to 1, 1 for entities with equal names.
+    pub score: f64,
+    /// How many entities both link to or are linked from.
+    pub shared: usize,
+}
+
+/// The likely duplicates scoring at least `threshold`, best first.
+pub fn candidates(projection: &Projection, threshold: f64) -> Vec<Candidate> {
+    let mut names: HashMap<Uuid, String> = HashMap::new();
+    let mut neighbors: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+    let mut dismissed: HashSet<(Uuid, Uuid)> = HashSet::new();
+    for (id, entity) in projection.entities() {
+        let name = KEYS.iter().find_map(|key| match entity.value(key) {
+            Some(Datum::String(name)) => Some(normalize(name)),
+            _ => None,
+        });
+        if let Some(name) = name.filter(|name| !name.is_empty()) {
+            names.insert(*id, name);
+        }
+        for (predicate, values) in entity.facts() {
+            for datum in values {
+                let Datum::Entity(target) = datum else {
+                    continue;
+                };
+                if predicate == DISMISSED {
+                    dismissed.insert(ordered(*id, *target));
+                } else if projection.contains(target) {
+                    neighbors.entry(*id).or_default().insert(*target);
+                    neighbors.entry(*target).or_default().insert(*id);
+                }
+            }
+        }
+    }
+
+    let mut blocks: HashMap<String, Vec<Uuid>> = HashMap::new();
+    for (id, name) in &names {
+        let prefix: String = name.chars().take(PREFIX).collect();
+        let keys: BTreeSet<String> = name
+            .split(' ')
+            .map(str::to_string)
+            .chain([format!("{}…", prefix)])
+            .collect();
+        for key in keys {
+            blocks.entry(key).or_default().push(*id);
+        }
+    }
+    let mut pairs: BTreeSet<(Uuid, Uuid)> = BTreeSet::new();
+    for ids in blocks.values() {
+        for (i, a) in ids.iter().enumerate() {
+            for b in &ids[i + 1..] {
+                pairs.insert(ordered(*a, *b));
+            }
+        }
+    }
+
+    let none = HashSet::new();
+    let mut candidates = Vec::new();
+    for (a, b) in pairs {
+        if dismissed.contains(&(a, b)) {
+            continue;
+        }
+        let similarity = similarity(&names[&a], &names[&b]);
+        let (links_a, links_b) = (
+            neighbors.get(&a).unwrap_or(&none),
+            neighbors.get(&b).unwrap_or(&none),
+        );
+        let shared = links_a.intersection(links_b).count();
+        let union = links_a.union(links_b).count();
+        let overlap = if union == 0 {
+            0.0
+        } else {
+            shared as f64 / union as f64
+        };
+        let score = similarity + (1.0 - similarity) * overlap;
+        if score < threshold {
+            continue;
+        }
+        let facts = |id: &Uuid| {
+            projection
+                .entity(id)
+                .map_or(0, |e| e.facts().map(|(_, v)| v.len()).sum::<usize>())
+        };
+        let (keep, duplicate) = if facts(&b) > facts(&a) {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        candidates.push(Candidate {
+            keep,
+            duplicate,
+            score,
+            shared,
+        });
+    }
+    candidates.sort_by(|x, y| {
+        y.score
+            .total_cmp(&x.score)
+            .then_with(|| (x.keep, x.duplicate).cmp(&(y.keep, y.duplicate)))
+    });
+    candidates
+}
+
+/// The actions that leave the pair out of later suggestions.
+pub fn dismiss(candidate: &Candidate) -> Vec<Action> {
+    vec![Action::AddFact {
+        subject: candidate.keep,
+        predicate: DISMISSED.to_string(),
+        datum: Datum::Entity(candidate.duplicate),
+    }]
+}
+
+/// `name` in lowercase, with runs of anything but letters and digits as a
+/// single space.
+fn normalize(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One minus the edit distance between `a` and `b` relative to the longer.
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    // Levenshtein distance, one row at a time.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    1.0 - row[b.len()] as f64 / longest as f64
+}
+
+fn ordered(a: Uuid, b: Uuid) -> (Uuid, Uuid) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(projection: &mut Projection, name: &str) -> Uuid {
+        let id = Uuid::new_v4();
+        projection.apply(&Action::CreateEntity { id });
+        projection.apply(&Action::AddFact {
+            subject: id,
+            predicate: "name".to_string(),
+            datum: Datum::String(name.to_string()),
+        });
+        id
+    }
+
+    fn link(projection: &mut Projection, subject: Uuid, target: Uuid) {
+        projection.apply(&Action::AddFact {
+            subject,
+            predicate: "knows".to_string(),
+            datum: Datum::Entity(target),
+        });
+    }
+
+    #[test]
+    fn suggests_similar_names_and_shared_links() {
+        let mut projection = Projection::new();
+        let ada = named(&mut projection, "Ada Lovelace");
+        let typo = named(&mut projection, "ada  lovelace.");
+        let grace = named(&mut projection, "Grace Hopper");
+        let hopper = named(&mut projection, "G. Hopper");
+        let other = named(&mut projection, "Grace Kelly");
+        let navy = named(&mut projection, "Navy");
+        link(&mut projection, ada, navy);
+        link(&mut projection, grace, navy);
+        link(&mut projection, hopper, navy);
+        link(&mut projection, hopper, ada);
+
+        let found = candidates(&projection, THRESHOLD);
+        let pairs: Vec<_> = found.iter().map(|c| ordered(c.keep, c.duplicate)).collect();
+        assert_eq!(pairs, [ordered(ada, typo), ordered(grace, hopper)]);
+        assert_eq!(found[0].score, 1.0);
+        assert_eq!(found[0].keep, ada);
+        assert_eq!(found[1].shared, 1);
+        assert!(found[1].score < 1.0);
+        assert!(!pairs.contains(&ordered(grace, other)));
+
+        for action in dismiss(&found[0]) {
+            projection.apply(&action);
+        }
+        assert_eq!(candidates(&projection, THRESHOLD).len(), 1);
+    }
+}
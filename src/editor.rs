@@ -1,41 +1,944 @@
-use iced::{executor, widget::text, window, Application, Command, Element, Theme};
+mod backups;
+mod board;
+mod calendar;
+mod canvas;
+mod chart;
+mod checkpoints;
+mod clock;
+mod conflicts;
+mod console;
+mod dashboard;
+mod data_dir;
+mod dedupe;
+mod diagnostics;
+mod file_drop;
+mod folder;
+mod help;
+mod hygiene;
+mod inspector;
+mod journal;
+mod locked;
+mod locks;
+mod nearby;
+mod operations;
+mod plugins;
+mod scripts;
+mod search;
+mod settings;
+mod staging;
+mod table;
+mod tasks;
+mod templates;
+mod thumbnails;
+mod timer;
+mod trash;
+mod validation;
 
-pub struct Editor;
+use crate::config::{Config, WindowMode};
+use crate::i18n::{self, Localizer};
+use crate::legacy::async_storage::AsyncStorage;
+use crate::legacy::error::GraphiteError;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::changes::Changes;
+use crate::legacy::storage::writer::Holder;
+use crate::legacy::storage::{Action, Datum, Event, EventCreator};
+use crate::location::Location;
+use crate::rollup::Rollups;
+use crate::shortcuts;
+use fluent_bundle::FluentValue;
+use iced::futures::SinkExt;
+use iced::keyboard::{self, key::Named, Key, Modifiers};
+use iced::multi_window::Application;
+use iced::widget::{column, container, row, text};
+use iced::{
+    event, executor, mouse, subscription, time, window, Command, Element, Length, Subscription,
+    Theme,
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Message {}
+/// How often a read-only editor looks for events other processes recorded.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+pub struct Editor {
+    storage: AsyncStorage,
+    /// Shared with the API, so their events never share a timestamp.
+    creator: Arc<Mutex<EventCreator>>,
+    projection: Projection,
+    /// Derived facts of parent entities, kept up to date with the projection.
+    rollups: Rollups,
+    /// The constraints each entity breaks, kept up to date with the
+    /// projection.
+    validator: crate::validation::Validator,
+    /// When each entity was last edited, to rank search results.
+    recency: crate::search::Recency,
+    journal: journal::Journal,
+    tasks: tasks::Tasks,
+    diagnostics: diagnostics::Diagnostics,
+    hygiene: hygiene::Hygiene,
+    scripts: scripts::Scripts,
+    templates: templates::Templates,
+    table: table::Table,
+    board: board::Board,
+    calendar: calendar::Calendar,
+    canvas: canvas::Canvas,
+    search: search::Search,
+    thumbnails: thumbnails::Thumbnails,
+    staging: staging::Staging,
+    conflicts: conflicts::Conflicts,
+    dedupe: dedupe::Dedupe,
+    checkpoints: checkpoints::Checkpoints,
+    backups: backups::Backups,
+    nearby: nearby::Nearby,
+    dashboards: dashboard::Dashboards,
+    file_drop: file_drop::FileDrop,
+    plugins: plugins::Plugins,
+    operations: operations::Operations,
+    locks: locks::Locks,
+    /// Whether the list of shortcuts is shown.
+    help: bool,
+    /// Whether the debug console is shown.
+    console: bool,
+    /// Whether the trash is shown.
+    trash: bool,
+    /// The modifier keys held, for Shift+click and Ctrl+click.
+    modifiers: Modifiers,
+    /// The state of each open inspector window.
+    inspectors: HashMap<window::Id, inspector::Inspector>,
+    config: Config,
+    /// The theme named in the config, resolved against the config and graph
+    /// palettes.
+    theme: Theme,
+    graph_colors: crate::theme::GraphColors,
+    /// The translations of the configured or the system's language.
+    i18n: Localizer,
+    /// Where the config is saved to, if the platform has a config directory.
+    config_path: Option<PathBuf>,
+    /// Where the config, the database and the rest are kept.
+    location: Location,
+    /// Whether the database was opened read-only, which disables editing.
+    read_only: bool,
+    /// The process writing to the database, while the prompt offering to
+    /// look at it read-only is shown.
+    locked: Option<Holder>,
+    /// The latest change read from the change feed, which a read-only
+    /// editor polls to follow the process writing to the database.
+    changes: Option<i64>,
+    /// How many seconds the system clock is behind the latest event, if by
+    /// more than tolerated.
+    clock_skew: Option<i64>,
+    data_dir: data_dir::DataDir,
+    settings: settings::Settings,
+    error: Option<String>,
+}
+
+pub struct Flags {
+    pub storage: AsyncStorage,
+    /// Creates the events for the edits made in the editor, and those of the
+    /// API if it is served.
+    pub creator: Arc<Mutex<EventCreator>>,
+    pub config: Config,
+    pub config_path: Option<PathBuf>,
+    pub location: Location,
+    pub read_only: bool,
+    /// The process holding the write lock, if that is why the database was
+    /// opened read-only.
+    pub locked: Option<Holder>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// The full event log was read from storage.
+    Loaded(Result<Vec<Event>, String>),
+    /// An event was recorded after the projection was loaded.
+    Recorded(Event),
+    /// The subscription missed events and the projection must be reloaded.
+    Lagged,
+    /// Time to look for events recorded by other processes.
+    Poll,
+    /// Events recorded by other processes, see
+    /// [`crate::legacy::storage::changes`].
+    Changes(Result<Changes, String>),
+    /// An edit made in the editor was written to storage.
+    Saved(Result<(), String>),
+    Journal(journal::Message),
+    Tasks(tasks::Message),
+    Diagnostics(diagnostics::Message),
+    Hygiene(hygiene::Message),
+    Dedupe(dedupe::Message),
+    Templates(templates::Message),
+    Table(table::Message),
+    Board(board::Message),
+    Calendar(calendar::Message),
+    Canvas(canvas::Message),
+    Search(search::Message),
+    Scripts(scripts::Message),
+    Thumbnails(thumbnails::Message),
+    Plugins(plugins::Message),
+    Staging(staging::Message),
+    Conflicts(conflicts::Message),
+    Trash(trash::Message),
+    Checkpoints(checkpoints::Message),
+    Backups(backups::Message),
+    Folder(folder::Message),
+    Nearby(nearby::Message),
+    Clock(clock::Message),
+    Dashboard(dashboard::Message),
+    FileDrop(file_drop::Message),
+    Operations(operations::Message),
+    Help(help::Message),
+    Console(console::Message),
+    Timer(timer::Message),
+    Settings(settings::Message),
+    DataDir(data_dir::Message),
+    Locks(locks::Message),
+    Locked(locked::Message),
+    /// A message from the inspector in a window.
+    Inspector(window::Id, inspector::Message),
+    Window(window::Id, window::Event),
+    KeyPressed(Key, Modifiers),
+    ModifiersChanged(Modifiers),
+}
+
+impl Message {
+    /// The name of the variant, for logging.
+    fn name(&self) -> &'static str {
+        match self {
+            Message::Loaded(_) => "Loaded",
+            Message::Recorded(_) => "Recorded",
+            Message::Lagged => "Lagged",
+            Message::Poll => "Poll",
+            Message::Changes(_) => "Changes",
+            Message::Saved(_) => "Saved",
+            Message::Journal(_) => "Journal",
+            Message::Tasks(_) => "Tasks",
+            Message::Diagnostics(_) => "Diagnostics",
+            Message::Hygiene(_) => "Hygiene",
+            Message::Dedupe(_) => "Dedupe",
+            Message::Templates(_) => "Templates",
+            Message::Table(_) => "Table",
+            Message::Board(_) => "Board",
+            Message::Calendar(_) => "Calendar",
+            Message::Canvas(_) => "Canvas",
+            Message::Search(_) => "Search",
+            Message::Scripts(_) => "Scripts",
+            Message::Thumbnails(_) => "Thumbnails",
+            Message::Plugins(_) => "Plugins",
+            Message::Staging(_) => "Staging",
+            Message::Conflicts(_) => "Conflicts",
+            Message::Trash(_) => "Trash",
+            Message::Checkpoints(_) => "Checkpoints",
+            Message::Backups(_) => "Backups",
+            Message::Folder(_) => "Folder",
+            Message::Nearby(_) => "Nearby",
+            Message::Clock(_) => "Clock",
+            Message::Dashboard(_) => "Dashboard",
+            Message::FileDrop(_) => "FileDrop",
+            Message::Operations(_) => "Operations",
+            Message::Help(_) => "Help",
+            Message::Console(_) => "Console",
+            Message::Timer(_) => "Timer",
+            Message::Settings(_) => "Settings",
+            Message::DataDir(_) => "DataDir",
+            Message::Locks(_) => "Locks",
+            Message::Locked(_) => "Locked",
+            Message::Inspector(..) => "Inspector",
+            Message::Window(..) => "Window",
+            Message::KeyPressed(..) => "KeyPressed",
+            Message::ModifiersChanged(_) => "ModifiersChanged",
+        }
+    }
+}
+
+impl Editor {
+    fn load(&mut self) -> Command<Message> {
+        let reporter = self.start_operation(self.t("operation-loading"));
+        let load = Command::perform(self.storage.events_with_progress(reporter), |events| {
+            Message::Loaded(events.map_err(|e| format!("{:#}", e)))
+        });
+        if !self.read_only {
+            return load;
+        }
+        // Storage calls run in order, so no change is missed between the
+        // two.
+        let since = Command::perform(self.storage.call(|s| s.last_change()), |last| {
+            Message::Changes(
+                last.map(|last| Changes {
+                    events: Vec::new(),
+                    last,
+                })
+                .map_err(|e: GraphiteError| format!("{:#}", e)),
+            )
+        });
+        Command::batch([since, load])
+    }
+
+    /// Records `actions` as a single event. The projection is updated when
+    /// the event comes back through the subscription. Edits to entities
+    /// others have locked wait for confirmation.
+    fn record(&mut self, actions: Vec<Action>) -> Command<Message> {
+        if actions.is_empty() {
+            return Command::none();
+        }
+        if self.is_staging() {
+            self.stage(actions);
+            return Command::none();
+        }
+        self.record_now(actions)
+    }
+
+    /// Records `actions` as a single transaction even while staging.
+    fn record_now(&mut self, actions: Vec<Action>) -> Command<Message> {
+        if actions.is_empty() {
+            return Command::none();
+        }
+        match self.check_locks(actions) {
+            Some(actions) => self.record_then(actions, Message::Saved),
+            None => Command::none(),
+        }
+    }
+
+    /// Records `actions` as a single transaction, chunked if it is large,
+    /// then sends `saved` with the result.
+    fn record_then(
+        &mut self,
+        actions: Vec<Action>,
+        saved: impl Fn(Result<(), String>) -> Message + Send + 'static,
+    ) -> Command<Message> {
+        if let Some(error) = self.read_only_reason() {
+            return Command::perform(async { Err(error) }, saved);
+        }
+        let events = self.creator().transaction(actions);
+        Command::perform(self.storage.record_batch(events), move |result| {
+            saved(result.map_err(|e| format!("{:#}", e)))
+        })
+    }
+
+    fn creator(&self) -> MutexGuard<'_, EventCreator> {
+        // Creating an event can't leave the creator half changed.
+        self.creator.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The actor the events of this device are recorded as.
+    fn actor(&self) -> Uuid {
+        self.creator().actor()
+    }
+
+    /// Why edits are refused, if they are: the database was opened read-only
+    /// or a checkpoint is shown.
+    fn read_only_reason(&self) -> Option<String> {
+        if self.read_only {
+            return Some(self.t("read-only-database"));
+        }
+        let name = self.checkpoints.viewing()?;
+        Some(self.tr("read-only", &[("name", name.to_string().into())]))
+    }
+
+    /// Resolves the configured theme again, after the config or the palettes
+    /// in the graph may have changed.
+    fn refresh_theme(&mut self) {
+        let (theme, colors) =
+            crate::theme::resolve(&self.config.theme, &self.config.palettes, &self.projection);
+        self.theme = theme;
+        self.graph_colors = colors;
+    }
+
+    /// The translated message `id`.
+    fn t(&self, id: &str) -> String {
+        self.i18n.text(id)
+    }
+
+    /// The translated message `id` with its variables set to `args`.
+    fn tr(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        self.i18n.format(id, args)
+    }
+
+    /// A short human readable name for an entity.
+    fn label(&self, id: &Uuid) -> String {
+        let entity = self.projection.entity(id);
+        let name = ["name", "title"]
+            .iter()
+            .find_map(|p| entity.and_then(|e| e.value(p)));
+        match name {
+            Some(Datum::String(name)) => name.clone(),
+            _ => id.simple().to_string()[..8].to_string(),
+        }
+    }
+
+    /// A value as shown in lists, links by the label of their target.
+    fn value_label(&self, datum: &Datum) -> String {
+        match datum {
+            Datum::String(s) => s.clone(),
+            Datum::Integer(n) => n.to_string(),
+            Datum::Float(n) => n.to_string(),
+            Datum::Boolean(b) => b.to_string(),
+            Datum::DateTime(t) => t.to_string(),
+            Datum::Entity(id) => self.label(id),
+        }
+    }
+
+    /// A Unix timestamp as the local date and time to the minute.
+    fn local_time(&self, timestamp: i64) -> String {
+        ::time::OffsetDateTime::from_unix_timestamp(timestamp)
+            .map(|t| {
+                let t = t.to_offset(crate::journal::local_offset());
+                format!("{} {:02}:{:02}", t.date(), t.hour(), t.minute())
+            })
+            .unwrap_or_else(|_| timestamp.to_string())
+    }
+}
 
 impl Application for Editor {
     type Message = Message;
     type Theme = Theme;
     type Executor = executor::Default;
-    type Flags = ();
+    type Flags = Flags;
 
-    fn new(_flags: Self::Flags) -> (Self, Command<Message>) {
-        (
-            Self,
-            window::change_mode(window::Id::MAIN, iced::window::Mode::Fullscreen),
-        )
+    fn new(flags: Self::Flags) -> (Self, Command<Message>) {
+        let mut editor = Self {
+            storage: flags.storage,
+            creator: flags.creator,
+            projection: Projection::new(),
+            rollups: Rollups::new(&flags.config.hierarchy, flags.config.rollups.clone()),
+            validator: crate::validation::Validator::default(),
+            recency: crate::search::Recency::default(),
+            journal: journal::Journal::new(),
+            tasks: tasks::Tasks::new(),
+            diagnostics: diagnostics::Diagnostics::default(),
+            hygiene: hygiene::Hygiene::default(),
+            scripts: scripts::Scripts::load(flags.config_path.as_deref()),
+            dedupe: dedupe::Dedupe::default(),
+            templates: templates::Templates::default(),
+            table: table::Table::default(),
+            board: board::Board::default(),
+            calendar: calendar::Calendar::default(),
+            canvas: canvas::Canvas::default(),
+            search: search::Search::default(),
+            thumbnails: thumbnails::Thumbnails::default(),
+            staging: staging::Staging::default(),
+            conflicts: conflicts::Conflicts::default(),
+            checkpoints: checkpoints::Checkpoints::default(),
+            backups: backups::Backups::default(),
+            nearby: nearby::Nearby::default(),
+            dashboards: dashboard::Dashboards::default(),
+            file_drop: file_drop::FileDrop::default(),
+            plugins: plugins::Plugins::load(flags.config_path.as_deref()),
+            operations: operations::Operations::default(),
+            locks: locks::Locks::default(),
+            // Conflicting shortcuts are pointed out on start.
+            help: !shortcuts::conflicts(&flags.config.keybindings).is_empty(),
+            console: false,
+            trash: false,
+            modifiers: Modifiers::default(),
+            inspectors: HashMap::new(),
+            theme: Theme::Dark,
+            graph_colors: crate::theme::GraphColors::derived(&Theme::Dark.palette()),
+            i18n: localizer(&flags.config),
+            config: flags.config,
+            config_path: flags.config_path,
+            data_dir: data_dir::DataDir::new(&flags.location),
+            location: flags.location,
+            read_only: flags.read_only,
+            locked: flags.locked,
+            changes: None,
+            clock_skew: None,
+            settings: settings::Settings::default(),
+            error: None,
+        };
+        editor.refresh_theme();
+        let commands = [
+            editor.load(),
+            apply_window_mode(&editor.config),
+            editor.update_clock(clock::Message::Check),
+        ];
+        (editor, Command::batch(commands))
     }
 
-    fn title(&self) -> String {
-        String::from("Graphite")
+    fn title(&self, window: window::Id) -> String {
+        if self.inspectors.contains_key(&window) {
+            self.t("inspector-title")
+        } else {
+            self.t("app-title")
+        }
     }
 
-    fn update(&mut self, _message: Message) -> Command<Message> {
+    fn update(&mut self, message: Message) -> Command<Message> {
+        let _span = tracing::trace_span!("update", message = message.name()).entered();
+        let error = self.error.clone();
+        let command = self.handle(message);
+        if let Some(error) = self.error.as_ref().filter(|e| error.as_ref() != Some(*e)) {
+            tracing::warn!("{}", error);
+        }
+        command
+    }
+
+    fn view(&self, window: window::Id) -> Element<'_, Message> {
+        if self.inspectors.contains_key(&window) {
+            return self.view_inspector(window);
+        }
+        let mut content = column![
+            text(self.t("app-title")).size(50),
+            text(self.tr("entity-count", &[("count", self.projection.len().into())])),
+        ]
+        .spacing(20)
+        .padding(20);
+        if let Some(count) = self.view_violation_count() {
+            content = content.push(count);
+        }
+        if let Some(count) = self.view_conflict_count() {
+            content = content.push(count);
+        }
+        if let Some(prompt) = self.view_locked() {
+            return content.push(prompt).into();
+        }
+        if let Some(help) = self.view_help() {
+            content = content.push(help);
+        }
+        if let Some(search) = self.view_search() {
+            content = content.push(search);
+        }
+        if self.read_only {
+            content = content.push(
+                container(text(self.t("read-only-database")))
+                    .padding(10)
+                    .style(iced::theme::Container::Box),
+            );
+        }
+        if let Some(staging) = self.view_staging() {
+            content = content.push(staging);
+        }
+        if let Some(banner) = self.view_time_travel() {
+            content = content.push(banner);
+        }
+        if let Some(presence) = self.view_presence() {
+            content = content.push(presence);
+        }
+        if let Some(warning) = self.view_clock_warning() {
+            content = content.push(warning);
+        }
+        if let Some(warning) = self.view_lock_warning() {
+            content = content.push(warning);
+        }
+        if let Some(data_dir) = self.view_data_dir() {
+            content = content.push(data_dir);
+        }
+        if let Some(settings) = self.view_settings() {
+            content = content.push(settings);
+        }
+        if let Some(diagnostics) = self.view_diagnostics() {
+            content = content.push(diagnostics);
+        }
+        if let Some(conflicts) = self.view_conflicts() {
+            content = content.push(conflicts);
+        }
+        if let Some(scripts) = self.view_scripts() {
+            content = content.push(scripts);
+        }
+        if let Some(hygiene) = self.view_hygiene() {
+            content = content.push(hygiene);
+        }
+        if let Some(canvas) = self.view_canvas() {
+            content = content.push(canvas);
+        }
+        if let Some(calendar) = self.view_calendar() {
+            content = content.push(calendar);
+        }
+        if let Some(board) = self.view_board() {
+            content = content.push(board);
+        }
+        if let Some(table) = self.view_table() {
+            content = content.push(table);
+        }
+        if let Some(templates) = self.view_templates() {
+            content = content.push(templates);
+        }
+        if let Some(dedupe) = self.view_dedupe() {
+            content = content.push(dedupe);
+        }
+        if let Some(trash) = self.view_trash() {
+            content = content.push(trash);
+        }
+        if let Some(checkpoints) = self.view_checkpoints() {
+            content = content.push(checkpoints);
+        }
+        if let Some(backups) = self.view_backups() {
+            content = content.push(backups);
+        }
+        if let Some(nearby) = self.view_nearby() {
+            content = content.push(nearby);
+        }
+        if let Some(file_drop) = self.view_file_drop() {
+            content = content.push(file_drop);
+        }
+        if let Some(operations) = self.view_operations() {
+            content = content.push(operations);
+        }
+        if let Some(console) = self.view_console() {
+            content = content.push(console);
+        }
+        content = content
+            .push(self.view_dashboard())
+            .push(self.view_journal())
+            .push(self.view_tasks())
+            .push(self.view_status_bar());
+        if let Some(error) = &self.error {
+            content = content.push(text(error));
+        }
+        match self.view_plugin_panels() {
+            Some(panels) => row![content.width(Length::Fill), panels]
+                .spacing(10)
+                .padding(10)
+                .into(),
+            None => content.into(),
+        }
+    }
+
+    fn theme(&self, _window: window::Id) -> iced::Theme {
+        self.theme.clone()
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let mut subscriptions = vec![
+            recorded_events(&self.storage),
+            keyboard::on_key_press(|key, modifiers| Some(Message::KeyPressed(key, modifiers))),
+            event::listen_with(|event, _| match event {
+                iced::Event::Window(window, event @ window::Event::Closed)
+                | iced::Event::Window(window, event @ window::Event::Resized { .. }) => {
+                    Some(Message::Window(window, event))
+                }
+                iced::Event::Window(_, window::Event::FileHovered(_)) => {
+                    Some(Message::FileDrop(file_drop::Message::Hovered))
+                }
+                iced::Event::Window(_, window::Event::FilesHoveredLeft) => {
+                    Some(Message::FileDrop(file_drop::Message::Left))
+                }
+                iced::Event::Window(_, window::Event::FileDropped(path)) => {
+                    Some(Message::FileDrop(file_drop::Message::Dropped(path)))
+                }
+                iced::Event::Mouse(mouse::Event::CursorMoved { position }) => {
+                    Some(Message::FileDrop(file_drop::Message::CursorMoved(position)))
+                }
+                iced::Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                    Some(Message::ModifiersChanged(modifiers))
+                }
+                _ => None,
+            }),
+        ];
+        if self.read_only {
+            subscriptions.push(time::every(POLL_INTERVAL).map(|_| Message::Poll));
+        }
+        if self.config.autosave_interval > 0 {
+            let interval = std::time::Duration::from_secs(self.config.autosave_interval);
+            subscriptions.push(
+                time::every(interval).map(|_| Message::Settings(settings::Message::Autosave)),
+            );
+        }
+        let backups = &self.config.backups;
+        if backups.interval > 0 && !backups.targets.is_empty() {
+            let interval = std::time::Duration::from_secs(backups.interval * 60 * 60);
+            subscriptions
+                .push(time::every(interval).map(|_| Message::Backups(backups::Message::BackUp)));
+        }
+        if backups.local.path.is_some() {
+            // Hourly, so the copy of the day is taken soon after midnight or
+            // after starting the editor.
+            let interval = std::time::Duration::from_secs(60 * 60);
+            subscriptions
+                .push(time::every(interval).map(|_| Message::Backups(backups::Message::Daily)));
+        }
+        let maintenance = &self.config.maintenance;
+        if maintenance.interval > 0 && !self.read_only {
+            let interval = std::time::Duration::from_secs(maintenance.interval * 60 * 60);
+            subscriptions
+                .push(time::every(interval).map(|_| Message::Backups(backups::Message::Maintain)));
+        }
+        let folder = &self.config.folder;
+        if folder.interval > 0 && folder.path.is_some() && !self.read_only {
+            let interval = std::time::Duration::from_secs(folder.interval * 60);
+            subscriptions
+                .push(time::every(interval).map(|_| Message::Folder(folder::Message::Sync)));
+        }
+        if !self.read_only {
+            let interval = std::time::Duration::from_secs(60);
+            subscriptions
+                .push(time::every(interval).map(|_| Message::Clock(clock::Message::Check)));
+        }
+        if self.nearby.is_running() {
+            let interval = std::time::Duration::from_secs(1);
+            subscriptions
+                .push(time::every(interval).map(|_| Message::Nearby(nearby::Message::Tick)));
+        }
+        if !self.operations.is_empty() {
+            let interval = std::time::Duration::from_millis(100);
+            subscriptions.push(
+                time::every(interval).map(|_| Message::Operations(operations::Message::Tick)),
+            );
+        }
+        if self.canvas.is_animating() {
+            subscriptions
+                .push(window::frames().map(|now| Message::Canvas(canvas::Message::Frame(now))));
+        }
+        if self.console {
+            let interval = std::time::Duration::from_millis(500);
+            subscriptions
+                .push(time::every(interval).map(|_| Message::Console(console::Message::Tick)));
+        }
+        Subscription::batch(subscriptions)
+    }
+}
+
+impl Editor {
+    /// Updates the editor with `message`, see [`Application::update`].
+    fn handle(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::Loaded(Ok(events)) => {
+                let window = self.config.trash_days as i64 * 24 * 60 * 60;
+                let projection = self.live_projection();
+                *projection = Projection::new();
+                projection.set_trash_window(window);
+                events.iter().for_each(|e| projection.apply_event(e));
+                self.restage();
+                self.recency = crate::search::Recency::default();
+                events.iter().for_each(|e| self.recency.record(e));
+                events.iter().for_each(|e| self.creator().observe(e));
+                self.rollups.rebuild(&self.projection);
+                self.refresh_validation(None);
+                self.retain_selections(None);
+                self.refresh_notes(None);
+                self.refresh_hygiene();
+                self.refresh_canvas();
+                self.refresh_dedupe();
+                self.refresh_theme();
+                self.refresh_plugins(None);
+                self.error = None;
+                return Command::batch([
+                    self.refresh_dashboards(None),
+                    self.load_thumbnails(),
+                    self.render_nodes(),
+                ]);
+            }
+            Message::Loaded(Err(error)) => self.error = Some(error),
+            Message::Recorded(event) => {
+                self.live_projection().apply_event(&event);
+                self.staging.apply_event(&event);
+                self.recency.record(&event);
+                self.creator().observe(&event);
+                let changed = event.action().subjects();
+                self.rollups.update(&self.projection, &changed);
+                // The parts of a chunked edit apply together with the last.
+                self.refresh_validation(match event.action() {
+                    Action::Chunk { .. } => None,
+                    _ => Some(&changed),
+                });
+                self.retain_selections(Some(&changed));
+                self.refresh_notes(Some(&event));
+                self.refresh_hygiene();
+                self.refresh_canvas_later(&changed);
+                self.refresh_dedupe();
+                self.refresh_theme();
+                self.refresh_plugins(Some(&changed));
+                let refresh = self.refresh_dashboards(Some(&event.action().touches()));
+                return Command::batch([refresh, self.load_thumbnails(), self.render_nodes()]);
+            }
+            Message::Lagged => {
+                tracing::warn!("Missed recorded events, reloading the graph");
+                return self.load();
+            }
+            Message::Poll => {
+                if let Some(since) = self.changes {
+                    return Command::perform(
+                        self.storage.call(move |s| s.poll_changes(since)),
+                        |changes| {
+                            Message::Changes(changes.map_err(|e: GraphiteError| format!("{:#}", e)))
+                        },
+                    );
+                }
+            }
+            Message::Changes(Ok(changes)) => {
+                self.changes = Some(changes.last);
+                let recorded = changes
+                    .events
+                    .into_iter()
+                    .map(|event| self.handle(Message::Recorded(event)));
+                return Command::batch(recorded.collect::<Vec<_>>());
+            }
+            Message::Changes(Err(error)) => self.error = Some(error),
+            Message::Saved(Ok(())) => {}
+            Message::Saved(Err(error)) => self.error = Some(error),
+            Message::Journal(message) => return self.update_journal(message),
+            Message::Tasks(message) => return self.update_tasks(message),
+            Message::Diagnostics(message) => return self.update_diagnostics(message),
+            Message::Hygiene(message) => return self.update_hygiene(message),
+            Message::Scripts(message) => return self.update_scripts(message),
+            Message::Dedupe(message) => return self.update_dedupe(message),
+            Message::Templates(message) => return self.update_templates(message),
+            Message::Table(message) => return self.update_table(message),
+            Message::Board(message) => return self.update_board(message),
+            Message::Calendar(message) => return self.update_calendar(message),
+            Message::Canvas(message) => return self.update_canvas(message),
+            Message::Search(message) => return self.update_search(message),
+            Message::Thumbnails(message) => return self.update_thumbnails(message),
+            Message::Plugins(message) => return self.update_plugins(message),
+            Message::Staging(message) => return self.update_staging(message),
+            Message::Conflicts(message) => return self.update_conflicts(message),
+            Message::Trash(message) => return self.update_trash(message),
+            Message::Checkpoints(message) => return self.update_checkpoints(message),
+            Message::Backups(message) => return self.update_backups(message),
+            Message::Folder(message) => return self.update_folder(message),
+            Message::Nearby(message) => return self.update_nearby(message),
+            Message::Clock(message) => return self.update_clock(message),
+            Message::Dashboard(message) => return self.update_dashboard(message),
+            Message::FileDrop(message) => return self.update_file_drop(message),
+            Message::Operations(message) => return self.update_operations(message),
+            Message::Help(message) => return self.update_help(message),
+            Message::Console(message) => return self.update_console(message),
+            Message::Timer(message) => return self.update_timer(message),
+            Message::Settings(message) => return self.update_settings(message),
+            Message::DataDir(message) => return self.update_data_dir(message),
+            Message::Locks(message) => return self.update_locks(message),
+            Message::Locked(message) => return self.update_locked(message),
+            Message::Inspector(window, message) => {
+                let update = self.update_inspector(window, message);
+                return Command::batch([update, self.load_thumbnails()]);
+            }
+            Message::Window(window::Id::MAIN, window::Event::Closed) => {
+                // The inspectors close with the main window.
+                let inspectors = self.inspectors.drain().map(|(id, _)| window::close(id));
+                return Command::batch(inspectors.collect::<Vec<_>>());
+            }
+            Message::Window(window, window::Event::Closed) => {
+                self.inspectors.remove(&window);
+            }
+            Message::Window(window, window::Event::Resized { width, height })
+                if window == window::Id::MAIN
+                    && self.config.window_mode == WindowMode::Windowed =>
+            {
+                // Remembered so the window reopens at the same size.
+                if (width, height) != (self.config.window_width, self.config.window_height) {
+                    self.edit_config(|c| {
+                        c.window_width = width;
+                        c.window_height = height;
+                    });
+                }
+            }
+            Message::Window(..) => {}
+            Message::KeyPressed(key, modifiers) => {
+                if self.search.is_open() {
+                    let message = match key {
+                        Key::Named(Named::ArrowUp) => Some(search::Message::Up),
+                        Key::Named(Named::ArrowDown) => Some(search::Message::Down),
+                        Key::Named(Named::Escape) => Some(search::Message::Close),
+                        _ => None,
+                    };
+                    if let Some(message) = message {
+                        return self.update_search(message);
+                    }
+                }
+                if let Some(command) = self.config.command(&key, modifiers) {
+                    return self.run(command);
+                }
+                if let Some(name) = self.scripts.bound(&key, modifiers) {
+                    return self.update_scripts(scripts::Message::Invoke(name));
+                }
+            }
+            Message::ModifiersChanged(modifiers) => self.modifiers = modifiers,
+        }
         Command::none()
     }
 
-    fn view(&self) -> Element<Message> {
-        text("Hello, Graphite!").size(50).into()
+    /// Runs a command bound to a key.
+    fn run(&mut self, command: shortcuts::Command) -> Command<Message> {
+        match command {
+            shortcuts::Command::JournalToday => self.update_journal(journal::Message::Today),
+            shortcuts::Command::Diagnostics => self.update_diagnostics(diagnostics::Message::Open),
+            shortcuts::Command::Settings => self.update_settings(settings::Message::Open),
+            shortcuts::Command::Checkpoints => self.update_checkpoints(checkpoints::Message::Open),
+            shortcuts::Command::Backups => self.update_backups(backups::Message::Open),
+            shortcuts::Command::Inspector => self.open_inspector(),
+            shortcuts::Command::Help => self.update_help(help::Message::Toggle),
+            shortcuts::Command::Console => self.update_console(console::Message::Toggle),
+            shortcuts::Command::Hygiene => self.update_hygiene(hygiene::Message::Open),
+            shortcuts::Command::Scripts => self.update_scripts(scripts::Message::Open),
+            shortcuts::Command::Trash => self.update_trash(trash::Message::Open),
+            shortcuts::Command::Duplicates => self.update_dedupe(dedupe::Message::Open),
+            shortcuts::Command::NewEntity => self.update_templates(templates::Message::Open),
+            shortcuts::Command::Table => self.update_table(table::Message::Open),
+            shortcuts::Command::Board => self.update_board(board::Message::Open),
+            shortcuts::Command::Calendar => self.update_calendar(calendar::Message::Open),
+            shortcuts::Command::Canvas => self.update_canvas(canvas::Message::Open),
+            shortcuts::Command::Search => self.update_search(search::Message::Open),
+            shortcuts::Command::Staging => self.update_staging(staging::Message::Toggle),
+            shortcuts::Command::Conflicts => self.update_conflicts(conflicts::Message::Open),
+            shortcuts::Command::Nearby => self.update_nearby(nearby::Message::Open),
+        }
     }
+}
+
+/// A file in the documents directory named after `label`, with the
+/// `extension` given.
+fn export_path(label: &str, extension: &str) -> PathBuf {
+    let name: String = label
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || " -_".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join(format!("{}.{}", name.trim(), extension))
+}
 
-    fn theme(&self) -> iced::Theme {
-        iced::Theme::Dark
+/// The translations of the configured language, or of the system's.
+fn localizer(config: &Config) -> Localizer {
+    let locale = config.language.clone().or_else(i18n::detect);
+    Localizer::new(locale.as_deref().unwrap_or("en"))
+}
+
+/// Puts the main window in the configured mode. The size of windowed mode is
+/// set when the window is created.
+fn apply_window_mode(config: &Config) -> Command<Message> {
+    match config.window_mode {
+        WindowMode::Windowed => Command::batch([
+            window::maximize(window::Id::MAIN, false),
+            window::change_mode(window::Id::MAIN, window::Mode::Windowed),
+        ]),
+        WindowMode::Maximized => Command::batch([
+            window::change_mode(window::Id::MAIN, window::Mode::Windowed),
+            window::maximize(window::Id::MAIN, true),
+        ]),
+        WindowMode::Fullscreen => window::change_mode(window::Id::MAIN, window::Mode::Fullscreen),
     }
 }
 
-struct Person {
-    full_name: String,
-    age: u8,
+/// Streams events recorded through `storage`, from this or any other part of
+/// the application, so the projection stays live.
+fn recorded_events(storage: &AsyncStorage) -> Subscription<Message> {
+    struct RecordedEvents;
+
+    let mut feed = storage.subscribe();
+    subscription::channel(
+        std::any::TypeId::of::<RecordedEvents>(),
+        100,
+        |mut output| async move {
+            loop {
+                let message = match feed.recv().await {
+                    Ok(event) => Message::Recorded(event),
+                    Err(RecvError::Lagged(_)) => Message::Lagged,
+                    Err(RecvError::Closed) => break,
+                };
+                if output.send(message).await.is_err() {
+                    break;
+                }
+            }
+            std::future::pending().await
+        },
+    )
 }
@@ -0,0 +1,297 @@
+//! The backups dialog: backing up to the configured targets and restoring
+//! from them, see [`crate::backup`].
+
+use super::Editor;
+use crate::backup::{self, Target};
+use crate::conflicts;
+use crate::maintenance;
+use crate::replication;
+use iced::widget::{button, column, container, row, scrollable, text, Column};
+use iced::{Command, Element, Length};
+use std::collections::BTreeMap;
+
+/// How many backups of a target are listed.
+const LIMIT: usize = 10;
+
+#[derive(Default)]
+pub struct Backups {
+    open: bool,
+    /// The backups on each target by its name, newest first, or why they
+    /// couldn't be listed.
+    lists: BTreeMap<String, Result<Vec<String>, String>>,
+    /// What the last backup or restore did.
+    status: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Listed(String, Result<Vec<String>, String>),
+    BackUp,
+    /// The number of targets backed up to.
+    BackedUp(Result<usize, String>),
+    /// Takes the local copy of the day unless it was taken already.
+    Daily,
+    BackUpLocally,
+    /// The name of the local copy taken.
+    Copied(Result<String, String>),
+    /// Maintains the database and rotates the backups, see
+    /// [`crate::maintenance`].
+    Maintain,
+    Maintained(Result<(), String>),
+    Restore(String, String),
+    /// The backup restored, the number of events it added and of conflicts
+    /// with local edits it brought in.
+    Restored(String, Result<(usize, usize), String>),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_backups(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => {
+                self.backups.open = true;
+                return self.list_backups();
+            }
+            Message::Listed(target, list) => {
+                self.backups.lists.insert(target, list);
+            }
+            Message::BackUp => return self.back_up(),
+            Message::BackedUp(Ok(targets)) => {
+                self.backups.status = Some(self.tr("backups-done", &[("targets", targets.into())]));
+                if self.backups.open {
+                    return self.list_backups();
+                }
+            }
+            Message::Daily => {
+                let now = time::OffsetDateTime::now_utc();
+                if backup::local::due(&self.config.backups.local, now) {
+                    return self.back_up_locally();
+                }
+            }
+            Message::BackUpLocally => return self.back_up_locally(),
+            Message::Copied(Ok(name)) => {
+                self.backups.status = Some(self.tr("backups-copied", &[("name", name.into())]));
+            }
+            Message::Maintain => return self.maintain(),
+            Message::Maintained(Ok(())) => {
+                self.backups.status = Some(self.t("backups-maintained"));
+            }
+            Message::Restore(target, name) => return self.restore(target, name),
+            Message::Restored(name, Ok((events, conflicts))) => {
+                let mut status = self.tr(
+                    "backups-restored",
+                    &[("events", events.into()), ("name", name.into())],
+                );
+                if conflicts > 0 {
+                    let found = self.tr("conflicts-found", &[("count", conflicts.into())]);
+                    status = format!("{} {}", status, found);
+                }
+                self.backups.status = Some(status);
+                // Restored events are older than the ones applied, so the
+                // graph is replayed in order.
+                return self.load();
+            }
+            Message::BackedUp(Err(error))
+            | Message::Copied(Err(error))
+            | Message::Maintained(Err(error))
+            | Message::Restored(_, Err(error)) => self.error = Some(error),
+            Message::Close => self.backups.open = false,
+        }
+        Command::none()
+    }
+
+    fn list_backups(&mut self) -> Command<super::Message> {
+        let lists = self.config.backups.targets.iter().cloned().map(|target| {
+            let name = target.name().to_string();
+            Command::perform(
+                tokio::task::spawn_blocking(move || target.list().map_err(|e| format!("{:#}", e))),
+                move |list| {
+                    let list = list.unwrap_or_else(|e| Err(e.to_string()));
+                    super::Message::Backups(Message::Listed(name, list))
+                },
+            )
+        });
+        Command::batch(lists)
+    }
+
+    /// Backs the database up to every target, as a cancellable operation.
+    pub(super) fn back_up(&mut self) -> Command<super::Message> {
+        let settings = self.config.backups.clone();
+        let secret = match settings.secret(self.location.keyring().as_deref()) {
+            Ok(secret) => secret,
+            Err(e) => {
+                self.error = Some(format!("{:#}", e));
+                return Command::none();
+            }
+        };
+        let reporter = self.start_operation(self.t("backups-running"));
+        reporter.set_total(1 + settings.targets.len() as u64);
+        let snapshot = self
+            .storage
+            .call(move |storage| backup::snapshot(storage, &secret));
+        let backed_up = async move {
+            let snapshot = snapshot.await?;
+            reporter.advance(1);
+            tokio::task::spawn_blocking(move || {
+                let now = time::OffsetDateTime::now_utc();
+                for target in &settings.targets {
+                    reporter.check()?;
+                    backup::upload(target, &snapshot, now, settings.retention)?;
+                    reporter.advance(1);
+                }
+                Ok(settings.targets.len())
+            })
+            .await?
+        };
+        Command::perform(backed_up, |result: anyhow::Result<usize>| {
+            super::Message::Backups(Message::BackedUp(result.map_err(|e| format!("{:#}", e))))
+        })
+    }
+
+    /// Copies the database to the local directory as the copy of the day.
+    fn back_up_locally(&mut self) -> Command<super::Message> {
+        let settings = self.config.backups.local.clone();
+        Command::perform(
+            self.storage.call(move |storage| {
+                let now = time::OffsetDateTime::now_utc();
+                backup::local::back_up(storage, &settings, now).map(|copied| copied.name)
+            }),
+            |copied| {
+                super::Message::Backups(Message::Copied(copied.map_err(|e| format!("{:#}", e))))
+            },
+        )
+    }
+
+    /// Maintains the database on the storage thread, then rotates the
+    /// backups off it, since targets are reached over the network.
+    fn maintain(&mut self) -> Command<super::Message> {
+        if self.read_only {
+            return Command::none();
+        }
+        let settings = self.config.maintenance.clone();
+        let backups = self.config.backups.clone();
+        let now = time::OffsetDateTime::now_utc();
+        let maintained = self
+            .storage
+            .call(move |storage| maintenance::database(storage, &settings, now));
+        let rotated = async move {
+            maintained.await?;
+            tokio::task::spawn_blocking(move || maintenance::rotate(&backups, now)).await??;
+            Ok(())
+        };
+        Command::perform(rotated, |result: anyhow::Result<()>| {
+            super::Message::Backups(Message::Maintained(result.map_err(|e| format!("{:#}", e))))
+        })
+    }
+
+    fn restore(&mut self, target: String, name: String) -> Command<super::Message> {
+        let settings = &self.config.backups;
+        let keyring = self.location.keyring();
+        let (target, secret, subscription) = match settings.target(&target).and_then(|target| {
+            let secret = settings.secret(keyring.as_deref())?;
+            Ok((target.clone(), secret, settings.subscription.clone()))
+        }) {
+            Ok(found) => found,
+            Err(e) => {
+                self.error = Some(format!("{:#}", e));
+                return Command::none();
+            }
+        };
+        let reporter =
+            self.start_operation(self.tr("backups-restoring", &[("name", name.clone().into())]));
+        let storage = self.storage.clone();
+        let restored = {
+            let name = name.clone();
+            async move {
+                let events =
+                    tokio::task::spawn_blocking(move || backup::download(&target, &name, &secret))
+                        .await??;
+                reporter.check()?;
+                storage
+                    .call(move |storage| {
+                        let events = replication::select_for(storage, &subscription, events)?;
+                        conflicts::merge(storage, events)
+                    })
+                    .await
+            }
+        };
+        Command::perform(restored, move |result: anyhow::Result<(usize, usize)>| {
+            super::Message::Backups(Message::Restored(
+                name,
+                result.map_err(|e| format!("{:#}", e)),
+            ))
+        })
+    }
+
+    /// The backups dialog, if it is open.
+    pub(super) fn view_backups(&self) -> Option<Element<'_, super::Message>> {
+        if !self.backups.open {
+            return None;
+        }
+        let message = |m| super::Message::Backups(m);
+        let targets = &self.config.backups.targets;
+        let local = self.config.backups.local.path.as_ref();
+        let mut content = column![
+            text(self.t("backups")).size(30),
+            row![
+                button(text(self.t("backups-now")))
+                    .on_press_maybe((!targets.is_empty()).then_some(message(Message::BackUp))),
+                button(text(self.t("backups-copy")))
+                    .on_press_maybe(local.is_some().then_some(message(Message::BackUpLocally))),
+            ]
+            .spacing(10),
+        ]
+        .spacing(10);
+        if let Some(path) = local {
+            let path = path.display().to_string();
+            content = content.push(text(self.tr("backups-local", &[("path", path.into())])));
+        }
+        if let Some(status) = &self.backups.status {
+            content = content.push(text(status));
+        }
+        if targets.is_empty() && local.is_none() {
+            content = content.push(text(self.t("backups-none")));
+        }
+        let mut lists = Column::new().spacing(10);
+        for target in targets {
+            lists = lists.push(self.view_target(target));
+        }
+        content = content
+            .push(scrollable(lists).height(Length::Shrink))
+            .push(button(text(self.t("close"))).on_press(message(Message::Close)));
+        Some(
+            container(content)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    fn view_target(&self, target: &Target) -> Element<'_, super::Message> {
+        let name = target.name();
+        let mut list = Column::new().spacing(4).push(text(name).size(20));
+        match self.backups.lists.get(name) {
+            Some(Ok(backups)) if backups.is_empty() => {
+                list = list.push(text(self.t("backups-empty")))
+            }
+            Some(Ok(backups)) => {
+                for backup in backups.iter().take(LIMIT) {
+                    let restore = Message::Restore(name.to_string(), backup.clone());
+                    list = list.push(
+                        row![
+                            text(backup).width(Length::Fill),
+                            button(text(self.t("backups-restore"))).on_press_maybe(
+                                (!self.read_only).then_some(super::Message::Backups(restore))
+                            ),
+                        ]
+                        .spacing(10),
+                    );
+                }
+            }
+            Some(Err(error)) => list = list.push(text(error)),
+            None => {}
+        }
+        list.into()
+    }
+}
@@ -0,0 +1,180 @@
+//! The board view, with a column per value of a predicate and cards dragged
+//! between them, see [`crate::board`].
+
+use super::Editor;
+use crate::board::{self, Column as BoardColumn};
+use crate::legacy::storage::Datum;
+use crate::query::Query;
+use iced::widget::{
+    button, column, container, mouse_area, row, scrollable, text, text_input, Column, Row,
+};
+use iced::{theme, Command, Element, Length};
+use uuid::Uuid;
+
+const COLUMN_WIDTH: f32 = 200.0;
+
+pub struct Board {
+    open: bool,
+    filter: String,
+    predicate: String,
+    /// Columns added that no card is in yet.
+    extra: Vec<String>,
+    new_column: String,
+    /// The card being dragged.
+    picked: Option<Uuid>,
+}
+
+impl Default for Board {
+    fn default() -> Board {
+        Board {
+            open: false,
+            filter: "type=task".to_string(),
+            predicate: "status".to_string(),
+            extra: Vec::new(),
+            new_column: String::new(),
+            picked: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Filter(String),
+    Predicate(String),
+    NewColumn(String),
+    AddColumn,
+    /// A card was pressed.
+    Pick(Uuid),
+    /// The card picked was released over the column of a value.
+    Drop(Option<Datum>),
+    /// The card picked was released outside the columns.
+    Release,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_board(&mut self, message: Message) -> Command<super::Message> {
+        let state = &mut self.board;
+        match message {
+            Message::Open => state.open = true,
+            Message::Filter(filter) => state.filter = filter,
+            Message::Predicate(predicate) => {
+                state.predicate = predicate;
+                state.extra.clear();
+            }
+            Message::NewColumn(column) => state.new_column = column,
+            Message::AddColumn => {
+                let column = std::mem::take(&mut state.new_column).trim().to_string();
+                if !column.is_empty() && !state.extra.contains(&column) {
+                    state.extra.push(column);
+                }
+            }
+            Message::Pick(id) => state.picked = Some(id),
+            Message::Drop(value) => {
+                if let Some(id) = state.picked.take() {
+                    let predicate = state.predicate.trim().to_string();
+                    let actions =
+                        board::move_card(&self.projection, id, &predicate, value.as_ref());
+                    if !actions.is_empty() {
+                        return self.record(actions);
+                    }
+                }
+            }
+            Message::Release => state.picked = None,
+            Message::Close => *state = Board::default(),
+        }
+        Command::none()
+    }
+
+    /// The board, if it is open.
+    pub(super) fn view_board(&self) -> Option<Element<'_, super::Message>> {
+        let state = &self.board;
+        if !state.open {
+            return None;
+        }
+        let message = |m| super::Message::Board(m);
+        let settings = row![
+            text_input(&self.t("board-filter"), &state.filter)
+                .on_input(move |f| message(Message::Filter(f))),
+            text_input(&self.t("board-predicate"), &state.predicate)
+                .on_input(move |p| message(Message::Predicate(p))),
+            text_input(&self.t("board-column"), &state.new_column)
+                .on_input(move |c| message(Message::NewColumn(c)))
+                .on_submit(message(Message::AddColumn)),
+            button(text(self.t("board-add-column"))).on_press(message(Message::AddColumn)),
+        ]
+        .spacing(10);
+
+        let predicate = state.predicate.trim();
+        let content: Element<'_, super::Message> = match state.filter.parse::<Query>() {
+            Err(error) => text(format!("{:#}", error)).into(),
+            Ok(_) if predicate.is_empty() => text(self.t("board-no-predicate")).into(),
+            Ok(query) => {
+                let columns = board::board(&self.projection, &query, predicate, &state.extra);
+                let mut lanes = Row::new().spacing(10);
+                for column in columns {
+                    lanes = lanes.push(self.view_board_column(column));
+                }
+                mouse_area(
+                    scrollable(lanes).direction(scrollable::Direction::Horizontal(
+                        scrollable::Properties::default(),
+                    )),
+                )
+                .on_release(message(Message::Release))
+                .into()
+            }
+        };
+
+        let dialog = column![
+            text(self.t("board")).size(30),
+            settings,
+            content,
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    fn view_board_column(&self, column: BoardColumn) -> Element<'_, super::Message> {
+        let message = |m| super::Message::Board(m);
+        let title = match &column.value {
+            Some(value) => self.value_label(value),
+            None => self.t("board-none"),
+        };
+        let mut cards: Vec<(String, Uuid)> = column
+            .cards
+            .iter()
+            .map(|id| (self.label(id), *id))
+            .collect();
+        cards.sort();
+        let mut list = Column::new()
+            .spacing(6)
+            .width(Length::Fixed(COLUMN_WIDTH))
+            .push(text(format!("{} ({})", title, cards.len())).size(18));
+        for (label, id) in cards {
+            let mut card = container(text(label)).padding(8).width(Length::Fill);
+            card = if self.board.picked == Some(id) {
+                card.style(container::Appearance {
+                    background: Some(self.graph_colors.selection.into()),
+                    ..container::Appearance::default()
+                })
+            } else {
+                card.style(theme::Container::Box)
+            };
+            let mut card = mouse_area(card);
+            if !self.read_only {
+                card = card.on_press(message(Message::Pick(id)));
+            }
+            list = list.push(card);
+        }
+        mouse_area(container(list).height(Length::Fixed(400.0)))
+            .on_release(message(Message::Drop(column.value)))
+            .into()
+    }
+}
@@ -0,0 +1,197 @@
+//! The calendar view, a month of the entities with a date, which are dragged
+//! to other days to reschedule them, see [`crate::calendar`].
+
+use super::Editor;
+use crate::calendar::{self, Entry};
+use crate::journal;
+use iced::widget::{button, column, container, mouse_area, row, text, text_input, Column, Row};
+use iced::{theme, Command, Element, Length};
+use time::{Date, Month};
+
+const DAY_WIDTH: f32 = 110.0;
+const DAY_HEIGHT: f32 = 90.0;
+/// How many entries a day lists.
+const PER_DAY: usize = 4;
+
+pub struct Calendar {
+    open: bool,
+    predicate: String,
+    /// A day in the month shown.
+    month: Date,
+    /// The entry being dragged and the day it was on.
+    picked: Option<(Entry, Date)>,
+}
+
+impl Default for Calendar {
+    fn default() -> Calendar {
+        Calendar {
+            open: false,
+            predicate: "due".to_string(),
+            month: journal::today(),
+            picked: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Predicate(String),
+    Previous,
+    Next,
+    Today,
+    /// An entry was pressed on a day.
+    Pick(Entry, Date),
+    /// The entry picked was released over a day: moved if it is another
+    /// day, or inspected if it is the same.
+    Drop(Date),
+    /// The entry picked was released outside the days.
+    Release,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_calendar(&mut self, message: Message) -> Command<super::Message> {
+        let state = &mut self.calendar;
+        match message {
+            Message::Open => state.open = true,
+            Message::Predicate(predicate) => state.predicate = predicate,
+            Message::Previous => state.month = shift_month(state.month, -1),
+            Message::Next => state.month = shift_month(state.month, 1),
+            Message::Today => state.month = journal::today(),
+            Message::Pick(entry, day) => state.picked = Some((entry, day)),
+            Message::Drop(day) => {
+                let Some((entry, from)) = state.picked.take() else {
+                    return Command::none();
+                };
+                if day == from {
+                    return self.inspect(entry.id);
+                }
+                if self.read_only {
+                    return Command::none();
+                }
+                let predicate = state.predicate.trim().to_string();
+                let actions = calendar::reschedule(
+                    &self.projection,
+                    entry,
+                    &predicate,
+                    day,
+                    journal::local_offset(),
+                );
+                return self.record(actions);
+            }
+            Message::Release => state.picked = None,
+            Message::Close => *state = Calendar::default(),
+        }
+        Command::none()
+    }
+
+    /// The calendar, if it is open.
+    pub(super) fn view_calendar(&self) -> Option<Element<'_, super::Message>> {
+        let state = &self.calendar;
+        if !state.open {
+            return None;
+        }
+        let message = |m| super::Message::Calendar(m);
+        let weeks = calendar::weeks(state.month);
+        let (first, last) = (weeks[0][0], weeks[weeks.len() - 1][6]);
+        let predicate = state.predicate.trim();
+        let entries = calendar::entries(
+            &self.projection,
+            predicate,
+            journal::local_offset(),
+            first,
+            last,
+        );
+
+        let mut grid = Column::new().spacing(2);
+        for week in weeks {
+            let mut days = Row::new().spacing(2);
+            for day in week {
+                let entries = entries.get(&day).map(Vec::as_slice).unwrap_or_default();
+                days = days.push(self.view_calendar_day(day, entries));
+            }
+            grid = grid.push(days);
+        }
+        let today = journal::today();
+        let header = row![
+            button(text("‹")).on_press(message(Message::Previous)),
+            text(format!("{} {}", state.month.month(), state.month.year())).size(20),
+            button(text("›")).on_press(message(Message::Next)),
+            button(text(self.t("calendar-today"))).on_press_maybe(
+                (state.month.month() != today.month() || state.month.year() != today.year())
+                    .then(|| message(Message::Today))
+            ),
+            text_input(&self.t("calendar-predicate"), &state.predicate)
+                .on_input(move |p| message(Message::Predicate(p))),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+
+        let dialog = column![
+            text(self.t("calendar")).size(30),
+            header,
+            mouse_area(grid).on_release(message(Message::Release)),
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    fn view_calendar_day(&self, day: Date, entries: &[Entry]) -> Element<'_, super::Message> {
+        let message = |m| super::Message::Calendar(m);
+        let in_month = day.month() == self.calendar.month.month();
+        let mut number = text(day.day()).size(12);
+        if !in_month {
+            number = number.style(theme::Text::Color(iced::Color {
+                a: 0.5,
+                ..self.theme.palette().text
+            }));
+        }
+        let mut list = Column::new().spacing(2).push(number);
+        for entry in entries.iter().take(PER_DAY) {
+            let mut card = container(text(self.label(&entry.id)).size(12))
+                .padding([1, 4])
+                .width(Length::Fill);
+            card = if self
+                .calendar
+                .picked
+                .is_some_and(|(picked, _)| picked == *entry)
+            {
+                card.style(container::Appearance {
+                    background: Some(self.graph_colors.selection.into()),
+                    ..container::Appearance::default()
+                })
+            } else {
+                card.style(theme::Container::Box)
+            };
+            list = list.push(mouse_area(card).on_press(message(Message::Pick(*entry, day))));
+        }
+        if entries.len() > PER_DAY {
+            let more = entries.len() - PER_DAY;
+            list = list.push(text(self.tr("hygiene-more", &[("count", more.into())])).size(12));
+        }
+        mouse_area(
+            container(list)
+                .padding(4)
+                .width(Length::Fixed(DAY_WIDTH))
+                .height(Length::Fixed(DAY_HEIGHT))
+                .style(theme::Container::Box),
+        )
+        .on_release(message(Message::Drop(day)))
+        .into()
+    }
+}
+
+/// A day in the month `months` before or after that of `day`.
+fn shift_month(day: Date, months: i32) -> Date {
+    let first = day.replace_day(1).expect("every month has a first day");
+    let index = first.year() * 12 + i32::from(u8::from(first.month())) - 1 + months;
+    let month = Month::try_from((index.rem_euclid(12) + 1) as u8).expect("within 1 to 12");
+    Date::from_calendar_date(index.div_euclid(12), month, 1).unwrap_or(first)
+}
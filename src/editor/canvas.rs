@@ -0,0 +1,1516 @@
+//! The graph canvas, see [`crate::graph`]: dragged to pan and scrolled to
+//! zoom. It is a widget of its own, drawing the nodes in view as quads
+//! rather than a widget each, and the edges as a single mesh, which only
+//! the GPU renderer draws.
+//!
+//! Touchpads pan with two fingers and zoom by pinching, or with Ctrl, and
+//! touchscreens the same way; the scroll deltas of touchpads are in physical
+//! pixels, so the canvas measures the scale factor of the window.
+//!
+//! Clicking an entity selects it. Focusing shows only the selected entities
+//! and their neighborhood, see [`crate::graph::focus`], and double-clicking
+//! an entity adds its neighbors.
+//!
+//! Nodes drawn large enough show the thumbnail of their image, see
+//! [`super::thumbnails`], unless too many are in view.
+//!
+//! Containers are drawn as boxes around their members, see
+//! [`crate::graph::groups`]: clicking the title of a box collapses it, and
+//! double-clicking a collapsed container expands it again.
+//!
+//! The side panel edits the filter and style rules of the canvas, and saves
+//! them as a view, see [`crate::graph::style`].
+//!
+//! The canvas is exported as an image to the documents directory, see
+//! [`crate::graph::snapshot`].
+
+use super::settings::Labeled;
+use super::thumbnails::Thumbnails;
+use super::Editor;
+use crate::graph::animation::Animation;
+use crate::graph::layout::Layout;
+use crate::graph::snapshot::{self, Colors, Extent, Format, Snapshot};
+use crate::graph::style::{self, Rule, Rules, View};
+use crate::graph::{
+    edges, focus, minimap, Graph, Hierarchy, Node, Rect, Scene, Viewport, CLUSTER_ZOOM,
+};
+use crate::legacy::projection::Projection;
+use crate::selection::{self, Selection, Shape};
+use crate::theme::{GraphColors, Hex};
+use crate::thumbnail::Thumbnail;
+use iced::advanced::graphics::color;
+use iced::advanced::graphics::mesh::{self, Mesh, SolidVertex2D};
+use iced::advanced::layout;
+use iced::advanced::mouse::{click, Click};
+use iced::advanced::renderer::{self, Quad, Renderer as _};
+use iced::advanced::text::{self as text_renderer, Renderer as _, Text};
+use iced::advanced::widget::{tree, Tree, Widget};
+use iced::advanced::{Clipboard, Shell};
+use iced::widget::{
+    button, column, container, pick_list, row, scrollable, text, text_input, Column,
+};
+use iced::{
+    alignment, event, keyboard, mouse, touch, window, Border, Color, Command, Element, Event,
+    Length, Pixels, Point, Rectangle, Size, Theme, Vector,
+};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// The height of the canvas.
+const HEIGHT: f32 = 480.0;
+const LABEL_SIZE: f32 = 12.0;
+const LABEL_WIDTH: f32 = 160.0;
+/// How much a line of the mouse wheel zooms.
+const WHEEL_ZOOM: f32 = 1.2;
+/// The pixels a touchpad scrolls for a line of the mouse wheel.
+const PIXELS_PER_LINE: f32 = 40.0;
+/// The size of the minimap, as wide for its height as its cells.
+const MAP_WIDTH: f32 = 160.0;
+const MAP_HEIGHT: f32 = 120.0;
+/// The distance of the minimap from the corner.
+const MAP_MARGIN: f32 = 10.0;
+/// The width of the side panel.
+const PANEL_WIDTH: f32 = 280.0;
+/// The smallest radius of the nodes icons are drawn on.
+const ICON_RADIUS: f32 = 6.0;
+/// The smallest radius of the nodes thumbnails are drawn in.
+const THUMBNAIL_RADIUS: f32 = 12.0;
+/// The most nodes in view for thumbnails to be drawn and loaded.
+const MAX_THUMBNAILS: usize = 100;
+/// The height of the title of the box of a container.
+const HEADER: f32 = LABEL_SIZE * 1.5;
+/// The width of the ring around collapsed containers.
+const RING: f32 = 3.0;
+/// How far the ring of a pulse grows from its node.
+const PULSE_GROWTH: f32 = 16.0;
+
+#[derive(Default)]
+pub struct Canvas {
+    open: bool,
+    /// The graph drawn, rebuilt when the projection changes while it is
+    /// open.
+    graph: Graph,
+    /// The nodes moving, fading in and pulsing as the graph changes.
+    animation: Animation,
+    /// Whether `graph` is rebuilt on the next frame, for the events recorded
+    /// since it was built.
+    stale: bool,
+    /// The entities to pulse once `graph` is rebuilt.
+    pulsing: BTreeSet<Uuid>,
+    /// The part of the graph shown, `frame` if `None`.
+    viewport: Option<Viewport>,
+    /// The part of the graph fit in view until it is moved, all of it if
+    /// `None`.
+    frame: Option<Rect>,
+    /// The physical pixels of the window per logical pixel, once measured.
+    scale: Option<f32>,
+    /// The size of the canvas, once drawn.
+    size: Option<(f32, f32)>,
+    selection: Selection,
+    /// The entities shown while focusing on a neighborhood, all of them if
+    /// `None`.
+    focus: Option<BTreeSet<Uuid>>,
+    /// The containers drawn as a node standing in for their members.
+    collapsed: BTreeSet<Uuid>,
+    /// Whether the side panel is open.
+    styling: bool,
+    /// The view as edited in the side panel.
+    view: View,
+    /// The rules of the view as last compiled without error.
+    rules: Rules,
+    /// Why the view as edited doesn't compile.
+    rules_error: Option<String>,
+    export: Export,
+}
+
+/// How the canvas is exported as an image.
+struct Export {
+    format: Format,
+    extent: Extent,
+    /// The image pixels per screen pixel.
+    scale: u32,
+    /// Where it was last exported to.
+    exported: Option<PathBuf>,
+}
+
+impl Default for Export {
+    fn default() -> Export {
+        Export {
+            format: Format::default(),
+            extent: Extent::default(),
+            scale: 1,
+            exported: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    /// The canvas of `size` was dragged by screen pixels.
+    Pan {
+        dx: f32,
+        dy: f32,
+        size: (f32, f32),
+    },
+    /// The canvas of `size` was zoomed by `factor` around the point
+    /// `offset` screen pixels from its center.
+    Zoom {
+        factor: f32,
+        offset: (f32, f32),
+        size: (f32, f32),
+    },
+    /// The viewport on the canvas of `size` was centered on the point `x`
+    /// and `y` of the graph from the minimap.
+    Center {
+        x: f32,
+        y: f32,
+        size: (f32, f32),
+    },
+    /// An entity was clicked, or nothing.
+    Select(Option<Uuid>),
+    /// An entity was double-clicked: show its neighbors too, or only it and
+    /// its neighborhood if not focusing yet.
+    Expand(Uuid),
+    /// Show only the neighborhood of the selected entities.
+    Focus,
+    /// Show all entities again.
+    Unfocus,
+    /// Collapse or expand a container.
+    ToggleGroup(Uuid),
+    /// Collapse all containers, or expand them all if any is collapsed.
+    CollapseAll,
+    /// Show the whole graph.
+    Fit,
+    /// Show the selected entities.
+    FitSelection,
+    /// The window has the logical size given, to measure its scale factor.
+    Measure(iced::Size),
+    /// The window has the scale factor given.
+    Measured(f32),
+    /// The canvas was drawn with the size given.
+    Resized((f32, f32)),
+    /// Arrange the selected entities.
+    Arrange(Shape),
+    /// The window is drawn at the time given, while nodes move.
+    Frame(Instant),
+    /// Open or close the side panel.
+    Styles,
+    /// Edit a recorded view, or start over with none.
+    PickView(Option<Uuid>),
+    ViewName(String),
+    Filter(String),
+    RuleQuery(usize, String),
+    RuleColor(usize, String),
+    RuleSize(usize, String),
+    RuleIcon(usize, String),
+    AddRule,
+    RemoveRule(usize),
+    Layout(Layout),
+    SaveView,
+    ExportFormat(Format),
+    ExportExtent(Extent),
+    ExportScale(u32),
+    /// Export the canvas as an image.
+    Export,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_canvas(&mut self, message: Message) -> Command<super::Message> {
+        let limits = self.config.canvas.zoom_limits();
+        let state = &mut self.canvas;
+        match message {
+            Message::Open => {
+                state.open = true;
+                state.fit(None);
+                self.refresh_canvas();
+                // Only screenshots tell the physical size of the window.
+                return window::fetch_size(window::Id::MAIN, |size| {
+                    super::Message::Canvas(Message::Measure(size))
+                });
+            }
+            Message::Measure(size) => {
+                return window::screenshot(window::Id::MAIN, move |screenshot| {
+                    let scale = screenshot.size.width as f32 / size.width;
+                    super::Message::Canvas(Message::Measured(scale))
+                });
+            }
+            Message::Measured(scale) => {
+                state.scale = (scale.is_finite() && scale > 0.0).then_some(scale);
+            }
+            Message::Resized(size) => state.size = Some(size),
+            Message::Pan { dx, dy, size } => state.viewport(size, limits).pan(dx, dy),
+            Message::Zoom {
+                factor,
+                offset,
+                size,
+            } => state.viewport(size, limits).zoom_at(factor, offset, limits),
+            Message::Center { x, y, size } => {
+                let viewport = state.viewport(size, limits);
+                viewport.x = x;
+                viewport.y = y;
+            }
+            Message::Select(None) => state.selection.clear(),
+            Message::Select(Some(id)) if self.modifiers.command() => state.selection.toggle(id),
+            Message::Select(Some(id)) => state.selection.click(id),
+            Message::Expand(id) => {
+                let hops = match state.focus {
+                    Some(_) => 1,
+                    None => self.config.canvas.focus_hops,
+                };
+                let neighborhood = focus::neighborhood(&self.projection, [id], hops);
+                match &mut state.focus {
+                    Some(focus) => focus.extend(neighborhood),
+                    None => {
+                        state.focus = Some(neighborhood);
+                        state.fit(None);
+                    }
+                }
+                self.refresh_canvas();
+            }
+            Message::Focus => {
+                let ids = state.selection.ids().iter().copied();
+                let hops = self.config.canvas.focus_hops;
+                state.focus = Some(focus::neighborhood(&self.projection, ids, hops));
+                state.fit(None);
+                self.refresh_canvas();
+            }
+            Message::Unfocus => {
+                state.focus = None;
+                state.fit(None);
+                self.refresh_canvas();
+            }
+            Message::ToggleGroup(id) => {
+                if !state.collapsed.remove(&id) {
+                    state.collapsed.insert(id);
+                }
+                self.refresh_canvas();
+            }
+            Message::CollapseAll => {
+                state.collapsed = match state.collapsed.is_empty() {
+                    true => Hierarchy::new(&self.projection).containers(),
+                    false => BTreeSet::new(),
+                };
+                self.refresh_canvas();
+            }
+            Message::Fit => state.fit(None),
+            Message::FitSelection => {
+                let selection = &state.selection;
+                let frame = Rect::around(
+                    state
+                        .graph
+                        .nodes()
+                        .iter()
+                        .filter(|node| selection.contains(&node.id))
+                        .map(|node| (node.x, node.y)),
+                );
+                state.fit(frame);
+            }
+            Message::Arrange(shape) => {
+                let actions = selection::arrange(&self.projection, &state.selection, shape);
+                return self.record(actions);
+            }
+            Message::Frame(now) => {
+                let stale = state.stale;
+                if stale {
+                    self.refresh_canvas();
+                }
+                let state = &mut self.canvas;
+                state.animation.step(&mut state.graph, now);
+                if !stale {
+                    return Command::none();
+                }
+            }
+            Message::Styles => state.styling = !state.styling,
+            Message::PickView(id) => {
+                state.view = style::views(&self.projection)
+                    .into_iter()
+                    .find(|view| id.is_some() && view.id == id)
+                    .unwrap_or_default();
+                self.compile_view();
+            }
+            Message::ViewName(name) => state.view.name = name,
+            Message::Filter(filter) => {
+                state.view.filter = filter;
+                self.compile_view();
+            }
+            Message::RuleQuery(i, query) => self.edit_rule(i, |rule| rule.query = query),
+            Message::RuleColor(i, color) => self.edit_rule(i, |rule| rule.color = color),
+            Message::RuleSize(i, size) => self.edit_rule(i, |rule| rule.size = size),
+            Message::RuleIcon(i, icon) => self.edit_rule(i, |rule| rule.icon = icon),
+            Message::AddRule => state.view.rules.push(Rule::default()),
+            Message::Layout(layout) => {
+                state.view.layout = layout;
+                state.fit(None);
+                self.compile_view();
+            }
+            Message::RemoveRule(i) => {
+                if i < state.view.rules.len() {
+                    state.view.rules.remove(i);
+                    self.compile_view();
+                }
+            }
+            Message::SaveView => {
+                if state.rules_error.is_none() {
+                    let actions = state.view.save();
+                    return self.record(actions);
+                }
+            }
+            Message::ExportFormat(format) => state.export.format = format,
+            Message::ExportExtent(extent) => state.export.extent = extent,
+            Message::ExportScale(scale) => state.export.scale = scale,
+            Message::Export => self.export_canvas(),
+            Message::Close => *state = Canvas::default(),
+        }
+        Command::batch([self.load_thumbnails(), self.render_nodes()])
+    }
+
+    /// The entities drawn with a thumbnail, if the canvas is open.
+    pub(super) fn canvas_thumbnailed(&self) -> Vec<Uuid> {
+        self.canvas_nodes(thumbnailed)
+    }
+
+    /// The entities drawn as nodes, if the canvas is open.
+    pub(super) fn canvas_drawn(&self) -> Vec<Uuid> {
+        self.canvas_nodes(|_, _, _| true)
+    }
+
+    /// The entities of the nodes in view that are `drawn`.
+    fn canvas_nodes(&self, drawn: impl Fn(&Scene, &Viewport, &Node) -> bool) -> Vec<Uuid> {
+        let state = &self.canvas;
+        let Some(size) = state.size.filter(|_| state.open) else {
+            return Vec::new();
+        };
+        let limits = self.config.canvas.zoom_limits();
+        let viewport = state
+            .viewport
+            .unwrap_or_else(|| fit(&state.graph, state.frame, size, limits));
+        let scene = state.graph.scene(&viewport, size);
+        scene
+            .nodes
+            .iter()
+            .map(|n| &state.graph.nodes()[*n])
+            .filter(|node| drawn(&scene, &viewport, node))
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// Writes the canvas as an image named after the view to the documents
+    /// directory.
+    fn export_canvas(&mut self) {
+        let name = match self.canvas.view.name.trim() {
+            "" => self.t("canvas"),
+            name => name.to_string(),
+        };
+        let colors = Colors {
+            canvas: hex(self.graph_colors.canvas),
+            node: hex(self.graph_colors.node),
+            edge: hex(self.graph_colors.edge),
+        };
+        let limits = self.config.canvas.zoom_limits();
+        let state = &mut self.canvas;
+        let size = state.size.unwrap_or((HEIGHT, HEIGHT));
+        let viewport = state
+            .viewport
+            .unwrap_or_else(|| fit(&state.graph, state.frame, size, limits));
+        let export = &state.export;
+        let snapshot = Snapshot::new(
+            &state.graph,
+            export.extent,
+            viewport,
+            size,
+            export.scale,
+            colors,
+            self.config.canvas.curved_edges,
+        );
+        let path = super::export_path(&name, export.format.extension());
+        match snapshot.write(&state.graph, export.format, &path) {
+            Ok(()) => state.export.exported = Some(path),
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    fn edit_rule(&mut self, i: usize, edit: impl FnOnce(&mut Rule)) {
+        if let Some(rule) = self.canvas.view.rules.get_mut(i) {
+            edit(rule);
+            self.compile_view();
+        }
+    }
+
+    /// Compiles the view edited, and draws the canvas with it unless it has
+    /// an error.
+    fn compile_view(&mut self) {
+        let state = &mut self.canvas;
+        match Rules::new(&state.view) {
+            Ok(rules) => {
+                state.rules = rules;
+                state.rules_error = None;
+                self.refresh_canvas();
+            }
+            Err(e) => state.rules_error = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Rebuilds the graph drawn, if the canvas is open.
+    pub(super) fn refresh_canvas(&mut self) {
+        let state = &mut self.canvas;
+        if state.open {
+            let projection = &self.projection;
+            state.selection.retain(|id| projection.contains(id));
+            let mut graph = Graph::new(
+                projection,
+                &self.config.canvas,
+                &state.rules,
+                state.focus.as_ref(),
+                &state.collapsed,
+            );
+            graph.restyle(|node| {
+                if let Some(rendered) = self.plugins.rendered(&node.id) {
+                    rendered.restyle(node);
+                }
+            });
+            let old = std::mem::replace(&mut state.graph, graph);
+            let now = Instant::now();
+            state.animation.rebuilt(&old, &mut state.graph, now);
+            state.stale = false;
+            let pulsing = std::mem::take(&mut state.pulsing);
+            let drawn = state.graph.nodes().iter().map(|n| n.id);
+            state
+                .animation
+                .pulse(drawn.filter(|id| pulsing.contains(id)), now);
+        }
+    }
+
+    /// Rebuilds the graph drawn on the next frame, once for all the events
+    /// recorded until then, and pulses the entities among `changed`.
+    pub(super) fn refresh_canvas_later(&mut self, changed: &[Uuid]) {
+        let state = &mut self.canvas;
+        if state.open {
+            state.stale = true;
+            state.pulsing.extend(changed);
+        }
+    }
+
+    /// The canvas, if it is open.
+    pub(super) fn view_canvas(&self) -> Option<Element<'_, super::Message>> {
+        let state = &self.canvas;
+        if !state.open {
+            return None;
+        }
+        let message = |m| super::Message::Canvas(m);
+        let view = GraphView {
+            graph: &state.graph,
+            viewport: state.viewport,
+            frame: state.frame,
+            limits: self.config.canvas.zoom_limits(),
+            scale: state.scale.unwrap_or(1.0),
+            colors: self.graph_colors,
+            curved: self.config.canvas.curved_edges,
+            minimap: self.config.canvas.minimap,
+            selection: &state.selection,
+            animation: &state.animation,
+            projection: &self.projection,
+            thumbnails: &self.thumbnails,
+            size: state.size,
+        };
+        let count = state.graph.nodes().len();
+        let focus = match state.focus {
+            Some(_) => button(text(self.t("canvas-unfocus"))).on_press(message(Message::Unfocus)),
+            None => button(text(self.t("canvas-focus")))
+                .on_press_maybe((!state.selection.is_empty()).then(|| message(Message::Focus))),
+        };
+        let arranges = state.selection.len() >= 2 && !self.read_only;
+        let controls = row![
+            button(text(self.t("canvas-fit"))).on_press(message(Message::Fit)),
+            button(text(self.t("canvas-fit-selection"))).on_press_maybe(
+                (!state.selection.is_empty()).then(|| message(Message::FitSelection))
+            ),
+            focus,
+            button(text(self.t(match state.collapsed.is_empty() {
+                true => "canvas-collapse",
+                false => "canvas-expand",
+            })))
+            .on_press(message(Message::CollapseAll)),
+            button(text(self.t("canvas-arrange-circle")))
+                .on_press_maybe(arranges.then(|| message(Message::Arrange(Shape::Circle)))),
+            button(text(self.t("canvas-arrange-grid")))
+                .on_press_maybe(arranges.then(|| message(Message::Arrange(Shape::Grid)))),
+            button(text(self.t("canvas-styles"))).on_press(message(Message::Styles)),
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+            text(self.tr("canvas-count", &[("count", count.into())])),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        let graph: Element<'_, super::Message> = match state.styling {
+            true => row![Element::new(view), self.view_canvas_styles()]
+                .spacing(10)
+                .into(),
+            false => Element::new(view),
+        };
+        let dialog = column![
+            text(self.t("canvas")).size(30),
+            graph,
+            controls,
+            self.view_canvas_export()
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    /// The row choosing how to export the canvas.
+    fn view_canvas_export(&self) -> Element<'_, super::Message> {
+        let export = &self.canvas.export;
+        let message = |m| super::Message::Canvas(m);
+        let formats: Vec<Labeled<Format>> = Format::ALL
+            .into_iter()
+            .map(|format| Labeled {
+                value: format,
+                label: format.extension().to_uppercase(),
+            })
+            .collect();
+        let extents: Vec<Labeled<Extent>> = Extent::ALL
+            .into_iter()
+            .map(|extent| Labeled {
+                value: extent,
+                label: self.t(extent.message_id()),
+            })
+            .collect();
+        let scales: Vec<Labeled<u32>> = snapshot::SCALES
+            .into_iter()
+            .map(|scale| Labeled {
+                value: scale,
+                label: format!("{}×", scale),
+            })
+            .collect();
+        let format = Labeled::selected(&formats, &export.format);
+        let extent = Labeled::selected(&extents, &export.extent);
+        let scale = Labeled::selected(&scales, &export.scale);
+        let mut row = row![
+            pick_list(formats, Some(format), move |f| message(
+                Message::ExportFormat(f.value)
+            )),
+            pick_list(extents, Some(extent), move |e| message(
+                Message::ExportExtent(e.value)
+            )),
+            pick_list(scales, Some(scale), move |s| message(Message::ExportScale(
+                s.value
+            ))),
+            button(text(self.t("canvas-export"))).on_press(message(Message::Export)),
+        ]
+        .spacing(10)
+        .align_items(iced::Alignment::Center);
+        if let Some(path) = &export.exported {
+            row = row.push(text(self.tr(
+                "canvas-exported",
+                &[("path", path.display().to_string().into())],
+            )));
+        }
+        row.into()
+    }
+
+    /// The side panel, editing the view drawn.
+    fn view_canvas_styles(&self) -> Element<'_, super::Message> {
+        let state = &self.canvas;
+        let message = |m| super::Message::Canvas(m);
+        let mut views = Column::new().spacing(4).push(
+            button(text(self.t("canvas-all")))
+                .width(Length::Fill)
+                .style(iced::theme::Button::Secondary)
+                .on_press(message(Message::PickView(None))),
+        );
+        for view in style::views(&self.projection) {
+            views = views.push(
+                button(text(&view.name))
+                    .width(Length::Fill)
+                    .style(iced::theme::Button::Secondary)
+                    .on_press(message(Message::PickView(view.id))),
+            );
+        }
+        let mut rules = Column::new().spacing(6);
+        for (i, rule) in state.view.rules.iter().enumerate() {
+            rules = rules.push(
+                column![
+                    row![
+                        text_input(&self.t("canvas-rule-query"), &rule.query)
+                            .on_input(move |v| message(Message::RuleQuery(i, v))),
+                        button(text("×")).on_press(message(Message::RemoveRule(i))),
+                    ]
+                    .spacing(4),
+                    row![
+                        text_input("#e06c75", &rule.color)
+                            .on_input(move |v| message(Message::RuleColor(i, v))),
+                        text_input(&self.t("canvas-rule-size"), &rule.size)
+                            .on_input(move |v| message(Message::RuleSize(i, v))),
+                        text_input(&self.t("canvas-rule-icon"), &rule.icon)
+                            .on_input(move |v| message(Message::RuleIcon(i, v))),
+                    ]
+                    .spacing(4),
+                ]
+                .spacing(4),
+            );
+        }
+        let layouts: Vec<Labeled<Layout>> = Layout::ALL
+            .into_iter()
+            .map(|layout| Labeled {
+                value: layout,
+                label: self.t(layout.message_id()),
+            })
+            .collect();
+        let layout = Labeled::selected(&layouts, &state.view.layout);
+        let mut panel = column![
+            scrollable(views).height(Length::Fixed(100.0)),
+            text_input(&self.t("canvas-view-name"), &state.view.name)
+                .on_input(move |v| message(Message::ViewName(v))),
+            text_input(&self.t("canvas-filter"), &state.view.filter)
+                .on_input(move |v| message(Message::Filter(v))),
+            pick_list(layouts, Some(layout), move |l| message(Message::Layout(
+                l.value
+            ))),
+            scrollable(rules).height(Length::Fill),
+            button(text(self.t("canvas-add-rule"))).on_press(message(Message::AddRule)),
+        ]
+        .spacing(6);
+        if let Some(error) = &state.rules_error {
+            panel = panel
+                .push(text(error).style(iced::theme::Text::Color(self.theme.palette().danger)));
+        }
+        let saves =
+            state.rules_error.is_none() && !state.view.name.trim().is_empty() && !self.read_only;
+        panel = panel.push(
+            button(text(self.t("canvas-save-view")))
+                .on_press_maybe(saves.then(|| message(Message::SaveView))),
+        );
+        container(panel)
+            .width(Length::Fixed(PANEL_WIDTH))
+            .height(Length::Fixed(HEIGHT))
+            .into()
+    }
+}
+
+impl Canvas {
+    /// Whether the canvas is animated or waits to be rebuilt, to be drawn on
+    /// every frame.
+    pub fn is_animating(&self) -> bool {
+        self.open && (self.stale || self.animation.is_running())
+    }
+
+    /// The viewport to move on a canvas of `size`, fitting the frame if
+    /// none was chosen yet.
+    fn viewport(&mut self, size: (f32, f32), limits: (f32, f32)) -> &mut Viewport {
+        let (graph, frame) = (&self.graph, self.frame);
+        self.viewport
+            .get_or_insert_with(|| fit(graph, frame, size, limits))
+    }
+
+    /// Fits `frame` of the graph in view, or all of it.
+    fn fit(&mut self, frame: Option<Rect>) {
+        self.viewport = None;
+        self.frame = frame;
+    }
+}
+
+/// The viewport showing `frame` of `graph` on a canvas of `size`, or all of
+/// it, as far as the zoom `limits` allow.
+fn fit(graph: &Graph, frame: Option<Rect>, size: (f32, f32), (min, max): (f32, f32)) -> Viewport {
+    let mut viewport = match frame.or(graph.bounds()) {
+        Some(frame) => Viewport::fit(&frame, size),
+        None => Viewport::default(),
+    };
+    viewport.zoom = viewport.zoom.clamp(min, max);
+    viewport
+}
+
+struct GraphView<'a> {
+    graph: &'a Graph,
+    selection: &'a Selection,
+    animation: &'a Animation,
+    projection: &'a Projection,
+    thumbnails: &'a Thumbnails,
+    viewport: Option<Viewport>,
+    frame: Option<Rect>,
+    limits: (f32, f32),
+    scale: f32,
+    colors: GraphColors,
+    curved: bool,
+    minimap: bool,
+    /// The size of the canvas as last told.
+    size: Option<(f32, f32)>,
+}
+
+#[derive(Default)]
+struct State {
+    drag: Drag,
+    /// The last click, to tell double clicks.
+    click: Option<Click>,
+    /// The fingers touching the canvas, where they last were.
+    fingers: Vec<(touch::Finger, Point)>,
+    modifiers: keyboard::Modifiers,
+}
+
+/// What the button pressed on the canvas drags, while it is held.
+#[derive(Default)]
+enum Drag {
+    #[default]
+    None,
+    /// The canvas, last dragged to the point.
+    Canvas(Point),
+    /// The viewport on the minimap.
+    Minimap,
+}
+
+impl GraphView<'_> {
+    fn viewport(&self, size: (f32, f32)) -> Viewport {
+        self.viewport
+            .unwrap_or_else(|| fit(self.graph, self.frame, size, self.limits))
+    }
+
+    /// The node at `point` of a canvas of `size`, unless it is in a
+    /// cluster.
+    fn node_at(&self, point: Vector, size: (f32, f32)) -> Option<&Node> {
+        let viewport = self.viewport(size);
+        if viewport.zoom < CLUSTER_ZOOM {
+            return None;
+        }
+        let point = viewport.to_canvas((point.x, point.y), size);
+        let radius = viewport.node_radius() / viewport.zoom;
+        let node = self.graph.node_at(point, radius)?;
+        Some(&self.graph.nodes()[node])
+    }
+
+    /// The container whose title is at `point` of a canvas of `size`, the
+    /// innermost.
+    fn group_at(&self, point: Vector, size: (f32, f32)) -> Option<Uuid> {
+        let viewport = self.viewport(size);
+        if viewport.zoom < CLUSTER_ZOOM {
+            return None;
+        }
+        let (x, y) = viewport.to_canvas((point.x, point.y), size);
+        let group = self.graph.groups().iter().rev().find(|group| {
+            let bounds = group.bounds;
+            (bounds.left..=bounds.right).contains(&x)
+                && (bounds.top..=bounds.top + HEADER / viewport.zoom).contains(&y)
+        })?;
+        Some(self.graph.nodes()[group.node].id)
+    }
+
+    /// What `node` of `radius` is drawn as, from the bottom: its pulse, its
+    /// ring as a collapsed container and itself, by radius and color.
+    fn outlines(&self, node: &Node, radius: f32) -> Vec<(f32, Color)> {
+        let color = match (self.selection.contains(&node.id), node.style.color) {
+            (true, _) => self.colors.selection,
+            (false, Some(color)) => color.into(),
+            (false, None) => self.colors.node,
+        };
+        let opacity = self.animation.opacity(&node.id);
+        let faded = |color: Color| Color {
+            a: color.a * opacity,
+            ..color
+        };
+        let mut outlines = Vec::with_capacity(3);
+        if let Some(pulse) = self.animation.pulse_of(&node.id) {
+            let ring = Color {
+                a: 0.6 * (1.0 - pulse),
+                ..self.colors.selection
+            };
+            outlines.push((radius + PULSE_GROWTH * pulse, ring));
+        }
+        if node.members > 0 {
+            outlines.push((radius + RING, faded(self.colors.edge)));
+        }
+        outlines.push((radius, faded(color)));
+        outlines
+    }
+
+    /// Where the minimap is on a canvas of `bounds`, if it is shown.
+    fn minimap_bounds(&self, bounds: Rectangle) -> Option<Rectangle> {
+        self.graph.minimap().extent()?;
+        (self.minimap && bounds.width > 2.0 * MAP_WIDTH).then_some(Rectangle {
+            x: bounds.x + bounds.width - MAP_WIDTH - MAP_MARGIN,
+            y: bounds.y + bounds.height - MAP_HEIGHT - MAP_MARGIN,
+            width: MAP_WIDTH,
+            height: MAP_HEIGHT,
+        })
+    }
+
+    /// Centers the view on the point of the canvas at `position` on the
+    /// minimap of the canvas at `bounds`, or ends the drag if the minimap is
+    /// gone, as when the graph was emptied or the canvas narrowed.
+    fn drag_minimap(&self, drag: &mut Drag, position: Point, bounds: Rectangle) -> Option<Message> {
+        let Some(map) = self.minimap_bounds(bounds) else {
+            *drag = Drag::None;
+            return None;
+        };
+        let (x, y) = self.graph.minimap().to_canvas(
+            (
+                position.x.clamp(map.x, map.x + map.width) - map.x,
+                position.y.clamp(map.y, map.y + map.height) - map.y,
+            ),
+            (map.width, map.height),
+        );
+        let size = (bounds.width, bounds.height);
+        Some(Message::Center { x, y, size })
+    }
+
+    /// Draws the density of the nodes on the minimap at `map`, and the part
+    /// of the graph in `viewport` on a canvas of `size`.
+    fn draw_minimap(
+        &self,
+        renderer: &mut iced::Renderer,
+        map: Rectangle,
+        viewport: &Viewport,
+        size: (f32, f32),
+    ) {
+        let minimap = self.graph.minimap();
+        renderer.fill_quad(
+            Quad {
+                bounds: map,
+                border: Border {
+                    color: self.colors.edge,
+                    width: 1.0,
+                    radius: 0.0.into(),
+                },
+                ..Quad::default()
+            },
+            self.colors.canvas,
+        );
+        let cell = Size::new(
+            map.width / minimap::COLUMNS as f32,
+            map.height / minimap::ROWS as f32,
+        );
+        for (column, row, full) in minimap.cells() {
+            let bounds = Rectangle {
+                x: map.x + column as f32 * cell.width,
+                y: map.y + row as f32 * cell.height,
+                width: cell.width,
+                height: cell.height,
+            };
+            let color = Color {
+                a: 0.3 + 0.7 * full,
+                ..self.colors.node
+            };
+            renderer.fill_quad(
+                Quad {
+                    bounds,
+                    ..Quad::default()
+                },
+                color,
+            );
+        }
+        let visible = viewport.visible(size);
+        let (left, top) = minimap.to_map((visible.left, visible.top), (map.width, map.height));
+        let (right, bottom) =
+            minimap.to_map((visible.right, visible.bottom), (map.width, map.height));
+        let frame = Rectangle {
+            x: map.x + left,
+            y: map.y + top,
+            width: right - left,
+            height: bottom - top,
+        };
+        if let Some(frame) = frame.intersection(&map) {
+            renderer.fill_quad(
+                Quad {
+                    bounds: frame,
+                    border: Border {
+                        color: self.colors.selection,
+                        width: 1.5,
+                        radius: 0.0.into(),
+                    },
+                    ..Quad::default()
+                },
+                Color::TRANSPARENT,
+            );
+        }
+    }
+}
+
+impl Widget<super::Message, Theme, iced::Renderer> for GraphView<'_> {
+    fn size(&self) -> Size<Length> {
+        Size::new(Length::Fill, Length::Fixed(HEIGHT))
+    }
+
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn layout(
+        &self,
+        _tree: &mut Tree,
+        _renderer: &iced::Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::Node::new(limits.resolve(Length::Fill, Length::Fixed(HEIGHT), Size::ZERO))
+    }
+
+    fn draw(
+        &self,
+        _tree: &Tree,
+        renderer: &mut iced::Renderer,
+        _theme: &Theme,
+        style: &renderer::Style,
+        layout: layout::Layout<'_>,
+        _cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        let size = (bounds.width, bounds.height);
+        let viewport = self.viewport(size);
+        let scene = self.graph.scene(&viewport, size);
+        let at = |x, y| {
+            let (x, y) = viewport.to_screen((x, y), size);
+            Point::new(bounds.x + x, bounds.y + y)
+        };
+        renderer.with_layer(bounds, |renderer| {
+            renderer.fill_quad(
+                Quad {
+                    bounds,
+                    ..Quad::default()
+                },
+                self.colors.canvas,
+            );
+            for group in scene.groups.iter().map(|g| &self.graph.groups()[*g]) {
+                let (left, top) = viewport.to_screen((group.bounds.left, group.bounds.top), size);
+                let (right, bottom) =
+                    viewport.to_screen((group.bounds.right, group.bounds.bottom), size);
+                let frame = Rectangle {
+                    x: bounds.x + left,
+                    y: bounds.y + top,
+                    width: right - left,
+                    height: bottom - top,
+                };
+                renderer.fill_quad(
+                    Quad {
+                        bounds: frame,
+                        border: Border {
+                            color: Color {
+                                a: 0.6,
+                                ..self.colors.node
+                            },
+                            width: 1.0,
+                            radius: 6.0.into(),
+                        },
+                        ..Quad::default()
+                    },
+                    Color {
+                        a: 0.08,
+                        ..self.colors.node
+                    },
+                );
+                if viewport.zoom >= CLUSTER_ZOOM {
+                    let title = &self.graph.nodes()[group.node].label;
+                    let position = Point::new(frame.center_x(), frame.y + 2.0);
+                    label(renderer, title, position, style.text_color, frame);
+                }
+            }
+            let edges = edges::mesh(&scene.segments, &viewport, size, self.curved);
+            if !edges.indices.is_empty() {
+                let color = color::pack(self.colors.edge);
+                let vertices = edges
+                    .vertices
+                    .into_iter()
+                    .map(|(x, y)| SolidVertex2D {
+                        position: [x, y],
+                        color,
+                    })
+                    .collect();
+                let mesh = Mesh::Solid {
+                    buffers: mesh::Indexed {
+                        vertices,
+                        indices: edges.indices,
+                    },
+                    size: bounds.size(),
+                };
+                renderer.with_translation(Vector::new(bounds.x, bounds.y), |renderer| {
+                    renderer.draw_mesh(mesh)
+                });
+            }
+            // Nodes of other shapes than circles are meshes too, drawn over
+            // the edges.
+            let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+            for node in scene.nodes.iter().map(|n| &self.graph.nodes()[*n]) {
+                let center = viewport.to_screen((node.x, node.y), size);
+                let radius = viewport.node_radius() * node.style.size;
+                for (radius, color) in self.outlines(node, radius) {
+                    let Some(corners) = node.style.shape.corners(center, radius) else {
+                        break;
+                    };
+                    let first = vertices.len() as u32;
+                    indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+                    vertices.extend(corners.map(|(x, y)| SolidVertex2D {
+                        position: [x, y],
+                        color: color::pack(color),
+                    }));
+                }
+            }
+            if !indices.is_empty() {
+                let mesh = Mesh::Solid {
+                    buffers: mesh::Indexed { vertices, indices },
+                    size: bounds.size(),
+                };
+                renderer.with_translation(Vector::new(bounds.x, bounds.y), |renderer| {
+                    renderer.draw_mesh(mesh)
+                });
+            }
+        });
+        // Meshes are drawn over the quads of their layer, so the nodes are
+        // drawn in one above.
+        renderer.with_layer(bounds, |renderer| {
+            for node in scene.nodes.iter().map(|n| &self.graph.nodes()[*n]) {
+                let center = at(node.x, node.y);
+                let radius = viewport.node_radius() * node.style.size;
+                let opacity = self.animation.opacity(&node.id);
+                let faded = |color: Color| Color {
+                    a: color.a * opacity,
+                    ..color
+                };
+                if node.style.shape == style::Shape::Circle {
+                    for (radius, color) in self.outlines(node, radius) {
+                        circle(renderer, center, radius, color);
+                    }
+                }
+                let thumbnail = match thumbnailed(&scene, &viewport, node) {
+                    true => self
+                        .projection
+                        .entity(&node.id)
+                        .and_then(|entity| self.thumbnails.get(entity)),
+                    false => None,
+                };
+                if let Some(thumbnail) = thumbnail {
+                    draw_thumbnail(renderer, thumbnail, center, radius, opacity);
+                }
+                let members = node.members.to_string();
+                let icon = match (&node.style.icon, node.members) {
+                    (Some(icon), _) => Some(icon.as_str()),
+                    (None, 0) => None,
+                    (None, _) => Some(members.as_str()),
+                };
+                if let Some(icon) = icon.filter(|_| radius >= ICON_RADIUS && thumbnail.is_none()) {
+                    centered(
+                        renderer,
+                        icon,
+                        center,
+                        radius,
+                        faded(self.colors.canvas),
+                        bounds,
+                    );
+                }
+                if scene.labels {
+                    let position = Point::new(center.x, center.y + radius + 2.0);
+                    label(
+                        renderer,
+                        &node.label,
+                        position,
+                        faded(style.text_color),
+                        bounds,
+                    );
+                }
+            }
+            for cluster in &scene.clusters {
+                let center = at(cluster.x, cluster.y);
+                let radius = cluster.radius();
+                circle(renderer, center, radius, self.colors.node);
+                let count = cluster.count.to_string();
+                centered(renderer, &count, center, radius, self.colors.canvas, bounds);
+            }
+        });
+        if let Some(map) = self.minimap_bounds(bounds) {
+            renderer.with_layer(map, |renderer| {
+                self.draw_minimap(renderer, map, &viewport, size)
+            });
+        }
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: layout::Layout<'_>,
+        cursor: mouse::Cursor,
+        _renderer: &iced::Renderer,
+        _clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, super::Message>,
+        _viewport: &Rectangle,
+    ) -> event::Status {
+        let bounds = layout.bounds();
+        let size = (bounds.width, bounds.height);
+        let map = self.minimap_bounds(bounds);
+        let state = tree.state.downcast_mut::<State>();
+        let drag = &mut state.drag;
+        let message = match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return event::Status::Ignored;
+                };
+                if map.is_some_and(|map| map.contains(position)) {
+                    *drag = Drag::Minimap;
+                    self.drag_minimap(drag, position, bounds)
+                } else {
+                    *drag = Drag::Canvas(position);
+                    let click = Click::new(position, state.click);
+                    state.click = Some(click);
+                    let point = position - bounds.position();
+                    match (self.node_at(point, size), click.kind()) {
+                        (Some(node), click::Kind::Double) if node.members > 0 => {
+                            Some(Message::ToggleGroup(node.id))
+                        }
+                        (Some(node), click::Kind::Double) => Some(Message::Expand(node.id)),
+                        (Some(node), _) => Some(Message::Select(Some(node.id))),
+                        (None, _) => match self.group_at(point, size) {
+                            Some(id) => Some(Message::ToggleGroup(id)),
+                            None => Some(Message::Select(None)),
+                        },
+                    }
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { position }) => match *drag {
+                Drag::None => return event::Status::Ignored,
+                Drag::Canvas(from) => {
+                    *drag = Drag::Canvas(position);
+                    Some(Message::Pan {
+                        dx: position.x - from.x,
+                        dy: position.y - from.y,
+                        size,
+                    })
+                }
+                Drag::Minimap => self.drag_minimap(drag, position, bounds),
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                if matches!(std::mem::take(drag), Drag::None) {
+                    return event::Status::Ignored;
+                }
+                None
+            }
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let Some(position) = cursor.position_over(bounds) else {
+                    return event::Status::Ignored;
+                };
+                let offset = (
+                    position.x - bounds.center_x(),
+                    position.y - bounds.center_y(),
+                );
+                let zoom = |lines: f32| Message::Zoom {
+                    factor: WHEEL_ZOOM.powf(lines),
+                    offset,
+                    size,
+                };
+                // Wheels zoom, touchpads pan with two fingers, and zoom when
+                // pinched, which they send as scrolling with Ctrl.
+                match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => Some(zoom(y)),
+                    mouse::ScrollDelta::Pixels { y, .. } if state.modifiers.command() => {
+                        Some(zoom(y / self.scale / PIXELS_PER_LINE))
+                    }
+                    mouse::ScrollDelta::Pixels { x, y } => Some(Message::Pan {
+                        dx: x / self.scale,
+                        dy: y / self.scale,
+                        size,
+                    }),
+                }
+            }
+            Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+                state.modifiers = modifiers;
+                return event::Status::Ignored;
+            }
+            // Exports of the view need the size of the canvas.
+            Event::Window(_, window::Event::RedrawRequested(_)) => {
+                if self.size != Some(size) {
+                    shell.publish(super::Message::Canvas(Message::Resized(size)));
+                }
+                return event::Status::Ignored;
+            }
+            Event::Touch(touch::Event::FingerPressed { id, position }) => {
+                if !bounds.contains(position) {
+                    return event::Status::Ignored;
+                }
+                state.fingers.retain(|(finger, _)| *finger != id);
+                state.fingers.push((id, position));
+                None
+            }
+            Event::Touch(touch::Event::FingerMoved { id, position }) => {
+                let Some(moved) = state.fingers.iter().position(|(finger, _)| *finger == id) else {
+                    return event::Status::Ignored;
+                };
+                let before = state.fingers.clone();
+                state.fingers[moved].1 = position;
+                match (&before[..], &state.fingers[..]) {
+                    ([(_, from)], [(_, to)]) => Some(Message::Pan {
+                        dx: to.x - from.x,
+                        dy: to.y - from.y,
+                        size,
+                    }),
+                    ([(_, a), (_, b), ..], [(_, c), (_, d), ..]) => {
+                        // Pinching zooms around the fingers, which pan too.
+                        let (from, to) = (midpoint(*a, *b), midpoint(*c, *d));
+                        let factor = c.distance(*d) / a.distance(*b).max(1.0);
+                        shell.publish(super::Message::Canvas(Message::Pan {
+                            dx: to.x - from.x,
+                            dy: to.y - from.y,
+                            size,
+                        }));
+                        Some(Message::Zoom {
+                            factor: if factor.is_finite() { factor } else { 1.0 },
+                            offset: (to.x - bounds.center_x(), to.y - bounds.center_y()),
+                            size,
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            Event::Touch(
+                touch::Event::FingerLifted { id, .. } | touch::Event::FingerLost { id, .. },
+            ) => {
+                let touching = state.fingers.len();
+                state.fingers.retain(|(finger, _)| *finger != id);
+                if state.fingers.len() == touching {
+                    return event::Status::Ignored;
+                }
+                None
+            }
+            _ => return event::Status::Ignored,
+        };
+        if let Some(message) = message {
+            shell.publish(super::Message::Canvas(message));
+        }
+        event::Status::Captured
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: layout::Layout<'_>,
+        cursor: mouse::Cursor,
+        _viewport: &Rectangle,
+        _renderer: &iced::Renderer,
+    ) -> mouse::Interaction {
+        let bounds = layout.bounds();
+        if !matches!(&tree.state.downcast_ref::<State>().drag, Drag::None) {
+            mouse::Interaction::Grabbing
+        } else if self
+            .minimap_bounds(bounds)
+            .is_some_and(|map| cursor.is_over(map))
+        {
+            mouse::Interaction::Pointer
+        } else if cursor.is_over(bounds) {
+            mouse::Interaction::Grab
+        } else {
+            mouse::Interaction::Idle
+        }
+    }
+}
+
+fn hex(color: Color) -> Hex {
+    let [r, g, b, _] = color.into_rgba8();
+    Hex(r, g, b)
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    Point::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0)
+}
+
+fn circle(renderer: &mut iced::Renderer, center: Point, radius: f32, color: Color) {
+    renderer.fill_quad(
+        Quad {
+            bounds: Rectangle {
+                x: center.x - radius,
+                y: center.y - radius,
+                width: 2.0 * radius,
+                height: 2.0 * radius,
+            },
+            border: Border {
+                radius: radius.into(),
+                ..Border::default()
+            },
+            ..Quad::default()
+        },
+        color,
+    );
+}
+
+/// Whether `node` of `scene` is drawn large enough for a thumbnail, with few
+/// enough others in view.
+fn thumbnailed(scene: &Scene, viewport: &Viewport, node: &Node) -> bool {
+    scene.nodes.len() <= MAX_THUMBNAILS
+        && viewport.node_radius() * node.style.size >= THUMBNAIL_RADIUS
+}
+
+/// Draws `thumbnail` in the square inside the circle of `radius` around
+/// `center`, a quad per cell.
+fn draw_thumbnail(
+    renderer: &mut iced::Renderer,
+    thumbnail: &Thumbnail,
+    center: Point,
+    radius: f32,
+    opacity: f32,
+) {
+    let side = radius * std::f32::consts::SQRT_2;
+    let cell = side / thumbnail.width.max(thumbnail.height).max(1) as f32;
+    let left = center.x - cell * thumbnail.width as f32 / 2.0;
+    let top = center.y - cell * thumbnail.height as f32 / 2.0;
+    for y in 0..thumbnail.height {
+        for x in 0..thumbnail.width {
+            let [r, g, b] = thumbnail.pixel(x, y);
+            renderer.fill_quad(
+                Quad {
+                    bounds: Rectangle {
+                        x: left + cell * x as f32,
+                        y: top + cell * y as f32,
+                        width: cell,
+                        height: cell,
+                    },
+                    ..Quad::default()
+                },
+                Color {
+                    a: opacity,
+                    ..Color::from_rgb8(r, g, b)
+                },
+            );
+        }
+    }
+}
+
+/// Draws `content` on the circle of `radius` around `center`.
+fn centered(
+    renderer: &mut iced::Renderer,
+    content: &str,
+    center: Point,
+    radius: f32,
+    color: Color,
+    clip: Rectangle,
+) {
+    renderer.fill_text(
+        Text {
+            content,
+            bounds: Size::new(2.0 * radius, 2.0 * radius),
+            size: Pixels(LABEL_SIZE.min(1.4 * radius)),
+            line_height: text_renderer::LineHeight::default(),
+            font: renderer.default_font(),
+            horizontal_alignment: alignment::Horizontal::Center,
+            vertical_alignment: alignment::Vertical::Center,
+            shaping: text_renderer::Shaping::Basic,
+        },
+        center,
+        color,
+        clip,
+    );
+}
+
+/// Draws `content` centered below `position`.
+fn label(
+    renderer: &mut iced::Renderer,
+    content: &str,
+    position: Point,
+    color: Color,
+    clip: Rectangle,
+) {
+    renderer.fill_text(
+        Text {
+            content,
+            bounds: Size::new(LABEL_WIDTH, LABEL_SIZE * 1.5),
+            size: Pixels(LABEL_SIZE),
+            line_height: text_renderer::LineHeight::default(),
+            font: renderer.default_font(),
+            horizontal_alignment: alignment::Horizontal::Center,
+            vertical_alignment: alignment::Vertical::Top,
+            shaping: text_renderer::Shaping::Basic,
+        },
+        position,
+        color,
+        clip,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Style;
+
+    #[test]
+    fn dragging_the_minimap_ends_when_it_is_gone() {
+        let nodes = (0..4)
+            .map(|n| Node {
+                id: Uuid::from_u128(n),
+                label: n.to_string(),
+                x: n as f32 * 100.0,
+                y: 0.0,
+                style: Style::default(),
+                members: 0,
+            })
+            .collect();
+        let graph = Graph::from_parts(nodes, Vec::new());
+        let (selection, animation) = (Selection::default(), Animation::default());
+        let (projection, thumbnails) = (Projection::default(), Thumbnails::default());
+        let view = |graph| GraphView {
+            graph,
+            selection: &selection,
+            animation: &animation,
+            projection: &projection,
+            thumbnails: &thumbnails,
+            viewport: None,
+            frame: None,
+            limits: (0.01, 8.0),
+            scale: 1.0,
+            colors: GraphColors::derived(&iced::theme::Palette::DARK),
+            curved: false,
+            minimap: true,
+            size: None,
+        };
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(800.0, HEIGHT));
+        let position = Point::new(700.0, 400.0);
+        let mut drag = Drag::Minimap;
+        let message = view(&graph).drag_minimap(&mut drag, position, bounds);
+        assert!(matches!(message, Some(Message::Center { .. })));
+        assert!(matches!(drag, Drag::Minimap));
+
+        // The graph empties while dragging.
+        let empty = Graph::default();
+        assert!(view(&empty)
+            .drag_minimap(&mut drag, position, bounds)
+            .is_none());
+        assert!(matches!(drag, Drag::None));
+    }
+
+    #[test]
+    fn thumbnails_are_drawn_on_large_nodes_not_crowded() {
+        let graph = |count: u128| {
+            let nodes = (0..count)
+                .map(|n| Node {
+                    id: Uuid::from_u128(n),
+                    label: n.to_string(),
+                    x: (n % 20) as f32 * 4.0,
+                    y: (n / 20) as f32 * 4.0,
+                    style: Style::default(),
+                    members: 0,
+                })
+                .collect();
+            Graph::from_parts(nodes, Vec::new())
+        };
+        let drawn = |graph: &Graph, zoom| {
+            let viewport = Viewport {
+                x: 40.0,
+                y: 20.0,
+                zoom,
+            };
+            let scene = graph.scene(&viewport, (800.0, HEIGHT));
+            scene
+                .nodes
+                .iter()
+                .filter(|n| thumbnailed(&scene, &viewport, &graph.nodes()[**n]))
+                .count()
+        };
+        let few = graph(40);
+        assert_eq!(drawn(&few, 1.0), 0);
+        assert_eq!(drawn(&few, 2.0), 40);
+        assert_eq!(drawn(&graph(200), 2.0), 0);
+    }
+}
@@ -0,0 +1,147 @@
+//! Draws the charts of [`crate::chart`] with plain widgets: a column of
+//! marks per series in each bucket.
+
+use super::Editor;
+use crate::chart::{Plot, Series, Style};
+use iced::widget::{column, container, row, text, Column, Row, Space};
+use iced::{Color, Element, Length};
+use std::future::Future;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// The height of the plot area, in pixels.
+const HEIGHT: f32 = 160.0;
+/// The number of buckets time is split into.
+const BUCKETS: usize = 40;
+
+impl Editor {
+    /// Reads the history of `predicate` of each of `entities` from storage.
+    pub(super) fn load_series(
+        &self,
+        entities: Vec<Uuid>,
+        predicate: String,
+    ) -> impl Future<Output = anyhow::Result<Vec<Series>>> {
+        self.storage.call(move |storage| {
+            entities
+                .into_iter()
+                .map(|entity| {
+                    let history = storage.history(entity, &predicate)?;
+                    Ok(Series::from_history(entity, &history))
+                })
+                .collect()
+        })
+    }
+
+    pub(super) fn view_chart(
+        &self,
+        series: &[Series],
+        style: Style,
+    ) -> Element<'_, super::Message> {
+        let Some(plot) = Plot::new(series, style, BUCKETS) else {
+            return text(self.t("chart-empty")).into();
+        };
+        let colors = self.series_colors();
+        let mut marks = Row::new().spacing(2).height(Length::Fixed(HEIGHT));
+        for bucket in &plot.buckets {
+            let mut group = Row::new().spacing(1).width(Length::Fill);
+            for (n, value) in bucket.iter().enumerate() {
+                group = group.push(mark(&plot, style, *value, colors[n % colors.len()]));
+            }
+            marks = marks.push(group);
+        }
+
+        let axis = column![
+            text(format_value(plot.max)).size(12),
+            Space::with_height(Length::Fill),
+            text(format_value(plot.min)).size(12),
+        ]
+        .height(Length::Fixed(HEIGHT))
+        .width(Length::Fixed(60.0));
+        let dates = row![
+            text(format_date(plot.start)).size(12),
+            Space::with_width(Length::Fill),
+            text(format_date(plot.end)).size(12),
+        ];
+        let mut legend = Row::new().spacing(10);
+        if series.len() > 1 {
+            for (n, s) in series.iter().enumerate() {
+                legend = legend.push(
+                    row![
+                        swatch(colors[n % colors.len()], Length::Fixed(10.0), 10.0),
+                        text(self.label(&s.entity)).size(12)
+                    ]
+                    .spacing(4),
+                );
+            }
+        }
+        Column::new()
+            .spacing(4)
+            .push(row![axis, marks].spacing(4))
+            .push(row![Space::with_width(Length::Fixed(64.0)), dates])
+            .push(legend)
+            .into()
+    }
+
+    /// A color per series, starting with that of graph nodes.
+    fn series_colors(&self) -> [Color; 4] {
+        let palette = self.theme.palette();
+        [
+            self.graph_colors.node,
+            palette.success,
+            palette.danger,
+            self.graph_colors.edge,
+        ]
+    }
+}
+
+/// A dot at the height of a line's value, or a bar up to a bar's.
+fn mark<'a>(
+    plot: &Plot,
+    style: Style,
+    value: Option<f64>,
+    color: Color,
+) -> Element<'a, super::Message> {
+    let Some(value) = value else {
+        return Space::with_width(Length::Fill).into();
+    };
+    let top = HEIGHT * (1.0 - plot.height(value));
+    let marked = match style {
+        Style::Line => column![
+            Space::with_height(Length::Fixed((top - 2.0).max(0.0))),
+            swatch(color, Length::Fill, 4.0),
+        ],
+        Style::Bar => {
+            // Bars grow from zero, up or down.
+            let zero = HEIGHT * (1.0 - plot.height(0.0));
+            let (from, to) = (top.min(zero), top.max(zero));
+            column![
+                Space::with_height(Length::Fixed(from)),
+                swatch(color, Length::Fill, (to - from).max(1.0)),
+            ]
+        }
+    };
+    marked.width(Length::Fill).into()
+}
+
+fn swatch<'a>(color: Color, width: Length, height: f32) -> Element<'a, super::Message> {
+    container(Space::new(width, Length::Fixed(height)))
+        .style(container::Appearance {
+            background: Some(color.into()),
+            ..container::Appearance::default()
+        })
+        .into()
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+fn format_date(seconds: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(seconds)
+        .map(|t| t.date().to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,281 @@
+//! Named checkpoints: tagging the graph, opening a checkpoint read-only and
+//! diffing two of them.
+
+use super::Editor;
+use crate::legacy::error::GraphiteError;
+use crate::legacy::projection::{Change, Projection};
+use crate::legacy::storage::checkpoint::Checkpoint;
+use crate::legacy::storage::{Datum, EventStorage};
+use anyhow::anyhow;
+use iced::widget::{
+    button, column, container, pick_list, row, scrollable, text, text_input, Column,
+};
+use iced::{Command, Element, Length};
+
+#[derive(Default)]
+pub struct Checkpoints {
+    open: bool,
+    list: Vec<Checkpoint>,
+    /// The name of the next checkpoint.
+    name: String,
+    base: Option<String>,
+    other: Option<String>,
+    diff: Option<Vec<Change>>,
+    /// The checkpoint shown instead of the live graph.
+    viewing: Option<String>,
+    /// The live projection while a checkpoint is shown, kept up to date with
+    /// recorded events.
+    live: Option<Projection>,
+}
+
+impl Checkpoints {
+    /// The name of the checkpoint being shown, if the graph is read-only.
+    pub fn viewing(&self) -> Option<&str> {
+        self.viewing.as_deref()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Listed(Result<Vec<Checkpoint>, String>),
+    Name(String),
+    Tag,
+    Untag(String),
+    Changed(Result<(), String>),
+    /// Show a checkpoint read-only.
+    View(String),
+    Viewed(String, Result<Projection, String>),
+    /// Return to the live graph.
+    Return,
+    Base(String),
+    Other(String),
+    Diff,
+    Diffed(Result<Vec<Change>, String>),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_checkpoints(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => {
+                self.checkpoints.open = true;
+                return self.list_checkpoints();
+            }
+            Message::Listed(Ok(list)) => self.checkpoints.list = list,
+            Message::Name(name) => self.checkpoints.name = name,
+            Message::Tag => {
+                let name = std::mem::take(&mut self.checkpoints.name);
+                return self.change_checkpoints(move |s| s.tag(&name).map(|_| ()));
+            }
+            Message::Untag(name) => {
+                return self.change_checkpoints(move |s| s.untag(&name).map(|_| ()));
+            }
+            Message::Changed(Ok(())) => return self.list_checkpoints(),
+            Message::View(name) => {
+                let future = self.storage.call({
+                    let name = name.clone();
+                    move |s| -> anyhow::Result<Projection> {
+                        let checkpoint = s
+                            .checkpoint(&name)?
+                            .ok_or_else(|| anyhow!("There is no checkpoint named {}", name))?;
+                        Ok(Projection::load_until(s, checkpoint.watermark)?)
+                    }
+                });
+                return Command::perform(future, move |projection| {
+                    super::Message::Checkpoints(Message::Viewed(
+                        name,
+                        projection.map_err(|e| format!("{:#}", e)),
+                    ))
+                });
+            }
+            Message::Viewed(name, Ok(projection)) => {
+                let live = std::mem::replace(&mut self.projection, projection);
+                self.checkpoints.live.get_or_insert(live);
+                self.checkpoints.viewing = Some(name);
+                self.rollups.rebuild(&self.projection);
+                self.refresh_validation(None);
+            }
+            Message::Return => {
+                if let Some(live) = self.checkpoints.live.take() {
+                    self.projection = live;
+                    self.rollups.rebuild(&self.projection);
+                    self.refresh_validation(None);
+                }
+                self.checkpoints.viewing = None;
+            }
+            Message::Base(name) => self.checkpoints.base = Some(name),
+            Message::Other(name) => self.checkpoints.other = Some(name),
+            Message::Diff => {
+                let (Some(base), Some(other)) = (
+                    self.checkpoints.base.clone(),
+                    self.checkpoints.other.clone(),
+                ) else {
+                    return Command::none();
+                };
+                let future = self.storage.call(move |s| -> anyhow::Result<_> {
+                    let mut projections = Vec::new();
+                    for name in [base, other] {
+                        let checkpoint = s
+                            .checkpoint(&name)?
+                            .ok_or_else(|| anyhow!("There is no checkpoint named {}", name))?;
+                        projections.push(Projection::load_until(s, checkpoint.watermark)?);
+                    }
+                    Ok(projections[0].diff(&projections[1]))
+                });
+                return Command::perform(future, |changes| {
+                    super::Message::Checkpoints(Message::Diffed(
+                        changes.map_err(|e| format!("{:#}", e)),
+                    ))
+                });
+            }
+            Message::Diffed(Ok(changes)) => self.checkpoints.diff = Some(changes),
+            Message::Listed(Err(error))
+            | Message::Changed(Err(error))
+            | Message::Viewed(_, Err(error))
+            | Message::Diffed(Err(error)) => self.error = Some(error),
+            Message::Close => self.checkpoints.open = false,
+        }
+        Command::none()
+    }
+
+    fn list_checkpoints(&self) -> Command<super::Message> {
+        Command::perform(self.storage.call(|s| s.checkpoints()), |list| {
+            super::Message::Checkpoints(Message::Listed(list.map_err(|e| format!("{:#}", e))))
+        })
+    }
+
+    fn change_checkpoints(
+        &self,
+        f: impl FnOnce(&mut EventStorage) -> Result<(), GraphiteError> + Send + 'static,
+    ) -> Command<super::Message> {
+        Command::perform(self.storage.call(f), |result| {
+            super::Message::Checkpoints(Message::Changed(result.map_err(|e| format!("{:#}", e))))
+        })
+    }
+
+    /// The projection that recorded events apply to, which is not the shown
+    /// one while a checkpoint is open.
+    pub(super) fn live_projection(&mut self) -> &mut Projection {
+        match &mut self.checkpoints.live {
+            Some(live) => live,
+            None => &mut self.projection,
+        }
+    }
+
+    /// A banner while a checkpoint is shown.
+    pub(super) fn view_time_travel(&self) -> Option<Element<'_, super::Message>> {
+        let name = self.checkpoints.viewing()?;
+        let message = |m| super::Message::Checkpoints(m);
+        Some(
+            container(
+                row![
+                    text(self.tr("checkpoints-viewing", &[("name", name.into())])),
+                    button(text(self.t("checkpoints-return"))).on_press(message(Message::Return)),
+                ]
+                .spacing(10),
+            )
+            .padding(10)
+            .style(iced::theme::Container::Box)
+            .into(),
+        )
+    }
+
+    /// The checkpoints dialog, if it is open.
+    pub(super) fn view_checkpoints(&self) -> Option<Element<'_, super::Message>> {
+        if !self.checkpoints.open {
+            return None;
+        }
+        let message = |m| super::Message::Checkpoints(m);
+        let tag = row![
+            text_input("before reorg", &self.checkpoints.name)
+                .on_input(move |name| message(Message::Name(name)))
+                .on_submit(message(Message::Tag)),
+            button(text(self.t("checkpoints-tag")))
+                .on_press_maybe((!self.read_only).then_some(message(Message::Tag))),
+        ]
+        .spacing(10);
+
+        let mut list = Column::new().spacing(4);
+        if self.checkpoints.list.is_empty() {
+            list = list.push(text(self.t("checkpoints-empty")));
+        }
+        for checkpoint in &self.checkpoints.list {
+            let created = time::OffsetDateTime::from_unix_timestamp(checkpoint.created)
+                .map(|t| {
+                    t.to_offset(crate::journal::local_offset())
+                        .date()
+                        .to_string()
+                })
+                .unwrap_or_default();
+            list = list.push(
+                row![
+                    text(format!("{} ({})", checkpoint.name, created)).width(Length::Fill),
+                    button(text(self.t("checkpoints-open")))
+                        .on_press(message(Message::View(checkpoint.name.clone()))),
+                    button(text(self.t("checkpoints-remove"))).on_press_maybe(
+                        (!self.read_only).then(|| message(Message::Untag(checkpoint.name.clone()))),
+                    ),
+                ]
+                .spacing(10),
+            );
+        }
+
+        let names: Vec<String> = self
+            .checkpoints
+            .list
+            .iter()
+            .map(|c| c.name.clone())
+            .collect();
+        let compare = row![
+            pick_list(names.clone(), self.checkpoints.base.clone(), move |n| {
+                message(Message::Base(n))
+            }),
+            text("→"),
+            pick_list(names, self.checkpoints.other.clone(), move |n| {
+                message(Message::Other(n))
+            }),
+            button(text(self.t("checkpoints-diff"))).on_press(message(Message::Diff)),
+        ]
+        .spacing(10);
+
+        let mut content =
+            column![text(self.t("checkpoints")).size(30), tag, list, compare].spacing(10);
+        if let Some(changes) = &self.checkpoints.diff {
+            let lines = changes
+                .iter()
+                .fold(Column::new(), |lines, c| lines.push(text(self.describe(c))));
+            content = content
+                .push(text(self.tr(
+                    "checkpoints-changes",
+                    &[("count", changes.len().into())],
+                )))
+                .push(scrollable(lines).height(Length::Fixed(200.0)));
+        }
+        content = content.push(button(text(self.t("close"))).on_press(message(Message::Close)));
+        Some(
+            container(content)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    fn describe(&self, change: &Change) -> String {
+        let datum = |datum: &Datum| self.value_label(datum);
+        match change {
+            Change::Created(id) => format!("+ {}", self.label(id)),
+            Change::Deleted(id) => format!("- {}", self.label(id)),
+            Change::Added {
+                subject,
+                predicate,
+                datum: d,
+            } => format!("  {} {} + {}", self.label(subject), predicate, datum(d)),
+            Change::Removed {
+                subject,
+                predicate,
+                datum: d,
+            } => format!("  {} {} - {}", self.label(subject), predicate, datum(d)),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! The warning shown while the system clock is behind the latest event, see
+//! [`crate::legacy::storage::clock`].
+
+use super::Editor;
+use crate::legacy::storage::clock::SkewPolicy;
+use iced::widget::{container, text};
+use iced::{Command, Element};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Check,
+    /// How many seconds the clock is behind, if by more than tolerated.
+    Checked(Result<Option<i64>, String>),
+}
+
+impl Editor {
+    pub(super) fn update_clock(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Check => {
+                return Command::perform(
+                    self.storage.call(|storage| storage.clock_skew()),
+                    |skew| super::Message::Clock(Message::Checked(skew.map_err(|e| e.to_string()))),
+                )
+            }
+            Message::Checked(Ok(skew)) => self.clock_skew = skew,
+            Message::Checked(Err(error)) => self.error = Some(error),
+        }
+        Command::none()
+    }
+
+    /// The warning about the clock, while it is behind.
+    pub(super) fn view_clock_warning(&self) -> Option<Element<'_, super::Message>> {
+        let behind = self.clock_skew?;
+        // Rounded up, so a skew just over the tolerance isn't shown as 0.
+        let minutes = (behind + 59) / 60;
+        let consequence = match self.config.clock.policy {
+            SkewPolicy::Continue => self.t("clock-continuing"),
+            SkewPolicy::Pause => self.t("clock-paused"),
+        };
+        let warning = format!(
+            "{} {}",
+            self.tr("clock-behind", &[("minutes", minutes.into())]),
+            consequence
+        );
+        Some(
+            container(text(warning))
+                .padding(10)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
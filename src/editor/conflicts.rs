@@ -0,0 +1,108 @@
+//! The conflicts dialog, which lists the facts concurrent edits disagreed on,
+//! see [`crate::conflicts`], to keep either side's values or both.
+
+use super::Editor;
+use crate::conflicts::{self, Conflict, Keep};
+use crate::legacy::storage::Datum;
+use iced::widget::{button, column, container, row, scrollable, text, Column};
+use iced::{theme, Command, Element, Length};
+
+#[derive(Default)]
+pub struct Conflicts {
+    open: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Resolve(Conflict, Keep),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_conflicts(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => self.conflicts.open = true,
+            Message::Resolve(conflict, keep) => return self.record(conflict.resolve(keep)),
+            Message::Close => self.conflicts.open = false,
+        }
+        Command::none()
+    }
+
+    /// A button opening the dialog while there are conflicts.
+    pub(super) fn view_conflict_count(&self) -> Option<Element<'_, super::Message>> {
+        let count = conflicts::conflicts(&self.projection).len();
+        (count > 0).then(|| {
+            button(text(self.tr("conflicts-count", &[("count", count.into())])))
+                .style(theme::Button::Destructive)
+                .on_press(super::Message::Conflicts(Message::Open))
+                .into()
+        })
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_conflicts(&self) -> Option<Element<'_, super::Message>> {
+        if !self.conflicts.open {
+            return None;
+        }
+        let message = |m| super::Message::Conflicts(m);
+        let conflicts = conflicts::conflicts(&self.projection);
+        let mut list = Column::new().spacing(12);
+        if conflicts.is_empty() {
+            list = list.push(text(self.t("conflicts-none")));
+        }
+        for conflict in conflicts {
+            let resolve =
+                |keep| (!self.read_only).then(|| message(Message::Resolve(conflict.clone(), keep)));
+            let origin = conflict.origin.clone().unwrap_or_default();
+            list = list.push(
+                column![
+                    text(format!(
+                        "{} · {}",
+                        self.label(&conflict.subject),
+                        conflict.predicate
+                    ))
+                    .size(18),
+                    row![
+                        text(self.t("conflicts-local")).width(Length::Fixed(160.0)),
+                        text(self.values_label(&conflict.local)).width(Length::Fill),
+                        button(text(self.t("conflicts-keep-local")))
+                            .on_press_maybe(resolve(Keep::Local)),
+                    ]
+                    .spacing(10),
+                    row![
+                        text(self.tr("conflicts-synced", &[("origin", origin.into())]))
+                            .width(Length::Fixed(160.0)),
+                        text(self.values_label(&conflict.synced)).width(Length::Fill),
+                        button(text(self.t("conflicts-keep-synced")))
+                            .on_press_maybe(resolve(Keep::Synced)),
+                    ]
+                    .spacing(10),
+                    button(text(self.t("conflicts-keep-both"))).on_press_maybe(resolve(Keep::Both)),
+                ]
+                .spacing(4),
+            );
+        }
+
+        let dialog = column![
+            text(self.t("conflicts")).size(30),
+            scrollable(list).height(Length::Fixed(400.0)),
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    fn values_label(&self, values: &[Datum]) -> String {
+        if values.is_empty() {
+            return self.t("conflicts-no-value");
+        }
+        let labels: Vec<String> = values.iter().map(|d| self.value_label(d)).collect();
+        labels.join(", ")
+    }
+}
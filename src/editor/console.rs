@@ -0,0 +1,55 @@
+//! The debug console: the most recent log lines, newest first, see
+//! [`crate::logging`].
+
+use super::Editor;
+use crate::logging;
+use iced::widget::{button, column, container, scrollable, text, Column};
+use iced::{Command, Element, Font, Length};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Toggle,
+    /// Shows the lines logged since the last tick.
+    Tick,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_console(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Toggle => self.console = !self.console,
+            Message::Tick => {}
+            Message::Close => self.console = false,
+        }
+        Command::none()
+    }
+
+    /// The console, if it is shown.
+    pub(super) fn view_console(&self) -> Option<Element<'_, super::Message>> {
+        if !self.console {
+            return None;
+        }
+        let lines = logging::recent();
+        let mut list = Column::new().spacing(2);
+        if lines.is_empty() {
+            list = list.push(text(self.t("console-empty")));
+        }
+        for line in lines.into_iter().rev() {
+            list = list.push(text(line).size(12).font(Font::MONOSPACE));
+        }
+        let console = column![
+            text(self.t("console")).size(30),
+            scrollable(list)
+                .height(Length::Fixed(300.0))
+                .width(Length::Fill),
+            button(text(self.t("close"))).on_press(super::Message::Console(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(console)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,285 @@
+//! The dashboard at the top of the main window, see [`crate::dashboard`].
+
+use super::settings::Labeled;
+use super::Editor;
+use crate::chart::Series;
+use crate::dashboard::{self, Dashboard, Kind, Output, Widget};
+use crate::legacy::error::GraphiteError;
+use crate::legacy::storage::stats::Stats;
+use crate::plugin::Block;
+use iced::widget::{button, column, container, pick_list, progress_bar, row, text, Column, Row};
+use iced::{Command, Element, Length};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct Dashboards {
+    /// The dashboard picked, or the first one by name if none was.
+    selected: Option<Uuid>,
+    dashboards: Vec<Dashboard>,
+    /// The output of each widget, as of the last change to the graph.
+    outputs: HashMap<Uuid, Output>,
+    /// The series of history widgets, read from storage.
+    histories: HashMap<Uuid, Vec<Series>>,
+    /// The rendered views of view widgets.
+    views: HashMap<Uuid, Block>,
+    /// The statistics shown by stats widgets, read from storage.
+    stats: Option<Stats>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Select(Uuid),
+    /// Create a dashboard with a few widgets to start from.
+    Create,
+    /// The series of a history widget were read.
+    History(Uuid, Result<Vec<Series>, String>),
+    /// The statistics of the database were read.
+    Stats(Result<Stats, String>),
+}
+
+impl Editor {
+    pub(super) fn update_dashboard(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Select(id) => {
+                self.dashboards.selected = Some(id);
+                Command::none()
+            }
+            Message::Create => {
+                let (id, actions) = dashboard::starter(&self.t("dashboard-home"));
+                self.dashboards.selected = Some(id);
+                self.record(actions)
+            }
+            Message::History(widget, Ok(series)) => {
+                self.dashboards.histories.insert(widget, series);
+                Command::none()
+            }
+            Message::Stats(Ok(stats)) => {
+                self.dashboards.stats = Some(stats);
+                Command::none()
+            }
+            Message::History(_, Err(error)) | Message::Stats(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+        }
+    }
+
+    /// Reads the dashboards again and evaluates their widgets, after the
+    /// graph changed. History widgets are read from storage again if
+    /// `touched` concerns them, or always if it is `None`, and the statistics
+    /// whenever a stats widget exists.
+    pub(super) fn refresh_dashboards(
+        &mut self,
+        touched: Option<&[(Uuid, Option<&str>)]>,
+    ) -> Command<super::Message> {
+        let dashboards = dashboard::dashboards(&self.projection);
+        self.dashboards.outputs = dashboards
+            .iter()
+            .flat_map(|d| &d.widgets)
+            .map(|widget| (widget.id, widget.evaluate(&self.projection)))
+            .collect();
+        self.dashboards.views = dashboards
+            .iter()
+            .flat_map(|d| &d.widgets)
+            .filter_map(|widget| {
+                let Kind::View { plugin, view } = &widget.kind else {
+                    return None;
+                };
+                let block = match self.plugins.view(plugin, view) {
+                    Some(layout) => layout.render(&self.projection, Some(widget.id)),
+                    None => Block::Error(self.tr(
+                        "dashboard-unknown-view",
+                        &[("view", format!("{}/{}", plugin, view).into())],
+                    )),
+                };
+                Some((widget.id, block))
+            })
+            .collect();
+        let mut loads = Vec::new();
+        let widgets = || dashboards.iter().flat_map(|d| &d.widgets);
+        if widgets().any(|widget| widget.kind == Kind::Stats) {
+            loads.push(Command::perform(
+                self.storage.call(|storage| storage.stats()),
+                |stats| {
+                    super::Message::Dashboard(Message::Stats(
+                        stats.map_err(|e: GraphiteError| format!("{:#}", e)),
+                    ))
+                },
+            ));
+        }
+        for widget in dashboards.iter().flat_map(|d| &d.widgets) {
+            let Kind::History {
+                entities,
+                predicate,
+                ..
+            } = &widget.kind
+            else {
+                continue;
+            };
+            if touched.is_some_and(|touched| !widget.reads(touched)) {
+                continue;
+            }
+            let id = widget.id;
+            loads.push(Command::perform(
+                self.load_series(entities.clone(), predicate.clone()),
+                move |series| {
+                    super::Message::Dashboard(Message::History(
+                        id,
+                        series.map_err(|e| format!("{:#}", e)),
+                    ))
+                },
+            ));
+        }
+        self.dashboards.dashboards = dashboards;
+        Command::batch(loads)
+    }
+
+    pub(super) fn view_dashboard(&self) -> Element<'_, super::Message> {
+        let message = |m| super::Message::Dashboard(m);
+        let all = &self.dashboards.dashboards;
+        let Some(shown) = self
+            .dashboards
+            .selected
+            .and_then(|id| all.iter().find(|d| d.id == id))
+            .or(all.first())
+        else {
+            return row![
+                text(self.t("dashboard-empty")),
+                button(text(self.t("dashboard-create"))).on_press(message(Message::Create)),
+            ]
+            .spacing(10)
+            .into();
+        };
+
+        let mut header = Row::new().spacing(10).push(text(&shown.name).size(30));
+        if all.len() > 1 {
+            let choices: Vec<Labeled<Uuid>> = all
+                .iter()
+                .map(|d| Labeled {
+                    value: d.id,
+                    label: d.name.clone(),
+                })
+                .collect();
+            let selected = Labeled::selected(&choices, &shown.id);
+            header = header.push(pick_list(choices, Some(selected), move |choice| {
+                message(Message::Select(choice.value))
+            }));
+        }
+
+        let mut rows: BTreeMap<i64, Vec<&Widget>> = BTreeMap::new();
+        for widget in &shown.widgets {
+            rows.entry(widget.row).or_default().push(widget);
+        }
+        let mut grid = Column::new().spacing(10);
+        for widgets in rows.into_values() {
+            let cells = widgets.into_iter().map(|widget| {
+                container(self.view_widget(widget))
+                    .padding(10)
+                    .width(Length::FillPortion(widget.span))
+                    .style(iced::theme::Container::Box)
+                    .into()
+            });
+            grid = grid.push(Row::with_children(cells).spacing(10));
+        }
+        column![header, grid].spacing(10).into()
+    }
+
+    fn view_widget(&self, widget: &Widget) -> Element<'_, super::Message> {
+        let mut content = Column::new().spacing(4);
+        if !widget.title.is_empty() {
+            content = content.push(text(&widget.title).size(20));
+        }
+        match self.dashboards.outputs.get(&widget.id) {
+            Some(Output::List(ids)) => {
+                for id in ids {
+                    content = content.push(text(self.label(id)));
+                }
+            }
+            Some(Output::Counter(count)) => content = content.push(text(count).size(40)),
+            Some(Output::Chart(counts)) => {
+                let max = counts.first().map_or(1, |(_, count)| *count) as f32;
+                for (value, count) in counts {
+                    content = content.push(
+                        row![
+                            text(value).width(Length::Fixed(120.0)),
+                            progress_bar(0.0..=max, *count as f32).height(Length::Fixed(12.0)),
+                            text(count).width(Length::Fixed(40.0)),
+                        ]
+                        .spacing(10),
+                    );
+                }
+            }
+            Some(Output::Pinned(Some(id))) => {
+                content = content.push(text(self.label(id)).size(16));
+                if let Some(entity) = self.projection.entity(id) {
+                    for (predicate, values) in entity.facts() {
+                        for datum in values {
+                            content = content.push(text(format!(
+                                "{}: {}",
+                                predicate,
+                                self.value_label(datum)
+                            )));
+                        }
+                    }
+                }
+            }
+            Some(Output::Pinned(None)) => {
+                content = content.push(text(self.t("dashboard-unpinned")))
+            }
+            Some(Output::History) => {
+                if let (Kind::History { style, .. }, Some(series)) =
+                    (&widget.kind, self.dashboards.histories.get(&widget.id))
+                {
+                    content = content.push(self.view_chart(series, *style));
+                }
+            }
+            Some(Output::View) => {
+                if let Some(block) = self.dashboards.views.get(&widget.id) {
+                    content = content.push(self.view_block(block));
+                }
+            }
+            Some(Output::Stats) => {
+                if let Some(stats) = &self.dashboards.stats {
+                    content = content.push(self.view_stats(stats));
+                }
+            }
+            Some(Output::Invalid(error)) => content = content.push(text(error)),
+            None => {}
+        }
+        content.into()
+    }
+
+    fn view_stats(&self, stats: &Stats) -> Element<'_, super::Message> {
+        let synced = match stats.last_sync {
+            Some(synced) => self.tr("stats-synced", &[("time", self.local_time(synced).into())]),
+            None => self.t("stats-never-synced"),
+        };
+        let mut content = column![
+            text(self.tr(
+                "stats-totals",
+                &[
+                    ("events", stats.total.into()),
+                    ("entities", stats.entities.into()),
+                ]
+            )),
+            text(self.tr("stats-size", &[("size", megabytes(stats.size).into())])),
+            text(synced),
+            text(self.t("stats-per-actor")).size(16),
+        ]
+        .spacing(4);
+        for (actor, count) in &stats.per_actor {
+            content = content.push(text(format!("{}: {}", self.label(actor), count)));
+        }
+        content = content.push(text(self.t("stats-per-predicate")).size(16));
+        for (predicate, count) in &stats.per_predicate {
+            content = content.push(text(format!("{}: {}", predicate, count)));
+        }
+        content.into()
+    }
+}
+
+/// `bytes` in megabytes with one decimal, e.g. `1.5`.
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1}", bytes as f64 / 1_000_000.0)
+}
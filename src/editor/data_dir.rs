@@ -0,0 +1,141 @@
+//! Choosing where Graphite keeps its data, see [`crate::location`]. Offered
+//! on the first run and from the settings.
+
+use super::Editor;
+use crate::location::{self, Location};
+use iced::widget::{button, column, container, row, text, text_input};
+use iced::{Command, Element, Length};
+use std::path::PathBuf;
+
+#[derive(Default)]
+pub struct DataDir {
+    open: bool,
+    /// The directory to move the data to.
+    input: String,
+    moving: bool,
+    /// Where the data was moved to, used from the next start on.
+    moved: Option<PathBuf>,
+}
+
+impl DataDir {
+    /// The chooser, open if Graphite runs for the first time.
+    pub(super) fn new(location: &Location) -> DataDir {
+        DataDir {
+            open: *location == Location::Platform && location.is_new(),
+            ..DataDir::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Input(String),
+    /// Fill in the directory of portable mode.
+    Portable,
+    Move,
+    Moved(Result<PathBuf, String>),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_data_dir(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => self.data_dir.open = true,
+            Message::Input(input) => self.data_dir.input = input,
+            Message::Portable => {
+                if let Some(dir) = location::portable_dir() {
+                    self.data_dir.input = dir.to_string_lossy().into_owned();
+                }
+            }
+            Message::Move => return self.move_data(),
+            Message::Moved(Ok(dir)) => {
+                self.data_dir.moving = false;
+                self.data_dir.moved = Some(dir);
+            }
+            Message::Moved(Err(error)) => {
+                self.data_dir.moving = false;
+                self.error = Some(error);
+            }
+            Message::Close => {
+                self.data_dir.open = false;
+                // Saving the config makes this the location of later starts,
+                // so the chooser isn't offered again.
+                if self.location.is_new() && self.data_dir.moved.is_none() {
+                    self.save_config();
+                }
+            }
+        }
+        Command::none()
+    }
+
+    fn move_data(&mut self) -> Command<super::Message> {
+        let input = self.data_dir.input.trim();
+        if input.is_empty() || self.data_dir.moving {
+            return Command::none();
+        }
+        self.data_dir.moving = true;
+        let to = PathBuf::from(input);
+        let from = self.location.clone();
+        let config = self.config.clone();
+        let moved = self.storage.call(move |storage| {
+            location::migrate(&from, &config, storage, &to)?;
+            location::choose(Some(&to))?;
+            Ok(to)
+        });
+        Command::perform(moved, |moved: anyhow::Result<PathBuf>| {
+            super::Message::DataDir(Message::Moved(moved.map_err(|e| format!("{:#}", e))))
+        })
+    }
+
+    /// Where the data is kept, for showing.
+    pub(super) fn data_dir_label(&self) -> String {
+        match self.location.config() {
+            Some(config) => config
+                .parent()
+                .unwrap_or(&config)
+                .to_string_lossy()
+                .into_owned(),
+            None => self.t("data-dir-none"),
+        }
+    }
+
+    /// The chooser, if it is open.
+    pub(super) fn view_data_dir(&self) -> Option<Element<'_, super::Message>> {
+        if !self.data_dir.open {
+            return None;
+        }
+        let message = |m| super::Message::DataDir(m);
+        let status = match (&self.data_dir.moved, self.data_dir.moving) {
+            (Some(dir), _) => self.tr(
+                "data-dir-moved",
+                &[("dir", dir.to_string_lossy().into_owned().into())],
+            ),
+            (None, true) => self.t("data-dir-moving"),
+            (None, false) => self.tr("data-dir-current", &[("dir", self.data_dir_label().into())]),
+        };
+        let chooser = column![
+            text(self.t("data-dir")).size(30),
+            text(status),
+            text(self.t("data-dir-explain")),
+            row![
+                text_input("/media/usb/graphite", &self.data_dir.input)
+                    .on_input(move |input| message(Message::Input(input)))
+                    .on_submit(message(Message::Move))
+                    .width(Length::Fill),
+                button(text(self.t("data-dir-portable"))).on_press(message(Message::Portable)),
+                button(text(self.t("data-dir-move")))
+                    .on_press_maybe((!self.data_dir.moving).then_some(message(Message::Move))),
+            ]
+            .spacing(10),
+            button(text(self.t("data-dir-keep"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(chooser)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
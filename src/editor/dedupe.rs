@@ -0,0 +1,110 @@
+//! The duplicates dialog, which suggests entities that likely describe the
+//! same thing, see [`crate::dedupe`], to be merged or dismissed.
+
+use super::Editor;
+use crate::dedupe::{self, Candidate};
+use crate::merge;
+use iced::widget::{button, column, container, row, scrollable, text, Column};
+use iced::{Command, Element, Length};
+
+/// How many suggestions are listed.
+const LIMIT: usize = 50;
+
+#[derive(Default)]
+pub struct Dedupe {
+    /// The suggestions, while the dialog is open.
+    candidates: Option<Vec<Candidate>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Merge(Candidate),
+    Dismiss(Candidate),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_dedupe(&mut self, message: Message) -> Command<super::Message> {
+        let actions = match message {
+            Message::Open => {
+                self.dedupe.candidates = Some(Vec::new());
+                self.refresh_dedupe();
+                return Command::none();
+            }
+            Message::Merge(candidate) => {
+                merge::merge(&self.projection, candidate.keep, candidate.duplicate)
+            }
+            Message::Dismiss(candidate) => dedupe::dismiss(&candidate),
+            Message::Close => {
+                self.dedupe.candidates = None;
+                return Command::none();
+            }
+        };
+        self.record(actions)
+    }
+
+    /// Looks for duplicates again if the dialog is open.
+    pub(super) fn refresh_dedupe(&mut self) {
+        if let Some(candidates) = &mut self.dedupe.candidates {
+            *candidates = dedupe::candidates(&self.projection, dedupe::THRESHOLD);
+        }
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_dedupe(&self) -> Option<Element<'_, super::Message>> {
+        let candidates = self.dedupe.candidates.as_ref()?;
+        let message = |m| super::Message::Dedupe(m);
+        let mut list = Column::new().spacing(6);
+        if candidates.is_empty() {
+            list = list.push(text(self.t("dedupe-none")));
+        }
+        for candidate in candidates.iter().take(LIMIT) {
+            let preview = merge::preview(&self.projection, candidate.keep, candidate.duplicate);
+            let details = self.tr(
+                "dedupe-details",
+                &[
+                    ("score", format!("{:.0}", candidate.score * 100.0).into()),
+                    ("shared", candidate.shared.into()),
+                    ("dropped", preview.conflicts.len().into()),
+                ],
+            );
+            let actions = (!self.read_only).then_some(candidate);
+            list = list.push(
+                row![
+                    column![
+                        text(format!(
+                            "{} ← {}",
+                            self.label(&candidate.keep),
+                            self.label(&candidate.duplicate)
+                        )),
+                        text(details).size(12),
+                    ]
+                    .width(Length::Fill),
+                    button(text(self.t("dedupe-merge")))
+                        .on_press_maybe(actions.map(|c| message(Message::Merge(c.clone())))),
+                    button(text(self.t("dedupe-dismiss")))
+                        .on_press_maybe(actions.map(|c| message(Message::Dismiss(c.clone())))),
+                ]
+                .spacing(10),
+            );
+        }
+        if candidates.len() > LIMIT {
+            let more = candidates.len() - LIMIT;
+            list = list.push(text(self.tr("hygiene-more", &[("count", more.into())])));
+        }
+
+        let dialog = column![
+            text(self.t("dedupe")).size(30),
+            scrollable(list).height(Length::Fixed(400.0)),
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,164 @@
+//! The diagnostics dialog, which verifies and repairs the database and
+//! lists the latest statements of the slow-query log.
+
+use super::Editor;
+use crate::export::iso_date_time;
+use crate::legacy::storage::profile::{self, Entry};
+use crate::legacy::storage::verify::{Repairs, Report};
+use iced::widget::{button, column, container, row, scrollable, text, Column};
+use iced::{Command, Element, Length};
+
+pub struct Diagnostics {
+    open: bool,
+    report: Option<Report>,
+    repairs: Option<Repairs>,
+    /// The latest slow statements, newest first, or why they couldn't be
+    /// read.
+    slow: Result<Vec<Entry>, String>,
+}
+
+/// How many slow statements are listed.
+const SLOW: usize = 50;
+
+impl Default for Diagnostics {
+    fn default() -> Diagnostics {
+        Diagnostics {
+            open: false,
+            report: None,
+            repairs: None,
+            slow: Ok(Vec::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Open the dialog and verify the database.
+    Open,
+    Verified(Result<Report, String>),
+    Repair,
+    Repaired(Result<Repairs, String>),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_diagnostics(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => {
+                let slow = match self.location.slow_queries() {
+                    Some(path) => profile::read(&path, SLOW).map_err(|e| format!("{:#}", e)),
+                    None => Ok(Vec::new()),
+                };
+                self.diagnostics = Diagnostics {
+                    open: true,
+                    slow,
+                    ..Diagnostics::default()
+                };
+                Command::perform(self.storage.call(|s| s.verify()), |report| {
+                    super::Message::Diagnostics(Message::Verified(
+                        report.map_err(|e| format!("{:#}", e)),
+                    ))
+                })
+            }
+            Message::Verified(Ok(report)) => {
+                self.diagnostics.report = Some(report);
+                Command::none()
+            }
+            Message::Repair => Command::perform(self.storage.call(|s| s.repair()), |repairs| {
+                super::Message::Diagnostics(Message::Repaired(
+                    repairs.map_err(|e| format!("{:#}", e)),
+                ))
+            }),
+            Message::Repaired(Ok(repairs)) => {
+                // Verify again to show what is left, and reload since repairs
+                // change the log behind the projection's back.
+                let verify = self.update_diagnostics(Message::Open);
+                self.diagnostics.repairs = Some(repairs);
+                Command::batch([self.load(), verify])
+            }
+            Message::Verified(Err(error)) | Message::Repaired(Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::Close => {
+                self.diagnostics.open = false;
+                Command::none()
+            }
+        }
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_diagnostics(&self) -> Option<Element<'_, super::Message>> {
+        if !self.diagnostics.open {
+            return None;
+        }
+        let message = |m| super::Message::Diagnostics(m);
+        let mut content = Column::new().spacing(8);
+        if let Some(repairs) = &self.diagnostics.repairs {
+            content = content.push(text(self.tr(
+                "diagnostics-repairs",
+                &[
+                    ("quarantined", repairs.quarantined.into()),
+                    ("resequenced", repairs.resequenced.into()),
+                    ("unlinked", repairs.unlinked.into()),
+                ],
+            )));
+        }
+        match &self.diagnostics.report {
+            None => content = content.push(text(self.t("diagnostics-checking"))),
+            Some(report) => {
+                content = content.push(text(self.tr(
+                    "diagnostics-report",
+                    &[
+                        ("events", report.events.into()),
+                        ("problems", report.problems.len().into()),
+                    ],
+                )));
+                let problems = report
+                    .problems
+                    .iter()
+                    .fold(Column::new(), |list, p| list.push(text(p.to_string())));
+                content = content.push(scrollable(problems).height(Length::Fixed(200.0)));
+            }
+        }
+
+        content = content.push(text(self.t("diagnostics-slow")).size(20));
+        match &self.diagnostics.slow {
+            Ok(slow) if slow.is_empty() => {
+                content = content.push(text(self.t("diagnostics-slow-none")))
+            }
+            Ok(slow) => {
+                let list = slow.iter().fold(Column::new().spacing(4), |list, entry| {
+                    let time = iso_date_time(entry.time).unwrap_or_default();
+                    let summary = self.tr(
+                        "diagnostics-slow-entry",
+                        &[
+                            ("time", time.into()),
+                            ("millis", entry.millis.into()),
+                            ("rows", entry.rows.into()),
+                        ],
+                    );
+                    list.push(column![text(summary), text(&entry.sql).size(12)])
+                });
+                content = content.push(scrollable(list).height(Length::Fixed(200.0)));
+            }
+            Err(error) => content = content.push(text(error)),
+        }
+
+        let mut actions =
+            row![button(text(self.t("close"))).on_press(message(Message::Close))].spacing(10);
+        if self.diagnostics.report.as_ref().is_some_and(|r| !r.is_ok()) {
+            actions = actions.push(
+                button(text(self.t("diagnostics-repair")))
+                    .on_press_maybe((!self.read_only).then_some(message(Message::Repair))),
+            );
+        }
+        let dialog = column![text(self.t("diagnostics")).size(30), content, actions].spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,137 @@
+//! Importing CSV, JSON and Markdown files dropped onto a window, and the
+//! files plugins import, see [`crate::wasm`].
+//!
+//! The entities of a file are placed where it was dropped. Large files are
+//! recorded in chunks of entities, an event each, as an operation that can be
+//! cancelled between chunks.
+
+use super::Editor;
+use crate::import::{self, Record};
+use crate::legacy::storage::{metadata, Action};
+use crate::progress::Reporter;
+use iced::widget::text;
+use iced::{Command, Element, Point};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// How many entities are recorded in one event.
+const CHUNK: usize = 500;
+
+#[derive(Default)]
+pub struct FileDrop {
+    /// Where the cursor was last seen, since drops don't say where they are.
+    cursor: Point,
+    /// Whether a file is dragged over a window.
+    hovering: bool,
+    /// The chunks waiting to be recorded, with the operation and the path of
+    /// their file.
+    queue: VecDeque<(Reporter, String, Vec<Action>)>,
+    /// The operation of the chunk being recorded, if any.
+    recording: Option<Reporter>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    CursorMoved(Point),
+    Hovered,
+    Left,
+    Dropped(PathBuf),
+    Read(PathBuf, Point, Result<Vec<Record>, String>),
+    Recorded(Result<(), String>),
+}
+
+impl FileDrop {
+    /// Where the pointer was last seen in a window.
+    pub(super) fn cursor(&self) -> Point {
+        self.cursor
+    }
+}
+
+impl Editor {
+    pub(super) fn update_file_drop(&mut self, message: Message) -> Command<super::Message> {
+        let state = &mut self.file_drop;
+        match message {
+            Message::CursorMoved(position) => state.cursor = position,
+            Message::Hovered => state.hovering = true,
+            Message::Left => state.hovering = false,
+            Message::Dropped(path) => {
+                state.hovering = false;
+                let at = state.cursor;
+                let importer = self.plugins.importer(&path);
+                let runtime = self.config.wasm.clone();
+                let read = tokio::task::spawn_blocking({
+                    let path = path.clone();
+                    move || {
+                        match importer {
+                            Some(module) => module.import(&runtime, &path),
+                            None => import::file(&path),
+                        }
+                        .map_err(|e| format!("{:#}", e))
+                    }
+                });
+                return Command::perform(read, move |records| {
+                    let records = records.unwrap_or_else(|e| Err(e.to_string()));
+                    super::Message::FileDrop(Message::Read(path, at, records))
+                });
+            }
+            Message::Read(path, at, Ok(records)) => {
+                let placed = import::place(&self.projection, &path, &records, (at.x, at.y));
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let label = self.tr("import-progress", &[("files", name.into_owned().into())]);
+                let reporter = self.start_operation(label);
+                for chunk in placed.chunks(CHUNK) {
+                    let actions: Vec<Action> = chunk.iter().flatten().cloned().collect();
+                    // Files imported before have nothing to record.
+                    if !actions.is_empty() {
+                        reporter.add_total(1);
+                        let source = path.display().to_string();
+                        self.file_drop
+                            .queue
+                            .push_back((reporter.clone(), source, actions));
+                    }
+                }
+                return self.record_next_chunk();
+            }
+            Message::Recorded(Ok(())) => {
+                if let Some(reporter) = state.recording.take() {
+                    reporter.advance(1);
+                }
+                return self.record_next_chunk();
+            }
+            Message::Read(_, _, Err(error)) | Message::Recorded(Err(error)) => {
+                // The rest of a failed import is dropped with it.
+                state.queue.clear();
+                state.recording = None;
+                self.error = Some(error);
+            }
+        }
+        Command::none()
+    }
+
+    fn record_next_chunk(&mut self) -> Command<super::Message> {
+        let state = &mut self.file_drop;
+        if state.recording.is_some() {
+            return Command::none();
+        }
+        // The chunks of cancelled imports are skipped.
+        state
+            .queue
+            .retain(|(reporter, ..)| !reporter.is_cancelled());
+        let Some((reporter, source, actions)) = state.queue.pop_front() else {
+            return Command::none();
+        };
+        state.recording = Some(reporter);
+        self.creator().set_metadata(metadata::IMPORT_SOURCE, source);
+        let recorded = self.record_then(actions, |result| {
+            super::Message::FileDrop(Message::Recorded(result))
+        });
+        self.creator().remove_metadata(metadata::IMPORT_SOURCE);
+        recorded
+    }
+
+    pub(super) fn view_file_drop(&self) -> Option<Element<'_, super::Message>> {
+        self.file_drop
+            .hovering
+            .then(|| text(self.t("import-drop")).size(20).into())
+    }
+}
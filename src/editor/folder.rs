@@ -0,0 +1,43 @@
+//! Syncing through the shared folder every few minutes, see
+//! [`crate::folder`].
+
+use super::Editor;
+use crate::folder;
+use iced::Command;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Sync,
+    Synced(Result<folder::Synced, String>),
+}
+
+impl Editor {
+    pub(super) fn update_folder(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Sync => {
+                let Some(path) = self.config.folder.path.clone() else {
+                    return Command::none();
+                };
+                if self.read_only {
+                    return Command::none();
+                }
+                let subscription = self.config.backups.subscription.clone();
+                return Command::perform(
+                    self.storage
+                        .call(move |storage| folder::sync(storage, &path, &subscription)),
+                    |synced| {
+                        super::Message::Folder(Message::Synced(
+                            synced.map_err(|e| format!("{:#}", e)),
+                        ))
+                    },
+                );
+            }
+            // Merged events are older than the ones applied, so the graph is
+            // replayed in order.
+            Message::Synced(Ok(synced)) if synced.read > 0 => return self.load(),
+            Message::Synced(Ok(_)) => {}
+            Message::Synced(Err(error)) => self.error = Some(error),
+        }
+        Command::none()
+    }
+}
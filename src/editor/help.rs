@@ -0,0 +1,69 @@
+//! The overlay listing the keyboard shortcuts, shown with `?` by default.
+
+use super::Editor;
+use crate::shortcuts;
+use iced::widget::{button, column, container, row, text, Column};
+use iced::{Command, Element, Length};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Toggle,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_help(&mut self, message: Message) -> Command<super::Message> {
+        self.help = match message {
+            Message::Toggle => !self.help,
+            Message::Close => false,
+        };
+        Command::none()
+    }
+
+    /// The overlay, if it is shown.
+    pub(super) fn view_help(&self) -> Option<Element<'_, super::Message>> {
+        if !self.help {
+            return None;
+        }
+        let mut list = Column::new().spacing(4);
+        for (command, binding) in &self.config.keybindings {
+            list = list.push(row![
+                text(binding).width(Length::Fixed(160.0)),
+                text(self.t(command.message_id())),
+            ]);
+        }
+        for script in self.scripts.bindings() {
+            let binding = script.key.as_ref().map(ToString::to_string);
+            list = list.push(row![
+                text(binding.unwrap_or_default()).width(Length::Fixed(160.0)),
+                text(self.tr("shortcuts-script", &[("name", script.name.as_str().into())])),
+            ]);
+        }
+        for (binding, commands) in shortcuts::conflicts(&self.config.keybindings) {
+            let commands: Vec<String> = commands
+                .iter()
+                .map(|command| self.t(command.message_id()))
+                .collect();
+            list = list.push(text(self.tr(
+                "shortcuts-conflict",
+                &[
+                    ("binding", binding.to_string().into()),
+                    ("commands", commands.join(", ").into()),
+                ],
+            )));
+        }
+        let overlay = column![
+            text(self.t("shortcuts")).size(30),
+            list,
+            text(self.t("shortcuts-remap")),
+            button(text(self.t("close"))).on_press(super::Message::Help(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(overlay)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,195 @@
+//! The hygiene dialog, which reports entities and links left behind, see
+//! [`crate::hygiene`], and fixes them in bulk.
+
+use super::settings::Labeled;
+use super::Editor;
+use crate::hygiene::{self, Report};
+use iced::widget::{button, column, container, pick_list, row, scrollable, text, Column};
+use iced::{Command, Element, Length};
+use uuid::Uuid;
+
+/// How many entities of each kind are listed.
+const LIMIT: usize = 20;
+
+#[derive(Default)]
+pub struct Hygiene {
+    open: bool,
+    report: Option<Report>,
+    /// The root unreachable entities are linked to.
+    parent: Option<Uuid>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Parent(Uuid),
+    /// Link the unreachable entities to the parent picked.
+    Link,
+    DeleteUnreachable,
+    IgnoreUnreachable,
+    DeleteEmpty,
+    IgnoreEmpty,
+    Unlink,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_hygiene(&mut self, message: Message) -> Command<super::Message> {
+        let report = self.hygiene.report.clone().unwrap_or_default();
+        let actions = match message {
+            Message::Open => {
+                self.hygiene.open = true;
+                self.refresh_hygiene();
+                return Command::none();
+            }
+            Message::Parent(parent) => {
+                self.hygiene.parent = Some(parent);
+                return Command::none();
+            }
+            Message::Link => match self.hygiene.parent {
+                Some(parent) => hygiene::link(&report.unreachable, parent, &self.config.hierarchy),
+                None => return Command::none(),
+            },
+            Message::DeleteUnreachable => hygiene::delete(&report.unreachable),
+            Message::IgnoreUnreachable => hygiene::ignore(&report.unreachable),
+            Message::DeleteEmpty => hygiene::delete(&report.empty),
+            Message::IgnoreEmpty => hygiene::ignore(&report.empty),
+            Message::Unlink => hygiene::unlink(&self.projection, &report.dangling),
+            Message::Close => {
+                self.hygiene.open = false;
+                self.hygiene.report = None;
+                return Command::none();
+            }
+        };
+        self.record(actions)
+    }
+
+    /// Analyses the graph again if the dialog is open.
+    pub(super) fn refresh_hygiene(&mut self) {
+        if self.hygiene.open {
+            self.hygiene.report = Some(hygiene::report(&self.projection, &self.config.roots));
+        }
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_hygiene(&self) -> Option<Element<'_, super::Message>> {
+        if !self.hygiene.open {
+            return None;
+        }
+        let report = self.hygiene.report.as_ref()?;
+        let message = |m| super::Message::Hygiene(m);
+        let mut content = Column::new().spacing(10);
+        if report.is_clean() {
+            content = content.push(text(self.t("hygiene-clean")));
+        }
+
+        if !report.unreachable.is_empty() {
+            let title = if self.config.roots.is_empty() {
+                "hygiene-unlinked"
+            } else {
+                "hygiene-unreachable"
+            };
+            let roots: Vec<Labeled<Uuid>> = self
+                .config
+                .roots
+                .iter()
+                .map(|id| Labeled {
+                    value: *id,
+                    label: self.label(id),
+                })
+                .collect();
+            let selected = self
+                .hygiene
+                .parent
+                .and_then(|parent| roots.iter().find(|r| r.value == parent).cloned());
+            let link = pick_list(roots, selected, move |root| {
+                message(Message::Parent(root.value))
+            });
+            content = content.push(
+                self.view_hygiene_list(
+                    self.tr(title, &[("count", report.unreachable.len().into())]),
+                    report.unreachable.iter().map(|id| self.label(id)),
+                    row![
+                        link,
+                        button(text(self.t("hygiene-link")))
+                            .on_press_maybe(self.hygiene.parent.map(|_| message(Message::Link))),
+                        button(text(self.t("hygiene-delete")))
+                            .on_press(message(Message::DeleteUnreachable)),
+                        button(text(self.t("hygiene-ignore")))
+                            .on_press(message(Message::IgnoreUnreachable)),
+                    ]
+                    .spacing(10)
+                    .into(),
+                ),
+            );
+        }
+        if !report.dangling.is_empty() {
+            content = content.push(
+                self.view_hygiene_list(
+                    self.tr(
+                        "hygiene-dangling",
+                        &[("count", report.dangling.len().into())],
+                    ),
+                    report.dangling.iter().map(|link| {
+                        format!(
+                            "{} {} → {}",
+                            self.label(&link.subject),
+                            link.predicate,
+                            link.target
+                        )
+                    }),
+                    button(text(self.t("hygiene-unlink")))
+                        .on_press(message(Message::Unlink))
+                        .into(),
+                ),
+            );
+        }
+        if !report.empty.is_empty() {
+            content = content.push(
+                self.view_hygiene_list(
+                    self.tr("hygiene-empty", &[("count", report.empty.len().into())]),
+                    report.empty.iter().map(Uuid::to_string),
+                    row![
+                        button(text(self.t("hygiene-delete")))
+                            .on_press(message(Message::DeleteEmpty)),
+                        button(text(self.t("hygiene-ignore")))
+                            .on_press(message(Message::IgnoreEmpty)),
+                    ]
+                    .spacing(10)
+                    .into(),
+                ),
+            );
+        }
+
+        let dialog = column![
+            text(self.t("hygiene")).size(30),
+            scrollable(content).height(Length::Fixed(400.0)),
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    /// A kind of problem: its title, the first few entries and the fixes.
+    fn view_hygiene_list<'a>(
+        &'a self,
+        title: String,
+        entries: impl ExactSizeIterator<Item = String>,
+        fixes: Element<'a, super::Message>,
+    ) -> Element<'a, super::Message> {
+        let more = entries.len().saturating_sub(LIMIT);
+        let mut list = Column::new().spacing(2).push(text(title).size(20));
+        for entry in entries.take(LIMIT) {
+            list = list.push(text(entry));
+        }
+        if more > 0 {
+            list = list.push(text(self.tr("hygiene-more", &[("count", more.into())])));
+        }
+        list.push(fixes).into()
+    }
+}
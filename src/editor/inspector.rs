@@ -0,0 +1,745 @@
+//! Inspector windows, which browse the entities and facts of the projection
+//! next to the main window. Each window keeps its own filter and selection.
+//! The entity clicked last can be exported as a PDF to the documents
+//! directory, and the history of its numeric facts charted. The sources
+//! cited for its facts are listed below them, see [`crate::provenance`].
+//! Entities breaking a constraint carry a badge, see [`crate::validation`],
+//! and entities with an image a thumbnail of it, see [`crate::thumbnail`].
+//! Staged entities and facts are drawn apart, see [`crate::staging`].
+//!
+//! Shift+click and Ctrl+click select several entities, which can then be
+//! deleted, tagged, aligned or distributed together in one event, or copied
+//! to the clipboard and pasted as new entities. Two selected entities can be
+//! merged after a preview, see [`crate::merge`].
+//!
+//! The shown entity can be locked for an hour, see [`crate::lock`].
+//!
+//! Predicates declared as texts in [`crate::crdt`] are edited in place, so
+//! that edits made elsewhere at the same time merge, see
+//! [`crate::legacy::text`].
+
+use super::Editor;
+use crate::chart::{Series, Style};
+use crate::clipboard::Subgraph;
+use crate::crdt;
+use crate::export::pdf;
+use crate::legacy::storage::{Datum, Event};
+use crate::legacy::text::Text;
+use crate::merge;
+use crate::provenance::{self, Source};
+use crate::selection::{self, Axis, Selection};
+use iced::widget::{
+    button, column, container, row, scrollable, text, text_editor, text_input, Column, Row,
+};
+use iced::{theme, window, Command, Element, Length, Size};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// How many matching entities are listed.
+const LIMIT: usize = 50;
+
+#[derive(Default)]
+pub struct Inspector {
+    filter: String,
+    selection: Selection,
+    /// The tag to add to the selected entities.
+    tag: String,
+    /// Whether deleting the selection awaits confirmation.
+    confirm_delete: bool,
+    /// The entity kept and the one merged into it, while the merge is
+    /// previewed.
+    merge: Option<(Uuid, Uuid)>,
+    /// Where the selected entity was last exported to.
+    exported: Option<PathBuf>,
+    /// The predicate charted and its history, once read.
+    chart: Option<(String, Option<Vec<Series>>)>,
+    /// The text being edited.
+    note: Option<Note>,
+}
+
+/// A text of the shown entity being edited.
+struct Note {
+    subject: Uuid,
+    predicate: String,
+    /// The text as edited here, with the edits recorded before they come
+    /// back and those made elsewhere.
+    text: Text,
+    /// The text the editor was last in step with.
+    shown: String,
+    content: text_editor::Content,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Filter(String),
+    /// Show a single entity, such as the target of a link.
+    Select(Uuid),
+    /// An entity in the list was clicked, with the modifiers held.
+    Click(Uuid),
+    ClearSelection,
+    Delete,
+    ConfirmDelete(bool),
+    /// Preview merging the two selected entities.
+    Merge,
+    /// Keep the other entity of the merge previewed.
+    SwapMerge,
+    ConfirmMerge(bool),
+    TagInput(String),
+    Tag,
+    Align(Axis),
+    Distribute(Axis),
+    Copy,
+    Paste,
+    /// The text on the clipboard was read.
+    Pasted(Option<String>),
+    ExportPdf,
+    /// Chart the history of a predicate of the selected entity.
+    Chart(String),
+    Charted(String, Result<Vec<Series>, String>),
+    /// Edit a text of the shown entity in place.
+    EditText(String),
+    TextAction(text_editor::Action),
+    CloseText,
+}
+
+impl Editor {
+    pub(super) fn open_inspector(&mut self) -> Command<super::Message> {
+        self.spawn_inspector(Inspector::default())
+    }
+
+    /// Opens an inspector showing `entity`.
+    pub(super) fn inspect(&mut self, entity: Uuid) -> Command<super::Message> {
+        let mut inspector = Inspector::default();
+        inspector.selection.click(entity);
+        self.spawn_inspector(inspector)
+    }
+
+    fn spawn_inspector(&mut self, inspector: Inspector) -> Command<super::Message> {
+        let (id, spawn) = window::spawn(window::Settings {
+            size: Size::new(480.0, 640.0),
+            ..window::Settings::default()
+        });
+        self.inspectors.insert(id, inspector);
+        Command::batch([spawn, self.load_thumbnails()])
+    }
+
+    pub(super) fn update_inspector(
+        &mut self,
+        window: window::Id,
+        message: Message,
+    ) -> Command<super::Message> {
+        // Messages of a window that was closed in the meantime are dropped.
+        let Some(inspector) = self.inspectors.get_mut(&window) else {
+            return Command::none();
+        };
+        match message {
+            Message::Filter(filter) => inspector.filter = filter,
+            Message::Select(entity) => {
+                inspector.selection.click(entity);
+                inspector.shown_changed();
+            }
+            Message::Click(entity) => {
+                let shown = inspector.selection.anchor();
+                if self.modifiers.shift() {
+                    let filter = inspector.filter.clone();
+                    let order: Vec<Uuid> = self
+                        .inspector_matches(&filter)
+                        .into_iter()
+                        .map(|(_, id)| id)
+                        .collect();
+                    let inspector = self.inspectors.get_mut(&window).expect("checked above");
+                    inspector.selection.extend(entity, &order);
+                } else if self.modifiers.command() {
+                    inspector.selection.toggle(entity);
+                } else {
+                    inspector.selection.click(entity);
+                }
+                let inspector = self.inspectors.get_mut(&window).expect("checked above");
+                if inspector.selection.anchor() != shown {
+                    inspector.shown_changed();
+                }
+            }
+            Message::ClearSelection => {
+                inspector.selection.clear();
+                inspector.shown_changed();
+            }
+            Message::Delete => inspector.confirm_delete = true,
+            Message::ConfirmDelete(false) => inspector.confirm_delete = false,
+            Message::ConfirmDelete(true) => {
+                inspector.confirm_delete = false;
+                let actions = selection::delete(&inspector.selection);
+                return self.record(actions);
+            }
+            Message::Merge => {
+                let ids = inspector.selection.ids();
+                if let [first, second] = *ids {
+                    // The entity shown is kept.
+                    inspector.merge = if inspector.selection.anchor() == Some(second) {
+                        Some((second, first))
+                    } else {
+                        Some((first, second))
+                    };
+                }
+            }
+            Message::SwapMerge => {
+                inspector.merge = inspector.merge.map(|(keep, duplicate)| (duplicate, keep));
+            }
+            Message::ConfirmMerge(false) => inspector.merge = None,
+            Message::ConfirmMerge(true) => {
+                let Some((keep, duplicate)) = inspector.merge.take() else {
+                    return Command::none();
+                };
+                let actions = merge::merge(&self.projection, keep, duplicate);
+                inspector.selection.click(keep);
+                inspector.shown_changed();
+                return self.record(actions);
+            }
+            Message::TagInput(tag) => inspector.tag = tag,
+            Message::Tag => {
+                let tag = std::mem::take(&mut inspector.tag);
+                if tag.trim().is_empty() {
+                    return Command::none();
+                }
+                let actions = selection::tag(&self.projection, &inspector.selection, tag.trim());
+                return self.record(actions);
+            }
+            Message::Align(axis) => {
+                let actions = selection::align(&self.projection, &inspector.selection, axis);
+                return self.record(actions);
+            }
+            Message::Distribute(axis) => {
+                let actions = selection::distribute(&self.projection, &inspector.selection, axis);
+                return self.record(actions);
+            }
+            Message::Copy => {
+                let copied = Subgraph::copy(&self.projection, inspector.selection.ids());
+                return iced::clipboard::write(copied.to_json());
+            }
+            Message::Paste => {
+                return iced::clipboard::read(move |text| {
+                    super::Message::Inspector(window, Message::Pasted(text))
+                })
+            }
+            Message::Pasted(text) => {
+                let subgraph = match Subgraph::from_json(text.as_deref().unwrap_or_default()) {
+                    Ok(subgraph) => subgraph,
+                    Err(e) => {
+                        self.error = Some(format!("{:#}", e));
+                        return Command::none();
+                    }
+                };
+                let (pasted, actions) = subgraph.paste(&self.projection);
+                // Selected now, so they are highlighted once recorded.
+                inspector.selection.set(pasted);
+                inspector.shown_changed();
+                return self.record(actions);
+            }
+            Message::Chart(predicate) => {
+                let Some(entity) = inspector.selection.anchor() else {
+                    return Command::none();
+                };
+                inspector.chart = Some((predicate.clone(), None));
+                let load = self.load_series(vec![entity], predicate.clone());
+                return Command::perform(load, move |series| {
+                    super::Message::Inspector(
+                        window,
+                        Message::Charted(predicate, series.map_err(|e| format!("{:#}", e))),
+                    )
+                });
+            }
+            Message::Charted(predicate, Ok(series)) => {
+                // Dropped if another predicate or entity was picked meanwhile.
+                if let Some((charted, history)) = &mut inspector.chart {
+                    if *charted == predicate {
+                        *history = Some(series);
+                    }
+                }
+            }
+            Message::Charted(_, Err(error)) => self.error = Some(error),
+            Message::EditText(predicate) => {
+                let Some(subject) = inspector.selection.anchor() else {
+                    return Command::none();
+                };
+                let entity = self.projection.entity(&subject);
+                let text = entity
+                    .and_then(|entity| entity.text(&predicate))
+                    .cloned()
+                    .unwrap_or_default();
+                // A value added as a plain fact is edited into the text.
+                let value = match entity.and_then(|entity| entity.value(&predicate)) {
+                    Some(Datum::String(value)) => value.clone(),
+                    _ => text.to_string(),
+                };
+                inspector.note = Some(Note {
+                    subject,
+                    predicate,
+                    shown: text.to_string(),
+                    text,
+                    content: text_editor::Content::with_text(&value),
+                });
+            }
+            Message::TextAction(action) => {
+                let edited = action.is_edit();
+                if let Some(error) = self.read_only_reason().filter(|_| edited) {
+                    self.error = Some(error);
+                    return Command::none();
+                }
+                let inspector = self.inspectors.get_mut(&window).expect("checked above");
+                let Some(note) = &mut inspector.note else {
+                    return Command::none();
+                };
+                note.content.perform(action);
+                if !edited {
+                    return Command::none();
+                }
+                let mut new = note.content.text();
+                // The editor ends every text with a line break.
+                if new.ends_with('\n') {
+                    new.pop();
+                }
+                if new == note.shown {
+                    return Command::none();
+                }
+                let actions = note.text.edit(note.subject, &note.predicate, &new);
+                for action in &actions {
+                    note.text.apply(note.subject, &note.predicate, action);
+                }
+                note.shown = new;
+                return self.record(actions);
+            }
+            Message::CloseText => inspector.note = None,
+            Message::ExportPdf => {
+                let Some(entity) = inspector.selection.anchor() else {
+                    return Command::none();
+                };
+                let path = super::export_path(&self.label(&entity), "pdf");
+                let options = pdf::Options {
+                    sections: self.config.pdf_sections.clone(),
+                    ..pdf::Options::default()
+                };
+                match pdf::pdf(&self.projection, &[entity], &options, &path) {
+                    Ok(()) => {
+                        if let Some(inspector) = self.inspectors.get_mut(&window) {
+                            inspector.exported = Some(path);
+                        }
+                    }
+                    Err(e) => self.error = Some(format!("{:#}", e)),
+                }
+            }
+        }
+        Command::none()
+    }
+
+    pub(super) fn view_inspector(&self, window: window::Id) -> Element<'_, super::Message> {
+        let Some(inspector) = self.inspectors.get(&window) else {
+            return text("").into();
+        };
+        let message = move |m| super::Message::Inspector(window, m);
+
+        let mut list = Column::new().spacing(2);
+        for (label, id) in self.inspector_matches(&inspector.filter) {
+            let mut entry = Row::new().spacing(4).align_items(iced::Alignment::Center);
+            if let Some(thumbnail) = self.view_thumbnail(&id, 2.0) {
+                entry = entry.push(thumbnail);
+            }
+            let mut label = text(label);
+            if let Some(style) = self.staged_style(&id, None) {
+                label = label.style(style);
+            }
+            entry = entry.push(
+                button(label)
+                    .style(theme::Button::Text)
+                    .on_press(message(Message::Click(id))),
+            );
+            if let Some(badge) = self.view_violation_badge(&id) {
+                entry = entry.push(badge);
+            }
+            if let Some(badge) = self.view_presence_badge(&id) {
+                entry = entry.push(badge);
+            }
+            let mut item = container(entry);
+            if inspector.selection.contains(&id) {
+                item = item.style(container::Appearance {
+                    background: Some(self.graph_colors.selection.into()),
+                    ..container::Appearance::default()
+                });
+            }
+            list = list.push(item);
+        }
+
+        let mut facts = Column::new().spacing(4);
+        if let Some(warning) = self.view_lock_warning() {
+            facts = facts.push(warning);
+        }
+        if inspector.selection.len() > 1 {
+            facts = facts.push(self.view_bulk_actions(inspector, window));
+        }
+        if let Some(entity) = inspector
+            .selection
+            .anchor()
+            .and_then(|id| self.projection.entity(&id))
+        {
+            let mut export =
+                row![button(text(self.t("inspector-export-pdf")))
+                    .on_press(message(Message::ExportPdf))]
+                .spacing(10);
+            if let Some(path) = &inspector.exported {
+                export = export.push(text(self.tr(
+                    "inspector-exported",
+                    &[("path", path.display().to_string().into())],
+                )));
+            }
+            facts = facts.push(export);
+            if let Some(id) = inspector.selection.anchor() {
+                if let Some(thumbnail) = self.view_thumbnail(&id, 8.0) {
+                    facts = facts.push(thumbnail);
+                }
+                facts = facts.push(self.view_lock(id));
+                if let Some(violations) = self.view_violations(&id) {
+                    facts = facts.push(violations);
+                }
+            }
+            for (predicate, values) in entity.facts() {
+                if provenance::is_meta(predicate) {
+                    continue;
+                }
+                if self.config.crdt.get(predicate) == Some(&crdt::Kind::Text) {
+                    let value = match values.first() {
+                        Some(Datum::String(value)) => value.as_str(),
+                        _ => "",
+                    };
+                    facts = facts.push(self.view_text_fact(inspector, window, predicate, value));
+                    continue;
+                }
+                for datum in values {
+                    let value: Element<'_, super::Message> = match datum {
+                        Datum::Entity(id) => button(text(self.label(id)))
+                            .style(theme::Button::Text)
+                            .on_press(message(Message::Select(*id)))
+                            .into(),
+                        Datum::String(s) => text(s).into(),
+                        Datum::Integer(_) | Datum::Float(_) => row![
+                            text(self.value_label(datum)),
+                            button(text(self.t("inspector-chart")))
+                                .style(theme::Button::Text)
+                                .on_press(message(Message::Chart(predicate.to_string()))),
+                        ]
+                        .spacing(10)
+                        .into(),
+                        Datum::Boolean(b) => text(b).into(),
+                        Datum::DateTime(t) => text(t).into(),
+                    };
+                    let mut name = text(predicate).width(Length::Fixed(140.0));
+                    let anchor = inspector.selection.anchor();
+                    if let Some(style) =
+                        anchor.and_then(|id| self.staged_style(&id, Some(predicate)))
+                    {
+                        name = name.style(style);
+                    }
+                    facts = facts.push(row![name, value].spacing(10));
+                }
+                for source in provenance::sources(entity, predicate) {
+                    let source: Element<'_, super::Message> = match source {
+                        Source::Document(id) => button(text(self.label(&id)).size(12))
+                            .style(theme::Button::Text)
+                            .on_press(message(Message::Select(id)))
+                            .into(),
+                        Source::Url(url) => text(url).size(12).into(),
+                        Source::Batch(id) => {
+                            text(self.tr("inspector-batch", &[("id", id.to_string().into())]))
+                                .size(12)
+                                .into()
+                        }
+                    };
+                    facts = facts.push(
+                        row![
+                            text(self.t("inspector-source"))
+                                .size(12)
+                                .width(Length::Fixed(140.0)),
+                            source
+                        ]
+                        .spacing(10),
+                    );
+                }
+            }
+            for (predicate, kind) in &self.config.crdt {
+                if *kind == crdt::Kind::Text && entity.values(predicate).is_empty() {
+                    facts = facts.push(self.view_text_fact(inspector, window, predicate, ""));
+                }
+            }
+            if let Some(id) = inspector.selection.anchor() {
+                facts = facts.push(self.view_plugin_sections(id));
+            }
+            if let Some((predicate, history)) = &inspector.chart {
+                facts = facts.push(text(predicate).size(20));
+                facts = facts.push(match history {
+                    Some(series) => self.view_chart(series, Style::Line),
+                    None => text(self.t("chart-loading")).into(),
+                });
+            }
+        }
+
+        let mut copy = button(text(self.t("inspector-copy")));
+        if !inspector.selection.is_empty() {
+            copy = copy.on_press(message(Message::Copy));
+        }
+        column![
+            row![
+                text_input(&self.t("inspector-filter"), &inspector.filter)
+                    .on_input(move |f| message(Message::Filter(f))),
+                copy,
+                button(text(self.t("inspector-paste"))).on_press(message(Message::Paste)),
+            ]
+            .spacing(10),
+            scrollable(list).height(Length::FillPortion(1)),
+            scrollable(facts).height(Length::FillPortion(1)),
+        ]
+        .spacing(10)
+        .padding(10)
+        .into()
+    }
+
+    /// A text of the shown entity, edited in place once opened.
+    fn view_text_fact<'a>(
+        &'a self,
+        inspector: &'a Inspector,
+        window: window::Id,
+        predicate: &'a str,
+        value: &'a str,
+    ) -> Element<'a, super::Message> {
+        let message = move |m| super::Message::Inspector(window, m);
+        let name = text(predicate).width(Length::Fixed(140.0));
+        match &inspector.note {
+            Some(note) if note.predicate == predicate => column![
+                row![
+                    name,
+                    button(text(self.t("inspector-close-text")))
+                        .style(theme::Button::Text)
+                        .on_press(message(Message::CloseText)),
+                ]
+                .spacing(10),
+                text_editor(&note.content)
+                    .height(Length::Fixed(200.0))
+                    .on_action(move |action| message(Message::TextAction(action))),
+            ]
+            .spacing(4)
+            .into(),
+            _ => row![
+                name,
+                text(value),
+                button(text(self.t("inspector-edit-text")))
+                    .style(theme::Button::Text)
+                    .on_press(message(Message::EditText(predicate.to_string()))),
+            ]
+            .spacing(10)
+            .into(),
+        }
+    }
+
+    /// Brings the texts being edited in step with `event`, or with the
+    /// projection after it was reloaded. The editor is only reset when an
+    /// edit from elsewhere changed the text.
+    pub(super) fn refresh_notes(&mut self, event: Option<&Event>) {
+        for inspector in self.inspectors.values_mut() {
+            let Some(note) = &mut inspector.note else {
+                continue;
+            };
+            match event {
+                Some(event) => note
+                    .text
+                    .apply(note.subject, &note.predicate, event.action()),
+                None => {
+                    note.text = self
+                        .projection
+                        .entity(&note.subject)
+                        .and_then(|entity| entity.text(&note.predicate))
+                        .cloned()
+                        .unwrap_or_default();
+                }
+            }
+            let value = note.text.to_string();
+            if value != note.shown {
+                note.content = text_editor::Content::with_text(&value);
+                note.shown = value;
+            }
+        }
+    }
+
+    /// The operations on all selected entities.
+    fn view_bulk_actions(
+        &self,
+        inspector: &Inspector,
+        window: window::Id,
+    ) -> Element<'_, super::Message> {
+        let message = move |m| super::Message::Inspector(window, m);
+        let count = inspector.selection.len();
+        let delete = if inspector.confirm_delete {
+            row![
+                button(text(
+                    self.tr("selection-confirm-delete", &[("count", count.into())])
+                ))
+                .style(theme::Button::Destructive)
+                .on_press(message(Message::ConfirmDelete(true))),
+                button(text(self.t("cancel"))).on_press(message(Message::ConfirmDelete(false))),
+            ]
+        } else {
+            row![button(text(self.t("selection-delete"))).on_press(message(Message::Delete))]
+        };
+        let merge = self.view_merge(inspector, window);
+        let layout = [
+            ("selection-align-left", Message::Align(Axis::Horizontal)),
+            ("selection-align-top", Message::Align(Axis::Vertical)),
+            (
+                "selection-distribute-horizontally",
+                Message::Distribute(Axis::Horizontal),
+            ),
+            (
+                "selection-distribute-vertically",
+                Message::Distribute(Axis::Vertical),
+            ),
+        ];
+        let mut arrange = Row::new().spacing(10);
+        for (id, action) in layout {
+            arrange = arrange.push(button(text(self.t(id))).on_press(message(action)));
+        }
+        column![
+            row![
+                text(self.tr("selection-count", &[("count", count.into())])),
+                button(text(self.t("selection-clear")))
+                    .style(theme::Button::Text)
+                    .on_press(message(Message::ClearSelection)),
+            ]
+            .spacing(10),
+            delete.spacing(10),
+            merge,
+            row![
+                text_input(&self.t("selection-tag"), &inspector.tag)
+                    .on_input(move |t| message(Message::TagInput(t)))
+                    .on_submit(message(Message::Tag)),
+                button(text(self.t("selection-add-tag"))).on_press(message(Message::Tag)),
+            ]
+            .spacing(10),
+            arrange,
+        ]
+        .spacing(6)
+        .into()
+    }
+
+    /// The merge of two selected entities, previewed before it is recorded.
+    fn view_merge(&self, inspector: &Inspector, window: window::Id) -> Element<'_, super::Message> {
+        let message = move |m| super::Message::Inspector(window, m);
+        let Some((keep, duplicate)) = inspector.merge else {
+            let button = button(text(self.t("selection-merge")))
+                .on_press_maybe((inspector.selection.len() == 2).then(|| message(Message::Merge)));
+            return row![button].into();
+        };
+        let preview = merge::preview(&self.projection, keep, duplicate);
+        let mut details = Column::new().spacing(4).push(text(self.tr(
+            "merge-into",
+            &[
+                ("duplicate", self.label(&duplicate).into()),
+                ("keep", self.label(&keep).into()),
+            ],
+        )));
+        for (predicate, datum) in &preview.copied {
+            details = details.push(text(self.tr(
+                "merge-copied",
+                &[
+                    ("predicate", predicate.as_str().into()),
+                    ("value", self.value_label(datum).into()),
+                ],
+            )));
+        }
+        for (predicate, dropped) in &preview.conflicts {
+            let values: Vec<String> = dropped.iter().map(|d| self.value_label(d)).collect();
+            details = details.push(text(self.tr(
+                "merge-dropped",
+                &[
+                    ("predicate", predicate.as_str().into()),
+                    ("value", values.join(", ").into()),
+                ],
+            )));
+        }
+        details = details.push(text(self.tr(
+            "merge-references",
+            &[("count", preview.references.len().into())],
+        )));
+        column![
+            details,
+            row![
+                button(text(self.t("merge-confirm")))
+                    .style(theme::Button::Destructive)
+                    .on_press(message(Message::ConfirmMerge(true))),
+                button(text(self.t("merge-swap"))).on_press(message(Message::SwapMerge)),
+                button(text(self.t("cancel"))).on_press(message(Message::ConfirmMerge(false))),
+            ]
+            .spacing(10),
+        ]
+        .spacing(6)
+        .into()
+    }
+
+    /// The entities whose label contains `filter`, in the order listed.
+    fn inspector_matches(&self, filter: &str) -> Vec<(String, Uuid)> {
+        let filter = filter.to_lowercase();
+        let mut matches: Vec<(String, Uuid)> = self
+            .projection
+            .entities()
+            .map(|(id, _)| (self.label(id), *id))
+            .filter(|(label, _)| label.to_lowercase().contains(&filter))
+            .collect();
+        matches.sort();
+        matches.truncate(LIMIT);
+        matches
+    }
+
+    /// The entities selected in any inspector, those shown first.
+    pub(super) fn selected(&self) -> Vec<Uuid> {
+        let mut selected: Vec<Uuid> = self
+            .inspectors
+            .values()
+            .filter_map(|inspector| inspector.selection.anchor())
+            .collect();
+        for inspector in self.inspectors.values() {
+            for id in inspector.selection.ids() {
+                if !selected.contains(id) {
+                    selected.push(*id);
+                }
+            }
+        }
+        selected
+    }
+
+    /// The entities listed or shown in any inspector.
+    pub(super) fn inspected(&self) -> Vec<Uuid> {
+        let mut shown = Vec::new();
+        for inspector in self.inspectors.values() {
+            let matches = self.inspector_matches(&inspector.filter);
+            shown.extend(matches.into_iter().map(|(_, id)| id));
+            shown.extend(inspector.selection.anchor());
+        }
+        shown
+    }
+
+    /// Drops deleted entities from the selection of every inspector: those
+    /// among `changed`, or all missing ones after a reload. Pasted entities
+    /// that are not recorded yet stay selected.
+    pub(super) fn retain_selections(&mut self, changed: Option<&[Uuid]>) {
+        for inspector in self.inspectors.values_mut() {
+            inspector.selection.retain(|id| {
+                self.projection.contains(id) || changed.is_some_and(|c| !c.contains(id))
+            });
+        }
+    }
+}
+
+impl Inspector {
+    /// Forgets what was derived from the entity shown.
+    fn shown_changed(&mut self) {
+        self.exported = None;
+        self.chart = None;
+        self.confirm_delete = false;
+        self.merge = None;
+        self.note = None;
+    }
+}
@@ -0,0 +1,177 @@
+//! The journal calendar and the note of the selected day.
+
+use super::Editor;
+use crate::journal;
+use crate::legacy::hlc::HLTimestamp;
+use crate::legacy::storage::{Datum, Event};
+use crate::recurrence;
+use iced::widget::{button, column, row, text, Column};
+use iced::{theme, Command, Element, Length};
+use time::{Date, Duration};
+use uuid::Uuid;
+
+pub struct Journal {
+    /// The first day of the month shown in the calendar.
+    month: Date,
+    selected: Option<Date>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Open today's note, creating it if needed.
+    Today,
+    /// Open the note of a day, creating it if needed.
+    Open(Date),
+    ShowMonth(Date),
+    /// The events of a day were read, so its mentions can be linked.
+    DayEvents(Date, Result<Vec<Event>, String>),
+    /// Skip the occurrence of a recurring entity on a day.
+    Skip(Uuid, Date),
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        let today = journal::today();
+        Journal {
+            month: today.replace_day(1).unwrap_or(today),
+            selected: None,
+        }
+    }
+}
+
+impl Editor {
+    pub(super) fn update_journal(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Today => self.update_journal(Message::Open(journal::today())),
+            Message::Open(date) => {
+                self.journal.selected = Some(date);
+                self.journal.month = date.replace_day(1).unwrap_or(date);
+                let (start, _) = journal::day_bounds(date, journal::local_offset());
+                let events = self.storage.events_from(HLTimestamp::new(start, 0));
+                Command::perform(events, move |events| {
+                    super::Message::Journal(Message::DayEvents(
+                        date,
+                        events.map_err(|e| format!("{:#}", e)),
+                    ))
+                })
+            }
+            Message::ShowMonth(month) => {
+                self.journal.month = month;
+                Command::none()
+            }
+            Message::DayEvents(date, Ok(events)) => {
+                let mut actions = journal::open(&self.projection, date);
+                let mentioned = journal::mentioned(&events, date, journal::local_offset());
+                actions.extend(journal::link_mentions(&self.projection, date, &mentioned));
+                self.record(actions)
+            }
+            Message::DayEvents(_, Err(error)) => {
+                self.error = Some(error);
+                Command::none()
+            }
+            Message::Skip(id, date) => self.record(vec![recurrence::skip(id, date)]),
+        }
+    }
+
+    pub(super) fn view_journal(&self) -> Element<'_, super::Message> {
+        let message = |m| super::Message::Journal(m);
+        let month = self.journal.month;
+        let previous = month
+            .previous_day()
+            .and_then(|d| d.replace_day(1).ok())
+            .unwrap_or(month);
+        let next = month
+            .replace_day(time::util::days_in_year_month(month.year(), month.month()))
+            .ok()
+            .and_then(|d| d.next_day())
+            .unwrap_or(month);
+
+        let header = row![
+            button("<").on_press(message(Message::ShowMonth(previous))),
+            text(self.tr(
+                "journal-month",
+                &[
+                    ("month", u8::from(month.month()).into()),
+                    ("year", month.year().into())
+                ]
+            ))
+            .width(Length::Fixed(140.0)),
+            button(">").on_press(message(Message::ShowMonth(next))),
+            button(text(self.t("journal-today"))).on_press(message(Message::Today)),
+        ]
+        .spacing(10);
+
+        let days = journal::days(&self.projection);
+        let recurring = recurrence::by_day(&self.projection, month, next);
+        let mut calendar = Column::new().spacing(4).push(header).push(
+            row(self
+                .t("journal-weekdays")
+                .split_whitespace()
+                .map(|d| text(d).width(Length::Fixed(40.0)).into()))
+            .spacing(4),
+        );
+        for week in journal::month_grid(month.year(), month.month()) {
+            calendar = calendar.push(
+                row(week.map(|day| match day {
+                    Some(day) => {
+                        let style = if Some(day) == self.journal.selected {
+                            theme::Button::Primary
+                        } else if days.contains(&day) {
+                            theme::Button::Secondary
+                        } else {
+                            theme::Button::Text
+                        };
+                        // Days with recurring entities are marked with a dot.
+                        let label = if recurring.contains_key(&day) {
+                            format!("{}•", day.day())
+                        } else {
+                            day.day().to_string()
+                        };
+                        button(text(label))
+                            .style(style)
+                            .width(Length::Fixed(40.0))
+                            .on_press(message(Message::Open(day)))
+                            .into()
+                    }
+                    None => text("").width(Length::Fixed(40.0)).into(),
+                }))
+                .spacing(4),
+            );
+        }
+
+        row![calendar, self.view_day()].spacing(40).into()
+    }
+
+    fn view_day(&self) -> Element<'_, super::Message> {
+        let Some(date) = self.journal.selected else {
+            return text(self.t("journal-hint")).into();
+        };
+        let mentions = self
+            .projection
+            .entity(&journal::day_entity(date))
+            .map(|e| e.values("mentions"))
+            .unwrap_or_default();
+        let mut day = column![text(date.to_string()).size(30)].spacing(8);
+        if mentions.is_empty() {
+            day = day.push(text(self.t("journal-empty")));
+        }
+        for mention in mentions {
+            if let Datum::Entity(id) = mention {
+                day = day.push(text(self.label(id)));
+            }
+        }
+        let recurring = recurrence::by_day(&self.projection, date, date + Duration::DAY);
+        for id in recurring.into_values().flatten() {
+            day = day.push(
+                row![
+                    text(format!("↻ {}", self.label(&id))),
+                    button(text(self.t("journal-skip")))
+                        .style(theme::Button::Text)
+                        .on_press(super::Message::Journal(Message::Skip(id, date))),
+                ]
+                .spacing(10),
+            );
+        }
+        day.into()
+    }
+}
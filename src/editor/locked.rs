@@ -0,0 +1,50 @@
+//! The prompt shown when another Graphite process writes to the database,
+//! see [`crate::legacy::storage::writer`]. The database was opened read-only
+//! instead, which the user can go on with or quit.
+
+use super::Editor;
+use iced::widget::{button, column, container, row, text};
+use iced::{window, Command, Element};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Go on with the database opened read-only.
+    ReadOnly,
+    Quit,
+}
+
+impl Editor {
+    pub(super) fn update_locked(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::ReadOnly => self.locked = None,
+            Message::Quit => return window::close(window::Id::MAIN),
+        }
+        Command::none()
+    }
+
+    /// The prompt, until it is answered.
+    pub(super) fn view_locked(&self) -> Option<Element<'_, super::Message>> {
+        let holder = self.locked?;
+        let message = |m| super::Message::Locked(m);
+        let explain = match holder.pid {
+            Some(pid) => self.tr("locked-by-process", &[("pid", pid.into())]),
+            None => self.t("locked"),
+        };
+        let prompt = column![
+            text(explain),
+            text(self.t("locked-explain")),
+            row![
+                button(text(self.t("locked-read-only"))).on_press(message(Message::ReadOnly)),
+                button(text(self.t("locked-quit"))).on_press(message(Message::Quit)),
+            ]
+            .spacing(10),
+        ]
+        .spacing(10);
+        Some(
+            container(prompt)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,124 @@
+//! Advisory edit locks, see [`crate::lock`]. The inspector shows the lock on
+//! the entity clicked last and takes or releases it, and edits to entities
+//! others have locked wait for confirmation.
+
+use super::Editor;
+use crate::legacy::storage::Action;
+use crate::lock::{self, Lock};
+use iced::widget::{button, column, container, row, text};
+use iced::{theme, Command, Element};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct Locks {
+    /// An edit to locked entities awaiting confirmation, and their locks.
+    pending: Option<(Vec<Lock>, Vec<Action>)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Lock an entity for [`lock::DEFAULT_DURATION`].
+    Lock(Uuid),
+    Unlock(Uuid),
+    /// Make the pending edit anyway, or drop it.
+    Confirm(bool),
+}
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+impl Editor {
+    pub(super) fn update_locks(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Lock(entity) => {
+                let until = now() + lock::DEFAULT_DURATION;
+                return self.record(lock::lock(entity, self.actor(), until));
+            }
+            Message::Unlock(entity) => return self.record(lock::unlock(entity)),
+            Message::Confirm(true) => {
+                if let Some((_, actions)) = self.locks.pending.take() {
+                    return self.record_then(actions, super::Message::Saved);
+                }
+            }
+            Message::Confirm(false) => self.locks.pending = None,
+        }
+        Command::none()
+    }
+
+    /// Holds back `actions` if they change entities others have locked, and
+    /// returns them otherwise.
+    pub(super) fn check_locks(&mut self, actions: Vec<Action>) -> Option<Vec<Action>> {
+        let held = lock::conflicts(
+            &self.projection,
+            &self.config.hierarchy,
+            &actions,
+            self.actor(),
+            now(),
+        );
+        if held.is_empty() {
+            return Some(actions);
+        }
+        self.locks.pending = Some((held, actions));
+        None
+    }
+
+    /// The lock covering `entity`, with a button to take or release it.
+    pub(super) fn view_lock(&self, entity: Uuid) -> Element<'_, super::Message> {
+        let message = |m| super::Message::Locks(m);
+        match lock::covering(&self.projection, &self.config.hierarchy, entity, now()) {
+            Some(held) => {
+                let mut status = row![text(self.lock_label(&held))].spacing(10);
+                if held.owner == self.actor() {
+                    status = status.push(
+                        button(text(self.t("lock-release")))
+                            .on_press(message(Message::Unlock(held.entity))),
+                    );
+                }
+                status.into()
+            }
+            None => button(text(self.t("lock-take")))
+                .on_press(message(Message::Lock(entity)))
+                .into(),
+        }
+    }
+
+    /// The warning about a pending edit to locked entities, if there is one.
+    pub(super) fn view_lock_warning(&self) -> Option<Element<'_, super::Message>> {
+        let (held, _) = self.locks.pending.as_ref()?;
+        let message = |m| super::Message::Locks(m);
+        let mut warning = column![text(self.t("lock-warning"))].spacing(10);
+        for held in held {
+            warning = warning.push(text(self.lock_label(held)));
+        }
+        warning = warning.push(
+            row![
+                button(text(self.t("lock-edit-anyway")))
+                    .style(theme::Button::Destructive)
+                    .on_press(message(Message::Confirm(true))),
+                button(text(self.t("cancel"))).on_press(message(Message::Confirm(false))),
+            ]
+            .spacing(10),
+        );
+        Some(
+            container(warning)
+                .padding(20)
+                .style(theme::Container::Box)
+                .into(),
+        )
+    }
+
+    /// Who holds `held` on what, and until when in local time.
+    fn lock_label(&self, held: &Lock) -> String {
+        let until = self.local_time(held.until);
+        self.tr(
+            "lock-held",
+            &[
+                ("entity", self.label(&held.entity).into()),
+                ("owner", self.label(&held.owner).into()),
+                ("until", until.into()),
+            ],
+        )
+    }
+}
@@ -0,0 +1,334 @@
+//! The nearby devices dialog: syncing with other instances on the local
+//! network, see [`crate::lan`].
+//!
+//! Once synced with, a device stays connected: each side sees which
+//! entities the other has selected and where its pointer is, see
+//! [`crate::lan::presence`].
+
+use super::Editor;
+use crate::lan::presence::{self, Presence};
+use crate::lan::{self, Lan, Pairing, Peer};
+use crate::legacy::storage::metadata;
+use iced::widget::{button, column, container, row, scrollable, text, Column, Row};
+use iced::{theme, Command, Element, Length};
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct Nearby {
+    open: bool,
+    /// Serving and discovering, from when the dialog is first opened.
+    lan: Option<Lan>,
+    peers: Vec<Peer>,
+    pending: Vec<Pairing>,
+    /// The device asked to pair, and the code both show, until it answers.
+    pairing: Option<(Peer, String)>,
+    /// What the last sync did.
+    status: Option<String>,
+    /// The devices synced with, with the token pairing gave.
+    paired: Vec<(Peer, String)>,
+    /// Whether presences are being exchanged with them.
+    sharing: bool,
+    /// The presences of the other devices.
+    others: Vec<Presence>,
+}
+
+impl Nearby {
+    /// Whether to keep polling for peers and pairings.
+    pub fn is_running(&self) -> bool {
+        self.lan.is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    /// Time to look for peers, pairings and merged events.
+    Tick,
+    Sync(Peer),
+    /// A sync finished, with the token pairing gave.
+    Synced(Peer, Result<(String, lan::Synced), String>),
+    /// The presences of the paired devices were exchanged.
+    Shared(Vec<Presence>),
+    /// Accept or refuse a pairing.
+    Answer(Uuid, bool),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_nearby(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => {
+                self.nearby.open = true;
+                if self.nearby.lan.is_none() {
+                    let device = metadata::device().unwrap_or_else(|| self.t("nearby-unnamed"));
+                    match Lan::start(self.storage.clone(), self.actor(), device) {
+                        Ok(lan) => {
+                            lan.set_subscription(self.config.backups.subscription.clone());
+                            self.nearby.lan = Some(lan);
+                        }
+                        Err(e) => self.error = Some(format!("{:#}", e)),
+                    }
+                }
+            }
+            Message::Tick => {
+                let Some(lan) = &self.nearby.lan else {
+                    return Command::none();
+                };
+                self.nearby.peers = lan.peers();
+                self.nearby.pending = lan.pending();
+                self.nearby.others = lan.presences();
+                let mine = self.presence(lan);
+                lan.set_presence(mine.clone());
+                lan.set_subscription(self.config.backups.subscription.clone());
+                let merged = lan.take_merged();
+                let share = self.share_presence(mine);
+                if merged > 0 {
+                    return Command::batch([share, self.load()]);
+                }
+                return share;
+            }
+            Message::Shared(presences) => {
+                self.nearby.sharing = false;
+                if let Some(lan) = &self.nearby.lan {
+                    presences.into_iter().for_each(|p| lan.heard(p));
+                    self.nearby.others = lan.presences();
+                }
+            }
+            Message::Sync(peer) => return self.sync_nearby(peer),
+            Message::Synced(peer, Ok((token, synced))) => {
+                self.nearby.pairing = None;
+                let device = peer.device.clone();
+                self.nearby.paired.retain(|(p, _)| p.actor != peer.actor);
+                self.nearby.paired.push((peer, token));
+                let mut status = self.tr(
+                    "nearby-synced",
+                    &[
+                        ("device", device.into()),
+                        ("pulled", synced.pulled.into()),
+                        ("pushed", synced.pushed.into()),
+                    ],
+                );
+                if synced.conflicts > 0 {
+                    let found = self.tr("conflicts-found", &[("count", synced.conflicts.into())]);
+                    status = format!("{} {}", status, found);
+                }
+                self.nearby.status = Some(status);
+                // Merged events are older than the ones applied, so the graph
+                // is replayed in order.
+                return self.load();
+            }
+            Message::Synced(_, Err(error)) => {
+                self.nearby.pairing = None;
+                self.error = Some(error);
+            }
+            Message::Answer(nonce, accept) => {
+                if let Some(lan) = &self.nearby.lan {
+                    lan.answer(nonce, accept);
+                    self.nearby.pending = lan.pending();
+                }
+            }
+            Message::Close => self.nearby.open = false,
+        }
+        Command::none()
+    }
+
+    /// Pairs with `peer` and syncs with it, as an operation.
+    fn sync_nearby(&mut self, peer: Peer) -> Command<super::Message> {
+        let Some(lan) = &self.nearby.lan else {
+            return Command::none();
+        };
+        let (actor, device) = (lan.actor(), lan.device().to_string());
+        let nonce = Uuid::new_v4();
+        let code = lan::pairing_code(actor, peer.actor, nonce);
+        self.nearby.pairing = Some((peer.clone(), code));
+        let reporter = self
+            .start_operation(self.tr("nearby-syncing", &[("device", peer.device.clone().into())]));
+        let storage = self.storage.clone();
+        let subscription = self.config.backups.subscription.clone();
+        let synced = tokio::task::spawn_blocking({
+            let peer = peer.clone();
+            move || {
+                let token = lan::pair(&peer, actor, &device, nonce)?;
+                reporter.check()?;
+                lan::sync(&peer, &token, &storage, &subscription).map(|synced| (token, synced))
+            }
+        });
+        Command::perform(synced, move |result| {
+            let result = result
+                .map_err(anyhow::Error::from)
+                .and_then(|synced| synced)
+                .map_err(|e| format!("{:#}", e));
+            super::Message::Nearby(Message::Synced(peer, result))
+        })
+    }
+
+    /// What this instance shows its peers: the entities selected in the
+    /// inspectors and the pointer.
+    fn presence(&self, lan: &Lan) -> Presence {
+        let cursor = self.file_drop.cursor();
+        Presence {
+            actor: lan.actor(),
+            device: lan.device().to_string(),
+            selected: self.selected(),
+            cursor: Some((cursor.x, cursor.y)),
+        }
+    }
+
+    /// Sends `mine` to the paired devices and takes in theirs, unless an
+    /// exchange is still under way. Devices that can't be reached are
+    /// skipped, and forgotten once their presence is.
+    fn share_presence(&mut self, mine: Presence) -> Command<super::Message> {
+        if self.nearby.sharing || self.nearby.paired.is_empty() {
+            return Command::none();
+        }
+        self.nearby.sharing = true;
+        let paired = self.nearby.paired.clone();
+        let shared = tokio::task::spawn_blocking(move || {
+            let mut theirs = Vec::new();
+            for (peer, token) in &paired {
+                match presence::exchange(peer, token, &mine) {
+                    Ok(presence) => theirs.extend(presence),
+                    Err(e) => tracing::debug!("Failed to exchange presence: {:#}", e),
+                }
+            }
+            theirs
+        });
+        Command::perform(shared, |theirs| {
+            super::Message::Nearby(Message::Shared(theirs.unwrap_or_default()))
+        })
+    }
+
+    /// Who else is looking at the graph and at what, if anyone.
+    pub(super) fn view_presence(&self) -> Option<Element<'_, super::Message>> {
+        if self.nearby.others.is_empty() {
+            return None;
+        }
+        let mut others = Row::new().spacing(20);
+        for other in &self.nearby.others {
+            let mut line = match other.selected.first() {
+                Some(id) => self.tr(
+                    "presence-viewing",
+                    &[
+                        ("device", other.device.clone().into()),
+                        ("entity", self.label(id).into()),
+                    ],
+                ),
+                None => self.tr("presence-here", &[("device", other.device.clone().into())]),
+            };
+            if let Some((x, y)) = other.cursor {
+                let pointer = self.tr(
+                    "presence-pointer",
+                    &[
+                        ("x", (x.round() as i64).into()),
+                        ("y", (y.round() as i64).into()),
+                    ],
+                );
+                line = format!("{} · {}", line, pointer);
+            }
+            others = others.push(text(line).size(12));
+        }
+        Some(container(others).padding(6).into())
+    }
+
+    /// The devices that have `id` selected, as a badge.
+    pub(super) fn view_presence_badge(&self, id: &Uuid) -> Option<Element<'_, super::Message>> {
+        let devices: Vec<&str> = self
+            .nearby
+            .others
+            .iter()
+            .filter(|other| other.selected.contains(id))
+            .map(|other| other.device.as_str())
+            .collect();
+        if devices.is_empty() {
+            return None;
+        }
+        Some(
+            container(text(format!("● {}", devices.join(", "))).size(12))
+                .padding([0, 4])
+                .style(theme::Container::Box)
+                .into(),
+        )
+    }
+
+    /// The nearby devices dialog, if it is open.
+    pub(super) fn view_nearby(&self) -> Option<Element<'_, super::Message>> {
+        if !self.nearby.open {
+            return None;
+        }
+        let message = |m| super::Message::Nearby(m);
+        let mut content = column![text(self.t("nearby")).size(30)].spacing(10);
+        if let Some(lan) = &self.nearby.lan {
+            content = content.push(text(self.tr(
+                "nearby-this-device",
+                &[
+                    ("device", lan.device().to_string().into()),
+                    ("actor", lan.actor().to_string().into()),
+                ],
+            )));
+        }
+        if let Some(status) = &self.nearby.status {
+            content = content.push(text(status));
+        }
+        if let Some((peer, code)) = &self.nearby.pairing {
+            content = content.push(text(self.tr(
+                "nearby-waiting",
+                &[
+                    ("device", peer.device.clone().into()),
+                    ("code", code.clone().into()),
+                ],
+            )));
+        }
+        for pairing in &self.nearby.pending {
+            content = content.push(
+                container(
+                    column![
+                        text(self.tr(
+                            "nearby-request",
+                            &[
+                                ("device", pairing.device.clone().into()),
+                                ("actor", pairing.actor.to_string().into()),
+                            ],
+                        )),
+                        text(self.tr("nearby-code", &[("code", pairing.code.clone().into())]))
+                            .size(24),
+                        row![
+                            button(text(self.t("nearby-accept")))
+                                .on_press(message(Message::Answer(pairing.nonce, true))),
+                            button(text(self.t("nearby-refuse")))
+                                .style(theme::Button::Destructive)
+                                .on_press(message(Message::Answer(pairing.nonce, false))),
+                        ]
+                        .spacing(10),
+                    ]
+                    .spacing(5),
+                )
+                .padding(10)
+                .style(theme::Container::Box),
+            );
+        }
+        if self.nearby.peers.is_empty() {
+            content = content.push(text(self.t("nearby-none")));
+        }
+        let mut peers = Column::new().spacing(5);
+        for peer in &self.nearby.peers {
+            let syncing = self.nearby.pairing.is_some();
+            peers = peers.push(
+                row![
+                    text(format!("{} ({})", peer.device, peer.actor)).width(Length::Fill),
+                    button(text(self.t("nearby-sync")))
+                        .on_press_maybe((!syncing).then(|| message(Message::Sync(peer.clone())))),
+                ]
+                .spacing(10),
+            );
+        }
+        content = content
+            .push(scrollable(peers).height(Length::Shrink))
+            .push(button(text(self.t("close"))).on_press(message(Message::Close)));
+        Some(
+            container(content)
+                .padding(20)
+                .style(theme::Container::Box)
+                .into(),
+        )
+    }
+}
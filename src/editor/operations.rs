@@ -0,0 +1,93 @@
+//! The long-running operations in progress, shown at the bottom of the main
+//! window with a progress bar and a button to cancel each, see
+//! [`crate::progress`].
+
+use super::Editor;
+use crate::progress::{self, Handle, Reporter};
+use iced::widget::{button, progress_bar, row, text, Column};
+use iced::{Command, Element, Length};
+
+#[derive(Default)]
+pub struct Operations {
+    running: Vec<Running>,
+    /// The id of the next operation started.
+    next: u64,
+}
+
+struct Running {
+    id: u64,
+    label: String,
+    handle: Handle,
+}
+
+impl Operations {
+    pub(super) fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Time to redraw the progress and drop the finished operations.
+    Tick,
+    Cancel(u64),
+}
+
+impl Editor {
+    /// Shows an operation described by `label` until the returned reporter,
+    /// and every clone of it, is dropped.
+    pub(super) fn start_operation(&mut self, label: String) -> Reporter {
+        let (reporter, handle) = progress::task();
+        let operations = &mut self.operations;
+        operations.running.push(Running {
+            id: operations.next,
+            label,
+            handle,
+        });
+        operations.next += 1;
+        reporter
+    }
+
+    pub(super) fn update_operations(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Tick => self.operations.running.retain(|r| !r.handle.is_finished()),
+            Message::Cancel(id) => {
+                if let Some(running) = self.operations.running.iter().find(|r| r.id == id) {
+                    running.handle.cancel();
+                }
+            }
+        }
+        Command::none()
+    }
+
+    pub(super) fn view_operations(&self) -> Option<Element<'_, super::Message>> {
+        if self.operations.is_empty() {
+            return None;
+        }
+        let rows = self.operations.running.iter().map(|running| {
+            let progress = running.handle.progress();
+            let count = match progress.total {
+                Some(total) => format!("{} / {}", progress.done, total),
+                None => progress.done.to_string(),
+            };
+            let cancel = if running.handle.is_cancelled() {
+                button(text(self.t("operation-cancelling")))
+            } else {
+                button(text(self.t("cancel")))
+                    .on_press(super::Message::Operations(Message::Cancel(running.id)))
+            };
+            row![
+                text(&running.label).width(Length::Fill),
+                progress_bar(0.0..=1.0, progress.fraction().unwrap_or(0.0))
+                    .width(Length::FillPortion(2))
+                    .height(Length::Fixed(8.0)),
+                text(count),
+                cancel,
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center)
+            .into()
+        });
+        Some(Column::with_children(rows).spacing(4).into())
+    }
+}
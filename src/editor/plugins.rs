@@ -0,0 +1,235 @@
+//! The parts of the interface contributed by plugins, see [`crate::plugin`],
+//! and the look of the nodes their renderer draws, see [`crate::wasm`], got
+//! in the background for the nodes on the canvas.
+
+use super::Editor;
+use crate::plugin::{self, Block, Layout, Plugin};
+use crate::wasm;
+use iced::widget::{column, container, text, Column, Row};
+use iced::{Command, Element, Length};
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+
+/// How many entities a renderer draws in one job.
+const RENDER_BATCH: usize = 200;
+
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+    /// Why the files that aren't valid plugins were skipped.
+    errors: Vec<String>,
+    /// The rendered side panels, as of the last change to the graph.
+    panels: Vec<(String, Block)>,
+    /// The look of the entities the renderer drew or is drawing, forgotten
+    /// when they change.
+    looks: HashMap<Uuid, Look>,
+    /// Whether a render job is running, as they run one at a time.
+    rendering: bool,
+}
+
+enum Look {
+    Rendering,
+    /// Drawn as usual if the renderer failed, which is logged.
+    Rendered(wasm::Rendered),
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Rendered(Vec<Uuid>, Result<Vec<(Uuid, wasm::Rendered)>, String>),
+}
+
+impl Plugins {
+    /// The plugins in the `plugins` directory next to the config.
+    pub(super) fn load(config_path: Option<&Path>) -> Plugins {
+        let Some(dir) = config_path.and_then(Path::parent) else {
+            return Plugins::default();
+        };
+        let (plugins, errors) = plugin::load(&dir.join("plugins"));
+        Plugins {
+            plugins,
+            errors,
+            ..Plugins::default()
+        }
+    }
+
+    /// The layout of the view `view` of the plugin named `plugin`.
+    pub(super) fn view(&self, plugin: &str, view: &str) -> Option<&Layout> {
+        let plugin = self.plugins.iter().find(|p| p.name == plugin)?;
+        Some(&plugin.view(view)?.layout)
+    }
+
+    /// The module of the first plugin importing files such as `path`.
+    pub(super) fn importer(&self, path: &Path) -> Option<wasm::Module> {
+        let mut modules = self.plugins.iter().filter_map(|p| p.wasm.as_ref());
+        modules.find(|module| module.imports(path)).cloned()
+    }
+
+    /// The module of the first plugin drawing entities.
+    fn renderer(&self) -> Option<&wasm::Module> {
+        let mut modules = self.plugins.iter().filter_map(|p| p.wasm.as_ref());
+        modules.find(|module| module.renderer)
+    }
+
+    /// How the renderer draws `id`, once it has.
+    pub(super) fn rendered(&self, id: &Uuid) -> Option<&wasm::Rendered> {
+        match self.looks.get(id) {
+            Some(Look::Rendered(rendered)) => Some(rendered),
+            _ => None,
+        }
+    }
+}
+
+impl Editor {
+    /// Renders the side panels again, after the graph changed, and forgets
+    /// the look of the entities among `changed`, of all if `None` or if the
+    /// renderer sees the whole graph.
+    pub(super) fn refresh_plugins(&mut self, changed: Option<&[Uuid]>) {
+        let graph = self.plugins.renderer().is_some_and(|m| m.permissions.graph);
+        match changed.filter(|_| !graph) {
+            Some(changed) => changed.iter().for_each(|id| {
+                self.plugins.looks.remove(id);
+            }),
+            None => self.plugins.looks.clear(),
+        }
+        self.plugins.panels = self
+            .plugins
+            .plugins
+            .iter()
+            .flat_map(|p| &p.panels)
+            .map(|panel| {
+                let block = panel.layout.render(&self.projection, None);
+                (panel.title.clone(), block)
+            })
+            .collect();
+    }
+
+    pub(super) fn update_plugins(&mut self, message: Message) -> Command<super::Message> {
+        let Message::Rendered(ids, result) = message;
+        self.plugins.rendering = false;
+        let mut rendered: HashMap<Uuid, wasm::Rendered> = match result {
+            Ok(rendered) => rendered.into_iter().collect(),
+            Err(error) => {
+                tracing::warn!("{}", error);
+                HashMap::new()
+            }
+        };
+        // Those changed since are drawn again.
+        for id in &ids {
+            if let Some(look @ Look::Rendering) = self.plugins.looks.get_mut(id) {
+                *look = Look::Rendered(rendered.remove(id).unwrap_or_default());
+            }
+        }
+        self.refresh_canvas_later(&[]);
+        self.render_nodes()
+    }
+
+    /// Starts drawing the nodes on the canvas the renderer, if any, hasn't
+    /// drawn yet.
+    pub(super) fn render_nodes(&mut self) -> Command<super::Message> {
+        if self.plugins.rendering || self.plugins.renderer().is_none() {
+            return Command::none();
+        }
+        let mut ids = self.canvas_drawn();
+        ids.retain(|id| !self.plugins.looks.contains_key(id));
+        ids.truncate(RENDER_BATCH);
+        let Some(job) = self
+            .plugins
+            .renderer()
+            .filter(|_| !ids.is_empty())
+            .map(|module| module.render_job(&self.projection, &ids))
+        else {
+            return Command::none();
+        };
+        for id in &ids {
+            self.plugins.looks.insert(*id, Look::Rendering);
+        }
+        self.plugins.rendering = true;
+        let runtime = self.config.wasm.clone();
+        let run =
+            tokio::task::spawn_blocking(move || job.run(&runtime).map_err(|e| format!("{:#}", e)));
+        Command::perform(run, move |rendered| {
+            let rendered = rendered.unwrap_or_else(|e| Err(e.to_string()));
+            super::Message::Plugins(Message::Rendered(ids, rendered))
+        })
+    }
+
+    pub(super) fn view_plugin_panels(&self) -> Option<Element<'_, super::Message>> {
+        if self.plugins.panels.is_empty() && self.plugins.errors.is_empty() {
+            return None;
+        }
+        let mut panels = Column::new().spacing(10).width(Length::Fixed(300.0));
+        for error in &self.plugins.errors {
+            panels = panels.push(text(error));
+        }
+        for (title, block) in &self.plugins.panels {
+            panels = panels.push(
+                container(column![text(title).size(20), self.view_block(block)].spacing(4))
+                    .padding(10)
+                    .width(Length::Fill)
+                    .style(iced::theme::Container::Box),
+            );
+        }
+        Some(panels.into())
+    }
+
+    /// The sections of plugins shown in the inspector for `entity`.
+    pub(super) fn view_plugin_sections(&self, entity: Uuid) -> Element<'_, super::Message> {
+        let mut sections = Column::new().spacing(10);
+        for section in self.plugins.plugins.iter().flat_map(|p| &p.sections) {
+            if section.shows(&self.projection, entity) {
+                let block = section.layout.render(&self.projection, Some(entity));
+                sections = sections.push(
+                    column![text(&section.title).size(20), self.view_block(&block)].spacing(4),
+                );
+            }
+        }
+        sections.into()
+    }
+
+    pub(super) fn view_block(&self, block: &Block) -> Element<'_, super::Message> {
+        match block {
+            Block::Text(s) => text(s).into(),
+            Block::List(ids) => {
+                Column::with_children(ids.iter().map(|id| text(self.label(id)).into()))
+                    .spacing(2)
+                    .into()
+            }
+            Block::Count(count) => text(count).size(40).into(),
+            Block::Table { columns, rows } => {
+                let cells = |cells: Vec<String>, size| {
+                    Row::with_children(
+                        cells
+                            .into_iter()
+                            .map(|cell| text(cell).size(size).width(Length::Fill).into()),
+                    )
+                    .spacing(10)
+                    .into()
+                };
+                let header = cells(columns.clone(), 14);
+                let rows = rows.iter().map(|(_, row)| cells(row.clone(), 16));
+                Column::with_children(std::iter::once(header).chain(rows))
+                    .spacing(2)
+                    .into()
+            }
+            Block::Facts(facts) => Column::with_children(facts.iter().map(|(predicate, datum)| {
+                text(format!("{}: {}", predicate, self.value_label(datum))).into()
+            }))
+            .spacing(2)
+            .into(),
+            Block::Column(blocks) => {
+                Column::with_children(blocks.iter().map(|b| self.view_block(b)))
+                    .spacing(6)
+                    .into()
+            }
+            Block::Row(blocks) => Row::with_children(
+                blocks
+                    .iter()
+                    .map(|b| container(self.view_block(b)).width(Length::Fill).into()),
+            )
+            .spacing(10)
+            .into(),
+            Block::Error(error) => text(error).into(),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! The script manager, which lists the scripts, see [`crate::script`], and
+//! runs the one chosen with the parameters typed. Scripts bound to keys run
+//! when they are pressed.
+
+use super::Editor;
+use crate::script::{self, Script};
+use iced::keyboard::{Key, Modifiers};
+use iced::widget::{button, column, container, scrollable, text, text_input, Column};
+use iced::{theme, Command, Element, Length};
+use std::path::Path;
+
+#[derive(Default)]
+pub struct Scripts {
+    open: bool,
+    scripts: Vec<Script>,
+    /// Why the files that couldn't be read were skipped.
+    errors: Vec<String>,
+    chosen: Option<usize>,
+    args: Vec<String>,
+    /// What the last script run printed, or why it failed.
+    output: Option<Result<String, String>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    /// Choose the script named so.
+    Choose(String),
+    /// Run the script named so, or choose it if it has parameters to type.
+    Invoke(String),
+    Arg(usize, String),
+    Run,
+    Close,
+}
+
+impl Scripts {
+    /// The scripts shipped and those in the `scripts` directory next to the
+    /// config.
+    pub(super) fn load(config_path: Option<&Path>) -> Scripts {
+        let dir = config_path.and_then(Path::parent);
+        let dir = dir.map(|dir| dir.join("scripts")).unwrap_or_default();
+        let (scripts, errors) = script::load(&dir);
+        Scripts {
+            scripts,
+            errors,
+            ..Scripts::default()
+        }
+    }
+
+    /// The names of the scripts whose name or description contains `query`.
+    pub(super) fn matching(&self, query: &str) -> Vec<String> {
+        let query = query.trim().to_lowercase();
+        self.scripts
+            .iter()
+            .filter(|s| {
+                s.name.to_lowercase().contains(&query)
+                    || s.description.to_lowercase().contains(&query)
+            })
+            .map(|s| s.name.clone())
+            .collect()
+    }
+
+    /// Opens the manager with the script named so chosen, and its
+    /// parameters empty.
+    fn choose(&mut self, name: &str) {
+        self.open = true;
+        self.chosen = self.scripts.iter().position(|s| s.name == name);
+        let params = self.chosen.map_or(0, |i| self.scripts[i].params.len());
+        self.args = vec![String::new(); params];
+        self.output = None;
+    }
+
+    /// The name of the script bound to a key press, if any.
+    pub(super) fn bound(&self, key: &Key, modifiers: Modifiers) -> Option<String> {
+        self.scripts
+            .iter()
+            .find(|s| s.key.as_ref().is_some_and(|k| k.matches(key, modifiers)))
+            .map(|s| s.name.clone())
+    }
+
+    /// The scripts bound to keys.
+    pub(super) fn bindings(&self) -> impl Iterator<Item = &Script> {
+        self.scripts.iter().filter(|s| s.key.is_some())
+    }
+}
+
+impl Editor {
+    pub(super) fn update_scripts(&mut self, message: Message) -> Command<super::Message> {
+        let state = &mut self.scripts;
+        match message {
+            Message::Open => {
+                // Read again, for the scripts written since.
+                *state = Scripts {
+                    open: true,
+                    ..Scripts::load(self.config_path.as_deref())
+                };
+            }
+            Message::Choose(name) => state.choose(&name),
+            Message::Invoke(name) => {
+                state.choose(&name);
+                let chosen = state.chosen.and_then(|i| state.scripts.get(i));
+                if chosen.is_some_and(|script| script.params.is_empty()) {
+                    return self.update_scripts(Message::Run);
+                }
+            }
+            Message::Arg(i, arg) => {
+                if let Some(slot) = state.args.get_mut(i) {
+                    *slot = arg;
+                }
+            }
+            Message::Run => {
+                if let Some(reason) = self.read_only_reason() {
+                    self.scripts.output = Some(Err(reason));
+                    return Command::none();
+                }
+                let state = &self.scripts;
+                let Some(script) = state.chosen.and_then(|i| state.scripts.get(i)) else {
+                    return Command::none();
+                };
+                match script.run(&state.args, &self.projection, &self.config.crdt) {
+                    Ok(ran) => {
+                        self.scripts.output = Some(Ok(ran.output));
+                        return self.record(ran.actions);
+                    }
+                    Err(e) => self.scripts.output = Some(Err(format!("{:#}", e))),
+                }
+            }
+            Message::Close => state.open = false,
+        }
+        Command::none()
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_scripts(&self) -> Option<Element<'_, super::Message>> {
+        let state = &self.scripts;
+        if !state.open {
+            return None;
+        }
+        let message = |m| super::Message::Scripts(m);
+        let mut list = Column::new().spacing(2);
+        for error in &state.errors {
+            list = list.push(text(error));
+        }
+        for (i, script) in state.scripts.iter().enumerate() {
+            let style = if state.chosen == Some(i) {
+                theme::Button::Primary
+            } else {
+                theme::Button::Text
+            };
+            let mut entry = column![text(&script.name)];
+            if !script.description.is_empty() {
+                entry = entry.push(text(&script.description).size(12));
+            }
+            list = list.push(
+                button(entry)
+                    .style(style)
+                    .width(Length::Fill)
+                    .on_press(message(Message::Choose(script.name.clone()))),
+            );
+        }
+
+        let mut dialog = column![
+            text(self.t("scripts")).size(30),
+            scrollable(list).height(Length::Fixed(200.0)),
+        ]
+        .spacing(10);
+        if let Some(script) = state.chosen.and_then(|i| state.scripts.get(i)) {
+            let mut params = Column::new().spacing(4);
+            for (i, (param, arg)) in script.params.iter().zip(&state.args).enumerate() {
+                params = params.push(
+                    text_input(param, arg)
+                        .on_input(move |arg| message(Message::Arg(i, arg)))
+                        .on_submit(message(Message::Run)),
+                );
+            }
+            dialog = dialog.push(params).push(
+                button(text(self.t("scripts-run"))).on_press_maybe(
+                    self.read_only_reason()
+                        .is_none()
+                        .then(|| message(Message::Run)),
+                ),
+            );
+        }
+        match &state.output {
+            Some(Ok(output)) if output.is_empty() => {
+                dialog = dialog.push(text(self.t("scripts-done")));
+            }
+            Some(Ok(output)) => {
+                dialog = dialog.push(scrollable(text(output)).height(Length::Fixed(200.0)));
+            }
+            Some(Err(error)) => dialog = dialog.push(text(error)),
+            None => {}
+        }
+        dialog = dialog.push(button(text(self.t("close"))).on_press(message(Message::Close)));
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,195 @@
+//! The jump to entity dialog, which ranks the entities matching what is typed,
+//! see [`crate::search`], and opens the one chosen in an inspector. Typing
+//! `>` first lists the scripts instead, see [`super::scripts`].
+
+use super::{scripts, Editor};
+use crate::search;
+use iced::widget::{button, column, container, text, text_input, Column};
+use iced::{theme, Command, Element, Length};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// How many results are listed.
+const LIMIT: usize = 12;
+/// How many entities jumped to are remembered.
+const JUMPLIST: usize = 20;
+
+#[derive(Default)]
+pub struct Search {
+    open: bool,
+    query: String,
+    /// The result highlighted, moved with the arrow keys.
+    selected: usize,
+    /// The entities jumped to, most recent first.
+    jumped: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Query(String),
+    Up,
+    Down,
+    /// Jump to the result highlighted.
+    Submit,
+    Jump(Uuid),
+    /// Run the script named so, see [`scripts::Message::Invoke`].
+    Script(String),
+    Close,
+}
+
+impl Search {
+    pub(super) fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+fn input_id() -> text_input::Id {
+    text_input::Id::new("search")
+}
+
+impl Editor {
+    pub(super) fn update_search(&mut self, message: Message) -> Command<super::Message> {
+        let state = &mut self.search;
+        match message {
+            Message::Open => {
+                state.open = true;
+                state.query.clear();
+                state.selected = 0;
+                return text_input::focus(input_id());
+            }
+            Message::Query(query) => {
+                state.query = query;
+                state.selected = 0;
+            }
+            Message::Up => state.selected = state.selected.saturating_sub(1),
+            Message::Down => {
+                let last = match self.script_query() {
+                    Some(query) => self.scripts.matching(query).len(),
+                    None => self.search_results().len(),
+                };
+                let last = last.saturating_sub(1);
+                self.search.selected = (self.search.selected + 1).min(last);
+            }
+            Message::Submit => {
+                if let Some(query) = self.script_query() {
+                    let scripts = self.scripts.matching(query);
+                    if let Some(name) = scripts.get(self.search.selected) {
+                        return self.update_search(Message::Script(name.clone()));
+                    }
+                    return Command::none();
+                }
+                let results = self.search_results();
+                if let Some((id, _)) = results.get(self.search.selected) {
+                    return self.update_search(Message::Jump(*id));
+                }
+            }
+            Message::Jump(id) => {
+                state.jumped.retain(|jumped| *jumped != id);
+                state.jumped.insert(0, id);
+                state.jumped.truncate(JUMPLIST);
+                state.open = false;
+                return self.inspect(id);
+            }
+            Message::Script(name) => {
+                state.open = false;
+                return self.update_scripts(scripts::Message::Invoke(name));
+            }
+            Message::Close => state.open = false,
+        }
+        Command::none()
+    }
+
+    /// What is typed after `>`, if scripts are searched for.
+    fn script_query(&self) -> Option<&str> {
+        self.search.query.trim_start().strip_prefix('>')
+    }
+
+    /// The entities matching the query with the value that matched, or if
+    /// nothing is typed, the entities jumped to and then those edited last.
+    fn search_results(&self) -> Vec<(Uuid, Option<String>)> {
+        let state = &self.search;
+        if !state.query.trim().is_empty() {
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            return search::search(&self.projection, &self.recency, &state.query, now, LIMIT)
+                .into_iter()
+                .map(|hit| (hit.id, Some(hit.matched)))
+                .collect();
+        }
+        let mut recent: Vec<Uuid> = Vec::new();
+        let jumped = state.jumped.iter().copied();
+        for id in jumped.chain(self.recency.latest(&self.projection, LIMIT)) {
+            if self.projection.contains(&id) && !recent.contains(&id) {
+                recent.push(id);
+            }
+        }
+        recent.truncate(LIMIT);
+        recent.into_iter().map(|id| (id, None)).collect()
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_search(&self) -> Option<Element<'_, super::Message>> {
+        let state = &self.search;
+        if !state.open {
+            return None;
+        }
+        let message = |m| super::Message::Search(m);
+        let input = text_input(&self.t("search-placeholder"), &state.query)
+            .id(input_id())
+            .on_input(move |q| message(Message::Query(q)))
+            .on_submit(message(Message::Submit));
+
+        let style = |i| {
+            if i == state.selected {
+                theme::Button::Primary
+            } else {
+                theme::Button::Text
+            }
+        };
+        let mut list = Column::new().spacing(2);
+        if let Some(query) = self.script_query() {
+            for (i, name) in self.scripts.matching(query).into_iter().enumerate() {
+                list = list.push(
+                    button(text(&name))
+                        .style(style(i))
+                        .width(Length::Fill)
+                        .on_press(message(Message::Script(name))),
+                );
+            }
+        }
+        let results = match self.script_query() {
+            Some(_) => Vec::new(),
+            None => self.search_results(),
+        };
+        if results.is_empty() && self.script_query().is_none() {
+            list = list.push(text(self.t("search-none")));
+        }
+        for (i, (id, matched)) in results.into_iter().enumerate() {
+            let label = self.label(&id);
+            let mut entry = column![text(&label)];
+            if let Some(matched) = matched.filter(|m| *m != label) {
+                entry = entry.push(text(matched).size(12));
+            }
+            list = list.push(
+                button(entry)
+                    .style(style(i))
+                    .width(Length::Fill)
+                    .on_press(message(Message::Jump(id))),
+            );
+        }
+
+        let dialog = column![
+            text(self.t("search")).size(30),
+            input,
+            list,
+            text(self.t("search-hint")).size(12),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,237 @@
+//! The settings screen, which edits and saves the [`Config`].
+
+use super::Editor;
+use crate::config::{Config, WindowMode};
+use crate::i18n::{self, Localizer};
+use crate::shortcuts::{self, Command as Bound};
+use iced::widget::{button, column, container, pick_list, row, text, text_input, Column};
+use iced::{Command, Element, Length};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Default)]
+pub struct Settings {
+    open: bool,
+    /// Whether the config has changes that are not saved yet.
+    dirty: bool,
+    /// The text of the fields that may not parse yet.
+    autosave_interval: String,
+    bindings: BTreeMap<Bound, String>,
+}
+
+/// A choice in a pick list, shown with a translated label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Labeled<T> {
+    pub(super) value: T,
+    pub(super) label: String,
+}
+
+impl<T: Clone + PartialEq> Labeled<T> {
+    pub(super) fn selected(choices: &[Labeled<T>], value: &T) -> Labeled<T> {
+        choices
+            .iter()
+            .find(|choice| &choice.value == value)
+            .unwrap_or(&choices[0])
+            .clone()
+    }
+}
+
+impl<T> std::fmt::Display for Labeled<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Close,
+    Theme(String),
+    WindowMode(WindowMode),
+    /// A language such as `de`, or `None` for the system's.
+    Language(Option<String>),
+    Database(String),
+    AutosaveInterval(String),
+    Binding(Bound, String),
+    Save,
+    /// Save the changes, if there are any.
+    Autosave,
+}
+
+impl Editor {
+    pub(super) fn update_settings(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => {
+                self.settings = Settings {
+                    open: true,
+                    dirty: self.settings.dirty,
+                    autosave_interval: self.config.autosave_interval.to_string(),
+                    bindings: self
+                        .config
+                        .keybindings
+                        .iter()
+                        .map(|(command, binding)| (*command, binding.to_string()))
+                        .collect(),
+                };
+            }
+            Message::Close => self.settings.open = false,
+            Message::Theme(theme) => {
+                self.edit_config(|c| c.theme = theme);
+                self.refresh_theme();
+            }
+            Message::WindowMode(mode) => {
+                self.edit_config(|c| c.window_mode = mode);
+                return super::apply_window_mode(&self.config);
+            }
+            Message::Language(language) => {
+                self.edit_config(|c| c.language = language);
+                self.i18n = super::localizer(&self.config);
+            }
+            Message::Database(path) => self.edit_config(|c| c.database = PathBuf::from(path)),
+            Message::AutosaveInterval(input) => {
+                if let Ok(seconds) = input.trim().parse() {
+                    self.edit_config(|c| c.autosave_interval = seconds);
+                }
+                self.settings.autosave_interval = input;
+            }
+            Message::Binding(command, input) => {
+                if let Ok(binding) = input.parse() {
+                    self.edit_config(|c| {
+                        c.keybindings.insert(command, binding);
+                    });
+                }
+                self.settings.bindings.insert(command, input);
+            }
+            Message::Save => self.save_config(),
+            Message::Autosave if self.settings.dirty => self.save_config(),
+            Message::Autosave => {}
+        }
+        Command::none()
+    }
+
+    pub(super) fn edit_config(&mut self, edit: impl FnOnce(&mut Config)) {
+        edit(&mut self.config);
+        self.settings.dirty = true;
+    }
+
+    pub(super) fn save_config(&mut self) {
+        let Some(path) = &self.config_path else {
+            self.error = Some(self.t("settings-no-config-dir"));
+            return;
+        };
+        match self.config.save(path) {
+            Ok(()) => self.settings.dirty = false,
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// The settings screen, if it is open.
+    pub(super) fn view_settings(&self) -> Option<Element<'_, super::Message>> {
+        if !self.settings.open {
+            return None;
+        }
+        let message = |m| super::Message::Settings(m);
+        let label = |id| text(self.t(id)).width(Length::Fixed(200.0));
+        let conflicts = shortcuts::conflicts(&self.config.keybindings);
+        let mut bindings = Column::new().spacing(4);
+        for (command, input) in &self.settings.bindings {
+            let command = *command;
+            let mut binding = row![
+                label(command.message_id()),
+                text_input("Ctrl+K", input)
+                    .on_input(move |i| message(Message::Binding(command, i)))
+                    .width(Length::Fixed(160.0)),
+            ]
+            .spacing(10);
+            let others: Vec<String> = conflicts
+                .iter()
+                .filter(|(_, commands)| commands.contains(&command))
+                .flat_map(|(_, commands)| commands)
+                .filter(|other| **other != command)
+                .map(|other| self.t(other.message_id()))
+                .collect();
+            if !others.is_empty() {
+                binding = binding.push(text(self.tr(
+                    "settings-binding-conflict",
+                    &[("commands", others.join(", ").into())],
+                )));
+            }
+            bindings = bindings.push(binding);
+        }
+
+        let languages: Vec<Labeled<Option<String>>> = std::iter::once(Labeled {
+            value: None,
+            label: self.t("settings-language-system"),
+        })
+        .chain(i18n::languages().map(|language| Labeled {
+            value: Some(language.to_string()),
+            label: Localizer::new(language).text("language-name"),
+        }))
+        .collect();
+        let language = Labeled::selected(&languages, &self.config.language);
+        let modes: Vec<Labeled<WindowMode>> = WindowMode::ALL
+            .into_iter()
+            .map(|mode| Labeled {
+                value: mode,
+                label: self.t(mode.message_id()),
+            })
+            .collect();
+        let mode = Labeled::selected(&modes, &self.config.window_mode);
+
+        let form = column![
+            text(self.t("settings")).size(30),
+            row![
+                label("settings-theme"),
+                pick_list(
+                    crate::theme::names(&self.config.palettes, &self.projection),
+                    Some(self.config.theme.clone()),
+                    move |t| message(Message::Theme(t)),
+                ),
+            ],
+            row![
+                label("settings-language"),
+                pick_list(languages, Some(language), move |l| {
+                    message(Message::Language(l.value))
+                }),
+            ],
+            row![
+                label("settings-window"),
+                pick_list(modes, Some(mode), move |m| message(Message::WindowMode(
+                    m.value
+                ))),
+            ],
+            row![
+                label("settings-database"),
+                text_input("graphite.db", &self.config.database.to_string_lossy())
+                    .on_input(move |p| message(Message::Database(p))),
+            ],
+            row![
+                label("settings-data-dir"),
+                text(self.data_dir_label()).width(Length::Fill),
+                button(text(self.t("settings-data-dir-change")))
+                    .on_press(super::Message::DataDir(super::data_dir::Message::Open)),
+            ]
+            .spacing(10),
+            row![
+                label("settings-autosave"),
+                text_input("30", &self.settings.autosave_interval)
+                    .on_input(move |i| message(Message::AutosaveInterval(i)))
+                    .width(Length::Fixed(80.0)),
+            ],
+            text(self.t("settings-keybindings")).size(20),
+            bindings,
+            row![
+                button(text(self.t("save"))).on_press(message(Message::Save)),
+                button(text(self.t("close"))).on_press(message(Message::Close)),
+            ]
+            .spacing(10),
+        ]
+        .spacing(10);
+        Some(
+            container(form)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,133 @@
+//! Staging mode, in which edits are held back from the event log until they
+//! are committed, see [`crate::staging`]. Staged entities and facts are drawn
+//! in the primary color.
+
+use super::Editor;
+use crate::legacy::storage::{Action, Event};
+use crate::staging::Stage;
+use iced::widget::{button, container, row, text};
+use iced::{theme, Command, Element};
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct Staging {
+    /// Whether edits are staged rather than recorded.
+    on: bool,
+    stage: Stage,
+}
+
+impl Staging {
+    /// Applies an event recorded meanwhile to the graph without the staged
+    /// edits.
+    pub(super) fn apply_event(&mut self, event: &Event) {
+        self.stage.apply_event(event);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Toggle,
+    /// Record the staged edits as one transaction.
+    Commit,
+    Discard,
+}
+
+impl Editor {
+    pub(super) fn update_staging(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Toggle => self.staging.on = !self.staging.on,
+            Message::Commit => {
+                if let Some(error) = self.read_only_reason() {
+                    self.error = Some(error);
+                    return Command::none();
+                }
+                let actions = self.unstage();
+                return self.record_now(actions);
+            }
+            Message::Discard => {
+                self.unstage();
+            }
+        }
+        Command::none()
+    }
+
+    /// Whether edits are staged instead of recorded now.
+    pub(super) fn is_staging(&self) -> bool {
+        self.staging.on && self.read_only_reason().is_none()
+    }
+
+    /// Stages `actions`, showing them in the graph.
+    pub(super) fn stage(&mut self, actions: Vec<Action>) {
+        let changed: Vec<Uuid> = actions.iter().flat_map(Action::subjects).collect();
+        let mut stage = std::mem::take(&mut self.staging.stage);
+        stage.stage(self.live_projection(), actions);
+        self.staging.stage = stage;
+        self.rollups.update(&self.projection, &changed);
+        self.refresh_validation(Some(&changed));
+        self.retain_selections(Some(&changed));
+        self.refresh_hygiene();
+        self.refresh_canvas();
+        self.refresh_dedupe();
+    }
+
+    /// Stages the edits again on top of the graph just reloaded.
+    pub(super) fn restage(&mut self) {
+        let mut stage = std::mem::take(&mut self.staging.stage);
+        stage.reload(self.live_projection());
+        self.staging.stage = stage;
+    }
+
+    /// Takes the staged actions out of the graph shown.
+    fn unstage(&mut self) -> Vec<Action> {
+        let mut stage = std::mem::take(&mut self.staging.stage);
+        let actions = stage.take(self.live_projection());
+        self.rollups.rebuild(&self.projection);
+        self.refresh_validation(None);
+        self.retain_selections(None);
+        self.refresh_hygiene();
+        self.refresh_canvas();
+        self.refresh_dedupe();
+        actions
+    }
+
+    /// The style of an entity, or of its `predicate` if given, if it has
+    /// staged changes.
+    pub(super) fn staged_style(&self, id: &Uuid, predicate: Option<&str>) -> Option<theme::Text> {
+        self.staging
+            .stage
+            .touches(id, predicate)
+            .then(|| theme::Text::Color(self.theme.palette().primary))
+    }
+
+    /// A banner while staging or while edits are staged.
+    pub(super) fn view_staging(&self) -> Option<Element<'_, super::Message>> {
+        let staged = self.staging.stage.len();
+        if !self.staging.on && staged == 0 {
+            return None;
+        }
+        let message = |m| super::Message::Staging(m);
+        let toggle = if self.staging.on {
+            self.t("staging-stop")
+        } else {
+            self.t("staging-start")
+        };
+        let pending = (staged > 0).then(|| message(Message::Commit));
+        Some(
+            container(
+                row![
+                    text(self.tr("staging-count", &[("count", staged.into())])),
+                    button(text(self.t("staging-commit"))).on_press_maybe(pending.clone()),
+                    button(text(self.t("staging-discard")))
+                        .style(theme::Button::Destructive)
+                        .on_press_maybe(pending.map(|_| message(Message::Discard))),
+                    button(text(toggle)).on_press(message(Message::Toggle)),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center),
+            )
+            .padding(10)
+            .style(iced::theme::Container::Box)
+            .into(),
+        )
+    }
+}
@@ -0,0 +1,196 @@
+//! The table view, which lists the entities matching a query with a column
+//! per predicate and edits their cells, see [`crate::table`].
+
+use super::Editor;
+use crate::query::Query;
+use crate::table::{self, Sort};
+use iced::widget::{button, column, container, row, scrollable, text, text_input, Column, Row};
+use iced::{theme, Command, Element, Length};
+use uuid::Uuid;
+
+/// How many rows are shown.
+const LIMIT: usize = 200;
+const CELL_WIDTH: f32 = 140.0;
+
+#[derive(Default)]
+pub struct Table {
+    open: bool,
+    filter: String,
+    /// Columns added that no entity has a value of yet.
+    extra: Vec<String>,
+    new_column: String,
+    sort: Sort,
+    /// The cell being edited and the text typed.
+    editing: Option<(Uuid, String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Filter(String),
+    /// Sort by a column, or reverse the order if it is sorted by already.
+    Sort(String),
+    NewColumn(String),
+    AddColumn,
+    Edit(Uuid, String),
+    Input(String),
+    Commit,
+    Cancel,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_table(&mut self, message: Message) -> Command<super::Message> {
+        let state = &mut self.table;
+        match message {
+            Message::Open => state.open = true,
+            Message::Filter(filter) => state.filter = filter,
+            Message::Sort(column) => {
+                state.sort = Sort {
+                    descending: state.sort.column == column && !state.sort.descending,
+                    column,
+                }
+            }
+            Message::NewColumn(column) => state.new_column = column,
+            Message::AddColumn => {
+                let column = std::mem::take(&mut state.new_column).trim().to_string();
+                if !column.is_empty() && !state.extra.contains(&column) {
+                    state.extra.push(column);
+                }
+            }
+            Message::Edit(id, column) => {
+                let input = self
+                    .projection
+                    .entity(&id)
+                    .and_then(|e| e.value(&column))
+                    .map(|datum| self.value_label(datum))
+                    .unwrap_or_default();
+                self.table.editing = Some((id, column, input));
+            }
+            Message::Input(input) => {
+                if let Some((_, _, typed)) = &mut state.editing {
+                    *typed = input;
+                }
+            }
+            Message::Commit => {
+                if let Some((id, column, input)) = state.editing.take() {
+                    let actions = table::edit(&self.projection, id, &column, &input);
+                    if !actions.is_empty() {
+                        return self.record(actions);
+                    }
+                }
+            }
+            Message::Cancel => state.editing = None,
+            Message::Close => *state = Table::default(),
+        }
+        Command::none()
+    }
+
+    /// The table, if it is open.
+    pub(super) fn view_table(&self) -> Option<Element<'_, super::Message>> {
+        let state = &self.table;
+        if !state.open {
+            return None;
+        }
+        let message = |m| super::Message::Table(m);
+        let filter = text_input(&self.t("table-filter"), &state.filter)
+            .on_input(move |f| message(Message::Filter(f)));
+        let add_column = row![
+            text_input(&self.t("table-column"), &state.new_column)
+                .on_input(move |c| message(Message::NewColumn(c)))
+                .on_submit(message(Message::AddColumn)),
+            button(text(self.t("table-add-column"))).on_press(message(Message::AddColumn)),
+        ]
+        .spacing(10);
+
+        let content: Element<'_, super::Message> = match state.filter.parse::<Query>() {
+            Err(error) => text(format!("{:#}", error)).into(),
+            Ok(query) => {
+                let table = table::table(&self.projection, &query, &state.extra, &state.sort);
+                let mut header = Row::new().spacing(4);
+                for column in &table.columns {
+                    let arrow = match &state.sort {
+                        sort if sort.column != *column => "",
+                        sort if sort.descending => " ▼",
+                        _ => " ▲",
+                    };
+                    header = header.push(
+                        button(text(format!("{}{}", column, arrow)).size(14))
+                            .style(theme::Button::Text)
+                            .width(Length::Fixed(CELL_WIDTH))
+                            .on_press(message(Message::Sort(column.clone()))),
+                    );
+                }
+                let mut rows = Column::new().spacing(2).push(header);
+                for id in table.rows.iter().take(LIMIT) {
+                    rows = rows.push(self.view_table_row(id, &table.columns));
+                }
+                if table.rows.len() > LIMIT {
+                    let more = table.rows.len() - LIMIT;
+                    rows = rows.push(text(self.tr("hygiene-more", &[("count", more.into())])));
+                }
+                scrollable(rows)
+                    .direction(scrollable::Direction::Both {
+                        vertical: scrollable::Properties::default(),
+                        horizontal: scrollable::Properties::default(),
+                    })
+                    .height(Length::Fixed(400.0))
+                    .into()
+            }
+        };
+
+        let dialog = column![
+            text(self.t("table")).size(30),
+            filter,
+            add_column,
+            content,
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    fn view_table_row(&self, id: &Uuid, columns: &[String]) -> Element<'_, super::Message> {
+        let message = |m| super::Message::Table(m);
+        let Some(entity) = self.projection.entity(id) else {
+            return Row::new().into();
+        };
+        let mut cells = Row::new().spacing(4);
+        for column in columns {
+            let cell: Element<'_, super::Message> = match &self.table.editing {
+                Some((editing, c, input)) if editing == id && c == column => row![
+                    text_input(column, input)
+                        .on_input(move |i| message(Message::Input(i)))
+                        .on_submit(message(Message::Commit)),
+                    button(text("✕"))
+                        .style(theme::Button::Text)
+                        .on_press(message(Message::Cancel)),
+                ]
+                .width(Length::Fixed(CELL_WIDTH))
+                .into(),
+                _ => {
+                    let values: Vec<String> = entity
+                        .values(column)
+                        .iter()
+                        .map(|datum| self.value_label(datum))
+                        .collect();
+                    let editable = !self.read_only && table::editable(entity, column);
+                    button(text(values.join(", ")))
+                        .style(theme::Button::Text)
+                        .width(Length::Fixed(CELL_WIDTH))
+                        .on_press_maybe(
+                            editable.then(|| message(Message::Edit(*id, column.clone()))),
+                        )
+                        .into()
+                }
+            };
+            cells = cells.push(cell);
+        }
+        cells.into()
+    }
+}
@@ -0,0 +1,118 @@
+//! Quick task entry and the "My tasks" view.
+
+use super::Editor;
+use crate::journal;
+use crate::legacy::storage::Datum;
+use crate::rollup::Aggregate;
+use crate::tasks;
+use iced::widget::{checkbox, column, row, text, text_input, Column};
+use iced::{Command, Element};
+use time::{OffsetDateTime, UtcOffset};
+use uuid::Uuid;
+
+pub struct Tasks {
+    /// The text in the quick entry field.
+    input: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Input(String),
+    /// Create a task from the quick entry field.
+    Add,
+    Complete(Uuid),
+}
+
+impl Tasks {
+    pub fn new() -> Tasks {
+        Tasks {
+            input: String::new(),
+        }
+    }
+}
+
+impl Editor {
+    pub(super) fn update_tasks(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Input(input) => {
+                self.tasks.input = input;
+                Command::none()
+            }
+            Message::Add => {
+                let me = self.actor();
+                match tasks::quick_add(&self.projection, &self.tasks.input, me, journal::today()) {
+                    Ok((_, actions)) => {
+                        self.tasks.input.clear();
+                        self.error = None;
+                        self.record(actions)
+                    }
+                    Err(error) => {
+                        self.error = Some(format!("{:#}", error));
+                        Command::none()
+                    }
+                }
+            }
+            Message::Complete(task) => {
+                let now = OffsetDateTime::now_utc().to_offset(journal::local_offset());
+                let actions = tasks::complete(&self.projection, task, now);
+                self.record(actions)
+            }
+        }
+    }
+
+    pub(super) fn view_tasks(&self) -> Element<'_, super::Message> {
+        let message = |m| super::Message::Tasks(m);
+        let entry = text_input(&self.t("tasks-placeholder"), &self.tasks.input)
+            .on_input(move |input| message(Message::Input(input)))
+            .on_submit(message(Message::Add));
+
+        let mut list = Column::new().spacing(4);
+        let groups = tasks::my_tasks(&self.projection, self.actor(), journal::today());
+        if groups.is_empty() {
+            list = list.push(text(self.t("tasks-empty")));
+        }
+        for (bucket, ids) in groups {
+            list = list.push(text(self.t(bucket.message_id())).size(20));
+            for id in ids {
+                list = list.push(
+                    row![
+                        checkbox(self.label(&id), false)
+                            .on_toggle(move |_| message(Message::Complete(id))),
+                        self.start_timer_button(id),
+                        text(self.rollup_summary(&id)),
+                    ]
+                    .spacing(10),
+                );
+            }
+        }
+
+        column![text(self.t("tasks")).size(30), entry, list]
+            .spacing(10)
+            .into()
+    }
+
+    /// The rollups of a task's subtasks, e.g. `done 50% · estimate 3`.
+    fn rollup_summary(&self, id: &Uuid) -> String {
+        let Some(values) = self.rollups.of(id) else {
+            return String::new();
+        };
+        self.config
+            .rollups
+            .iter()
+            .filter_map(|definition| {
+                let value = match (&definition.aggregate, values.get(&definition.name)?) {
+                    (Aggregate::Percent { .. }, Datum::Integer(percent)) => format!("{}%", percent),
+                    (_, Datum::Integer(n)) => n.to_string(),
+                    (_, Datum::Float(n)) => format!("{:.1}", n),
+                    (_, Datum::String(s)) => s.clone(),
+                    (_, Datum::DateTime(t)) => OffsetDateTime::from_unix_timestamp(*t)
+                        .map(|t| t.to_offset(UtcOffset::UTC).date().to_string())
+                        .unwrap_or_default(),
+                    (_, Datum::Boolean(_) | Datum::Entity(_)) => return None,
+                };
+                Some(format!("{} {}", definition.name, value))
+            })
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+}
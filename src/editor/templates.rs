@@ -0,0 +1,239 @@
+//! The new entity dialog, which creates entities from templates, and the
+//! template editor, see [`crate::template`].
+
+use super::Editor;
+use crate::legacy::storage::Action;
+use crate::template::{self, Template};
+use iced::widget::{button, column, container, row, scrollable, text, text_input, Column};
+use iced::{theme, Command, Element, Length};
+
+#[derive(Default)]
+pub struct Templates {
+    open: bool,
+    /// The template picked for a new entity and the value of each field.
+    form: Option<(Template, Vec<String>)>,
+    /// The template being edited.
+    editing: Option<Template>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    /// Fill in the fields of a template for a new entity.
+    New(Template),
+    Value(usize, String),
+    Create,
+    /// Edit a template, or a new one.
+    Edit(Option<Template>),
+    Name(String),
+    Kind(String),
+    Field(usize, String),
+    AddField,
+    RemoveField(usize),
+    Save,
+    Delete,
+    /// Back to the list of templates.
+    Back,
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_templates(&mut self, message: Message) -> Command<super::Message> {
+        let state = &mut self.templates;
+        match message {
+            Message::Open => state.open = true,
+            Message::New(template) => {
+                let values = vec![String::new(); template.fields.len()];
+                state.form = Some((template, values));
+            }
+            Message::Value(i, value) => {
+                if let Some(field) = state.form.as_mut().and_then(|(_, v)| v.get_mut(i)) {
+                    *field = value;
+                }
+            }
+            Message::Create => {
+                if let Some((template, values)) = state.form.take() {
+                    let (_, actions) = template.instantiate(&values);
+                    return self.record(actions);
+                }
+            }
+            Message::Edit(template) => state.editing = Some(template.unwrap_or_default()),
+            Message::Name(name) => {
+                if let Some(template) = &mut state.editing {
+                    template.name = name;
+                }
+            }
+            Message::Kind(kind) => {
+                if let Some(template) = &mut state.editing {
+                    template.kind = Some(kind);
+                }
+            }
+            Message::Field(i, field) => {
+                if let Some(f) = state.editing.as_mut().and_then(|t| t.fields.get_mut(i)) {
+                    *f = field;
+                }
+            }
+            Message::AddField => {
+                if let Some(template) = &mut state.editing {
+                    template.fields.push(String::new());
+                }
+            }
+            Message::RemoveField(i) => {
+                if let Some(template) = &mut state.editing {
+                    if i < template.fields.len() {
+                        template.fields.remove(i);
+                    }
+                }
+            }
+            Message::Save => {
+                if let Some(template) = state.editing.take() {
+                    return self.record(template.save());
+                }
+            }
+            Message::Delete => {
+                if let Some(id) = state.editing.take().and_then(|t| t.id) {
+                    return self.record(vec![Action::DeleteEntity { id }]);
+                }
+            }
+            Message::Back => {
+                state.form = None;
+                state.editing = None;
+            }
+            Message::Close => *state = Templates::default(),
+        }
+        Command::none()
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_templates(&self) -> Option<Element<'_, super::Message>> {
+        if !self.templates.open {
+            return None;
+        }
+        let message = |m| super::Message::Templates(m);
+        let content = if let Some((template, values)) = &self.templates.form {
+            self.view_template_form(template, values)
+        } else if let Some(template) = &self.templates.editing {
+            self.view_template_editor(template)
+        } else {
+            let mut list = Column::new().spacing(6);
+            let templates = template::templates(&self.projection);
+            if templates.is_empty() {
+                list = list.push(text(self.t("templates-empty")));
+            }
+            for template in templates {
+                list = list.push(
+                    row![
+                        button(text(&template.name))
+                            .width(Length::Fill)
+                            .on_press_maybe(
+                                (!self.read_only).then(|| message(Message::New(template.clone())))
+                            ),
+                        button(text(self.t("templates-edit")))
+                            .style(theme::Button::Secondary)
+                            .on_press_maybe(
+                                (!self.read_only).then(|| message(Message::Edit(Some(template))))
+                            ),
+                    ]
+                    .spacing(10),
+                );
+            }
+            column![
+                scrollable(list).height(Length::Fixed(300.0)),
+                button(text(self.t("templates-new-template")))
+                    .on_press_maybe((!self.read_only).then(|| message(Message::Edit(None)))),
+            ]
+            .spacing(10)
+            .into()
+        };
+
+        let dialog = column![
+            text(self.t("templates")).size(30),
+            content,
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+
+    /// The fields of a new entity.
+    fn view_template_form<'a>(
+        &'a self,
+        template: &'a Template,
+        values: &'a [String],
+    ) -> Element<'a, super::Message> {
+        let message = |m| super::Message::Templates(m);
+        let mut form = Column::new().spacing(6).push(
+            text(self.tr("templates-new", &[("name", template.name.as_str().into())])).size(20),
+        );
+        for (i, (field, value)) in template.fields.iter().zip(values).enumerate() {
+            form = form.push(
+                row![
+                    text(field).width(Length::Fixed(140.0)),
+                    text_input(field, value)
+                        .on_input(move |v| message(Message::Value(i, v)))
+                        .on_submit(message(Message::Create)),
+                ]
+                .spacing(10),
+            );
+        }
+        form.push(
+            row![
+                button(text(self.t("templates-create"))).on_press(message(Message::Create)),
+                button(text(self.t("cancel"))).on_press(message(Message::Back)),
+            ]
+            .spacing(10),
+        )
+        .into()
+    }
+
+    /// The template editor.
+    fn view_template_editor<'a>(&'a self, template: &'a Template) -> Element<'a, super::Message> {
+        let message = |m| super::Message::Templates(m);
+        let mut fields = Column::new().spacing(6);
+        for (i, field) in template.fields.iter().enumerate() {
+            fields = fields.push(
+                row![
+                    text_input(&self.t("templates-field"), field)
+                        .on_input(move |f| message(Message::Field(i, f))),
+                    button(text("✕"))
+                        .style(theme::Button::Text)
+                        .on_press(message(Message::RemoveField(i))),
+                ]
+                .spacing(10),
+            );
+        }
+        let mut actions = row![
+            button(text(self.t("save")))
+                .on_press_maybe((!template.name.trim().is_empty()).then(|| message(Message::Save))),
+            button(text(self.t("cancel"))).on_press(message(Message::Back)),
+        ]
+        .spacing(10);
+        if template.id.is_some() {
+            actions = actions.push(
+                button(text(self.t("templates-delete")))
+                    .style(theme::Button::Destructive)
+                    .on_press(message(Message::Delete)),
+            );
+        }
+        column![
+            text_input(&self.t("templates-name"), &template.name)
+                .on_input(move |n| message(Message::Name(n))),
+            text_input(
+                &self.t("templates-type"),
+                template.kind.as_deref().unwrap_or("")
+            )
+            .on_input(move |k| message(Message::Kind(k))),
+            text(self.t("templates-fields")),
+            fields,
+            button(text(self.t("templates-add-field"))).on_press(message(Message::AddField)),
+            actions,
+        ]
+        .spacing(10)
+        .into()
+    }
+}
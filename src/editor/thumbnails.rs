@@ -0,0 +1,114 @@
+//! Loads the thumbnails of the images of the entities shown in inspectors,
+//! and of the nodes the canvas draws large enough, in the background, see
+//! [`crate::thumbnail`]. Inspectors draw them with plain widgets, a cell per
+//! color, and the canvas as quads inside the nodes.
+
+use super::Editor;
+use crate::legacy::projection::Entity;
+use crate::thumbnail::{self, Thumbnail};
+use iced::widget::{container, Column, Row};
+use iced::{Background, Color, Command, Element, Length};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// The thumbnails by source, kept for as long as the editor runs.
+#[derive(Default)]
+pub struct Thumbnails {
+    cache: HashMap<String, Load>,
+}
+
+enum Load {
+    Loading,
+    Loaded(Arc<Thumbnail>),
+    /// Not tried again, the error is logged.
+    Failed,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Loaded(String, Result<Arc<Thumbnail>, String>),
+}
+
+impl Thumbnails {
+    /// The thumbnail of the image of `entity`, if it is loaded.
+    pub(super) fn get(&self, entity: &Entity) -> Option<&Arc<Thumbnail>> {
+        match self.cache.get(thumbnail::source(entity)?) {
+            Some(Load::Loaded(thumbnail)) => Some(thumbnail),
+            _ => None,
+        }
+    }
+}
+
+impl Editor {
+    pub(super) fn update_thumbnails(&mut self, message: Message) -> Command<super::Message> {
+        let Message::Loaded(source, result) = message;
+        let load = match result {
+            Ok(thumbnail) => Load::Loaded(thumbnail),
+            Err(error) => {
+                tracing::warn!("{}", error);
+                Load::Failed
+            }
+        };
+        self.thumbnails.cache.insert(source, load);
+        Command::none()
+    }
+
+    /// Starts loading the images of the entities listed in inspectors or
+    /// drawn with a thumbnail on the canvas that were not loaded yet.
+    pub(super) fn load_thumbnails(&mut self) -> Command<super::Message> {
+        let mut loads = Vec::new();
+        let shown = self
+            .inspected()
+            .into_iter()
+            .chain(self.canvas_thumbnailed());
+        for id in shown {
+            let Some(source) = self.projection.entity(&id).and_then(thumbnail::source) else {
+                continue;
+            };
+            if self.thumbnails.cache.contains_key(source) {
+                continue;
+            }
+            let source = source.to_string();
+            self.thumbnails.cache.insert(source.clone(), Load::Loading);
+            let load = tokio::task::spawn_blocking({
+                let source = source.clone();
+                move || thumbnail::load(&source).map_err(|e| format!("{:#}", e))
+            });
+            loads.push(Command::perform(load, move |thumbnail| {
+                let thumbnail = thumbnail.unwrap_or_else(|e| Err(e.to_string()));
+                super::Message::Thumbnails(Message::Loaded(source, thumbnail.map(Arc::new)))
+            }));
+        }
+        Command::batch(loads)
+    }
+
+    /// The thumbnail of the image of `id` if it is loaded, drawn with cells
+    /// `cell` pixels wide.
+    pub(super) fn view_thumbnail(
+        &self,
+        id: &Uuid,
+        cell: f32,
+    ) -> Option<Element<'_, super::Message>> {
+        let thumbnail = self.thumbnails.get(self.projection.entity(id)?)?;
+        let mut rows = Column::new();
+        for y in 0..thumbnail.height {
+            let mut cells = Row::new();
+            for x in 0..thumbnail.width {
+                let [r, g, b] = thumbnail.pixel(x, y);
+                let color = Color::from_rgb8(r, g, b);
+                cells = cells.push(
+                    container(Row::new())
+                        .width(Length::Fixed(cell))
+                        .height(Length::Fixed(cell))
+                        .style(container::Appearance {
+                            background: Some(Background::Color(color)),
+                            ..container::Appearance::default()
+                        }),
+                );
+            }
+            rows = rows.push(cells);
+        }
+        Some(rows.into())
+    }
+}
@@ -0,0 +1,73 @@
+//! Timers and the status bar that shows the running one.
+
+use super::Editor;
+use crate::journal;
+use crate::timer;
+use iced::widget::{button, row, text, Row};
+use iced::{theme, Command, Element};
+use time::{OffsetDateTime, Time};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Start a timer on an entity, stopping the running one.
+    Start(Uuid),
+    Stop,
+}
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+impl Editor {
+    pub(super) fn update_timer(&mut self, message: Message) -> Command<super::Message> {
+        let me = self.actor();
+        let actions = match message {
+            Message::Start(entity) => timer::start(&self.projection, entity, me, now()),
+            Message::Stop => timer::stop(&self.projection, me, now()),
+        };
+        self.record(actions)
+    }
+
+    /// A button that starts a timer on `entity`.
+    pub(super) fn start_timer_button(&self, entity: Uuid) -> Element<'_, super::Message> {
+        button("▶")
+            .style(theme::Button::Text)
+            .on_press(super::Message::Timer(Message::Start(entity)))
+            .into()
+    }
+
+    pub(super) fn view_status_bar(&self) -> Element<'_, super::Message> {
+        let offset = journal::local_offset();
+        let (start, end) = journal::day_bounds(journal::today(), offset);
+        let today: i64 = timer::by_entity(&self.projection, start, end, now())
+            .values()
+            .sum();
+        let mut bar: Row<'_, super::Message> = row![].spacing(10);
+        if let Some(entry) = timer::running(&self.projection, self.actor()) {
+            let since = OffsetDateTime::from_unix_timestamp(entry.start)
+                .map(|t| t.to_offset(offset).time())
+                .unwrap_or(Time::MIDNIGHT);
+            bar = bar
+                .push(text(self.tr(
+                    "timer-tracking",
+                    &[
+                        ("entity", self.label(&entry.entity).into()),
+                        (
+                            "since",
+                            format!("{:02}:{:02}", since.hour(), since.minute()).into(),
+                        ),
+                    ],
+                )))
+                .push(
+                    button(text(self.t("timer-stop")))
+                        .on_press(super::Message::Timer(Message::Stop)),
+                );
+        }
+        bar.push(text(self.tr(
+            "timer-today",
+            &[("duration", timer::format_duration(today).into())],
+        )))
+        .into()
+    }
+}
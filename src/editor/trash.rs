@@ -0,0 +1,81 @@
+//! The trash dialog, listing deleted entities that can still be restored,
+//! see [`crate::legacy::projection::Projection`].
+
+use super::Editor;
+use crate::legacy::storage::{Action, Datum};
+use iced::widget::{button, column, container, row, scrollable, text, Column};
+use iced::{Command, Element, Length};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Restore(Uuid),
+    Close,
+}
+
+impl Editor {
+    pub(super) fn update_trash(&mut self, message: Message) -> Command<super::Message> {
+        match message {
+            Message::Open => self.trash = true,
+            Message::Restore(id) => return self.record(vec![Action::RestoreEntity { id }]),
+            Message::Close => self.trash = false,
+        }
+        Command::none()
+    }
+
+    /// The dialog, if it is open.
+    pub(super) fn view_trash(&self) -> Option<Element<'_, super::Message>> {
+        if !self.trash {
+            return None;
+        }
+        let message = |m| super::Message::Trash(m);
+        let now = ::time::OffsetDateTime::now_utc().unix_timestamp();
+        let mut trashed: Vec<_> = self.projection.trash(now).collect();
+        // Most recently deleted first.
+        trashed.sort_by_key(|(_, trashed)| std::cmp::Reverse(trashed.deleted));
+
+        let mut list = Column::new().spacing(4);
+        if trashed.is_empty() {
+            list = list.push(text(self.t("trash-empty")));
+        }
+        for (id, trashed) in trashed {
+            let name = ["name", "title"]
+                .iter()
+                .find_map(|p| match trashed.entity.value(p) {
+                    Some(Datum::String(name)) => Some(name.clone()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| id.to_string());
+            let deleted = trashed
+                .deleted
+                .map(|deleted| self.local_time(deleted))
+                .unwrap_or_default();
+            list = list.push(
+                row![
+                    text(name).width(Length::Fill),
+                    text(deleted),
+                    button(text(self.t("trash-restore"))).on_press_maybe(
+                        (!self.read_only).then_some(message(Message::Restore(*id)))
+                    ),
+                ]
+                .spacing(10),
+            );
+        }
+
+        let days = self.projection.trash_window() / (24 * 60 * 60);
+        let dialog = column![
+            text(self.t("trash")).size(30),
+            text(self.tr("trash-window", &[("days", days.into())])),
+            scrollable(list).height(Length::Fixed(400.0)),
+            button(text(self.t("close"))).on_press(message(Message::Close)),
+        ]
+        .spacing(10);
+        Some(
+            container(dialog)
+                .padding(20)
+                .style(iced::theme::Container::Box)
+                .into(),
+        )
+    }
+}
@@ -0,0 +1,72 @@
+//! Badges and lists of the constraints entities break, see
+//! [`crate::validation`].
+
+use super::Editor;
+use crate::validation::{Rule, Validator, Violation};
+use iced::widget::{container, text, Column};
+use iced::{theme, Element};
+use uuid::Uuid;
+
+impl Editor {
+    /// Validates the `changed` entities again, or the whole graph.
+    pub(super) fn refresh_validation(&mut self, changed: Option<&[Uuid]>) {
+        match changed {
+            Some(changed) => self.validator.update(&self.projection, changed),
+            None => self.validator = Validator::new(&self.projection),
+        }
+    }
+
+    /// How many entities break a constraint.
+    pub(super) fn view_violation_count(&self) -> Option<Element<'_, super::Message>> {
+        let count = self.validator.violations().len();
+        (count > 0).then(|| {
+            text(self.tr("validation-count", &[("count", count.into())]))
+                .style(theme::Text::Color(self.theme.palette().danger))
+                .into()
+        })
+    }
+
+    /// A badge with the number of constraints `id` breaks, if any.
+    pub(super) fn view_violation_badge(&self, id: &Uuid) -> Option<Element<'_, super::Message>> {
+        let count = self.validator.violations().get(id)?.len();
+        Some(
+            container(text(format!("⚠ {}", count)).size(12))
+                .padding([0, 4])
+                .style(theme::Container::Box)
+                .into(),
+        )
+    }
+
+    /// The constraints `id` breaks, one per line.
+    pub(super) fn view_violations(&self, id: &Uuid) -> Option<Element<'_, super::Message>> {
+        let violations = self.validator.violations().get(id)?;
+        let danger = self.theme.palette().danger;
+        let mut list = Column::new().spacing(2);
+        for violation in violations {
+            list =
+                list.push(text(self.violation_text(violation)).style(theme::Text::Color(danger)));
+        }
+        Some(list.into())
+    }
+
+    fn violation_text(&self, violation: &Violation) -> String {
+        let predicate = ("predicate", violation.predicate.as_str().into());
+        match &violation.rule {
+            Rule::Required => self.tr("validation-required", &[predicate]),
+            Rule::Min(min) => self.tr("validation-min", &[predicate, ("min", (*min).into())]),
+            Rule::Max(max) => self.tr("validation-max", &[predicate, ("max", (*max).into())]),
+            Rule::Pattern(pattern) => self.tr(
+                "validation-pattern",
+                &[predicate, ("pattern", pattern.as_str().into())],
+            ),
+            Rule::Unique(other) => self.tr(
+                "validation-unique",
+                &[predicate, ("other", self.label(other).into())],
+            ),
+            Rule::InvalidPattern(pattern) => self.tr(
+                "validation-invalid-pattern",
+                &[("pattern", pattern.as_str().into())],
+            ),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Exporters that render the graph, or part of it, for use outside Graphite.
+
+pub mod explorer;
+pub mod jsonld;
+pub mod pdf;
+pub mod rdf;
+pub mod site;
+pub mod text;
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// The name or title of an entity, or its id.
+pub(crate) fn label(projection: &Projection, id: &Uuid) -> String {
+    let entity = projection.entity(id);
+    let name = ["name", "title"]
+        .iter()
+        .find_map(|p| entity.and_then(|e| e.value(p)));
+    match name {
+        Some(Datum::String(name)) => name.clone(),
+        _ => id.to_string(),
+    }
+}
+
+/// `t` as an ISO 8601 date and time in UTC, as RDF and JSON-LD have them.
+pub(crate) fn iso_date_time(t: i64) -> Option<String> {
+    let t = OffsetDateTime::from_unix_timestamp(t).ok()?;
+    Some(format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        t.date(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    ))
+}
+
+fn format_datum(datum: &Datum) -> String {
+    match datum {
+        Datum::String(s) => s.clone(),
+        Datum::Integer(n) => n.to_string(),
+        Datum::Float(n) => n.to_string(),
+        Datum::Boolean(b) => b.to_string(),
+        Datum::DateTime(t) => match OffsetDateTime::from_unix_timestamp(*t) {
+            Ok(t) if t.time() == time::Time::MIDNIGHT => t.date().to_string(),
+            Ok(t) => format!("{} {:02}:{:02} UTC", t.date(), t.hour(), t.minute()),
+            Err(_) => t.to_string(),
+        },
+        Datum::Entity(id) => id.to_string(),
+    }
+}
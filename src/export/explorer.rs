@@ -0,0 +1,347 @@
+//! Renders the graph to a single HTML file to explore it in a browser, for
+//! sharing it read-only with people who don't run Graphite.
+//!
+//! The file embeds the entities, their links and a search index as JSON, and
+//! a script that draws them on a canvas that pans and zooms. Clicking an
+//! entity shows its facts and note, with its links and backlinks to follow.
+//! Entities keep the place they have on the canvas in Graphite, and those
+//! without one are laid out on a grid below.
+
+use super::{format_datum, label};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use uuid::Uuid;
+
+/// The distance between entities laid out on the grid.
+const GRID: f64 = 120.0;
+
+/// The explorer of the `entities` of `projection`, all of them if `None`.
+pub fn explorer(projection: &Projection, entities: Option<&BTreeSet<Uuid>>, title: &str) -> String {
+    let mut ids: Vec<Uuid> = match entities {
+        Some(entities) => entities.iter().copied().collect(),
+        None => projection.entities().map(|(id, _)| *id).collect(),
+    };
+    ids.sort_by_cached_key(|id| (label(projection, id).to_lowercase(), *id));
+    let index_of: BTreeMap<Uuid, usize> = ids.iter().enumerate().map(|(n, id)| (*id, n)).collect();
+
+    let coordinate = |id: &Uuid, predicate: &str| match projection.entity(id)?.value(predicate)? {
+        Datum::Float(n) => Some(*n),
+        Datum::Integer(n) => Some(*n as f64),
+        _ => None,
+    };
+    let placed: Vec<(f64, f64)> = ids
+        .iter()
+        .filter_map(|id| Some((coordinate(id, "x")?, coordinate(id, "y")?)))
+        .collect();
+    let bottom = placed.iter().map(|(_, y)| *y).fold(0.0, f64::max) + GRID;
+    let columns = (ids.len() as f64).sqrt().ceil().max(1.0) as usize;
+    let mut unplaced = 0;
+
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut search: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+    for (n, id) in ids.iter().enumerate() {
+        let name = label(projection, id);
+        let (x, y) = match (coordinate(id, "x"), coordinate(id, "y")) {
+            (Some(x), Some(y)) => (x, y),
+            _ => {
+                let at = unplaced;
+                unplaced += 1;
+                (
+                    (at % columns) as f64 * GRID,
+                    bottom + (at / columns) as f64 * GRID,
+                )
+            }
+        };
+        let mut facts = Vec::new();
+        let mut note = None;
+        for (predicate, values) in projection.entity(id).into_iter().flat_map(|e| e.facts()) {
+            if predicate == "x" || predicate == "y" {
+                continue;
+            }
+            for datum in values {
+                let fact = match datum {
+                    Datum::Entity(target) => match index_of.get(target) {
+                        Some(target) => {
+                            edges.push(json!([n, target, predicate]));
+                            json!([predicate, Value::Null, target])
+                        }
+                        None => json!([predicate, label(projection, target)]),
+                    },
+                    Datum::String(text) if predicate == "content" => {
+                        note = Some(text.clone());
+                        continue;
+                    }
+                    datum => json!([predicate, format_datum(datum)]),
+                };
+                if let Datum::String(text) = datum {
+                    words(text).for_each(|w| {
+                        search.entry(w).or_default().insert(n);
+                    });
+                }
+                facts.push(fact);
+            }
+        }
+        for word in words(&name).chain(note.iter().flat_map(|note| words(note))) {
+            search.entry(word).or_default().insert(n);
+        }
+        nodes.push(json!({
+            "id": id,
+            "label": name,
+            "x": x,
+            "y": y,
+            "facts": facts,
+            "note": note,
+        }));
+    }
+    let data = json!({
+        "title": title,
+        "nodes": nodes,
+        "edges": edges,
+        "index": search,
+    });
+    // Closing tags in the data must not end the script element.
+    let data = data.to_string().replace("</", "<\\/");
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n\
+         <canvas id=\"graph\"></canvas>\n<aside>\n\
+         <input id=\"search\" type=\"search\" placeholder=\"Search\" autofocus>\n\
+         <ul id=\"results\"></ul>\n<article id=\"entity\"></article>\n</aside>\n\
+         <script id=\"data\" type=\"application/json\">{}</script>\n\
+         <script>{}</script>\n</body>\n</html>\n",
+        super::site::escape(title),
+        STYLE,
+        data,
+        SCRIPT
+    )
+}
+
+/// Writes the explorer to the file `out`.
+pub fn write(
+    projection: &Projection,
+    entities: Option<&BTreeSet<Uuid>>,
+    title: &str,
+    out: &Path,
+) -> Result<()> {
+    std::fs::write(out, explorer(projection, entities, title))
+        .with_context(|| format!("Failed to write {}", out.display()))
+}
+
+/// The lowercase words of `text`, for the search index.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1)
+        .map(str::to_lowercase)
+}
+
+const STYLE: &str = "html,body{margin:0;height:100%;font-family:sans-serif}\
+canvas{position:fixed;inset:0 24rem 0 0;width:calc(100% - 24rem);height:100%;cursor:grab}\
+aside{position:fixed;top:0;right:0;bottom:0;width:24rem;overflow:auto;padding:1rem;\
+box-sizing:border-box;border-left:1px solid #ccc;background:#fafafa}\
+#search{width:100%;font-size:1rem}#results{padding-left:1.2rem}\
+a{cursor:pointer;color:#1a5fb4}th{text-align:left;vertical-align:top;padding-right:.5rem}\
+pre{white-space:pre-wrap;font-family:inherit}";
+
+/// Draws the graph, pans and zooms it, searches the index and shows the
+/// entity clicked last.
+const SCRIPT: &str = r##"
+const data = JSON.parse(document.getElementById("data").textContent);
+document.title = data.title;
+const canvas = document.getElementById("graph");
+const context = canvas.getContext("2d");
+const view = { x: 0, y: 0, scale: 1 };
+let selected = null;
+
+function fit() {
+  if (data.nodes.length === 0) return;
+  const xs = data.nodes.map((n) => n.x), ys = data.nodes.map((n) => n.y);
+  const [left, right] = [Math.min(...xs), Math.max(...xs)];
+  const [top, bottom] = [Math.min(...ys), Math.max(...ys)];
+  view.scale = Math.min(1.5, canvas.clientWidth / (right - left + 200), canvas.clientHeight / (bottom - top + 200));
+  view.x = canvas.clientWidth / 2 - (left + right) / 2 * view.scale;
+  view.y = canvas.clientHeight / 2 - (top + bottom) / 2 * view.scale;
+}
+
+function draw() {
+  canvas.width = canvas.clientWidth * devicePixelRatio;
+  canvas.height = canvas.clientHeight * devicePixelRatio;
+  context.setTransform(devicePixelRatio * view.scale, 0, 0, devicePixelRatio * view.scale,
+    devicePixelRatio * view.x, devicePixelRatio * view.y);
+  context.strokeStyle = "#999";
+  context.lineWidth = 1 / view.scale;
+  for (const [source, target] of data.edges) {
+    const [a, b] = [data.nodes[source], data.nodes[target]];
+    context.beginPath();
+    context.moveTo(a.x, a.y);
+    context.lineTo(b.x, b.y);
+    context.stroke();
+  }
+  context.font = `${12 / view.scale}px sans-serif`;
+  data.nodes.forEach((node, n) => {
+    context.fillStyle = n === selected ? "#e66100" : "#1a5fb4";
+    context.beginPath();
+    context.arc(node.x, node.y, 6 / view.scale, 0, 2 * Math.PI);
+    context.fill();
+    context.fillStyle = "#222";
+    context.fillText(node.label, node.x + 9 / view.scale, node.y + 4 / view.scale);
+  });
+}
+
+function link(n) {
+  const a = document.createElement("a");
+  a.textContent = data.nodes[n].label;
+  a.onclick = () => select(n, true);
+  return a;
+}
+
+function select(n, center) {
+  selected = n;
+  const node = data.nodes[n];
+  if (center) {
+    view.x = canvas.clientWidth / 2 - node.x * view.scale;
+    view.y = canvas.clientHeight / 2 - node.y * view.scale;
+  }
+  const article = document.getElementById("entity");
+  article.replaceChildren();
+  const heading = document.createElement("h2");
+  heading.textContent = node.label;
+  const table = document.createElement("table");
+  for (const [predicate, value, target] of node.facts) {
+    const row = table.insertRow();
+    const th = document.createElement("th");
+    th.textContent = predicate;
+    row.append(th);
+    const cell = row.insertCell();
+    target === undefined ? (cell.textContent = value) : cell.append(link(target));
+  }
+  article.append(heading, table);
+  if (node.note) {
+    const note = document.createElement("pre");
+    note.textContent = node.note;
+    article.append(note);
+  }
+  const backlinks = [...new Set(data.edges.filter((e) => e[1] === n).map((e) => e[0]))];
+  if (backlinks.length > 0) {
+    const title = document.createElement("h3");
+    title.textContent = "Linked from";
+    const list = document.createElement("ul");
+    for (const source of backlinks) {
+      const item = document.createElement("li");
+      item.append(link(source));
+      list.append(item);
+    }
+    article.append(title, list);
+  }
+  draw();
+}
+
+document.getElementById("search").addEventListener("input", (e) => {
+  const words = e.target.value.toLowerCase().split(/[^\p{L}\p{N}]+/u).filter((w) => w);
+  const results = document.getElementById("results");
+  results.replaceChildren();
+  if (words.length === 0) return;
+  let found = null;
+  for (const word of words) {
+    const matches = new Set();
+    for (const [key, nodes] of Object.entries(data.index)) {
+      if (key.startsWith(word)) nodes.forEach((n) => matches.add(n));
+    }
+    found = found === null ? matches : new Set([...found].filter((n) => matches.has(n)));
+  }
+  for (const n of [...found].slice(0, 50)) {
+    const item = document.createElement("li");
+    item.append(link(n));
+    results.append(item);
+  }
+});
+
+let drag = null;
+canvas.addEventListener("mousedown", (e) => {
+  drag = { x: e.clientX, y: e.clientY, moved: false };
+});
+addEventListener("mousemove", (e) => {
+  if (!drag) return;
+  view.x += e.clientX - drag.x;
+  view.y += e.clientY - drag.y;
+  drag = { x: e.clientX, y: e.clientY, moved: true };
+  draw();
+});
+addEventListener("mouseup", (e) => {
+  if (drag && !drag.moved) {
+    const rect = canvas.getBoundingClientRect();
+    const x = (e.clientX - rect.left - view.x) / view.scale;
+    const y = (e.clientY - rect.top - view.y) / view.scale;
+    const radius = 10 / view.scale;
+    const hit = data.nodes.findIndex((node) => Math.hypot(node.x - x, node.y - y) < radius);
+    if (hit >= 0) select(hit, false);
+  }
+  drag = null;
+});
+canvas.addEventListener("wheel", (e) => {
+  e.preventDefault();
+  const factor = Math.exp(-e.deltaY / 500);
+  const rect = canvas.getBoundingClientRect();
+  const [x, y] = [e.clientX - rect.left, e.clientY - rect.top];
+  view.x = x - (x - view.x) * factor;
+  view.y = y - (y - view.y) * factor;
+  view.scale *= factor;
+  draw();
+}, { passive: false });
+addEventListener("resize", draw);
+fit();
+draw();
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn embeds_the_graph() {
+        let mut projection = Projection::new();
+        let [thesis, leaf] = [Uuid::new_v4(), Uuid::new_v4()];
+        let fact = |subject, predicate: &str, datum| Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        };
+        for action in [
+            Action::CreateEntity { id: thesis },
+            Action::CreateEntity { id: leaf },
+            fact(thesis, "name", Datum::String("Thesis".into())),
+            fact(thesis, "x", Datum::Float(10.0)),
+            fact(thesis, "y", Datum::Float(20.0)),
+            fact(thesis, "cites", Datum::Entity(leaf)),
+            fact(leaf, "name", Datum::String("Leaf</script>".into())),
+            fact(leaf, "content", Datum::String("Mosses and ferns".into())),
+        ] {
+            projection.apply(&action);
+        }
+
+        let html = explorer(&projection, None, "Notes & more");
+        assert!(html.contains("<title>Notes &amp; more</title>"));
+        assert_eq!(html.matches("</script>").count(), 2);
+        let start = html.find("application/json\">").unwrap() + "application/json\">".len();
+        let end = start + html[start..].find("</script>").unwrap();
+        let data: Value = serde_json::from_str(&html[start..end]).unwrap();
+        let nodes = data["nodes"].as_array().unwrap();
+        assert_eq!(nodes[0]["label"], "Leaf</script>");
+        assert_eq!(nodes[0]["note"], "Mosses and ferns");
+        // Placed on the canvas where it is in Graphite, the other below.
+        assert_eq!(
+            (nodes[1]["x"].as_f64(), nodes[1]["y"].as_f64()),
+            (Some(10.0), Some(20.0))
+        );
+        assert!(nodes[0]["y"].as_f64().unwrap() > 20.0);
+        assert_eq!(data["edges"], json!([[1, 0, "cites"]]));
+        assert_eq!(data["index"]["mosses"], json!([0]));
+        assert_eq!(data["index"]["thesis"], json!([1]));
+    }
+}
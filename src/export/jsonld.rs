@@ -0,0 +1,195 @@
+//! Writes typed entities as JSON-LD, for websites and other consumers of
+//! structured data.
+//!
+//! Entities whose `type` is in `types` become nodes of that type, schema.org
+//! by default, e.g. a `person` a `Person`. Only the predicates in
+//! `predicates` are written, under the term they map to, so the output only
+//! uses the vocabulary of the context. Links become references by `@id` to
+//! the entities linked to, which are written too when they have a type.
+
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::Datum;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use uuid::Uuid;
+
+/// How typed entities map to JSON-LD.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mapping {
+    /// The `@context` the terms are defined in.
+    pub context: String,
+    /// What the ids of entities are appended to for their `@id`.
+    pub entities: String,
+    /// Terms by `type` value, e.g. `Person` for `person`.
+    pub types: BTreeMap<String, String>,
+    /// Terms by predicate, e.g. `text` for `content`.
+    pub predicates: BTreeMap<String, String>,
+}
+
+impl Default for Mapping {
+    fn default() -> Mapping {
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        Mapping {
+            context: "https://schema.org/".to_string(),
+            entities: "urn:uuid:".to_string(),
+            types: pairs(&[
+                ("person", "Person"),
+                ("organization", "Organization"),
+                ("place", "Place"),
+                ("event", "Event"),
+                ("book", "Book"),
+                ("paper", "ScholarlyArticle"),
+                ("bookmark", "WebPage"),
+                ("task", "Action"),
+            ]),
+            predicates: pairs(&[
+                ("name", "name"),
+                ("title", "name"),
+                ("description", "description"),
+                ("content", "text"),
+                ("url", "url"),
+                ("email", "email"),
+                ("tags", "keywords"),
+                ("author", "author"),
+                ("created", "dateCreated"),
+                ("date", "startDate"),
+            ]),
+        }
+    }
+}
+
+impl Mapping {
+    /// The term of the type of `entity`, if it has one that maps.
+    fn term(&self, entity: &Entity) -> Option<&str> {
+        match entity.value("type") {
+            Some(Datum::String(kind)) => self.types.get(kind).map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
+/// The JSON-LD document of every typed entity in `projection`.
+pub fn document(projection: &Projection, mapping: &Mapping) -> Value {
+    let mut ids: Vec<&Uuid> = projection.entities().map(|(id, _)| id).collect();
+    ids.sort();
+    let graph: Vec<Value> = ids
+        .into_iter()
+        .filter_map(|id| {
+            let entity = projection.entity(id)?;
+            let kind = mapping.term(entity)?;
+            Some(node(id, entity, kind, mapping))
+        })
+        .collect();
+    json!({ "@context": mapping.context, "@graph": graph })
+}
+
+fn node(id: &Uuid, entity: &Entity, kind: &str, mapping: &Mapping) -> Value {
+    let mut node = Map::new();
+    node.insert("@id".into(), json!(format!("{}{}", mapping.entities, id)));
+    node.insert("@type".into(), json!(kind));
+    for (predicate, values) in entity.facts() {
+        let Some(term) = mapping.predicates.get(predicate) else {
+            continue;
+        };
+        // Predicates mapped to the same term, such as a name and a title,
+        // keep the first.
+        if node.contains_key(term) || values.is_empty() {
+            continue;
+        }
+        let mut values: Vec<Value> = values.iter().map(|d| value(d, mapping)).collect();
+        let value = match values.len() {
+            1 => values.remove(0),
+            _ => Value::Array(values),
+        };
+        node.insert(term.clone(), value);
+    }
+    Value::Object(node)
+}
+
+fn value(datum: &Datum, mapping: &Mapping) -> Value {
+    match datum {
+        Datum::String(s) => json!(s),
+        Datum::Integer(n) => json!(n),
+        Datum::Float(n) => json!(n),
+        Datum::Boolean(b) => json!(b),
+        Datum::DateTime(t) => match super::iso_date_time(*t) {
+            Some(text) => json!(text),
+            None => json!(t),
+        },
+        Datum::Entity(id) => json!({ "@id": format!("{}{}", mapping.entities, id) }),
+    }
+}
+
+/// Writes the document to `out`.
+pub fn write(projection: &Projection, mapping: &Mapping, out: &Path) -> Result<usize> {
+    let document = document(projection, mapping);
+    let count = document["@graph"].as_array().map_or(0, Vec::len);
+    let text = serde_json::to_string_pretty(&document)?;
+    std::fs::write(out, text).with_context(|| format!("Failed to write {}", out.display()))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn typed_entities_become_nodes() {
+        let mut projection = Projection::new();
+        let [ada, company, note] = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let fact = |subject, predicate: &str, datum| Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        };
+        let text = |s: &str| Datum::String(s.to_string());
+        for action in [
+            Action::CreateEntity { id: ada },
+            Action::CreateEntity { id: company },
+            Action::CreateEntity { id: note },
+            fact(ada, "type", text("person")),
+            fact(ada, "name", text("Ada")),
+            fact(ada, "tags", text("math")),
+            fact(ada, "tags", text("poetry")),
+            fact(ada, "works_for", Datum::Entity(company)),
+            fact(ada, "secret", text("not for the web")),
+            fact(company, "type", text("company")),
+            fact(note, "name", text("Untyped")),
+        ] {
+            projection.apply(&action);
+        }
+        let mut mapping = Mapping::default();
+        mapping
+            .types
+            .insert("company".into(), "Organization".into());
+        mapping
+            .predicates
+            .insert("works_for".into(), "worksFor".into());
+
+        let document = document(&projection, &mapping);
+        assert_eq!(document["@context"], "https://schema.org/");
+        let graph = document["@graph"].as_array().unwrap();
+        assert_eq!(graph.len(), 2);
+        let person = graph.iter().find(|n| n["@type"] == "Person").unwrap();
+        assert_eq!(
+            person,
+            &json!({
+                "@id": format!("urn:uuid:{}", ada),
+                "@type": "Person",
+                "name": "Ada",
+                "keywords": ["math", "poetry"],
+                "worksFor": { "@id": format!("urn:uuid:{}", company) },
+            })
+        );
+    }
+}
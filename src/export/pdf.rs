@@ -0,0 +1,758 @@
+//! Renders entities to a PDF for printing, or for sharing a record with
+//! someone who doesn't use Graphite.
+//!
+//! Every entity starts on a new page with its name, followed by the sections
+//! asked for. Text is set in the standard Helvetica fonts, which every PDF
+//! reader has, so characters outside Latin-1 are replaced. PNG and JPEG
+//! images are embedded; other files are only named in the facts.
+
+use super::{format_datum, label};
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::Datum;
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::write::ZlibEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::{self, Write as _};
+use std::io::Write as _;
+use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    /// Every fact of the entity.
+    Facts,
+    /// The entities it links to, with their facts.
+    Linked,
+    /// The entities that link to it.
+    Backlinks,
+    /// The images referenced by image predicates.
+    Images,
+}
+
+impl Section {
+    pub const ALL: [Section; 4] = [
+        Section::Facts,
+        Section::Linked,
+        Section::Backlinks,
+        Section::Images,
+    ];
+
+    fn heading(self) -> &'static str {
+        match self {
+            Section::Facts => "Facts",
+            Section::Linked => "Linked entities",
+            Section::Backlinks => "Linked from",
+            Section::Images => "Images",
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Section::Facts => "facts",
+            Section::Linked => "linked",
+            Section::Backlinks => "backlinks",
+            Section::Images => "images",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Section {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Section> {
+        Section::ALL
+            .into_iter()
+            .find(|section| section.to_string() == s.to_ascii_lowercase())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown section {}, expected one of facts, linked, backlinks or images",
+                    s
+                )
+            })
+    }
+}
+
+pub struct Options {
+    /// The sections of each entity's page, in order.
+    pub sections: Vec<Section>,
+    /// Predicates whose values are paths of images to print.
+    pub image_predicates: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            sections: Section::ALL.to_vec(),
+            image_predicates: vec!["image".to_string(), "file".to_string()],
+        }
+    }
+}
+
+/// Writes the pages of `entities` to the PDF at `out`.
+pub fn pdf(
+    projection: &Projection,
+    entities: &[Uuid],
+    options: &Options,
+    out: &Path,
+) -> Result<()> {
+    let document = render(projection, entities, options)?;
+    std::fs::write(out, document).with_context(|| format!("Failed to write {}", out.display()))
+}
+
+/// The PDF of the pages of `entities`.
+pub fn render(projection: &Projection, entities: &[Uuid], options: &Options) -> Result<Vec<u8>> {
+    if entities.is_empty() {
+        bail!("There are no entities to export");
+    }
+    let mut document = Document::new();
+    for id in entities {
+        let entity = projection
+            .entity(id)
+            .ok_or_else(|| anyhow!("There is no entity {}", id))?;
+        document.new_page();
+        document.paragraph(Font::Bold, 20.0, 0.0, &label(projection, id));
+        for section in &options.sections {
+            page_section(&mut document, projection, id, entity, *section, options)?;
+        }
+    }
+    Ok(document.finish(&label(projection, &entities[0])))
+}
+
+fn page_section(
+    document: &mut Document,
+    projection: &Projection,
+    id: &Uuid,
+    entity: &Entity,
+    section: Section,
+    options: &Options,
+) -> Result<()> {
+    match section {
+        Section::Facts => {
+            document.heading(section.heading());
+            facts(document, projection, entity, 0.0);
+        }
+        Section::Linked => {
+            let linked: BTreeSet<(String, Uuid)> = entity
+                .facts()
+                .flat_map(|(_, values)| values)
+                .filter_map(|datum| match datum {
+                    Datum::Entity(target) if target != id && projection.contains(target) => {
+                        Some((label(projection, target), *target))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if linked.is_empty() {
+                return Ok(());
+            }
+            document.heading(section.heading());
+            for (name, target) in linked {
+                document.paragraph(Font::Bold, 11.0, 0.0, &name);
+                if let Some(entity) = projection.entity(&target) {
+                    facts(document, projection, entity, INDENT);
+                }
+                document.gap(4.0);
+            }
+        }
+        Section::Backlinks => {
+            let mut sources: Vec<(String, &str)> = projection
+                .entities()
+                .filter(|(source, _)| *source != id)
+                .flat_map(|(source, e)| {
+                    e.facts()
+                        .filter(|(_, values)| values.contains(&Datum::Entity(*id)))
+                        .map(move |(predicate, _)| (label(projection, source), predicate))
+                })
+                .collect();
+            if sources.is_empty() {
+                return Ok(());
+            }
+            sources.sort();
+            document.heading(section.heading());
+            for (name, predicate) in sources {
+                document.row(&name, predicate, 0.0);
+            }
+        }
+        Section::Images => {
+            let mut images = Vec::new();
+            for predicate in &options.image_predicates {
+                for datum in entity.values(predicate) {
+                    if let Datum::String(path) = datum {
+                        if let Some(image) = load_image(Path::new(path))? {
+                            images.push((path, image));
+                        }
+                    }
+                }
+            }
+            if images.is_empty() {
+                return Ok(());
+            }
+            document.heading(section.heading());
+            for (path, image) in images {
+                document.image(image);
+                let name = Path::new(path)
+                    .file_name()
+                    .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                document.paragraph(Font::Regular, 9.0, 0.0, &name);
+                document.gap(8.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A row per fact of `entity`, links by the name of their target.
+fn facts(document: &mut Document, projection: &Projection, entity: &Entity, indent: f32) {
+    for (predicate, values) in entity.facts() {
+        for datum in values {
+            let value = match datum {
+                Datum::Entity(target) => label(projection, target),
+                datum => format_datum(datum),
+            };
+            document.row(predicate, &value, indent);
+        }
+    }
+}
+
+/// A4 in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+const INDENT: f32 = 16.0;
+/// The width of the predicate column of fact rows.
+const KEY_WIDTH: f32 = 140.0;
+const LEADING: f32 = 1.35;
+const BODY_SIZE: f32 = 10.0;
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+
+    /// The width of `text` set at `size`, from Helvetica's metrics. Bold is
+    /// a little wider than regular for the same text.
+    fn width(self, text: &str, size: f32) -> f32 {
+        let units: u32 = text
+            .chars()
+            .map(|c| match c as u32 {
+                code @ 32..=126 => HELVETICA_WIDTHS[code as usize - 32] as u32,
+                _ => 556,
+            })
+            .sum();
+        let scale = match self {
+            Font::Regular => 1.0,
+            Font::Bold => 1.06,
+        };
+        units as f32 * size * scale / 1000.0
+    }
+}
+
+/// Advance widths of the printable ASCII characters in Helvetica, in
+/// thousandths of the font size.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+struct Image {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    data: Vec<u8>,
+}
+
+/// The image at `path`, or `None` if there is no such file or it is neither
+/// a PNG nor a JPEG.
+fn load_image(path: &Path) -> Result<Option<Image>> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    if !path.is_file() || !matches!(extension.as_deref(), Some("png" | "jpg" | "jpeg")) {
+        return Ok(None);
+    }
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let image = match extension.as_deref() {
+        Some("png") => png_image(&bytes),
+        _ => jpeg_image(bytes),
+    };
+    image
+        .map(Some)
+        .with_context(|| format!("Failed to read the image {}", path.display()))
+}
+
+/// Decodes a PNG to 8-bit RGB or gray, blending transparency onto white.
+fn png_image(bytes: &[u8]) -> Result<Image> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let pixels = &buffer[..info.buffer_size()];
+    let blend = |value: u8, alpha: u8| {
+        let alpha = alpha as u32;
+        ((value as u32 * alpha + 255 * (255 - alpha)) / 255) as u8
+    };
+    let (color_space, samples): (_, Vec<u8>) = match info.color_type {
+        png::ColorType::Rgb => ("DeviceRGB", pixels.to_vec()),
+        png::ColorType::Rgba => (
+            "DeviceRGB",
+            pixels
+                .chunks_exact(4)
+                .flat_map(|p| [blend(p[0], p[3]), blend(p[1], p[3]), blend(p[2], p[3])])
+                .collect(),
+        ),
+        png::ColorType::Grayscale => ("DeviceGray", pixels.to_vec()),
+        png::ColorType::GrayscaleAlpha => (
+            "DeviceGray",
+            pixels.chunks_exact(2).map(|p| blend(p[0], p[1])).collect(),
+        ),
+        png::ColorType::Indexed => bail!("Indexed colors were not expanded"),
+    };
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&samples)?;
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        color_space,
+        filter: "FlateDecode",
+        data: encoder.finish()?,
+    })
+}
+
+/// Embeds a JPEG as is, reading its size and colors from the frame header.
+fn jpeg_image(bytes: Vec<u8>) -> Result<Image> {
+    if !bytes.starts_with(&[0xff, 0xd8]) {
+        bail!("Not a JPEG");
+    }
+    let u16_at = |i: usize| -> Result<usize> {
+        match bytes.get(i..i + 2) {
+            Some(b) => Ok(u16::from_be_bytes([b[0], b[1]]) as usize),
+            None => bail!("The JPEG ends early"),
+        }
+    };
+    let mut i = 2;
+    loop {
+        while bytes.get(i) == Some(&0xff) && bytes.get(i + 1) == Some(&0xff) {
+            i += 1;
+        }
+        let (Some(0xff), Some(&marker)) = (bytes.get(i), bytes.get(i + 1)) else {
+            bail!("The JPEG has no frame header");
+        };
+        // Start of frame markers, except those of huffman tables, arithmetic
+        // coding and restarts.
+        if matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+            let color_space = match bytes.get(i + 9) {
+                Some(1) => "DeviceGray",
+                Some(3) => "DeviceRGB",
+                Some(4) => "DeviceCMYK",
+                _ => bail!("Unsupported JPEG colors"),
+            };
+            return Ok(Image {
+                height: u16_at(i + 5)? as u32,
+                width: u16_at(i + 7)? as u32,
+                color_space,
+                filter: "DCTDecode",
+                data: bytes,
+            });
+        }
+        i += 2 + u16_at(i + 2)?;
+    }
+}
+
+/// Lays out text and images on pages, top to bottom.
+struct Document {
+    /// The content streams of the pages.
+    pages: Vec<String>,
+    images: Vec<Image>,
+    /// Where the next line goes on the last page, from the bottom.
+    y: f32,
+}
+
+impl Document {
+    fn new() -> Document {
+        Document {
+            pages: Vec::new(),
+            images: Vec::new(),
+            y: 0.0,
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(String::new());
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Starts a new page unless `height` fits on this one.
+    fn reserve(&mut self, height: f32) {
+        if self.pages.is_empty() || self.y - height < MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn content(&mut self) -> &mut String {
+        self.pages.last_mut().expect("a page was started")
+    }
+
+    fn text_at(&mut self, font: Font, size: f32, x: f32, y: f32, text: &str) {
+        let command = format!(
+            "BT /{} {} Tf {:.2} {:.2} Td ({}) Tj ET\n",
+            font.resource(),
+            size,
+            x,
+            y,
+            escape(text)
+        );
+        self.content().push_str(&command);
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.gap(10.0);
+        // Keep a heading with at least a line of what follows it.
+        self.reserve(14.0 * LEADING + BODY_SIZE * LEADING);
+        self.paragraph(Font::Bold, 14.0, 0.0, text);
+        self.gap(2.0);
+    }
+
+    fn paragraph(&mut self, font: Font, size: f32, indent: f32, text: &str) {
+        let width = PAGE_WIDTH - 2.0 * MARGIN - indent;
+        for line in wrap(font, size, width, text) {
+            self.reserve(size * LEADING);
+            self.y -= size * LEADING;
+            let y = self.y;
+            self.text_at(font, size, MARGIN + indent, y, &line);
+        }
+    }
+
+    /// A fact: `key` in bold, with `value` wrapped beside it.
+    fn row(&mut self, key: &str, value: &str, indent: f32) {
+        let width = PAGE_WIDTH - 2.0 * MARGIN - indent - KEY_WIDTH;
+        let keys = wrap(Font::Bold, BODY_SIZE, KEY_WIDTH - 8.0, key);
+        let values = wrap(Font::Regular, BODY_SIZE, width, value);
+        for n in 0..keys.len().max(values.len()) {
+            self.reserve(BODY_SIZE * LEADING);
+            self.y -= BODY_SIZE * LEADING;
+            let y = self.y;
+            if let Some(key) = keys.get(n) {
+                self.text_at(Font::Bold, BODY_SIZE, MARGIN + indent, y, key);
+            }
+            if let Some(value) = values.get(n) {
+                self.text_at(
+                    Font::Regular,
+                    BODY_SIZE,
+                    MARGIN + indent + KEY_WIDTH,
+                    y,
+                    value,
+                );
+            }
+        }
+    }
+
+    /// The image at its size in points, shrunk to fit the page.
+    fn image(&mut self, image: Image) {
+        let max_width = PAGE_WIDTH - 2.0 * MARGIN;
+        let max_height = (PAGE_HEIGHT - 2.0 * MARGIN) / 2.0;
+        let scale = (max_width / image.width as f32)
+            .min(max_height / image.height as f32)
+            .min(1.0);
+        let (width, height) = (image.width as f32 * scale, image.height as f32 * scale);
+        self.reserve(height + 4.0);
+        self.y -= height + 4.0;
+        let command = format!(
+            "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q\n",
+            width,
+            height,
+            MARGIN,
+            self.y,
+            self.images.len()
+        );
+        self.content().push_str(&command);
+        self.images.push(image);
+    }
+
+    /// The PDF file, with page numbers in the footers.
+    fn finish(mut self, title: &str) -> Vec<u8> {
+        let count = self.pages.len();
+        for n in 0..count {
+            let number = format!("{} / {}", n + 1, count);
+            let x = (PAGE_WIDTH - Font::Regular.width(&number, 8.0)) / 2.0;
+            self.pages[n].push_str(&format!(
+                "BT /F1 8 Tf {:.2} {:.2} Td ({}) Tj ET\n",
+                x,
+                MARGIN / 2.0,
+                number
+            ));
+        }
+
+        // Objects are numbered from 1: the catalog, the page tree, the info,
+        // the two fonts, the images, then each page and its content.
+        let mut objects: Vec<Vec<u8>> = Vec::new();
+        let first_image = 6;
+        let first_page = first_image + self.images.len();
+        let kids: Vec<String> = (0..count)
+            .map(|n| format!("{} 0 R", first_page + 2 * n))
+            .collect();
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                count
+            )
+            .into_bytes(),
+        );
+        let mut info = b"<< /Producer (Graphite) /Title (".to_vec();
+        info.extend(encode(&escape(title)));
+        info.extend(b") >>");
+        objects.push(info);
+        for font in ["Helvetica", "Helvetica-Bold"] {
+            objects.push(
+                format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                    font
+                )
+                .into_bytes(),
+            );
+        }
+        for image in &self.images {
+            let mut object = format!(
+                "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} \
+                 /BitsPerComponent 8 /Filter /{} /Length {} >>\nstream\n",
+                image.width,
+                image.height,
+                image.color_space,
+                image.filter,
+                image.data.len()
+            )
+            .into_bytes();
+            object.extend(&image.data);
+            object.extend(b"\nendstream");
+            objects.push(object);
+        }
+        let mut resources = String::from("<< /Font << /F1 4 0 R /F2 5 0 R >> /XObject << ");
+        for n in 0..self.images.len() {
+            let _ = write!(resources, "/Im{} {} 0 R ", n, first_image + n);
+        }
+        resources.push_str(">> >>");
+        for (n, page) in self.pages.iter().enumerate() {
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources {} \
+                     /Contents {} 0 R >>",
+                    PAGE_WIDTH,
+                    PAGE_HEIGHT,
+                    resources,
+                    first_page + 2 * n + 1
+                )
+                .into_bytes(),
+            );
+            let content = encode(page);
+            let mut object = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            object.extend(content);
+            object.extend(b"\nendstream");
+            objects.push(object);
+        }
+
+        let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = Vec::new();
+        for (n, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend(format!("{} 0 obj\n", n + 1).into_bytes());
+            pdf.extend(object);
+            pdf.extend(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+        for offset in offsets {
+            pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+        }
+        pdf.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .into_bytes(),
+        );
+        pdf
+    }
+}
+
+/// Breaks `text` into lines no wider than `width`, at spaces where possible.
+fn wrap(font: Font, size: f32, width: f32, text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if font.width(&candidate, size) <= width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // Words too long for a line of their own are broken anywhere.
+            for c in word.chars() {
+                if !line.is_empty() && font.width(&format!("{}{}", line, c), size) > width {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(c);
+            }
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// `text` for a PDF string literal, still as Unicode.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\t' => escaped.push(' '),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `text` in the Windows-1252 encoding of the fonts, with `?` for the
+/// characters it doesn't have.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            '€' => 0x80,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            c if (c as u32) < 0x80 || (0xa0..=0xff).contains(&(c as u32)) => c as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    fn fact(projection: &mut Projection, subject: Uuid, predicate: &str, datum: Datum) {
+        projection.apply(&Action::AddFact {
+            subject,
+            predicate: predicate.into(),
+            datum,
+        });
+    }
+
+    #[test]
+    fn renders_the_sections_of_an_entity() {
+        let mut projection = Projection::new();
+        let (record, author) = (Uuid::new_v4(), Uuid::new_v4());
+        projection.apply(&Action::CreateEntity { id: record });
+        projection.apply(&Action::CreateEntity { id: author });
+        fact(
+            &mut projection,
+            record,
+            "name",
+            Datum::String("Recipe (draft)".into()),
+        );
+        fact(&mut projection, record, "author", Datum::Entity(author));
+        fact(&mut projection, author, "name", Datum::String("Zoë".into()));
+        fact(&mut projection, author, "wrote", Datum::Entity(record));
+
+        let dir = std::env::temp_dir().join(format!("graphite-pdf-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dot.png");
+        let mut encoder = png::Encoder::new(std::fs::File::create(&path).unwrap(), 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[255, 0, 0, 255, 0, 0, 0, 0])
+            .unwrap();
+        writer.finish().unwrap();
+        fact(
+            &mut projection,
+            record,
+            "image",
+            Datum::String(path.to_string_lossy().into_owned()),
+        );
+
+        let pdf = render(&projection, &[record], &Options::default()).unwrap();
+        let contains = |needle: &[u8]| pdf.windows(needle.len()).any(|w| w == needle);
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(contains(b"(Recipe \\(draft\\)) Tj"));
+        assert!(contains(b"(Zo\xeb) Tj"));
+        assert!(contains(b"(Linked from) Tj"));
+        assert!(contains(b"/Width 2 /Height 1 /ColorSpace /DeviceRGB"));
+        assert!(contains(b"(1 / 1) Tj"));
+
+        let facts_only = Options {
+            sections: vec![Section::Facts],
+            ..Options::default()
+        };
+        let pdf = render(&projection, &[record], &facts_only).unwrap();
+        assert!(!pdf.windows(6).any(|w| w == b"/Image"));
+        assert!(render(&projection, &[Uuid::new_v4()], &facts_only).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn wraps_long_text() {
+        let lines = wrap(
+            Font::Regular,
+            10.0,
+            100.0,
+            "a few short words and averyveryverylongword",
+        );
+        assert!(lines.len() > 2);
+        assert!(lines
+            .iter()
+            .all(|line| Font::Regular.width(line, 10.0) <= 100.0));
+        assert_eq!(
+            lines.concat().replace(' ', ""),
+            "afewshortwordsandaveryveryverylongword"
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Writes the graph as RDF triples, in Turtle or N-Triples.
+//!
+//! Every fact is a triple of its entity, its predicate and its value.
+//! Entities become IRIs under `entities`, `urn:uuid:<id>` by default, and
+//! predicates IRIs under `vocabulary` unless `predicates` maps them to
+//! another one, e.g. `name` to `schema:name`. Values become literals typed
+//! with XML Schema datatypes, and links the IRIs of the entities linked to.
+//! [`crate::import::rdf`] reads them back with the same mapping.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use uuid::Uuid;
+
+pub const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+pub const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+/// How entities and predicates map to IRIs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mapping {
+    /// What the ids of entities are appended to.
+    pub entities: String,
+    /// What predicates without a mapping are appended to.
+    pub vocabulary: String,
+    /// Prefixes for the IRIs in `predicates` and in Turtle, e.g. `schema` for
+    /// `https://schema.org/`.
+    pub prefixes: BTreeMap<String, String>,
+    /// IRIs by predicate, full or with a prefix, e.g. `schema:name`.
+    pub predicates: BTreeMap<String, String>,
+}
+
+impl Default for Mapping {
+    fn default() -> Mapping {
+        Mapping {
+            entities: "urn:uuid:".to_string(),
+            vocabulary: "urn:graphite:".to_string(),
+            prefixes: BTreeMap::new(),
+            predicates: BTreeMap::new(),
+        }
+    }
+}
+
+impl Mapping {
+    /// The IRI of entity `id`.
+    pub fn entity(&self, id: &Uuid) -> String {
+        format!("{}{}", self.entities, id)
+    }
+
+    /// The IRI of `predicate`.
+    pub fn predicate(&self, predicate: &str) -> String {
+        match self.predicates.get(predicate) {
+            Some(iri) => self.expand(iri),
+            None => format!("{}{}", self.vocabulary, percent_encode(predicate)),
+        }
+    }
+
+    /// `iri` with its prefix, if it has a known one, expanded.
+    pub fn expand(&self, iri: &str) -> String {
+        let prefixes = self.prefixes.iter().map(|(p, i)| (p.as_str(), i.as_str()));
+        for (prefix, expansion) in prefixes.chain([("xsd", XSD), ("rdf", RDF)]) {
+            if let Some(local) = iri.strip_prefix(prefix).and_then(|r| r.strip_prefix(':')) {
+                return format!("{}{}", expansion, local);
+            }
+        }
+        iri.to_string()
+    }
+
+    /// `iri` with a known prefix, if it has one and is a valid prefixed name.
+    fn compact(&self, iri: &str) -> Option<String> {
+        let prefixes = self.prefixes.iter().map(|(p, i)| (p.as_str(), i.as_str()));
+        prefixes
+            .chain([("xsd", XSD)])
+            .find_map(|(prefix, expansion)| {
+                let local = iri.strip_prefix(expansion)?;
+                let valid = local
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+                (valid && !local.is_empty()).then(|| format!("{}:{}", prefix, local))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Turtle,
+    NTriples,
+}
+
+impl Format {
+    /// The format of a file by its extension, `.ttl` or `.nt`.
+    pub fn of(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "ttl" => Some(Format::Turtle),
+            "nt" => Some(Format::NTriples),
+            _ => None,
+        }
+    }
+}
+
+/// The triples of every fact in `projection`.
+pub fn triples(projection: &Projection, mapping: &Mapping, format: Format) -> String {
+    let mut out = String::new();
+    let mut ids: Vec<&Uuid> = projection.entities().map(|(id, _)| id).collect();
+    ids.sort();
+    let term = |iri: &str| match format {
+        Format::Turtle => mapping.compact(iri).unwrap_or_else(|| format!("<{}>", iri)),
+        Format::NTriples => format!("<{}>", iri),
+    };
+    if format == Format::Turtle {
+        let _ = writeln!(out, "@prefix xsd: <{}> .", XSD);
+        for (prefix, iri) in &mapping.prefixes {
+            let _ = writeln!(out, "@prefix {}: <{}> .", prefix, iri);
+        }
+    }
+    for id in ids {
+        let Some(entity) = projection.entity(id) else {
+            continue;
+        };
+        let subject = term(&mapping.entity(id));
+        let mut statements = Vec::new();
+        for (predicate, values) in entity.facts() {
+            let predicate = term(&mapping.predicate(predicate));
+            for datum in values {
+                let object = match datum {
+                    Datum::Entity(target) => term(&mapping.entity(target)),
+                    datum => {
+                        let (text, datatype) = literal(datum);
+                        match datatype {
+                            Some(datatype) => {
+                                format!(
+                                    "\"{}\"^^{}",
+                                    escape(&text),
+                                    term(&format!("{}{}", XSD, datatype))
+                                )
+                            }
+                            None => format!("\"{}\"", escape(&text)),
+                        }
+                    }
+                };
+                statements.push((predicate.clone(), object));
+            }
+        }
+        match format {
+            Format::NTriples => {
+                for (predicate, object) in statements {
+                    let _ = writeln!(out, "{} {} {} .", subject, predicate, object);
+                }
+            }
+            Format::Turtle if statements.is_empty() => {}
+            Format::Turtle => {
+                let _ = writeln!(out, "\n{}", subject);
+                let last = statements.len() - 1;
+                for (n, (predicate, object)) in statements.into_iter().enumerate() {
+                    let end = if n == last { " ." } else { " ;" };
+                    let _ = writeln!(out, "    {} {}{}", predicate, object, end);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Writes the triples to `out`, in the format of its extension.
+pub fn write(projection: &Projection, mapping: &Mapping, out: &Path) -> Result<()> {
+    let format = Format::of(out)
+        .with_context(|| format!("Expected a .ttl or .nt file, not {}", out.display()))?;
+    std::fs::write(out, triples(projection, mapping, format))
+        .with_context(|| format!("Failed to write {}", out.display()))
+}
+
+/// The lexical form of a value and its XML Schema datatype, none for plain
+/// strings.
+fn literal(datum: &Datum) -> (String, Option<&'static str>) {
+    match datum {
+        Datum::String(s) => (s.clone(), None),
+        Datum::Integer(n) => (n.to_string(), Some("integer")),
+        Datum::Float(n) => (format!("{:e}", n), Some("double")),
+        Datum::Boolean(b) => (b.to_string(), Some("boolean")),
+        Datum::DateTime(t) => match super::iso_date_time(*t) {
+            Some(text) => (text, Some("dateTime")),
+            None => (t.to_string(), Some("integer")),
+        },
+        Datum::Entity(id) => (id.to_string(), None),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Encodes what can't appear in an IRI, such as spaces.
+pub fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn facts_become_triples() {
+        let mut projection = Projection::new();
+        let [ada, team] = [Uuid::new_v4(), Uuid::new_v4()];
+        let fact = |subject, predicate: &str, datum| Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        };
+        for action in [
+            Action::CreateEntity { id: ada },
+            Action::CreateEntity { id: team },
+            fact(ada, "name", Datum::String("Ada \"the\" first".into())),
+            fact(ada, "age", Datum::Integer(36)),
+            fact(ada, "born", Datum::DateTime(0)),
+            fact(ada, "team", Datum::Entity(team)),
+            fact(ada, "shoe size", Datum::Float(38.5)),
+        ] {
+            projection.apply(&action);
+        }
+        let mut mapping = Mapping::default();
+        mapping
+            .prefixes
+            .insert("schema".into(), "https://schema.org/".into());
+        mapping
+            .predicates
+            .insert("name".into(), "schema:name".into());
+
+        let lines = triples(&projection, &mapping, Format::NTriples);
+        let subject = format!("<urn:uuid:{}>", ada);
+        assert!(lines.contains(&format!(
+            "{} <https://schema.org/name> \"Ada \\\"the\\\" first\" .",
+            subject
+        )));
+        assert!(lines.contains(&format!(
+            "{} <urn:graphite:age> \"36\"^^<{}integer> .",
+            subject, XSD
+        )));
+        assert!(lines.contains(&format!("<urn:graphite:team> <urn:uuid:{}> .", team)));
+        assert!(lines.contains("<urn:graphite:shoe%20size> \"3.85e1\"^^"));
+        assert!(lines.contains("\"1970-01-01T00:00:00Z\"^^"));
+
+        let turtle = triples(&projection, &mapping, Format::Turtle);
+        assert!(turtle.starts_with("@prefix xsd:"));
+        assert!(turtle.contains("    schema:name \"Ada"));
+        assert!(turtle.contains("\"36\"^^xsd:integer ;"));
+    }
+}
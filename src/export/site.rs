@@ -0,0 +1,340 @@
+//! Renders a subgraph to a static HTML site that can be published as is.
+//!
+//! The site has an `index.html` that lists and searches every page, one page
+//! per entity at `<id>.html` and the files referenced by asset predicates in
+//! `assets/`. Entities outside the subgraph are named but not linked, as they
+//! have no page.
+
+use super::label;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub struct Options {
+    /// The entities the subgraph grows from.
+    pub roots: Vec<Uuid>,
+    /// How many links to follow from the roots, or `None` to follow every
+    /// link.
+    pub depth: Option<usize>,
+    /// Predicates whose values are paths of local files to publish with the
+    /// site, e.g. `image` or `attachment`.
+    pub asset_predicates: Vec<String>,
+    pub title: String,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            roots: Vec::new(),
+            depth: None,
+            asset_predicates: vec![
+                "file".to_string(),
+                "image".to_string(),
+                "attachment".to_string(),
+            ],
+            title: "Graphite".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub pages: usize,
+    pub assets: usize,
+}
+
+/// The entities reachable from `roots` by following at most `depth` links.
+pub fn subgraph(projection: &Projection, roots: &[Uuid], depth: Option<usize>) -> BTreeSet<Uuid> {
+    let mut included: BTreeSet<Uuid> = roots
+        .iter()
+        .filter(|id| projection.contains(id))
+        .copied()
+        .collect();
+    let mut frontier: Vec<Uuid> = included.iter().copied().collect();
+    let mut level = 0;
+    while !frontier.is_empty() && depth.is_none_or(|depth| level < depth) {
+        let mut next = Vec::new();
+        for id in frontier {
+            let Some(entity) = projection.entity(&id) else {
+                continue;
+            };
+            for (_, values) in entity.facts() {
+                for datum in values {
+                    if let Datum::Entity(target) = datum {
+                        if projection.contains(target) && included.insert(*target) {
+                            next.push(*target);
+                        }
+                    }
+                }
+            }
+        }
+        frontier = next;
+        level += 1;
+    }
+    included
+}
+
+/// Renders the subgraph of `options.roots` to `out`, which is created if
+/// needed. Existing files with the same names are overwritten.
+pub fn site(projection: &Projection, options: &Options, out: &Path) -> Result<Report> {
+    let pages = subgraph(projection, &options.roots, options.depth);
+    std::fs::create_dir_all(out.join("assets"))
+        .with_context(|| format!("Failed to create {}", out.display()))?;
+
+    let mut backlinks: HashMap<Uuid, BTreeSet<Uuid>> = HashMap::new();
+    for id in &pages {
+        for (_, values) in projection.entity(id).into_iter().flat_map(|e| e.facts()) {
+            for datum in values {
+                if let Datum::Entity(target) = datum {
+                    if pages.contains(target) && target != id {
+                        backlinks.entry(*target).or_default().insert(*id);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut assets = 0;
+    let mut index = BTreeMap::new();
+    for id in &pages {
+        let Some(entity) = projection.entity(id) else {
+            continue;
+        };
+        let title = label(projection, id);
+        let mut body = String::new();
+        writeln!(body, "<h1>{}</h1>\n<table>", escape(&title))?;
+        for (predicate, values) in entity.facts() {
+            for (n, datum) in values.iter().enumerate() {
+                let value = match datum {
+                    Datum::Entity(target) if pages.contains(target) => format!(
+                        "<a href=\"{}\">{}</a>",
+                        page_name(target),
+                        escape(&label(projection, target))
+                    ),
+                    Datum::Entity(target) => escape(&label(projection, target)),
+                    Datum::String(path)
+                        if options.asset_predicates.iter().any(|p| p == predicate) =>
+                    {
+                        match export_asset(Path::new(path), id, predicate, n, out)? {
+                            Some(name) => {
+                                assets += 1;
+                                asset_html(&name)
+                            }
+                            None => escape(path),
+                        }
+                    }
+                    datum => escape(&super::format_datum(datum)),
+                };
+                writeln!(
+                    body,
+                    "<tr><th>{}</th><td>{}</td></tr>",
+                    escape(predicate),
+                    value
+                )?;
+            }
+        }
+        body.push_str("</table>\n");
+        if let Some(sources) = backlinks.get(id) {
+            body.push_str("<h2>Linked from</h2>\n<ul>\n");
+            for source in sources {
+                writeln!(
+                    body,
+                    "<li><a href=\"{}\">{}</a></li>",
+                    page_name(source),
+                    escape(&label(projection, source))
+                )?;
+            }
+            body.push_str("</ul>\n");
+        }
+        body.push_str("<p><a href=\"index.html\">Index</a></p>\n");
+        write(&out.join(page_name(id)), &document(&title, &body))?;
+        index.insert((title.to_lowercase(), *id), title);
+    }
+
+    let mut body = format!("<h1>{}</h1>\n", escape(&options.title));
+    body.push_str("<input id=\"search\" type=\"search\" placeholder=\"Search\" autofocus>\n");
+    body.push_str("<ul id=\"pages\">\n");
+    for ((_, id), title) in &index {
+        writeln!(
+            body,
+            "<li><a href=\"{}\">{}</a></li>",
+            page_name(id),
+            escape(title)
+        )?;
+    }
+    body.push_str("</ul>\n");
+    body.push_str(SEARCH_SCRIPT);
+    write(&out.join("index.html"), &document(&options.title, &body))?;
+
+    Ok(Report {
+        pages: index.len(),
+        assets,
+    })
+}
+
+/// Filters the index as you type.
+const SEARCH_SCRIPT: &str = r##"<script>
+document.getElementById("search").addEventListener("input", (e) => {
+  const query = e.target.value.toLowerCase();
+  for (const item of document.querySelectorAll("#pages li")) {
+    item.hidden = !item.textContent.toLowerCase().includes(query);
+  }
+});
+</script>
+"##;
+
+fn page_name(id: &Uuid) -> String {
+    format!("{}.html", id)
+}
+
+/// Copies the file at `path` into `assets/`, returning its name there, or
+/// `None` if there is no such file.
+fn export_asset(
+    path: &Path,
+    entity: &Uuid,
+    predicate: &str,
+    n: usize,
+    out: &Path,
+) -> Result<Option<String>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    // Named after where it is referenced, so re-exports overwrite it.
+    let mut name = format!(
+        "{}-{}-{}",
+        entity.simple(),
+        predicate
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>(),
+        n
+    );
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        name = format!("{}.{}", name, extension.to_lowercase());
+    }
+    let target: PathBuf = out.join("assets").join(&name);
+    std::fs::copy(path, &target)
+        .with_context(|| format!("Failed to copy {} to {}", path.display(), target.display()))?;
+    Ok(Some(name))
+}
+
+fn asset_html(name: &str) -> String {
+    let href = format!("assets/{}", escape(name));
+    let is_image = ["png", "jpg", "jpeg", "gif", "svg", "webp"]
+        .iter()
+        .any(|extension| name.ends_with(&format!(".{}", extension)));
+    if is_image {
+        format!("<a href=\"{0}\"><img src=\"{0}\" alt=\"\"></a>", href)
+    } else {
+        format!("<a href=\"{}\">{}</a>", href, escape(name))
+    }
+}
+
+fn document(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:48rem;margin:2rem auto;padding:0 1rem}\
+th{text-align:left;vertical-align:top;padding-right:1rem}img{max-width:100%}";
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub(super) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    fn entity(projection: &mut Projection, name: &str, links: &[Uuid]) -> Uuid {
+        let id = Uuid::new_v4();
+        projection.apply(&Action::CreateEntity { id });
+        projection.apply(&Action::AddFact {
+            subject: id,
+            predicate: "name".into(),
+            datum: Datum::String(name.into()),
+        });
+        for link in links {
+            projection.apply(&Action::AddFact {
+                subject: id,
+                predicate: "link".into(),
+                datum: Datum::Entity(*link),
+            });
+        }
+        id
+    }
+
+    #[test]
+    fn exports_the_subgraph_of_the_roots() {
+        let mut projection = Projection::new();
+        let private = entity(&mut projection, "Diary", &[]);
+        let leaf = entity(&mut projection, "Leaf <b>", &[private]);
+        let root = entity(&mut projection, "Thesis", &[leaf]);
+        entity(&mut projection, "Unrelated", &[root]);
+
+        let dir = std::env::temp_dir().join(format!("graphite-site-{}", Uuid::new_v4()));
+        let file = dir.join("figure.PNG");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&file, b"png").unwrap();
+        projection.apply(&Action::AddFact {
+            subject: leaf,
+            predicate: "image".into(),
+            datum: Datum::String(file.to_string_lossy().into_owned()),
+        });
+
+        assert_eq!(subgraph(&projection, &[root], None).len(), 3);
+        let options = Options {
+            roots: vec![root],
+            depth: Some(1),
+            ..Options::default()
+        };
+        let out = dir.join("site");
+        let report = site(&projection, &options, &out).unwrap();
+        assert_eq!(
+            report,
+            Report {
+                pages: 2,
+                assets: 1
+            }
+        );
+
+        let page = std::fs::read_to_string(out.join(page_name(&leaf))).unwrap();
+        assert!(page.contains("<h1>Leaf &lt;b&gt;</h1>"));
+        // The diary is beyond the depth, so it is named but not linked.
+        assert!(page.contains("<td>Diary</td>"));
+        assert!(page.contains(&format!("<a href=\"{}\">Thesis</a>", page_name(&root))));
+        let asset = format!("assets/{}-image-0.png", leaf.simple());
+        assert!(page.contains(&format!("<img src=\"{}\"", asset)));
+        assert!(out.join(asset).is_file());
+
+        let index = std::fs::read_to_string(out.join("index.html")).unwrap();
+        assert!(index.contains("Thesis") && !index.contains("Unrelated"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
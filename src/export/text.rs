@@ -0,0 +1,193 @@
+//! Writes the graph as plain text meant to be kept under version control.
+//!
+//! The output is canonical: the same graph always gives the same files, byte
+//! for byte, so a diff between two exports shows what changed and nothing
+//! else. Each entity is a block of its facts under its id, with predicates in
+//! order and one value per line:
+//!
+//! ```text
+//! [4a1d6c2e-8a8f-4d5e-9b1c-2f7c1f0b3e11]
+//! due: 2024-07-01T09:30:00Z
+//! name: "Fix the roof"
+//! parent: <0d3a0c52-45b9-4c8e-b1f4-6a5f8f2b9c07>
+//! priority: 2
+//! ```
+//!
+//! Strings are quoted as in JSON, links are ids in angle brackets and floats
+//! always have a fraction or an exponent, so every value reads back as the
+//! type it was written as. See [`crate::import::text`] for the importer.
+
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::Datum;
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// The extension of the files written, the only ones read back or removed.
+pub const EXTENSION: &str = "graph";
+
+/// How entities are split into files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Split {
+    /// A file per entity, named by its id.
+    #[default]
+    Entity,
+    /// A file per value of the `type` predicate, e.g. `task.graph`.
+    Type,
+}
+
+impl FromStr for Split {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Split> {
+        match s {
+            "entity" => Ok(Split::Entity),
+            "type" => Ok(Split::Type),
+            _ => bail!("Unknown split {:?}, expected entity or type", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub entities: usize,
+    /// The files whose content changed.
+    pub written: usize,
+    /// The files of entities or types that are gone.
+    pub removed: usize,
+}
+
+/// Writes every entity of `projection` to `dir`. Files of earlier exports
+/// that are no longer needed are removed, unchanged ones are left alone.
+pub fn export(projection: &Projection, split: Split, dir: &Path) -> Result<Report> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let files = files(projection, split);
+
+    let mut report = Report {
+        entities: projection.entities().count(),
+        written: 0,
+        removed: 0,
+    };
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let stale = path.extension().is_some_and(|e| e == EXTENSION)
+            && path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_none_or(|name| !files.contains_key(name));
+        if stale {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            report.removed += 1;
+        }
+    }
+    for (name, content) in &files {
+        let path = dir.join(name);
+        if std::fs::read_to_string(&path).is_ok_and(|current| current == *content) {
+            continue;
+        }
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        report.written += 1;
+    }
+    Ok(report)
+}
+
+/// The content of each file, by file name.
+pub fn files(projection: &Projection, split: Split) -> BTreeMap<String, String> {
+    let mut entities: Vec<(&Uuid, &Entity)> = projection.entities().collect();
+    entities.sort_by_key(|(id, _)| **id);
+    let mut files: BTreeMap<String, String> = BTreeMap::new();
+    for (id, entity) in entities {
+        let name = match split {
+            Split::Entity => id.to_string(),
+            Split::Type => match entity.value("type") {
+                Some(Datum::String(kind)) => file_stem(kind),
+                _ => "untyped".to_string(),
+            },
+        };
+        let file = files.entry(format!("{}.{}", name, EXTENSION)).or_default();
+        if !file.is_empty() {
+            file.push('\n');
+        }
+        file.push_str(&block(id, entity));
+    }
+    files
+}
+
+/// The text of one entity.
+pub fn block(id: &Uuid, entity: &Entity) -> String {
+    let mut text = format!("[{}]\n", id);
+    for (predicate, values) in entity.facts() {
+        let predicate = if is_bare(predicate) {
+            predicate.to_string()
+        } else {
+            quote(predicate)
+        };
+        for datum in values {
+            let _ = writeln!(text, "{}: {}", predicate, value(datum));
+        }
+    }
+    text
+}
+
+/// A value, in the syntax [`crate::import::text`] reads.
+pub fn value(datum: &Datum) -> String {
+    match datum {
+        Datum::String(s) => quote(s),
+        Datum::Integer(n) => n.to_string(),
+        // Debug keeps a fraction on whole numbers and round-trips exactly.
+        Datum::Float(n) => format!("{:?}", n),
+        Datum::Boolean(b) => b.to_string(),
+        Datum::DateTime(t) => match OffsetDateTime::from_unix_timestamp(*t) {
+            Ok(t) if (0..=9999).contains(&t.year()) => format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                t.year(),
+                t.month() as u8,
+                t.day(),
+                t.hour(),
+                t.minute(),
+                t.second()
+            ),
+            _ => format!("datetime({})", t),
+        },
+        Datum::Entity(id) => format!("<{}>", id),
+    }
+}
+
+/// Whether `predicate` can be written without quotes.
+fn is_bare(predicate: &str) -> bool {
+    !predicate.is_empty()
+        && predicate
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-./".contains(c))
+}
+
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).expect("strings serialize")
+}
+
+/// `kind` made safe to use as a file name.
+fn file_stem(kind: &str) -> String {
+    let stem: String = kind
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if stem.is_empty() {
+        "untyped".to_string()
+    } else {
+        stem
+    }
+}
@@ -0,0 +1,276 @@
+//! Subscriptions to RSS and Atom feeds.
+//!
+//! A subscription is a `feed` entity with a `url` fact. Polling fetches every
+//! subscribed feed and records a `feed_item` entity per item, linked to its
+//! feed. Item ids are derived from the feed url and the item's GUID, so an
+//! item seen on many polls is only created once.
+
+use crate::import::{entity_id, upsert};
+use crate::legacy::async_storage::AsyncStorage;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum, EventCreator};
+use crate::scheduler::Scheduler;
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use time::format_description::well_known::{Rfc2822, Rfc3339};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    /// The RSS `guid` or Atom `id`, falling back to the link.
+    pub guid: String,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub published: Option<i64>,
+}
+
+/// Fetches the body of a feed.
+pub trait Fetch {
+    fn fetch(&self, url: &str) -> Result<String>;
+}
+
+/// Fetches feeds over HTTP(S).
+pub struct Http;
+
+impl Fetch for Http {
+    fn fetch(&self, url: &str) -> Result<String> {
+        ureq::get(url)
+            .call()
+            .with_context(|| format!("Failed to fetch {}", url))?
+            .into_string()
+            .with_context(|| format!("Failed to read {}", url))
+    }
+}
+
+pub fn feed_entity(url: &str) -> Uuid {
+    entity_id(&format!("feed:{}", url))
+}
+
+pub fn item_entity(feed_url: &str, guid: &str) -> Uuid {
+    entity_id(&format!("feed-item:{}:{}", feed_url, guid))
+}
+
+/// Returns the actions that subscribe to the feed at `url`.
+pub fn subscribe(projection: &Projection, url: &str) -> Vec<Action> {
+    let mut facts = BTreeMap::new();
+    facts.insert("type".to_string(), vec![Datum::String("feed".into())]);
+    facts.insert("url".to_string(), vec![Datum::String(url.to_string())]);
+    upsert(projection, feed_entity(url), &facts)
+}
+
+/// Returns the id and url of every subscribed feed.
+pub fn subscriptions(projection: &Projection) -> Vec<(Uuid, String)> {
+    projection
+        .entities()
+        .filter(|(_, e)| e.value("type") == Some(&Datum::String("feed".into())))
+        .filter_map(|(id, e)| match e.value("url") {
+            Some(Datum::String(url)) => Some((*id, url.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parses an RSS 2.0 or Atom document.
+pub fn parse(xml: &str) -> Result<Vec<Item>> {
+    let document = roxmltree::Document::parse(xml).context("Failed to parse feed")?;
+    let root = document.root_element();
+    let child = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|c| c.tag_name().name() == name)
+            .and_then(|c| c.text())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+    };
+
+    let items = match root.tag_name().name() {
+        "rss" => root
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .filter_map(|item| {
+                let url = child(item, "link");
+                Some(Item {
+                    guid: child(item, "guid").or_else(|| url.clone())?,
+                    title: child(item, "title"),
+                    published: child(item, "pubDate")
+                        .and_then(|d| OffsetDateTime::parse(&d, &Rfc2822).ok())
+                        .map(|d| d.unix_timestamp()),
+                    url,
+                })
+            })
+            .collect(),
+        "feed" => root
+            .children()
+            .filter(|n| n.tag_name().name() == "entry")
+            .filter_map(|entry| {
+                let url = entry
+                    .children()
+                    .filter(|c| c.tag_name().name() == "link")
+                    .find(|c| matches!(c.attribute("rel"), None | Some("alternate")))
+                    .and_then(|c| c.attribute("href"))
+                    .map(str::to_string);
+                Some(Item {
+                    guid: child(entry, "id").or_else(|| url.clone())?,
+                    title: child(entry, "title"),
+                    published: child(entry, "published")
+                        .or_else(|| child(entry, "updated"))
+                        .and_then(|d| OffsetDateTime::parse(&d, &Rfc3339).ok())
+                        .map(|d| d.unix_timestamp()),
+                    url,
+                })
+            })
+            .collect(),
+        other => return Err(anyhow!("Unknown feed format <{}>", other)),
+    };
+    Ok(items)
+}
+
+/// Returns the actions that record `items` of the feed at `feed_url`.
+pub fn ingest(projection: &Projection, feed_url: &str, items: &[Item]) -> Vec<Action> {
+    let feed = feed_entity(feed_url);
+    items
+        .iter()
+        .flat_map(|item| {
+            let mut facts = BTreeMap::new();
+            facts.insert("type".to_string(), vec![Datum::String("feed_item".into())]);
+            facts.insert("guid".to_string(), vec![Datum::String(item.guid.clone())]);
+            facts.insert("feed".to_string(), vec![Datum::Entity(feed)]);
+            facts.insert(
+                "title".to_string(),
+                item.title.iter().cloned().map(Datum::String).collect(),
+            );
+            facts.insert(
+                "url".to_string(),
+                item.url.iter().cloned().map(Datum::String).collect(),
+            );
+            facts.insert(
+                "published".to_string(),
+                item.published.into_iter().map(Datum::DateTime).collect(),
+            );
+            upsert(projection, item_entity(feed_url, &item.guid), &facts)
+        })
+        .collect()
+}
+
+/// Fetches every subscribed feed and records new and changed items.
+///
+/// A feed that fails to load does not stop the others from being polled; the
+/// failures are returned together once everything else has been recorded.
+pub fn poll(storage: &AsyncStorage, creator: &mut EventCreator, fetch: &impl Fetch) -> Result<()> {
+    let projection = futures::executor::block_on(storage.call(|s| Projection::load(s)))?;
+
+    let mut actions = Vec::new();
+    let mut failures = Vec::new();
+    for (_, url) in subscriptions(&projection) {
+        match fetch.fetch(&url).and_then(|xml| parse(&xml)) {
+            Ok(items) => actions.extend(ingest(&projection, &url, &items)),
+            Err(e) => failures.push(format!("{}: {:#}", url, e)),
+        }
+    }
+
+    if !actions.is_empty() {
+        let event = creator.create(Action::Transaction { actions });
+        futures::executor::block_on(storage.record(event))?;
+    }
+    if !failures.is_empty() {
+        return Err(anyhow!("Failed to poll feeds: {}", failures.join("; ")));
+    }
+    Ok(())
+}
+
+/// How often subscribed feeds are polled.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Adds a job to `scheduler` that polls the subscribed feeds over HTTP.
+pub fn schedule(
+    scheduler: Scheduler,
+    storage: AsyncStorage,
+    mut creator: EventCreator,
+) -> Scheduler {
+    scheduler.every("feeds", POLL_INTERVAL, move || {
+        poll(&storage, &mut creator, &Http)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use futures::executor::block_on;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+        <rss version="2.0"><channel><title>Blog</title>
+            <item>
+                <title>First</title>
+                <link>https://example.com/1</link>
+                <guid>urn:1</guid>
+                <pubDate>Mon, 01 Jan 2024 10:00:00 +0000</pubDate>
+            </item>
+            <item><title>No guid</title><link>https://example.com/2</link></item>
+        </channel></rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0"?>
+        <feed xmlns="http://www.w3.org/2005/Atom">
+            <entry>
+                <id>tag:example.com,2024:1</id>
+                <title>Entry</title>
+                <link rel="self" href="https://example.com/self"/>
+                <link href="https://example.com/entry"/>
+                <updated>2024-01-01T10:00:00Z</updated>
+            </entry>
+        </feed>"#;
+
+    struct Static;
+
+    impl Fetch for Static {
+        fn fetch(&self, url: &str) -> Result<String> {
+            match url {
+                "rss" => Ok(RSS.to_string()),
+                _ => Err(anyhow!("not found")),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_rss_and_atom() {
+        let rss = parse(RSS).unwrap();
+        assert_eq!(rss.len(), 2);
+        assert_eq!(rss[0].guid, "urn:1");
+        assert_eq!(rss[0].published, Some(1704103200));
+        assert_eq!(rss[1].guid, "https://example.com/2");
+
+        let atom = parse(ATOM).unwrap();
+        assert_eq!(atom[0].guid, "tag:example.com,2024:1");
+        assert_eq!(atom[0].url.as_deref(), Some("https://example.com/entry"));
+        assert_eq!(atom[0].published, Some(1704103200));
+
+        assert!(parse("<html/>").is_err());
+    }
+
+    #[test]
+    fn polling_twice_does_not_duplicate_items() {
+        let storage = AsyncStorage::open(":memory:").unwrap();
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let subscriptions = ["rss", "missing"]
+            .map(|url| {
+                creator.create(Action::Transaction {
+                    actions: subscribe(&Projection::new(), url),
+                })
+            })
+            .to_vec();
+        block_on(storage.record_batch(subscriptions)).unwrap();
+
+        // The missing feed is reported, but the other one is still recorded.
+        assert!(poll(&storage, &mut creator, &Static).is_err());
+        assert!(poll(&storage, &mut creator, &Static).is_err());
+
+        let projection = block_on(storage.call(|s| Projection::load(s))).unwrap();
+        assert_eq!(projection.len(), 4);
+        let item = projection.entity(&item_entity("rss", "urn:1")).unwrap();
+        assert_eq!(item.value("feed"), Some(&Datum::Entity(feed_entity("rss"))));
+        // Two subscriptions and one poll; the second poll found nothing new.
+        assert_eq!(block_on(storage.events()).unwrap().len(), 3);
+    }
+}
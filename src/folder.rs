@@ -0,0 +1,295 @@
+//! Sync through a shared folder, kept in sync between devices by a tool
+//! like Dropbox or Syncthing.
+//!
+//! Each device writes only to its own directory in the folder, named after
+//! its actor, and never changes a file once written: every sync adds a file
+//! with the events recorded since the last one, as JSON lines, named after
+//! the range of their timestamps, e.g. `1700000000+0_1700000360+2.jsonl`.
+//! Files are written under a hidden name and renamed when complete, so the
+//! sync tool never copies half a file. Syncing reads the files of the other
+//! devices not read before, merges their events, which skips those already
+//! recorded, see [`crate::conflicts::merge`], then writes the file of this
+//! device. Hidden files and anything but `.jsonl` files are ignored, as are
+//! files that fail to parse, e.g. while the sync tool copies them, until the
+//! next sync.
+//!
+//! Once the subscription changes, every file is read again, see
+//! [`crate::replication::read_with`].
+
+use crate::conflicts;
+use crate::legacy::hlc::HLTimestamp;
+use crate::legacy::storage::{metadata, Event, EventStorage};
+use crate::replication::{self, Subscription};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{info, instrument, warn};
+
+/// The change written last, see [`EventStorage::poll_changes`].
+const WRITTEN: &str = "folder_written";
+/// The files of other devices read, as a JSON list of `actor/file` paths.
+const READ: &str = "folder_read";
+/// The subscription the files were read with.
+const SUBSCRIPTION: &str = "folder_subscription";
+/// The extension of event files.
+const EXTENSION: &str = "jsonl";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The shared folder, none to not sync through one.
+    pub path: Option<PathBuf>,
+    /// How often the editor syncs, in minutes, 0 for never.
+    pub interval: u64,
+}
+
+/// What syncing through the folder did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Synced {
+    pub read: usize,
+    pub written: usize,
+    pub conflicts: usize,
+}
+
+/// Merges the events of the other devices in `folder` into `storage`, then
+/// adds a file with those recorded since the last sync.
+#[instrument(skip(storage, subscription))]
+pub fn sync(
+    storage: &mut EventStorage,
+    folder: &Path,
+    subscription: &Subscription,
+) -> Result<Synced> {
+    let actor = storage.local_actor()?.simple().to_string();
+    let mut synced = Synced::default();
+
+    let mut read: BTreeSet<String> = match storage.meta_value(READ)? {
+        // Read again what another subscription left out.
+        Some(_) if replication::read_with(storage, SUBSCRIPTION)? != *subscription => {
+            BTreeSet::new()
+        }
+        Some(json) => serde_json::from_str(&json).context("Invalid list of files read")?,
+        None => BTreeSet::new(),
+    };
+    let mut events = Vec::new();
+    for (device, path) in files(folder)? {
+        let key = format!("{}/{}", device, file_name(&path));
+        if device == actor || read.contains(&key) {
+            continue;
+        }
+        match parse(&path) {
+            Ok(parsed) => {
+                let origin = format!("folder/{}", device);
+                events.extend(parsed.into_iter().map(|e| e.synced_from(&origin)));
+                read.insert(key);
+            }
+            Err(e) => warn!("Skipping {} for now: {:#}", path.display(), e),
+        }
+    }
+    let events = replication::select_for(storage, subscription, events)?;
+    (synced.read, synced.conflicts) = conflicts::merge(storage, events)?;
+    storage.set_meta_value(READ, &serde_json::to_string(&read)?)?;
+    replication::set_read_with(storage, SUBSCRIPTION, subscription)?;
+
+    // Everything the first time, then what was recorded since.
+    let (events, last) = match storage.meta_value(WRITTEN)? {
+        Some(since) => {
+            let since = since.parse().context("Invalid folder change")?;
+            let changes = storage.poll_changes(since)?;
+            (changes.events, changes.last)
+        }
+        None => {
+            let last = storage.last_change()?;
+            let mut events = Vec::new();
+            storage.play(|event| {
+                events.push(event);
+                Ok(())
+            })?;
+            (events, last)
+        }
+    };
+    let events: Vec<Event> = events
+        .into_iter()
+        .filter(|e| {
+            let origin = e.metadata().get(metadata::SYNC_ORIGIN);
+            !origin.is_some_and(|o| o.starts_with("folder/"))
+        })
+        .collect();
+    if !events.is_empty() {
+        write(&folder.join(&actor), &events)?;
+        synced.written = events.len();
+    }
+    storage.set_meta_value(WRITTEN, &last.to_string())?;
+    info!(
+        read = synced.read,
+        written = synced.written,
+        conflicts = synced.conflicts,
+        "Synced through the folder"
+    );
+    Ok(synced)
+}
+
+/// The event files in `folder`, by the directory of their device, in the
+/// order they were written.
+fn files(folder: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let devices = fs::read_dir(folder)
+        .with_context(|| format!("Failed to read the shared folder {}", folder.display()))?;
+    for device in devices {
+        let device = device?;
+        let name = device.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !device.file_type()?.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(device.path())? {
+            let path = file?.path();
+            let hidden = file_name(&path).starts_with('.');
+            if !hidden && path.extension().is_some_and(|e| e == EXTENSION) {
+                files.push((name.clone(), path));
+            }
+        }
+    }
+    files.sort_by_key(|(device, path)| (device.clone(), range(path)));
+    Ok(files)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The range of timestamps in the name of an event file.
+fn range(path: &Path) -> Option<(HLTimestamp, HLTimestamp)> {
+    let stem = path.file_stem()?.to_str()?;
+    let (first, last) = stem.split_once('_')?;
+    let last = last.split_once('-').map_or(last, |(last, _)| last);
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+fn parse(path: &Path) -> Result<Vec<Event>> {
+    let content = fs::read_to_string(path)?;
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_str(line).context("Invalid event"))
+        .collect()
+}
+
+/// Writes `events` to a new file in `dir`, under a hidden name first.
+fn write(dir: &Path, events: &[Event]) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let first = events
+        .iter()
+        .map(Event::hlc)
+        .min()
+        .expect("events to write");
+    let last = events
+        .iter()
+        .map(Event::hlc)
+        .max()
+        .expect("events to write");
+    let stem = format!("{}_{}", first, last);
+    let mut path = dir.join(format!("{}.{}", stem, EXTENSION));
+    // Ranges only repeat if the clock went back.
+    for n in 2.. {
+        if !path.exists() {
+            break;
+        }
+        path = dir.join(format!("{}-{}.{}", stem, n, EXTENSION));
+    }
+    let hidden = dir.join(format!(".{}.tmp", file_name(&path)));
+    let mut file = fs::File::create(&hidden)
+        .with_context(|| format!("Failed to create {}", hidden.display()))?;
+    for event in events {
+        serde_json::to_writer(&mut file, event)?;
+        file.write_all(b"\n")?;
+    }
+    file.sync_all()?;
+    fs::rename(&hidden, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+    use uuid::Uuid;
+
+    #[test]
+    fn devices_converge_through_the_folder() {
+        let folder = std::env::temp_dir().join(format!("graphite-folder-{}", Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let mut devices = [(); 2].map(|_| EventStorage::open(":memory:").unwrap());
+        let everything = Subscription::default();
+        let create = |storage: &mut EventStorage| {
+            let mut creator = storage.creator().unwrap();
+            let event = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+            storage.record(event).unwrap();
+        };
+        let count = |storage: &EventStorage| {
+            let mut count = 0;
+            storage
+                .play(|_| {
+                    count += 1;
+                    Ok(())
+                })
+                .unwrap();
+            count
+        };
+
+        create(&mut devices[0]);
+        let synced = sync(&mut devices[0], &folder, &everything).unwrap();
+        assert_eq!((synced.read, synced.written), (0, 1));
+        create(&mut devices[1]);
+        // A file being copied in, and a half written one.
+        let other = folder.join(devices[0].local_actor().unwrap().simple().to_string());
+        fs::write(other.join(".partial.jsonl.tmp"), "{").unwrap();
+        fs::write(other.join("1+0_2+0.jsonl"), "{").unwrap();
+        let synced = sync(&mut devices[1], &folder, &everything).unwrap();
+        assert_eq!((synced.read, synced.written), (1, 1));
+
+        fs::remove_file(other.join("1+0_2+0.jsonl")).unwrap();
+        let synced = sync(&mut devices[0], &folder, &everything).unwrap();
+        assert_eq!((synced.read, synced.written), (1, 0));
+        let synced = sync(&mut devices[1], &folder, &everything).unwrap();
+        assert_eq!(synced, Synced::default());
+        assert_eq!((count(&devices[0]), count(&devices[1])), (2, 2));
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn widening_the_subscription_reads_what_it_adds() {
+        let folder = std::env::temp_dir().join(format!("graphite-folder-{}", Uuid::new_v4()));
+        fs::create_dir_all(&folder).unwrap();
+        let [mut laptop, mut phone] = [(); 2].map(|_| EventStorage::open(":memory:").unwrap());
+        let mut creator = laptop.creator().unwrap();
+        let mut events = Vec::new();
+        for name in ["Graphite", "Chores"] {
+            let id = Uuid::new_v4();
+            events.push(creator.create(Action::CreateEntity { id }));
+            events.push(creator.create(Action::AddFact {
+                subject: id,
+                predicate: "project".to_string(),
+                datum: crate::legacy::storage::Datum::String(name.to_string()),
+            }));
+        }
+        laptop.record_batch(events).unwrap();
+        sync(&mut laptop, &folder, &Subscription::default()).unwrap();
+
+        let mut subscription = Subscription {
+            queries: vec!["project=Graphite".to_string()],
+            depth: 0,
+        };
+        let synced = sync(&mut phone, &folder, &subscription).unwrap();
+        assert_eq!(synced.read, 2);
+        let synced = sync(&mut phone, &folder, &subscription).unwrap();
+        assert_eq!(synced.read, 0);
+        subscription.queries.push("project=Chores".to_string());
+        let synced = sync(&mut phone, &folder, &subscription).unwrap();
+        assert_eq!(synced.read, 2);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,693 @@
+//! The graph canvas of the editor: each entity is a node at its `x` and `y`
+//! facts, see [`crate::selection::positions`], and those without a position
+//! are laid out on a grid below the others, as in the HTML explorer.
+//!
+//! Large graphs stay responsive because only what is in view is drawn: the
+//! nodes in it are found through a [`QuadTree`], zoomed out far they are
+//! merged into clusters drawn with their count, and labels are hidden when
+//! they would be too small or too many to read.
+//!
+//! Entities can be grouped into containers, see [`groups`].
+
+pub mod animation;
+pub mod edges;
+pub mod focus;
+pub mod groups;
+pub mod layout;
+pub mod minimap;
+pub mod quadtree;
+pub mod snapshot;
+pub mod style;
+
+pub use groups::{Group, Hierarchy};
+pub use minimap::Minimap;
+pub use quadtree::QuadTree;
+pub use style::{Rules, Shape, Style};
+
+use crate::export::label;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use crate::selection::positions;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+/// The distance between entities laid out on the grid.
+pub const GRID: f32 = 120.0;
+/// The radius of a node at zoom 1, in logical pixels.
+pub const RADIUS: f32 = 8.0;
+/// Below this zoom nodes are merged into clusters.
+pub const CLUSTER_ZOOM: f32 = 0.25;
+/// The size of the cells of the screen nodes are clustered in, in logical
+/// pixels.
+pub const CLUSTER_CELL: f32 = 40.0;
+/// Below this zoom labels are hidden.
+pub const LABEL_ZOOM: f32 = 0.5;
+/// Labels are hidden when more nodes than this are in view.
+pub const MAX_LABELS: usize = 400;
+/// The smallest radius nodes are drawn with, however far zoomed out, in
+/// screen pixels.
+pub const MIN_RADIUS: f32 = 2.0;
+/// The radius of a cluster of a few nodes, in screen pixels.
+pub const CLUSTER_RADIUS: f32 = 10.0;
+
+/// A rectangle of the canvas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Rect {
+    /// The smallest rectangle around `points`, if there are any.
+    pub fn around(points: impl IntoIterator<Item = (f32, f32)>) -> Option<Rect> {
+        points.into_iter().fold(None, |rect, (x, y)| {
+            Some(match rect {
+                None => Rect {
+                    left: x,
+                    top: y,
+                    right: x,
+                    bottom: y,
+                },
+                Some(r) => Rect {
+                    left: r.left.min(x),
+                    top: r.top.min(y),
+                    right: r.right.max(x),
+                    bottom: r.bottom.max(y),
+                },
+            })
+        })
+    }
+
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f32 {
+        self.bottom - self.top
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (
+            (self.left + self.right) / 2.0,
+            (self.top + self.bottom) / 2.0,
+        )
+    }
+
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        self.left <= x && x <= self.right && self.top <= y && y <= self.bottom
+    }
+
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        self.left <= other.left
+            && other.right <= self.right
+            && self.top <= other.top
+            && other.bottom <= self.bottom
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.left <= other.right
+            && other.left <= self.right
+            && self.top <= other.bottom
+            && other.top <= self.bottom
+    }
+
+    /// The rectangle grown by `margin` on every side.
+    pub fn expand(&self, margin: f32) -> Rect {
+        Rect {
+            left: self.left - margin,
+            top: self.top - margin,
+            right: self.right + margin,
+            bottom: self.bottom + margin,
+        }
+    }
+}
+
+/// The part of the canvas shown: the point at the center of the screen and
+/// how many screen pixels a canvas pixel takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Viewport {
+        Viewport {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Viewport {
+    /// The viewport showing all of `rect` on a screen of `size`.
+    pub fn fit(rect: &Rect, size: (f32, f32)) -> Viewport {
+        let rect = rect.expand(GRID / 2.0);
+        let (x, y) = rect.center();
+        let zoom = (size.0 / rect.width()).min(size.1 / rect.height());
+        Viewport {
+            x,
+            y,
+            zoom: if zoom.is_finite() && zoom > 0.0 {
+                zoom
+            } else {
+                1.0
+            },
+        }
+    }
+
+    /// Where `point` of the canvas is on a screen of `size`.
+    pub fn to_screen(&self, (x, y): (f32, f32), size: (f32, f32)) -> (f32, f32) {
+        (
+            (x - self.x) * self.zoom + size.0 / 2.0,
+            (y - self.y) * self.zoom + size.1 / 2.0,
+        )
+    }
+
+    /// The point of the canvas at `point` of a screen of `size`.
+    pub fn to_canvas(&self, (x, y): (f32, f32), size: (f32, f32)) -> (f32, f32) {
+        (
+            (x - size.0 / 2.0) / self.zoom + self.x,
+            (y - size.1 / 2.0) / self.zoom + self.y,
+        )
+    }
+
+    /// The part of the canvas on a screen of `size`.
+    pub fn visible(&self, size: (f32, f32)) -> Rect {
+        let (left, top) = self.to_canvas((0.0, 0.0), size);
+        let (right, bottom) = self.to_canvas(size, size);
+        Rect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Moves the canvas by `dx` and `dy` screen pixels.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.x -= dx / self.zoom;
+        self.y -= dy / self.zoom;
+    }
+
+    /// The radius nodes are drawn with, in screen pixels.
+    pub fn node_radius(&self) -> f32 {
+        (RADIUS * self.zoom).max(MIN_RADIUS)
+    }
+
+    /// Zooms by `factor`, between the `min` and `max` zoom, keeping the
+    /// point `offset` screen pixels from the center where it is, such as the
+    /// one under the cursor.
+    pub fn zoom_at(&mut self, factor: f32, offset: (f32, f32), (min, max): (f32, f32)) {
+        let zoom = (self.zoom * factor).clamp(min, max);
+        self.x += offset.0 / self.zoom - offset.0 / zoom;
+        self.y += offset.1 / self.zoom - offset.1 / zoom;
+        self.zoom = zoom;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub id: Uuid,
+    pub label: String,
+    pub x: f32,
+    pub y: f32,
+    pub style: Style,
+    /// The entities it stands in for as a collapsed container.
+    pub members: usize,
+}
+
+/// A fact linking two entities of the graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    /// The index of the subject.
+    pub from: usize,
+    /// The index of the entity it links to.
+    pub to: usize,
+    pub predicate: String,
+    /// Whether it is drawn with an arrowhead, see [`Settings::undirected`].
+    pub directed: bool,
+}
+
+/// Nodes in a cell of the screen, drawn as one when zoomed out far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cluster {
+    /// The average position of its nodes.
+    pub x: f32,
+    pub y: f32,
+    pub count: usize,
+}
+
+impl Cluster {
+    /// The radius it is drawn with, in screen pixels, growing with the
+    /// logarithm of its count.
+    pub fn radius(&self) -> f32 {
+        CLUSTER_RADIUS * (1.0 + (self.count as f32).log10())
+    }
+}
+
+/// An edge to draw, between nodes or clusters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    pub directed: bool,
+    /// The radius of what it points to, in screen pixels, which its
+    /// arrowhead stops short of.
+    pub inset: f32,
+}
+
+/// What to draw of a graph in a viewport.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    /// The indices of the nodes drawn on their own, in order.
+    pub nodes: Vec<usize>,
+    pub clusters: Vec<Cluster>,
+    /// The edges in view. Edges between the nodes of two clusters are drawn
+    /// once, and those within a cluster not at all.
+    pub segments: Vec<Segment>,
+    /// The indices of the groups in view, outer ones first.
+    pub groups: Vec<usize>,
+    /// Whether the nodes are drawn with their labels.
+    pub labels: bool,
+}
+
+/// How the canvas draws the graph, the `[canvas]` table of the config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Whether edges are drawn as curves, so the facts two entities link
+    /// each other with don't overlap.
+    pub curved_edges: bool,
+    /// The predicates whose facts go both ways, such as `related`, drawn
+    /// without an arrowhead.
+    pub undirected: Vec<String>,
+    /// Whether the map of the whole graph is shown in a corner.
+    pub minimap: bool,
+    /// How many links away from the entities focused on the entities shown
+    /// are, see [`focus`].
+    pub focus_hops: usize,
+    /// How far the canvas zooms out and in, in percent.
+    pub min_zoom: u32,
+    pub max_zoom: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            curved_edges: false,
+            undirected: Vec::new(),
+            minimap: true,
+            focus_hops: 1,
+            min_zoom: 1,
+            max_zoom: 800,
+        }
+    }
+}
+
+impl Settings {
+    /// The smallest and largest zoom.
+    pub fn zoom_limits(&self) -> (f32, f32) {
+        let min = self.min_zoom.max(1);
+        (min as f32 / 100.0, self.max_zoom.max(min) as f32 / 100.0)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    index: QuadTree,
+    bounds: Option<Rect>,
+    minimap: Minimap,
+    hierarchy: Hierarchy,
+    groups: Vec<Group>,
+}
+
+impl Graph {
+    /// The graph of the entities of `projection` shown by `rules` and laid
+    /// out as they say, or of those of them in `only`, with those in the `collapsed` containers
+    /// drawn as their container. `part-of` facts are drawn as the boxes of
+    /// the containers rather than edges.
+    pub fn new(
+        projection: &Projection,
+        settings: &Settings,
+        rules: &Rules,
+        only: Option<&BTreeSet<Uuid>>,
+        collapsed: &BTreeSet<Uuid>,
+    ) -> Graph {
+        let shown: HashSet<Uuid> = projection
+            .entities()
+            .filter(|(id, entity)| {
+                only.is_none_or(|only| only.contains(id)) && rules.shows(projection, entity)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        let hierarchy = Hierarchy::new(projection);
+        let mut members: HashMap<Uuid, usize> = HashMap::new();
+        let mut standing_in: HashMap<Uuid, Uuid> = HashMap::new();
+        for id in &shown {
+            if let Some(container) = hierarchy
+                .collapsed_in(id, collapsed)
+                .filter(|c| shown.contains(c))
+            {
+                *members.entry(container).or_default() += 1;
+                standing_in.insert(*id, container);
+            }
+        }
+        let mut ids: Vec<Uuid> = shown
+            .iter()
+            .filter(|id| !standing_in.contains_key(id))
+            .copied()
+            .collect();
+        ids.sort_by_cached_key(|id| (label(projection, id).to_lowercase(), *id));
+        let placed = positions(projection, &ids);
+        let bottom = placed.values().map(|(_, y)| *y).fold(0.0, f32::max) + GRID;
+        let columns = (ids.len() as f64).sqrt().ceil().max(1.0) as usize;
+        let mut unplaced = 0;
+        let index_of: HashMap<Uuid, usize> =
+            ids.iter().enumerate().map(|(n, id)| (*id, n)).collect();
+        // The node an entity is drawn as.
+        let node_of = |id: &Uuid| index_of.get(standing_in.get(id).unwrap_or(id)).copied();
+        let mut sources: Vec<&Uuid> = shown.iter().collect();
+        sources.sort_unstable();
+        let mut edges = Vec::new();
+        let mut drawn = HashSet::new();
+        for id in sources {
+            let Some(from) = node_of(id) else {
+                continue;
+            };
+            for (predicate, values) in projection.entity(id).into_iter().flat_map(|e| e.facts()) {
+                if predicate == groups::PART_OF {
+                    continue;
+                }
+                for value in values {
+                    let Datum::Entity(target) = value else {
+                        continue;
+                    };
+                    let Some(to) = node_of(target).filter(|to| *to != from) else {
+                        continue;
+                    };
+                    if drawn.insert((from, to, predicate)) {
+                        edges.push(Edge {
+                            from,
+                            to,
+                            predicate: predicate.to_string(),
+                            directed: !settings.undirected.iter().any(|p| p == predicate),
+                        });
+                    }
+                }
+            }
+        }
+        edges.sort_by_key(|edge| (edge.from, edge.to));
+        let laid_out = rules.layout().place(ids.len(), &edges);
+        let nodes: Vec<Node> = ids
+            .into_iter()
+            .enumerate()
+            .map(|(n, id)| {
+                let position = match &laid_out {
+                    Some(laid_out) => Some(laid_out[n]),
+                    None => placed.get(&id).copied(),
+                };
+                let (x, y) = position.unwrap_or_else(|| {
+                    let at = unplaced;
+                    unplaced += 1;
+                    (
+                        (at % columns) as f32 * GRID,
+                        bottom + (at / columns) as f32 * GRID,
+                    )
+                });
+                let style = match projection.entity(&id) {
+                    Some(entity) => rules.style(projection, entity),
+                    None => Style::default(),
+                };
+                Node {
+                    id,
+                    label: label(projection, &id),
+                    x,
+                    y,
+                    style,
+                    members: members.get(&id).copied().unwrap_or(0),
+                }
+            })
+            .collect();
+        let groups = groups::boxes(&hierarchy, &nodes);
+        Graph {
+            hierarchy,
+            groups,
+            ..Graph::from_parts(nodes, edges)
+        }
+    }
+
+    pub fn from_parts(nodes: Vec<Node>, edges: Vec<Edge>) -> Graph {
+        let points: Vec<(f32, f32)> = nodes.iter().map(|n| (n.x, n.y)).collect();
+        Graph {
+            bounds: Rect::around(points.iter().copied()),
+            index: QuadTree::new(points),
+            minimap: Minimap::new(&nodes),
+            nodes,
+            edges,
+            hierarchy: Hierarchy::default(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Moves each node to its position in `positions`.
+    pub fn set_positions(&mut self, positions: &[(f32, f32)]) {
+        for (node, (x, y)) in self.nodes.iter_mut().zip(positions) {
+            (node.x, node.y) = (*x, *y);
+        }
+        let points: Vec<(f32, f32)> = self.nodes.iter().map(|n| (n.x, n.y)).collect();
+        self.bounds = Rect::around(points.iter().copied());
+        self.index = QuadTree::new(points);
+        self.minimap = Minimap::new(&self.nodes);
+        self.groups = groups::boxes(&self.hierarchy, &self.nodes);
+    }
+
+    /// Changes how each node is drawn, see [`crate::wasm::Rendered`]. It
+    /// must leave where they are alone.
+    pub fn restyle(&mut self, mut restyle: impl FnMut(&mut Node)) {
+        for node in &mut self.nodes {
+            restyle(node);
+        }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The rectangle around all nodes, if there are any.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    /// The node at `point` of the canvas, the closest within `radius` of
+    /// it.
+    pub fn node_at(&self, point: (f32, f32), radius: f32) -> Option<usize> {
+        self.index.nearest(point, radius)
+    }
+
+    pub fn minimap(&self) -> &Minimap {
+        &self.minimap
+    }
+
+    /// The boxes of the expanded containers, outer ones first.
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    /// What to draw in `viewport` on a screen of `size`.
+    pub fn scene(&self, viewport: &Viewport, size: (f32, f32)) -> Scene {
+        // Nodes just outside still show their edge.
+        let margin = RADIUS.max(CLUSTER_CELL / viewport.zoom);
+        let area = viewport.visible(size).expand(margin);
+        let mut nodes = Vec::new();
+        self.index.query(&area, &mut nodes);
+        nodes.sort_unstable();
+        let in_view = |from: (f32, f32), to: (f32, f32)| {
+            Rect::around([from, to]).is_some_and(|r| r.intersects(&area))
+        };
+        let groups = (0..self.groups.len())
+            .filter(|g| self.groups[*g].bounds.intersects(&area))
+            .collect();
+        if viewport.zoom >= CLUSTER_ZOOM {
+            let segments = self
+                .edges
+                .iter()
+                .map(|edge| Segment {
+                    from: self.position(edge.from),
+                    to: self.position(edge.to),
+                    directed: edge.directed,
+                    inset: viewport.node_radius() * self.nodes[edge.to].style.size,
+                })
+                .filter(|segment| in_view(segment.from, segment.to))
+                .collect();
+            return Scene {
+                labels: viewport.zoom >= LABEL_ZOOM && nodes.len() <= MAX_LABELS,
+                nodes,
+                clusters: Vec::new(),
+                segments,
+                groups,
+            };
+        }
+        // The cells are fixed on the canvas, so clusters don't change when
+        // panning.
+        let cell = CLUSTER_CELL / viewport.zoom;
+        let key = |n: usize| {
+            let node = &self.nodes[n];
+            (
+                (node.x / cell).floor() as i64,
+                (node.y / cell).floor() as i64,
+            )
+        };
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for n in nodes {
+            cells.entry(key(n)).or_default().push(n);
+        }
+        let mut cells: Vec<((i64, i64), Vec<usize>)> = cells.into_iter().collect();
+        cells.sort_unstable_by_key(|(key, _)| *key);
+        let mut scene = Scene {
+            groups,
+            ..Scene::default()
+        };
+        let mut clusters = HashMap::new();
+        for (key, members) in cells {
+            match members[..] {
+                [n] => scene.nodes.push(n),
+                _ => {
+                    let count = members.len();
+                    let (x, y) = members.iter().fold((0.0, 0.0), |(x, y), n| {
+                        (x + self.nodes[*n].x, y + self.nodes[*n].y)
+                    });
+                    let cluster = Cluster {
+                        x: x / count as f32,
+                        y: y / count as f32,
+                        count,
+                    };
+                    clusters.insert(key, cluster);
+                    scene.clusters.push(cluster);
+                }
+            }
+        }
+        scene.nodes.sort_unstable();
+        // Where an edge ends: the cluster of its node if it is in view,
+        // else the node.
+        let end = |n: usize| match clusters.get(&key(n)) {
+            Some(cluster) => ((cluster.x, cluster.y), cluster.radius()),
+            None => (
+                self.position(n),
+                viewport.node_radius() * self.nodes[n].style.size,
+            ),
+        };
+        let mut drawn = HashSet::new();
+        for edge in &self.edges {
+            let (from, to) = (key(edge.from), key(edge.to));
+            if from == to || !drawn.insert((from, to, edge.directed)) {
+                continue;
+            }
+            let ((from, _), (to, inset)) = (end(edge.from), end(edge.to));
+            if in_view(from, to) {
+                scene.segments.push(Segment {
+                    from,
+                    to,
+                    directed: edge.directed,
+                    inset,
+                });
+            }
+        }
+        scene
+    }
+
+    fn position(&self, n: usize) -> (f32, f32) {
+        (self.nodes[n].x, self.nodes[n].y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(side: usize) -> Graph {
+        Graph::from_parts(
+            (0..side * side)
+                .map(|n| Node {
+                    id: Uuid::from_u128(n as u128),
+                    label: n.to_string(),
+                    x: (n % side) as f32 * 20.0,
+                    y: (n / side) as f32 * 20.0,
+                    style: Style::default(),
+                    members: 0,
+                })
+                .collect(),
+            (0..side * side)
+                .filter(|n| (n + 1) % side != 0)
+                .map(|n| Edge {
+                    from: n,
+                    to: n + 1,
+                    predicate: "next".to_string(),
+                    directed: true,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn draws_only_what_is_in_view() {
+        let graph = grid(250);
+        let size = (400.0, 300.0);
+
+        let close = Viewport {
+            x: 100.0,
+            y: 100.0,
+            zoom: 2.0,
+        };
+        let scene = graph.scene(&close, size);
+        let area = close.visible(size).expand(RADIUS.max(CLUSTER_CELL / 2.0));
+        let expected: Vec<usize> = (0..graph.nodes().len())
+            .filter(|n| area.contains((graph.nodes()[*n].x, graph.nodes()[*n].y)))
+            .collect();
+        assert_eq!(scene.nodes, expected);
+        assert!(scene.clusters.is_empty());
+        assert!(scene.labels);
+        let crossing = graph
+            .edges()
+            .iter()
+            .filter(|e| {
+                let (from, to) = (&graph.nodes()[e.from], &graph.nodes()[e.to]);
+                let edge = Rect::around([(from.x, from.y), (to.x, to.y)]).unwrap();
+                edge.intersects(&area)
+            })
+            .count();
+        assert_eq!(scene.segments.len(), crossing);
+
+        let mut closest = close;
+        closest.zoom_at(100.0, (50.0, 0.0), Settings::default().zoom_limits());
+        assert_eq!(closest.zoom, 8.0);
+        assert!(closest.x > close.x);
+
+        let middle = Viewport { zoom: 0.3, ..close };
+        let scene = graph.scene(&middle, size);
+        assert!(scene.clusters.is_empty());
+        assert!(!scene.labels);
+
+        let far = Viewport::fit(&graph.bounds().unwrap(), size);
+        assert!(far.zoom < CLUSTER_ZOOM);
+        let scene = graph.scene(&far, size);
+        let clustered: usize = scene.clusters.iter().map(|c| c.count).sum();
+        assert_eq!(scene.nodes.len() + clustered, graph.nodes().len());
+        assert!(scene.clusters.len() < 200);
+        assert!(!scene.labels);
+        assert!(!scene.segments.is_empty());
+        assert!(scene.segments.len() < scene.clusters.len() * 2);
+        assert!(scene.segments.iter().all(|s| s.from != s.to));
+    }
+}
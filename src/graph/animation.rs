@@ -0,0 +1,226 @@
+//! Animating the canvas as the graph changes, so changes can be followed
+//! rather than jump: nodes move smoothly to where they are placed after the
+//! graph changes, such as when picking another layout, arranging the
+//! selection or syncing, new nodes fade in and entities whose facts changed
+//! pulse. The canvas steps it on every frame while it runs.
+
+use super::Graph;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long nodes take to move.
+pub const DURATION: Duration = Duration::from_millis(400);
+/// How long a changed entity pulses.
+pub const PULSE: Duration = Duration::from_millis(1200);
+/// Graphs with more nodes than this move at once, as updating all their
+/// positions on every frame would stutter.
+pub const MAX_NODES: usize = 20_000;
+
+/// The nodes of a graph on their way from where they were drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    from: Vec<(f32, f32)>,
+    to: Vec<(f32, f32)>,
+    started: Instant,
+}
+
+impl Transition {
+    /// Moves the nodes of `graph` back to where they are in `old`, to move
+    /// them from there, unless none of them moved. New nodes are where they
+    /// will be.
+    pub fn start(old: &Graph, graph: &mut Graph, now: Instant) -> Option<Transition> {
+        if graph.nodes().len() > MAX_NODES {
+            return None;
+        }
+        let was: HashMap<Uuid, (f32, f32)> =
+            old.nodes().iter().map(|n| (n.id, (n.x, n.y))).collect();
+        let to: Vec<(f32, f32)> = graph.nodes().iter().map(|n| (n.x, n.y)).collect();
+        let from: Vec<(f32, f32)> = graph
+            .nodes()
+            .iter()
+            .zip(&to)
+            .map(|(node, to)| was.get(&node.id).copied().unwrap_or(*to))
+            .collect();
+        if from == to {
+            return None;
+        }
+        graph.set_positions(&from);
+        Some(Transition {
+            from,
+            to,
+            started: now,
+        })
+    }
+
+    /// Moves the nodes of `graph` to where they are at `now`, and tells
+    /// whether they arrived.
+    pub fn step(&self, graph: &mut Graph, now: Instant) -> bool {
+        let t = now.saturating_duration_since(self.started).as_secs_f32() / DURATION.as_secs_f32();
+        if t >= 1.0 {
+            graph.set_positions(&self.to);
+            return true;
+        }
+        let t = ease(t);
+        let positions: Vec<(f32, f32)> = self
+            .from
+            .iter()
+            .zip(&self.to)
+            .map(|(from, to)| (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t))
+            .collect();
+        graph.set_positions(&positions);
+        false
+    }
+}
+
+/// The animations running on a graph.
+#[derive(Debug, Clone, Default)]
+pub struct Animation {
+    transition: Option<Transition>,
+    /// When each new node appeared.
+    appeared: HashMap<Uuid, Instant>,
+    /// When the facts of each entity last changed.
+    changed: HashMap<Uuid, Instant>,
+    /// The time of the frame drawn.
+    now: Option<Instant>,
+}
+
+impl Animation {
+    /// Animates `graph` from `old`, the graph it was rebuilt from: moves the
+    /// nodes from where they were and fades the new ones in, unless `old`
+    /// had none, as when opening the canvas.
+    pub fn rebuilt(&mut self, old: &Graph, graph: &mut Graph, now: Instant) {
+        self.now = Some(now);
+        self.transition = Transition::start(old, graph, now);
+        if old.nodes().is_empty() || graph.nodes().len() > MAX_NODES {
+            return;
+        }
+        let was: HashSet<Uuid> = old.nodes().iter().map(|n| n.id).collect();
+        for node in graph.nodes().iter().filter(|n| !was.contains(&n.id)) {
+            self.appeared.insert(node.id, now);
+        }
+    }
+
+    /// Pulses the entities in `ids`.
+    pub fn pulse(&mut self, ids: impl IntoIterator<Item = Uuid>, now: Instant) {
+        self.now = Some(now);
+        self.changed.extend(ids.into_iter().map(|id| (id, now)));
+    }
+
+    /// Moves the nodes of `graph` to where they are at `now`, and forgets
+    /// what is over.
+    pub fn step(&mut self, graph: &mut Graph, now: Instant) {
+        self.now = Some(now);
+        if let Some(transition) = &self.transition {
+            if transition.step(graph, now) {
+                self.transition = None;
+            }
+        }
+        let over = |since: &Instant, duration| now.saturating_duration_since(*since) >= duration;
+        self.appeared.retain(|_, since| !over(since, DURATION));
+        self.changed.retain(|_, since| !over(since, PULSE));
+    }
+
+    /// Whether anything is moving, fading or pulsing.
+    pub fn is_running(&self) -> bool {
+        self.transition.is_some() || !self.appeared.is_empty() || !self.changed.is_empty()
+    }
+
+    /// How opaque the node of `id` is, fading in from 0 to 1.
+    pub fn opacity(&self, id: &Uuid) -> f32 {
+        match self.appeared.get(id) {
+            Some(since) => ease(self.progress(since, DURATION)),
+            None => 1.0,
+        }
+    }
+
+    /// How far the pulse of `id` is, from 0 to 1, if it pulses.
+    pub fn pulse_of(&self, id: &Uuid) -> Option<f32> {
+        let since = self.changed.get(id)?;
+        Some(self.progress(since, PULSE))
+    }
+
+    fn progress(&self, since: &Instant, duration: Duration) -> f32 {
+        let elapsed = match self.now {
+            Some(now) => now.saturating_duration_since(*since),
+            None => Duration::ZERO,
+        };
+        (elapsed.as_secs_f32() / duration.as_secs_f32()).min(1.0)
+    }
+}
+
+/// Eases `t` from 0 to 1 in and out, slow at both ends.
+pub fn ease(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match t < 0.5 {
+        true => 4.0 * t * t * t,
+        false => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Node;
+
+    fn graph(nodes: &[(u128, f32)]) -> Graph {
+        Graph::from_parts(
+            nodes
+                .iter()
+                .map(|(id, x)| Node {
+                    id: Uuid::from_u128(*id),
+                    label: String::new(),
+                    x: *x,
+                    y: 0.0,
+                    style: Default::default(),
+                    members: 0,
+                })
+                .collect(),
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn nodes_move_from_where_they_were() {
+        let old = graph(&[(1, 0.0), (2, 0.0)]);
+        let mut new = graph(&[(1, 100.0), (3, 50.0)]);
+        let start = Instant::now();
+        let transition = Transition::start(&old, &mut new, start).unwrap();
+        assert_eq!(new.nodes()[0].x, 0.0);
+        assert_eq!(new.nodes()[1].x, 50.0);
+
+        assert!(!transition.step(&mut new, start + DURATION / 2));
+        assert!((new.nodes()[0].x - 50.0).abs() < 1e-3);
+        assert_eq!(new.bounds().unwrap().left, 50.0);
+        assert!(transition.step(&mut new, start + DURATION));
+        assert_eq!(new.nodes()[0].x, 100.0);
+        assert!(Transition::start(&new.clone(), &mut new, start).is_none());
+        assert!(ease(0.25) < 0.25 && ease(0.75) > 0.75);
+    }
+
+    #[test]
+    fn new_nodes_fade_in_and_changed_ones_pulse() {
+        let old = graph(&[(1, 0.0)]);
+        let mut new = graph(&[(1, 0.0), (2, 10.0)]);
+        let start = Instant::now();
+        let mut animation = Animation::default();
+        animation.rebuilt(&old, &mut new, start);
+        animation.pulse([Uuid::from_u128(1)], start);
+        assert!(animation.is_running());
+        assert_eq!(animation.opacity(&Uuid::from_u128(1)), 1.0);
+        assert_eq!(animation.opacity(&Uuid::from_u128(2)), 0.0);
+        assert_eq!(animation.pulse_of(&Uuid::from_u128(1)), Some(0.0));
+        assert_eq!(animation.pulse_of(&Uuid::from_u128(2)), None);
+
+        animation.step(&mut new, start + DURATION);
+        assert_eq!(animation.opacity(&Uuid::from_u128(2)), 1.0);
+        let pulse = animation.pulse_of(&Uuid::from_u128(1)).unwrap();
+        assert!(pulse > 0.0 && pulse < 1.0);
+        animation.step(&mut new, start + PULSE);
+        assert!(!animation.is_running());
+
+        let mut first = graph(&[(1, 0.0)]);
+        animation.rebuilt(&Graph::default(), &mut first, start);
+        assert!(!animation.is_running());
+    }
+}
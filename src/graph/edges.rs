@@ -0,0 +1,161 @@
+//! The edges in view as one mesh of triangles, drawn in a single call
+//! however many there are: a thin quad per line, a few per curve, and a
+//! triangle per arrowhead.
+
+use super::{Segment, Viewport};
+
+/// The width of edges, in screen pixels.
+pub const WIDTH: f32 = 1.5;
+const ARROW_LENGTH: f32 = 9.0;
+const ARROW_WIDTH: f32 = 7.0;
+/// How far curved edges bend from a straight line, relative to their
+/// length.
+const BEND: f32 = 0.15;
+/// The lines a curved edge is made of.
+const CURVE_STEPS: usize = 12;
+
+/// Triangles in screen pixels.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<(f32, f32)>,
+    /// Three vertices per triangle.
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    fn triangle(&mut self, a: (f32, f32), b: (f32, f32), c: (f32, f32)) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend([a, b, c]);
+        self.indices.extend([first, first + 1, first + 2]);
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        let Some((dx, dy)) = direction(from, to) else {
+            return;
+        };
+        let (nx, ny) = (-dy * WIDTH / 2.0, dx * WIDTH / 2.0);
+        let first = self.vertices.len() as u32;
+        self.vertices.extend([
+            (from.0 + nx, from.1 + ny),
+            (from.0 - nx, from.1 - ny),
+            (to.0 - nx, to.1 - ny),
+            (to.0 + nx, to.1 + ny),
+        ]);
+        self.indices
+            .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+/// The mesh of `segments` in `viewport` on a screen of `size`.
+pub fn mesh(segments: &[Segment], viewport: &Viewport, size: (f32, f32), curved: bool) -> Mesh {
+    let mut mesh = Mesh::default();
+    for segment in segments {
+        let from = viewport.to_screen(segment.from, size);
+        let to = viewport.to_screen(segment.to, size);
+        let points = match curved {
+            true => curve(from, to),
+            false => vec![from, to],
+        };
+        if !segment.directed {
+            points.windows(2).for_each(|w| mesh.line(w[0], w[1]));
+            continue;
+        }
+        let body = cut(&points, segment.inset + ARROW_LENGTH);
+        let (Some(&base), Some(&tip)) = (body.last(), cut(&points, segment.inset).last()) else {
+            continue;
+        };
+        body.windows(2).for_each(|w| mesh.line(w[0], w[1]));
+        if let Some((dx, dy)) = direction(base, tip) {
+            let (nx, ny) = (-dy * ARROW_WIDTH / 2.0, dx * ARROW_WIDTH / 2.0);
+            mesh.triangle(tip, (base.0 + nx, base.1 + ny), (base.0 - nx, base.1 - ny));
+        }
+    }
+    mesh
+}
+
+/// The unit vector from `from` to `to`, unless they are too close.
+fn direction(from: (f32, f32), to: (f32, f32)) -> Option<(f32, f32)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = dx.hypot(dy);
+    (length > 0.01).then(|| (dx / length, dy / length))
+}
+
+/// A quadratic curve from `from` to `to`, bending to the left.
+fn curve(from: (f32, f32), to: (f32, f32)) -> Vec<(f32, f32)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let control = (
+        (from.0 + to.0) / 2.0 + dy * BEND,
+        (from.1 + to.1) / 2.0 - dx * BEND,
+    );
+    (0..=CURVE_STEPS)
+        .map(|step| {
+            let t = step as f32 / CURVE_STEPS as f32;
+            let (a, b, c) = ((1.0 - t).powi(2), 2.0 * t * (1.0 - t), t * t);
+            (
+                a * from.0 + b * control.0 + c * to.0,
+                a * from.1 + b * control.1 + c * to.1,
+            )
+        })
+        .collect()
+}
+
+/// The line through `points` without its last `length` pixels, nothing if
+/// it is shorter.
+fn cut(points: &[(f32, f32)], mut length: f32) -> Vec<(f32, f32)> {
+    let mut points = points.to_vec();
+    while let [.., from, to] = points[..] {
+        let last = (to.0 - from.0).hypot(to.1 - from.1);
+        if last > length {
+            let t = length / last;
+            points.pop();
+            points.push((to.0 + (from.0 - to.0) * t, to.1 + (from.1 - to.1) * t));
+            return points;
+        }
+        length -= last;
+        points.pop();
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrowheads_stop_at_the_node() {
+        let viewport = Viewport::default();
+        let size = (200.0, 200.0);
+        let segment = Segment {
+            from: (-50.0, 0.0),
+            to: (50.0, 0.0),
+            directed: true,
+            inset: 8.0,
+        };
+
+        let straight = mesh(&[segment], &viewport, size, false);
+        assert_eq!(straight.vertices.len(), 4 + 3);
+        assert_eq!(straight.indices.len(), 6 + 3);
+        let tip = straight.vertices[4];
+        assert!((tip.0 - 142.0).abs() < 1e-3 && (tip.1 - 100.0).abs() < 1e-3);
+        let end = straight.vertices[2];
+        assert!((end.0 - (142.0 - ARROW_LENGTH)).abs() < 1e-3);
+
+        let undirected = Segment {
+            directed: false,
+            ..segment
+        };
+        let both = mesh(&[segment, undirected], &viewport, size, true);
+        assert!(both.vertices.len() > 2 * 4 * 2);
+        assert_eq!(both.indices.len() % 3, 0);
+        assert!(both
+            .indices
+            .iter()
+            .all(|i| (*i as usize) < both.vertices.len()));
+
+        let touching = Segment {
+            to: (-45.0, 0.0),
+            ..segment
+        };
+        assert!(mesh(&[touching], &viewport, size, false).indices.is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+//! Focusing the canvas on a few entities and their neighborhood, to explore
+//! a large graph outwards from them: the entities they link to and those
+//! linking to them, then theirs, as many hops away as asked.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+/// `start` and the entities at most `hops` links away from them, following
+/// links both ways.
+pub fn neighborhood(
+    projection: &Projection,
+    start: impl IntoIterator<Item = Uuid>,
+    hops: usize,
+) -> BTreeSet<Uuid> {
+    let mut links: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for (id, entity) in projection.entities() {
+        for (_, values) in entity.facts() {
+            for value in values {
+                if let Datum::Entity(target) = value {
+                    if projection.contains(target) {
+                        links.entry(*id).or_default().push(*target);
+                        links.entry(*target).or_default().push(*id);
+                    }
+                }
+            }
+        }
+    }
+    let mut found: BTreeSet<Uuid> = start
+        .into_iter()
+        .filter(|id| projection.contains(id))
+        .collect();
+    let mut frontier: Vec<Uuid> = found.iter().copied().collect();
+    for _ in 0..hops {
+        frontier = frontier
+            .iter()
+            .flat_map(|id| links.get(id).into_iter().flatten())
+            .filter(|id| found.insert(**id))
+            .copied()
+            .collect();
+        if frontier.is_empty() {
+            break;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn follows_links_both_ways() {
+        let ids: Vec<Uuid> = (0..6).map(Uuid::from_u128).collect();
+        let mut projection = Projection::new();
+        for id in &ids {
+            projection.apply(&Action::CreateEntity { id: *id });
+        }
+        // 0 -> 1 <- 2 -> 3 -> 4, and 5 on its own.
+        for (from, to) in [(0, 1), (2, 1), (2, 3), (3, 4)] {
+            projection.apply(&Action::AddFact {
+                subject: ids[from],
+                predicate: "link".to_string(),
+                datum: Datum::Entity(ids[to]),
+            });
+        }
+        let hops =
+            |n| -> Vec<Uuid> { neighborhood(&projection, [ids[0]], n).into_iter().collect() };
+        assert_eq!(hops(0), [ids[0]]);
+        assert_eq!(hops(1), [ids[0], ids[1]]);
+        assert_eq!(hops(2), [ids[0], ids[1], ids[2]]);
+        assert_eq!(hops(10), ids[..5]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Containers of entities, to organize a large graph hierarchically: an
+//! entity with a `part-of` fact linking to another is in it, and containers
+//! can be in containers. An expanded container is drawn as a box around its
+//! members, and a collapsed one as a single node standing in for them.
+
+use super::{Node, Rect, GRID};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+/// The predicate linking an entity to its container.
+pub const PART_OF: &str = "part-of";
+/// The space between a box and what is in it.
+const PADDING: f32 = GRID / 4.0;
+
+/// The container of each entity in one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hierarchy {
+    parent: HashMap<Uuid, Uuid>,
+}
+
+impl Hierarchy {
+    /// The containers of the entities of `projection`, the first entity
+    /// each is `part-of`. A fact that would make a container part of itself
+    /// is ignored.
+    pub fn new(projection: &Projection) -> Hierarchy {
+        let mut parent = HashMap::new();
+        for (id, entity) in projection.entities() {
+            let container = entity.values(PART_OF).iter().find_map(|value| match value {
+                Datum::Entity(container) if container != id && projection.contains(container) => {
+                    Some(*container)
+                }
+                _ => None,
+            });
+            if let Some(container) = container {
+                parent.insert(*id, container);
+            }
+        }
+        let mut hierarchy = Hierarchy { parent };
+        let mut ids: Vec<Uuid> = hierarchy.parent.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            if hierarchy.ancestors(id).any(|ancestor| ancestor == id) {
+                hierarchy.parent.remove(&id);
+            }
+        }
+        hierarchy
+    }
+
+    /// The container `id` is directly in.
+    pub fn parent(&self, id: &Uuid) -> Option<Uuid> {
+        self.parent.get(id).copied()
+    }
+
+    /// The entities with members.
+    pub fn containers(&self) -> BTreeSet<Uuid> {
+        self.parent.values().copied().collect()
+    }
+
+    /// The containers `id` is in, innermost first, stopping at a cycle.
+    fn ancestors(&self, id: Uuid) -> impl Iterator<Item = Uuid> + '_ {
+        let mut seen = HashSet::new();
+        std::iter::successors(self.parent(&id), move |id| self.parent(id))
+            .take_while(move |ancestor| seen.insert(*ancestor))
+    }
+
+    /// The outermost of the containers of `id` in `collapsed`, which stands
+    /// in for it, if any.
+    pub fn collapsed_in(&self, id: &Uuid, collapsed: &BTreeSet<Uuid>) -> Option<Uuid> {
+        self.ancestors(*id)
+            .filter(|ancestor| collapsed.contains(ancestor))
+            .last()
+    }
+}
+
+/// An expanded container drawn as a box around its members.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Group {
+    /// The index of the node of the container.
+    pub node: usize,
+    pub bounds: Rect,
+    /// How many boxes it is in.
+    pub depth: usize,
+}
+
+/// The boxes of the containers among `nodes` with members among them, outer
+/// ones first.
+pub fn boxes(hierarchy: &Hierarchy, nodes: &[Node]) -> Vec<Group> {
+    let index: HashMap<Uuid, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(n, node)| (node.id, n))
+        .collect();
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (n, node) in nodes.iter().enumerate() {
+        if let Some(container) = hierarchy.parent(&node.id).and_then(|c| index.get(&c)) {
+            members.entry(*container).or_default().push(n);
+        }
+    }
+    let mut bounds = HashMap::new();
+    let mut containers: Vec<usize> = members.keys().copied().collect();
+    containers.sort_unstable();
+    for container in &containers {
+        around(*container, nodes, &members, &mut bounds);
+    }
+    let depth = |n: usize| {
+        std::iter::successors(Some(n), |n| {
+            let parent = hierarchy.parent(&nodes[*n].id)?;
+            index.get(&parent).copied()
+        })
+        .skip(1)
+        .take(nodes.len())
+        .filter(|n| members.contains_key(n))
+        .count()
+    };
+    let mut groups: Vec<Group> = containers
+        .into_iter()
+        .map(|node| Group {
+            node,
+            bounds: bounds[&node],
+            depth: depth(node),
+        })
+        .collect();
+    groups.sort_by_key(|group| group.depth);
+    groups
+}
+
+/// The box of `container`, around it, its members and their boxes.
+fn around(
+    container: usize,
+    nodes: &[Node],
+    members: &HashMap<usize, Vec<usize>>,
+    bounds: &mut HashMap<usize, Rect>,
+) -> Rect {
+    if let Some(rect) = bounds.get(&container) {
+        return *rect;
+    }
+    let node = &nodes[container];
+    let mut rect = Rect::around([(node.x, node.y)]).expect("a point");
+    for member in members.get(&container).into_iter().flatten() {
+        let inner = match members.contains_key(member) {
+            true => around(*member, nodes, members, bounds),
+            false => Rect::around([(nodes[*member].x, nodes[*member].y)]).expect("a point"),
+        };
+        rect = Rect {
+            left: rect.left.min(inner.left),
+            top: rect.top.min(inner.top),
+            right: rect.right.max(inner.right),
+            bottom: rect.bottom.max(inner.bottom),
+        };
+    }
+    let rect = rect.expand(PADDING);
+    bounds.insert(container, rect);
+    rect
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn collapsed_containers_stand_in_for_their_members() {
+        let ids: Vec<Uuid> = (0..5).map(Uuid::from_u128).collect();
+        let mut projection = Projection::new();
+        for id in &ids {
+            projection.apply(&Action::CreateEntity { id: *id });
+        }
+        // 1 and 2 are in 0, 3 in 1, and 0 in 3, which is ignored.
+        for (member, container) in [(1, 0), (2, 0), (3, 1), (0, 3)] {
+            projection.apply(&Action::AddFact {
+                subject: ids[member],
+                predicate: PART_OF.to_string(),
+                datum: Datum::Entity(ids[container]),
+            });
+        }
+        let hierarchy = Hierarchy::new(&projection);
+        assert_eq!(hierarchy.parent(&ids[0]), None);
+        assert_eq!(hierarchy.parent(&ids[3]), Some(ids[1]));
+        assert_eq!(hierarchy.containers(), [ids[0], ids[1]].into());
+        let collapsed = [ids[0], ids[1]].into();
+        assert_eq!(hierarchy.collapsed_in(&ids[3], &collapsed), Some(ids[0]));
+        assert_eq!(hierarchy.collapsed_in(&ids[0], &collapsed), None);
+        assert_eq!(hierarchy.collapsed_in(&ids[4], &collapsed), None);
+
+        let nodes: Vec<Node> = ids
+            .iter()
+            .enumerate()
+            .map(|(n, id)| Node {
+                id: *id,
+                label: String::new(),
+                x: n as f32 * GRID,
+                y: 0.0,
+                style: Default::default(),
+                members: 0,
+            })
+            .collect();
+        let groups = boxes(&hierarchy, &nodes);
+        assert_eq!(
+            groups.iter().map(|g| (g.node, g.depth)).collect::<Vec<_>>(),
+            [(0, 0), (1, 1)]
+        );
+        let (outer, inner) = (groups[0].bounds, groups[1].bounds);
+        assert!(outer.contains_rect(&inner) && outer != inner);
+        assert!(inner.contains((3.0 * GRID, 0.0)));
+        assert!(!outer.contains((4.0 * GRID, 0.0)));
+    }
+}
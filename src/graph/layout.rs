@@ -0,0 +1,408 @@
+//! Laying the graph out automatically rather than at the positions recorded
+//! for the entities, chosen per view, see [`super::style`].
+//!
+//! The hierarchical layout suits graphs that are mostly acyclic, such as
+//! org charts and dependencies. It is the layered layout of Sugiyama et al:
+//! the links closing a cycle are reversed, each node gets the rank after
+//! the longest path of links into it, links spanning several ranks are
+//! routed through a placeholder on each, and the nodes of each rank are
+//! reordered by the average position of their neighbors on the previous
+//! one, sweeping down and up, to keep the order with the fewest crossings.
+//!
+//! The circular and grid layouts place the nodes in order around a circle
+//! or in rows, which also tidies the selected entities, see
+//! [`crate::selection::arrange`].
+
+use super::{Edge, GRID};
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// The sweeps reordering the ranks, at most.
+const SWEEPS: usize = 12;
+
+/// How the nodes of a view are placed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// At the `x` and `y` of each entity, on a grid below for those
+    /// without.
+    #[default]
+    Positions,
+    /// In ranks following the links, the first rank on the side given.
+    Hierarchical(Direction),
+    Circular,
+    Grid,
+}
+
+/// The side of the canvas the first rank of a hierarchical layout is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Down,
+    Up,
+    Right,
+    Left,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 7] = [
+        Layout::Positions,
+        Layout::Hierarchical(Direction::Down),
+        Layout::Hierarchical(Direction::Up),
+        Layout::Hierarchical(Direction::Right),
+        Layout::Hierarchical(Direction::Left),
+        Layout::Circular,
+        Layout::Grid,
+    ];
+
+    /// The id of its name in the locales.
+    pub fn message_id(self) -> &'static str {
+        match self {
+            Layout::Positions => "canvas-layout-positions",
+            Layout::Hierarchical(Direction::Down) => "canvas-layout-down",
+            Layout::Hierarchical(Direction::Up) => "canvas-layout-up",
+            Layout::Hierarchical(Direction::Right) => "canvas-layout-right",
+            Layout::Hierarchical(Direction::Left) => "canvas-layout-left",
+            Layout::Circular => "canvas-layout-circular",
+            Layout::Grid => "canvas-layout-grid",
+        }
+    }
+
+    /// The position of each of `nodes` nodes linked by `edges`, unless they
+    /// are at their recorded positions.
+    pub fn place(self, nodes: usize, edges: &[Edge]) -> Option<Vec<(f32, f32)>> {
+        match self {
+            Layout::Positions => None,
+            Layout::Hierarchical(direction) => Some(hierarchical(nodes, edges, direction)),
+            Layout::Circular => Some(circle(nodes)),
+            Layout::Grid => Some(grid(nodes)),
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Layout> {
+        match Layout::ALL
+            .into_iter()
+            .find(|layout| layout.to_string() == s)
+        {
+            Some(layout) => Ok(layout),
+            None => bail!("Invalid layout {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Layout::Positions => "positions",
+            Layout::Hierarchical(Direction::Down) => "hierarchical-down",
+            Layout::Hierarchical(Direction::Up) => "hierarchical-up",
+            Layout::Hierarchical(Direction::Right) => "hierarchical-right",
+            Layout::Hierarchical(Direction::Left) => "hierarchical-left",
+            Layout::Circular => "circular",
+            Layout::Grid => "grid",
+        })
+    }
+}
+
+/// The layered layout of `nodes` nodes linked by `edges`.
+pub fn hierarchical(nodes: usize, edges: &[Edge], direction: Direction) -> Vec<(f32, f32)> {
+    let links = acyclic(nodes, edges);
+    let rank = ranks(nodes, &links);
+    // The vertices are the nodes, then the placeholders of long links.
+    let mut rank_of = rank.clone();
+    let mut up = vec![Vec::new(); nodes];
+    let mut down = vec![Vec::new(); nodes];
+    for (from, to) in links {
+        let mut previous = from;
+        for r in rank[from] + 1..rank[to] {
+            let placeholder = rank_of.len();
+            rank_of.push(r);
+            up.push(vec![previous]);
+            down.push(Vec::new());
+            down[previous].push(placeholder);
+            previous = placeholder;
+        }
+        down[previous].push(to);
+        up[to].push(previous);
+    }
+    let mut layers = vec![Vec::new(); rank_of.iter().max().map_or(0, |r| r + 1)];
+    for (v, r) in rank_of.iter().enumerate() {
+        layers[*r].push(v);
+    }
+
+    let mut position = vec![0; rank_of.len()];
+    let mut best = layers.clone();
+    let mut fewest = crossings(&layers, &down, &mut position);
+    for sweep in 0..SWEEPS {
+        if fewest == 0 {
+            break;
+        }
+        for r in 1..layers.len() {
+            match sweep % 2 {
+                0 => reorder(&mut layers, r - 1, r, &up, &mut position),
+                _ => {
+                    let r = layers.len() - r;
+                    reorder(&mut layers, r, r - 1, &down, &mut position)
+                }
+            }
+        }
+        let count = crossings(&layers, &down, &mut position);
+        if count < fewest {
+            fewest = count;
+            best = layers.clone();
+        }
+    }
+
+    let mut placed = vec![(0.0, 0.0); nodes];
+    for (r, layer) in best.iter().enumerate() {
+        let middle = (layer.len() as f32 - 1.0) / 2.0;
+        for (n, v) in layer.iter().enumerate() {
+            if *v < nodes {
+                let (across, along) = ((n as f32 - middle) * GRID, r as f32 * GRID);
+                placed[*v] = match direction {
+                    Direction::Down => (across, along),
+                    Direction::Up => (across, -along),
+                    Direction::Right => (along, across),
+                    Direction::Left => (-along, across),
+                };
+            }
+        }
+    }
+    placed
+}
+
+/// `nodes` points around a circle centered on the origin, clockwise from
+/// the top, a grid step apart.
+pub fn circle(nodes: usize) -> Vec<(f32, f32)> {
+    let radius = match nodes {
+        0 | 1 => 0.0,
+        _ => GRID / (2.0 * (std::f32::consts::PI / nodes as f32).sin()),
+    };
+    (0..nodes)
+        .map(|n| {
+            let angle = n as f32 / nodes as f32 * std::f32::consts::TAU;
+            (radius * angle.sin(), -radius * angle.cos())
+        })
+        .collect()
+}
+
+/// `nodes` points in rows of a square grid centered on the origin.
+pub fn grid(nodes: usize) -> Vec<(f32, f32)> {
+    let columns = (nodes as f32).sqrt().ceil().max(1.0) as usize;
+    let rows = nodes.div_ceil(columns);
+    let middle = |count: usize| (count as f32 - 1.0) / 2.0;
+    (0..nodes)
+        .map(|n| {
+            let (column, row) = (n % columns, n / columns);
+            (
+                (column as f32 - middle(columns)) * GRID,
+                (row as f32 - middle(rows)) * GRID,
+            )
+        })
+        .collect()
+}
+
+/// The links of `edges`, once each, with those closing a cycle reversed.
+fn acyclic(nodes: usize, edges: &[Edge]) -> Vec<(usize, usize)> {
+    let mut out = vec![Vec::new(); nodes];
+    for edge in edges.iter().filter(|e| e.from != e.to) {
+        out[edge.from].push(edge.to);
+    }
+    for targets in &mut out {
+        targets.sort_unstable();
+        targets.dedup();
+    }
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        New,
+        Open,
+        Done,
+    }
+    let mut visit = vec![Visit::New; nodes];
+    let mut links = Vec::new();
+    for start in 0..nodes {
+        if visit[start] != Visit::New {
+            continue;
+        }
+        visit[start] = Visit::Open;
+        // Each node on the path, and the next of its links to follow.
+        let mut path = vec![(start, 0)];
+        while let Some((from, next)) = path.last_mut() {
+            let from = *from;
+            let Some(&to) = out[from].get(*next) else {
+                visit[from] = Visit::Done;
+                path.pop();
+                continue;
+            };
+            *next += 1;
+            match visit[to] {
+                Visit::New => {
+                    visit[to] = Visit::Open;
+                    links.push((from, to));
+                    path.push((to, 0));
+                }
+                Visit::Open => links.push((to, from)),
+                Visit::Done => links.push((from, to)),
+            }
+        }
+    }
+    links.sort_unstable();
+    links.dedup();
+    links
+}
+
+/// The rank of each node: one after the longest path of `links` into it.
+fn ranks(nodes: usize, links: &[(usize, usize)]) -> Vec<usize> {
+    let mut out = vec![Vec::new(); nodes];
+    let mut incoming = vec![0; nodes];
+    for (from, to) in links {
+        out[*from].push(*to);
+        incoming[*to] += 1;
+    }
+    let mut rank = vec![0; nodes];
+    let mut ready: VecDeque<usize> = (0..nodes).filter(|n| incoming[*n] == 0).collect();
+    while let Some(from) = ready.pop_front() {
+        for to in &out[from] {
+            rank[*to] = rank[*to].max(rank[from] + 1);
+            incoming[*to] -= 1;
+            if incoming[*to] == 0 {
+                ready.push_back(*to);
+            }
+        }
+    }
+    rank
+}
+
+/// Sorts the layer `r` by the average position of the `neighbors` of its
+/// vertices in the layer `fixed`, leaving those without any where they are.
+fn reorder(
+    layers: &mut [Vec<usize>],
+    fixed: usize,
+    r: usize,
+    neighbors: &[Vec<usize>],
+    position: &mut [usize],
+) {
+    for (n, v) in layers[fixed].iter().enumerate() {
+        position[*v] = n;
+    }
+    let center = |(n, v): (usize, &usize)| {
+        let of = &neighbors[*v];
+        let center = match of.is_empty() {
+            true => n as f32,
+            false => of.iter().map(|w| position[*w] as f32).sum::<f32>() / of.len() as f32,
+        };
+        (center, *v)
+    };
+    let mut centers: Vec<(f32, usize)> = layers[r].iter().enumerate().map(center).collect();
+    centers.sort_by(|a, b| a.0.total_cmp(&b.0));
+    layers[r] = centers.into_iter().map(|(_, v)| v).collect();
+}
+
+/// The links crossing between adjacent layers.
+fn crossings(layers: &[Vec<usize>], down: &[Vec<usize>], position: &mut [usize]) -> usize {
+    for layer in layers {
+        for (n, v) in layer.iter().enumerate() {
+            position[*v] = n;
+        }
+    }
+    let mut count = 0;
+    for pair in layers.windows(2) {
+        let mut links: Vec<(usize, usize)> = pair[0]
+            .iter()
+            .flat_map(|v| down[*v].iter().map(|w| (position[*v], position[*w])))
+            .collect();
+        links.sort_unstable();
+        // Counts the links ending right of each one's end that start left
+        // of it, in a Fenwick tree of the ends so far.
+        let mut tree = vec![0; pair[1].len() + 1];
+        for (seen, (_, end)) in links.iter().enumerate() {
+            let mut at = end + 1;
+            let mut before = 0;
+            while at > 0 {
+                before += tree[at];
+                at &= at - 1;
+            }
+            count += seen - before;
+            let mut at = end + 1;
+            while at < tree.len() {
+                tree[at] += 1;
+                at += at & at.wrapping_neg();
+            }
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(from: usize, to: usize) -> Edge {
+        Edge {
+            from,
+            to,
+            predicate: "link".to_string(),
+            directed: true,
+        }
+    }
+
+    #[test]
+    fn ranks_follow_links_without_crossing() {
+        // 0 and 1 lead to 3 and 2 crossed, 2 to 4, 0 straight to 4, and 4
+        // back to 0.
+        let edges = [edge(0, 3), edge(1, 2), edge(2, 4), edge(0, 4), edge(4, 0)];
+        let placed = hierarchical(5, &edges, Direction::Down);
+        let rank = |n: usize| placed[n].1 / GRID;
+        assert_eq!([0, 1, 2, 3, 4].map(rank), [0.0, 0.0, 1.0, 1.0, 2.0]);
+        let (x0, x1) = (placed[0].0, placed[1].0);
+        let (x3, x2) = (placed[3].0, placed[2].0);
+        assert_eq!(x0 < x1, x3 < x2);
+
+        let right = hierarchical(5, &edges, Direction::Right);
+        assert!(placed
+            .iter()
+            .zip(&right)
+            .all(|(down, right)| down.0 == right.1 && down.1 == right.0));
+
+        let links = acyclic(5, &edges);
+        assert_eq!(links, [(0, 3), (0, 4), (1, 2), (2, 4)]);
+        let mut position = vec![0; 5];
+        let crossed = [vec![0, 1], vec![2, 3]];
+        let down = [vec![3], vec![2], vec![], vec![], vec![]];
+        assert_eq!(crossings(&crossed, &down, &mut position), 1);
+        assert_eq!(
+            "hierarchical-left".parse::<Layout>().unwrap(),
+            Layout::ALL[4]
+        );
+    }
+
+    #[test]
+    fn circles_and_grids_keep_nodes_apart() {
+        let circle = circle(7);
+        let (x, y) = circle
+            .iter()
+            .fold((0.0, 0.0), |(x, y), p| (x + p.0, y + p.1));
+        assert!(x.abs() < 1e-3 && y.abs() < 1e-3);
+        assert!(circle[0].0.abs() < 1e-3 && circle[0].1 < 0.0);
+        for points in [circle, grid(7)] {
+            let closest = points
+                .iter()
+                .enumerate()
+                .flat_map(|(n, a)| {
+                    points[n + 1..]
+                        .iter()
+                        .map(move |b| (a.0 - b.0).hypot(a.1 - b.1))
+                })
+                .fold(f32::MAX, f32::min);
+            assert!((closest - GRID).abs() < 1e-3, "{:?}", points);
+        }
+        assert_eq!(
+            grid(4),
+            [(-60.0, -60.0), (60.0, -60.0), (-60.0, 60.0), (60.0, 60.0)]
+        );
+    }
+}
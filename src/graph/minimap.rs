@@ -0,0 +1,117 @@
+//! The map of the whole graph in a corner of the canvas. However many nodes
+//! there are, it draws how many are in each of a fixed grid of cells, and
+//! the viewport on top.
+
+use super::{Node, Rect, GRID};
+
+/// The cells of the map across and down, as wide as they are high.
+pub const COLUMNS: usize = 48;
+pub const ROWS: usize = 36;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Minimap {
+    /// The part of the canvas shown, around all nodes and as wide as the
+    /// map for its height.
+    extent: Option<Rect>,
+    /// The nodes in each cell, row by row.
+    counts: Vec<u32>,
+    max: u32,
+}
+
+impl Minimap {
+    pub fn new(nodes: &[Node]) -> Minimap {
+        let Some(bounds) = Rect::around(nodes.iter().map(|n| (n.x, n.y))) else {
+            return Minimap::default();
+        };
+        let bounds = bounds.expand(GRID / 2.0);
+        let (x, y) = bounds.center();
+        let aspect = COLUMNS as f32 / ROWS as f32;
+        let width = bounds.width().max(bounds.height() * aspect);
+        let height = width / aspect;
+        let extent = Rect {
+            left: x - width / 2.0,
+            top: y - height / 2.0,
+            right: x + width / 2.0,
+            bottom: y + height / 2.0,
+        };
+        let mut counts = vec![0; COLUMNS * ROWS];
+        for node in nodes {
+            let column = ((node.x - extent.left) / width * COLUMNS as f32) as usize;
+            let row = ((node.y - extent.top) / height * ROWS as f32) as usize;
+            counts[row.min(ROWS - 1) * COLUMNS + column.min(COLUMNS - 1)] += 1;
+        }
+        Minimap {
+            extent: Some(extent),
+            max: counts.iter().copied().max().unwrap_or(0),
+            counts,
+        }
+    }
+
+    /// The part of the canvas shown, if the graph has nodes.
+    pub fn extent(&self) -> Option<Rect> {
+        self.extent
+    }
+
+    /// The column and row of each cell with nodes, and how full it is
+    /// compared to the fullest.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(n, count)| (n % COLUMNS, n / COLUMNS, *count as f32 / self.max as f32))
+    }
+
+    /// Where `point` of the canvas is on a map of `size`.
+    pub fn to_map(&self, (x, y): (f32, f32), size: (f32, f32)) -> (f32, f32) {
+        let Some(extent) = self.extent else {
+            return (0.0, 0.0);
+        };
+        (
+            (x - extent.left) / extent.width() * size.0,
+            (y - extent.top) / extent.height() * size.1,
+        )
+    }
+
+    /// The point of the canvas at `point` of a map of `size`.
+    pub fn to_canvas(&self, (x, y): (f32, f32), size: (f32, f32)) -> (f32, f32) {
+        let Some(extent) = self.extent else {
+            return (0.0, 0.0);
+        };
+        (
+            extent.left + x / size.0 * extent.width(),
+            extent.top + y / size.1 * extent.height(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn counts_the_nodes_of_each_cell() {
+        let nodes: Vec<Node> = (0..1000)
+            .map(|n| Node {
+                id: Uuid::from_u128(n),
+                label: String::new(),
+                x: (n % 100) as f32 * 30.0,
+                y: if n < 900 { 0.0 } else { 500.0 },
+                style: Default::default(),
+                members: 0,
+            })
+            .collect();
+        let map = Minimap::new(&nodes);
+        let total: f32 = map.cells().map(|(_, _, full)| full * map.max as f32).sum();
+        assert_eq!(total.round() as usize, nodes.len());
+        assert!(map.cells().any(|(_, _, full)| full == 1.0));
+
+        let size = (160.0, 120.0);
+        let extent = map.extent().unwrap();
+        assert!((extent.width() / extent.height() - size.0 / size.1).abs() < 1e-3);
+        let point = (1234.0, 321.0);
+        let (x, y) = map.to_canvas(map.to_map(point, size), size);
+        assert!((x - point.0).abs() < 0.01 && (y - point.1).abs() < 0.01);
+    }
+}
@@ -0,0 +1,164 @@
+//! A quadtree of node positions, to find the nodes in a part of the canvas
+//! without looking at all of them.
+
+use super::Rect;
+
+/// The most points in a cell before it is split.
+const CAPACITY: usize = 16;
+/// How often cells are split at most, so many points at the same position
+/// don't split them forever.
+const MAX_DEPTH: u32 = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct QuadTree {
+    points: Vec<(f32, f32)>,
+    /// The root is the first.
+    cells: Vec<Cell>,
+}
+
+#[derive(Debug, Clone)]
+struct Cell {
+    bounds: Rect,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    /// The indices of the points in the cell.
+    Leaf(Vec<usize>),
+    /// The cells of the quarters of the cell.
+    Branch([usize; 4]),
+}
+
+impl QuadTree {
+    /// Indexes `points` by their position, each found by its index.
+    pub fn new(points: Vec<(f32, f32)>) -> QuadTree {
+        let mut tree = QuadTree {
+            points,
+            cells: Vec::new(),
+        };
+        if let Some(bounds) = Rect::around(tree.points.iter().copied()) {
+            let all = (0..tree.points.len()).collect();
+            tree.build(bounds, all, 0);
+        }
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Adds the cell of `points` within `bounds` and returns its index.
+    fn build(&mut self, bounds: Rect, points: Vec<usize>, depth: u32) -> usize {
+        let cell = self.cells.len();
+        if points.len() <= CAPACITY || depth == MAX_DEPTH {
+            self.cells.push(Cell {
+                bounds,
+                kind: Kind::Leaf(points),
+            });
+            return cell;
+        }
+        self.cells.push(Cell {
+            bounds,
+            kind: Kind::Branch([0; 4]),
+        });
+        let (cx, cy) = bounds.center();
+        let mut quarters: [Vec<usize>; 4] = Default::default();
+        for n in points {
+            let (x, y) = self.points[n];
+            quarters[usize::from(x >= cx) + 2 * usize::from(y >= cy)].push(n);
+        }
+        let mut children = [0; 4];
+        for (quarter, points) in quarters.into_iter().enumerate() {
+            let bounds = Rect {
+                left: if quarter % 2 == 0 { bounds.left } else { cx },
+                top: if quarter < 2 { bounds.top } else { cy },
+                right: if quarter % 2 == 0 { cx } else { bounds.right },
+                bottom: if quarter < 2 { cy } else { bounds.bottom },
+            };
+            children[quarter] = self.build(bounds, points, depth + 1);
+        }
+        self.cells[cell].kind = Kind::Branch(children);
+        cell
+    }
+
+    /// Adds the indices of the points inside `area` to `found`.
+    pub fn query(&self, area: &Rect, found: &mut Vec<usize>) {
+        if self.cells.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(cell) = stack.pop() {
+            let cell = &self.cells[cell];
+            if !cell.bounds.intersects(area) {
+                continue;
+            }
+            match &cell.kind {
+                Kind::Leaf(points) if area.contains_rect(&cell.bounds) => {
+                    found.extend_from_slice(points)
+                }
+                Kind::Leaf(points) => found.extend(
+                    points
+                        .iter()
+                        .filter(|n| area.contains(self.points[**n]))
+                        .copied(),
+                ),
+                Kind::Branch(children) => stack.extend_from_slice(children),
+            }
+        }
+    }
+
+    /// The point closest to `at` no further than `radius` from it.
+    pub fn nearest(&self, at: (f32, f32), radius: f32) -> Option<usize> {
+        let mut found = Vec::new();
+        let area = Rect {
+            left: at.0 - radius,
+            top: at.1 - radius,
+            right: at.0 + radius,
+            bottom: at.1 + radius,
+        };
+        self.query(&area, &mut found);
+        let distance = |n: &usize| {
+            let (x, y) = self.points[*n];
+            (x - at.0).powi(2) + (y - at.1).powi(2)
+        };
+        found
+            .into_iter()
+            .filter(|n| distance(n) <= radius * radius)
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)).then(a.cmp(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_points_in_an_area() {
+        let points: Vec<(f32, f32)> = (0..5000)
+            .map(|n| ((n * 37 % 1000) as f32, (n * 91 % 700) as f32))
+            .chain(std::iter::repeat_n((5.0, 5.0), 100))
+            .collect();
+        let tree = QuadTree::new(points.clone());
+        let area = Rect {
+            left: 100.0,
+            top: 0.0,
+            right: 350.5,
+            bottom: 200.0,
+        };
+        let mut found = Vec::new();
+        tree.query(&area, &mut found);
+        found.sort();
+        let expected: Vec<usize> = (0..points.len())
+            .filter(|n| area.contains(points[*n]))
+            .collect();
+        assert_eq!(found, expected);
+
+        assert_eq!(tree.nearest((4.0, 4.0), 2.0), Some(5000));
+        assert_eq!(tree.nearest((-10.0, -10.0), 2.0), None);
+    }
+}
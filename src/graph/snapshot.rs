@@ -0,0 +1,478 @@
+//! Snapshots of the canvas as images, to share diagrams in documents and
+//! presentations: the part of the graph in view, or all of it, as SVG or
+//! PNG, drawn like the canvas at a resolution of several image pixels per
+//! screen pixel. PNGs have no text, there being no font to rasterize it
+//! with outside the editor; SVGs keep the labels as text.
+
+use super::{edges, Graph, Rect, Shape, Viewport, CLUSTER_ZOOM, GRID};
+use crate::theme::Hex;
+use anyhow::{anyhow, Context, Result};
+use std::fmt::Write as _;
+use std::path::Path;
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+/// The widest and tallest image, in pixels.
+pub const MAX_SIDE: f32 = 8192.0;
+/// The image pixels per screen pixel to choose from.
+pub const SCALES: [u32; 3] = [1, 2, 4];
+const LABEL_SIZE: f32 = 12.0;
+/// The width of the ring around collapsed containers.
+const RING: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Png,
+    Svg,
+}
+
+impl Format {
+    pub const ALL: [Format; 2] = [Format::Png, Format::Svg];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Svg => "svg",
+        }
+    }
+}
+
+/// The part of the graph a snapshot shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Extent {
+    /// What is in view.
+    #[default]
+    View,
+    /// All of the graph, at the zoom of the view.
+    All,
+}
+
+impl Extent {
+    pub const ALL: [Extent; 2] = [Extent::View, Extent::All];
+
+    /// The id of its name in the locales.
+    pub fn message_id(self) -> &'static str {
+        match self {
+            Extent::View => "canvas-export-view",
+            Extent::All => "canvas-export-all",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Colors {
+    pub canvas: Hex,
+    pub node: Hex,
+    /// Edges, and text.
+    pub edge: Hex,
+}
+
+/// A part of a graph to draw as an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    pub viewport: Viewport,
+    /// The size of the screen drawn, in screen pixels.
+    pub size: (f32, f32),
+    /// The image pixels per screen pixel.
+    pub scale: f32,
+    pub colors: Colors,
+    pub curved: bool,
+}
+
+impl Snapshot {
+    /// The snapshot of `extent` of `graph`, from the `viewport` on a screen
+    /// of `size`, at most [`MAX_SIDE`] pixels wide and high.
+    pub fn new(
+        graph: &Graph,
+        extent: Extent,
+        viewport: Viewport,
+        size: (f32, f32),
+        scale: u32,
+        colors: Colors,
+        curved: bool,
+    ) -> Snapshot {
+        let mut snapshot = Snapshot {
+            viewport,
+            size,
+            scale: scale.max(1) as f32,
+            colors,
+            curved,
+        };
+        let corners = graph
+            .groups()
+            .iter()
+            .flat_map(|g| {
+                [
+                    (g.bounds.left, g.bounds.top),
+                    (g.bounds.right, g.bounds.bottom),
+                ]
+            })
+            .chain(graph.nodes().iter().map(|n| (n.x, n.y)));
+        if let (Extent::All, Some(bounds)) = (extent, Rect::around(corners)) {
+            // Labels hang below the nodes and past their sides.
+            let bounds = bounds.expand(GRID / 2.0);
+            let zoom = viewport
+                .zoom
+                .min(MAX_SIDE / snapshot.scale / bounds.width())
+                .min(MAX_SIDE / snapshot.scale / bounds.height());
+            let (x, y) = bounds.center();
+            snapshot.viewport = Viewport { x, y, zoom };
+            snapshot.size = (bounds.width() * zoom, bounds.height() * zoom);
+        }
+        let largest = MAX_SIDE / snapshot.scale;
+        snapshot.size = (
+            snapshot.size.0.clamp(1.0, largest),
+            snapshot.size.1.clamp(1.0, largest),
+        );
+        snapshot
+    }
+
+    /// The width and height of the image, in pixels.
+    pub fn pixels(&self) -> (u32, u32) {
+        (
+            (self.size.0 * self.scale).ceil() as u32,
+            (self.size.1 * self.scale).ceil() as u32,
+        )
+    }
+
+    /// Writes the image to `path`.
+    pub fn write(&self, graph: &Graph, format: Format, path: &Path) -> Result<()> {
+        let image = match format {
+            Format::Png => self.png(graph)?,
+            Format::Svg => self.svg(graph).into_bytes(),
+        };
+        std::fs::write(path, image).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The image as SVG.
+    pub fn svg(&self, graph: &Graph) -> String {
+        let (width, height) = self.pixels();
+        let (viewport, size) = (&self.viewport, self.size);
+        let scene = graph.scene(viewport, size);
+        let Colors { canvas, node, edge } = self.colors;
+        let mut svg = String::new();
+        // Writing to a string doesn't fail.
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" font-family="sans-serif" font-size="{}" text-anchor="middle">"#,
+            width, height, size.0, size.1, LABEL_SIZE
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}"/>"#,
+            canvas
+        );
+        for group in scene.groups.iter().map(|g| &graph.groups()[*g]) {
+            let (left, top) = viewport.to_screen((group.bounds.left, group.bounds.top), size);
+            let (right, bottom) =
+                viewport.to_screen((group.bounds.right, group.bounds.bottom), size);
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" rx="6" fill="{}" fill-opacity="0.08" stroke="{}" stroke-opacity="0.6"/>"#,
+                left,
+                top,
+                right - left,
+                bottom - top,
+                node,
+                node
+            );
+            if viewport.zoom >= CLUSTER_ZOOM {
+                let title = &graph.nodes()[group.node].label;
+                text(
+                    &mut svg,
+                    title,
+                    ((left + right) / 2.0, top + LABEL_SIZE + 2.0),
+                    edge,
+                );
+            }
+        }
+        let mesh = edges::mesh(&scene.segments, viewport, size, self.curved);
+        if !mesh.indices.is_empty() {
+            let mut d = String::new();
+            for [a, b, c] in triangles(&mesh) {
+                let _ = write!(d, "M{} {}L{} {}L{} {}Z", a.0, a.1, b.0, b.1, c.0, c.1);
+            }
+            let _ = writeln!(svg, r#"<path d="{}" fill="{}"/>"#, d, edge);
+        }
+        for n in &scene.nodes {
+            let n = &graph.nodes()[*n];
+            let center = viewport.to_screen((n.x, n.y), size);
+            let radius = viewport.node_radius() * n.style.size;
+            let shape = n.style.shape;
+            if n.members > 0 {
+                outline(&mut svg, shape, center, radius + RING, edge);
+            }
+            outline(
+                &mut svg,
+                shape,
+                center,
+                radius,
+                n.style.color.unwrap_or(node),
+            );
+            let members = n.members.to_string();
+            let icon = match (&n.style.icon, n.members) {
+                (Some(icon), _) => Some(icon.as_str()),
+                (None, 0) => None,
+                (None, _) => Some(members.as_str()),
+            };
+            if let Some(icon) = icon {
+                text(
+                    &mut svg,
+                    icon,
+                    (center.0, center.1 + LABEL_SIZE / 3.0),
+                    canvas,
+                );
+            }
+            if scene.labels {
+                let position = (center.0, center.1 + radius + LABEL_SIZE + 2.0);
+                text(&mut svg, &n.label, position, edge);
+            }
+        }
+        for cluster in &scene.clusters {
+            let center = viewport.to_screen((cluster.x, cluster.y), size);
+            circle(&mut svg, center, cluster.radius(), node);
+            let position = (center.0, center.1 + LABEL_SIZE / 3.0);
+            text(&mut svg, &cluster.count.to_string(), position, canvas);
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The image as PNG.
+    pub fn png(&self, graph: &Graph) -> Result<Vec<u8>> {
+        let (width, height) = self.pixels();
+        let mut pixmap = Pixmap::new(width, height)
+            .ok_or_else(|| anyhow!("Failed to allocate an image of {}×{}", width, height))?;
+        let (viewport, size) = (&self.viewport, self.size);
+        let scene = graph.scene(viewport, size);
+        let Colors { canvas, node, edge } = self.colors;
+        let transform = Transform::from_scale(self.scale, self.scale);
+        pixmap.fill(color(canvas, 1.0));
+        for group in scene.groups.iter().map(|g| &graph.groups()[*g]) {
+            let (left, top) = viewport.to_screen((group.bounds.left, group.bounds.top), size);
+            let (right, bottom) =
+                viewport.to_screen((group.bounds.right, group.bounds.bottom), size);
+            let Some(rect) = tiny_skia::Rect::from_ltrb(left, top, right, bottom) else {
+                continue;
+            };
+            let path = PathBuilder::from_rect(rect);
+            pixmap.fill_path(
+                &path,
+                &paint(node, 0.08),
+                FillRule::Winding,
+                transform,
+                None,
+            );
+            pixmap.stroke_path(
+                &path,
+                &paint(node, 0.6),
+                &Stroke::default(),
+                transform,
+                None,
+            );
+        }
+        let mesh = edges::mesh(&scene.segments, viewport, size, self.curved);
+        let mut path = PathBuilder::new();
+        for [a, b, c] in triangles(&mesh) {
+            path.move_to(a.0, a.1);
+            path.line_to(b.0, b.1);
+            path.line_to(c.0, c.1);
+            path.close();
+        }
+        if let Some(path) = path.finish() {
+            pixmap.fill_path(&path, &paint(edge, 1.0), FillRule::Winding, transform, None);
+        }
+        let mut disk = |shape: Shape, center: (f32, f32), radius: f32, fill: Hex| {
+            let path = match shape.corners(center, radius) {
+                None => PathBuilder::from_circle(center.0, center.1, radius),
+                Some(corners) => {
+                    let mut path = PathBuilder::new();
+                    path.move_to(corners[0].0, corners[0].1);
+                    for (x, y) in &corners[1..] {
+                        path.line_to(*x, *y);
+                    }
+                    path.close();
+                    path.finish()
+                }
+            };
+            if let Some(path) = path {
+                pixmap.fill_path(&path, &paint(fill, 1.0), FillRule::Winding, transform, None);
+            }
+        };
+        for n in &scene.nodes {
+            let n = &graph.nodes()[*n];
+            let center = viewport.to_screen((n.x, n.y), size);
+            let radius = viewport.node_radius() * n.style.size;
+            if n.members > 0 {
+                disk(n.style.shape, center, radius + RING, edge);
+            }
+            disk(n.style.shape, center, radius, n.style.color.unwrap_or(node));
+        }
+        for cluster in &scene.clusters {
+            disk(
+                Shape::Circle,
+                viewport.to_screen((cluster.x, cluster.y), size),
+                cluster.radius(),
+                node,
+            );
+        }
+        pixmap.encode_png().context("Failed to encode the PNG")
+    }
+}
+
+/// The triangles of `mesh`, all wound the same way, so that overlapping ones
+/// don't cancel out when filled.
+fn triangles(mesh: &edges::Mesh) -> impl Iterator<Item = [(f32, f32); 3]> + '_ {
+    mesh.indices.chunks_exact(3).map(|t| {
+        let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[t[i] as usize]);
+        let cross = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+        match cross < 0.0 {
+            true => [a, c, b],
+            false => [a, b, c],
+        }
+    })
+}
+
+/// A node of `shape`, as large as the circle of `radius`.
+fn outline(svg: &mut String, shape: Shape, center: (f32, f32), radius: f32, fill: Hex) {
+    let Some(corners) = shape.corners(center, radius) else {
+        return circle(svg, center, radius, fill);
+    };
+    let points: Vec<String> = corners
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect();
+    let _ = writeln!(
+        svg,
+        r#"<polygon points="{}" fill="{}"/>"#,
+        points.join(" "),
+        fill
+    );
+}
+
+fn circle(svg: &mut String, (x, y): (f32, f32), radius: f32, fill: Hex) {
+    let _ = writeln!(
+        svg,
+        r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+        x, y, radius, fill
+    );
+}
+
+/// Writes `content` centered on `x`, with its baseline at `y`.
+fn text(svg: &mut String, content: &str, (x, y): (f32, f32), fill: Hex) {
+    let mut escaped = String::with_capacity(content.len());
+    for c in content.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            c => escaped.push(c),
+        }
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" fill="{}">{}</text>"#,
+        x, y, fill, escaped
+    );
+}
+
+fn color(Hex(r, g, b): Hex, alpha: f32) -> tiny_skia::Color {
+    tiny_skia::Color::from_rgba8(r, g, b, (alpha * 255.0).round() as u8)
+}
+
+fn paint(fill: Hex, alpha: f32) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(color(fill, alpha));
+    paint.anti_alias = true;
+    paint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Edge, Node, Style};
+    use uuid::Uuid;
+
+    #[test]
+    fn snapshots_show_the_view_or_the_whole_graph() {
+        let nodes = ["a", "b & c", "d"]
+            .iter()
+            .enumerate()
+            .map(|(n, label)| Node {
+                id: Uuid::from_u128(n as u128),
+                label: label.to_string(),
+                x: n as f32 * 1000.0,
+                y: 0.0,
+                style: Style::default(),
+                members: 0,
+            })
+            .collect();
+        let edges = vec![Edge {
+            from: 0,
+            to: 1,
+            predicate: "knows".to_string(),
+            directed: true,
+        }];
+        let mut graph = Graph::from_parts(nodes, edges);
+        graph.restyle(|node| {
+            if node.label == "d" {
+                node.style.shape = Shape::Diamond;
+            }
+        });
+        let colors = Colors {
+            canvas: Hex(255, 255, 255),
+            node: Hex(0, 0, 255),
+            edge: Hex(0, 0, 0),
+        };
+        let viewport = Viewport::default();
+        let view = Snapshot::new(
+            &graph,
+            Extent::View,
+            viewport,
+            (200.0, 100.0),
+            2,
+            colors,
+            false,
+        );
+        assert_eq!(view.pixels(), (400, 200));
+        let svg = view.svg(&graph);
+        assert_eq!(svg.matches("<circle").count(), 1);
+        assert!(svg.contains(">a</text>"));
+
+        let all = Snapshot::new(
+            &graph,
+            Extent::All,
+            viewport,
+            (200.0, 100.0),
+            1,
+            colors,
+            false,
+        );
+        let svg = all.svg(&graph);
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert_eq!(svg.matches("<polygon").count(), 1);
+        assert!(svg.contains(">b &amp; c</text>") && svg.contains("<path"));
+
+        // Too wide to fit at full zoom, so zoomed out.
+        let huge = Snapshot::new(
+            &graph,
+            Extent::All,
+            viewport,
+            (200.0, 100.0),
+            4,
+            colors,
+            false,
+        );
+        assert!(huge.viewport.zoom < 1.0 && huge.pixels().0 <= MAX_SIDE as u32);
+
+        let png = view.png(&graph).unwrap();
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (400, 200));
+        // The corner is the canvas, and the center the node.
+        assert_eq!(pixels[..4], [255, 255, 255, 255]);
+        let center = (100 * 400 + 200) * 4;
+        assert_eq!(pixels[center..center + 4], [0, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,379 @@
+//! Views of the canvas, defined in the graph: which entities it shows and
+//! how they are drawn.
+//!
+//! A view is an entity with `type=canvas-view`, a `name`, a `filter` query
+//! the entities shown match, see [`crate::query`], and its `rule` facts in
+//! order. A rule styles the entities matching its query, as in
+//! `type=person | color=#e06c75 size=1.5 icon=P`: `color` fills the node,
+//! `size` scales it and `icon` is drawn on it. Of the rules an entity
+//! matches, the last to set each of them wins. Its `layout`, if any, places
+//! the nodes, see [`super::layout`]. The renderers of plugins may also give
+//! nodes a [`Shape`], see [`crate::wasm`].
+
+use super::layout::Layout;
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{Action, Datum};
+use crate::query::Query;
+use crate::theme::Hex;
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// The type of view entities.
+pub const VIEW: &str = "canvas-view";
+/// The smallest and largest sizes of nodes, relative to the default.
+const SIZES: (f32, f32) = (0.25, 4.0);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct View {
+    /// The view entity, or `None` for one not yet recorded.
+    pub id: Option<Uuid>,
+    pub name: String,
+    /// The query the entities shown match, empty for all.
+    pub filter: String,
+    pub rules: Vec<Rule>,
+    pub layout: Layout,
+}
+
+/// A rule as written, each part empty if it doesn't set it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rule {
+    pub query: String,
+    pub color: String,
+    pub size: String,
+    pub icon: String,
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Rule> {
+        let (query, style) = s
+            .rsplit_once('|')
+            .ok_or_else(|| anyhow!("Invalid rule {}, expected <query> | <style>", s))?;
+        let mut rule = Rule {
+            query: query.trim().to_string(),
+            ..Rule::default()
+        };
+        for part in style.split_whitespace() {
+            match part.split_once('=') {
+                Some(("color", color)) => rule.color = color.to_string(),
+                Some(("size", size)) => rule.size = size.to_string(),
+                Some(("icon", icon)) => rule.icon = icon.to_string(),
+                _ => bail!("Invalid style {}, expected color, size or icon", part),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} |", self.query.trim())?;
+        for (name, value) in [
+            ("color", &self.color),
+            ("size", &self.size),
+            ("icon", &self.icon),
+        ] {
+            let value = value.trim();
+            if !value.is_empty() {
+                write!(f, " {}={}", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl View {
+    fn read(id: Uuid, entity: &Entity) -> Option<View> {
+        if entity.value("type") != Some(&Datum::String(VIEW.to_string())) {
+            return None;
+        }
+        let string = |datum: &Datum| match datum {
+            Datum::String(s) => Some(s.clone()),
+            _ => None,
+        };
+        Some(View {
+            id: Some(id),
+            name: entity.value("name").and_then(string).unwrap_or_default(),
+            filter: entity.value("filter").and_then(string).unwrap_or_default(),
+            rules: entity
+                .values("rule")
+                .iter()
+                .filter_map(string)
+                .filter_map(|rule| rule.parse().ok())
+                .collect(),
+            layout: entity
+                .value("layout")
+                .and_then(string)
+                .and_then(|layout| layout.parse().ok())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The actions that record the view, replacing what was recorded of it
+    /// before, giving it an entity if it has none yet.
+    pub fn save(&mut self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let id = match self.id {
+            Some(id) => {
+                for predicate in ["name", "filter", "rule", "layout"] {
+                    actions.push(Action::RemoveFact {
+                        subject: id,
+                        predicate: predicate.to_string(),
+                    });
+                }
+                id
+            }
+            None => {
+                let id = Uuid::new_v4();
+                actions.push(Action::CreateEntity { id });
+                actions.push(Action::AddFact {
+                    subject: id,
+                    predicate: "type".to_string(),
+                    datum: Datum::String(VIEW.to_string()),
+                });
+                self.id = Some(id);
+                id
+            }
+        };
+        let fact = |predicate: &str, value: String| Action::AddFact {
+            subject: id,
+            predicate: predicate.to_string(),
+            datum: Datum::String(value),
+        };
+        actions.push(fact("name", self.name.trim().to_string()));
+        if !self.filter.trim().is_empty() {
+            actions.push(fact("filter", self.filter.trim().to_string()));
+        }
+        for rule in &self.rules {
+            actions.push(fact("rule", rule.to_string()));
+        }
+        if self.layout != Layout::Positions {
+            actions.push(fact("layout", self.layout.to_string()));
+        }
+        actions
+    }
+}
+
+/// The views in the graph, by name.
+pub fn views(projection: &Projection) -> Vec<View> {
+    let mut views: Vec<View> = projection
+        .entities()
+        .filter_map(|(id, entity)| View::read(*id, entity))
+        .collect();
+    views.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+    views
+}
+
+/// How a node is drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    pub color: Option<Hex>,
+    /// Relative to the default size.
+    pub size: f32,
+    pub icon: Option<String>,
+    pub shape: Shape,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            color: None,
+            size: 1.0,
+            icon: None,
+            shape: Shape::Circle,
+        }
+    }
+}
+
+/// The outline of a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Shape {
+    #[default]
+    Circle,
+    Square,
+    Diamond,
+}
+
+impl FromStr for Shape {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Shape> {
+        match s.trim().to_lowercase().as_str() {
+            "circle" => Ok(Shape::Circle),
+            "square" => Ok(Shape::Square),
+            "diamond" => Ok(Shape::Diamond),
+            _ => bail!("Invalid shape {}, expected circle, square or diamond", s),
+        }
+    }
+}
+
+impl Shape {
+    /// The corners of the shape around `center`, as large as the circle of
+    /// `radius`, or `None` for a circle.
+    pub fn corners(self, (x, y): (f32, f32), radius: f32) -> Option<[(f32, f32); 4]> {
+        // Of the same area as the circle, so no shape looks more important.
+        let half = radius * std::f32::consts::PI.sqrt();
+        match self {
+            Shape::Circle => None,
+            Shape::Square => {
+                let r = half / 2.0;
+                Some([
+                    (x - r, y - r),
+                    (x + r, y - r),
+                    (x + r, y + r),
+                    (x - r, y + r),
+                ])
+            }
+            Shape::Diamond => {
+                let r = half / std::f32::consts::SQRT_2;
+                Some([(x, y - r), (x + r, y), (x, y + r), (x - r, y)])
+            }
+        }
+    }
+}
+
+/// The filter, rules and layout of a view, parsed. The default shows every
+/// entity as it is, where it is.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    filter: Option<Query>,
+    layout: Layout,
+    /// The styles of the entities matching each query, with a size of 0
+    /// for those setting none.
+    rules: Vec<(Query, Style)>,
+}
+
+impl Rules {
+    pub fn new(view: &View) -> Result<Rules> {
+        let filter = match view.filter.trim() {
+            "" => None,
+            filter => Some(filter.parse().context("Invalid filter")?),
+        };
+        let mut rules = Vec::new();
+        for rule in &view.rules {
+            let query: Query = rule
+                .query
+                .parse()
+                .with_context(|| format!("Invalid rule {}", rule))?;
+            let color = match rule.color.trim() {
+                "" => None,
+                color => Some(color.parse()?),
+            };
+            let size = match rule.size.trim() {
+                "" => 0.0,
+                size => size
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|size| size.is_finite() && *size > 0.0)
+                    .ok_or_else(|| anyhow!("Invalid size {}", size))?
+                    .clamp(SIZES.0, SIZES.1),
+            };
+            let icon = Some(rule.icon.trim().to_string()).filter(|icon| !icon.is_empty());
+            let style = Style {
+                color,
+                size,
+                icon,
+                ..Style::default()
+            };
+            rules.push((query, style));
+        }
+        Ok(Rules {
+            filter,
+            layout: view.layout,
+            rules,
+        })
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Whether `entity` is shown.
+    pub fn shows(&self, projection: &Projection, entity: &Entity) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(projection, entity))
+    }
+
+    /// How `entity` is drawn.
+    pub fn style(&self, projection: &Projection, entity: &Entity) -> Style {
+        let mut style = Style::default();
+        for (query, rule) in &self.rules {
+            if !query.matches(projection, entity) {
+                continue;
+            }
+            style.color = rule.color.or(style.color);
+            if rule.size > 0.0 {
+                style.size = rule.size;
+            }
+            if rule.icon.is_some() {
+                style.icon.clone_from(&rule.icon);
+            }
+        }
+        style
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_are_saved_and_applied() {
+        let mut projection = Projection::new();
+        let mut view = View {
+            id: None,
+            name: "People".to_string(),
+            filter: "type=person".to_string(),
+            rules: vec![
+                "type=person | color=#e06c75 icon=P".parse().unwrap(),
+                Rule {
+                    query: "role=lead".to_string(),
+                    size: "9".to_string(),
+                    ..Rule::default()
+                },
+            ],
+            layout: Layout::ALL[1],
+        };
+        view.save().iter().for_each(|a| projection.apply(a));
+        let [saved] = &views(&projection)[..] else {
+            panic!("expected one view");
+        };
+        assert_eq!(saved, &view);
+        assert_eq!(saved.rules[1].to_string(), "role=lead | size=9");
+
+        let (ada, box_) = (Uuid::new_v4(), Uuid::new_v4());
+        for (id, facts) in [
+            (ada, [("type", "person"), ("role", "lead")]),
+            (box_, [("type", "thing"), ("role", "lead")]),
+        ] {
+            projection.apply(&Action::CreateEntity { id });
+            for (predicate, value) in facts {
+                projection.apply(&Action::AddFact {
+                    subject: id,
+                    predicate: predicate.to_string(),
+                    datum: Datum::String(value.to_string()),
+                });
+            }
+        }
+        let rules = Rules::new(saved).unwrap();
+        let ada = projection.entity(&ada).unwrap();
+        let box_ = projection.entity(&box_).unwrap();
+        assert!(rules.shows(&projection, ada));
+        assert!(!rules.shows(&projection, box_));
+        let style = rules.style(&projection, ada);
+        assert_eq!(style.color, Some(Hex(0xe0, 0x6c, 0x75)));
+        assert_eq!(style.size, SIZES.1);
+        assert_eq!(style.icon.as_deref(), Some("P"));
+
+        let invalid = View {
+            rules: vec!["type=person | color=red".parse().unwrap()],
+            ..View::default()
+        };
+        assert!(Rules::new(&invalid).is_err());
+        assert!("type=person | shape=round".parse::<Rule>().is_err());
+    }
+}
@@ -0,0 +1,637 @@
+//! A GraphQL view of the graph, for dashboards and integrations, served by
+//! [`crate::api`] at `/graphql`.
+//!
+//! The schema is generated from the predicates in the graph: every entity
+//! has a field for each predicate whose name is a GraphQL name, a list of
+//! entities for predicates that only link and a list of values otherwise.
+//! Generic fields reach the others and follow links either way:
+//!
+//! ```graphql
+//! {
+//!   entities(query: "type=task status!=done", first: 10) {
+//!     id
+//!     name
+//!     due
+//!     parent { name }
+//!     backlinks(predicate: "blocks") { name }
+//!   }
+//! }
+//! ```
+//!
+//! Only queries are supported, without fragments, directives or
+//! introspection; `GET /graphql` answers the schema instead.
+
+use crate::export::iso_date_time;
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::Datum;
+use crate::query::Query;
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The fields of entities that aren't predicates.
+const ENTITY_FIELDS: [&str; 8] = [
+    "__typename",
+    "id",
+    "name",
+    "value",
+    "values",
+    "facts",
+    "links",
+    "backlinks",
+];
+
+/// What the graph holds under a predicate.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Predicate {
+    pub name: String,
+    /// The kinds of its values, e.g. `String` and `Entity`.
+    pub kinds: BTreeSet<&'static str>,
+    /// How many values it has.
+    pub count: usize,
+}
+
+impl Predicate {
+    /// Whether all of its values link to entities.
+    fn links(&self) -> bool {
+        self.kinds.len() == 1 && self.kinds.contains("Entity")
+    }
+
+    /// Whether it is a field of entities in the schema.
+    fn is_field(&self) -> bool {
+        let mut chars = self.name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+            && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+            && !self.name.starts_with("__")
+            && !ENTITY_FIELDS.contains(&self.name.as_str())
+    }
+}
+
+/// The predicates in the graph, by name.
+pub fn predicates(projection: &Projection) -> BTreeMap<String, Predicate> {
+    let mut predicates: BTreeMap<String, Predicate> = BTreeMap::new();
+    for (_, entity) in projection.entities() {
+        for (name, values) in entity.facts() {
+            let predicate = predicates
+                .entry(name.to_string())
+                .or_insert_with(|| Predicate {
+                    name: name.to_string(),
+                    ..Predicate::default()
+                });
+            predicate.count += values.len();
+            predicate.kinds.extend(values.iter().map(kind));
+        }
+    }
+    predicates
+}
+
+fn kind(datum: &Datum) -> &'static str {
+    match datum {
+        Datum::String(_) => "String",
+        Datum::Integer(_) => "Integer",
+        Datum::Float(_) => "Float",
+        Datum::Boolean(_) => "Boolean",
+        Datum::DateTime(_) => "DateTime",
+        Datum::Entity(_) => "Entity",
+    }
+}
+
+/// The schema of the graph in the GraphQL schema language.
+pub fn schema(projection: &Projection) -> String {
+    let mut schema = String::from(
+        "\"\"\"A value of a fact: a string, a number, a boolean, a date and time in
+RFC 3339 or the id of an entity.\"\"\"
+scalar Value
+
+type Query {
+  entity(id: ID!): Entity
+  \"\"\"The entities matching a query such as \"type=task status!=done\".\"\"\"
+  entities(query: String, first: Int): [Entity!]!
+  predicates: [Predicate!]!
+}
+
+type Entity {
+  id: ID!
+  name: String
+  value(predicate: String!): Value
+  values(predicate: String!): [Value!]!
+  facts(predicate: String): [Fact!]!
+  \"\"\"The entities this one links to.\"\"\"
+  links(predicate: String): [Entity!]!
+  \"\"\"The entities linking to this one.\"\"\"
+  backlinks(predicate: String): [Entity!]!
+",
+    );
+    for predicate in predicates(projection).values().filter(|p| p.is_field()) {
+        let kind = if predicate.links() { "Entity" } else { "Value" };
+        let _ = writeln!(schema, "  {}: [{}!]!", predicate.name, kind);
+    }
+    schema.push_str(
+        "}
+
+type Fact {
+  predicate: String!
+  value: Value!
+  \"\"\"The entity the value links to, if it does.\"\"\"
+  entity: Entity
+}
+
+type Predicate {
+  name: String!
+  kinds: [String!]!
+  count: Int!
+}
+",
+    );
+    schema
+}
+
+/// Runs the GraphQL `query` on `projection`, with the values of its
+/// `variables`, and answers as GraphQL does, with `data` or `errors`.
+pub fn execute(projection: &Projection, query: &str, variables: &Map<String, Value>) -> Value {
+    let run = || -> Result<Value> {
+        let selection = parse(query)?;
+        let executor = Executor {
+            projection,
+            predicates: predicates(projection),
+            variables,
+        };
+        executor.select(Node::Query, &selection)
+    };
+    match run() {
+        Ok(data) => json!({ "data": data }),
+        Err(e) => json!({ "data": null, "errors": [{ "message": format!("{:#}", e) }] }),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    alias: Option<String>,
+    name: String,
+    arguments: Vec<(String, Value)>,
+    selection: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Punct(char),
+    Name(String),
+    Value(Value),
+}
+
+fn tokens(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() || c == ',' => {}
+            '#' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '{' | '}' | '(' | ')' | ':' | '!' | '$' | '[' | ']' | '=' | '@' => {
+                tokens.push(Token::Punct(c))
+            }
+            '.' => bail!("Fragments aren't supported"),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(c) => string.push(c),
+                            None => bail!("Unclosed string"),
+                        },
+                        Some(c) => string.push(c),
+                        None => bail!("Unclosed string"),
+                    }
+                }
+                tokens.push(Token::Value(Value::String(string)));
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || ".+-".contains(*c))
+                {
+                    number.push(c);
+                }
+                let value = match number.parse::<i64>() {
+                    Ok(n) => json!(n),
+                    Err(_) => json!(number
+                        .parse::<f64>()
+                        .with_context(|| format!("Invalid number {}", number))?),
+                };
+                tokens.push(Token::Value(value));
+            }
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let mut name = c.to_string();
+                while let Some(c) = chars.next_if(|c| *c == '_' || c.is_ascii_alphanumeric()) {
+                    name.push(c);
+                }
+                tokens.push(Token::Name(name));
+            }
+            c => bail!("Unexpected {}", c),
+        }
+    }
+    Ok(tokens)
+}
+
+/// The selection of the query operation in `text`.
+fn parse(text: &str) -> Result<Vec<Field>> {
+    let mut parser = Parser {
+        tokens: tokens(text)?,
+        at: 0,
+    };
+    match parser.peek() {
+        Some(Token::Name(keyword)) if keyword == "query" => {
+            parser.at += 1;
+            if let Some(Token::Name(_)) = parser.peek() {
+                parser.at += 1;
+            }
+            if parser.eat('(') {
+                // The types of the variables aren't checked.
+                while !parser.eat(')') {
+                    parser.next()?;
+                }
+            }
+        }
+        Some(Token::Name(keyword)) => bail!("Only queries are supported, not {}", keyword),
+        _ => {}
+    }
+    let selection = parser.selection()?;
+    if parser.at < parser.tokens.len() {
+        bail!("Only one operation is supported");
+    }
+    Ok(selection)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .peek()
+            .cloned()
+            .context("Unexpected end of the query")?;
+        self.at += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        let eaten = self.peek() == Some(&Token::Punct(punct));
+        if eaten {
+            self.at += 1;
+        }
+        eaten
+    }
+
+    fn expect(&mut self, punct: char) -> Result<()> {
+        match self.eat(punct) {
+            true => Ok(()),
+            false => bail!("Expected {}", punct),
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name),
+            token => bail!("Expected a name, not {:?}", token),
+        }
+    }
+
+    fn selection(&mut self) -> Result<Vec<Field>> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        while !self.eat('}') {
+            if self.peek() == Some(&Token::Punct('@')) {
+                bail!("Directives aren't supported");
+            }
+            let mut name = self.name()?;
+            let mut alias = None;
+            if self.eat(':') {
+                alias = Some(name);
+                name = self.name()?;
+            }
+            let mut arguments = Vec::new();
+            if self.eat('(') {
+                while !self.eat(')') {
+                    let argument = self.name()?;
+                    self.expect(':')?;
+                    arguments.push((argument, self.value()?));
+                }
+            }
+            let selection = match self.peek() {
+                Some(Token::Punct('{')) => self.selection()?,
+                _ => Vec::new(),
+            };
+            fields.push(Field {
+                alias,
+                name,
+                arguments,
+                selection,
+            });
+        }
+        Ok(fields)
+    }
+
+    /// A value, with variables as `{"$": name}`.
+    fn value(&mut self) -> Result<Value> {
+        Ok(match self.next()? {
+            Token::Value(value) => value,
+            Token::Punct('$') => json!({ "$": self.name()? }),
+            Token::Name(name) => match name.as_str() {
+                "true" => json!(true),
+                "false" => json!(false),
+                "null" => Value::Null,
+                // Enum values.
+                _ => Value::String(name),
+            },
+            Token::Punct('[') => {
+                let mut list = Vec::new();
+                while !self.eat(']') {
+                    list.push(self.value()?);
+                }
+                Value::Array(list)
+            }
+            token => bail!("Expected a value, not {:?}", token),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Node<'a> {
+    Query,
+    Entity(Uuid, &'a Entity),
+    Fact(&'a str, &'a Datum),
+    Predicate(&'a Predicate),
+}
+
+struct Executor<'a> {
+    projection: &'a Projection,
+    predicates: BTreeMap<String, Predicate>,
+    variables: &'a Map<String, Value>,
+}
+
+impl<'a> Executor<'a> {
+    fn select(&'a self, node: Node<'a>, selection: &[Field]) -> Result<Value> {
+        let mut object = Map::new();
+        for field in selection {
+            let key = field.alias.clone().unwrap_or_else(|| field.name.clone());
+            let value = self
+                .resolve(node, field)
+                .with_context(|| format!("Failed to resolve {}", key))?;
+            object.insert(key, value);
+        }
+        Ok(Value::Object(object))
+    }
+
+    /// The value of the argument `name` of `field`, if given.
+    fn argument(&self, field: &Field, name: &str) -> Option<Value> {
+        let (_, value) = field.arguments.iter().find(|(n, _)| n == name)?;
+        match value.get("$").and_then(Value::as_str) {
+            Some(variable) => self.variables.get(variable).cloned(),
+            None => Some(value.clone()),
+        }
+        .filter(|value| !value.is_null())
+    }
+
+    fn string(&self, field: &Field, name: &str) -> Result<Option<String>> {
+        match self.argument(field, name) {
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(value) => bail!("{} must be a string, not {}", name, value),
+            None => Ok(None),
+        }
+    }
+
+    /// The objects in `nodes`, each with the selection of `field`.
+    fn objects(&'a self, nodes: Vec<Node<'a>>, field: &Field) -> Result<Value> {
+        if field.selection.is_empty() {
+            bail!("Select the fields of {}", field.name);
+        }
+        let objects: Result<Vec<Value>> = nodes
+            .into_iter()
+            .map(|node| self.select(node, &field.selection))
+            .collect();
+        Ok(Value::Array(objects?))
+    }
+
+    fn entity(&'a self, id: Uuid) -> Option<Node<'a>> {
+        Some(Node::Entity(id, self.projection.entity(&id)?))
+    }
+
+    fn resolve(&'a self, node: Node<'a>, field: &Field) -> Result<Value> {
+        let scalar = |value: Value| -> Result<Value> {
+            match field.selection.is_empty() {
+                true => Ok(value),
+                false => bail!("{} has no fields to select", field.name),
+            }
+        };
+        let first = |list: Value| match list {
+            Value::Array(mut list) if !list.is_empty() => list.swap_remove(0),
+            _ => Value::Null,
+        };
+        match (node, field.name.as_str()) {
+            (Node::Query, "__typename") => scalar(json!("Query")),
+            (Node::Query, "entity") => {
+                let id = self.string(field, "id")?.context("Give the id")?;
+                let id: Uuid = id.parse().with_context(|| format!("Invalid id {}", id))?;
+                let nodes = self.entity(id).into_iter().collect();
+                Ok(first(self.objects(nodes, field)?))
+            }
+            (Node::Query, "entities") => {
+                let query: Query = self.string(field, "query")?.unwrap_or_default().parse()?;
+                let first = match self.argument(field, "first") {
+                    Some(first) => first.as_u64().context("first must be a count")? as usize,
+                    None => usize::MAX,
+                };
+                let mut ids = query.run(self.projection);
+                ids.sort();
+                let nodes = ids.into_iter().filter_map(|id| self.entity(id));
+                self.objects(nodes.take(first).collect(), field)
+            }
+            (Node::Query, "predicates") => {
+                let nodes = self.predicates.values().map(Node::Predicate).collect();
+                self.objects(nodes, field)
+            }
+
+            (Node::Entity(..), "__typename") => scalar(json!("Entity")),
+            (Node::Entity(id, _), "id") => scalar(json!(id)),
+            (Node::Entity(_, entity), "name") => {
+                scalar(entity.value("name").map_or(Value::Null, datum))
+            }
+            (Node::Entity(_, entity), "value" | "values") => {
+                let predicate = self
+                    .string(field, "predicate")?
+                    .context("Give the predicate")?;
+                let values: Vec<Value> = entity.values(&predicate).iter().map(datum).collect();
+                match field.name == "value" {
+                    true => scalar(first(Value::Array(values))),
+                    false => scalar(Value::Array(values)),
+                }
+            }
+            (Node::Entity(_, entity), "facts") => {
+                let predicate = self.string(field, "predicate")?;
+                let facts = entity
+                    .facts()
+                    .filter(|(p, _)| predicate.as_deref().is_none_or(|q| q == *p))
+                    .flat_map(|(p, values)| values.iter().map(move |d| Node::Fact(p, d)));
+                self.objects(facts.collect(), field)
+            }
+            (Node::Entity(_, entity), "links") => {
+                let predicate = self.string(field, "predicate")?;
+                let targets = entity
+                    .facts()
+                    .filter(|(p, _)| predicate.as_deref().is_none_or(|q| q == *p))
+                    .flat_map(|(_, values)| values)
+                    .filter_map(|d| match d {
+                        Datum::Entity(target) => self.entity(*target),
+                        _ => None,
+                    });
+                self.objects(targets.collect(), field)
+            }
+            (Node::Entity(id, _), "backlinks") => {
+                let predicate = self.string(field, "predicate")?;
+                let mut sources: Vec<Uuid> = self
+                    .projection
+                    .entities()
+                    .filter(|(_, e)| {
+                        e.facts().any(|(p, values)| {
+                            predicate.as_deref().is_none_or(|q| q == p)
+                                && values.contains(&Datum::Entity(id))
+                        })
+                    })
+                    .map(|(source, _)| *source)
+                    .collect();
+                sources.sort();
+                let nodes = sources.into_iter().filter_map(|s| self.entity(s));
+                self.objects(nodes.collect(), field)
+            }
+            (Node::Entity(_, entity), name) => {
+                let predicate = self
+                    .predicates
+                    .get(name)
+                    .filter(|p| p.is_field())
+                    .ok_or_else(|| anyhow!("Entities have no field {}", name))?;
+                let values = entity.values(name);
+                match predicate.links() {
+                    true => {
+                        let targets = values.iter().filter_map(|d| match d {
+                            Datum::Entity(target) => self.entity(*target),
+                            _ => None,
+                        });
+                        self.objects(targets.collect(), field)
+                    }
+                    false => scalar(Value::Array(values.iter().map(datum).collect())),
+                }
+            }
+
+            (Node::Fact(..), "__typename") => scalar(json!("Fact")),
+            (Node::Fact(predicate, _), "predicate") => scalar(json!(predicate)),
+            (Node::Fact(_, value), "value") => scalar(datum(value)),
+            (Node::Fact(_, value), "entity") => {
+                let nodes = match value {
+                    Datum::Entity(target) => self.entity(*target).into_iter().collect(),
+                    _ => Vec::new(),
+                };
+                Ok(first(self.objects(nodes, field)?))
+            }
+
+            (Node::Predicate(_), "__typename") => scalar(json!("Predicate")),
+            (Node::Predicate(predicate), "name") => scalar(json!(predicate.name)),
+            (Node::Predicate(predicate), "kinds") => scalar(json!(predicate.kinds)),
+            (Node::Predicate(predicate), "count") => scalar(json!(predicate.count)),
+
+            (node, name) => {
+                let typename = match node {
+                    Node::Query => "Query",
+                    Node::Entity(..) => "Entity",
+                    Node::Fact(..) => "Fact",
+                    Node::Predicate(_) => "Predicate",
+                };
+                bail!("{} has no field {}", typename, name)
+            }
+        }
+    }
+}
+
+/// A value as GraphQL answers it.
+fn datum(datum: &Datum) -> Value {
+    match datum {
+        Datum::String(s) => json!(s),
+        Datum::Integer(n) => json!(n),
+        Datum::Float(n) => json!(n),
+        Datum::Boolean(b) => json!(b),
+        Datum::DateTime(t) => match iso_date_time(*t) {
+            Some(time) => json!(time),
+            None => json!(t),
+        },
+        Datum::Entity(id) => json!(id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn queries_follow_links_both_ways() {
+        let mut projection = Projection::new();
+        let (project, task) = (Uuid::new_v4(), Uuid::new_v4());
+        let fact = |subject, predicate: &str, datum| Action::AddFact {
+            subject,
+            predicate: predicate.into(),
+            datum,
+        };
+        for action in [
+            Action::CreateEntity { id: project },
+            fact(project, "name", Datum::String("Roof".into())),
+            Action::CreateEntity { id: task },
+            fact(task, "type", Datum::String("task".into())),
+            fact(task, "name", Datum::String("Buy tiles".into())),
+            fact(task, "parent", Datum::Entity(project)),
+            fact(task, "estimate", Datum::Integer(3)),
+        ] {
+            projection.apply(&action);
+        }
+        assert!(schema(&projection).contains("  parent: [Entity!]!\n"));
+
+        let query = r#"
+            query Tasks($type: String) {
+                tasks: entities(query: $type) {
+                    name
+                    estimate
+                    parent { name backlinks(predicate: "parent") { id } }
+                }
+            }
+        "#;
+        let variables = json!({ "type": "type=task" });
+        let answer = execute(&projection, query, variables.as_object().unwrap());
+        assert_eq!(
+            answer,
+            json!({ "data": { "tasks": [{
+                "name": "Buy tiles",
+                "estimate": [3],
+                "parent": [{ "name": "Roof", "backlinks": [{ "id": task }] }],
+            }] } })
+        );
+
+        let wrong = execute(&projection, "{ entities { colour } }", &Map::new());
+        assert_eq!(wrong["data"], Value::Null);
+        assert!(wrong["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("no field colour"));
+        assert!(execute(&projection, "mutation { x }", &Map::new())["errors"].is_array());
+    }
+}
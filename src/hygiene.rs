@@ -0,0 +1,220 @@
+//! Graph hygiene: entities and facts that were likely left behind.
+//!
+//! The report lists
+//!
+//! - entities that can't be reached from the configured roots by following
+//!   links in either direction, or if there are no roots, entities without
+//!   any links,
+//! - links to entities that were deleted, and
+//! - entities without any facts.
+//!
+//! Each can be fixed in bulk: linked to a parent, deleted, or ignored, which
+//! marks the entity with an `ignored_by_hygiene` fact so that later reports
+//! leave it out.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use std::collections::{HashMap, HashSet, VecDeque};
+use uuid::Uuid;
+
+/// The predicate marking entities the report leaves out.
+pub const IGNORED: &str = "ignored_by_hygiene";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Entities not reachable from the roots, or without links if there are
+    /// no roots.
+    pub unreachable: Vec<Uuid>,
+    /// Links to deleted entities.
+    pub dangling: Vec<Dangling>,
+    pub empty: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Dangling {
+    pub subject: Uuid,
+    pub predicate: String,
+    /// The deleted entity linked to.
+    pub target: Uuid,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.unreachable.is_empty() && self.dangling.is_empty() && self.empty.is_empty()
+    }
+}
+
+pub fn report(projection: &Projection, roots: &[Uuid]) -> Report {
+    let mut links: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    let mut report = Report::default();
+    for (id, entity) in projection.entities() {
+        for (predicate, values) in entity.facts() {
+            for datum in values {
+                let Datum::Entity(target) = datum else {
+                    continue;
+                };
+                if !projection.contains(target) {
+                    report.dangling.push(Dangling {
+                        subject: *id,
+                        predicate: predicate.to_string(),
+                        target: *target,
+                    });
+                    continue;
+                }
+                links.entry(*id).or_default().push(*target);
+                links.entry(*target).or_default().push(*id);
+            }
+        }
+    }
+
+    let reached = if roots.is_empty() {
+        links.keys().copied().collect()
+    } else {
+        reachable(&links, roots)
+    };
+    let ignored = |id: &Uuid| {
+        projection
+            .entity(id)
+            .is_some_and(|e| e.value(IGNORED) == Some(&Datum::Boolean(true)))
+    };
+    for (id, entity) in projection.entities() {
+        if ignored(id) {
+            continue;
+        }
+        if entity.facts().next().is_none() {
+            report.empty.push(*id);
+        } else if !reached.contains(id) {
+            report.unreachable.push(*id);
+        }
+    }
+    report.unreachable.sort();
+    report.empty.sort();
+    report.dangling.sort();
+    report
+}
+
+/// The entities connected to `roots` by links in either direction.
+fn reachable(links: &HashMap<Uuid, Vec<Uuid>>, roots: &[Uuid]) -> HashSet<Uuid> {
+    let mut reached: HashSet<Uuid> = roots.iter().copied().collect();
+    let mut queue: VecDeque<Uuid> = roots.iter().copied().collect();
+    while let Some(id) = queue.pop_front() {
+        for next in links.get(&id).into_iter().flatten() {
+            if reached.insert(*next) {
+                queue.push_back(*next);
+            }
+        }
+    }
+    reached
+}
+
+/// The actions that link each of `ids` to `parent` through `hierarchy`.
+pub fn link(ids: &[Uuid], parent: Uuid, hierarchy: &str) -> Vec<Action> {
+    ids.iter()
+        .filter(|id| **id != parent)
+        .map(|id| Action::AddFact {
+            subject: *id,
+            predicate: hierarchy.to_string(),
+            datum: Datum::Entity(parent),
+        })
+        .collect()
+}
+
+/// The actions that delete `ids`.
+pub fn delete(ids: &[Uuid]) -> Vec<Action> {
+    ids.iter()
+        .map(|id| Action::DeleteEntity { id: *id })
+        .collect()
+}
+
+/// The actions that leave `ids` out of later reports.
+pub fn ignore(ids: &[Uuid]) -> Vec<Action> {
+    ids.iter()
+        .map(|id| Action::AddFact {
+            subject: *id,
+            predicate: IGNORED.to_string(),
+            datum: Datum::Boolean(true),
+        })
+        .collect()
+}
+
+/// The actions that drop the `dangling` links, keeping the other values of
+/// their predicates.
+pub fn unlink(projection: &Projection, dangling: &[Dangling]) -> Vec<Action> {
+    let mut targets: HashMap<(Uuid, &str), HashSet<Uuid>> = HashMap::new();
+    for link in dangling {
+        targets
+            .entry((link.subject, &link.predicate))
+            .or_default()
+            .insert(link.target);
+    }
+    let mut actions = Vec::new();
+    for ((subject, predicate), gone) in targets {
+        let Some(entity) = projection.entity(&subject) else {
+            continue;
+        };
+        actions.push(Action::RemoveFact {
+            subject,
+            predicate: predicate.to_string(),
+        });
+        for datum in entity.values(predicate) {
+            if !matches!(datum, Datum::Entity(target) if gone.contains(target)) {
+                actions.push(Action::AddFact {
+                    subject,
+                    predicate: predicate.to_string(),
+                    datum: datum.clone(),
+                });
+            }
+        }
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(subject: Uuid, predicate: &str, datum: Datum) -> Action {
+        Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        }
+    }
+
+    #[test]
+    fn reports_and_fixes_left_behind_entities() {
+        let [root, child, stray, empty, gone] = [(); 5].map(|_| Uuid::new_v4());
+        let mut projection = Projection::new();
+        for id in [root, child, stray, empty, gone] {
+            projection.apply(&Action::CreateEntity { id });
+        }
+        projection.apply(&add(root, "name", Datum::String("Home".into())));
+        projection.apply(&add(child, "parent", Datum::Entity(root)));
+        projection.apply(&add(child, "see", Datum::Entity(gone)));
+        projection.apply(&add(child, "see", Datum::Entity(root)));
+        projection.apply(&add(stray, "name", Datum::String("Stray".into())));
+        projection.apply(&Action::DeleteEntity { id: gone });
+
+        let found = report(&projection, &[root]);
+        assert_eq!(found.unreachable, [stray]);
+        assert_eq!(found.empty, [empty]);
+        assert_eq!(
+            found.dangling,
+            [Dangling {
+                subject: child,
+                predicate: "see".to_string(),
+                target: gone,
+            }]
+        );
+
+        let mut fixes = link(&found.unreachable, root, "parent");
+        fixes.extend(ignore(&found.empty));
+        fixes.extend(unlink(&projection, &found.dangling));
+        projection.apply(&Action::Transaction { actions: fixes });
+        assert!(report(&projection, &[root]).is_clean());
+        assert_eq!(
+            projection.entity(&child).unwrap().values("see"),
+            [Datum::Entity(root)]
+        );
+    }
+}
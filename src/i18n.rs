@@ -0,0 +1,140 @@
+//! Translations of the editor's strings, as Fluent files in `locales/`.
+//!
+//! Messages missing from a locale fall back to English, and unknown message
+//! ids are shown as is, so a partial translation is still usable.
+
+use fluent_bundle::{FluentArgs, FluentBundle, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+/// The locales with a translation, by language and the Fluent source.
+const LOCALES: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// The languages with a translation, English first.
+pub fn languages() -> impl Iterator<Item = &'static str> {
+    LOCALES.iter().map(|(language, _)| *language)
+}
+
+/// The user's locale as reported by the platform, e.g. `de-AT`.
+pub fn detect() -> Option<String> {
+    sys_locale::get_locale()
+}
+
+pub struct Localizer {
+    /// The bundle of the chosen locale followed by the English one.
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+impl Localizer {
+    /// A localizer for `locale`, e.g. `de-AT`. Only its language is used to
+    /// pick a translation; unknown languages get English.
+    pub fn new(locale: &str) -> Localizer {
+        let requested: LanguageIdentifier = locale
+            .replace('_', "-")
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .unwrap_or_default();
+        let mut bundles = Vec::new();
+        let translated = LOCALES
+            .iter()
+            .find(|(language, _)| *language != "en" && requested.language.as_str() == *language);
+        if let Some((_, source)) = translated {
+            bundles.push(bundle(requested, source));
+        }
+        let english = "en".parse().expect("locales have valid names");
+        bundles.push(bundle(english, LOCALES[0].1));
+        Localizer { bundles }
+    }
+
+    /// The language of the translation in use.
+    pub fn language(&self) -> &str {
+        self.bundles[0].locales[0].language.as_str()
+    }
+
+    /// The message `id`.
+    pub fn text(&self, id: &str) -> String {
+        self.format(id, &[])
+    }
+
+    /// The message `id` with its variables set to `args`.
+    pub fn format(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+        for bundle in &self.bundles {
+            let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) else {
+                continue;
+            };
+            let mut errors = Vec::new();
+            return bundle
+                .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                .into_owned();
+        }
+        id.to_string()
+    }
+}
+
+fn bundle(locale: LanguageIdentifier, source: &str) -> FluentBundle<FluentResource> {
+    let resource = FluentResource::try_new(source.to_string()).expect("locales are valid Fluent");
+    let mut bundle = FluentBundle::new(vec![locale]);
+    // Isolation marks show up as boxes in iced's text.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .expect("locales have unique message ids");
+    bundle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_with_english_fallback() {
+        let german = Localizer::new("de_AT.UTF-8");
+        assert_eq!(german.language(), "de");
+        assert_eq!(german.text("close"), "Schließen");
+        assert_eq!(
+            german.format("entity-count", &[("count", 1.into())]),
+            "1 Entität"
+        );
+        assert_eq!(
+            german.format(
+                "journal-month",
+                &[("month", 3.into()), ("year", 2024.into())]
+            ),
+            "März 2024"
+        );
+
+        let english = Localizer::new("fr-FR");
+        assert_eq!(english.language(), "en");
+        assert_eq!(
+            english.format("entity-count", &[("count", 2.into())]),
+            "2 entities"
+        );
+        assert_eq!(english.text("no-such-message"), "no-such-message");
+    }
+
+    #[test]
+    fn locales_define_the_same_messages() {
+        // Messages start at the beginning of a line with `id =`.
+        let ids = |source: &str| -> Vec<String> {
+            let mut ids: Vec<String> = source
+                .lines()
+                .filter(|line| line.starts_with(|c: char| c.is_ascii_alphabetic()))
+                .filter_map(|line| line.split_once(" =").map(|(id, _)| id.to_string()))
+                .collect();
+            ids.sort();
+            ids
+        };
+        let english = ids(LOCALES[0].1);
+        for (language, source) in &LOCALES[1..] {
+            assert_eq!(ids(source), english, "messages of {}", language);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Importers that turn external data into actions on the graph.
+//!
+//! Importers derive entity ids from a stable key (e.g. a primary key or a
+//! message id) so running the same import twice updates the entities from the
+//! first run instead of creating duplicates.
+
+pub mod csv;
+pub mod json;
+pub mod markdown;
+pub mod mbox;
+pub mod rdf;
+pub mod sql;
+pub mod text;
+pub mod vault;
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use uuid::{uuid, Uuid};
+
+/// The facts of an entity read from a file, by predicate.
+pub type Record = BTreeMap<String, Vec<Datum>>;
+
+/// The distance between entities placed on a grid, in logical pixels.
+const GRID: f32 = 40.0;
+
+/// The namespace for the name-based ids of imported entities.
+pub const NAMESPACE: Uuid = uuid!("b7cdf714-7bcd-4709-ae02-f32718b7f088");
+
+/// Returns the id of the entity imported under `key`.
+pub fn entity_id(key: &str) -> Uuid {
+    Uuid::new_v5(&NAMESPACE, key.as_bytes())
+}
+
+/// Returns the actions that give entity `id` exactly the values in `facts`.
+///
+/// The entity is created if it is not in the projection. Predicates that are
+/// not mentioned in `facts` are left untouched, so edits made in the graph to
+/// other predicates survive a re-import. An empty list of values removes the
+/// predicate.
+pub fn upsert(
+    projection: &Projection,
+    id: Uuid,
+    facts: &BTreeMap<String, Vec<Datum>>,
+) -> Vec<Action> {
+    let mut actions = Vec::new();
+    let entity = projection.entity(&id);
+    if entity.is_none() {
+        actions.push(Action::CreateEntity { id });
+    }
+
+    for (predicate, values) in facts {
+        let current = entity.map(|e| e.values(predicate)).unwrap_or_default();
+        if current == values.as_slice() {
+            continue;
+        }
+        if !current.is_empty() {
+            actions.push(Action::RemoveFact {
+                subject: id,
+                predicate: predicate.clone(),
+            });
+        }
+        for datum in values {
+            actions.push(Action::AddFact {
+                subject: id,
+                predicate: predicate.clone(),
+                datum: datum.clone(),
+            });
+        }
+    }
+
+    actions
+}
+
+/// The value in a cell of untyped text: a number, a boolean or the text.
+pub fn infer(text: &str) -> Datum {
+    if let Ok(n) = text.parse() {
+        Datum::Integer(n)
+    } else if let Ok(n) = text.parse::<f64>() {
+        Datum::Float(n)
+    } else if let Ok(b) = text.parse() {
+        Datum::Boolean(b)
+    } else {
+        Datum::String(text.to_string())
+    }
+}
+
+/// The entities in a CSV, JSON or Markdown file, by its extension.
+pub fn file(path: &Path) -> Result<Vec<Record>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let records = match extension.to_ascii_lowercase().as_str() {
+        "csv" => csv::records(&text),
+        "json" => json::records(&text),
+        "md" | "markdown" => {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            Ok(vec![markdown::record(&text, stem)])
+        }
+        _ => bail!(
+            "Can't import {}, expected a CSV, JSON or Markdown file",
+            path.display()
+        ),
+    };
+    records.with_context(|| format!("Failed to import {}", path.display()))
+}
+
+/// The actions that import each of `records` from the file `source`, laid
+/// out on a square grid from `at` with their `x` and `y` facts.
+pub fn place(
+    projection: &Projection,
+    source: &Path,
+    records: &[Record],
+    at: (f32, f32),
+) -> Vec<Vec<Action>> {
+    let columns = (records.len() as f32).sqrt().ceil().max(1.0) as usize;
+    records
+        .iter()
+        .enumerate()
+        .map(|(n, record)| {
+            let id = entity_id(&format!("{}#{}", source.display(), n));
+            let mut facts = record.clone();
+            let (column, row) = (n % columns, n / columns);
+            for (predicate, coordinate) in [
+                ("x", at.0 + column as f32 * GRID),
+                ("y", at.1 + row as f32 * GRID),
+            ] {
+                facts.insert(predicate.to_string(), vec![Datum::Float(coordinate as f64)]);
+            }
+            upsert(projection, id, &facts)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upsert_only_emits_changes() {
+        let id = entity_id("test:1");
+        let mut projection = Projection::new();
+        let mut facts = BTreeMap::new();
+        facts.insert("name".to_string(), vec![Datum::String("Ada".into())]);
+        facts.insert("age".to_string(), vec![Datum::Integer(36)]);
+
+        let first = upsert(&projection, id, &facts);
+        assert_eq!(first.len(), 3);
+        first.iter().for_each(|a| projection.apply(a));
+        assert!(upsert(&projection, id, &facts).is_empty());
+
+        facts.insert("age".to_string(), vec![Datum::Integer(37)]);
+        assert_eq!(
+            upsert(&projection, id, &facts),
+            vec![
+                Action::RemoveFact {
+                    subject: id,
+                    predicate: "age".into()
+                },
+                Action::AddFact {
+                    subject: id,
+                    predicate: "age".into(),
+                    datum: Datum::Integer(37)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn places_files_on_a_grid() {
+        let dir = std::env::temp_dir().join(format!("graphite-import-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let json = dir.join("people.json");
+        std::fs::write(
+            &json,
+            r#"[{"name": "Ada", "tags": ["math", null]}, {"age": 36.5}]"#,
+        )
+        .unwrap();
+        let note = dir.join("Ideas.md");
+        std::fs::write(&note, "---\nstatus: draft\n---\nSome thoughts\n").unwrap();
+
+        let people = file(&json).unwrap();
+        assert_eq!(people[0]["tags"], vec![Datum::String("math".into())]);
+        assert_eq!(people[1]["age"], vec![Datum::Float(36.5)]);
+        let ideas = file(&note).unwrap();
+        assert_eq!(ideas[0]["name"], vec![Datum::String("Ideas".into())]);
+        assert_eq!(ideas[0]["status"], vec![Datum::String("draft".into())]);
+        assert!(file(&dir.join("photo.gif")).is_err());
+
+        let mut projection = Projection::new();
+        let placed = place(&projection, &json, &people, (100.0, 50.0));
+        placed.iter().flatten().for_each(|a| projection.apply(a));
+        let second = projection.entity(&entity_id(&format!("{}#1", json.display())));
+        assert_eq!(second.unwrap().value("x"), Some(&Datum::Float(140.0)));
+        // Importing again changes nothing.
+        let again = place(&projection, &json, &people, (100.0, 50.0));
+        assert!(again.iter().all(Vec::is_empty));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
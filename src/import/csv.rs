@@ -0,0 +1,102 @@
+//! Imports the rows of a CSV file as entities, with the header row naming
+//! the predicates.
+//!
+//! Cells are read as numbers or booleans where they parse as such and as
+//! text otherwise; empty cells are skipped. Fields may be quoted, with `""`
+//! for a quote inside them, and span lines.
+
+use crate::import::{infer, Record};
+use anyhow::{bail, Result};
+
+pub fn records(text: &str) -> Result<Vec<Record>> {
+    let mut rows = rows(text)?.into_iter();
+    let Some(header) = rows.next() else {
+        return Ok(Vec::new());
+    };
+    let mut records = Vec::new();
+    for (n, row) in rows.enumerate() {
+        if row.len() > header.len() {
+            bail!(
+                "Row {} has {} cells but the header only {}",
+                n + 2,
+                row.len(),
+                header.len()
+            );
+        }
+        let mut record = Record::new();
+        for (predicate, cell) in header.iter().zip(&row) {
+            if !cell.is_empty() && !predicate.is_empty() {
+                record.insert(predicate.clone(), vec![infer(cell)]);
+            }
+        }
+        if !record.is_empty() {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+fn rows(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if cell.is_empty() => quoted = true,
+            ',' if !quoted => row.push(std::mem::take(&mut cell)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            c => cell.push(c),
+        }
+    }
+    if quoted {
+        bail!("Unclosed quote in CSV");
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|cell| !cell.is_empty()));
+    Ok(rows.into_iter().map(trim).collect())
+}
+
+fn trim(row: Vec<String>) -> Vec<String> {
+    row.into_iter()
+        .map(|cell| cell.trim().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Datum;
+
+    #[test]
+    fn reads_rows_as_records() {
+        let csv = "name,estimate,done,note\r\n\
+                   Roof,3,false,\"Tiles, \"\"red\"\"\nor brown\"\r\n\
+                   \r\n\
+                   Garden,,true,\n";
+        let records = records(csv).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["estimate"], vec![Datum::Integer(3)]);
+        assert_eq!(
+            records[0]["note"],
+            vec![Datum::String("Tiles, \"red\"\nor brown".into())]
+        );
+        assert_eq!(records[1]["done"], vec![Datum::Boolean(true)]);
+        assert!(!records[1].contains_key("estimate"));
+        assert!(super::records("a\n1,2\n").is_err());
+        assert!(super::records("a\n\"open\n").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+//! Imports JSON objects as entities: a single object or an array of them.
+//!
+//! Strings, numbers and booleans become facts, arrays several values of a
+//! predicate, and nested objects are kept as their JSON text. Nulls are
+//! skipped.
+
+use crate::import::Record;
+use crate::legacy::storage::Datum;
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+pub fn records(text: &str) -> Result<Vec<Record>> {
+    let value: Value = serde_json::from_str(text).context("Failed to parse JSON")?;
+    let objects = match value {
+        Value::Array(values) => values,
+        object @ Value::Object(_) => vec![object],
+        _ => bail!("Expected a JSON object or an array of objects"),
+    };
+    objects
+        .into_iter()
+        .enumerate()
+        .map(|(n, object)| {
+            let Value::Object(fields) = object else {
+                bail!("Item {} of the array is not an object", n + 1);
+            };
+            let mut record = Record::new();
+            for (predicate, value) in fields {
+                let values: Vec<Datum> = match value {
+                    Value::Array(values) => values.iter().filter_map(datum).collect(),
+                    value => datum(&value).into_iter().collect(),
+                };
+                if !values.is_empty() {
+                    record.insert(predicate, values);
+                }
+            }
+            Ok(record)
+        })
+        .collect()
+}
+
+fn datum(value: &Value) -> Option<Datum> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(b) => Datum::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(n) => Datum::Integer(n),
+            None => Datum::Float(n.as_f64()?),
+        },
+        Value::String(s) => Datum::String(s.clone()),
+        value => Datum::String(value.to_string()),
+    })
+}
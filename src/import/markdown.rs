@@ -0,0 +1,40 @@
+//! Imports a Markdown note as an entity, named after its first heading or
+//! the file, with the note as its `content`.
+//!
+//! A front matter block of `key: value` lines between `---` lines becomes
+//! facts of the entity.
+
+use crate::import::{infer, Record};
+use crate::legacy::storage::Datum;
+
+pub fn record(text: &str, file_name: &str) -> Record {
+    let mut record = Record::new();
+    let mut body = text;
+    if let Some(rest) = text.strip_prefix("---\n") {
+        if let Some((front, after)) = rest.split_once("\n---\n") {
+            for (key, value) in front.lines().filter_map(|line| line.split_once(':')) {
+                let value = value.trim().trim_matches('"');
+                if !value.is_empty() {
+                    record
+                        .entry(key.trim().to_string())
+                        .or_default()
+                        .push(infer(value));
+                }
+            }
+            body = after;
+        }
+    }
+    let body = body.trim();
+    if !record.contains_key("name") {
+        let heading = body
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(str::trim);
+        let name = heading.unwrap_or(file_name);
+        record.insert("name".to_string(), vec![Datum::String(name.to_string())]);
+    }
+    if !body.is_empty() {
+        record.insert("content".to_string(), vec![Datum::String(body.to_string())]);
+    }
+    record
+}
@@ -0,0 +1,348 @@
+//! Imports email from an mbox file.
+//!
+//! Every message becomes an `email` entity with its subject, date and body.
+//! Senders and recipients become `person` entities keyed on their address, so
+//! the same person is shared across messages and can be linked from notes.
+//! Replies point at the message they answer through `in_reply_to`.
+//!
+//! Bodies are imported as they appear in the file; MIME parts and transfer
+//! encodings are not decoded.
+
+use crate::import::{entity_id, upsert};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use std::collections::BTreeMap;
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Message {
+    pub message_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub from: Vec<Address>,
+    pub to: Vec<Address>,
+    pub cc: Vec<Address>,
+    pub subject: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub date: Option<i64>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+/// Splits an mbox file into messages.
+pub fn parse(mbox: &str) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+    let mut previous_blank = true;
+
+    for line in mbox.lines() {
+        if line.starts_with("From ") && previous_blank {
+            if let Some(lines) = current.take() {
+                messages.push(parse_message(&lines));
+            }
+            current = Some(Vec::new());
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+        previous_blank = line.is_empty();
+    }
+    if let Some(lines) = current {
+        messages.push(parse_message(&lines));
+    }
+    messages
+}
+
+fn parse_message(lines: &[&str]) -> Message {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut rest = lines.iter();
+    for line in rest.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            // A folded header continues the previous one.
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let addresses = |name: &str| header(name).map(parse_addresses).unwrap_or_default();
+
+    let mut body = rest
+        .map(|line| unescape_from(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    // The blank line separating messages belongs to the mbox, not the body.
+    if body.ends_with('\n') {
+        body.pop();
+    }
+
+    Message {
+        message_id: header("message-id").map(strip_angle_brackets),
+        in_reply_to: header("in-reply-to").map(strip_angle_brackets),
+        from: addresses("from"),
+        to: addresses("to"),
+        cc: addresses("cc"),
+        subject: header("subject").map(str::to_string),
+        date: header("date").and_then(parse_date),
+        body,
+    }
+}
+
+/// Undoes the `>From ` quoting mboxrd applies to body lines.
+fn unescape_from(line: &str) -> &str {
+    let quoted = line.trim_start_matches('>');
+    if line.starts_with('>') && quoted.starts_with("From ") {
+        &line[1..]
+    } else {
+        line
+    }
+}
+
+fn strip_angle_brackets(value: &str) -> String {
+    value
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+}
+
+fn parse_date(value: &str) -> Option<i64> {
+    // Drop trailing comments such as "(UTC)".
+    let value = value.split('(').next().unwrap_or_default().trim();
+    OffsetDateTime::parse(value, &Rfc2822)
+        .ok()
+        .map(|date| date.unix_timestamp())
+}
+
+/// Parses a comma separated address list such as
+/// `"Doe, Jane" <jane@example.com>, bob@example.com`.
+pub fn parse_addresses(value: &str) -> Vec<Address> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    let mut angled = false;
+    for c in value.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '<' if !quoted => angled = true,
+            '>' if !quoted => angled = false,
+            ',' if !quoted && !angled => {
+                parts.push(std::mem::take(&mut part));
+                continue;
+            }
+            _ => {}
+        }
+        part.push(c);
+    }
+    parts.push(part);
+
+    parts
+        .iter()
+        .filter_map(|part| {
+            let part = part.trim();
+            match (part.rfind('<'), part.rfind('>')) {
+                (Some(start), Some(end)) if start < end => {
+                    let name = part[..start].trim().trim_matches('"').trim();
+                    Some(Address {
+                        name: (!name.is_empty()).then(|| name.to_string()),
+                        email: part[start + 1..end].trim().to_string(),
+                    })
+                }
+                _ if part.contains('@') => Some(Address {
+                    name: None,
+                    email: part.to_string(),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// The id of the email with the given `Message-ID`.
+pub fn message_entity(message_id: &str) -> Uuid {
+    entity_id(&format!("mail:message:{}", message_id))
+}
+
+/// The id of the person with the given address.
+pub fn person_entity(email: &str) -> Uuid {
+    entity_id(&format!("mail:person:{}", email.to_lowercase()))
+}
+
+/// Returns the actions that import every message in `mbox`.
+///
+/// Messages and people that were imported before are updated in place.
+pub fn import(mbox: &str, projection: &Projection) -> Vec<Action> {
+    // Apply actions as they are produced so that a person appearing in many
+    // messages is only created once.
+    let mut projection = projection.clone();
+    let mut actions = Vec::new();
+    let mut emit = |projection: &mut Projection, new: Vec<Action>| {
+        new.iter().for_each(|a| projection.apply(a));
+        actions.extend(new);
+    };
+
+    for message in parse(mbox) {
+        let people = message.from.iter().chain(&message.to).chain(&message.cc);
+        for address in people {
+            let id = person_entity(&address.email);
+            let mut facts = BTreeMap::new();
+            facts.insert("type".to_string(), vec![text("person")]);
+            facts.insert(
+                "email".to_string(),
+                vec![text(&address.email.to_lowercase())],
+            );
+            // Keep the first name seen, display names vary between clients.
+            let named = projection
+                .entity(&id)
+                .is_some_and(|e| e.value("name").is_some());
+            if let (Some(name), false) = (&address.name, named) {
+                facts.insert("name".to_string(), vec![text(name)]);
+            }
+            let new = upsert(&projection, id, &facts);
+            emit(&mut projection, new);
+        }
+
+        let key = message.message_id.clone().unwrap_or_else(|| {
+            format!(
+                "{}/{}/{}",
+                message.from.first().map(|a| a.email.as_str()).unwrap_or(""),
+                message.date.unwrap_or_default(),
+                message.subject.as_deref().unwrap_or("")
+            )
+        });
+        let entity = |addresses: &[Address]| {
+            addresses
+                .iter()
+                .map(|a| Datum::Entity(person_entity(&a.email)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut facts = BTreeMap::new();
+        facts.insert("type".to_string(), vec![text("email")]);
+        facts.insert("message_id".to_string(), vec![text(&key)]);
+        facts.insert("sender".to_string(), entity(&message.from));
+        let mut recipients = entity(&message.to);
+        recipients.extend(entity(&message.cc));
+        facts.insert("recipient".to_string(), recipients);
+        facts.insert(
+            "subject".to_string(),
+            message.subject.iter().map(|s| text(s)).collect(),
+        );
+        facts.insert(
+            "date".to_string(),
+            message.date.into_iter().map(Datum::DateTime).collect(),
+        );
+        facts.insert("body".to_string(), vec![text(&message.body)]);
+        facts.insert(
+            "in_reply_to".to_string(),
+            message
+                .in_reply_to
+                .iter()
+                .map(|id| Datum::Entity(message_entity(id)))
+                .collect(),
+        );
+        let new = upsert(&projection, message_entity(&key), &facts);
+        emit(&mut projection, new);
+    }
+
+    actions
+}
+
+fn text(value: &str) -> Datum {
+    Datum::String(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "\
+From alice@example.com Mon Jan  1 10:00:00 2024
+Message-ID: <1@example.com>
+From: \"Liddell, Alice\" <Alice@example.com>
+To: bob@example.com, Carol <carol@example.com>
+Subject: Tea
+ party
+Date: Mon, 1 Jan 2024 10:00:00 +0000
+
+Shall we?
+>From the garden.
+
+From bob@example.com Mon Jan  1 11:00:00 2024
+Message-ID: <2@example.com>
+In-Reply-To: <1@example.com>
+From: Bob <bob@example.com>
+To: alice@example.com
+Subject: Re: Tea party
+Date: Mon, 1 Jan 2024 11:00:00 +0000 (UTC)
+
+Yes.
+";
+
+    #[test]
+    fn parses_messages() {
+        let messages = parse(MBOX);
+        assert_eq!(messages.len(), 2);
+
+        let first = &messages[0];
+        assert_eq!(first.message_id.as_deref(), Some("1@example.com"));
+        assert_eq!(first.subject.as_deref(), Some("Tea party"));
+        assert_eq!(first.date, Some(1704103200));
+        assert_eq!(first.body, "Shall we?\nFrom the garden.");
+        assert_eq!(
+            first.from,
+            vec![Address {
+                name: Some("Liddell, Alice".into()),
+                email: "Alice@example.com".into()
+            }]
+        );
+        assert_eq!(first.to.len(), 2);
+
+        assert_eq!(messages[1].in_reply_to.as_deref(), Some("1@example.com"));
+        assert_eq!(messages[1].date, Some(1704106800));
+    }
+
+    #[test]
+    fn imports_messages_and_people() {
+        let mut projection = Projection::new();
+        import(MBOX, &projection)
+            .iter()
+            .for_each(|a| projection.apply(a));
+
+        // Three people and two messages.
+        assert_eq!(projection.len(), 5);
+        let alice = person_entity("alice@example.com");
+        assert_eq!(
+            projection.entity(&alice).unwrap().value("name"),
+            Some(&text("Liddell, Alice"))
+        );
+
+        let reply = projection.entity(&message_entity("2@example.com")).unwrap();
+        assert_eq!(reply.values("recipient"), &[Datum::Entity(alice)]);
+        assert_eq!(
+            reply.value("in_reply_to"),
+            Some(&Datum::Entity(message_entity("1@example.com")))
+        );
+
+        assert!(import(MBOX, &projection).is_empty());
+    }
+}
@@ -0,0 +1,506 @@
+//! Reads small RDF files, in Turtle or N-Triples, into the graph.
+//!
+//! Subjects become entities, and each triple a fact of its subject. IRIs
+//! under the `entities` of the [`Mapping`] are the ids of the entities
+//! [`crate::export::rdf`] wrote, and keep them. Other IRIs become entities
+//! with an `iri` fact, named after their last segment unless the file names
+//! them, and blank nodes entities of their own. Predicates map back through
+//! the `predicates` of the mapping or its `vocabulary`, and otherwise to the
+//! last segment of their IRI, e.g. `name` for `https://schema.org/name`.
+//! Literals typed with XML Schema datatypes keep their type, and classes,
+//! the objects of `rdf:type` or `a`, become text.
+//!
+//! Turtle is read without collections and nested blank nodes.
+
+use super::{entity_id, upsert, Record};
+use crate::export::rdf::{Mapping, RDF, XSD};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::{Date, OffsetDateTime};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    Iri(String),
+    Blank(String),
+    Literal {
+        value: String,
+        /// The expanded IRI of the datatype, none for plain strings.
+        datatype: Option<String>,
+    },
+}
+
+/// The triples in `text`, with IRIs expanded.
+pub fn parse(text: &str) -> Result<Vec<(Term, String, Term)>> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        at: 0,
+        prefixes: HashMap::new(),
+        base: String::new(),
+        triples: Vec::new(),
+    };
+    parser.document().map_err(|e| {
+        let line = parser.chars[..parser.at.min(parser.chars.len())]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count();
+        e.context(format!("On line {}", line + 1))
+    })?;
+    Ok(parser.triples)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+    prefixes: HashMap<String, String>,
+    base: String,
+    triples: Vec<(Term, String, Term)>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn looking_at(&self, word: &str) -> bool {
+        let end = self.at + word.chars().count();
+        end <= self.chars.len()
+            && self.chars[self.at..end]
+                .iter()
+                .zip(word.chars())
+                .all(|(a, b)| a.eq_ignore_ascii_case(&b))
+    }
+
+    fn skip(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.at += 1;
+                }
+            } else if c.is_whitespace() {
+                self.at += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.at += 1;
+                Ok(())
+            }
+            found => bail!("Expected {:?}, found {:?}", expected, found),
+        }
+    }
+
+    fn document(&mut self) -> Result<()> {
+        loop {
+            self.skip();
+            if self.peek().is_none() {
+                return Ok(());
+            }
+            // SPARQL style directives don't end with a dot.
+            let sparql = self.looking_at("prefix ") || self.looking_at("base ");
+            if self.looking_at("@prefix") || self.looking_at("prefix ") {
+                self.at += if sparql { 6 } else { 7 };
+                self.skip();
+                let prefix = self.word();
+                let prefix = prefix
+                    .strip_suffix(':')
+                    .ok_or_else(|| anyhow!("Expected a prefix, found {}", prefix))?;
+                let Term::Iri(iri) = self.term()? else {
+                    bail!("Expected the IRI of the prefix {}", prefix);
+                };
+                self.prefixes.insert(prefix.to_string(), iri);
+            } else if self.looking_at("@base") || self.looking_at("base ") {
+                self.at += if sparql { 4 } else { 5 };
+                let Term::Iri(iri) = self.term()? else {
+                    bail!("Expected the base IRI");
+                };
+                self.base = iri;
+            } else {
+                let subject = self.term()?;
+                if matches!(subject, Term::Literal { .. }) {
+                    bail!("A literal can't be a subject");
+                }
+                self.predicates(&subject)?;
+            }
+            if !sparql {
+                self.expect('.')?;
+            }
+        }
+    }
+
+    fn predicates(&mut self, subject: &Term) -> Result<()> {
+        loop {
+            self.skip();
+            let predicate = if self.peek() == Some('a')
+                && self
+                    .chars
+                    .get(self.at + 1)
+                    .is_some_and(|c| c.is_whitespace())
+            {
+                self.at += 1;
+                format!("{}type", RDF)
+            } else {
+                match self.term()? {
+                    Term::Iri(iri) => iri,
+                    _ => bail!("A predicate must be an IRI"),
+                }
+            };
+            loop {
+                let object = self.term()?;
+                self.triples
+                    .push((subject.clone(), predicate.clone(), object));
+                self.skip();
+                if self.peek() != Some(',') {
+                    break;
+                }
+                self.at += 1;
+            }
+            if self.peek() != Some(';') {
+                return Ok(());
+            }
+            while self.peek() == Some(';') {
+                self.at += 1;
+                self.skip();
+            }
+            if self.peek() == Some('.') {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads up to whitespace or punctuation ending a statement.
+    fn word(&mut self) -> String {
+        let start = self.at;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !matches!(c, ',' | ';' | '<' | '"'))
+        {
+            self.at += 1;
+        }
+        // A dot at the end ends the statement.
+        while self.at > start && self.chars[self.at - 1] == '.' {
+            self.at -= 1;
+        }
+        self.chars[start..self.at].iter().collect()
+    }
+
+    fn term(&mut self) -> Result<Term> {
+        self.skip();
+        match self.peek() {
+            None => bail!("Unexpected end of file"),
+            Some('<') => {
+                self.at += 1;
+                let start = self.at;
+                while self.peek().is_some_and(|c| c != '>') {
+                    self.at += 1;
+                }
+                let iri: String = self.chars[start..self.at].iter().collect();
+                self.expect('>')?;
+                Ok(Term::Iri(if iri.contains(':') {
+                    iri
+                } else {
+                    format!("{}{}", self.base, iri)
+                }))
+            }
+            Some('"' | '\'') => self.literal(),
+            Some('[' | '(') => bail!("Collections and nested blank nodes aren't supported"),
+            Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-' | '.') => {
+                let number = self.word();
+                let datatype = if number.contains(['e', 'E']) {
+                    "double"
+                } else if number.contains('.') {
+                    "decimal"
+                } else {
+                    "integer"
+                };
+                Ok(Term::Literal {
+                    value: number,
+                    datatype: Some(format!("{}{}", XSD, datatype)),
+                })
+            }
+            Some(_) => {
+                let word = self.word();
+                if let Some(label) = word.strip_prefix("_:") {
+                    return Ok(Term::Blank(label.to_string()));
+                }
+                if word == "true" || word == "false" {
+                    return Ok(Term::Literal {
+                        value: word,
+                        datatype: Some(format!("{}boolean", XSD)),
+                    });
+                }
+                let (prefix, local) = word
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("Unexpected {}", word))?;
+                let expansion = self
+                    .prefixes
+                    .get(prefix)
+                    .ok_or_else(|| anyhow!("Unknown prefix {}", prefix))?;
+                Ok(Term::Iri(format!(
+                    "{}{}",
+                    expansion,
+                    local.replace('\\', "")
+                )))
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Term> {
+        let quote = self.peek().expect("a quote");
+        let long = self.looking_at(&quote.to_string().repeat(3));
+        self.at += if long { 3 } else { 1 };
+        let mut value = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                bail!("Unterminated string");
+            };
+            if c == quote && (!long || self.looking_at(&quote.to_string().repeat(3))) {
+                self.at += if long { 3 } else { 1 };
+                break;
+            }
+            self.at += 1;
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            let escaped = self.peek().ok_or_else(|| anyhow!("Unterminated string"))?;
+            self.at += 1;
+            match escaped {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'u' | 'U' => {
+                    let length = if escaped == 'u' { 4 } else { 8 };
+                    let digits: String = self.chars.iter().skip(self.at).take(length).collect();
+                    self.at += length;
+                    let code = u32::from_str_radix(&digits, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| anyhow!("Invalid escape \\{}{}", escaped, digits))?;
+                    value.push(code);
+                }
+                c => value.push(c),
+            }
+        }
+        let mut datatype = None;
+        if self.peek() == Some('@') {
+            // Language tags are dropped.
+            self.word();
+        } else if self.looking_at("^^") {
+            self.at += 2;
+            match self.term()? {
+                Term::Iri(iri) => datatype = Some(iri),
+                _ => bail!("A datatype must be an IRI"),
+            }
+        }
+        Ok(Term::Literal { value, datatype })
+    }
+}
+
+/// The last segment of `iri`, e.g. `name` for `https://schema.org/name`.
+fn local_name(iri: &str) -> &str {
+    iri.rsplit(['#', '/', ':'])
+        .find(|s| !s.is_empty())
+        .unwrap_or(iri)
+}
+
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut n = 0;
+    while n < bytes.len() {
+        let hex = bytes
+            .get(n + 1..n + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[n] == b'%' => {
+                decoded.push(byte);
+                n += 3;
+            }
+            _ => {
+                decoded.push(bytes[n]);
+                n += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The entities the `triples` of the file `source` describe.
+pub fn records(
+    triples: &[(Term, String, Term)],
+    mapping: &Mapping,
+    source: &str,
+) -> BTreeMap<Uuid, Record> {
+    let predicates: HashMap<String, &str> = mapping
+        .predicates
+        .iter()
+        .map(|(predicate, iri)| (mapping.expand(iri), predicate.as_str()))
+        .collect();
+    let mut records: BTreeMap<Uuid, Record> = BTreeMap::new();
+    let entity = |records: &mut BTreeMap<Uuid, Record>, term: &Term| match term {
+        Term::Iri(iri) => {
+            let id = iri
+                .strip_prefix(&mapping.entities)
+                .and_then(|id| id.parse().ok());
+            id.unwrap_or_else(|| {
+                let id = entity_id(&format!("rdf:{}", iri));
+                let record = records.entry(id).or_default();
+                record.insert("iri".to_string(), vec![Datum::String(iri.clone())]);
+                id
+            })
+        }
+        Term::Blank(label) => {
+            let id = entity_id(&format!("rdf:{}#{}", source, label));
+            records.entry(id).or_default();
+            id
+        }
+        Term::Literal { .. } => unreachable!("literals are values"),
+    };
+
+    for (subject, predicate, object) in triples {
+        let subject = entity(&mut records, subject);
+        let name = match predicates.get(predicate) {
+            Some(name) => name.to_string(),
+            None => match predicate.strip_prefix(&mapping.vocabulary) {
+                Some(name) => percent_decode(name),
+                None => local_name(predicate).to_string(),
+            },
+        };
+        let datum = match object {
+            Term::Iri(class) if *predicate == format!("{}type", RDF) => {
+                Datum::String(local_name(class).to_string())
+            }
+            Term::Literal { value, datatype } => literal(value, datatype.as_deref()),
+            object => Datum::Entity(entity(&mut records, object)),
+        };
+        let record = records.entry(subject).or_default();
+        record.entry(name).or_default().push(datum);
+    }
+    for record in records.values_mut() {
+        if record.contains_key("name") {
+            continue;
+        }
+        if let Some(Datum::String(iri)) = record.get("iri").and_then(|v| v.first()) {
+            let name = local_name(iri).to_string();
+            record.insert("name".to_string(), vec![Datum::String(name)]);
+        }
+    }
+    records
+}
+
+/// The value of a literal, by its datatype.
+fn literal(value: &str, datatype: Option<&str>) -> Datum {
+    let text = || Datum::String(value.to_string());
+    let Some(datatype) = datatype.and_then(|d| d.strip_prefix(XSD)) else {
+        return text();
+    };
+    match datatype {
+        "integer" | "int" | "long" | "short" | "byte" | "nonNegativeInteger"
+        | "positiveInteger" | "negativeInteger" | "nonPositiveInteger" | "unsignedInt"
+        | "unsignedLong" => value.parse().map(Datum::Integer).unwrap_or_else(|_| text()),
+        "decimal" | "double" | "float" => {
+            value.parse().map(Datum::Float).unwrap_or_else(|_| text())
+        }
+        "boolean" => match value {
+            "true" | "1" => Datum::Boolean(true),
+            "false" | "0" => Datum::Boolean(false),
+            _ => text(),
+        },
+        "dateTime" => OffsetDateTime::parse(value, &Rfc3339)
+            .map(|t| Datum::DateTime(t.unix_timestamp()))
+            .unwrap_or_else(|_| text()),
+        "date" => Date::parse(value, &Iso8601::DEFAULT)
+            .map(|d| Datum::DateTime(d.midnight().assume_utc().unix_timestamp()))
+            .unwrap_or_else(|_| text()),
+        _ => text(),
+    }
+}
+
+/// The actions that import the RDF file at `path`.
+pub fn import(projection: &Projection, path: &Path, mapping: &Mapping) -> Result<Vec<Action>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let triples = parse(&text).with_context(|| format!("Failed to import {}", path.display()))?;
+    let records = records(&triples, mapping, &path.display().to_string());
+    Ok(records
+        .iter()
+        .flat_map(|(id, record)| upsert(projection, *id, record))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::rdf::{triples, Format};
+
+    #[test]
+    fn reads_turtle_and_exported_triples() {
+        let turtle = r#"
+            @prefix schema: <https://schema.org/> .
+            PREFIX ex: <https://example.com/>
+            # People and where they work.
+            ex:ada a schema:Person ;
+                schema:name "Ada"@en, """Countess of
+Lovelace""" ;
+                schema:birthDate "1815-12-10"^^<http://www.w3.org/2001/XMLSchema#date> ;
+                ex:age 36 ; ex:ratio 0.5 ; ex:active true ;
+                schema:worksFor _:team .
+            _:team schema:name "Analytical \"Engine\" é" .
+        "#;
+        let parsed = parse(turtle).unwrap();
+        assert_eq!(parsed.len(), 9);
+        let records = records(&parsed, &Mapping::default(), "people.ttl");
+        let ada = &records[&entity_id("rdf:https://example.com/ada")];
+        assert_eq!(ada["type"], vec![Datum::String("Person".into())]);
+        assert_eq!(ada["name"].len(), 2);
+        assert_eq!(ada["birthDate"], vec![Datum::DateTime(-4861728000)]);
+        assert_eq!(ada["age"], vec![Datum::Integer(36)]);
+        assert_eq!(ada["ratio"], vec![Datum::Float(0.5)]);
+        assert_eq!(ada["active"], vec![Datum::Boolean(true)]);
+        let team = entity_id("rdf:people.ttl#team");
+        assert_eq!(ada["worksFor"], vec![Datum::Entity(team)]);
+        assert_eq!(
+            records[&team]["name"],
+            vec![Datum::String("Analytical \"Engine\" é".into())]
+        );
+        assert!(parse("ex:a ex:b ex:c .").is_err());
+
+        // What was exported comes back with the same ids and facts.
+        let mut projection = Projection::new();
+        for (id, record) in &records {
+            upsert(&projection, *id, record)
+                .iter()
+                .for_each(|a| projection.apply(a));
+        }
+        let mut mapping = Mapping::default();
+        mapping
+            .predicates
+            .insert("name".into(), "https://schema.org/name".into());
+        for format in [Format::Turtle, Format::NTriples] {
+            let exported = triples(&projection, &mapping, format);
+            let back = super::records(&parse(&exported).unwrap(), &mapping, "back");
+            for (id, record) in &back {
+                let entity = projection.entity(id).unwrap();
+                let facts: Record = entity
+                    .facts()
+                    .map(|(p, v)| (p.to_string(), v.to_vec()))
+                    .collect();
+                assert_eq!(record, &facts);
+            }
+            assert_eq!(back.len(), projection.entities().count());
+        }
+    }
+}
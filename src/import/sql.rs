@@ -0,0 +1,245 @@
+//! Imports rows from a relational database as entities.
+//!
+//! A [`Mapping`] says which tables to read, which columns become which
+//! predicates, and which foreign keys point at rows of other tables. Every row
+//! becomes one entity whose id is derived from the source name, the table and
+//! the primary key, so re-importing updates the same entities.
+//!
+//! Rows are read through a [`RowSource`]: a rusqlite [`Connection`] for
+//! SQLite files, or [`postgres::Postgres`] for PostgreSQL servers.
+
+use crate::import::{entity_id, upsert};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use anyhow::{Context, Result};
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub mod postgres;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Mapping {
+    /// A name for the database, recorded on every imported entity.
+    pub source: String,
+    pub tables: Vec<TableMapping>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TableMapping {
+    pub table: String,
+    /// Recorded as the `type` fact of every entity imported from the table.
+    pub entity_type: String,
+    pub primary_key: String,
+    /// Column name to predicate.
+    #[serde(default)]
+    pub columns: BTreeMap<String, String>,
+    /// Foreign key column to the table it references.
+    #[serde(default)]
+    pub references: BTreeMap<String, Reference>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Reference {
+    pub table: String,
+    pub predicate: String,
+}
+
+impl Mapping {
+    pub fn from_json(json: &str) -> Result<Mapping> {
+        serde_json::from_str(json).context("Failed to parse the import mapping")
+    }
+}
+
+/// A database the importer can read rows from.
+pub trait RowSource {
+    /// Returns every row of `table`, restricted to the given columns.
+    fn rows(&self, table: &str, columns: &[&str]) -> Result<Vec<BTreeMap<String, Value>>>;
+}
+
+impl RowSource for Connection {
+    fn rows(&self, table: &str, columns: &[&str]) -> Result<Vec<BTreeMap<String, Value>>> {
+        let query = format!(
+            "SELECT {} FROM {}",
+            columns
+                .iter()
+                .map(|c| quote(c))
+                .collect::<Vec<_>>()
+                .join(", "),
+            quote(table)
+        );
+        let mut stmt = self
+            .prepare(&query)
+            .with_context(|| format!("Failed to prepare SQL statement to read {}", table))?;
+        let rows = stmt
+            .query_map([], |row| {
+                let mut values = BTreeMap::new();
+                for (i, column) in columns.iter().enumerate() {
+                    values.insert(column.to_string(), row.get::<_, Value>(i)?);
+                }
+                Ok(values)
+            })
+            .with_context(|| format!("Failed to read rows of {}", table))?;
+        rows.collect::<rusqlite::Result<_>>()
+            .with_context(|| format!("Failed to read a row of {}", table))
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Returns the actions that bring the projection up to date with the source.
+///
+/// Only values that changed since the last import produce actions.
+pub fn import(
+    source: &impl RowSource,
+    mapping: &Mapping,
+    projection: &Projection,
+) -> Result<Vec<Action>> {
+    let mut actions = Vec::new();
+    for table in &mapping.tables {
+        let mut columns = vec![table.primary_key.as_str()];
+        for column in table.columns.keys().chain(table.references.keys()) {
+            if !columns.contains(&column.as_str()) {
+                columns.push(column);
+            }
+        }
+
+        for row in source.rows(&table.table, &columns)? {
+            let key = row
+                .get(&table.primary_key)
+                .and_then(key_string)
+                .with_context(|| format!("Row of {} has no usable primary key", table.table))?;
+
+            let mut facts = BTreeMap::new();
+            facts.insert(
+                "type".to_string(),
+                vec![Datum::String(table.entity_type.clone())],
+            );
+            facts.insert(
+                "source".to_string(),
+                vec![Datum::String(mapping.source.clone())],
+            );
+            facts.insert(
+                "source_key".to_string(),
+                vec![Datum::String(format!("{}/{}", table.table, key))],
+            );
+            for (column, predicate) in &table.columns {
+                let datum = row.get(column).and_then(datum);
+                facts.insert(predicate.clone(), datum.into_iter().collect());
+            }
+            for (column, reference) in &table.references {
+                let target = row.get(column).and_then(key_string).map(|target| {
+                    Datum::Entity(row_id(&mapping.source, &reference.table, &target))
+                });
+                facts.insert(reference.predicate.clone(), target.into_iter().collect());
+            }
+
+            let id = row_id(&mapping.source, &table.table, &key);
+            actions.extend(upsert(projection, id, &facts));
+        }
+    }
+    Ok(actions)
+}
+
+/// The id of the entity imported from the row of `table` with primary key `key`.
+pub fn row_id(source: &str, table: &str, key: &str) -> uuid::Uuid {
+    entity_id(&format!("sql:{}:{}:{}", source, table, key))
+}
+
+fn key_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Text(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn datum(value: &Value) -> Option<Datum> {
+    match value {
+        Value::Null | Value::Blob(_) => None,
+        Value::Integer(i) => Some(Datum::Integer(*i)),
+        Value::Real(f) => Some(Datum::Float(*f)),
+        Value::Text(s) => Some(Datum::String(s.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, author_id INTEGER);
+             INSERT INTO authors VALUES (1, 'Ursula');
+             INSERT INTO books VALUES (10, 'The Dispossessed', 1);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn mapping() -> Mapping {
+        Mapping::from_json(
+            r#"{
+                "source": "library",
+                "tables": [
+                    {
+                        "table": "authors",
+                        "entity_type": "person",
+                        "primary_key": "id",
+                        "columns": { "name": "name" }
+                    },
+                    {
+                        "table": "books",
+                        "entity_type": "book",
+                        "primary_key": "id",
+                        "columns": { "title": "title" },
+                        "references": {
+                            "author_id": { "table": "authors", "predicate": "author" }
+                        }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn imports_rows_as_entities() {
+        let mut projection = Projection::new();
+        for action in import(&database(), &mapping(), &projection).unwrap() {
+            projection.apply(&action);
+        }
+
+        let author = row_id("library", "authors", "1");
+        let book = projection
+            .entity(&row_id("library", "books", "10"))
+            .unwrap();
+        assert_eq!(
+            book.value("title"),
+            Some(&Datum::String("The Dispossessed".into()))
+        );
+        assert_eq!(book.value("author"), Some(&Datum::Entity(author)));
+        assert_eq!(book.value("type"), Some(&Datum::String("book".into())));
+        assert!(projection.contains(&author));
+    }
+
+    #[test]
+    fn reimport_only_applies_changes() {
+        let db = database();
+        let mut projection = Projection::new();
+        for action in import(&db, &mapping(), &projection).unwrap() {
+            projection.apply(&action);
+        }
+        assert!(import(&db, &mapping(), &projection).unwrap().is_empty());
+
+        db.execute("UPDATE authors SET name = 'Ursula K. Le Guin'", [])
+            .unwrap();
+        let actions = import(&db, &mapping(), &projection).unwrap();
+        assert_eq!(actions.len(), 2);
+    }
+}
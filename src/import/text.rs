@@ -0,0 +1,210 @@
+//! Reads the plain text written by [`crate::export::text`] back into the
+//! graph, e.g. after pulling changes to an exported directory from git.
+//!
+//! The entities keep the ids they were exported with, and each one ends up
+//! with exactly the facts in its block. Entities that are in the graph but in
+//! none of the files are only deleted when pruning.
+
+use super::Record;
+use crate::export::text::EXTENSION;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// The entities in every file of `dir` with the export extension.
+pub fn read(dir: &Path) -> Result<BTreeMap<Uuid, Record>> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == EXTENSION) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut entities = BTreeMap::new();
+    for path in paths {
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let blocks =
+            parse(&text).with_context(|| format!("Failed to import {}", path.display()))?;
+        for (id, record) in blocks {
+            if entities.insert(id, record).is_some() {
+                bail!(
+                    "{} is in more than one file, again in {}",
+                    id,
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(entities)
+}
+
+/// The entities in the text of a file.
+pub fn parse(text: &str) -> Result<Vec<(Uuid, Record)>> {
+    let mut entities: Vec<(Uuid, Record)> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = if let Some(id) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            id.parse()
+                .map(|id| entities.push((id, Record::new())))
+                .map_err(|_| anyhow!("Invalid id {}", id))
+        } else {
+            match entities.last_mut() {
+                Some((_, record)) => fact(line).map(|(predicate, datum)| {
+                    record.entry(predicate).or_default().push(datum);
+                }),
+                None => Err(anyhow!("Expected the id of an entity first")),
+            }
+        };
+        parsed.with_context(|| format!("On line {}", n + 1))?;
+    }
+    Ok(entities)
+}
+
+/// The actions that give the entities in the graph the facts in `entities`,
+/// and delete the others if `prune`.
+pub fn actions(
+    projection: &Projection,
+    entities: &BTreeMap<Uuid, Record>,
+    prune: bool,
+) -> Vec<Action> {
+    let mut actions = Vec::new();
+    for (id, record) in entities {
+        let mut facts = record.clone();
+        // Predicates missing from the file are removed.
+        if let Some(entity) = projection.entity(id) {
+            for (predicate, _) in entity.facts() {
+                facts.entry(predicate.to_string()).or_default();
+            }
+        }
+        actions.extend(super::upsert(projection, *id, &facts));
+    }
+    if prune {
+        let mut gone: Vec<Uuid> = projection
+            .entities()
+            .map(|(id, _)| *id)
+            .filter(|id| !entities.contains_key(id))
+            .collect();
+        gone.sort();
+        actions.extend(gone.into_iter().map(|id| Action::DeleteEntity { id }));
+    }
+    actions
+}
+
+/// A `predicate: value` line.
+fn fact(line: &str) -> Result<(String, Datum)> {
+    let (predicate, rest) = if line.starts_with('"') {
+        let mut strings = serde_json::Deserializer::from_str(line).into_iter::<String>();
+        let predicate = strings
+            .next()
+            .context("Expected a predicate")?
+            .context("Invalid predicate")?;
+        (predicate, &line[strings.byte_offset()..])
+    } else {
+        let end = line.find(':').context("Expected a predicate and a value")?;
+        (line[..end].to_string(), &line[end..])
+    };
+    let value = rest
+        .strip_prefix(':')
+        .context("Expected a colon after the predicate")?
+        .trim();
+    let datum = datum(value).with_context(|| format!("Invalid value {}", value))?;
+    Ok((predicate, datum))
+}
+
+/// A value, typed by its syntax.
+fn datum(value: &str) -> Result<Datum> {
+    if value.starts_with('"') {
+        return Ok(Datum::String(serde_json::from_str(value)?));
+    }
+    if let Some(id) = value.strip_prefix('<').and_then(|v| v.strip_suffix('>')) {
+        return Ok(Datum::Entity(id.parse()?));
+    }
+    if let Some(t) = value
+        .strip_prefix("datetime(")
+        .and_then(|v| v.strip_suffix(')'))
+    {
+        return Ok(Datum::DateTime(t.parse()?));
+    }
+    if let Ok(b) = value.parse() {
+        return Ok(Datum::Boolean(b));
+    }
+    if let Ok(n) = value.parse() {
+        return Ok(Datum::Integer(n));
+    }
+    if let Ok(t) = OffsetDateTime::parse(value, &Rfc3339) {
+        return Ok(Datum::DateTime(t.unix_timestamp()));
+    }
+    Ok(Datum::Float(value.parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::text::{export, files, Split};
+
+    #[test]
+    fn round_trips_through_files() {
+        let mut projection = Projection::new();
+        let (task, project) = (Uuid::new_v4(), Uuid::new_v4());
+        let facts = [
+            (
+                "name",
+                Datum::String("Fix the \"roof\"\nbefore winter".into()),
+            ),
+            ("type", Datum::String("task".into())),
+            ("priority", Datum::Integer(-2)),
+            ("estimate", Datum::Float(3.0)),
+            ("ratio", Datum::Float(1e-9)),
+            ("done", Datum::Boolean(false)),
+            ("due", Datum::DateTime(1_719_826_200)),
+            ("parent", Datum::Entity(project)),
+            ("odd: predicate", Datum::String("x".into())),
+            ("tag", Datum::String("b".into())),
+            ("tag", Datum::String("a".into())),
+        ];
+        projection.apply(&Action::CreateEntity { id: task });
+        projection.apply(&Action::CreateEntity { id: project });
+        for (predicate, datum) in facts {
+            projection.apply(&Action::AddFact {
+                subject: task,
+                predicate: predicate.into(),
+                datum,
+            });
+        }
+
+        for split in [Split::Entity, Split::Type] {
+            let dir = std::env::temp_dir().join(format!("graphite-text-{}", Uuid::new_v4()));
+            export(&projection, split, &dir).unwrap();
+            let entities = read(&dir).unwrap();
+            let mut imported = Projection::new();
+            for action in actions(&imported, &entities, false) {
+                imported.apply(&action);
+            }
+            assert_eq!(imported, projection);
+            assert_eq!(files(&imported, split), files(&projection, split));
+            assert!(actions(&imported, &entities, true).is_empty());
+
+            // Exporting again changes nothing; removing an entity removes
+            // its file.
+            let report = export(&projection, split, &dir).unwrap();
+            assert_eq!((report.written, report.removed), (0, 0));
+            imported.apply(&Action::DeleteEntity { id: project });
+            let report = export(&imported, Split::Entity, &dir).unwrap();
+            assert_eq!(report.entities, 1);
+            assert_eq!(read(&dir).unwrap().len(), 1);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
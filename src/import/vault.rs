@@ -0,0 +1,327 @@
+//! Imports a vault of Markdown notes, as kept by Obsidian or Logseq.
+//!
+//! Every note becomes an entity named after its file, with the note as its
+//! `content`. Front matter becomes typed facts: numbers, booleans, dates,
+//! lists as several values, and `[[wikilinks]]` as links. So do Logseq's
+//! `key:: value` properties. The `[[wikilinks]]` and `![[embeds]]` in the
+//! body become `links` to the notes they name, by file name or by path
+//! within the vault, and inline `#tags` become `tags`. Links to notes that
+//! don't exist yet get an entity with just a name, as Obsidian shows them.
+//!
+//! Ids derive from the path of the note, so importing the vault again
+//! updates the entities instead of adding new ones. Hidden directories,
+//! such as `.obsidian`, are skipped.
+
+use super::{entity_id, infer, upsert, Record};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use uuid::Uuid;
+
+/// A note read from a vault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    /// The path within the vault, without the extension, e.g. `Projects/Graphite`.
+    pub path: String,
+    pub record: Record,
+    /// The notes the front matter and properties link to, by predicate.
+    pub properties: BTreeMap<String, Vec<String>>,
+    /// The notes linked to from the body.
+    pub links: Vec<String>,
+}
+
+/// Every note in the vault at `dir`.
+pub fn read(dir: &Path) -> Result<Vec<Note>> {
+    let mut notes = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        let entries = std::fs::read_dir(&current)
+            .with_context(|| format!("Failed to read {}", current.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "md") {
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let relative = path.strip_prefix(dir).unwrap_or(&path).with_extension("");
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                notes.push(parse(&text, &relative));
+            }
+        }
+    }
+    notes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(notes)
+}
+
+/// The note at `path` within the vault, with `text`.
+pub fn parse(text: &str, path: &str) -> Note {
+    let mut note = Note {
+        path: path.to_string(),
+        record: Record::new(),
+        properties: BTreeMap::new(),
+        links: Vec::new(),
+    };
+    let mut body = text;
+    if let Some(rest) = text.strip_prefix("---\n") {
+        if let Some((front, after)) = rest.split_once("\n---\n") {
+            front_matter(&mut note, front);
+            body = after;
+        }
+    }
+    let mut content = Vec::new();
+    for line in body.lines() {
+        match line.split_once(":: ") {
+            Some((key, value)) if is_key(key) => property(&mut note, key, value.trim()),
+            _ => content.push(line),
+        }
+    }
+    let body = content.join("\n");
+    let body = body.trim();
+
+    note.links = wikilinks(body);
+    let tags: Vec<Datum> = inline_tags(body)
+        .into_iter()
+        .map(|tag| Datum::String(tag.to_string()))
+        .collect();
+    if !tags.is_empty() {
+        note.record
+            .entry("tags".to_string())
+            .or_default()
+            .extend(tags);
+    }
+    note.record.entry("name".to_string()).or_insert_with(|| {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        vec![Datum::String(name.to_string())]
+    });
+    if !body.is_empty() {
+        note.record
+            .insert("content".to_string(), vec![Datum::String(body.to_string())]);
+    }
+    note
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Reads `key: value` lines, with lists inline as `[a, b]` or as `- item`
+/// lines below the key.
+fn front_matter(note: &mut Note, front: &str) {
+    let mut key: Option<&str> = None;
+    for line in front.lines() {
+        if let (Some(item), Some(key)) = (line.trim_start().strip_prefix("- "), key) {
+            property(note, key, item.trim());
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        key = Some(name).filter(|name| is_key(name));
+        let Some(name) = key else {
+            continue;
+        };
+        match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            // Not a list but a link on its own.
+            Some(inner) if inner.starts_with('[') => property(note, name, value),
+            Some(items) => {
+                for item in items.split(',') {
+                    property(note, name, item.trim());
+                }
+            }
+            None => property(note, name, value),
+        }
+    }
+}
+
+/// Adds the value of a property, a link to a note or a typed fact.
+fn property(note: &mut Note, key: &str, value: &str) {
+    let value = value.trim_matches(|c| c == '"' || c == '\'');
+    if value.is_empty() {
+        return;
+    }
+    let key = match key {
+        "title" => "name",
+        "tags" | "tag" => "tags",
+        key => key,
+    };
+    let links = wikilinks(value);
+    if !links.is_empty() && value.starts_with("[[") {
+        note.properties
+            .entry(key.to_string())
+            .or_default()
+            .extend(links);
+        return;
+    }
+    let datum = if key == "tags" {
+        Datum::String(value.trim_start_matches('#').to_string())
+    } else if value.starts_with(|c: char| c.is_ascii_digit()) && value.contains('-') {
+        crate::shell::date_time(value)
+            .map(Datum::DateTime)
+            .unwrap_or_else(|| infer(value))
+    } else {
+        infer(value)
+    };
+    note.record.entry(key.to_string()).or_default().push(datum);
+}
+
+/// The notes named by `[[wikilinks]]` and `![[embeds]]` in `text`, without
+/// their headings, blocks or aliases.
+fn wikilinks(text: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let target = rest[..end]
+            .split(['|', '#', '^'])
+            .next()
+            .unwrap_or("")
+            .trim();
+        let embedded = text[..text.len() - rest.len() - 2].ends_with('!');
+        // Embedded images and other attachments aren't notes.
+        let attachment = embedded && target.contains('.') && !target.ends_with(".md");
+        if !target.is_empty() && !attachment && !links.iter().any(|l| l == target) {
+            links.push(target.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    links
+}
+
+/// The `#tags` in `text`, outside of headings.
+fn inline_tags(text: &str) -> Vec<&str> {
+    let mut tags = Vec::new();
+    for word in text.split_whitespace() {
+        let Some(tag) = word.strip_prefix('#') else {
+            continue;
+        };
+        let tag = tag.trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'));
+        let valid = tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'));
+        // Digits alone are issue numbers, not tags.
+        if valid && tag.chars().any(|c| !c.is_ascii_digit()) && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// The id of the note at `path` within a vault.
+pub fn note_entity(path: &str) -> Uuid {
+    entity_id(&format!("vault:{}", path.to_lowercase()))
+}
+
+/// The actions that import `notes`, and name the notes they link to that
+/// aren't among them.
+pub fn actions(projection: &Projection, notes: &[Note]) -> Vec<Action> {
+    // Links name notes by file name, or by path when names repeat; the note
+    // nearest the root wins, as in Obsidian.
+    let mut targets: HashMap<String, Uuid> = HashMap::new();
+    let mut by_depth: Vec<&Note> = notes.iter().collect();
+    by_depth.sort_by_key(|n| (n.path.matches('/').count(), n.path.clone()));
+    for note in by_depth {
+        let id = note_entity(&note.path);
+        let name = note.path.rsplit('/').next().unwrap_or(&note.path);
+        targets.entry(name.to_lowercase()).or_insert(id);
+        targets.insert(note.path.to_lowercase(), id);
+    }
+    let mut missing: BTreeMap<Uuid, String> = BTreeMap::new();
+    let mut resolve = |target: &str| {
+        let target = target.trim_end_matches(".md");
+        let key = target.to_lowercase();
+        *targets.entry(key.clone()).or_insert_with(|| {
+            let id = note_entity(&key);
+            missing.insert(id, target.to_string());
+            id
+        })
+    };
+
+    let mut actions = Vec::new();
+    for note in notes {
+        let mut facts = note.record.clone();
+        for (predicate, links) in &note.properties {
+            facts
+                .entry(predicate.clone())
+                .or_default()
+                .extend(links.iter().map(|l| Datum::Entity(resolve(l))));
+        }
+        let links: Vec<Datum> = note
+            .links
+            .iter()
+            .map(|l| Datum::Entity(resolve(l)))
+            .collect();
+        facts.insert("links".to_string(), links);
+        actions.extend(upsert(projection, note_entity(&note.path), &facts));
+    }
+    for (id, name) in missing {
+        let name = name.rsplit('/').next().unwrap_or(&name).to_string();
+        let facts = BTreeMap::from([("name".to_string(), vec![Datum::String(name)])]);
+        actions.extend(upsert(projection, id, &facts));
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_link_to_each_other() {
+        let project = "---\nstatus: active\npriority: 2\ndue: 2024-03-01\n\
+            tags: [work, rust]\nowner: \"[[Ada]]\"\nrelated:\n  - \"[[Ideas|some ideas]]\"\n---\n\
+            # Graphite\n\nSee [[Ada#Contact]] and ![[diagram.png]], #planning.\n\
+            reviewed:: true\n";
+        let notes = vec![
+            parse(project, "Projects/Graphite"),
+            parse("Writes code. #people", "People/Ada"),
+            parse("Old notes", "Archive/People/Ada"),
+        ];
+        let graphite = &notes[0];
+        assert_eq!(
+            graphite.record["status"],
+            vec![Datum::String("active".into())]
+        );
+        assert_eq!(graphite.record["priority"], vec![Datum::Integer(2)]);
+        assert_eq!(graphite.record["due"], vec![Datum::DateTime(1709251200)]);
+        assert_eq!(graphite.record["reviewed"], vec![Datum::Boolean(true)]);
+        let tags: Vec<Datum> = ["work", "rust", "planning"]
+            .map(|t| Datum::String(t.into()))
+            .to_vec();
+        assert_eq!(graphite.record["tags"], tags);
+        assert_eq!(graphite.properties["owner"], vec!["Ada"]);
+        assert_eq!(graphite.properties["related"], vec!["Ideas"]);
+        assert_eq!(graphite.links, vec!["Ada"]);
+        let Datum::String(content) = &graphite.record["content"][0] else {
+            panic!("the content is text");
+        };
+        assert!(content.starts_with("# Graphite") && !content.contains("reviewed"));
+
+        let mut projection = Projection::new();
+        actions(&projection, &notes)
+            .iter()
+            .for_each(|a| projection.apply(a));
+        let entity = projection
+            .entity(&note_entity("Projects/Graphite"))
+            .unwrap();
+        let ada = Datum::Entity(note_entity("People/Ada"));
+        assert_eq!(entity.values("links")[0], ada);
+        assert_eq!(entity.values("owner"), &[ada]);
+        let ideas = projection.entity(&note_entity("ideas")).unwrap();
+        assert_eq!(ideas.value("name"), Some(&Datum::String("Ideas".into())));
+        assert!(actions(&projection, &notes).is_empty());
+    }
+}
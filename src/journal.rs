@@ -0,0 +1,198 @@
+//! Daily journal notes.
+//!
+//! Every day has at most one `journal` entity, whose id is derived from its
+//! date so that every device agrees on it. A note links to the notes of the
+//! previous and next day, and to the entities that were created or changed
+//! during its day through `mentions`.
+
+use crate::import::{entity_id, upsert};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum, Event};
+use std::collections::{BTreeMap, BTreeSet};
+use time::{Date, Duration, Month, OffsetDateTime, UtcOffset, Weekday};
+use uuid::Uuid;
+
+pub fn day_entity(date: Date) -> Uuid {
+    entity_id(&format!("journal:{}", date))
+}
+
+/// The local time zone's offset, or UTC if it is unknown.
+pub fn local_offset() -> UtcOffset {
+    UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC)
+}
+
+/// Today's date in the local time zone.
+pub fn today() -> Date {
+    OffsetDateTime::now_utc().to_offset(local_offset()).date()
+}
+
+/// The first and one past the last second of `date` at `offset`.
+pub fn day_bounds(date: Date, offset: UtcOffset) -> (i64, i64) {
+    let start = date.midnight().assume_offset(offset).unix_timestamp();
+    (start, start + Duration::DAY.whole_seconds())
+}
+
+/// Returns the actions that create the note for `date`, if it is missing.
+pub fn open(projection: &Projection, date: Date) -> Vec<Action> {
+    let mut facts = BTreeMap::new();
+    facts.insert(
+        "type".to_string(),
+        vec![Datum::String("journal".to_string())],
+    );
+    facts.insert("title".to_string(), vec![Datum::String(date.to_string())]);
+    // Midnight UTC, so every device records the same value.
+    let midnight = day_bounds(date, UtcOffset::UTC).0;
+    facts.insert("date".to_string(), vec![Datum::DateTime(midnight)]);
+    let neighbours = [("previous", date.previous_day()), ("next", date.next_day())];
+    for (predicate, day) in neighbours {
+        let link = day.map(|d| Datum::Entity(day_entity(d)));
+        facts.insert(predicate.to_string(), link.into_iter().collect());
+    }
+    upsert(projection, day_entity(date), &facts)
+}
+
+/// The entities that `events` on `date` at `offset` created or changed, in
+/// the order they were first touched.
+pub fn mentioned(events: &[Event], date: Date, offset: UtcOffset) -> Vec<Uuid> {
+    let (start, end) = day_bounds(date, offset);
+    let mut mentioned = Vec::new();
+    for event in events {
+        if !(start..end).contains(&event.hlc().seconds()) {
+            continue;
+        }
+        for subject in event.action().subjects() {
+            if !mentioned.contains(&subject) {
+                mentioned.push(subject);
+            }
+        }
+    }
+    mentioned
+}
+
+/// Returns the actions that link the note for `date` to the `mentioned`
+/// entities it does not link to yet.
+///
+/// Entities that no longer exist and other journal notes are skipped.
+pub fn link_mentions(projection: &Projection, date: Date, mentioned: &[Uuid]) -> Vec<Action> {
+    let day = day_entity(date);
+    let linked = projection
+        .entity(&day)
+        .map(|e| e.values("mentions"))
+        .unwrap_or_default();
+    mentioned
+        .iter()
+        .filter(|id| **id != day && !linked.contains(&Datum::Entity(**id)))
+        .filter(|id| {
+            projection
+                .entity(id)
+                .is_some_and(|e| e.value("type") != Some(&Datum::String("journal".to_string())))
+        })
+        .map(|id| Action::AddFact {
+            subject: day,
+            predicate: "mentions".to_string(),
+            datum: Datum::Entity(*id),
+        })
+        .collect()
+}
+
+/// The dates that have a journal note.
+pub fn days(projection: &Projection) -> BTreeSet<Date> {
+    projection
+        .entities()
+        .filter(|(_, e)| e.value("type") == Some(&Datum::String("journal".to_string())))
+        .filter_map(|(_, e)| match e.value("date") {
+            Some(Datum::DateTime(seconds)) => OffsetDateTime::from_unix_timestamp(*seconds)
+                .ok()
+                .map(|d| d.date()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The weeks of a month as rows from Monday to Sunday, with `None` for the
+/// days that belong to the neighbouring months.
+pub fn month_grid(year: i32, month: Month) -> Vec<[Option<Date>; 7]> {
+    let mut weeks = Vec::new();
+    let mut week = [None; 7];
+    let Ok(mut date) = Date::from_calendar_date(year, month, 1) else {
+        return weeks;
+    };
+    while date.month() == month {
+        let column = date.weekday().number_days_from_monday() as usize;
+        week[column] = Some(date);
+        if date.weekday() == Weekday::Sunday {
+            weeks.push(std::mem::take(&mut week));
+        }
+        match date.next_day() {
+            Some(next) => date = next,
+            None => break,
+        }
+    }
+    if week.iter().any(Option::is_some) {
+        weeks.push(week);
+    }
+    weeks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::EventCreator;
+    use time::macros::date;
+
+    #[test]
+    fn opening_a_day_is_idempotent() {
+        let mut projection = Projection::new();
+        let day = date!(2024 - 03 - 01);
+        open(&projection, day)
+            .iter()
+            .for_each(|a| projection.apply(a));
+        assert!(open(&projection, day).is_empty());
+
+        let note = projection.entity(&day_entity(day)).unwrap();
+        assert_eq!(
+            note.value("previous"),
+            Some(&Datum::Entity(day_entity(date!(2024 - 02 - 29))))
+        );
+        assert_eq!(days(&projection).into_iter().collect::<Vec<_>>(), [day]);
+    }
+
+    #[test]
+    fn links_entities_touched_that_day() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut creator = EventCreator::new(Uuid::nil(), HLTimestamp::new(0, 0));
+        let events = vec![
+            creator.create(Action::CreateEntity { id: a }),
+            creator.create(Action::CreateEntity { id: b }),
+        ];
+        let day = OffsetDateTime::from_unix_timestamp(events[0].hlc().seconds())
+            .unwrap()
+            .date();
+        let mentions = mentioned(&events, day, UtcOffset::UTC);
+        assert_eq!(mentions, [a, b]);
+        assert!(mentioned(&events, day.previous_day().unwrap(), UtcOffset::UTC).is_empty());
+
+        let mut projection = Projection::new();
+        projection.apply(&Action::CreateEntity { id: a });
+        open(&projection, day)
+            .iter()
+            .for_each(|x| projection.apply(x));
+        // `b` is not in the projection, so it is not linked.
+        let links = link_mentions(&projection, day, &[a, b, day_entity(day)]);
+        assert_eq!(links.len(), 1);
+        links.iter().for_each(|x| projection.apply(x));
+        assert!(link_mentions(&projection, day, &[a]).is_empty());
+    }
+
+    #[test]
+    fn month_grid_starts_on_monday() {
+        // February 2024 starts on a Thursday and has 29 days.
+        let weeks = month_grid(2024, Month::February);
+        assert_eq!(weeks.len(), 5);
+        assert_eq!(weeks[0][..3], [None, None, None]);
+        assert_eq!(weeks[0][3], Some(date!(2024 - 02 - 01)));
+        assert_eq!(weeks[4][3], Some(date!(2024 - 02 - 29)));
+        assert_eq!(weeks[4][4], None);
+    }
+}
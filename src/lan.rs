@@ -0,0 +1,768 @@
+//! Syncing with other instances on the local network, without any
+//! configuration.
+//!
+//! Each instance serves its events over HTTP on a free port and announces
+//! it with multicast DNS, see [`mdns`], so the others list it as a [`Peer`].
+//! Syncing with a peer first pairs with it: the peer shows who asks, by
+//! device and actor, and a code both sides show, and only once someone
+//! accepts there does it hand out a token. With it, the events of each side
+//! are fetched and merged into the other, see [`crate::conflicts::merge`],
+//! restricted to the subscription of the side merging them, see
+//! [`crate::replication`].
+//!
+//! Each side notes, by peer, the change of the peer's events it merged up
+//! to, see [`EventStorage::poll_changes`], so syncs only exchange what is
+//! new. The first sync, and the first after the subscription changed,
+//! exchange the whole log instead, in pages.
+//!
+//! - `POST /pair` asks to pair, with the actor, device and a nonce,
+//! - `GET /pair/<nonce>` answers whether the pairing was accepted,
+//! - `GET /events` answers the events recorded since the change in the
+//!   `Graphite-Since` header, or else a page of all of them after the cursor
+//!   in `Graphite-After`, as JSON lines. `Graphite-Change` tells the change
+//!   they go up to, `Graphite-Next` the cursor of the next page, if any, and
+//!   `Graphite-Merged` the change of the asker's events merged up to, if
+//!   any,
+//! - `POST /events` merges the events sent, and notes the change of the
+//!   asker's events in `Graphite-Change` as merged up to,
+//! - `POST /presence` exchanges what each side looks at, see [`presence`].
+
+pub mod mdns;
+pub mod presence;
+
+use crate::conflicts;
+use crate::legacy::async_storage::AsyncStorage;
+use crate::legacy::storage::{metadata, Cursor, Event, EventStorage};
+use crate::replication::{self, Subscription};
+use anyhow::{anyhow, bail, Context, Result};
+use futures::executor::block_on;
+use presence::Presence;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Request, Response, Server};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// How often instances ask who is around.
+const QUERY_INTERVAL: Duration = Duration::from_secs(10);
+/// How long a peer is listed after it was last heard of.
+const FORGET_AFTER: Duration = Duration::from_secs(60);
+/// How long a pairing waits to be accepted.
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(120);
+/// The largest body accepted.
+const MAX_BODY: u64 = 256 * 1024 * 1024;
+/// The most events of the whole log in one page.
+const PAGE: usize = 10_000;
+const SINCE: &str = "Graphite-Since";
+const AFTER: &str = "Graphite-After";
+const CHANGE: &str = "Graphite-Change";
+const NEXT: &str = "Graphite-Next";
+const MERGED: &str = "Graphite-Merged";
+/// The change of the events of a peer merged up to, suffixed with its
+/// actor.
+const MERGED_UP_TO: &str = "lan_merged";
+/// The subscription they were merged with, suffixed with its actor.
+const MERGED_WITH: &str = "lan_subscription";
+
+/// Another instance on the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub actor: Uuid,
+    pub device: String,
+    pub address: SocketAddr,
+}
+
+/// Someone asking to pair with this instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pairing {
+    pub nonce: Uuid,
+    pub actor: Uuid,
+    pub device: String,
+    /// The code the device asking shows too.
+    pub code: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct PairRequest {
+    actor: Uuid,
+    device: String,
+    nonce: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum PairAnswer {
+    Pending,
+    Accepted { token: String },
+    Refused,
+}
+
+#[derive(Default)]
+struct State {
+    peers: HashMap<Uuid, (Peer, Instant)>,
+    pending: Vec<Pairing>,
+    answers: HashMap<Uuid, PairAnswer>,
+    /// The actors paired, by their token.
+    tokens: HashMap<String, Uuid>,
+    /// What is merged of the events peers send.
+    subscription: Subscription,
+    /// Events merged from peers that weren't reported yet.
+    merged: usize,
+    /// What this instance shares with its peers.
+    presence: Option<Presence>,
+    /// The presences of peers, with when they were last heard of.
+    others: HashMap<Uuid, (Presence, Instant)>,
+}
+
+/// This instance on the network: serving its events, and announcing itself
+/// and listening for others while discovery is on.
+pub struct Lan {
+    actor: Uuid,
+    device: String,
+    state: Arc<Mutex<State>>,
+    server: Arc<Server>,
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Lan {
+    /// Serves `storage` as `actor` on `device`, and discovers peers.
+    pub fn start(storage: AsyncStorage, actor: Uuid, device: String) -> Result<Lan> {
+        let mut lan = Lan::serve(storage, actor, device)?;
+        lan.discover()?;
+        Ok(lan)
+    }
+
+    /// Serves `storage` without announcing it.
+    pub fn serve(storage: AsyncStorage, actor: Uuid, device: String) -> Result<Lan> {
+        let server = Server::http("0.0.0.0:0").map_err(|e| anyhow!("Failed to listen: {}", e))?;
+        let server = Arc::new(server);
+        let address = server
+            .server_addr()
+            .to_ip()
+            .context("Not listening on an IP address")?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let endpoint = Endpoint {
+            actor,
+            storage,
+            state: state.clone(),
+        };
+        let listener = server.clone();
+        let thread = thread::Builder::new()
+            .name("graphite-lan".into())
+            .spawn(move || {
+                for mut request in listener.incoming_requests() {
+                    let response = endpoint.answer(&mut request);
+                    let _ = request.respond(response);
+                }
+            })
+            .context("Failed to spawn the LAN thread")?;
+        Ok(Lan {
+            actor,
+            device,
+            state,
+            server,
+            address,
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: vec![thread],
+        })
+    }
+
+    fn discover(&mut self) -> Result<()> {
+        let socket = mdns::socket()?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let announcement = mdns::Announcement {
+            actor: self.actor,
+            device: self.device.clone(),
+            port: self.address.port(),
+        };
+        let (state, stop) = (self.state.clone(), self.stop.clone());
+        let thread = thread::Builder::new()
+            .name("graphite-mdns".into())
+            .spawn(move || listen(&socket, &announcement, &state, &stop))
+            .context("Failed to spawn the discovery thread")?;
+        self.threads.push(thread);
+        Ok(())
+    }
+
+    pub fn actor(&self) -> Uuid {
+        self.actor
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The peers heard of lately, by device.
+    pub fn peers(&self) -> Vec<Peer> {
+        let state = self.state.lock().expect("the LAN state is not poisoned");
+        let mut peers: Vec<Peer> = state
+            .peers
+            .values()
+            .filter(|(_, seen)| seen.elapsed() < FORGET_AFTER)
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        peers.sort_by(|a, b| (&a.device, a.actor).cmp(&(&b.device, b.actor)));
+        peers
+    }
+
+    /// The pairings waiting for an answer.
+    pub fn pending(&self) -> Vec<Pairing> {
+        let state = self.state.lock().expect("the LAN state is not poisoned");
+        state.pending.clone()
+    }
+
+    /// Accepts or refuses the pairing `nonce`.
+    pub fn answer(&self, nonce: Uuid, accept: bool) {
+        let mut state = self.state.lock().expect("the LAN state is not poisoned");
+        let actor = state
+            .pending
+            .iter()
+            .find(|p| p.nonce == nonce)
+            .map(|p| p.actor);
+        let answer = match (accept, actor) {
+            (true, Some(actor)) => {
+                let token = Uuid::new_v4().simple().to_string();
+                state.tokens.insert(token.clone(), actor);
+                PairAnswer::Accepted { token }
+            }
+            _ => PairAnswer::Refused,
+        };
+        state.pending.retain(|p| p.nonce != nonce);
+        state.answers.insert(nonce, answer);
+    }
+
+    /// Sets what is merged of the events peers send.
+    pub fn set_subscription(&self, subscription: Subscription) {
+        let mut state = self.state.lock().expect("the LAN state is not poisoned");
+        state.subscription = subscription;
+    }
+
+    /// How many events peers merged in since last asked.
+    pub fn take_merged(&self) -> usize {
+        let mut state = self.state.lock().expect("the LAN state is not poisoned");
+        std::mem::take(&mut state.merged)
+    }
+}
+
+impl Drop for Lan {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.server.unblock();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Answers questions for the service and notes the peers that announce
+/// themselves, until `stop`.
+fn listen(
+    socket: &UdpSocket,
+    announcement: &mdns::Announcement,
+    state: &Mutex<State>,
+    stop: &AtomicBool,
+) {
+    let (query, answer) = (mdns::query(), mdns::answer(announcement));
+    let mut asked: Option<Instant> = None;
+    let mut buffer = [0; 9000];
+    while !stop.load(Ordering::Relaxed) {
+        if asked.is_none_or(|at| at.elapsed() >= QUERY_INTERVAL) {
+            for packet in [&query, &answer] {
+                if let Err(e) = socket.send_to(packet, mdns::group()) {
+                    warn!("Failed to send a multicast DNS message: {}", e);
+                }
+            }
+            asked = Some(Instant::now());
+        }
+        let Ok((length, from)) = socket.recv_from(&mut buffer) else {
+            continue;
+        };
+        match mdns::parse(&buffer[..length]) {
+            Ok(mdns::Message::Query) => {
+                let _ = socket.send_to(&answer, mdns::group());
+            }
+            Ok(mdns::Message::Answer(peer)) if peer.actor != announcement.actor => {
+                debug!(actor = %peer.actor, %from, "Found a peer");
+                let peer = Peer {
+                    actor: peer.actor,
+                    device: peer.device,
+                    address: SocketAddr::new(from.ip(), peer.port),
+                };
+                let mut state = state.lock().expect("the LAN state is not poisoned");
+                state.peers.insert(peer.actor, (peer, Instant::now()));
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Endpoint {
+    actor: Uuid,
+    storage: AsyncStorage,
+    state: Arc<Mutex<State>>,
+}
+
+impl Endpoint {
+    fn answer(&self, request: &mut Request) -> Response<io::Cursor<Vec<u8>>> {
+        let headers: HashMap<String, String> = request
+            .headers()
+            .iter()
+            .map(|h| {
+                (
+                    h.field.as_str().as_str().to_ascii_lowercase(),
+                    h.value.to_string(),
+                )
+            })
+            .collect();
+        let mut body = Vec::new();
+        let read = request.as_reader().take(MAX_BODY).read_to_end(&mut body);
+        if read.is_err() {
+            return reply(400, b"Failed to read the body".to_vec());
+        }
+        let method = request.method().as_str().to_string();
+        match self.handle(&method, request.url(), &headers, &body) {
+            Ok(answer) => answer,
+            Err(e) => reply(500, format!("{:#}", e).into_bytes()),
+        }
+    }
+
+    fn handle(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &HashMap<String, String>,
+        body: &[u8],
+    ) -> Result<Response<io::Cursor<Vec<u8>>>> {
+        let header = |name: &str| headers.get(&name.to_ascii_lowercase()).map(String::as_str);
+        let mut state = self.state.lock().expect("the LAN state is not poisoned");
+        if let ("POST", "/pair") = (method, path) {
+            let request: PairRequest = serde_json::from_slice(body)?;
+            if !state.answers.contains_key(&request.nonce) {
+                state.pending.push(Pairing {
+                    nonce: request.nonce,
+                    actor: request.actor,
+                    code: pairing_code(request.actor, self.actor, request.nonce),
+                    device: request.device,
+                });
+                state.answers.insert(request.nonce, PairAnswer::Pending);
+            }
+            return Ok(reply(202, Vec::new()));
+        }
+        if let Some(nonce) = path.strip_prefix("/pair/") {
+            let answer = Uuid::parse_str(nonce)
+                .ok()
+                .and_then(|nonce| state.answers.get(&nonce));
+            return Ok(match answer {
+                Some(answer) => reply(200, serde_json::to_vec(answer)?),
+                None => reply(404, b"No such pairing".to_vec()),
+            });
+        }
+        if path != "/events" && path != "/presence" {
+            return Ok(reply(404, b"Not found".to_vec()));
+        }
+        let token = header("Authorization").and_then(|a| a.strip_prefix("Bearer "));
+        let Some(asker) = token.and_then(|t| state.tokens.get(t)).copied() else {
+            return Ok(reply(401, b"Pair first".to_vec()));
+        };
+        if path == "/presence" {
+            if method != "POST" {
+                return Ok(reply(405, b"Only POST is allowed".to_vec()));
+            }
+            let theirs: Presence = serde_json::from_slice(body)?;
+            state.others.insert(theirs.actor, (theirs, Instant::now()));
+            return Ok(reply(200, serde_json::to_vec(&state.presence)?));
+        }
+        let subscription = state.subscription.clone();
+        drop(state);
+        match method {
+            "GET" => {
+                let since = header(SINCE).map(str::parse).transpose()?;
+                let after = header(AFTER).map(str::parse).transpose()?;
+                let (page, merged) = block_on(self.storage.call(move |s| -> Result<_> {
+                    Ok((
+                        outgoing(s, since, after)?,
+                        merged_up_to(s, asker, &subscription)?,
+                    ))
+                }))?;
+                let mut response = reply(200, encode(&page.events)?)
+                    .with_header(header_of(CHANGE, &page.change.to_string()));
+                if let Some(next) = page.next {
+                    response.add_header(header_of(NEXT, &next.to_string()));
+                }
+                if let Some(merged) = merged {
+                    response.add_header(header_of(MERGED, &merged.to_string()));
+                }
+                Ok(response)
+            }
+            "POST" => {
+                let events = decode(body)?;
+                let change: Option<i64> = header(CHANGE).map(str::parse).transpose()?;
+                let merged = block_on(self.storage.call(move |s| -> Result<_> {
+                    let events = replication::select_for(s, &subscription, events)?;
+                    let (merged, _) = conflicts::merge(s, events)?;
+                    if let Some(change) = change {
+                        s.set_meta_value(&key(MERGED_UP_TO, asker), &change.to_string())?;
+                        replication::set_read_with(s, &key(MERGED_WITH, asker), &subscription)?;
+                    }
+                    Ok(merged)
+                }))?;
+                let mut state = self.state.lock().expect("the LAN state is not poisoned");
+                state.merged += merged;
+                Ok(reply(200, merged.to_string().into_bytes()))
+            }
+            _ => Ok(reply(405, b"Only GET and POST are allowed".to_vec())),
+        }
+    }
+}
+
+fn reply(status: u16, body: Vec<u8>) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_data(body).with_status_code(status)
+}
+
+fn header_of(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("a valid header")
+}
+
+/// The metadata key `prefix` for `actor`.
+fn key(prefix: &str, actor: Uuid) -> String {
+    format!("{}/{}", prefix, actor.simple())
+}
+
+/// The change of the events of `actor` merged up to, unless they were
+/// merged with another subscription than `subscription`.
+fn merged_up_to(
+    storage: &EventStorage,
+    actor: Uuid,
+    subscription: &Subscription,
+) -> Result<Option<i64>> {
+    if replication::read_with(storage, &key(MERGED_WITH, actor))? != *subscription {
+        return Ok(None);
+    }
+    match storage.meta_value(&key(MERGED_UP_TO, actor))? {
+        Some(change) => Ok(Some(change.parse().context("Invalid LAN change")?)),
+        None => Ok(None),
+    }
+}
+
+/// Events to send a peer, and where they go up to.
+struct Outgoing {
+    events: Vec<Event>,
+    /// The change they go up to.
+    change: i64,
+    /// Where the next page of the whole log starts, if there is one.
+    next: Option<Cursor>,
+}
+
+/// The events recorded since the change `since`, or else the page of all
+/// events after `after`.
+fn outgoing(storage: &EventStorage, since: Option<i64>, after: Option<Cursor>) -> Result<Outgoing> {
+    if let Some(since) = since {
+        let changes = storage.poll_changes(since)?;
+        return Ok(Outgoing {
+            events: changes.events,
+            change: changes.last,
+            next: None,
+        });
+    }
+    // Events recorded while paging come again with the next sync.
+    let change = storage.last_change()?;
+    let page = storage.play_page(after, PAGE)?;
+    Ok(Outgoing {
+        events: page.events,
+        change,
+        next: page.next,
+    })
+}
+
+/// The code both sides of a pairing show, to tell they talk to each other.
+pub fn pairing_code(requester: Uuid, responder: Uuid, nonce: Uuid) -> String {
+    let bytes = [
+        *requester.as_bytes(),
+        *responder.as_bytes(),
+        *nonce.as_bytes(),
+    ]
+    .concat();
+    let hash = digest::digest(&digest::SHA256, &bytes);
+    let number = u32::from_be_bytes(hash.as_ref()[..4].try_into().expect("4 bytes"));
+    format!("{:06}", number % 1_000_000)
+}
+
+/// Asks `peer` to pair and waits until someone there accepts, returning the
+/// token to sync with.
+pub fn pair(peer: &Peer, actor: Uuid, device: &str, nonce: Uuid) -> Result<String> {
+    let url = format!("http://{}/pair", peer.address);
+    let request = PairRequest {
+        actor,
+        device: device.to_string(),
+        nonce,
+    };
+    crate::backup::send(ureq::post(&url), &serde_json::to_vec(&request)?)
+        .with_context(|| format!("Failed to reach {}", peer.device))?;
+    let started = Instant::now();
+    while started.elapsed() < PAIRING_TIMEOUT {
+        let response = crate::backup::send(ureq::get(&format!("{}/{}", url, nonce)), &[])?;
+        match serde_json::from_reader(response.into_reader())? {
+            PairAnswer::Pending => thread::sleep(Duration::from_secs(1)),
+            PairAnswer::Accepted { token } => return Ok(token),
+            PairAnswer::Refused => bail!("{} refused to pair", peer.device),
+        }
+    }
+    bail!("{} didn't accept in time", peer.device)
+}
+
+/// What syncing with a peer did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Synced {
+    pub pulled: usize,
+    pub pushed: usize,
+    pub conflicts: usize,
+}
+
+/// Merges the events of `peer` into `storage` and those of `storage` into
+/// the peer, with the `token` pairing gave, only those new to the other
+/// side since the last sync. The events merged here are restricted to
+/// `subscription`.
+pub fn sync(
+    peer: &Peer,
+    token: &str,
+    storage: &AsyncStorage,
+    subscription: &Subscription,
+) -> Result<Synced> {
+    let url = format!("http://{}/events", peer.address);
+    let authorization = format!("Bearer {}", token);
+    let since = block_on(storage.call({
+        let (actor, subscription) = (peer.actor, subscription.clone());
+        move |s| -> Result<_> { merged_up_to(s, actor, &subscription) }
+    }))?;
+
+    // What the peer merged of ours, and the change its events go up to, as
+    // of the first answer.
+    let (mut merged, mut change) = (None, None);
+    let mut theirs = Vec::new();
+    let mut after: Option<Cursor> = None;
+    loop {
+        let mut request = ureq::get(&url).set("Authorization", &authorization);
+        match (since, after) {
+            (Some(since), _) => request = request.set(SINCE, &since.to_string()),
+            (None, Some(after)) => request = request.set(AFTER, &after.to_string()),
+            (None, None) => {}
+        }
+        let response = crate::backup::send(request, &[])
+            .with_context(|| format!("Failed to fetch the events of {}", peer.device))?;
+        let number = |name: &str| -> Result<Option<i64>> {
+            let value = response.header(name);
+            value
+                .map(|v| v.parse().context("Invalid LAN change"))
+                .transpose()
+        };
+        if change.is_none() {
+            change = number(CHANGE)?;
+            merged = number(MERGED)?;
+        }
+        after = response.header(NEXT).map(str::parse).transpose()?;
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_BODY)
+            .read_to_end(&mut body)?;
+        theirs.extend(decode(&body)?);
+        if since.is_some() || after.is_none() {
+            break;
+        }
+    }
+    let origin = format!("lan/{}", peer.device);
+    let theirs: Vec<Event> = theirs.into_iter().map(|e| e.synced_from(&origin)).collect();
+    let change = change.context("The peer didn't tell the change of its events")?;
+    let (pulled, conflicts) = block_on(storage.call({
+        let (actor, subscription) = (peer.actor, subscription.clone());
+        move |s| -> Result<_> {
+            let theirs = replication::select_for(s, &subscription, theirs)?;
+            let merged = conflicts::merge(s, theirs)?;
+            s.set_meta_value(&key(MERGED_UP_TO, actor), &change.to_string())?;
+            replication::set_read_with(s, &key(MERGED_WITH, actor), &subscription)?;
+            Ok(merged)
+        }
+    }))?;
+
+    // Everything the peer hasn't merged, but what came from it.
+    let (mine, last) = block_on(storage.call(move |s| -> Result<_> {
+        let (events, last) = match merged {
+            Some(since) => {
+                let changes = s.poll_changes(since)?;
+                (changes.events, changes.last)
+            }
+            None => {
+                let last = s.last_change()?;
+                let mut events = Vec::new();
+                s.play(|event| {
+                    events.push(event);
+                    Ok(())
+                })?;
+                (events, last)
+            }
+        };
+        Ok((events, last))
+    }))?;
+    let mine: Vec<Event> = mine
+        .into_iter()
+        .filter(|e| e.metadata().get(metadata::SYNC_ORIGIN) != Some(&origin))
+        .collect();
+    let mut pushed = 0;
+    let pages: Vec<&[Event]> = match mine.is_empty() {
+        true => vec![&[]],
+        false => mine.chunks(PAGE).collect(),
+    };
+    for (n, page) in pages.iter().enumerate() {
+        let mut request = ureq::post(&url).set("Authorization", &authorization);
+        // The change is noted once the last page is merged.
+        if n + 1 == pages.len() {
+            request = request.set(CHANGE, &last.to_string());
+        }
+        let response = crate::backup::send(request, &encode(page)?)
+            .with_context(|| format!("Failed to send the events to {}", peer.device))?;
+        pushed += response.into_string()?.trim().parse().unwrap_or(0);
+    }
+    Ok(Synced {
+        pulled,
+        pushed,
+        conflicts,
+    })
+}
+
+fn encode(events: &[Event]) -> Result<Vec<u8>> {
+    let mut lines = Vec::new();
+    for event in events {
+        serde_json::to_writer(&mut lines, event)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+fn decode(body: &[u8]) -> Result<Vec<Event>> {
+    body.split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).context("Invalid event"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn paired_peers_sync() {
+        let storages = [(); 2].map(|_| AsyncStorage::open(":memory:").unwrap());
+        for storage in &storages {
+            block_on(storage.call(|s| {
+                let mut creator = s.creator()?;
+                s.record_batch(vec![
+                    creator.create(Action::CreateEntity { id: Uuid::new_v4() })
+                ])
+            }))
+            .unwrap();
+        }
+        let [laptop, desktop] = [Uuid::new_v4(), Uuid::new_v4()];
+        let served = Lan::serve(storages[1].clone(), desktop, "desktop".into()).unwrap();
+        let peer = Peer {
+            actor: desktop,
+            device: "desktop".into(),
+            address: SocketAddr::new([127, 0, 0, 1].into(), served.address().port()),
+        };
+        assert!(sync(&peer, "guess", &storages[0], &Subscription::default()).is_err());
+
+        let nonce = Uuid::new_v4();
+        let pairing =
+            thread::spawn(move || pair(&peer, laptop, "laptop", nonce).map(|t| (peer, t)));
+        let pending = loop {
+            if let Some(pending) = served.pending().pop() {
+                break pending;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!((pending.actor, pending.device.as_str()), (laptop, "laptop"));
+        assert_eq!(pending.code, pairing_code(laptop, desktop, nonce));
+        served.answer(pending.nonce, true);
+        let (peer, token) = pairing.join().unwrap().unwrap();
+
+        let everything = Subscription::default();
+        let synced = sync(&peer, &token, &storages[0], &everything).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (1, 1));
+        assert_eq!(served.take_merged(), 1);
+        let count = |storage: &AsyncStorage| block_on(storage.events()).unwrap().len();
+        assert_eq!((count(&storages[0]), count(&storages[1])), (2, 2));
+        // Only what is new is exchanged.
+        let synced = sync(&peer, &token, &storages[0], &everything).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (0, 0));
+    }
+
+    #[test]
+    fn peers_merge_what_they_subscribe_to() {
+        let [laptop, desktop] = [(); 2].map(|_| AsyncStorage::open(":memory:").unwrap());
+        // Each has a project the other isn't subscribed to at first.
+        for (storage, names) in [
+            (&laptop, ["Graphite", "Chores"]),
+            (&desktop, ["Garden", "Taxes"]),
+        ] {
+            block_on(storage.call(move |s| {
+                let mut creator = s.creator()?;
+                let mut events = Vec::new();
+                for name in names {
+                    let id = Uuid::new_v4();
+                    events.push(creator.create(Action::CreateEntity { id }));
+                    events.push(creator.create(Action::AddFact {
+                        subject: id,
+                        predicate: "project".to_string(),
+                        datum: crate::legacy::storage::Datum::String(name.to_string()),
+                    }));
+                }
+                s.record_batch(events)
+            }))
+            .unwrap();
+        }
+        let actor = Uuid::new_v4();
+        let served = Lan::serve(desktop.clone(), actor, "desktop".into()).unwrap();
+        let token = "paired".to_string();
+        served
+            .state
+            .lock()
+            .unwrap()
+            .tokens
+            .insert(token.clone(), Uuid::new_v4());
+        let subscribed = |queries: &[&str]| Subscription {
+            queries: queries.iter().map(|q| q.to_string()).collect(),
+            depth: 0,
+        };
+        served.set_subscription(subscribed(&["project=Graphite"]));
+        let peer = Peer {
+            actor,
+            device: "desktop".into(),
+            address: SocketAddr::new([127, 0, 0, 1].into(), served.address().port()),
+        };
+
+        let mut subscription = subscribed(&["project=Garden"]);
+        let synced = sync(&peer, &token, &laptop, &subscription).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (2, 2));
+        subscription.queries.push("project=Taxes".to_string());
+        let synced = sync(&peer, &token, &laptop, &subscription).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (2, 0));
+        served.set_subscription(subscribed(&["project=Graphite", "project=Chores"]));
+        let synced = sync(&peer, &token, &laptop, &subscription).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (0, 2));
+        let count = |storage: &AsyncStorage| block_on(storage.events()).unwrap().len();
+        assert_eq!((count(&laptop), count(&desktop)), (8, 8));
+    }
+}
@@ -0,0 +1,349 @@
+//! Just enough multicast DNS (RFC 6762) to announce instances and find the
+//! others.
+//!
+//! Instances ask for the [`SERVICE`] and answer with a PTR record to their
+//! instance name, an SRV record with their port, and a TXT record with
+//! their actor, device and port. Everything else on the group is ignored.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use uuid::Uuid;
+
+/// The service instances announce.
+pub const SERVICE: &str = "_graphite._tcp.local";
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+
+const PTR: u16 = 12;
+const TXT: u16 = 16;
+const SRV: u16 = 33;
+const ANY: u16 = 255;
+const IN: u16 = 1;
+const TTL: u32 = 120;
+
+/// An instance, as it announces itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    pub actor: Uuid,
+    pub device: String,
+    /// The port it serves its events on.
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Someone asks who offers the service.
+    Query,
+    Answer(Announcement),
+    /// Anything about other services.
+    Other,
+}
+
+/// The group to send to.
+pub fn group() -> SocketAddr {
+    SocketAddrV4::new(GROUP, PORT).into()
+}
+
+/// A socket on the multicast DNS port, in the group, sharing the port with
+/// other responders on the machine.
+pub fn socket() -> Result<UdpSocket> {
+    let socket = bind().context("Failed to bind the multicast DNS port")?;
+    socket
+        .join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)
+        .context("Failed to join the multicast DNS group")?;
+    socket.set_multicast_loop_v4(true)?;
+    Ok(socket)
+}
+
+#[cfg(unix)]
+fn bind() -> std::io::Result<UdpSocket> {
+    use std::io::Error;
+    use std::os::fd::FromRawFd;
+
+    let enable: libc::c_int = 1;
+    let set = |fd, option| {
+        // SAFETY: the option is an int, as long as claimed.
+        let result = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                option,
+                (&enable as *const libc::c_int).cast(),
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(Error::last_os_error())
+        }
+    };
+    // SAFETY: a plain UDP socket, owned by the UdpSocket from then on.
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+    set(fd, libc::SO_REUSEADDR)?;
+    set(fd, libc::SO_REUSEPORT)?;
+    // SAFETY: zeroes are a valid sockaddr_in, filled in below.
+    let mut address: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+    address.sin_family = libc::AF_INET as libc::sa_family_t;
+    address.sin_port = PORT.to_be();
+    address.sin_addr.s_addr = u32::from(Ipv4Addr::UNSPECIFIED).to_be();
+    // SAFETY: the address is a sockaddr_in, as long as claimed.
+    let result = unsafe {
+        libc::bind(
+            fd,
+            (&address as *const libc::sockaddr_in).cast(),
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+#[cfg(not(unix))]
+fn bind() -> std::io::Result<UdpSocket> {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, PORT))
+}
+
+/// Asks who offers the service.
+pub fn query() -> Vec<u8> {
+    let mut packet = header(0, 1, 0);
+    name(&mut packet, SERVICE);
+    packet.extend(PTR.to_be_bytes());
+    packet.extend(IN.to_be_bytes());
+    packet
+}
+
+/// Announces `announcement`.
+pub fn answer(announcement: &Announcement) -> Vec<u8> {
+    let instance = format!("{}.{}", announcement.actor.simple(), SERVICE);
+    let mut packet = header(0x8400, 0, 3);
+
+    let mut pointer = Vec::new();
+    name(&mut pointer, &instance);
+    record(&mut packet, SERVICE, PTR, &pointer);
+
+    let mut service = Vec::new();
+    service.extend(0u16.to_be_bytes());
+    service.extend(0u16.to_be_bytes());
+    service.extend(announcement.port.to_be_bytes());
+    name(
+        &mut service,
+        &format!("{}.local", announcement.actor.simple()),
+    );
+    record(&mut packet, &instance, SRV, &service);
+
+    let mut text = Vec::new();
+    for entry in [
+        format!("actor={}", announcement.actor),
+        format!("device={}", announcement.device),
+        format!("port={}", announcement.port),
+    ] {
+        let entry = &entry.as_bytes()[..entry.len().min(255)];
+        text.push(entry.len() as u8);
+        text.extend(entry);
+    }
+    record(&mut packet, &instance, TXT, &text);
+    packet
+}
+
+/// Reads a message off the group.
+pub fn parse(packet: &[u8]) -> Result<Message> {
+    let mut reader = Reader { packet, at: 0 };
+    let _id = reader.u16()?;
+    let flags = reader.u16()?;
+    let questions = reader.u16()?;
+    let records: usize = (0..3)
+        .map(|_| reader.u16().map(usize::from))
+        .sum::<Result<_>>()?;
+
+    let mut asked = false;
+    for _ in 0..questions {
+        let name = reader.name()?;
+        let kind = reader.u16()?;
+        let _class = reader.u16()?;
+        asked |= name.eq_ignore_ascii_case(SERVICE) && (kind == PTR || kind == ANY);
+    }
+    if flags & 0x8000 == 0 {
+        return Ok(if asked {
+            Message::Query
+        } else {
+            Message::Other
+        });
+    }
+
+    for _ in 0..records {
+        let name = reader.name()?;
+        let kind = reader.u16()?;
+        let _class = reader.u16()?;
+        let _ttl = reader.u32()?;
+        let length = usize::from(reader.u16()?);
+        let data = reader.take(length)?;
+        if kind != TXT || !name.to_ascii_lowercase().ends_with(SERVICE) {
+            continue;
+        }
+        let mut entries = HashMap::new();
+        let mut text = data;
+        while let Some((&length, rest)) = text.split_first() {
+            let length = usize::from(length).min(rest.len());
+            let entry = String::from_utf8_lossy(&rest[..length]);
+            if let Some((key, value)) = entry.split_once('=') {
+                entries.insert(key.to_string(), value.to_string());
+            }
+            text = &rest[length..];
+        }
+        let actor = entries.get("actor").and_then(|a| a.parse().ok());
+        let port = entries.get("port").and_then(|p| p.parse().ok());
+        if let (Some(actor), Some(port)) = (actor, port) {
+            let device = entries.remove("device").unwrap_or_default();
+            return Ok(Message::Answer(Announcement {
+                actor,
+                device,
+                port,
+            }));
+        }
+    }
+    Ok(Message::Other)
+}
+
+fn header(flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+    let mut packet = Vec::new();
+    for field in [0, flags, questions, answers, 0, 0] {
+        packet.extend(field.to_be_bytes());
+    }
+    packet
+}
+
+fn name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        packet.push(label.len() as u8);
+        packet.extend(label);
+    }
+    packet.push(0);
+}
+
+fn record(packet: &mut Vec<u8>, owner: &str, kind: u16, data: &[u8]) {
+    name(packet, owner);
+    packet.extend(kind.to_be_bytes());
+    packet.extend(IN.to_be_bytes());
+    packet.extend(TTL.to_be_bytes());
+    packet.extend((data.len() as u16).to_be_bytes());
+    packet.extend(data);
+}
+
+struct Reader<'a> {
+    packet: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let Some(bytes) = self.packet.get(self.at..self.at + length) else {
+            bail!("Truncated packet");
+        };
+        self.at += length;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a name, following compression pointers.
+    fn name(&mut self) -> Result<String> {
+        let mut labels = Vec::new();
+        let mut at = self.at;
+        let mut jumped = false;
+        for _ in 0..128 {
+            let Some(&length) = self.packet.get(at) else {
+                bail!("Truncated name");
+            };
+            if length & 0xC0 == 0xC0 {
+                let Some(&low) = self.packet.get(at + 1) else {
+                    bail!("Truncated name");
+                };
+                if !jumped {
+                    self.at = at + 2;
+                    jumped = true;
+                }
+                at = usize::from(u16::from_be_bytes([length & 0x3F, low]));
+                continue;
+            }
+            let length = usize::from(length);
+            if length == 0 {
+                if !jumped {
+                    self.at = at + 1;
+                }
+                return Ok(labels.join("."));
+            }
+            let Some(label) = self.packet.get(at + 1..at + 1 + length) else {
+                bail!("Truncated name");
+            };
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            at += 1 + length;
+        }
+        bail!("Name with too many labels or pointers")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_announce_instances() {
+        assert_eq!(parse(&query()).unwrap(), Message::Query);
+        let announcement = Announcement {
+            actor: Uuid::new_v4(),
+            device: "laptop".to_string(),
+            port: 40123,
+        };
+        assert_eq!(
+            parse(&answer(&announcement)).unwrap(),
+            Message::Answer(announcement)
+        );
+
+        // An answer naming its record by a pointer to the question.
+        let actor = Uuid::new_v4();
+        let mut compressed = header(0x8400, 1, 1);
+        name(&mut compressed, SERVICE);
+        compressed.extend(PTR.to_be_bytes());
+        compressed.extend(IN.to_be_bytes());
+        let entries = [format!("actor={}", actor), "port=1".to_string()];
+        let text: Vec<u8> = entries
+            .iter()
+            .flat_map(|e| [vec![e.len() as u8], e.as_bytes().to_vec()].concat())
+            .collect();
+        compressed.extend([0xC0, 12]);
+        compressed.extend(TXT.to_be_bytes());
+        compressed.extend(IN.to_be_bytes());
+        compressed.extend(TTL.to_be_bytes());
+        compressed.extend((text.len() as u16).to_be_bytes());
+        compressed.extend(text);
+        let Message::Answer(found) = parse(&compressed).unwrap() else {
+            panic!("the answer was not read");
+        };
+        assert_eq!((found.actor, found.port), (actor, 1));
+
+        let mut other = header(0, 1, 0);
+        name(&mut other, "_printer._tcp.local");
+        other.extend(PTR.to_be_bytes());
+        other.extend(IN.to_be_bytes());
+        assert_eq!(parse(&other).unwrap(), Message::Other);
+        assert!(parse(&compressed[..20]).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Who else has the graph open, and what they look at.
+//!
+//! Paired instances exchange their [`Presence`] while connected: the one
+//! that paired posts its own to `POST /presence` with the token and gets the
+//! other's in return. It is only kept in memory, never recorded as events,
+//! and forgotten when not heard of for a while.
+
+use super::{Lan, Peer, MAX_BODY};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long a presence is shown after it was last heard of.
+pub const FORGET_AFTER: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Presence {
+    pub actor: Uuid,
+    pub device: String,
+    /// The entities selected, the one shown first.
+    pub selected: Vec<Uuid>,
+    /// Where the pointer is on the canvas, in logical pixels.
+    pub cursor: Option<(f32, f32)>,
+}
+
+impl Lan {
+    /// Sets what this instance shares with its peers.
+    pub fn set_presence(&self, presence: Presence) {
+        let mut state = self.state.lock().expect("the LAN state is not poisoned");
+        state.presence = Some(presence);
+    }
+
+    /// Notes the presence of a peer.
+    pub fn heard(&self, presence: Presence) {
+        let mut state = self.state.lock().expect("the LAN state is not poisoned");
+        state
+            .others
+            .insert(presence.actor, (presence, Instant::now()));
+    }
+
+    /// The presences of the peers heard of lately, by device.
+    pub fn presences(&self) -> Vec<Presence> {
+        let mut state = self.state.lock().expect("the LAN state is not poisoned");
+        state
+            .others
+            .retain(|_, (_, heard)| heard.elapsed() < FORGET_AFTER);
+        let mut presences: Vec<Presence> = state.others.values().map(|(p, _)| p.clone()).collect();
+        presences.sort_by(|a, b| (&a.device, a.actor).cmp(&(&b.device, b.actor)));
+        presences
+    }
+}
+
+/// Sends `mine` to `peer`, with the `token` pairing gave, and answers its
+/// presence, if it shares one.
+pub fn exchange(peer: &Peer, token: &str, mine: &Presence) -> Result<Option<Presence>> {
+    let url = format!("http://{}/presence", peer.address);
+    let request = ureq::post(&url)
+        .set("Authorization", &format!("Bearer {}", token))
+        .timeout(Duration::from_secs(2));
+    let response = crate::backup::send(request, &serde_json::to_vec(mine)?)
+        .with_context(|| format!("Failed to reach {}", peer.device))?;
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_BODY)
+        .read_to_end(&mut body)?;
+    serde_json::from_slice(&body).context("Invalid presence")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::async_storage::AsyncStorage;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn paired_peers_see_each_other() {
+        let storage = AsyncStorage::open(":memory:").unwrap();
+        let desktop = Uuid::new_v4();
+        let served = Lan::serve(storage, desktop, "desktop".into()).unwrap();
+        let peer = Peer {
+            actor: desktop,
+            device: "desktop".into(),
+            address: SocketAddr::new([127, 0, 0, 1].into(), served.address().port()),
+        };
+        let presence = |actor, device: &str| Presence {
+            actor,
+            device: device.into(),
+            selected: vec![Uuid::new_v4()],
+            cursor: Some((12.0, 34.0)),
+        };
+        let laptop = presence(Uuid::new_v4(), "laptop");
+        assert!(exchange(&peer, "guess", &laptop).is_err());
+
+        let token = "paired".to_string();
+        served
+            .state
+            .lock()
+            .unwrap()
+            .tokens
+            .insert(token.clone(), Uuid::new_v4());
+        assert_eq!(exchange(&peer, &token, &laptop).unwrap(), None);
+        assert_eq!(served.presences(), vec![laptop.clone()]);
+
+        let shown = presence(desktop, "desktop");
+        served.set_presence(shown.clone());
+        assert_eq!(exchange(&peer, &token, &laptop).unwrap(), Some(shown));
+    }
+}
@@ -0,0 +1,8 @@
+pub mod async_storage;
+pub mod codec;
+pub mod error;
+pub mod hlc;
+pub mod projection;
+pub mod storage;
+pub mod text;
+pub mod vector_clock;
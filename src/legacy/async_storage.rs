@@ -0,0 +1,245 @@
+use crate::legacy::error::{Context, GraphiteError, Result};
+use crate::legacy::hlc::HLTimestamp;
+use crate::legacy::storage::{Cursor, Event, EventStorage, Page};
+use crate::progress::Reporter;
+use std::future::Future;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use tokio::sync::{broadcast, oneshot};
+
+type Job = Box<dyn FnOnce(&mut EventStorage) + Send>;
+
+/// A non-blocking handle to an `EventStorage`.
+///
+/// The connection lives on a dedicated thread and every call is queued to it,
+/// so calls run in the order they were made. The returned futures are
+/// `'static` and can be handed straight to `Command::perform`.
+///
+/// Every event recorded through the handle is also published to the
+/// receivers returned by [`AsyncStorage::subscribe`].
+///
+/// The thread shuts down once every clone of the handle has been dropped.
+#[derive(Clone)]
+pub struct AsyncStorage {
+    sender: mpsc::Sender<Job>,
+    recorded: broadcast::Sender<Event>,
+}
+
+impl AsyncStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AsyncStorage> {
+        let storage = EventStorage::open(path)?;
+        AsyncStorage::new(storage)
+    }
+
+    pub fn new(mut storage: EventStorage) -> Result<AsyncStorage> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("graphite-storage".into())
+            .spawn(move || {
+                for job in receiver {
+                    job(&mut storage);
+                }
+            })
+            .context("Failed to spawn the storage thread")?;
+        let (recorded, _) = broadcast::channel(AsyncStorage::FEED_CAPACITY);
+        Ok(AsyncStorage { sender, recorded })
+    }
+
+    /// How many recorded events a slow subscriber may fall behind before it
+    /// starts missing them.
+    pub const FEED_CAPACITY: usize = 1024;
+
+    /// Returns a receiver of every event recorded from now on.
+    ///
+    /// A receiver that falls more than `FEED_CAPACITY` events behind gets
+    /// `RecvError::Lagged` and should reload from storage.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.recorded.subscribe()
+    }
+
+    /// Runs `f` on the storage thread and resolves to its result.
+    ///
+    /// The job is queued immediately, not when the future is first polled.
+    /// `f` may fail with any error a [`GraphiteError`] converts into, such
+    /// as `anyhow::Error`.
+    pub fn call<T, E, F>(&self, f: F) -> impl Future<Output = Result<T, E>> + Send + 'static
+    where
+        T: Send + 'static,
+        E: From<GraphiteError> + Send + 'static,
+        F: FnOnce(&mut EventStorage) -> Result<T, E> + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        // The job runs in the span of the caller, so that what it logs says
+        // what it was for.
+        let span = tracing::Span::current();
+        let queued = self.sender.send(Box::new(move |storage| {
+            let _entered = span.enter();
+            // The caller may have dropped the future, in which case nobody is
+            // waiting for the result.
+            let _ = reply.send(f(storage));
+        }));
+
+        async move {
+            queued.map_err(|_| GraphiteError::Stopped)?;
+            response.await.map_err(|_| GraphiteError::Stopped)?
+        }
+    }
+
+    pub fn record(&self, event: Event) -> impl Future<Output = Result<()>> + Send + 'static {
+        let recorded = self.recorded.clone();
+        self.call(move |storage| {
+            storage.record(event.clone())?;
+            // Having no subscribers is not an error.
+            let _ = recorded.send(event);
+            Ok(())
+        })
+    }
+
+    pub fn record_batch(
+        &self,
+        events: Vec<Event>,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        let recorded = self.recorded.clone();
+        self.call(move |storage| {
+            storage.record_batch(events.clone())?;
+            for event in events {
+                let _ = recorded.send(event);
+            }
+            Ok(())
+        })
+    }
+
+    /// Collects every event in timestamp order.
+    pub fn events(&self) -> impl Future<Output = Result<Vec<Event>>> + Send + 'static {
+        self.call(|storage| {
+            let mut events = Vec::new();
+            storage.play(|event| {
+                events.push(event);
+                Ok(())
+            })?;
+            Ok(events)
+        })
+    }
+
+    /// Collects every event like [`AsyncStorage::events`], reporting to
+    /// `reporter` as they are read.
+    pub fn events_with_progress(
+        &self,
+        reporter: Reporter,
+    ) -> impl Future<Output = Result<Vec<Event>>> + Send + 'static {
+        self.call(move |storage| {
+            let mut events = Vec::new();
+            storage.play_with_progress(&reporter, |event| {
+                events.push(event);
+                Ok(())
+            })?;
+            Ok(events)
+        })
+    }
+
+    /// Collects the events from `hlc` onwards in timestamp order.
+    pub fn events_from(
+        &self,
+        hlc: HLTimestamp,
+    ) -> impl Future<Output = Result<Vec<Event>>> + Send + 'static {
+        self.call(move |storage| {
+            let mut events = Vec::new();
+            storage.play_from(hlc, |event| {
+                events.push(event);
+                Ok(())
+            })?;
+            Ok(events)
+        })
+    }
+
+    /// Reads at most `limit` events following `after`, see
+    /// [`EventStorage::play_page`].
+    pub fn page(
+        &self,
+        after: Option<Cursor>,
+        limit: usize,
+    ) -> impl Future<Output = Result<Page>> + Send + 'static {
+        self.call(move |storage| storage.play_page(after, limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::{Action, EventCreator};
+    use uuid::Uuid;
+
+    fn storage() -> AsyncStorage {
+        AsyncStorage::open(":memory:").unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_and_plays_events() {
+        let storage = storage();
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let first = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+        let rest = vec![
+            creator.create(Action::CreateEntity { id: Uuid::new_v4() }),
+            creator.create(Action::DeleteEntity { id: Uuid::new_v4() }),
+        ];
+
+        storage.record(first.clone()).await.unwrap();
+        storage.record_batch(rest.clone()).await.unwrap();
+
+        let mut expected = vec![first];
+        expected.extend(rest);
+        assert_eq!(storage.events().await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn calls_run_in_order() {
+        let storage = storage();
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let event = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+
+        // Queue the write before awaiting either future.
+        let record = storage.record(event.clone());
+        let events = storage.events();
+
+        record.await.unwrap();
+        assert_eq!(events.await.unwrap(), vec![event]);
+    }
+
+    #[tokio::test]
+    async fn publishes_recorded_events() {
+        let storage = storage();
+        let mut feed = storage.subscribe();
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let event = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+        let batch = vec![creator.create(Action::CreateEntity { id: Uuid::new_v4() })];
+
+        storage.record(event.clone()).await.unwrap();
+        storage.record_batch(batch.clone()).await.unwrap();
+
+        assert_eq!(feed.recv().await.unwrap(), event);
+        assert_eq!(feed.recv().await.unwrap(), batch[0]);
+    }
+
+    #[tokio::test]
+    async fn failed_writes_are_not_published() {
+        let storage = storage();
+        let mut feed = storage.subscribe();
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let event = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+
+        storage.record(event.clone()).await.unwrap();
+        // The id is the primary key, so the second write fails.
+        assert!(storage.record(event.clone()).await.is_err());
+
+        assert_eq!(feed.recv().await.unwrap(), event);
+        assert!(feed.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn errors_are_returned_to_the_caller() {
+        let storage = storage();
+        let result: anyhow::Result<()> = storage.call(|_| Err(anyhow::anyhow!("boom"))).await;
+        assert_eq!(result.unwrap_err().to_string(), "boom");
+    }
+}
@@ -0,0 +1,138 @@
+//! Encodings for the actions stored in the event log.
+//!
+//! Every event records the codec its action was written with, so a log can
+//! mix encodings and switching codecs only affects new events.
+
+use crate::legacy::error::{GraphiteError, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    /// Readable and the format of logs written before codecs existed.
+    #[default]
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 3] = [Codec::Json, Codec::Cbor, Codec::MessagePack];
+
+    /// The id stored next to every event. Never change these.
+    pub fn id(self) -> i64 {
+        match self {
+            Codec::Json => 0,
+            Codec::Cbor => 1,
+            Codec::MessagePack => 2,
+        }
+    }
+
+    pub fn from_id(id: i64) -> Result<Codec> {
+        Codec::ALL
+            .into_iter()
+            .find(|c| c.id() == id)
+            .ok_or(GraphiteError::UnknownCodec(id))
+    }
+
+    /// Whether the encoding is UTF-8 text rather than binary.
+    pub fn is_text(self) -> bool {
+        self == Codec::Json
+    }
+
+    /// Appends the encoding of `value` to `buffer`.
+    pub fn encode<T: Serialize>(self, value: &T, buffer: &mut Vec<u8>) -> Result<()> {
+        let encoded = match self {
+            Codec::Json => serde_json::to_writer(buffer, value).map_err(Into::into),
+            Codec::Cbor => ciborium::into_writer(value, buffer).map_err(Into::into),
+            Codec::MessagePack => rmp_serde::encode::write_named(buffer, value).map_err(Into::into),
+        };
+        encoded.map_err(|source| GraphiteError::Codec {
+            context: format!("Failed to serialize to {}", self),
+            source,
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T> {
+        let decoded = match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(Into::into),
+            Codec::Cbor => ciborium::from_reader(bytes).map_err(Into::into),
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(Into::into),
+        };
+        decoded.map_err(|source| GraphiteError::Codec {
+            context: format!("Failed to deserialize from {}", self),
+            source,
+        })
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Codec::Json => "json",
+            Codec::Cbor => "cbor",
+            Codec::MessagePack => "messagepack",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCodecError(String);
+
+impl fmt::Display for ParseCodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown codec {:?}, expected json, cbor or messagepack",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseCodecError {}
+
+impl FromStr for Codec {
+    type Err = ParseCodecError;
+
+    fn from_str(s: &str) -> Result<Codec, ParseCodecError> {
+        match s.to_ascii_lowercase().as_str() {
+            "msgpack" => Ok(Codec::MessagePack),
+            name => Codec::ALL
+                .into_iter()
+                .find(|c| c.to_string() == name)
+                .ok_or_else(|| ParseCodecError(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::{Action, Datum};
+    use uuid::Uuid;
+
+    #[test]
+    fn codecs_round_trip_actions() {
+        let action = Action::Transaction {
+            actions: vec![
+                Action::CreateEntity { id: Uuid::new_v4() },
+                Action::AddFact {
+                    subject: Uuid::new_v4(),
+                    predicate: "weight".into(),
+                    datum: Datum::Float(1.5),
+                },
+            ],
+        };
+        for codec in Codec::ALL {
+            let mut buffer = Vec::new();
+            codec.encode(&action, &mut buffer).unwrap();
+            assert_eq!(codec.decode::<Action>(&buffer).unwrap(), action);
+            assert_eq!(Codec::from_id(codec.id()).unwrap(), codec);
+            assert_eq!(codec.to_string().parse::<Codec>().unwrap(), codec);
+        }
+        assert!("xml".parse::<Codec>().is_err());
+    }
+}
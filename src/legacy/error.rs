@@ -0,0 +1,116 @@
+//! The errors of the storage layer.
+//!
+//! Callers can match on what went wrong, e.g. to tell a corrupt event from a
+//! full disk, while `{:#}` still prints the whole chain of causes.
+
+use crate::legacy::storage::writer::Holder;
+use crate::progress::Cancelled;
+use uuid::Uuid;
+
+pub type Result<T, E = GraphiteError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GraphiteError {
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{context}")]
+    Sqlite {
+        context: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// An action couldn't be encoded or decoded with a codec.
+    #[error("{context}")]
+    Codec {
+        context: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A recorded event can't be read back.
+    #[error("Event {event_id} is corrupt")]
+    Corrupt {
+        event_id: Uuid,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// The event was recorded by a newer version of Graphite.
+    #[error("Event {event_id} has version {version}, which this version can't read")]
+    VersionUnsupported { event_id: Uuid, version: u32 },
+    #[error("Unknown codec {0}")]
+    UnknownCodec(i64),
+    /// Something with the same name or id already exists.
+    #[error("{0}")]
+    Conflict(String),
+    /// The arguments of a call were rejected.
+    #[error("{0}")]
+    Invalid(String),
+    /// Another process holds the write lock of the database, see
+    /// [`writer`](crate::legacy::storage::writer).
+    #[error("Another Graphite process{} is writing to the database, open it read-only to look at it", .0.pid.map(|pid| format!(" ({})", pid)).unwrap_or_default())]
+    Locked(Holder),
+    /// The database was opened read-only.
+    #[error("The database is open read-only")]
+    ReadOnly,
+    /// New events are paused while the system clock is behind, see
+    /// [`clock`](crate::legacy::storage::clock).
+    #[error("The system clock is {behind} seconds behind the latest event, edits are paused until it is corrected")]
+    ClockSkew { behind: i64 },
+    #[error("The storage thread has stopped")]
+    Stopped,
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+}
+
+impl From<rusqlite::Error> for GraphiteError {
+    fn from(source: rusqlite::Error) -> GraphiteError {
+        GraphiteError::Sqlite {
+            context: "The database failed".to_string(),
+            source,
+        }
+    }
+}
+
+/// Adds what was being done to the errors of SQLite and the file system,
+/// like `anyhow::Context`.
+pub trait Context<T> {
+    fn context(self, context: impl Into<String>) -> Result<T>;
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Source> Context<T> for std::result::Result<T, E> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|source| source.wrap(context.into()))
+    }
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|source| source.wrap(context().into()))
+    }
+}
+
+/// An error [`Context`] can wrap.
+pub trait Source {
+    fn wrap(self, context: String) -> GraphiteError;
+}
+
+impl Source for rusqlite::Error {
+    fn wrap(self, context: String) -> GraphiteError {
+        GraphiteError::Sqlite {
+            context,
+            source: self,
+        }
+    }
+}
+
+impl Source for std::io::Error {
+    fn wrap(self, context: String) -> GraphiteError {
+        GraphiteError::Io {
+            context,
+            source: self,
+        }
+    }
+}
@@ -31,6 +31,7 @@ use time;
 
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Error, Formatter};
+use std::str::FromStr;
 use std::sync::Mutex;
 
 /// The `HLTimestamp` type stores a hybrid logical timestamp.
@@ -42,7 +43,7 @@ use std::sync::Mutex;
 /// # Examples
 ///
 /// ```
-/// use graphite::hlc::HLTimestamp;
+/// use graphite::legacy::hlc::HLTimestamp;
 /// let early = HLTimestamp::new(0, 0);
 /// let middle = HLTimestamp::new(1, 0);
 /// let late = HLTimestamp::new(1, 1);
@@ -61,7 +62,7 @@ impl HLTimestamp {
     /// # Examples
     ///
     /// ```
-    /// use graphite::hlc::HLTimestamp;
+    /// use graphite::legacy::hlc::HLTimestamp;
     /// let ts = HLTimestamp::new(1, 2);
     /// assert_eq!(format!("{}", ts), "1+2");
     /// ```
@@ -75,7 +76,7 @@ impl HLTimestamp {
     /// Returns the seconds time of the timestamp.
     /// # Examples
     /// ```
-    /// use graphite::hlc::HLTimestamp;
+    /// use graphite::legacy::hlc::HLTimestamp;
     /// let ts = HLTimestamp::new(1, 2);
     /// assert_eq!(ts.seconds(), 1);
     /// ```
@@ -86,13 +87,49 @@ impl HLTimestamp {
     /// Returns the logical component of the timestamp.
     /// # Examples
     /// ```
-    /// use graphite::hlc::HLTimestamp;
+    /// use graphite::legacy::hlc::HLTimestamp;
     /// let ts = HLTimestamp::new(1, 2);
     /// assert_eq!(ts.logical(), 2);
     /// ```
     pub fn logical(&self) -> u16 {
         self.logical
     }
+
+    /// Encodes the timestamp as 10 big-endian bytes: 8 for the seconds and 2
+    /// for the logical component. The sign bit of the seconds is flipped so
+    /// that the byte strings sort in the same order as the timestamps.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use graphite::legacy::hlc::HLTimestamp;
+    /// let early = HLTimestamp::new(-1, 5);
+    /// let late = HLTimestamp::new(0, 0);
+    /// assert!(early.to_bytes() < late.to_bytes());
+    /// assert_eq!(HLTimestamp::from_bytes(late.to_bytes()), late);
+    /// ```
+    pub fn to_bytes(&self) -> [u8; HLTimestamp::ENCODED_LEN] {
+        let mut bytes = [0; HLTimestamp::ENCODED_LEN];
+        let seconds = (self.seconds as u64) ^ (1 << 63);
+        bytes[..8].copy_from_slice(&seconds.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.logical.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a timestamp produced by [`HLTimestamp::to_bytes`].
+    pub fn from_bytes(bytes: [u8; HLTimestamp::ENCODED_LEN]) -> HLTimestamp {
+        let mut seconds = [0; 8];
+        seconds.copy_from_slice(&bytes[..8]);
+        let mut logical = [0; 2];
+        logical.copy_from_slice(&bytes[8..]);
+        HLTimestamp {
+            seconds: (u64::from_be_bytes(seconds) ^ (1 << 63)) as i64,
+            logical: u16::from_be_bytes(logical),
+        }
+    }
+
+    /// The number of bytes in the binary encoding of a timestamp.
+    pub const ENCODED_LEN: usize = 10;
 }
 
 impl Display for HLTimestamp {
@@ -101,12 +138,65 @@ impl Display for HLTimestamp {
     }
 }
 
+/// The error returned when parsing a `HLTimestamp` from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseHLTimestampError {
+    /// The string did not contain the `+` separating seconds and logical.
+    MissingSeparator,
+    /// The seconds component was not a valid `i64`.
+    InvalidSeconds(std::num::ParseIntError),
+    /// The logical component was not a valid `u16`.
+    InvalidLogical(std::num::ParseIntError),
+}
+
+impl Display for ParseHLTimestampError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            ParseHLTimestampError::MissingSeparator => {
+                f.write_str("missing '+' between seconds and logical")
+            }
+            ParseHLTimestampError::InvalidSeconds(e) => write!(f, "invalid seconds: {}", e),
+            ParseHLTimestampError::InvalidLogical(e) => write!(f, "invalid logical: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseHLTimestampError {}
+
+/// Parses the `"seconds+logical"` form written by `Display`.
+///
+/// # Examples
+///
+/// ```
+/// use graphite::legacy::hlc::HLTimestamp;
+/// let ts: HLTimestamp = "-3+7".parse().unwrap();
+/// assert_eq!(ts, HLTimestamp::new(-3, 7));
+/// assert_eq!(ts.to_string().parse::<HLTimestamp>(), Ok(ts));
+/// ```
+impl FromStr for HLTimestamp {
+    type Err = ParseHLTimestampError;
+
+    fn from_str(s: &str) -> Result<HLTimestamp, ParseHLTimestampError> {
+        // Seconds may be negative, so split on the last '+' rather than the first.
+        let (seconds, logical) = s
+            .rsplit_once('+')
+            .ok_or(ParseHLTimestampError::MissingSeparator)?;
+        let seconds = seconds
+            .parse()
+            .map_err(ParseHLTimestampError::InvalidSeconds)?;
+        let logical = logical
+            .parse()
+            .map_err(ParseHLTimestampError::InvalidLogical)?;
+        Ok(HLTimestamp { seconds, logical })
+    }
+}
+
 /// `State` is a hybrid logical clock.
 ///
 /// # Examples
 ///
 /// ```
-/// use graphite::hlc::{HLTimestamp, State};
+/// use graphite::legacy::hlc::{HLTimestamp, State};
 /// let mut s = State::new();
 /// println!("{}", s.get_time()); // attach to outgoing event
 /// let ext_event_ts = HLTimestamp::new(12345, 89); // external event's timestamp
@@ -117,7 +207,7 @@ impl Display for HLTimestamp {
 /// a `State` wrapped in a `Mutex`:
 ///
 /// ```
-/// use graphite::hlc::State;
+/// use graphite::legacy::hlc::State;
 /// let mut mu = State::new_sendable();
 /// {
 ///     let mut s = mu.lock().unwrap();
@@ -150,7 +240,7 @@ impl<F: FnMut() -> i64> State<F> {
     ///
     /// ```
     /// # fn main() {
-    /// use graphite::hlc::{HLTimestamp, State};
+    /// use graphite::legacy::hlc::{HLTimestamp, State};
     /// let mut times = vec![42];
     /// let mut s = State::new_with(move || times.pop().unwrap());
     /// let mut ts = s.get_time();
@@ -163,7 +253,7 @@ impl<F: FnMut() -> i64> State<F> {
                 seconds: 0,
                 logical: 0,
             },
-            now: now,
+            now,
         }
     }
 
@@ -177,7 +267,7 @@ impl<F: FnMut() -> i64> State<F> {
         } else {
             s.logical += 1;
         }
-        s.clone()
+        *s
     }
 
     /// Assigns a timestamp to an event which happened at the given timestamp
@@ -199,7 +289,7 @@ impl<F: FnMut() -> i64> State<F> {
             }
             s.logical += 1;
         }
-        s.clone()
+        *s
     }
 }
 
@@ -240,9 +330,48 @@ mod tests {
             let t = if op.1 == zero {
                 s.get_time()
             } else {
-                s.update(op.1.clone())
+                s.update(op.1)
             };
             assert_eq!(t, op.2);
         }
     }
+
+    #[test]
+    fn bytes_sort_like_timestamps() {
+        let mut timestamps = vec![
+            hlts(i64::MIN, 0),
+            hlts(-1, u16::MAX),
+            hlts(0, 0),
+            hlts(0, 1),
+            hlts(1, 0),
+            hlts(256, 0),
+            hlts(i64::MAX, u16::MAX),
+        ];
+        let sorted = timestamps.clone();
+        timestamps.reverse();
+        timestamps.sort_by_key(|ts| ts.to_bytes());
+        assert_eq!(timestamps, sorted);
+        for ts in sorted {
+            assert_eq!(HLTimestamp::from_bytes(ts.to_bytes()), ts);
+        }
+    }
+
+    #[test]
+    fn string_round_trip() {
+        for ts in [hlts(0, 0), hlts(-42, 7), hlts(i64::MIN, u16::MAX)] {
+            assert_eq!(ts.to_string().parse::<HLTimestamp>(), Ok(ts));
+        }
+        assert_eq!(
+            "12".parse::<HLTimestamp>(),
+            Err(ParseHLTimestampError::MissingSeparator)
+        );
+        assert!(matches!(
+            "a+1".parse::<HLTimestamp>(),
+            Err(ParseHLTimestampError::InvalidSeconds(_))
+        ));
+        assert!(matches!(
+            "1+70000".parse::<HLTimestamp>(),
+            Err(ParseHLTimestampError::InvalidLogical(_))
+        ));
+    }
 }
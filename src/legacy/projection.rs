@@ -0,0 +1,578 @@
+use crate::legacy::error::Result;
+use crate::legacy::storage::{chunk, Action, Cursor, Datum, Event, EventStorage};
+use crate::legacy::text::Text;
+use crate::progress::Reporter;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+/// The current state of the graph, built by applying events in order.
+///
+/// A predicate may hold several values. `AddFact` appends a value unless it
+/// is already present and `RemoveFact` clears every value of the predicate.
+/// Facts about entities that do not exist (or have been deleted) are ignored.
+/// The chunks of a large transaction are applied together when the last one
+/// is, see [`chunk`].
+///
+/// Counters, sets and registers merge instead, see [`crate::crdt`]:
+/// `Increment` adds to the integer value of a predicate, and the elements of
+/// sets and registers are kept by tag, so that removing one only removes
+/// what the remover had seen. Texts edited with `InsertText` and
+/// `DeleteText` hold their characters by id, see [`crate::legacy::text`].
+/// `RemoveFact` clears them too.
+///
+/// Deleted entities move to the trash with their facts, where
+/// `RestoreEntity` brings them back. They stay there for the trash window
+/// after they were deleted, measured in event time so that every replica
+/// agrees on whether a restore came in time.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Projection {
+    entities: HashMap<Uuid, Entity>,
+    /// The actions of chunked transactions whose last chunk is yet to come.
+    pending: HashMap<Uuid, Vec<Action>>,
+    trash: HashMap<Uuid, Trashed>,
+    /// How long deleted entities can be restored, in seconds, if not
+    /// [`DEFAULT_TRASH_WINDOW`].
+    trash_window: Option<i64>,
+}
+
+/// How long deleted entities can be restored by default: 30 days.
+pub const DEFAULT_TRASH_WINDOW: i64 = 30 * 24 * 60 * 60;
+
+/// An entity in the trash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trashed {
+    pub entity: Entity,
+    /// When it was deleted, in unix seconds, if known.
+    pub deleted: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Entity {
+    facts: BTreeMap<String, Vec<Datum>>,
+    /// The elements of sets and registers by predicate, with their tags.
+    tagged: BTreeMap<String, Vec<(Uuid, Datum)>>,
+    texts: BTreeMap<String, Text>,
+}
+
+impl Entity {
+    pub fn facts(&self) -> impl Iterator<Item = (&str, &[Datum])> {
+        self.facts
+            .iter()
+            .map(|(predicate, values)| (predicate.as_str(), values.as_slice()))
+    }
+
+    /// All values of `predicate` in the order they were added.
+    pub fn values(&self, predicate: &str) -> &[Datum] {
+        self.facts
+            .get(predicate)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The first value of `predicate`, for predicates used as single values.
+    pub fn value(&self, predicate: &str) -> Option<&Datum> {
+        self.values(predicate).first()
+    }
+
+    /// The elements of the set or register `predicate` with their tags, in
+    /// the order they were added.
+    pub fn tagged(&self, predicate: &str) -> &[(Uuid, Datum)] {
+        self.tagged
+            .get(predicate)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The text `predicate` holds, if it was edited as one.
+    pub fn text(&self, predicate: &str) -> Option<&Text> {
+        self.texts.get(predicate)
+    }
+
+    /// Changes the text of `predicate` and its value with it.
+    fn edit_text(&mut self, predicate: &str, change: impl FnOnce(&mut Text)) {
+        let text = self.texts.entry(predicate.to_string()).or_default();
+        change(text);
+        if text.is_empty() {
+            self.facts.remove(predicate);
+        } else {
+            let value = Datum::String(text.to_string());
+            self.facts.insert(predicate.to_string(), vec![value]);
+        }
+    }
+
+    /// Changes the elements of `predicate` and its values with them.
+    fn retag(&mut self, predicate: &str, change: impl FnOnce(&mut Vec<(Uuid, Datum)>)) {
+        let elements = self.tagged.entry(predicate.to_string()).or_default();
+        change(elements);
+        let mut values: Vec<Datum> = Vec::new();
+        for (_, datum) in elements.iter() {
+            if !values.contains(datum) {
+                values.push(datum.clone());
+            }
+        }
+        if values.is_empty() {
+            self.tagged.remove(predicate);
+            self.facts.remove(predicate);
+        } else {
+            self.facts.insert(predicate.to_string(), values);
+        }
+    }
+}
+
+impl Projection {
+    pub fn new() -> Projection {
+        Projection::default()
+    }
+
+    /// Builds a projection from every event in the storage.
+    #[instrument(skip_all)]
+    pub fn load(storage: &EventStorage) -> Result<Projection> {
+        let mut projection = Projection::new();
+        storage.play(|event| {
+            projection.apply_event(&event);
+            Ok(())
+        })?;
+        debug!(entities = projection.len(), "Replayed the graph");
+        Ok(projection)
+    }
+
+    /// Builds the projection like [`Projection::load`], reporting each event
+    /// replayed to `reporter`.
+    #[instrument(skip_all)]
+    pub fn load_with_progress(storage: &EventStorage, reporter: &Reporter) -> Result<Projection> {
+        let mut projection = Projection::new();
+        storage.play_with_progress(reporter, |event| {
+            projection.apply_event(&event);
+            Ok(())
+        })?;
+        debug!(entities = projection.len(), "Replayed the graph");
+        Ok(projection)
+    }
+
+    /// Builds the projection as it was at a checkpoint's `watermark`.
+    #[instrument(skip(storage))]
+    pub fn load_until(storage: &EventStorage, watermark: Option<Cursor>) -> Result<Projection> {
+        let mut projection = Projection::new();
+        storage.play_until(watermark, |event| {
+            projection.apply_event(&event);
+            Ok(())
+        })?;
+        Ok(projection)
+    }
+
+    /// Sets how long deleted entities can be restored, in seconds. It must
+    /// be set before events are applied.
+    pub fn set_trash_window(&mut self, seconds: i64) {
+        self.trash_window = Some(seconds);
+    }
+
+    pub fn trash_window(&self) -> i64 {
+        self.trash_window.unwrap_or(DEFAULT_TRASH_WINDOW)
+    }
+
+    pub fn apply_event(&mut self, event: &Event) {
+        self.apply_at(event.action(), Some(event.hlc().seconds()));
+    }
+
+    /// Applies `action` without knowing when it happened, so deleted
+    /// entities can always be restored.
+    pub fn apply(&mut self, action: &Action) {
+        self.apply_at(action, None);
+    }
+
+    fn apply_at(&mut self, action: &Action, time: Option<i64>) {
+        match action {
+            Action::CreateEntity { id } => {
+                self.trash.remove(id);
+                self.entities.entry(*id).or_default();
+            }
+            Action::AddFact {
+                subject,
+                predicate,
+                datum,
+            } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    let values = entity.facts.entry(predicate.clone()).or_default();
+                    if !values.contains(datum) {
+                        values.push(datum.clone());
+                    }
+                }
+            }
+            Action::RemoveFact { subject, predicate } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    entity.facts.remove(predicate);
+                    entity.tagged.remove(predicate);
+                    entity.texts.remove(predicate);
+                }
+            }
+            Action::DeleteEntity { id } => {
+                if let Some(time) = time {
+                    let window = self.trash_window();
+                    self.trash
+                        .retain(|_, trashed| trashed.deleted.is_none_or(|d| d >= time - window));
+                }
+                if let Some(entity) = self.entities.remove(id) {
+                    let deleted = time;
+                    self.trash.insert(*id, Trashed { entity, deleted });
+                }
+            }
+            Action::RestoreEntity { id } => {
+                let in_time = |trashed: &Trashed| match (trashed.deleted, time) {
+                    (Some(deleted), Some(time)) => time - deleted <= self.trash_window(),
+                    _ => true,
+                };
+                if self.entities.contains_key(id) || !self.trash.get(id).is_some_and(in_time) {
+                    return;
+                }
+                if let Some(trashed) = self.trash.remove(id) {
+                    self.entities.insert(*id, trashed.entity);
+                }
+            }
+            Action::Transaction { actions } => {
+                for action in actions {
+                    self.apply_at(action, time);
+                }
+            }
+            Action::Chunk {
+                transaction,
+                actions,
+                last,
+            } => {
+                let pending = self.pending.entry(*transaction).or_default();
+                pending.extend(actions.iter().cloned());
+                if *last {
+                    let pending = self.pending.remove(transaction).unwrap_or_default();
+                    for action in chunk::reassemble(pending) {
+                        self.apply_at(&action, time);
+                    }
+                }
+            }
+            Action::AppendString {
+                subject,
+                predicate,
+                text,
+            } => {
+                // Outside of a chunk it continues the latest value.
+                let latest = self
+                    .entities
+                    .get_mut(subject)
+                    .and_then(|entity| entity.facts.get_mut(predicate))
+                    .and_then(|values| values.last_mut());
+                if let Some(Datum::String(value)) = latest {
+                    value.push_str(text);
+                }
+            }
+            Action::Increment {
+                subject,
+                predicate,
+                amount,
+            } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    let values = entity.facts.entry(predicate.clone()).or_default();
+                    let count = match values.first() {
+                        Some(Datum::Integer(count)) => *count,
+                        _ => 0,
+                    };
+                    *values = vec![Datum::Integer(count.saturating_add(*amount))];
+                }
+            }
+            Action::AddElement {
+                subject,
+                predicate,
+                datum,
+                tag,
+            } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    entity.retag(predicate, |elements| elements.push((*tag, datum.clone())));
+                }
+            }
+            Action::RemoveElements {
+                subject,
+                predicate,
+                tags,
+            } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    entity.retag(predicate, |elements| {
+                        elements.retain(|(tag, _)| !tags.contains(tag))
+                    });
+                }
+            }
+            Action::Assign {
+                subject,
+                predicate,
+                datum,
+                tag,
+                replaces,
+            } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    entity.retag(predicate, |elements| {
+                        elements.retain(|(tag, _)| !replaces.contains(tag));
+                        elements.push((*tag, datum.clone()));
+                    });
+                }
+            }
+            Action::InsertText {
+                subject,
+                predicate,
+                after,
+                id,
+                text,
+            } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    entity.edit_text(predicate, |t| t.insert(*after, *id, text));
+                }
+            }
+            Action::DeleteText {
+                subject,
+                predicate,
+                spans,
+            } => {
+                if let Some(entity) = self.entities.get_mut(subject) {
+                    entity.edit_text(predicate, |t| t.delete(spans));
+                }
+            }
+        }
+    }
+
+    pub fn entity(&self, id: &Uuid) -> Option<&Entity> {
+        self.entities.get(id)
+    }
+
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.entities.contains_key(id)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = (&Uuid, &Entity)> {
+        self.entities.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// The deleted entities that can still be restored, as of `now` in unix
+    /// seconds.
+    pub fn trash(&self, now: i64) -> impl Iterator<Item = (&Uuid, &Trashed)> {
+        let window = self.trash_window();
+        self.trash
+            .iter()
+            .filter(move |(_, trashed)| trashed.deleted.is_none_or(|d| now - d <= window))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// The changes that turn this projection into `other`, by entity.
+    pub fn diff(&self, other: &Projection) -> Vec<Change> {
+        let mut ids: Vec<&Uuid> = self.entities.keys().chain(other.entities.keys()).collect();
+        ids.sort();
+        ids.dedup();
+        let empty = Entity::default();
+        let mut changes = Vec::new();
+        for id in ids {
+            let (before, after) = match (self.entity(id), other.entity(id)) {
+                (Some(_), None) => {
+                    changes.push(Change::Deleted(*id));
+                    continue;
+                }
+                (None, Some(after)) => {
+                    changes.push(Change::Created(*id));
+                    (&empty, after)
+                }
+                (Some(before), Some(after)) => (before, after),
+                (None, None) => continue,
+            };
+            for (predicate, values) in before.facts() {
+                for datum in values {
+                    if !after.values(predicate).contains(datum) {
+                        changes.push(Change::Removed {
+                            subject: *id,
+                            predicate: predicate.to_string(),
+                            datum: datum.clone(),
+                        });
+                    }
+                }
+            }
+            for (predicate, values) in after.facts() {
+                for datum in values {
+                    if !before.values(predicate).contains(datum) {
+                        changes.push(Change::Added {
+                            subject: *id,
+                            predicate: predicate.to_string(),
+                            datum: datum.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// A difference between two projections, see [`Projection::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Created(Uuid),
+    Deleted(Uuid),
+    Added {
+        subject: Uuid,
+        predicate: String,
+        datum: Datum,
+    },
+    Removed {
+        subject: Uuid,
+        predicate: String,
+        datum: Datum,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(subject: Uuid, predicate: &str, datum: Datum) -> Action {
+        Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        }
+    }
+
+    #[test]
+    fn applies_actions() {
+        let id = Uuid::new_v4();
+        let mut projection = Projection::new();
+        projection.apply(&Action::Transaction {
+            actions: vec![
+                Action::CreateEntity { id },
+                add(id, "tag", Datum::String("a".into())),
+                add(id, "tag", Datum::String("b".into())),
+                add(id, "tag", Datum::String("a".into())),
+                add(id, "age", Datum::Integer(3)),
+            ],
+        });
+
+        let entity = projection.entity(&id).unwrap();
+        assert_eq!(
+            entity.values("tag"),
+            &[Datum::String("a".into()), Datum::String("b".into())]
+        );
+        assert_eq!(entity.value("age"), Some(&Datum::Integer(3)));
+
+        projection.apply(&Action::RemoveFact {
+            subject: id,
+            predicate: "tag".into(),
+        });
+        assert!(projection.entity(&id).unwrap().values("tag").is_empty());
+
+        projection.apply(&Action::DeleteEntity { id });
+        assert!(projection.is_empty());
+    }
+
+    #[test]
+    fn diffs_projections() {
+        let (kept, gone, new) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut before = Projection::new();
+        for id in [kept, gone] {
+            before.apply(&Action::CreateEntity { id });
+        }
+        before.apply(&add(kept, "tag", Datum::String("a".into())));
+        before.apply(&add(gone, "tag", Datum::String("a".into())));
+
+        let mut after = before.clone();
+        after.apply(&Action::DeleteEntity { id: gone });
+        after.apply(&Action::CreateEntity { id: new });
+        after.apply(&add(new, "age", Datum::Integer(1)));
+        after.apply(&Action::RemoveFact {
+            subject: kept,
+            predicate: "tag".into(),
+        });
+        after.apply(&add(kept, "tag", Datum::String("b".into())));
+
+        let mut changes = before.diff(&after);
+        let mut expected = vec![
+            Change::Removed {
+                subject: kept,
+                predicate: "tag".into(),
+                datum: Datum::String("a".into()),
+            },
+            Change::Added {
+                subject: kept,
+                predicate: "tag".into(),
+                datum: Datum::String("b".into()),
+            },
+            Change::Deleted(gone),
+            Change::Created(new),
+            Change::Added {
+                subject: new,
+                predicate: "age".into(),
+                datum: Datum::Integer(1),
+            },
+        ];
+        let subject = |c: &Change| match c {
+            Change::Created(id) | Change::Deleted(id) => *id,
+            Change::Added { subject, .. } | Change::Removed { subject, .. } => *subject,
+        };
+        // Changes are grouped by entity in id order.
+        changes.sort_by_key(subject);
+        expected.sort_by_key(subject);
+        assert_eq!(changes, expected);
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn ignores_facts_about_unknown_entities() {
+        let mut projection = Projection::new();
+        projection.apply(&add(Uuid::new_v4(), "name", Datum::Boolean(true)));
+        assert!(projection.is_empty());
+    }
+
+    #[test]
+    fn applies_chunked_transactions_once_complete() {
+        let id = Uuid::new_v4();
+        let text = "x".repeat(chunk::TEXT + 1);
+        let mut actions = vec![
+            Action::CreateEntity { id },
+            add(id, "body", Datum::String(text.clone())),
+        ];
+        actions.extend((0..chunk::ACTIONS).map(|_| Action::CreateEntity { id: Uuid::new_v4() }));
+        let chunks = chunk::split(actions);
+
+        let mut projection = Projection::new();
+        projection.apply(&chunks[0]);
+        assert!(projection.is_empty());
+        for action in &chunks[1..] {
+            projection.apply(action);
+        }
+        assert_eq!(projection.len(), chunk::ACTIONS + 1);
+        assert_eq!(
+            projection.entity(&id).unwrap().value("body"),
+            Some(&Datum::String(text))
+        );
+    }
+
+    #[test]
+    fn restores_deleted_entities_within_the_trash_window() {
+        let (kept, late) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut projection = Projection::new();
+        projection.set_trash_window(100);
+        for id in [kept, late] {
+            projection.apply(&Action::CreateEntity { id });
+            projection.apply(&add(id, "name", Datum::String("Ada".into())));
+            projection.apply_at(&Action::DeleteEntity { id }, Some(1000));
+        }
+        assert!(projection.is_empty());
+        assert_eq!(projection.trash(1050).count(), 2);
+
+        projection.apply_at(&Action::RestoreEntity { id: kept }, Some(1100));
+        projection.apply_at(&Action::RestoreEntity { id: late }, Some(1101));
+        assert_eq!(
+            projection.entity(&kept).unwrap().value("name"),
+            Some(&Datum::String("Ada".into()))
+        );
+        assert!(!projection.contains(&late));
+        assert_eq!(projection.trash(1101).count(), 0);
+    }
+}
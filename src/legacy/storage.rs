@@ -1,141 +1,1078 @@
-use crate::hlc;
-use crate::hlc::HLTimestamp;
-use anyhow::{Context, Result};
-use rusqlite::Connection;
+use crate::legacy::codec::Codec;
+use crate::legacy::error::{Context, GraphiteError, Result};
+use crate::legacy::hlc;
+use crate::legacy::hlc::HLTimestamp;
+use crate::legacy::text;
+use crate::legacy::vector_clock::{self, VectorClock};
+use crate::progress::Reporter;
+use metadata::Metadata;
+use rusqlite::types::{ToSqlOutput, Type, Value, ValueRef};
 use rusqlite::Error as RusqliteError;
+use rusqlite::{Connection, DatabaseName, OpenFlags, OptionalExtension, Row};
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{debug, instrument, warn};
 use uuid::Uuid;
 
+pub mod chain;
+pub mod changes;
+pub mod checkpoint;
+pub mod chunk;
+pub mod clock;
+pub mod fuzz;
+pub mod history;
+pub mod maintenance;
+pub mod metadata;
+pub mod profile;
+pub mod stats;
+pub mod verify;
+pub mod writer;
+
+/// The meta key of the latest timestamp recorded, see
+/// [`EventStorage::latest_timestamp`].
+const LAST_HLC: &str = "last_hlc";
+
+/// How long a write waits for those of other connections to finish.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many pages a backup copies at a time, and how long it then lets
+/// writes through.
+const BACKUP_PAGES: std::ffi::c_int = 256;
+const BACKUP_PAUSE: Duration = Duration::from_millis(10);
+
 pub struct EventStorage {
     conn: Connection,
+    /// The codec new events are recorded with.
+    codec: Codec,
+    /// Whether new events record a vector clock.
+    causal: bool,
+    /// Whether events are hash chained, see [`chain`].
+    chained: bool,
+    clock: clock::Guard,
+    /// The write lock, held while the database is open writable.
+    _writer: Option<File>,
+    /// The hook of the slow-query log, removed before it is dropped.
+    profiler: Option<Box<profile::Profiler>>,
+}
+
+impl Drop for EventStorage {
+    fn drop(&mut self) {
+        if self.profiler.is_some() {
+            profile::uninstall(&self.conn);
+        }
+    }
+}
+
+/// How durably SQLite writes to disk, see `PRAGMA synchronous`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+}
+
+/// Connection tuning applied when the storage is opened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageConfig {
+    pub synchronous: Synchronous,
+    /// Page cache size, see `PRAGMA cache_size`. Positive values are pages,
+    /// negative values are KiB.
+    pub cache_size: i64,
+    /// The codec new events are recorded with. Events are always read with
+    /// the codec they were recorded with.
+    pub codec: Codec,
+    /// Open the file without ever writing to it, e.g. to look at someone
+    /// else's database or a backup. Writes fail with
+    /// [`GraphiteError::ReadOnly`].
+    #[serde(default)]
+    pub read_only: bool,
+    /// Record in each new event which events it had seen, so merging tells
+    /// concurrent edits from sequential ones, see
+    /// [`crate::legacy::vector_clock`].
+    #[serde(default)]
+    pub causal: bool,
+    /// Chain the events of each actor by hash, so removing or reordering
+    /// history shows in [`EventStorage::verify`], see [`chain`].
+    #[serde(default)]
+    pub chained: bool,
+    /// What happens to new events while the system clock is behind, see
+    /// [`clock`].
+    #[serde(default)]
+    pub clock: clock::Guard,
+    /// Log the statements slower than a threshold, see [`profile`].
+    #[serde(default)]
+    pub slow_queries: Option<profile::SlowLog>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            synchronous: Synchronous::Normal,
+            cache_size: -16 * 1024,
+            codec: Codec::Json,
+            read_only: false,
+            causal: false,
+            chained: false,
+            clock: clock::Guard::default(),
+            slow_queries: None,
+        }
+    }
 }
 
 impl EventStorage {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<EventStorage> {
-        let conn = Connection::open(path).context("Failed to open database")?;
-        let storage = EventStorage { conn };
-        storage.init()?;
+        EventStorage::open_with(path, &StorageConfig::default())
+    }
+
+    #[instrument(skip_all, fields(path = %path.as_ref().display()))]
+    pub fn open_with<P: AsRef<Path>>(path: P, config: &StorageConfig) -> Result<EventStorage> {
+        let path = path.as_ref();
+        let writer = match config.read_only {
+            true => None,
+            false => writer::acquire(path)?,
+        };
+        let conn = if config.read_only {
+            Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+        } else {
+            Connection::open(path)
+        }
+        .context("Failed to open database")?;
+        // Waits for the writes of other connections, e.g. a read-only
+        // instance reading while this one writes.
+        conn.busy_timeout(BUSY_TIMEOUT)
+            .context("Failed to set the busy timeout")?;
+        // The database opens without the log if it can't be written.
+        let profiler = config.slow_queries.as_ref().and_then(|log| {
+            profile::install(&conn, log)
+                .inspect_err(|e| warn!("{}", e))
+                .ok()
+        });
+        let storage = EventStorage {
+            conn,
+            codec: config.codec,
+            causal: config.causal,
+            chained: config.chained,
+            clock: config.clock,
+            _writer: writer,
+            profiler,
+        };
+        storage.configure(config)?;
+        if config.read_only {
+            storage.check_schema()?;
+        } else {
+            storage.init()?;
+        }
+        if let Some(behind) = storage.clock_skew()? {
+            warn!(
+                behind,
+                "The system clock is behind the latest event, new events count up from it"
+            );
+        }
+        debug!(codec = %config.codec, read_only = config.read_only, "Opened the database");
         Ok(storage)
     }
 
+    /// Whether the database was opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.conn.is_readonly(DatabaseName::Main).unwrap_or(false)
+    }
+
+    /// Fails with [`GraphiteError::ReadOnly`] if the database was opened
+    /// read-only, before a write is attempted.
+    fn writable(&self) -> Result<()> {
+        if self.is_read_only() {
+            return Err(GraphiteError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Checks that a database opened read-only has the tables and columns
+    /// [`Self::init`] would create, since it can't create them.
+    fn check_schema(&self) -> Result<()> {
+        let mut complete = self
+            .conn
+            .prepare(
+                "SELECT COUNT(*) = 3 FROM pragma_table_info('events')
+                WHERE name IN ('codec', 'metadata', 'chain')",
+            )?
+            .query_row([], |row| row.get(0))
+            .context("Failed to inspect the events table")?;
+        for table in ["meta", "event_subjects", "checkpoints", "changes"] {
+            complete = complete && self.has_table(table)?;
+        }
+        if !complete {
+            return Err(GraphiteError::Invalid(
+                "The database lacks tables of this version, open it writable once to upgrade it"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn configure(&self, config: &StorageConfig) -> Result<()> {
+        let synchronous = match config.synchronous {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+        };
+        self.conn
+            .pragma_update(None, "synchronous", synchronous)
+            .context("Failed to set synchronous")?;
+        self.conn
+            .pragma_update(None, "cache_size", config.cache_size)
+            .context("Failed to set cache_size")?;
+        Ok(())
+    }
+
     fn init(&self) -> Result<()> {
         self.conn
             .execute(
                 "CREATE TABLE IF NOT EXISTS events (
-                id BLOB PRIMARY KEY, // UUID as BLOB
-                hlc_seconds INTEGER NOT NULL, // 8 Bytes
-                hlc_logical INTEGER NOT NULL, // 2 Bytes
-                action TEXT NOT NULL, // JSON
-                actor BLOB NOT NULL, // UUID as BLOB
-                version INTEGER NOT NULL
+                id BLOB PRIMARY KEY, -- UUID as BLOB
+                hlc_seconds INTEGER NOT NULL, -- 8 Bytes
+                hlc_logical INTEGER NOT NULL, -- 2 Bytes
+                action BLOB NOT NULL, -- TEXT for JSON, BLOB for binary codecs
+                actor BLOB NOT NULL, -- UUID as BLOB
+                version INTEGER NOT NULL,
+                subject BLOB, -- UUID as BLOB, see Action::subject
+                predicate TEXT, -- see Action::predicate
+                codec INTEGER NOT NULL DEFAULT 0 -- see Codec::id
             )",
                 [],
             )
             .context("Failed to Create events table")?;
+        self.conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value BLOB NOT NULL
+            )",
+                [],
+            )
+            .context("Failed to Create meta table")?;
+        let has_links = self.has_table("event_subjects")?;
+        self.conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS event_subjects (
+                event BLOB NOT NULL, -- UUID as BLOB
+                subject BLOB NOT NULL, -- UUID as BLOB
+                predicate TEXT, -- NULL when the whole entity is created or deleted
+                PRIMARY KEY (subject, event, predicate)
+            )",
+                [],
+            )
+            .context("Failed to Create event_subjects table")?;
+        self.init_checkpoints()?;
+        self.init_changes()?;
+        // Older databases lack these columns.
+        let added_subject = self.add_column("subject", "BLOB")?;
+        let added_predicate = self.add_column("predicate", "TEXT")?;
+        self.add_column("codec", "INTEGER NOT NULL DEFAULT 0")?;
+        self.add_column("metadata", "TEXT")?;
+        self.add_column("chain", "BLOB")?;
+        if added_subject || added_predicate || !has_links {
+            self.backfill_subjects()?;
+        }
+        self.conn
+            .execute("DROP INDEX IF EXISTS events_hlc", [])
+            .context("Failed to drop an old index")?;
+        Self::create_indexes(&self.conn)?;
+        if self.chained {
+            // Events recorded while chaining was off join the chain.
+            self.seal_chain(false)?;
+        }
+        Ok(())
+    }
+
+    fn has_table(&self, name: &str) -> Result<bool> {
+        self.conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")?
+            .exists([name])
+            .context("Failed to inspect the schema")
+    }
+
+    /// Adds `column` to the events table unless it exists. Returns whether it
+    /// was added.
+    fn add_column(&self, column: &str, definition: &str) -> Result<bool> {
+        let exists = self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info('events') WHERE name = ?")?
+            .exists([column])
+            .context("Failed to inspect the events table")?;
+        if !exists {
+            self.conn
+                .execute(
+                    &format!("ALTER TABLE events ADD COLUMN {} {}", column, definition),
+                    [],
+                )
+                .with_context(|| format!("Failed to add the {} column", column))?;
+        }
+        Ok(!exists)
+    }
+
+    /// Fills in the subject columns and links of every recorded event.
+    fn backfill_subjects(&self) -> Result<()> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .context("Failed to open a transaction")?;
+        {
+            let mut select = tx.prepare("SELECT id, action, codec FROM events")?;
+            let mut update =
+                tx.prepare("UPDATE events SET subject = ?, predicate = ? WHERE id = ?")?;
+            let rows = select.query_map([], |row| Ok((row.get::<_, Uuid>(0)?, action(row, 1)?)))?;
+            for row in rows {
+                let (id, action) = row.context("Failed to read an event")?;
+                update
+                    .execute(rusqlite::params![action.subject(), action.predicate(), id])
+                    .context("Failed to fill in the subject of an event")?;
+                Self::insert_links(&tx, id, &action)?;
+            }
+        }
+        tx.commit()
+            .context("Failed to commit the subject backfill")?;
+        Ok(())
+    }
+
+    /// The value kept under `key` in the database, e.g. how far it was
+    /// synced with a relay.
+    pub fn meta_value(&self, key: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .context("Failed to read from the meta table")
+    }
+
+    pub fn set_meta_value(&self, key: &str, value: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)",
+                [key, value],
+            )
+            .context("Failed to write to the meta table")?;
         Ok(())
     }
 
+    /// The actor that events created on this database are recorded as.
+    ///
+    /// The id is generated the first time it is asked for and kept in the
+    /// database afterwards.
+    pub fn local_actor(&self) -> Result<Uuid> {
+        if self.is_read_only() {
+            // Nothing is recorded in a read-only database, so one that never
+            // had an actor doesn't need one.
+            let actor = self
+                .conn
+                .query_row(
+                    "SELECT value FROM meta WHERE key = 'local_actor'",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()
+                .context("Failed to read the local actor")?;
+            return Ok(actor.unwrap_or(Uuid::nil()));
+        }
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO meta (key, value) VALUES ('local_actor', ?)",
+                [Uuid::new_v4()],
+            )
+            .context("Failed to store the local actor")?;
+        self.conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'local_actor'",
+                [],
+                |row| row.get(0),
+            )
+            .context("Failed to read the local actor")
+    }
+
+    /// The timestamp of the latest event, or zero if there are none.
+    pub fn latest_hlc(&self) -> Result<HLTimestamp> {
+        let latest = self
+            .conn
+            .query_row(
+                "SELECT hlc_seconds, hlc_logical FROM events
+                ORDER BY hlc_seconds DESC, hlc_logical DESC LIMIT 1",
+                [],
+                |row| Ok(HLTimestamp::new(row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .context("Failed to read the latest timestamp")?;
+        Ok(latest.unwrap_or(HLTimestamp::new(0, 0)))
+    }
+
+    /// The latest timestamp ever recorded in the database, or zero if none
+    /// was.
+    ///
+    /// Unlike [`Self::latest_hlc`], this is kept in the `meta` table as
+    /// events are recorded, so it doesn't go back when events are removed,
+    /// e.g. quarantined, and new events still sort after them.
+    pub fn latest_timestamp(&self) -> Result<HLTimestamp> {
+        let kept = match self.meta_value(LAST_HLC)? {
+            Some(value) => value.parse().map_err(|e| {
+                GraphiteError::Invalid(format!("Invalid {} {}: {}", LAST_HLC, value, e))
+            })?,
+            None => HLTimestamp::new(0, 0),
+        };
+        Ok(kept.max(self.latest_hlc()?))
+    }
+
+    /// Keeps `hlc` as the latest timestamp recorded, unless a later one is.
+    fn advance_clock(conn: &Connection, hlc: HLTimestamp) -> Result<()> {
+        let kept: Option<String> = conn
+            .query_row("SELECT value FROM meta WHERE key = ?", [LAST_HLC], |row| {
+                row.get(0)
+            })
+            .optional()
+            .context("Failed to read the latest timestamp")?;
+        if kept.and_then(|kept| kept.parse::<HLTimestamp>().ok()) < Some(hlc) {
+            conn.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?, ?)",
+                [LAST_HLC, &hlc.to_string()],
+            )
+            .context("Failed to keep the latest timestamp")?;
+        }
+        Ok(())
+    }
+
+    /// Returns an `EventCreator` for the local actor that continues from the
+    /// latest timestamp recorded, with the metadata of this device and, if
+    /// causal metadata is on, the clock of every event recorded.
+    pub fn creator(&self) -> Result<EventCreator> {
+        let mut creator = EventCreator::new(self.local_actor()?, self.latest_timestamp()?);
+        creator.metadata = metadata::local();
+        if self.causal {
+            creator.clock = Some(VectorClock::default());
+            self.play(|event| {
+                creator.observe(&event);
+                Ok(())
+            })?;
+        }
+        Ok(creator)
+    }
+
+    #[instrument(skip_all)]
     pub fn play(&self, f: impl FnMut(Event) -> Result<()>) -> Result<()> {
         let mut stmt = self
             .conn
-            .prepare("SELECT * FROM events ORDER BY hlc_seconds, hlc_logical")
+            .prepare_cached(&format!(
+                "SELECT {} FROM events ORDER BY {}",
+                EVENT_COLUMNS, EVENT_ORDER
+            ))
             .context("Failed to prepare SQL statement to play all events")?;
 
-        Self::play_internal(&mut stmt, f)
+        Self::play_internal(&mut stmt, [], f)
     }
 
+    /// Plays every event like [`EventStorage::play`], advancing `reporter`
+    /// by one per event and stopping once it was cancelled.
+    pub fn play_with_progress(
+        &self,
+        reporter: &Reporter,
+        mut f: impl FnMut(Event) -> Result<()>,
+    ) -> Result<()> {
+        reporter.set_total(self.count()?);
+        self.play(|event| {
+            reporter.check()?;
+            f(event)?;
+            reporter.advance(1);
+            Ok(())
+        })
+    }
+
+    /// The number of recorded events.
+    pub fn count(&self) -> Result<u64> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))
+            .context("Failed to count events")
+    }
+
+    /// Plays the events at or after `hlc`.
+    #[instrument(skip(self, f))]
     pub fn play_from(&self, hlc: HLTimestamp, f: impl FnMut(Event) -> Result<()>) -> Result<()> {
-        let query = format!("SELECT * FROM events WHERE hlc_seconds >= {} AND hlc_logical >= {} ORDER BY hlc_seconds, hlc_logical", hlc.seconds(), hlc.logical());
         let mut stmt = self
             .conn
-            .prepare(&query)
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE (hlc_seconds, hlc_logical) >= (?, ?)
+                ORDER BY {}",
+                EVENT_COLUMNS, EVENT_ORDER
+            ))
             .context("Failed to prepare SQL statement to play subset of events")?;
-        Self::play_internal(&mut stmt, f)
+        Self::play_internal(
+            &mut stmt,
+            rusqlite::params![hlc.seconds(), hlc.logical()],
+            f,
+        )
+    }
+
+    /// Plays the events that touch `entity`, including through transactions,
+    /// oldest first.
+    #[instrument(skip(self, f))]
+    pub fn play_for_entity(&self, entity: Uuid, f: impl FnMut(Event) -> Result<()>) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE id IN (
+                    SELECT event FROM event_subjects WHERE subject = ?
+                ) ORDER BY {}",
+                EVENT_COLUMNS, EVENT_ORDER
+            ))
+            .context("Failed to prepare SQL statement to play the events of an entity")?;
+        Self::play_internal(&mut stmt, rusqlite::params![entity], f)
+    }
+
+    /// Returns at most `limit` events following `after`, or from the first
+    /// event if it is `None`, together with the cursor for the next page.
+    #[instrument(skip(self))]
+    pub fn play_page(&self, after: Option<Cursor>, limit: usize) -> Result<Page> {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let mut events = Vec::new();
+        let collect = |event| {
+            events.push(event);
+            Ok(())
+        };
+        match after {
+            Some(cursor) => {
+                let mut stmt = self
+                    .conn
+                    .prepare_cached(&format!(
+                        "SELECT {} FROM events WHERE (hlc_seconds, hlc_logical, id) > (?, ?, ?)
+                        ORDER BY {} LIMIT ?",
+                        EVENT_COLUMNS, EVENT_ORDER
+                    ))
+                    .context("Failed to prepare SQL statement to play a page of events")?;
+                let params =
+                    rusqlite::params![cursor.hlc.seconds(), cursor.hlc.logical(), cursor.id, limit];
+                Self::play_internal(&mut stmt, params, collect)?;
+            }
+            None => {
+                let mut stmt = self
+                    .conn
+                    .prepare_cached(&format!(
+                        "SELECT {} FROM events ORDER BY {} LIMIT ?",
+                        EVENT_COLUMNS, EVENT_ORDER
+                    ))
+                    .context("Failed to prepare SQL statement to play a page of events")?;
+                Self::play_internal(&mut stmt, [limit], collect)?;
+            }
+        }
+        let next = match events.last() {
+            Some(last) if events.len() as i64 == limit => Some(Cursor::after(last)),
+            _ => None,
+        };
+        Ok(Page { events, next })
     }
 
     fn play_internal(
         stmt: &mut rusqlite::Statement,
+        params: impl rusqlite::Params,
         mut f: impl FnMut(Event) -> Result<()>,
     ) -> Result<()> {
         let rows = stmt
-            .query_map([], |row| {
+            .query_map(params, |row| {
                 let id: Uuid = row.get(0)?;
                 let hlc_seconds: i64 = row.get(1)?;
                 let hlc_logical: u16 = row.get(2)?;
-                let action_string: String = row.get(3)?;
-                let action: Action = serde_json::from_str(&action_string)
-                    .map_err(|e| RusqliteError::ToSqlConversionFailure(Box::new(e)))?;
-                let actor: Uuid = row.get(4)?;
-                let version: u32 = row.get(5)?;
-
-                Ok(Event {
-                    id,
-                    hlc: HLTimestamp::new(hlc_seconds, hlc_logical),
-                    action,
-                    actor,
-                    version,
-                })
+                let actor: Uuid = row.get(5)?;
+                let version: u32 = row.get(6)?;
+                // An action that can't be decoded is reported with its event
+                // rather than failing the whole query.
+                let decoded =
+                    action(row, 3).and_then(|action| Ok((action, metadata::column(row, 7)?)));
+                let event = match decoded {
+                    Ok((action, metadata)) => Ok(Event {
+                        id,
+                        hlc: HLTimestamp::new(hlc_seconds, hlc_logical),
+                        action,
+                        actor,
+                        version,
+                        metadata,
+                    }),
+                    Err(source) => {
+                        tracing::error!(event = %id, "Failed to decode an event: {}", source);
+                        Err(GraphiteError::Corrupt {
+                            event_id: id,
+                            source,
+                        })
+                    }
+                };
+                Ok(event)
             })
             .context("Failed to play events")?;
 
         for event in rows {
-            let e = event.context("Failed to get event")?;
-            f(e).context("Failed to execute on event")?;
+            let event = event.context("Failed to get event")??;
+            if event.version > EVENT_VERSION {
+                return Err(GraphiteError::VersionUnsupported {
+                    event_id: event.id,
+                    version: event.version,
+                });
+            }
+            f(event)?;
         }
 
         Ok(())
     }
 
+    #[instrument(skip_all, fields(event = %envelope.id))]
     pub fn record(&self, envelope: Event) -> Result<()> {
-        let action =
-            serde_json::to_string(&envelope.action).context("Failed to serialize to JSON")?;
+        self.writable()?;
+        self.check_clock()?;
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .context("Failed to open a transaction")?;
+        Self::insert(&tx, self.codec, self.chained, &envelope, &mut Vec::new())?;
+        Self::advance_clock(&tx, envelope.hlc)?;
+        tx.commit().context("Failed to commit an event")?;
+        Ok(())
+    }
 
+    #[instrument(skip_all, fields(events = envelopes.len()))]
+    pub fn record_batch(&mut self, envelopes: Vec<Event>) -> Result<()> {
+        self.writable()?;
+        self.check_clock()?;
+        let (codec, chained) = (self.codec, self.chained);
+        let tx = self
+            .conn
+            .transaction()
+            .context("Failed to open a transaction")?;
+        let bulk = Self::is_bulk_load(&tx, envelopes.len())?;
+        if bulk {
+            Self::drop_indexes(&tx)?;
+        }
+        let mut heads = HashMap::new();
+        let mut buffer = Vec::new();
+        for rows in envelopes.chunks(ROWS_PER_INSERT) {
+            Self::insert_rows(&tx, codec, chained, rows, &mut heads, &mut buffer)?;
+        }
+        let mut links: Vec<_> = envelopes
+            .iter()
+            .flat_map(|envelope| {
+                envelope
+                    .action
+                    .touches()
+                    .into_iter()
+                    .map(|(subject, predicate)| (subject, envelope.id, predicate))
+            })
+            .collect();
+        // In key order the rows append to the table rather than land all over it.
+        links.sort_unstable();
+        for rows in links.chunks(ROWS_PER_INSERT) {
+            Self::insert_link_rows(&tx, rows)?;
+        }
+        if bulk {
+            Self::create_indexes(&tx)?;
+        }
+        if let Some(latest) = envelopes.iter().map(|e| e.hlc).max() {
+            Self::advance_clock(&tx, latest)?;
+        }
+        tx.commit().context("Failed to commit batch of events")?;
+        Ok(())
+    }
+
+    /// Records the events that are not recorded yet, e.g. those of a backup,
+    /// and returns how many were.
+    #[instrument(skip_all, fields(events = envelopes.len()))]
+    pub fn merge(&mut self, envelopes: Vec<Event>) -> Result<usize> {
+        self.writable()?;
+        let (codec, chained) = (self.codec, self.chained);
+        let tx = self
+            .conn
+            .transaction()
+            .context("Failed to open a transaction")?;
+        let mut merged = 0;
+        let mut latest = None;
+        let mut buffer = Vec::new();
+        for envelope in &envelopes {
+            let recorded = tx
+                .prepare_cached("SELECT 1 FROM events WHERE id = ?")
+                .context("Failed to prepare SQL statement to find an event")?
+                .exists([envelope.id])
+                .context("Failed to find an event")?;
+            if !recorded {
+                Self::insert(&tx, codec, chained, envelope, &mut buffer)?;
+                merged += 1;
+                latest = latest.max(Some(envelope.hlc));
+            }
+        }
+        if let Some(latest) = latest {
+            Self::advance_clock(&tx, latest)?;
+        }
+        if merged > 0 {
+            stats::synced(&tx)?;
+        }
+        tx.commit().context("Failed to commit the merged events")?;
+        debug!(merged, "Merged events");
+        Ok(merged)
+    }
+
+    /// Writes a consistent copy of the database to a new file at `path`.
+    #[instrument(skip(self))]
+    pub fn copy_to(&self, path: &Path) -> Result<()> {
+        let path = path.to_str().ok_or_else(|| {
+            GraphiteError::Invalid(format!("{} is not valid UTF-8", path.display()))
+        })?;
         self.conn
-            .execute(
-                "INSERT INTO events (id, hlc_seconds, hlc_logical, action, actor, version)
-      VALUES (?, ?, ?, ?, ?, ?)",
-                rusqlite::params![
-                    envelope.id,
-                    envelope.hlc.seconds(),
-                    envelope.hlc.logical(),
-                    action,
-                    envelope.actor,
-                    envelope.version,
-                ],
-            )
-            .context("Failed to insert an event")?;
+            .execute("VACUUM INTO ?", [path])
+            .with_context(|| format!("Failed to copy the database to {}", path))?;
+        Ok(())
+    }
 
+    /// Copies the database to `path` with SQLite's online backup, replacing
+    /// what is there. The copy proceeds a few pages at a time, so writes to
+    /// the database aren't held up while it runs.
+    #[instrument(skip(self))]
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        let mut copy =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        rusqlite::backup::Backup::new(&self.conn, &mut copy)
+            .and_then(|backup| backup.run_to_completion(BACKUP_PAGES, BACKUP_PAUSE, None))
+            .with_context(|| format!("Failed to back the database up to {}", path.display()))?;
         Ok(())
     }
 
-    pub fn record_batch(&mut self, envelopes: Vec<Event>) -> Result<()> {
+    /// Re-encodes every event not yet encoded with `codec`, and records new
+    /// events with it. Returns the number of events re-encoded.
+    ///
+    /// Nothing is re-encoded if `reporter` is cancelled before the end. The
+    /// space freed is only returned to the file system by a `VACUUM`.
+    #[instrument(skip(self, reporter), fields(codec = %codec))]
+    pub fn migrate_codec(&mut self, codec: Codec, reporter: &Reporter) -> Result<usize> {
+        self.writable()?;
         let tx = self
             .conn
             .transaction()
             .context("Failed to open a transaction")?;
+        let mut migrated = 0;
+        {
+            let total: u64 = tx
+                .query_row(
+                    "SELECT COUNT(*) FROM events WHERE codec != ?",
+                    [codec.id()],
+                    |row| row.get(0),
+                )
+                .context("Failed to count events")?;
+            reporter.set_total(total);
+            let mut select = tx.prepare("SELECT id, action, codec FROM events WHERE codec != ?")?;
+            let mut update = tx.prepare("UPDATE events SET action = ?, codec = ? WHERE id = ?")?;
+            let rows = select.query_map([codec.id()], |row| {
+                Ok((row.get::<_, Uuid>(0)?, action(row, 1)?))
+            })?;
+            let mut buffer = Vec::new();
+            for row in rows {
+                reporter.check()?;
+                let (id, action) = row.context("Failed to read an event")?;
+                buffer.clear();
+                codec.encode(&action, &mut buffer)?;
+                update
+                    .execute(rusqlite::params![encoded(codec, &buffer), codec.id(), id])
+                    .context("Failed to re-encode an event")?;
+                migrated += 1;
+                reporter.advance(1);
+            }
+        }
+        tx.commit()
+            .context("Failed to commit the re-encoded events")?;
+        self.codec = codec;
+        debug!(migrated, "Re-encoded events");
+        Ok(migrated)
+    }
+
+    /// Inserts one event, encoding its action into `buffer` so that batches
+    /// can reuse the allocation.
+    fn insert(
+        conn: &Connection,
+        codec: Codec,
+        chained: bool,
+        envelope: &Event,
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        buffer.clear();
+        codec.encode(&envelope.action, buffer)?;
+        let link = match chained {
+            true => Some(chain::link(
+                chain::head(conn, envelope.actor)?.as_deref(),
+                envelope,
+            )),
+            false => None,
+        };
+
+        conn.prepare_cached(INSERT_EVENT)
+            .context("Failed to prepare SQL statement to insert an event")?
+            .execute(rusqlite::params![
+                envelope.id,
+                envelope.hlc.seconds(),
+                envelope.hlc.logical(),
+                encoded(codec, buffer),
+                envelope.actor,
+                envelope.version,
+                envelope.action.subject(),
+                envelope.action.predicate(),
+                codec.id(),
+                metadata::to_sql(&envelope.metadata),
+                link,
+            ])
+            .context("Failed to insert an event")?;
+        Self::insert_links(conn, envelope.id, &envelope.action)
+    }
+
+    /// Records every entity and predicate `action` touches against `event`.
+    fn insert_links(conn: &Connection, event: Uuid, action: &Action) -> Result<()> {
+        let mut stmt = conn
+            .prepare_cached(
+                "INSERT OR IGNORE INTO event_subjects (event, subject, predicate) VALUES (?, ?, ?)",
+            )
+            .context("Failed to prepare SQL statement to link an event")?;
+        for (subject, predicate) in action.touches() {
+            stmt.execute(rusqlite::params![event, subject, predicate])
+                .context("Failed to link an event to its subject")?;
+        }
+        Ok(())
+    }
+
+    /// Inserts `envelopes` with one statement, chaining each to the head of
+    /// its actor in `heads`, which is looked up the first time and carried
+    /// forward from there.
+    fn insert_rows(
+        conn: &Connection,
+        codec: Codec,
+        chained: bool,
+        envelopes: &[Event],
+        heads: &mut HashMap<Uuid, Option<Vec<u8>>>,
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        buffer.clear();
+        let mut ends = Vec::with_capacity(envelopes.len());
         for envelope in envelopes {
-            let action =
-                serde_json::to_string(&envelope.action).context("Failed to serialize to JSON")?;
-            tx.execute(
-                "INSERT INTO events (id, hlc_seconds, hlc_logical, action, actor, version)
-              VALUES (?, ?, ?, ?, ?, ?)",
-                rusqlite::params![
-                    envelope.id,
-                    envelope.hlc.seconds(),
-                    envelope.hlc.logical(),
-                    action,
-                    envelope.actor,
-                    envelope.version,
-                ],
+            codec.encode(&envelope.action, buffer)?;
+            ends.push(buffer.len());
+        }
+        let mut values: Vec<ToSqlOutput> = Vec::with_capacity(envelopes.len() * EVENT_FIELDS);
+        let mut start = 0;
+        for (envelope, end) in envelopes.iter().zip(ends) {
+            let link = match chained {
+                true => {
+                    let head = match heads.entry(envelope.actor) {
+                        Entry::Occupied(head) => head.into_mut(),
+                        Entry::Vacant(head) => head.insert(chain::head(conn, envelope.actor)?),
+                    };
+                    let link = chain::link(head.as_deref(), envelope);
+                    *head = Some(link.clone());
+                    Some(link)
+                }
+                false => None,
+            };
+            values.extend([
+                ToSqlOutput::from(envelope.id),
+                envelope.hlc.seconds().into(),
+                envelope.hlc.logical().into(),
+                encoded(codec, &buffer[start..end]),
+                envelope.actor.into(),
+                envelope.version.into(),
+                nullable(envelope.action.subject()),
+                nullable(envelope.action.predicate()),
+                codec.id().into(),
+                nullable(metadata::to_sql(&envelope.metadata)),
+                nullable(link),
+            ]);
+            start = end;
+        }
+        conn.prepare_cached(&format!(
+            "{} {}",
+            INSERT_EVENTS,
+            placeholders(envelopes.len(), EVENT_FIELDS)
+        ))
+        .context("Failed to prepare SQL statement to insert events")?
+        .execute(rusqlite::params_from_iter(values))
+        .context("Failed to insert events")?;
+        Ok(())
+    }
+
+    /// Links events to the entities and predicates they touch with one
+    /// statement, see [`Self::insert_links`].
+    fn insert_link_rows(conn: &Connection, links: &[(Uuid, Uuid, Option<&str>)]) -> Result<()> {
+        let values = links.iter().flat_map(|(subject, event, predicate)| {
+            [
+                ToSqlOutput::from(*subject),
+                (*event).into(),
+                nullable(*predicate),
+            ]
+        });
+        conn.prepare_cached(&format!(
+            "INSERT OR IGNORE INTO event_subjects (subject, event, predicate) VALUES {}",
+            placeholders(links.len(), 3)
+        ))
+        .context("Failed to prepare SQL statement to link events")?
+        .execute(rusqlite::params_from_iter(values))
+        .context("Failed to link events to their subjects")?;
+        Ok(())
+    }
+
+    /// Whether a batch of `size` events is a bulk load: large, and larger
+    /// than the log it is recorded into, so building the indexes again once
+    /// it is in costs less than updating them for every event.
+    fn is_bulk_load(conn: &Connection, size: usize) -> Result<bool> {
+        if size < BULK_LOAD {
+            return Ok(false);
+        }
+        // Events are rarely deleted, so the last rowid is about their number.
+        let recorded: i64 = conn
+            .query_row("SELECT COALESCE(MAX(rowid), 0) FROM events", [], |row| {
+                row.get(0)
+            })
+            .context("Failed to count events")?;
+        Ok((recorded as usize) < size)
+    }
+
+    fn create_indexes(conn: &Connection) -> Result<()> {
+        for (name, on) in INDEXES {
+            conn.execute(
+                &format!("CREATE INDEX IF NOT EXISTS {} ON {}", name, on),
+                [],
             )
-            .context("Failed to insert an event")?;
+            .with_context(|| format!("Failed to create the {} index", name))?;
+        }
+        Ok(())
+    }
+
+    fn drop_indexes(conn: &Connection) -> Result<()> {
+        for (name, _) in INDEXES {
+            conn.execute(&format!("DROP INDEX IF EXISTS {}", name), [])
+                .with_context(|| format!("Failed to drop the {} index", name))?;
         }
-        tx.commit().context("Failed to commit batch of events")?;
         Ok(())
     }
 }
 
+/// The newest event version this build can read.
+pub const EVENT_VERSION: u32 = 2;
+
+const EVENT_COLUMNS: &str = "id, hlc_seconds, hlc_logical, action, codec, actor, version, metadata";
+
+/// Events are played in timestamp order. The id breaks ties between actors
+/// so that the order, and with it pagination, is stable.
+const EVENT_ORDER: &str = "hlc_seconds, hlc_logical, id";
+
+const INSERT_EVENT: &str =
+    "INSERT INTO events (id, hlc_seconds, hlc_logical, action, actor, version, subject, predicate, codec, metadata, chain)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+
+const INSERT_EVENTS: &str =
+    "INSERT INTO events (id, hlc_seconds, hlc_logical, action, actor, version, subject, predicate, codec, metadata, chain)
+    VALUES";
+
+/// The columns [`INSERT_EVENTS`] sets for each event.
+const EVENT_FIELDS: usize = 11;
+
+/// How many rows a batch inserts with one statement. Binding them all at
+/// once saves running a statement per row, within SQLite's limit of 32766
+/// parameters.
+const ROWS_PER_INSERT: usize = 64;
+
+/// The smallest batch that can be a bulk load, see
+/// [`EventStorage::is_bulk_load`].
+const BULK_LOAD: usize = 10_000;
+
+/// The secondary indexes on the log and what they index.
+const INDEXES: [(&str, &str); 5] = [
+    ("events_order", "events (hlc_seconds, hlc_logical, id)"),
+    ("events_actor", "events (actor)"),
+    (
+        "events_subject",
+        "events (subject, hlc_seconds, hlc_logical)",
+    ),
+    ("events_predicate", "events (predicate)"),
+    ("event_subjects_event", "event_subjects (event)"),
+];
+
+/// Binds `value`, or NULL without one.
+fn nullable<'a>(value: Option<impl Into<ToSqlOutput<'a>>>) -> ToSqlOutput<'a> {
+    value.map_or(ToSqlOutput::Owned(Value::Null), Into::into)
+}
+
+/// `rows` groups of `columns` parameters, for a multi-row `VALUES`.
+fn placeholders(rows: usize, columns: usize) -> String {
+    let row = format!("({})", vec!["?"; columns].join(", "));
+    vec![row; rows].join(", ")
+}
+
+/// Decodes the action in column `index` with the codec in the column after it.
+fn action(row: &Row, index: usize) -> rusqlite::Result<Action> {
+    let codec = Codec::from_id(row.get(index + 1)?)
+        .map_err(|e| RusqliteError::FromSqlConversionFailure(index + 1, Type::Integer, e.into()))?;
+    codec
+        .decode(row.get_ref(index)?.as_bytes()?)
+        .map_err(|e| RusqliteError::FromSqlConversionFailure(index, Type::Blob, e.into()))
+}
+
+/// Binds an encoded action as text for text codecs so the log stays readable.
+fn encoded(codec: Codec, bytes: &[u8]) -> ToSqlOutput<'_> {
+    ToSqlOutput::Borrowed(if codec.is_text() {
+        ValueRef::Text(bytes)
+    } else {
+        ValueRef::Blob(bytes)
+    })
+}
+
+/// A position in the event log, just after the event it was taken from.
+///
+/// The string form, `<seconds>+<logical>/<id>`, can be handed to tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    hlc: HLTimestamp,
+    id: Uuid,
+}
+
+impl Cursor {
+    pub fn after(event: &Event) -> Cursor {
+        Cursor {
+            hlc: event.hlc,
+            id: event.id,
+        }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.hlc, self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = GraphiteError;
+
+    fn from_str(s: &str) -> Result<Cursor> {
+        let invalid = || GraphiteError::Invalid(format!("Invalid cursor {}", s));
+        let (hlc, id) = s.split_once('/').ok_or_else(invalid)?;
+        Ok(Cursor {
+            hlc: hlc.parse().map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub events: Vec<Event>,
+    /// Where the next page starts, or `None` if this is the last page.
+    pub next: Option<Cursor>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Datum {
     String(String),
@@ -163,40 +1100,943 @@ pub enum Action {
     DeleteEntity {
         id: Uuid,
     },
+    /// Brings a deleted entity back from the trash, see
+    /// [`crate::legacy::projection::Projection`]. Needs event version 1.
+    RestoreEntity {
+        id: Uuid,
+    },
     Transaction {
         actions: Vec<Action>,
     },
+    /// A part of a transaction too large for one event, see [`chunk`].
+    Chunk {
+        transaction: Uuid,
+        actions: Vec<Action>,
+        /// Whether this is the last part, which completes the transaction.
+        last: bool,
+    },
+    /// More of the string added by the `AddFact` before it in a chunked
+    /// transaction, see [`chunk`].
+    AppendString {
+        subject: Uuid,
+        predicate: String,
+        text: String,
+    },
+    /// Adds `amount` to a counter, see [`crate::crdt`]. Needs event version 2.
+    Increment {
+        subject: Uuid,
+        predicate: String,
+        amount: i64,
+    },
+    /// Adds `datum` to a set under a `tag` of its own, see [`crate::crdt`].
+    /// Needs event version 2.
+    AddElement {
+        subject: Uuid,
+        predicate: String,
+        datum: Datum,
+        tag: Uuid,
+    },
+    /// Removes the elements added under `tags` from a set, those seen when
+    /// removing, so that elements added elsewhere meanwhile stay. Needs
+    /// event version 2.
+    RemoveElements {
+        subject: Uuid,
+        predicate: String,
+        tags: Vec<Uuid>,
+    },
+    /// Writes `datum` to a register under a `tag` of its own, replacing the
+    /// values written under `replaces`, see [`crate::crdt`]. Needs event
+    /// version 2.
+    Assign {
+        subject: Uuid,
+        predicate: String,
+        datum: Datum,
+        tag: Uuid,
+        replaces: Vec<Uuid>,
+    },
+    /// Inserts `text` into the text of a predicate after the character
+    /// `after`, or at its start, numbering its characters from `id`, see
+    /// [`text`]. Needs event version 2.
+    InsertText {
+        subject: Uuid,
+        predicate: String,
+        after: Option<text::CharId>,
+        id: text::CharId,
+        text: String,
+    },
+    /// Deletes the characters in `spans` from the text of a predicate, see
+    /// [`text`]. Needs event version 2.
+    DeleteText {
+        subject: Uuid,
+        predicate: String,
+        spans: Vec<text::Span>,
+    },
 }
 
+impl Action {
+    /// The oldest event version that can hold this action, so that events
+    /// stay readable by older builds unless they use newer actions.
+    pub fn version(&self) -> u32 {
+        match self {
+            Action::RestoreEntity { .. } => 1,
+            Action::Increment { .. }
+            | Action::AddElement { .. }
+            | Action::RemoveElements { .. }
+            | Action::Assign { .. }
+            | Action::InsertText { .. }
+            | Action::DeleteText { .. } => 2,
+            Action::Transaction { actions } | Action::Chunk { actions, .. } => {
+                actions.iter().map(Action::version).max().unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    /// The entity this action concerns, if it concerns exactly one.
+    pub fn subject(&self) -> Option<Uuid> {
+        match self {
+            Action::CreateEntity { id }
+            | Action::DeleteEntity { id }
+            | Action::RestoreEntity { id } => Some(*id),
+            Action::AddFact { subject, .. }
+            | Action::RemoveFact { subject, .. }
+            | Action::AppendString { subject, .. }
+            | Action::Increment { subject, .. }
+            | Action::AddElement { subject, .. }
+            | Action::RemoveElements { subject, .. }
+            | Action::Assign { subject, .. }
+            | Action::InsertText { subject, .. }
+            | Action::DeleteText { subject, .. } => Some(*subject),
+            Action::Transaction { actions } | Action::Chunk { actions, .. } => {
+                let mut subjects = actions.iter().map(Action::subject);
+                let first = subjects.next()??;
+                subjects.all(|s| s == Some(first)).then_some(first)
+            }
+        }
+    }
+
+    /// The predicate this action changes, if it changes exactly one.
+    pub fn predicate(&self) -> Option<&str> {
+        match self {
+            Action::CreateEntity { .. }
+            | Action::DeleteEntity { .. }
+            | Action::RestoreEntity { .. } => None,
+            Action::AddFact { predicate, .. }
+            | Action::RemoveFact { predicate, .. }
+            | Action::AppendString { predicate, .. }
+            | Action::Increment { predicate, .. }
+            | Action::AddElement { predicate, .. }
+            | Action::RemoveElements { predicate, .. }
+            | Action::Assign { predicate, .. }
+            | Action::InsertText { predicate, .. }
+            | Action::DeleteText { predicate, .. } => Some(predicate),
+            Action::Transaction { actions } | Action::Chunk { actions, .. } => {
+                let mut predicates = actions.iter().map(Action::predicate);
+                let first = predicates.next()??;
+                predicates.all(|p| p == Some(first)).then_some(first)
+            }
+        }
+    }
+
+    /// Every entity this action touches, with the predicate it changes or
+    /// `None` when it creates or deletes the entity, including inside
+    /// transactions.
+    pub fn touches(&self) -> Vec<(Uuid, Option<&str>)> {
+        match self {
+            Action::CreateEntity { id }
+            | Action::DeleteEntity { id }
+            | Action::RestoreEntity { id } => vec![(*id, None)],
+            Action::AddFact {
+                subject, predicate, ..
+            }
+            | Action::RemoveFact { subject, predicate }
+            | Action::AppendString {
+                subject, predicate, ..
+            }
+            | Action::Increment {
+                subject, predicate, ..
+            }
+            | Action::AddElement {
+                subject, predicate, ..
+            }
+            | Action::RemoveElements {
+                subject, predicate, ..
+            }
+            | Action::Assign {
+                subject, predicate, ..
+            }
+            | Action::InsertText {
+                subject, predicate, ..
+            }
+            | Action::DeleteText {
+                subject, predicate, ..
+            } => vec![(*subject, Some(predicate))],
+            Action::Transaction { actions } | Action::Chunk { actions, .. } => {
+                let mut touches = Vec::new();
+                for touch in actions.iter().flat_map(Action::touches) {
+                    if !touches.contains(&touch) {
+                        touches.push(touch);
+                    }
+                }
+                touches
+            }
+        }
+    }
+
+    /// Every entity this action concerns, including inside transactions, in
+    /// the order they first appear.
+    pub fn subjects(&self) -> Vec<Uuid> {
+        let mut subjects = Vec::new();
+        self.collect_subjects(&mut subjects);
+        subjects
+    }
+
+    fn collect_subjects(&self, subjects: &mut Vec<Uuid>) {
+        match self {
+            Action::CreateEntity { id: subject }
+            | Action::DeleteEntity { id: subject }
+            | Action::RestoreEntity { id: subject }
+            | Action::AddFact { subject, .. }
+            | Action::RemoveFact { subject, .. }
+            | Action::AppendString { subject, .. }
+            | Action::Increment { subject, .. }
+            | Action::AddElement { subject, .. }
+            | Action::RemoveElements { subject, .. }
+            | Action::Assign { subject, .. }
+            | Action::InsertText { subject, .. }
+            | Action::DeleteText { subject, .. } => {
+                if !subjects.contains(subject) {
+                    subjects.push(*subject);
+                }
+            }
+            Action::Transaction { actions } | Action::Chunk { actions, .. } => {
+                for action in actions {
+                    action.collect_subjects(subjects);
+                }
+            }
+        }
+    }
+}
+
+/// What was done, by whom and when.
+///
+/// Events read from elsewhere, such as a peer or an archive, are checked as
+/// they are deserialized, see [`Event::new_from_parts`].
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(try_from = "Parts")]
 pub struct Event {
     id: Uuid,         // The unique identifier of the event
     hlc: HLTimestamp, // Hybrid Logical timestamp
     action: Action,   // The event that was performed
     actor: Uuid, // The actor who performed the event, e.g. a user. should be present amoung the entities
     version: u32, // Event version
+    /// Where the event was created, see [`metadata`].
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    metadata: Metadata,
+}
+
+/// The fields of a serialized event, before they are checked.
+#[derive(Deserialize)]
+struct Parts {
+    id: Uuid,
+    hlc: HLTimestamp,
+    action: Action,
+    actor: Uuid,
+    version: u32,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+impl TryFrom<Parts> for Event {
+    type Error = GraphiteError;
+
+    fn try_from(parts: Parts) -> Result<Event> {
+        Event::new_from_parts(
+            parts.id,
+            parts.hlc,
+            parts.action,
+            parts.actor,
+            parts.version,
+            parts.metadata,
+        )
+    }
+}
+
+impl Event {
+    /// The event made of these parts, e.g. one received over the network.
+    ///
+    /// Fails if the actor is nil, or the version isn't one this build can
+    /// read or too old for the action.
+    pub fn new_from_parts(
+        id: Uuid,
+        hlc: HLTimestamp,
+        action: Action,
+        actor: Uuid,
+        version: u32,
+        metadata: Metadata,
+    ) -> Result<Event> {
+        if actor.is_nil() {
+            return Err(GraphiteError::Invalid(format!("Event {} has no actor", id)));
+        }
+        if version > EVENT_VERSION {
+            return Err(GraphiteError::VersionUnsupported {
+                event_id: id,
+                version,
+            });
+        }
+        if version < action.version() {
+            return Err(GraphiteError::Invalid(format!(
+                "Event {} has version {}, but its action needs version {}",
+                id,
+                version,
+                action.version()
+            )));
+        }
+        Ok(Event {
+            id,
+            hlc,
+            action,
+            actor,
+            version,
+            metadata,
+        })
+    }
+
+    /// Builds an event of `action` part by part.
+    pub fn builder(action: Action) -> EventBuilder {
+        EventBuilder {
+            id: Uuid::new_v4(),
+            hlc: None,
+            version: action.version(),
+            action,
+            actor: Uuid::nil(),
+            metadata: Metadata::new(),
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn hlc(&self) -> HLTimestamp {
+        self.hlc
+    }
+
+    pub fn action(&self) -> &Action {
+        &self.action
+    }
+
+    pub fn actor(&self) -> Uuid {
+        self.actor
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// The event with `key` of its metadata set to `value`.
+    pub fn with_metadata(mut self, key: &str, value: impl Into<String>) -> Event {
+        self.metadata.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// The event with `origin` as its [`metadata::SYNC_ORIGIN`], unless it
+    /// came from elsewhere before.
+    pub fn synced_from(mut self, origin: &str) -> Event {
+        self.metadata
+            .entry(metadata::SYNC_ORIGIN.to_string())
+            .or_insert_with(|| origin.to_string());
+        self
+    }
+}
+
+/// Builds an [`Event`], see [`Event::builder`]. The id is a new one and the
+/// version the one the action needs unless set, the actor and the timestamp
+/// must be set.
+#[derive(Debug, Clone)]
+pub struct EventBuilder {
+    id: Uuid,
+    hlc: Option<HLTimestamp>,
+    action: Action,
+    actor: Uuid,
+    version: u32,
+    metadata: Metadata,
+}
+
+impl EventBuilder {
+    pub fn id(mut self, id: Uuid) -> EventBuilder {
+        self.id = id;
+        self
+    }
+
+    pub fn hlc(mut self, hlc: HLTimestamp) -> EventBuilder {
+        self.hlc = Some(hlc);
+        self
+    }
+
+    pub fn actor(mut self, actor: Uuid) -> EventBuilder {
+        self.actor = actor;
+        self
+    }
+
+    pub fn version(mut self, version: u32) -> EventBuilder {
+        self.version = version;
+        self
+    }
+
+    /// Sets `key` of the metadata to `value`.
+    pub fn metadata(mut self, key: &str, value: impl Into<String>) -> EventBuilder {
+        self.metadata.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// The event, checked as by [`Event::new_from_parts`].
+    pub fn build(self) -> Result<Event> {
+        let hlc = self
+            .hlc
+            .ok_or_else(|| GraphiteError::Invalid(format!("Event {} has no timestamp", self.id)))?;
+        Event::new_from_parts(
+            self.id,
+            hlc,
+            self.action,
+            self.actor,
+            self.version,
+            self.metadata,
+        )
+    }
 }
 
 pub struct EventCreator {
     actor: Uuid,
     hlc: hlc::State<fn() -> i64>,
+    /// The metadata of the created events.
+    metadata: Metadata,
+    /// The events seen, if the created events record it, see
+    /// [`crate::legacy::vector_clock`].
+    clock: Option<VectorClock>,
 }
 
 impl EventCreator {
     pub fn new(actor: Uuid, hlt: HLTimestamp) -> EventCreator {
         let mut hlc = hlc::State::new();
         hlc.update(hlt); // Update the HLC with the given timestamp to have the correct time
-        EventCreator { actor, hlc }
+        EventCreator {
+            actor,
+            hlc,
+            metadata: Metadata::new(),
+            clock: None,
+        }
+    }
+
+    /// Records `clock` as the events seen in the events created from now on.
+    pub fn set_clock(&mut self, clock: VectorClock) {
+        self.clock = Some(clock);
+    }
+
+    /// Counts `event` as seen by the events created from now on, if they
+    /// record it.
+    pub fn observe(&mut self, event: &Event) {
+        if let (Some(clock), Some(seen)) = (&mut self.clock, vector_clock::clock(event)) {
+            clock.merge(&seen);
+        }
+    }
+
+    /// Sets `key` of the metadata of the events created from now on, e.g.
+    /// the [`metadata::IMPORT_SOURCE`] while importing a file.
+    pub fn set_metadata(&mut self, key: &str, value: impl Into<String>) {
+        self.metadata.insert(key.to_string(), value.into());
+    }
+
+    pub fn remove_metadata(&mut self, key: &str) {
+        self.metadata.remove(key);
+    }
+
+    /// The actor the created events are recorded as.
+    pub fn actor(&self) -> Uuid {
+        self.actor
     }
 
     pub fn create(&mut self, action: Action) -> Event {
         let hlc = self.hlc.get_time();
+        let mut metadata = self.metadata.clone();
+        if let Some(clock) = &mut self.clock {
+            clock.tick(self.actor);
+            metadata.insert(metadata::CLOCK.to_string(), clock.to_string());
+        }
         Event {
             id: Uuid::new_v4(),
             hlc,
+            version: action.version(),
             action,
             actor: self.actor,
+            metadata,
+        }
+    }
+
+    /// The events that record `actions` as one transaction: a single event,
+    /// or the chunks of a large transaction, which must be recorded together
+    /// with [`EventStorage::record_batch`].
+    pub fn transaction(&mut self, actions: Vec<Action>) -> Vec<Event> {
+        chunk::split(actions)
+            .into_iter()
+            .map(|action| self.create(action))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(n: usize) -> Vec<Event> {
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        (0..n)
+            .map(|i| {
+                creator.create(Action::AddFact {
+                    subject: Uuid::new_v4(),
+                    predicate: "count".to_string(),
+                    datum: Datum::Integer(i as i64),
+                })
+            })
+            .collect()
+    }
+
+    fn played(storage: &EventStorage) -> Vec<Event> {
+        let mut events = Vec::new();
+        storage
+            .play(|event| {
+                events.push(event);
+                Ok(())
+            })
+            .unwrap();
+        events
+    }
+
+    #[test]
+    fn record_and_record_batch_round_trip() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let events = events(5);
+        for event in &events[..2] {
+            storage.record(event.clone()).unwrap();
+        }
+        storage.record_batch(events[2..].to_vec()).unwrap();
+        assert_eq!(played(&storage), events);
+    }
+
+    #[test]
+    fn bulk_load_builds_the_indexes_again() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let events = events(BULK_LOAD);
+        storage.record_batch(events.clone()).unwrap();
+        assert_eq!(played(&storage), events);
+        let count =
+            |sql: &str| -> usize { storage.conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            count("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND sql IS NOT NULL"),
+            INDEXES.len()
+        );
+        assert_eq!(count("SELECT COUNT(*) FROM event_subjects"), BULK_LOAD);
+    }
+
+    #[test]
+    fn failed_batch_records_nothing() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let mut batch = events(3);
+        batch.push(batch[0].clone());
+        assert!(storage.record_batch(batch).is_err());
+        assert!(played(&storage).is_empty());
+    }
+
+    fn event_at(seconds: i64, logical: u16, action: Action) -> Event {
+        Event {
+            id: Uuid::new_v4(),
+            hlc: HLTimestamp::new(seconds, logical),
+            action,
+            actor: Uuid::nil(),
             version: 0,
+            metadata: Metadata::new(),
         }
     }
+
+    #[test]
+    fn events_are_built_from_valid_parts() {
+        let actor = Uuid::new_v4();
+        let restore = Action::RestoreEntity { id: Uuid::new_v4() };
+        let event = Event::builder(restore.clone())
+            .actor(actor)
+            .hlc(HLTimestamp::new(1, 0))
+            .metadata(metadata::DEVICE, "laptop")
+            .build()
+            .unwrap();
+        assert_eq!((event.actor(), event.version()), (actor, 1));
+        assert_eq!(event.metadata()[metadata::DEVICE], "laptop");
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), event);
+
+        assert!(Event::builder(restore.clone())
+            .actor(actor)
+            .build()
+            .is_err());
+        let invalid = [
+            Event::builder(restore.clone()).hlc(HLTimestamp::new(1, 0)),
+            Event::builder(restore.clone()).actor(actor).version(0),
+            Event::builder(restore)
+                .actor(actor)
+                .version(EVENT_VERSION + 1),
+        ];
+        for builder in invalid {
+            assert!(builder.hlc(HLTimestamp::new(1, 0)).build().is_err());
+        }
+        // Events received from elsewhere are checked too.
+        let nil = json.replace(&actor.to_string(), &Uuid::nil().to_string());
+        assert!(serde_json::from_str::<Event>(&nil).is_err());
+    }
+
+    #[test]
+    fn local_actor_is_stable() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        assert_eq!(
+            storage.local_actor().unwrap(),
+            storage.local_actor().unwrap()
+        );
+        assert_eq!(storage.latest_hlc().unwrap(), HLTimestamp::new(0, 0));
+        storage
+            .record(event_at(7, 3, Action::CreateEntity { id: Uuid::nil() }))
+            .unwrap();
+        assert_eq!(storage.latest_hlc().unwrap(), HLTimestamp::new(7, 3));
+    }
+
+    #[test]
+    fn timestamps_never_go_back() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        // Far ahead of the wall clock, as if it went back since.
+        let future = HLTimestamp::new(4_000_000_000, 2);
+        let mut creator = EventCreator::new(storage.local_actor().unwrap(), future);
+        storage
+            .record(creator.create(Action::CreateEntity { id: Uuid::new_v4() }))
+            .unwrap();
+        let recorded = storage.latest_timestamp().unwrap();
+        assert!(recorded > future);
+
+        storage.conn.execute("DELETE FROM events", []).unwrap();
+        assert_eq!(storage.latest_hlc().unwrap(), HLTimestamp::new(0, 0));
+        assert_eq!(storage.latest_timestamp().unwrap(), recorded);
+        let event = storage
+            .creator()
+            .unwrap()
+            .create(Action::CreateEntity { id: Uuid::new_v4() });
+        assert!(event.hlc() > recorded);
+    }
+
+    #[test]
+    fn read_only_databases_refuse_writes() {
+        let path = std::env::temp_dir().join(format!("graphite-{}.db", Uuid::new_v4()));
+        let recorded = events(2);
+        let actor = {
+            let mut storage = EventStorage::open(&path).unwrap();
+            storage.record_batch(recorded.clone()).unwrap();
+            storage.local_actor().unwrap()
+        };
+        let config = StorageConfig {
+            read_only: true,
+            ..StorageConfig::default()
+        };
+        let mut storage = EventStorage::open_with(&path, &config).unwrap();
+        assert!(storage.is_read_only());
+        assert_eq!(storage.local_actor().unwrap(), actor);
+        assert_eq!(storage.creator().unwrap().actor(), actor);
+        let mut played = Vec::new();
+        storage
+            .play(|event| {
+                played.push(event);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(played, recorded);
+        assert!(matches!(
+            storage.record_batch(events(1)),
+            Err(GraphiteError::ReadOnly)
+        ));
+        assert!(matches!(
+            storage.tag("before"),
+            Err(GraphiteError::ReadOnly)
+        ));
+        assert_eq!(storage.count().unwrap(), 2);
+        drop(storage);
+        std::fs::remove_file(writer::lock_path(&path)).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn play_from_compares_whole_timestamps() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let id = Uuid::new_v4();
+        let events = vec![
+            event_at(1, 5, Action::CreateEntity { id }),
+            event_at(2, 0, Action::CreateEntity { id }),
+            event_at(2, 3, Action::CreateEntity { id }),
+        ];
+        storage.record_batch(events.clone()).unwrap();
+
+        let mut played = Vec::new();
+        storage
+            .play_from(HLTimestamp::new(1, 6), |e| {
+                played.push(e);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(played, events[1..]);
+    }
+
+    fn played_for(storage: &EventStorage, entity: Uuid) -> Vec<Event> {
+        let mut played = Vec::new();
+        storage
+            .play_for_entity(entity, |e| {
+                played.push(e);
+                Ok(())
+            })
+            .unwrap();
+        played
+    }
+
+    #[test]
+    fn play_for_entity_includes_transactions() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let events = vec![
+            event_at(1, 0, Action::CreateEntity { id: a }),
+            event_at(2, 0, Action::CreateEntity { id: b }),
+            event_at(
+                3,
+                0,
+                Action::Transaction {
+                    actions: vec![
+                        Action::RemoveFact {
+                            subject: a,
+                            predicate: "name".into(),
+                        },
+                        Action::DeleteEntity { id: a },
+                    ],
+                },
+            ),
+            event_at(
+                4,
+                0,
+                Action::Transaction {
+                    actions: vec![
+                        Action::AddFact {
+                            subject: a,
+                            predicate: "name".into(),
+                            datum: Datum::String("a".into()),
+                        },
+                        Action::AddFact {
+                            subject: b,
+                            predicate: "name".into(),
+                            datum: Datum::String("b".into()),
+                        },
+                    ],
+                },
+            ),
+        ];
+        storage.record_batch(events.clone()).unwrap();
+
+        assert_eq!(
+            played_for(&storage, a),
+            vec![events[0].clone(), events[2].clone(), events[3].clone()]
+        );
+        assert_eq!(
+            played_for(&storage, b),
+            vec![events[1].clone(), events[3].clone()]
+        );
+        assert_eq!(events[3].action().subject(), None);
+        assert_eq!(events[3].action().predicate(), Some("name"));
+        assert_eq!(events[2].action().predicate(), None);
+    }
+
+    #[test]
+    fn migrate_codec_keeps_mixed_logs_readable() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let events = events(10);
+        storage.record_batch(events[..5].to_vec()).unwrap();
+        storage.codec = Codec::Cbor;
+        storage.record_batch(events[5..].to_vec()).unwrap();
+        assert_eq!(played(&storage), events);
+
+        assert_eq!(
+            storage
+                .migrate_codec(Codec::MessagePack, &Reporter::none())
+                .unwrap(),
+            10
+        );
+        assert_eq!(
+            storage
+                .migrate_codec(Codec::MessagePack, &Reporter::none())
+                .unwrap(),
+            0
+        );
+        assert_eq!(played(&storage), events);
+        assert_eq!(
+            played_for(&storage, events[0].action().subject().unwrap()).len(),
+            1
+        );
+
+        storage
+            .migrate_codec(Codec::Json, &Reporter::none())
+            .unwrap();
+        let action: String = storage
+            .conn
+            .query_row("SELECT action FROM events LIMIT 1", [], |row| row.get(0))
+            .unwrap();
+        assert!(action.starts_with('{'));
+    }
+
+    #[test]
+    fn cancelled_operations_change_nothing() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        storage.record_batch(events(3)).unwrap();
+
+        let (reporter, handle) = crate::progress::task();
+        let mut played = 0;
+        storage
+            .play_with_progress(&reporter, |_| {
+                played += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!((played, handle.progress().fraction()), (3, Some(1.0)));
+
+        handle.cancel();
+        let error = storage
+            .migrate_codec(Codec::MessagePack, &reporter)
+            .unwrap_err();
+        assert!(matches!(error, GraphiteError::Cancelled(_)));
+        assert!(crate::progress::is_cancelled(&error.into()));
+        assert_eq!(storage.codec, Codec::Json);
+        let migrated: u64 = storage
+            .conn
+            .query_row("SELECT COUNT(*) FROM events WHERE codec != 0", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(migrated, 0);
+    }
+
+    #[test]
+    fn unreadable_events_are_reported_by_id() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let events = events(2);
+        storage.record_batch(events.clone()).unwrap();
+        storage
+            .conn
+            .execute(
+                "UPDATE events SET action = 'garbage' WHERE id = ?",
+                [events[0].id],
+            )
+            .unwrap();
+        let error = storage.play(|_| Ok(())).unwrap_err();
+        assert!(
+            matches!(error, GraphiteError::Corrupt { event_id, .. } if event_id == events[0].id)
+        );
+
+        storage
+            .conn
+            .execute("UPDATE events SET version = 3 WHERE id = ?", [events[1].id])
+            .unwrap();
+        let error = storage.play_from(events[1].hlc, |_| Ok(())).unwrap_err();
+        assert!(matches!(
+            error,
+            GraphiteError::VersionUnsupported { version: 3, .. }
+        ));
+    }
+
+    #[test]
+    fn pages_cover_the_log_once() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        // Events of different actors may share a timestamp.
+        let mut events: Vec<Event> = (0..7)
+            .map(|i| event_at(i / 3, 0, Action::CreateEntity { id: Uuid::new_v4() }))
+            .collect();
+        storage.record_batch(events.clone()).unwrap();
+        events.sort_by_key(|e| (e.hlc, e.id));
+
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = storage.play_page(cursor, 3).unwrap();
+            assert!(page.events.len() <= 3);
+            paged.extend(page.events);
+            match page.next {
+                Some(next) => cursor = Some(next.to_string().parse().unwrap()),
+                None => break,
+            }
+        }
+        assert_eq!(paged, events);
+    }
+
+    #[test]
+    fn queries_use_indexes() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        let plan = |query: &str| -> String {
+            let mut stmt = storage
+                .conn
+                .prepare(&format!("EXPLAIN QUERY PLAN {}", query))
+                .unwrap();
+            stmt.query_map([], |row| row.get::<_, String>(3))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let ordered = plan(
+            "SELECT * FROM events WHERE (hlc_seconds, hlc_logical) >= (1, 2)
+            ORDER BY hlc_seconds, hlc_logical, id",
+        );
+        assert!(ordered.contains("events_order"));
+        assert!(!ordered.contains("TEMP B-TREE"));
+        assert!(plan("SELECT * FROM events WHERE subject = x'00'").contains("events_subject"));
+        assert!(plan("SELECT * FROM events WHERE predicate = 'name'").contains("events_predicate"));
+        assert!(
+            plan("SELECT event FROM event_subjects WHERE subject = x'00'")
+                .contains("event_subjects")
+        );
+        assert!(plan("SELECT * FROM events WHERE actor = x'00'").contains("events_actor"));
+    }
+
+    #[test]
+    fn init_links_events_of_old_databases() {
+        let conn = Connection::open_in_memory().unwrap();
+        let id = Uuid::new_v4();
+        conn.execute(
+            "CREATE TABLE events (id BLOB PRIMARY KEY, hlc_seconds INTEGER NOT NULL,
+            hlc_logical INTEGER NOT NULL, action TEXT NOT NULL, actor BLOB NOT NULL,
+            version INTEGER NOT NULL)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO events VALUES (?, 1, 0, ?, ?, 0)",
+            rusqlite::params![
+                Uuid::new_v4(),
+                serde_json::to_string(&Action::CreateEntity { id }).unwrap(),
+                Uuid::nil()
+            ],
+        )
+        .unwrap();
+
+        let storage = EventStorage {
+            conn,
+            codec: Codec::Json,
+            causal: false,
+            chained: false,
+            clock: clock::Guard::default(),
+            _writer: None,
+            profiler: None,
+        };
+        storage.init().unwrap();
+        assert_eq!(played_for(&storage, id).len(), 1);
+    }
 }
@@ -0,0 +1,229 @@
+//! A hash chain over the events of each actor, for deployments that have to
+//! show their history wasn't tampered with.
+//!
+//! With [`super::StorageConfig::chained`] on, every event is recorded with
+//! the SHA-256 of the hash of the event of the same actor inserted before it
+//! and of its own content. Removing, reordering or changing an event then
+//! breaks the chain at the next event of its actor, which
+//! [`EventStorage::verify`] reports. Removing the newest events of an actor
+//! leaves no gap, so audits note the hashes of [`EventStorage::chain_heads`]
+//! to compare with later.
+
+use super::{action, metadata, Event, EventStorage, EVENT_COLUMNS};
+use crate::legacy::error::{Context, Result};
+use crate::legacy::hlc::HLTimestamp;
+use ring::digest::{Context as Digest, SHA256};
+use rusqlite::{Connection, Row};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The hash of `event` following `previous`, the hash of the event of its
+/// actor before it, if there is one.
+///
+/// The action and metadata are hashed as JSON, so re-encoding events with
+/// another codec keeps the chain.
+pub fn link(previous: Option<&[u8]>, event: &Event) -> Vec<u8> {
+    let mut digest = Digest::new(&SHA256);
+    digest.update(previous.unwrap_or(&[0; 32]));
+    digest.update(event.id.as_bytes());
+    digest.update(&event.hlc.seconds().to_be_bytes());
+    digest.update(&event.hlc.logical().to_be_bytes());
+    digest.update(event.actor.as_bytes());
+    digest.update(&event.version.to_be_bytes());
+    for part in [
+        serde_json::to_vec(&event.action),
+        serde_json::to_vec(&event.metadata),
+    ] {
+        let part = part.unwrap_or_default();
+        digest.update(&(part.len() as u64).to_be_bytes());
+        digest.update(&part);
+    }
+    digest.finish().as_ref().to_vec()
+}
+
+/// The hash of the event `actor` inserted last, if it has one.
+pub(super) fn head(conn: &Connection, actor: Uuid) -> Result<Option<Vec<u8>>> {
+    conn.prepare_cached("SELECT chain FROM events WHERE actor = ? ORDER BY rowid DESC LIMIT 1")
+        .context("Failed to prepare SQL statement to find the head of a chain")?
+        .query_row([actor], |row| row.get(0))
+        .or_else(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            e => Err(e),
+        })
+        .context("Failed to find the head of a chain")
+}
+
+/// The event in the columns of [`super::EVENT_COLUMNS`] from `offset` on.
+fn event(row: &Row, offset: usize) -> rusqlite::Result<Event> {
+    Ok(Event {
+        id: row.get(offset)?,
+        hlc: HLTimestamp::new(row.get(offset + 1)?, row.get(offset + 2)?),
+        action: action(row, offset + 3)?,
+        actor: row.get(offset + 5)?,
+        version: row.get(offset + 6)?,
+        metadata: metadata::column(row, offset + 7)?,
+    })
+}
+
+impl EventStorage {
+    /// Chains the events recorded without a hash, in the order they were
+    /// inserted. With `reseal`, every event is chained again from the start.
+    ///
+    /// Events that can't be read are left out, for verify to report.
+    pub(super) fn seal_chain(&self, reseal: bool) -> Result<usize> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .context("Failed to open a transaction")?;
+        let mut sealed = 0;
+        {
+            let mut select = tx.prepare(&format!(
+                "SELECT rowid, chain, {} FROM events ORDER BY rowid",
+                EVENT_COLUMNS
+            ))?;
+            let mut update = tx.prepare("UPDATE events SET chain = ? WHERE rowid = ?")?;
+            let mut rows = select.query([]).context("Failed to read events")?;
+            let mut heads: HashMap<Uuid, Vec<u8>> = HashMap::new();
+            while let Some(row) = rows.next().context("Failed to read an event")? {
+                let rowid: i64 = row.get(0)?;
+                let stored: Option<Vec<u8>> = row.get(1)?;
+                let Ok(event) = event(row, 2) else {
+                    continue;
+                };
+                let hash = match stored {
+                    Some(hash) if !reseal => hash,
+                    _ => {
+                        let hash = link(heads.get(&event.actor).map(Vec::as_slice), &event);
+                        update
+                            .execute(rusqlite::params![hash, rowid])
+                            .context("Failed to chain an event")?;
+                        sealed += 1;
+                        hash
+                    }
+                };
+                heads.insert(event.actor, hash);
+            }
+        }
+        tx.commit().context("Failed to commit the chain")?;
+        Ok(sealed)
+    }
+
+    /// The rowids of the events whose hash doesn't follow from the event of
+    /// their actor before them, in order.
+    pub(super) fn broken_links(&self) -> Result<Vec<i64>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT rowid, chain, {} FROM events ORDER BY rowid",
+                EVENT_COLUMNS
+            ))
+            .context("Failed to prepare SQL statement to verify the chain")?;
+        let mut rows = stmt.query([]).context("Failed to read events")?;
+        let mut heads: HashMap<Uuid, Vec<u8>> = HashMap::new();
+        let mut broken = Vec::new();
+        while let Some(row) = rows.next().context("Failed to read an event")? {
+            let rowid: i64 = row.get(0)?;
+            let stored: Option<Vec<u8>> = row.get(1)?;
+            // What can't be read is reported by the other checks, and the
+            // events after it are checked against its stored hash.
+            let Ok(event) = event(row, 2) else {
+                if let (Ok(actor), Some(hash)) = (row.get::<_, Uuid>(7), stored) {
+                    heads.insert(actor, hash);
+                }
+                continue;
+            };
+            let expected = link(heads.get(&event.actor).map(Vec::as_slice), &event);
+            if stored.as_ref() != Some(&expected) {
+                broken.push(rowid);
+            }
+            heads.insert(event.actor, stored.unwrap_or(expected));
+        }
+        Ok(broken)
+    }
+
+    /// The hash of the last event of each actor, to note down and compare
+    /// with later.
+    pub fn chain_heads(&self) -> Result<Vec<(Uuid, Vec<u8>)>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT actor, chain FROM events
+                WHERE rowid IN (SELECT MAX(rowid) FROM events GROUP BY actor)
+                AND chain IS NOT NULL ORDER BY actor",
+            )
+            .context("Failed to prepare SQL statement to find the heads of the chains")?;
+        let heads = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .context("Failed to find the heads of the chains")?;
+        heads
+            .collect::<rusqlite::Result<_>>()
+            .context("Failed to read the head of a chain")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::verify::ProblemKind;
+    use crate::legacy::storage::{Action, StorageConfig};
+
+    #[test]
+    fn removing_or_reordering_breaks_the_chain() {
+        let config = StorageConfig {
+            chained: true,
+            ..StorageConfig::default()
+        };
+        let mut storage = EventStorage::open_with(":memory:", &config).unwrap();
+        let actor = Uuid::new_v4();
+        let events: Vec<Event> = (1..=4)
+            .map(|seconds| Event {
+                id: Uuid::new_v4(),
+                hlc: HLTimestamp::new(seconds, 0),
+                action: Action::CreateEntity { id: Uuid::new_v4() },
+                actor,
+                version: 0,
+                metadata: Default::default(),
+            })
+            .collect();
+        storage.record_batch(events.clone()).unwrap();
+        assert!(storage.verify().unwrap().is_ok());
+        let heads = storage.chain_heads().unwrap();
+        assert_eq!(heads.len(), 1);
+        assert_eq!(heads[0].1, {
+            let mut hash = None;
+            for event in &events {
+                hash = Some(link(hash.as_deref(), event));
+            }
+            hash.unwrap()
+        });
+
+        let conn = &storage.conn;
+        conn.execute("DELETE FROM events WHERE rowid = 2", [])
+            .unwrap();
+        let broken = |storage: &EventStorage| -> Vec<i64> {
+            let report = storage.verify().unwrap();
+            report
+                .problems
+                .iter()
+                .filter(|p| p.kind == ProblemKind::BrokenChain)
+                .map(|p| p.row)
+                .collect()
+        };
+        assert_eq!(broken(&storage), vec![3]);
+
+        // Swapping the last two, with their timestamps, so only the chain
+        // tells.
+        let conn = &storage.conn;
+        conn.execute_batch(
+            "UPDATE events SET rowid = 5 WHERE rowid = 3;
+            UPDATE events SET rowid = 3 WHERE rowid = 4;
+            UPDATE events SET rowid = 4 WHERE rowid = 5;
+            UPDATE events SET hlc_seconds = 7 - hlc_seconds WHERE rowid IN (3, 4);",
+        )
+        .unwrap();
+        assert_eq!(broken(&storage), vec![3, 4]);
+
+        storage.repair().unwrap();
+        assert!(storage.verify().unwrap().is_ok());
+    }
+}
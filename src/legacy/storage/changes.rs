@@ -0,0 +1,106 @@
+//! The change feed, which tells other processes that events were recorded.
+//!
+//! A trigger adds a row to the `changes` table for every event inserted, in
+//! the same transaction, so its ids grow in the order events were written
+//! rather than in timestamp order. A process that read the log remembers
+//! [`EventStorage::last_change`] and asks [`EventStorage::poll_changes`] for
+//! what was recorded after it, e.g. an editor that opened a database another
+//! process is writing to.
+
+use super::{Event, EventStorage, EVENT_COLUMNS};
+use crate::legacy::error::{Context, Result};
+use tracing::instrument;
+
+/// The events recorded after a change, see [`EventStorage::poll_changes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changes {
+    /// In the order they were recorded.
+    pub events: Vec<Event>,
+    /// The change to poll from next time.
+    pub last: i64,
+}
+
+impl EventStorage {
+    pub(super) fn init_changes(&self) -> Result<()> {
+        self.conn
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS changes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                event BLOB NOT NULL -- UUID as BLOB
+            );
+            CREATE TRIGGER IF NOT EXISTS changes_insert AFTER INSERT ON events
+            BEGIN
+                INSERT INTO changes (event) VALUES (new.id);
+            END;",
+            )
+            .context("Failed to Create changes table")?;
+        Ok(())
+    }
+
+    /// The latest change, or 0 if no event was recorded since the feed was
+    /// added.
+    pub fn last_change(&self) -> Result<i64> {
+        self.conn
+            .query_row("SELECT COALESCE(MAX(id), 0) FROM changes", [], |row| {
+                row.get(0)
+            })
+            .context("Failed to find the latest change")
+    }
+
+    /// The events recorded after the change `since`, by this or any other
+    /// process.
+    #[instrument(skip(self))]
+    pub fn poll_changes(&self, since: i64) -> Result<Changes> {
+        let last = self.last_change()?.max(since);
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM events JOIN (
+                    SELECT id AS change, event FROM changes WHERE id > ? AND id <= ?
+                ) ON event = events.id ORDER BY change",
+                EVENT_COLUMNS
+            ))
+            .context("Failed to prepare SQL statement to poll changes")?;
+        let mut events = Vec::new();
+        Self::play_internal(&mut stmt, [since, last], |event| {
+            events.push(event);
+            Ok(())
+        })?;
+        Ok(Changes { events, last })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Action, EventCreator, StorageConfig};
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::writer;
+    use uuid::Uuid;
+
+    #[test]
+    fn other_connections_see_new_events() {
+        let path = std::env::temp_dir().join(format!("graphite-{}.db", Uuid::new_v4()));
+        let mut writer_storage = EventStorage::open(&path).unwrap();
+        let config = StorageConfig {
+            read_only: true,
+            ..StorageConfig::default()
+        };
+        let reader = EventStorage::open_with(&path, &config).unwrap();
+        let since = reader.last_change().unwrap();
+        assert_eq!(reader.poll_changes(since).unwrap().events, []);
+
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let events: Vec<Event> = (0..3)
+            .map(|_| creator.create(Action::CreateEntity { id: Uuid::new_v4() }))
+            .collect();
+        writer_storage.record_batch(events.clone()).unwrap();
+        let changes = reader.poll_changes(since).unwrap();
+        assert_eq!(changes.events, events);
+        assert_eq!(reader.poll_changes(changes.last).unwrap().events, []);
+
+        drop(writer_storage);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(writer::lock_path(&path)).unwrap();
+    }
+}
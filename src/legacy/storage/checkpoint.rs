@@ -0,0 +1,226 @@
+//! Named checkpoints of the graph.
+//!
+//! A checkpoint stores the position of the latest event when it was taken,
+//! its watermark. Playing the events up to the watermark rebuilds the graph
+//! as it was, apart from events synced later from actors whose clocks were
+//! behind.
+
+use super::{Cursor, Event, EventStorage, EVENT_COLUMNS, EVENT_ORDER};
+use crate::legacy::error::{Context, GraphiteError, Result};
+use crate::legacy::hlc::HLTimestamp;
+use rusqlite::{OptionalExtension, Row};
+use time::OffsetDateTime;
+use tracing::instrument;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub name: String,
+    /// The latest event included, or `None` if the log was empty.
+    pub watermark: Option<Cursor>,
+    /// When the checkpoint was taken, in unix seconds.
+    pub created: i64,
+}
+
+impl Checkpoint {
+    fn from_row(row: &Row) -> rusqlite::Result<Checkpoint> {
+        let seconds: Option<i64> = row.get(1)?;
+        let logical: Option<u16> = row.get(2)?;
+        let event = row.get(3)?;
+        let watermark = match (seconds, logical, event) {
+            (Some(seconds), Some(logical), Some(id)) => Some(Cursor {
+                hlc: HLTimestamp::new(seconds, logical),
+                id,
+            }),
+            _ => None,
+        };
+        Ok(Checkpoint {
+            name: row.get(0)?,
+            watermark,
+            created: row.get(4)?,
+        })
+    }
+}
+
+impl EventStorage {
+    pub(super) fn init_checkpoints(&self) -> Result<()> {
+        self.conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS checkpoints (
+                name TEXT PRIMARY KEY,
+                hlc_seconds INTEGER, -- NULL when the log was empty
+                hlc_logical INTEGER,
+                event BLOB, -- UUID as BLOB
+                created INTEGER NOT NULL
+            )",
+                [],
+            )
+            .context("Failed to Create checkpoints table")?;
+        Ok(())
+    }
+
+    /// Tags the current state of the graph as `name`.
+    #[instrument(skip(self))]
+    pub fn tag(&self, name: &str) -> Result<Checkpoint> {
+        self.writable()?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(GraphiteError::Invalid(
+                "Checkpoints need a name".to_string(),
+            ));
+        }
+        if self.checkpoint(name)?.is_some() {
+            return Err(GraphiteError::Conflict(format!(
+                "There already is a checkpoint named {}",
+                name
+            )));
+        }
+        let watermark = self
+            .conn
+            .query_row(
+                "SELECT hlc_seconds, hlc_logical, id FROM events
+                ORDER BY hlc_seconds DESC, hlc_logical DESC, id DESC LIMIT 1",
+                [],
+                |row| {
+                    Ok(Cursor {
+                        hlc: HLTimestamp::new(row.get(0)?, row.get(1)?),
+                        id: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+            .context("Failed to read the latest event")?;
+        let checkpoint = Checkpoint {
+            name: name.to_string(),
+            watermark,
+            created: OffsetDateTime::now_utc().unix_timestamp(),
+        };
+        self.conn
+            .execute(
+                "INSERT INTO checkpoints (name, hlc_seconds, hlc_logical, event, created)
+                VALUES (?, ?, ?, ?, ?)",
+                rusqlite::params![
+                    checkpoint.name,
+                    watermark.map(|w| w.hlc.seconds()),
+                    watermark.map(|w| w.hlc.logical()),
+                    watermark.map(|w| w.id),
+                    checkpoint.created
+                ],
+            )
+            .context("Failed to store the checkpoint")?;
+        Ok(checkpoint)
+    }
+
+    pub fn checkpoint(&self, name: &str) -> Result<Option<Checkpoint>> {
+        self.conn
+            .query_row(
+                "SELECT name, hlc_seconds, hlc_logical, event, created FROM checkpoints
+                WHERE name = ?",
+                [name],
+                Checkpoint::from_row,
+            )
+            .optional()
+            .context("Failed to read the checkpoint")
+    }
+
+    /// Every checkpoint, oldest first.
+    pub fn checkpoints(&self) -> Result<Vec<Checkpoint>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT name, hlc_seconds, hlc_logical, event, created FROM checkpoints
+                ORDER BY hlc_seconds, hlc_logical, event, name",
+            )
+            .context("Failed to prepare SQL statement to list checkpoints")?;
+        let checkpoints = stmt
+            .query_map([], Checkpoint::from_row)
+            .context("Failed to list checkpoints")?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to list checkpoints")?;
+        Ok(checkpoints)
+    }
+
+    /// Removes a checkpoint, returning whether it existed.
+    pub fn untag(&self, name: &str) -> Result<bool> {
+        self.writable()?;
+        let removed = self
+            .conn
+            .execute("DELETE FROM checkpoints WHERE name = ?", [name])
+            .context("Failed to remove the checkpoint")?;
+        Ok(removed > 0)
+    }
+
+    /// Plays the events up to and including `watermark`. No events are played
+    /// for a checkpoint of an empty log.
+    #[instrument(skip(self, f))]
+    pub fn play_until(
+        &self,
+        watermark: Option<Cursor>,
+        f: impl FnMut(Event) -> Result<()>,
+    ) -> Result<()> {
+        let Some(watermark) = watermark else {
+            return Ok(());
+        };
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE (hlc_seconds, hlc_logical, id) <= (?, ?, ?)
+                ORDER BY {}",
+                EVENT_COLUMNS, EVENT_ORDER
+            ))
+            .context("Failed to prepare SQL statement to play events up to a checkpoint")?;
+        let params = rusqlite::params![
+            watermark.hlc.seconds(),
+            watermark.hlc.logical(),
+            watermark.id
+        ];
+        Self::play_internal(&mut stmt, params, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::{Action, EventCreator};
+    use uuid::Uuid;
+
+    #[test]
+    fn checkpoints_replay_the_graph_as_it_was() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        let empty = storage.tag("empty").unwrap();
+        assert_eq!(empty.watermark, None);
+
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let first = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+        storage.record(first.clone()).unwrap();
+        storage.tag(" before reorg ").unwrap();
+        storage
+            .record(creator.create(Action::CreateEntity { id: Uuid::new_v4() }))
+            .unwrap();
+
+        assert!(matches!(
+            storage.tag("before reorg"),
+            Err(GraphiteError::Conflict(_))
+        ));
+        assert!(matches!(storage.tag("  "), Err(GraphiteError::Invalid(_))));
+        let names: Vec<_> = storage
+            .checkpoints()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, ["empty", "before reorg"]);
+
+        let before = storage.checkpoint("before reorg").unwrap().unwrap();
+        let mut played = Vec::new();
+        storage
+            .play_until(before.watermark, |e| {
+                played.push(e);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(played, vec![first]);
+
+        assert!(storage.untag("empty").unwrap());
+        assert!(!storage.untag("empty").unwrap());
+    }
+}
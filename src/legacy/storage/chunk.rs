@@ -0,0 +1,165 @@
+//! Chunked transactions, for edits too large to record as one event, such as
+//! importing tens of thousands of entities at once.
+//!
+//! Such a transaction is recorded as several [`Action::Chunk`] events of at
+//! most [`ACTIONS`] actions each, and strings longer than [`TEXT`] bytes are
+//! split into an `AddFact` and [`Action::AppendString`]s. No event is larger
+//! than a chunk, so neither recording nor replaying holds the whole
+//! transaction encoded at once. The chunks are recorded in one SQLite
+//! transaction, and the projection applies them together once the last one
+//! arrives, see [`reassemble`].
+
+use super::{Action, Datum};
+use uuid::Uuid;
+
+/// The most actions in one chunk.
+pub const ACTIONS: usize = 1000;
+/// The most bytes of a string in one action.
+pub const TEXT: usize = 64 * 1024;
+
+/// `actions` as a single transaction if it is small enough, otherwise as
+/// the chunks of one, in order.
+pub fn split(actions: Vec<Action>) -> Vec<Action> {
+    let large = actions.len() > ACTIONS || actions.iter().any(is_large);
+    if !large {
+        return vec![Action::Transaction { actions }];
+    }
+    let mut split = Vec::with_capacity(actions.len());
+    for action in actions {
+        split_text(action, &mut split);
+    }
+    let transaction = Uuid::new_v4();
+    let count = split.len().div_ceil(ACTIONS);
+    let mut actions = split.into_iter();
+    (0..count)
+        .map(|index| Action::Chunk {
+            transaction,
+            actions: actions.by_ref().take(ACTIONS).collect(),
+            last: index + 1 == count,
+        })
+        .collect()
+}
+
+fn is_large(action: &Action) -> bool {
+    match action {
+        Action::AddFact {
+            datum: Datum::String(s),
+            ..
+        } => s.len() > TEXT,
+        Action::Transaction { actions } => actions.iter().any(is_large),
+        _ => false,
+    }
+}
+
+/// Pushes `action` to `split`, with long strings split into pieces and
+/// nested transactions flattened.
+fn split_text(action: Action, split: &mut Vec<Action>) {
+    match action {
+        Action::AddFact {
+            subject,
+            predicate,
+            datum: Datum::String(s),
+        } if s.len() > TEXT => {
+            let mut pieces = pieces(&s);
+            split.push(Action::AddFact {
+                subject,
+                predicate: predicate.clone(),
+                datum: Datum::String(pieces.next().unwrap_or_default().to_string()),
+            });
+            split.extend(pieces.map(|text| Action::AppendString {
+                subject,
+                predicate: predicate.clone(),
+                text: text.to_string(),
+            }));
+        }
+        Action::Transaction { actions } => {
+            for action in actions {
+                split_text(action, split);
+            }
+        }
+        action => split.push(action),
+    }
+}
+
+/// `s` in pieces of at most [`TEXT`] bytes, split between characters.
+fn pieces(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = rest.len().min(TEXT);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, tail) = rest.split_at(end);
+        rest = tail;
+        Some(piece)
+    })
+}
+
+/// The actions of the chunks of a transaction, with the pieces of split
+/// strings joined to the `AddFact` they continue.
+pub fn reassemble(actions: Vec<Action>) -> Vec<Action> {
+    let mut joined: Vec<Action> = Vec::with_capacity(actions.len());
+    for action in actions {
+        if let Action::AppendString {
+            subject,
+            predicate,
+            text,
+        } = &action
+        {
+            if let Some(Action::AddFact {
+                subject: s,
+                predicate: p,
+                datum: Datum::String(value),
+            }) = joined.last_mut()
+            {
+                if s == subject && p == predicate {
+                    value.push_str(text);
+                    continue;
+                }
+            }
+        }
+        joined.push(action);
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_transactions_are_split_and_reassembled() {
+        let subject = Uuid::new_v4();
+        let text = "ä".repeat(TEXT);
+        let mut actions = vec![Action::AddFact {
+            subject,
+            predicate: "body".to_string(),
+            datum: Datum::String(text.clone()),
+        }];
+        actions.extend((0..ACTIONS).map(|_| Action::CreateEntity { id: Uuid::new_v4() }));
+
+        let chunks = split(actions.clone());
+        assert_eq!(chunks.len(), 2);
+        let mut parts = Vec::new();
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let Action::Chunk { actions, last, .. } = chunk else {
+                panic!("expected a chunk, got {:?}", chunk);
+            };
+            assert_eq!(last, index == 1);
+            parts.extend(actions);
+        }
+        // The string was twice the limit, so it takes an AddFact and an
+        // AppendString.
+        assert!(matches!(parts[1], Action::AppendString { .. }));
+        assert_eq!(reassemble(parts), actions);
+
+        let small = vec![Action::CreateEntity { id: subject }];
+        assert_eq!(
+            split(small.clone()),
+            [Action::Transaction { actions: small }]
+        );
+    }
+}
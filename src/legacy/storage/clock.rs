@@ -0,0 +1,112 @@
+//! Guards the order of events against the system clock going back, or
+//! being behind that of other devices.
+//!
+//! New events are timestamped after the latest one recorded, see
+//! [`EventStorage::latest_timestamp`], so a clock that went back still gives
+//! them the right order, but with the time they were made lost: they only
+//! count up the logical part of the last timestamp until the clock catches
+//! up. A clock behind by more than the tolerance is logged when the
+//! database is opened, and with [`SkewPolicy::Pause`] new events are refused
+//! until it is corrected. Events merged from elsewhere are still taken in.
+//!
+//! ```toml
+//! [clock]
+//! tolerance = 300 # seconds
+//! policy = "pause"
+//! ```
+
+use super::EventStorage;
+use crate::legacy::error::{GraphiteError, Result};
+use serde::{Deserialize, Serialize};
+
+/// What happens to new events while the clock is behind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkewPolicy {
+    /// Record them with logical increments of the latest timestamp.
+    #[default]
+    Continue,
+    /// Refuse them with [`GraphiteError::ClockSkew`].
+    Pause,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Guard {
+    /// How many seconds the clock may be behind before it counts as skewed.
+    pub tolerance: u64,
+    pub policy: SkewPolicy,
+}
+
+impl Default for Guard {
+    fn default() -> Guard {
+        Guard {
+            tolerance: 5 * 60,
+            policy: SkewPolicy::Continue,
+        }
+    }
+}
+
+impl EventStorage {
+    /// How many seconds the system clock is behind the latest timestamp
+    /// recorded, if by more than the tolerance.
+    pub fn clock_skew(&self) -> Result<Option<i64>> {
+        self.skew_at(time::OffsetDateTime::now_utc().unix_timestamp())
+    }
+
+    fn skew_at(&self, now: i64) -> Result<Option<i64>> {
+        let behind = self.latest_timestamp()?.seconds() - now;
+        Ok((behind > self.clock.tolerance as i64).then_some(behind))
+    }
+
+    /// Fails with [`GraphiteError::ClockSkew`] if new events are paused.
+    pub(super) fn check_clock(&self) -> Result<()> {
+        if self.clock.policy == SkewPolicy::Pause {
+            if let Some(behind) = self.clock_skew()? {
+                return Err(GraphiteError::ClockSkew { behind });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::{Action, EventCreator, StorageConfig};
+    use uuid::Uuid;
+
+    #[test]
+    fn pauses_while_the_clock_is_behind() {
+        let config = StorageConfig {
+            clock: Guard {
+                tolerance: 60,
+                policy: SkewPolicy::Pause,
+            },
+            ..StorageConfig::default()
+        };
+        let mut storage = EventStorage::open_with(":memory:", &config).unwrap();
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let create = || Action::CreateEntity { id: Uuid::new_v4() };
+        let mut creator = EventCreator::new(storage.local_actor().unwrap(), HLTimestamp::new(0, 0));
+        storage.record(creator.create(create())).unwrap();
+        assert_eq!(storage.clock_skew().unwrap(), None);
+
+        // Another device with its clock an hour ahead.
+        let mut ahead = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(now + 3600, 0));
+        assert_eq!(storage.merge(vec![ahead.create(create())]).unwrap(), 1);
+        let behind = storage.clock_skew().unwrap().unwrap();
+        assert!((3599..=3601).contains(&behind));
+        assert!(matches!(
+            storage.record(storage.creator().unwrap().create(create())),
+            Err(GraphiteError::ClockSkew { .. })
+        ));
+        assert_eq!(storage.skew_at(now + 3600).unwrap(), None);
+
+        storage.clock.policy = SkewPolicy::Continue;
+        let event = storage.creator().unwrap().create(create());
+        assert!(event.hlc() > HLTimestamp::new(now + 3600, 0));
+        storage.record(event).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+//! The history of a fact: the values recorded for a predicate of an entity
+//! over time, as found through the `event_subjects` links.
+
+use super::{Action, Datum, Event, EventStorage, EVENT_COLUMNS, EVENT_ORDER};
+use crate::legacy::error::{Context, Result};
+use crate::legacy::hlc::HLTimestamp;
+use tracing::instrument;
+use uuid::Uuid;
+
+impl EventStorage {
+    /// Plays the events that change `predicate` of `entity`, including
+    /// through transactions, oldest first.
+    #[instrument(skip(self, f))]
+    pub fn play_for_fact(
+        &self,
+        entity: Uuid,
+        predicate: &str,
+        f: impl FnMut(Event) -> Result<()>,
+    ) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM events WHERE id IN (
+                    SELECT event FROM event_subjects WHERE subject = ? AND predicate = ?
+                ) ORDER BY {}",
+                EVENT_COLUMNS, EVENT_ORDER
+            ))
+            .context("Failed to prepare SQL statement to play the events of a fact")?;
+        Self::play_internal(&mut stmt, rusqlite::params![entity, predicate], f)
+    }
+
+    /// Every value added to `predicate` of `entity`, with the timestamp of
+    /// the event that added it, oldest first.
+    pub fn history(&self, entity: Uuid, predicate: &str) -> Result<Vec<(HLTimestamp, Datum)>> {
+        let mut history = Vec::new();
+        self.play_for_fact(entity, predicate, |event| {
+            collect_added(event.action(), entity, predicate, &mut |datum| {
+                history.push((event.hlc(), datum.clone()))
+            });
+            Ok(())
+        })?;
+        Ok(history)
+    }
+}
+
+fn collect_added(action: &Action, entity: Uuid, predicate: &str, f: &mut impl FnMut(&Datum)) {
+    match action {
+        Action::AddFact {
+            subject,
+            predicate: p,
+            datum,
+        } if *subject == entity && p == predicate => f(datum),
+        Action::Transaction { actions } | Action::Chunk { actions, .. } => actions
+            .iter()
+            .for_each(|action| collect_added(action, entity, predicate, f)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::EventCreator;
+
+    #[test]
+    fn history_lists_added_values() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let (me, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let weight = |subject, kg| Action::AddFact {
+            subject,
+            predicate: "weight".into(),
+            datum: Datum::Float(kg),
+        };
+        let events = [
+            Action::CreateEntity { id: me },
+            weight(me, 80.5),
+            weight(other, 60.0),
+            Action::Transaction {
+                actions: vec![
+                    Action::RemoveFact {
+                        subject: me,
+                        predicate: "weight".into(),
+                    },
+                    weight(me, 79.0),
+                    Action::AddFact {
+                        subject: me,
+                        predicate: "note".into(),
+                        datum: Datum::String("after the holidays".into()),
+                    },
+                ],
+            },
+        ]
+        .map(|action| creator.create(action));
+        for event in &events {
+            storage.record(event.clone()).unwrap();
+        }
+
+        let history = storage.history(me, "weight").unwrap();
+        assert_eq!(
+            history,
+            vec![
+                (events[1].hlc(), Datum::Float(80.5)),
+                (events[3].hlc(), Datum::Float(79.0)),
+            ]
+        );
+        assert!(storage.history(me, "height").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+//! Upkeep of the database file: statistics for the query planner, returning
+//! free pages to the file system, and pruning the events quarantined by
+//! [`EventStorage::repair`].
+
+use super::EventStorage;
+use crate::legacy::error::{Context, Result};
+use rusqlite::Connection;
+use time::OffsetDateTime;
+use tracing::{debug, instrument};
+
+/// Creates the `quarantine` table, adding when each event was quarantined to
+/// a table created before that was recorded. Events quarantined before count
+/// as quarantined at `since`.
+pub(super) fn create_quarantine(conn: &Connection, since: i64) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS quarantine (
+            row INTEGER NOT NULL, -- rowid in events
+            id BLOB,
+            hlc_seconds INTEGER,
+            hlc_logical INTEGER,
+            action BLOB,
+            actor BLOB,
+            version INTEGER,
+            subject BLOB,
+            predicate TEXT,
+            codec INTEGER,
+            reason TEXT NOT NULL,
+            quarantined INTEGER -- unix seconds
+        )",
+        [],
+    )
+    .context("Failed to create the quarantine table")?;
+    let dated = conn
+        .prepare("SELECT quarantined FROM quarantine LIMIT 0")
+        .is_ok();
+    if !dated {
+        conn.execute("ALTER TABLE quarantine ADD COLUMN quarantined INTEGER", [])
+            .context("Failed to migrate the quarantine table")?;
+        conn.execute("UPDATE quarantine SET quarantined = ?", [since])
+            .context("Failed to migrate the quarantine table")?;
+    }
+    Ok(())
+}
+
+impl EventStorage {
+    /// Updates the statistics SQLite plans queries with.
+    #[instrument(skip(self))]
+    pub fn analyze(&self) -> Result<()> {
+        self.writable()?;
+        self.conn
+            .execute_batch("ANALYZE")
+            .context("Failed to analyze the database")?;
+        Ok(())
+    }
+
+    /// Rebuilds the database file, returning the pages freed by deleted rows
+    /// to the file system. Returns the number of bytes freed.
+    #[instrument(skip(self))]
+    pub fn vacuum(&self) -> Result<u64> {
+        self.writable()?;
+        let size = || -> Result<u64> {
+            self.conn
+                .query_row(
+                    "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                    [],
+                    |row| row.get(0),
+                )
+                .context("Failed to read the size of the database")
+        };
+        let before = size()?;
+        self.conn
+            .execute_batch("VACUUM")
+            .context("Failed to vacuum the database")?;
+        let freed = before.saturating_sub(size()?);
+        debug!(freed, "Vacuumed the database");
+        Ok(freed)
+    }
+
+    /// Deletes the events quarantined before `before`, in unix seconds.
+    /// Returns how many were deleted.
+    #[instrument(skip(self))]
+    pub fn prune_quarantine(&self, before: i64) -> Result<usize> {
+        self.writable()?;
+        let exists: bool = self
+            .conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'quarantine')",
+                [],
+                |row| row.get(0),
+            )
+            .context("Failed to look for the quarantine table")?;
+        if !exists {
+            return Ok(0);
+        }
+        // Events quarantined before it was recorded when are kept for the
+        // whole time from now on.
+        create_quarantine(&self.conn, OffsetDateTime::now_utc().unix_timestamp())?;
+        let pruned = self
+            .conn
+            .execute("DELETE FROM quarantine WHERE quarantined < ?", [before])
+            .context("Failed to prune the quarantine")?;
+        debug!(pruned, "Pruned the quarantine");
+        Ok(pruned)
+    }
+}
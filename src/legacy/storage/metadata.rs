@@ -0,0 +1,118 @@
+//! Provenance of events: where and how they were created.
+//!
+//! Besides its actor, an event carries a map of metadata recorded next to
+//! its action, e.g. the device it was created on or the file it was
+//! imported from. The metadata isn't part of what an event does, so it is
+//! kept out of the action and may be missing, e.g. for events recorded
+//! before it was introduced.
+
+use rusqlite::types::Type;
+use rusqlite::Error as RusqliteError;
+use rusqlite::Row;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+pub type Metadata = BTreeMap<String, String>;
+
+/// The name of the machine the event was created on.
+pub const DEVICE: &str = "device";
+/// The version of Graphite that created the event.
+pub const APP_VERSION: &str = "app_version";
+/// The process that created the event, an id per run.
+pub const SESSION: &str = "session";
+/// The file or service the event was imported from.
+pub const IMPORT_SOURCE: &str = "import_source";
+/// Where an event that was merged in came from, e.g. a backup.
+pub const SYNC_ORIGIN: &str = "sync_origin";
+/// The events seen when the event was created, see
+/// [`crate::legacy::vector_clock`].
+pub const CLOCK: &str = "clock";
+
+/// The metadata of the events created by this process.
+pub fn local() -> Metadata {
+    static SESSION_ID: OnceLock<Uuid> = OnceLock::new();
+    let mut metadata = Metadata::new();
+    if let Some(device) = device() {
+        metadata.insert(DEVICE.to_string(), device);
+    }
+    metadata.insert(
+        APP_VERSION.to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+    );
+    metadata.insert(
+        SESSION.to_string(),
+        SESSION_ID.get_or_init(Uuid::new_v4).to_string(),
+    );
+    metadata
+}
+
+/// The name of this machine, if it has one.
+#[cfg(unix)]
+pub fn device() -> Option<String> {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer is as long as claimed, and the name is read up to
+    // its terminating zero only.
+    let result = unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) };
+    if result != 0 {
+        return None;
+    }
+    let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    Some(String::from_utf8_lossy(&name[..end]).into_owned()).filter(|name| !name.is_empty())
+}
+
+#[cfg(not(unix))]
+pub fn device() -> Option<String> {
+    std::env::var("COMPUTERNAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Decodes the metadata in column `index`, stored as a JSON object or NULL
+/// if there is none.
+pub(super) fn column(row: &Row, index: usize) -> rusqlite::Result<Metadata> {
+    let Some(json) = row.get_ref(index)?.as_str_or_null()? else {
+        return Ok(Metadata::new());
+    };
+    serde_json::from_str(json)
+        .map_err(|e| RusqliteError::FromSqlConversionFailure(index, Type::Text, e.into()))
+}
+
+/// The metadata as stored, NULL if there is none.
+pub(super) fn to_sql(metadata: &Metadata) -> Option<String> {
+    (!metadata.is_empty())
+        .then(|| serde_json::to_string(metadata).expect("metadata serializes to JSON"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Action, EventCreator, EventStorage};
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+
+    #[test]
+    fn metadata_is_recorded_with_events() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let mut creator = storage.creator().unwrap();
+        creator.set_metadata(IMPORT_SOURCE, "people.csv");
+        let imported = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+        let mut bare = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let old = bare.create(Action::CreateEntity { id: Uuid::new_v4() });
+        storage
+            .record_batch(vec![imported.clone(), old.clone()])
+            .unwrap();
+
+        let mut played = Vec::new();
+        storage
+            .play(|event| {
+                played.push(event);
+                Ok(())
+            })
+            .unwrap();
+        let imported = played.iter().find(|e| e.id() == imported.id()).unwrap();
+        assert_eq!(imported.metadata()[IMPORT_SOURCE], "people.csv");
+        assert_eq!(imported.metadata()[APP_VERSION], env!("CARGO_PKG_VERSION"));
+        let old = played.iter().find(|e| e.id() == old.id()).unwrap();
+        assert!(old.metadata().is_empty());
+    }
+}
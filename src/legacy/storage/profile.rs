@@ -0,0 +1,214 @@
+//! The slow-query log: every SQL statement taking longer than a threshold is
+//! appended to a file with how long it took and how many rows it returned or
+//! changed, so users with large graphs can report what is slow.
+//!
+//! Each line is tab separated: when the statement finished in unix seconds,
+//! its duration in milliseconds, its rows and its SQL on one line. The SQL is
+//! logged without the values bound to it, so the log holds no graph data.
+
+use crate::legacy::error::{Context, Result};
+use rusqlite::{ffi, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::{c_int, c_uint, c_void, CStr};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use time::OffsetDateTime;
+use tracing::warn;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlowLog {
+    /// The file the statements are appended to.
+    pub path: PathBuf,
+    /// Statements taking at least this many milliseconds are logged.
+    pub threshold_ms: u64,
+}
+
+/// A statement of the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// When it finished, in unix seconds.
+    pub time: i64,
+    pub millis: u64,
+    /// The rows a query returned, or the rows a write changed.
+    pub rows: u64,
+    pub sql: String,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Entry> {
+        let mut fields = line.splitn(4, '\t');
+        Some(Entry {
+            time: fields.next()?.parse().ok()?,
+            millis: fields.next()?.parse().ok()?,
+            rows: fields.next()?.parse().ok()?,
+            sql: fields.next()?.to_string(),
+        })
+    }
+}
+
+/// The state of the hook of a connection, which must outlive it.
+pub(super) struct Profiler {
+    threshold_ns: i64,
+    file: Mutex<File>,
+    /// The rows each running statement returned so far, by its address.
+    rows: Mutex<HashMap<usize, u64>>,
+}
+
+/// Logs the statements of `conn` slower than the threshold of `log`. The
+/// hook must be removed with [`uninstall`] before the profiler is dropped.
+pub(super) fn install(conn: &Connection, log: &SlowLog) -> Result<Box<Profiler>> {
+    if let Some(dir) = log.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log.path)
+        .context(format!("Failed to open {}", log.path.display()))?;
+    let profiler = Box::new(Profiler {
+        threshold_ns: i64::try_from(log.threshold_ms)
+            .unwrap_or(i64::MAX)
+            .saturating_mul(1_000_000),
+        file: Mutex::new(file),
+        rows: Mutex::new(HashMap::new()),
+    });
+    let context = &*profiler as *const Profiler as *mut c_void;
+    let mask = (ffi::SQLITE_TRACE_PROFILE | ffi::SQLITE_TRACE_ROW) as c_uint;
+    // SAFETY: the connection is open, and the profiler the callback reads is
+    // boxed, so it stays put, and outlives the hook.
+    let result = unsafe { ffi::sqlite3_trace_v2(conn.handle(), mask, Some(trace), context) };
+    if result != ffi::SQLITE_OK {
+        return Err(rusqlite::Error::SqliteFailure(
+            ffi::Error::new(result),
+            None,
+        ))
+        .context("Failed to set up the slow-query log");
+    }
+    Ok(profiler)
+}
+
+/// Removes the hook of [`install`] from `conn`.
+pub(super) fn uninstall(conn: &Connection) {
+    // SAFETY: the connection is open, and no callback is set.
+    unsafe { ffi::sqlite3_trace_v2(conn.handle(), 0, None, std::ptr::null_mut()) };
+}
+
+/// Called by SQLite for every row a statement returns and once it finishes.
+unsafe extern "C" fn trace(
+    mask: c_uint,
+    context: *mut c_void,
+    p: *mut c_void,
+    x: *mut c_void,
+) -> c_int {
+    // SAFETY: `context` is the profiler given to `sqlite3_trace_v2`.
+    let profiler = unsafe { &*(context as *const Profiler) };
+    let statement = p as *mut ffi::sqlite3_stmt;
+    let mut rows = profiler.rows.lock().unwrap_or_else(|e| e.into_inner());
+    if mask == ffi::SQLITE_TRACE_ROW as c_uint {
+        *rows.entry(statement as usize).or_default() += 1;
+        return 0;
+    }
+    if mask != ffi::SQLITE_TRACE_PROFILE as c_uint {
+        return 0;
+    }
+    let returned = rows.remove(&(statement as usize)).unwrap_or(0);
+    drop(rows);
+    // SAFETY: for a profile, `x` points to the nanoseconds it took.
+    let nanos = unsafe { *(x as *const i64) };
+    if nanos < profiler.threshold_ns {
+        return 0;
+    }
+    // SAFETY: the statement is valid until the callback returns.
+    let (sql, rows) = unsafe {
+        let sql = ffi::sqlite3_sql(statement);
+        let sql = match sql.is_null() {
+            true => String::new(),
+            false => CStr::from_ptr(sql).to_string_lossy().into_owned(),
+        };
+        let rows = match ffi::sqlite3_stmt_readonly(statement) {
+            0 => ffi::sqlite3_changes(ffi::sqlite3_db_handle(statement)).max(0) as u64,
+            _ => returned,
+        };
+        (sql, rows)
+    };
+    let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    let line = format!(
+        "{}\t{}\t{}\t{}\n",
+        OffsetDateTime::now_utc().unix_timestamp(),
+        nanos / 1_000_000,
+        rows,
+        sql
+    );
+    let mut file = profiler.file.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = file.write_all(line.as_bytes()) {
+        warn!("Failed to write to the slow-query log: {}", e);
+    }
+    0
+}
+
+/// The last `limit` statements of the log at `path`, newest first. A log
+/// that doesn't exist yet has none.
+pub fn read(path: &Path, limit: usize) -> Result<Vec<Entry>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(format!("Failed to open {}", path.display())),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.context(format!("Failed to read {}", path.display()))?;
+        entries.extend(Entry::parse(&line));
+    }
+    entries.reverse();
+    entries.truncate(limit);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::{EventStorage, StorageConfig};
+
+    #[test]
+    fn logs_statements_over_the_threshold() {
+        let path = std::env::temp_dir().join(format!("{}.log", uuid::Uuid::new_v4()));
+        let config = StorageConfig {
+            slow_queries: Some(SlowLog {
+                path: path.clone(),
+                threshold_ms: 0,
+            }),
+            ..StorageConfig::default()
+        };
+        let storage = EventStorage::open_with(":memory:", &config).unwrap();
+        storage
+            .conn
+            .execute_batch(
+                "CREATE TABLE numbers (n INTEGER);
+                INSERT INTO numbers VALUES (1), (2), (3);",
+            )
+            .unwrap();
+        let sum: i64 = storage
+            .conn
+            .query_row("SELECT   SUM(n)\n FROM numbers WHERE n > ?", [1], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(sum, 5);
+        storage
+            .conn
+            .execute("DELETE FROM numbers WHERE n < 3", [])
+            .unwrap();
+        drop(storage);
+
+        let entries = read(&path, 2).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries[0].sql, "DELETE FROM numbers WHERE n < 3");
+        assert_eq!(entries[0].rows, 2);
+        assert_eq!(entries[1].sql, "SELECT SUM(n) FROM numbers WHERE n > ?");
+        assert_eq!(entries[1].rows, 1);
+        assert!(read(&path, 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+//! Statistics of the database, for the `stats` command and the dashboard.
+//!
+//! They are counted from the events and their `event_subjects` links without
+//! replaying the log, so entities and facts are counted as the events
+//! mention them, deleted ones included.
+
+use super::EventStorage;
+use crate::legacy::error::{Context, Result};
+use rusqlite::{OptionalExtension, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub total: u64,
+    /// The number of entities the events mention.
+    pub entities: u64,
+    /// The number of events changing each predicate, most changed first.
+    pub per_predicate: Vec<(String, u64)>,
+    /// The number of events of each actor, most active first.
+    pub per_actor: Vec<(Uuid, u64)>,
+    /// The size of the database file in bytes.
+    pub size: u64,
+    /// When events from elsewhere were last merged in, as a Unix timestamp.
+    pub last_sync: Option<i64>,
+}
+
+impl EventStorage {
+    #[instrument(skip(self))]
+    pub fn stats(&self) -> Result<Stats> {
+        let per_actor = self.counts(
+            "SELECT actor, COUNT(*) FROM events GROUP BY actor ORDER BY COUNT(*) DESC, actor",
+        )?;
+        let per_predicate = self.counts(
+            "SELECT predicate, COUNT(DISTINCT event) FROM event_subjects
+            WHERE predicate IS NOT NULL
+            GROUP BY predicate ORDER BY COUNT(DISTINCT event) DESC, predicate",
+        )?;
+        let entities = self
+            .conn
+            .query_row(
+                "SELECT COUNT(DISTINCT subject) FROM event_subjects",
+                [],
+                |row| row.get(0),
+            )
+            .context("Failed to count entities")?;
+        let size: i64 = self
+            .conn
+            .query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                [],
+                |row| row.get(0),
+            )
+            .context("Failed to read the database size")?;
+        let last_sync = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'last_sync'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("Failed to read the last sync")?;
+        Ok(Stats {
+            total: per_actor.iter().map(|(_, count)| count).sum(),
+            entities,
+            per_predicate,
+            per_actor,
+            size: size as u64,
+            last_sync,
+        })
+    }
+
+    /// The rows of a `SELECT key, COUNT(*)` query.
+    fn counts<K: rusqlite::types::FromSql>(&self, sql: &str) -> Result<Vec<(K, u64)>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(sql)
+            .context("Failed to prepare SQL statement to count events")?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .context("Failed to count events")?
+            .collect::<rusqlite::Result<_>>()
+            .context("Failed to count events")?;
+        Ok(counts)
+    }
+}
+
+/// Remembers that events from elsewhere were merged in now.
+pub(super) fn synced(tx: &Transaction) -> Result<()> {
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    tx.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('last_sync', ?)",
+        [now],
+    )
+    .context("Failed to record the last sync")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::{Action, Datum, EventCreator};
+
+    #[test]
+    fn stats_count_events_entities_and_predicates() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let mut local = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let mut remote = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let name = |subject, name: &str| Action::AddFact {
+            subject,
+            predicate: "name".to_string(),
+            datum: Datum::String(name.to_string()),
+        };
+        storage
+            .record_batch(vec![
+                local.create(Action::CreateEntity { id: a }),
+                local.create(name(a, "A")),
+                local.create(Action::Transaction {
+                    actions: vec![name(a, "Renamed"), name(b, "B")],
+                }),
+            ])
+            .unwrap();
+        assert_eq!(storage.stats().unwrap().last_sync, None);
+        let merged = vec![remote.create(Action::RemoveFact {
+            subject: b,
+            predicate: "done".to_string(),
+        })];
+        storage.merge(merged).unwrap();
+
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.total, 4);
+        assert_eq!(stats.entities, 2);
+        assert_eq!(
+            stats.per_predicate,
+            [("name".to_string(), 2), ("done".to_string(), 1)]
+        );
+        assert_eq!(stats.per_actor, [(local.actor(), 3), (remote.actor(), 1)]);
+        assert!(stats.size > 0);
+        assert!(stats.last_sync.is_some());
+    }
+}
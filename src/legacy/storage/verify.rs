@@ -0,0 +1,430 @@
+//! Integrity checks for the event log, and repairs for what they find.
+
+use super::{action, maintenance, Action, EventStorage};
+use crate::legacy::error::{Context, Result};
+use crate::legacy::hlc::HLTimestamp;
+use rusqlite::types::ValueRef;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use time::OffsetDateTime;
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// A UUID column does not hold a 16 byte blob.
+    InvalidUuid {
+        column: &'static str,
+    },
+    /// The logical clock does not fit in 16 bits.
+    InvalidLogical,
+    /// The event is not later than an event its actor recorded before it.
+    TimestampRegression {
+        previous: HLTimestamp,
+    },
+    /// The event has the same timestamp as an event its actor recorded before.
+    DuplicateTimestamp,
+    UndecodableAction {
+        error: String,
+    },
+    /// A transaction without actions.
+    EmptyTransaction,
+    /// A link in `event_subjects` to an event that does not exist.
+    OrphanLink,
+    /// Another event was recorded with the same id before this one.
+    DuplicateId,
+    /// The hash of the event doesn't follow from the event of its actor
+    /// before it, see [`super::chain`].
+    BrokenChain,
+}
+
+impl ProblemKind {
+    /// Whether [`EventStorage::repair`] gives the event a new timestamp
+    /// rather than quarantining it.
+    fn is_resequenced(&self) -> bool {
+        matches!(
+            self,
+            ProblemKind::TimestampRegression { .. } | ProblemKind::DuplicateTimestamp
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The rowid of the event, or of the link for an [`ProblemKind::OrphanLink`].
+    pub row: i64,
+    pub kind: ProblemKind,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ProblemKind::InvalidUuid { column } => {
+                write!(f, "event {}: {} is not a UUID", self.row, column)
+            }
+            ProblemKind::InvalidLogical => {
+                write!(f, "event {}: logical clock out of range", self.row)
+            }
+            ProblemKind::TimestampRegression { previous } => write!(
+                f,
+                "event {}: timestamp is before {} of the same actor",
+                self.row, previous
+            ),
+            ProblemKind::DuplicateTimestamp => write!(
+                f,
+                "event {}: timestamp is shared with an earlier event of the same actor",
+                self.row
+            ),
+            ProblemKind::UndecodableAction { error } => {
+                write!(f, "event {}: undecodable action: {}", self.row, error)
+            }
+            ProblemKind::EmptyTransaction => write!(f, "event {}: empty transaction", self.row),
+            ProblemKind::OrphanLink => {
+                write!(f, "link {}: refers to a missing event", self.row)
+            }
+            ProblemKind::DuplicateId => write!(f, "event {}: duplicate id", self.row),
+            ProblemKind::BrokenChain => write!(
+                f,
+                "event {}: hash chain broken, it or an earlier event of the same actor was removed, reordered or changed",
+                self.row
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The number of events checked.
+    pub events: u64,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Repairs {
+    /// Events moved to the `quarantine` table.
+    pub quarantined: usize,
+    /// Events given a new timestamp.
+    pub resequenced: usize,
+    /// Orphan links removed.
+    pub unlinked: usize,
+}
+
+fn is_uuid(value: ValueRef) -> bool {
+    matches!(value, ValueRef::Blob(bytes) if bytes.len() == 16)
+}
+
+/// The smallest timestamp after `hlc`.
+fn successor(hlc: HLTimestamp) -> HLTimestamp {
+    match hlc.logical().checked_add(1) {
+        Some(logical) => HLTimestamp::new(hlc.seconds(), logical),
+        None => HLTimestamp::new(hlc.seconds() + 1, 0),
+    }
+}
+
+impl EventStorage {
+    /// Checks every event and link, without changing anything.
+    ///
+    /// Timestamps are checked per actor in the order the events were
+    /// inserted, since an actor's clock never goes backwards.
+    #[instrument(skip_all)]
+    pub fn verify(&self) -> Result<Report> {
+        let mut report = Report::default();
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT rowid, id, hlc_seconds, hlc_logical, action, codec, actor, subject
+                FROM events ORDER BY rowid",
+            )
+            .context("Failed to prepare SQL statement to verify events")?;
+        let mut rows = stmt.query([]).context("Failed to read events")?;
+        let mut ids = HashSet::new();
+        let mut latest: HashMap<Vec<u8>, HLTimestamp> = HashMap::new();
+        while let Some(row) = rows.next().context("Failed to read an event")? {
+            report.events += 1;
+            let rowid: i64 = row.get(0)?;
+            let mut problem = |kind| report.problems.push(Problem { row: rowid, kind });
+
+            for (index, column) in [(1, "id"), (6, "actor")] {
+                if !is_uuid(row.get_ref(index)?) {
+                    problem(ProblemKind::InvalidUuid { column });
+                }
+            }
+            if !matches!(row.get_ref(7)?, ValueRef::Null) && !is_uuid(row.get_ref(7)?) {
+                problem(ProblemKind::InvalidUuid { column: "subject" });
+            }
+            if !ids.insert(row.get_ref(1)?.as_bytes().unwrap_or_default().to_vec()) {
+                problem(ProblemKind::DuplicateId);
+            }
+
+            match action(row, 4) {
+                Ok(Action::Transaction { actions }) if actions.is_empty() => {
+                    problem(ProblemKind::EmptyTransaction)
+                }
+                Ok(_) => {}
+                Err(e) => problem(ProblemKind::UndecodableAction {
+                    error: e.to_string(),
+                }),
+            }
+
+            let Ok(logical) = u16::try_from(row.get::<_, i64>(3)?) else {
+                problem(ProblemKind::InvalidLogical);
+                continue;
+            };
+            let hlc = HLTimestamp::new(row.get(2)?, logical);
+            if let ValueRef::Blob(actor) = row.get_ref(6)? {
+                match latest.get(actor) {
+                    Some(previous) if hlc == *previous => problem(ProblemKind::DuplicateTimestamp),
+                    Some(previous) if hlc < *previous => {
+                        problem(ProblemKind::TimestampRegression {
+                            previous: *previous,
+                        })
+                    }
+                    _ => {
+                        latest.insert(actor.to_vec(), hlc);
+                    }
+                }
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT rowid FROM event_subjects
+                WHERE event NOT IN (SELECT id FROM events) ORDER BY rowid",
+            )
+            .context("Failed to prepare SQL statement to verify links")?;
+        let orphans = stmt
+            .query_map([], |row| row.get(0))
+            .context("Failed to read links")?;
+        for row in orphans {
+            report.problems.push(Problem {
+                row: row.context("Failed to read a link")?,
+                kind: ProblemKind::OrphanLink,
+            });
+        }
+
+        if self.chained {
+            for row in self.broken_links()? {
+                report.problems.push(Problem {
+                    row,
+                    kind: ProblemKind::BrokenChain,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Repairs what [`EventStorage::verify`] finds.
+    ///
+    /// Events whose timestamps go backwards are given the next timestamp of
+    /// their actor. Any other broken event is moved to the `quarantine`
+    /// table, with the reason, where it can be inspected. Orphan links are
+    /// removed.
+    ///
+    /// Re-sequenced events keep their old timestamps on replicas that
+    /// already have them. A hash chain is sealed again from the start, as it
+    /// is after the repairs.
+    #[instrument(skip_all)]
+    pub fn repair(&mut self) -> Result<Repairs> {
+        self.writable()?;
+        let report = self.verify()?;
+        let mut repairs = Repairs::default();
+        let tx = self
+            .conn
+            .transaction()
+            .context("Failed to open a transaction")?;
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        maintenance::create_quarantine(&tx, now)?;
+
+        let mut quarantined = HashSet::new();
+        for problem in &report.problems {
+            if matches!(
+                problem.kind,
+                ProblemKind::OrphanLink | ProblemKind::BrokenChain
+            ) || problem.kind.is_resequenced()
+            {
+                continue;
+            }
+            if !quarantined.insert(problem.row) {
+                continue;
+            }
+            tx.execute(
+                "INSERT INTO quarantine (row, id, hlc_seconds, hlc_logical, action, actor,
+                version, subject, predicate, codec, reason, quarantined)
+                SELECT rowid, id, hlc_seconds, hlc_logical, action, actor, version, subject,
+                predicate, codec, ?, ? FROM events WHERE rowid = ?",
+                rusqlite::params![problem.to_string(), now, problem.row],
+            )
+            .context("Failed to quarantine an event")?;
+            tx.execute("DELETE FROM events WHERE rowid = ?", [problem.row])
+                .context("Failed to quarantine an event")?;
+            repairs.quarantined += 1;
+        }
+
+        // Quarantined events may leave links behind, so look for orphans after.
+        repairs.unlinked = tx
+            .execute(
+                "DELETE FROM event_subjects WHERE event NOT IN (SELECT id FROM events)",
+                [],
+            )
+            .context("Failed to remove orphan links")?;
+
+        {
+            let mut select = tx.prepare(
+                "SELECT rowid, actor, hlc_seconds, hlc_logical FROM events ORDER BY rowid",
+            )?;
+            let mut update =
+                tx.prepare("UPDATE events SET hlc_seconds = ?, hlc_logical = ? WHERE rowid = ?")?;
+            let rows = select.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Uuid>(1)?,
+                    HLTimestamp::new(row.get(2)?, row.get(3)?),
+                ))
+            })?;
+            let mut latest: HashMap<Uuid, HLTimestamp> = HashMap::new();
+            for row in rows {
+                let (rowid, actor, hlc) = row.context("Failed to read an event")?;
+                let hlc = match latest.get(&actor) {
+                    Some(previous) if hlc <= *previous => {
+                        let next = successor(*previous);
+                        update
+                            .execute(rusqlite::params![next.seconds(), next.logical(), rowid])
+                            .context("Failed to re-sequence an event")?;
+                        repairs.resequenced += 1;
+                        next
+                    }
+                    _ => hlc,
+                };
+                latest.insert(actor, hlc);
+            }
+        }
+
+        tx.commit().context("Failed to commit the repairs")?;
+        if self.chained {
+            self.seal_chain(true)?;
+        }
+        info!(
+            quarantined = repairs.quarantined,
+            resequenced = repairs.resequenced,
+            unlinked = repairs.unlinked,
+            "Repaired the database"
+        );
+        Ok(repairs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Event;
+
+    fn event(actor: Uuid, seconds: i64, action: Action) -> Event {
+        Event {
+            id: Uuid::new_v4(),
+            hlc: HLTimestamp::new(seconds, 0),
+            action,
+            actor,
+            version: 0,
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn verify_finds_and_repair_fixes_problems() {
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let create = || Action::CreateEntity { id: Uuid::new_v4() };
+        storage
+            .record_batch(vec![
+                event(a, 5, create()),
+                event(b, 1, create()),
+                // Goes backwards for `a`, but not for `b`.
+                event(a, 3, create()),
+                event(a, 5, Action::Transaction { actions: vec![] }),
+                event(a, 6, create()),
+            ])
+            .unwrap();
+        let conn = &storage.conn;
+        conn.execute("UPDATE events SET action = 'nonsense' WHERE rowid = 5", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO event_subjects (event, subject) VALUES (?, ?)",
+            [Uuid::new_v4(), Uuid::new_v4()],
+        )
+        .unwrap();
+        conn.execute("UPDATE events SET actor = x'00' WHERE rowid = 2", [])
+            .unwrap();
+
+        let report = storage.verify().unwrap();
+        assert_eq!(report.events, 5);
+        let kinds: Vec<(i64, &ProblemKind)> =
+            report.problems.iter().map(|p| (p.row, &p.kind)).collect();
+        assert_eq!(
+            kinds[..4],
+            [
+                (2, &ProblemKind::InvalidUuid { column: "actor" }),
+                (
+                    3,
+                    &ProblemKind::TimestampRegression {
+                        previous: HLTimestamp::new(5, 0)
+                    }
+                ),
+                (4, &ProblemKind::EmptyTransaction),
+                (4, &ProblemKind::DuplicateTimestamp),
+            ]
+        );
+        assert!(matches!(
+            kinds[4],
+            (5, ProblemKind::UndecodableAction { .. })
+        ));
+        assert_eq!(kinds[5].1, &ProblemKind::OrphanLink);
+
+        let repairs = storage.repair().unwrap();
+        assert_eq!(
+            repairs,
+            Repairs {
+                quarantined: 3,
+                resequenced: 1,
+                // The orphan and the links of the two quarantined entities.
+                unlinked: 3,
+            }
+        );
+        assert!(storage.verify().unwrap().is_ok());
+        let quarantined: i64 = storage
+            .conn
+            .query_row("SELECT COUNT(*) FROM quarantine", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(quarantined, 3);
+        assert_eq!(storage.prune_quarantine(0).unwrap(), 0);
+        assert_eq!(storage.prune_quarantine(i64::MAX).unwrap(), 3);
+    }
+
+    #[test]
+    fn undated_quarantines_are_kept_from_now_on() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        // A quarantine from before it recorded when events were quarantined.
+        storage
+            .conn
+            .execute_batch(
+                "CREATE TABLE quarantine (
+                    row INTEGER NOT NULL, id BLOB, hlc_seconds INTEGER,
+                    hlc_logical INTEGER, action BLOB, actor BLOB, version INTEGER,
+                    subject BLOB, predicate TEXT, codec INTEGER, reason TEXT NOT NULL
+                );
+                INSERT INTO quarantine (row, reason) VALUES (1, 'undecodable');",
+            )
+            .unwrap();
+        let day_ago = OffsetDateTime::now_utc().unix_timestamp() - 24 * 60 * 60;
+        assert_eq!(storage.prune_quarantine(day_ago).unwrap(), 0);
+        // The next maintenance, a minute later.
+        assert_eq!(storage.prune_quarantine(day_ago + 60).unwrap(), 0);
+        assert_eq!(storage.prune_quarantine(i64::MAX).unwrap(), 1);
+    }
+}
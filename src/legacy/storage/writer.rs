@@ -0,0 +1,92 @@
+//! The write lock that keeps two processes from writing to one database.
+//!
+//! SQLite keeps each write consistent, but two Graphite processes writing
+//! the same log interleave their edits confusingly. A process that opens a
+//! database writable holds an exclusive lock on `<database>.lock` for as
+//! long as it is open, with its process id in the file. Another one opening
+//! it writable fails with [`GraphiteError::Locked`], and can open it
+//! read-only instead. The operating system releases the lock when the
+//! process exits, even if it crashed.
+
+use crate::legacy::error::{Context, GraphiteError, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// The process holding the write lock of a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Holder {
+    /// Its process id, if it could be read.
+    pub pid: Option<u32>,
+}
+
+/// The lock file of the database at `path`.
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Takes the write lock of the database at `path`, which is held until the
+/// returned file is dropped. In-memory databases need none.
+pub(super) fn acquire(path: &Path) -> Result<Option<File>> {
+    if path.as_os_str().is_empty() || path == Path::new(":memory:") {
+        return Ok(None);
+    }
+    let lock = lock_path(path);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock)
+        .with_context(|| format!("Failed to open {}", lock.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut pid = String::new();
+            let _ = file.read_to_string(&mut pid);
+            return Err(GraphiteError::Locked(Holder {
+                pid: pid.trim().parse().ok(),
+            }));
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock {}", lock.display()))
+        }
+    }
+    file.set_len(0)
+        .and_then(|()| file.rewind())
+        .and_then(|()| write!(file, "{}", std::process::id()))
+        .with_context(|| format!("Failed to write {}", lock.display()))?;
+    Ok(Some(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::EventStorage;
+    use super::*;
+    use crate::legacy::storage::StorageConfig;
+    use uuid::Uuid;
+
+    #[test]
+    fn a_second_writer_is_refused() {
+        let path = std::env::temp_dir().join(format!("graphite-{}.db", Uuid::new_v4()));
+        let first = EventStorage::open(&path).unwrap();
+        match EventStorage::open(&path) {
+            Err(GraphiteError::Locked(holder)) => {
+                assert_eq!(holder.pid, Some(std::process::id()))
+            }
+            other => panic!("expected the database to be locked, got {:?}", other.err()),
+        }
+        let config = StorageConfig {
+            read_only: true,
+            ..StorageConfig::default()
+        };
+        assert!(EventStorage::open_with(&path, &config).is_ok());
+
+        drop(first);
+        assert!(EventStorage::open(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(lock_path(&path)).unwrap();
+    }
+}
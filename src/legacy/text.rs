@@ -0,0 +1,260 @@
+//! Texts that several devices edit at once, such as notes.
+//!
+//! A text is a sequence of characters, each with an id of its own that never
+//! changes (RGA, a replicated growable array). Edits refer to characters by
+//! id instead of by position, so they still apply after edits made
+//! elsewhere meanwhile: `InsertText` puts characters after a given one, and
+//! `DeleteText` hides the given ones, leaving a tombstone for inserts after
+//! them. Characters inserted after the same one at once are ordered by their
+//! ids, which count up from the largest seen, so that what was typed later
+//! comes first, as it would have if typed on one device.
+
+use crate::legacy::storage::Action;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The id of a character: the edit that inserted it and its place in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CharId {
+    /// Larger than that of every character seen by the edit.
+    pub seq: u64,
+    /// Tells apart the edits of different devices with the same `seq`.
+    pub tag: Uuid,
+    pub offset: u32,
+}
+
+/// `len` characters inserted together, from `start` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: CharId,
+    pub len: u32,
+}
+
+impl Span {
+    fn contains(&self, id: &CharId) -> bool {
+        id.seq == self.start.seq
+            && id.tag == self.start.tag
+            && (self.start.offset..self.start.offset.saturating_add(self.len)).contains(&id.offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Char {
+    id: CharId,
+    value: char,
+    deleted: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Text {
+    chars: Vec<Char>,
+}
+
+impl Text {
+    /// Inserts `text` after the character `after`, or at the start, with
+    /// the ids from `id` on. Inserting the same characters again, after a
+    /// character not seen yet, or with offsets past `u32::MAX`, does
+    /// nothing.
+    pub fn insert(&mut self, after: Option<CharId>, id: CharId, text: &str) {
+        if self.chars.iter().any(|c| c.id == id) {
+            return;
+        }
+        let mut index = match after {
+            Some(after) => match self.chars.iter().position(|c| c.id == after) {
+                Some(index) => index + 1,
+                None => return,
+            },
+            None => 0,
+        };
+        // Characters inserted at the same place by edits that hadn't seen
+        // this one have larger ids, and so do the ones inserted after them.
+        while self.chars.get(index).is_some_and(|c| c.id > id) {
+            index += 1;
+        }
+        let chars: Option<Vec<Char>> = text
+            .chars()
+            .enumerate()
+            .map(|(i, value)| {
+                let offset = u32::try_from(i).ok()?;
+                Some(Char {
+                    id: CharId {
+                        offset: id.offset.checked_add(offset)?,
+                        ..id
+                    },
+                    value,
+                    deleted: false,
+                })
+            })
+            .collect();
+        if let Some(chars) = chars {
+            self.chars.splice(index..index, chars);
+        }
+    }
+
+    /// Deletes the characters in `spans`.
+    pub fn delete(&mut self, spans: &[Span]) {
+        for c in &mut self.chars {
+            if spans.iter().any(|span| span.contains(&c.id)) {
+                c.deleted = true;
+            }
+        }
+    }
+
+    /// Applies the edits of `action` to `predicate` of `subject`, including
+    /// those in transactions.
+    pub fn apply(&mut self, subject: Uuid, predicate: &str, action: &Action) {
+        match action {
+            Action::InsertText {
+                subject: s,
+                predicate: p,
+                after,
+                id,
+                text,
+            } if *s == subject && p == predicate => self.insert(*after, *id, text),
+            Action::DeleteText {
+                subject: s,
+                predicate: p,
+                spans,
+            } if *s == subject && p == predicate => self.delete(spans),
+            Action::RemoveFact {
+                subject: s,
+                predicate: p,
+            } if *s == subject && p == predicate => self.chars.clear(),
+            Action::Transaction { actions } => actions
+                .iter()
+                .for_each(|action| self.apply(subject, predicate, action)),
+            _ => {}
+        }
+    }
+
+    /// The actions that turn this text of `predicate` of `subject` into
+    /// `new`, replacing what lies between the start and the end they share.
+    pub fn edit(&self, subject: Uuid, predicate: &str, new: &str) -> Vec<Action> {
+        let old: Vec<&Char> = self.chars.iter().filter(|c| !c.deleted).collect();
+        let new: Vec<char> = new.chars().collect();
+        let prefix = old
+            .iter()
+            .zip(&new)
+            .take_while(|(c, n)| c.value == **n)
+            .count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(c, n)| c.value == **n)
+            .count();
+
+        let mut actions = Vec::new();
+        let mut spans: Vec<Span> = Vec::new();
+        for c in &old[prefix..old.len() - suffix] {
+            match spans.last_mut() {
+                Some(span)
+                    if span.start.seq == c.id.seq
+                        && span.start.tag == c.id.tag
+                        && span.start.offset + span.len == c.id.offset =>
+                {
+                    span.len += 1
+                }
+                _ => spans.push(Span {
+                    start: c.id,
+                    len: 1,
+                }),
+            }
+        }
+        if !spans.is_empty() {
+            actions.push(Action::DeleteText {
+                subject,
+                predicate: predicate.to_string(),
+                spans,
+            });
+        }
+        let inserted: String = new[prefix..new.len() - suffix].iter().collect();
+        if !inserted.is_empty() {
+            actions.push(Action::InsertText {
+                subject,
+                predicate: predicate.to_string(),
+                after: prefix.checked_sub(1).map(|i| old[i].id),
+                id: CharId {
+                    seq: self.chars.iter().map(|c| c.id.seq).max().unwrap_or(0) + 1,
+                    tag: Uuid::new_v4(),
+                    offset: 0,
+                },
+                text: inserted,
+            });
+        }
+        actions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.iter().all(|c| c.deleted)
+    }
+}
+
+impl std::fmt::Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for c in self.chars.iter().filter(|c| !c.deleted) {
+            write!(f, "{}", c.value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_edits_keep_both_sides() {
+        let (subject, predicate) = (Uuid::new_v4(), "note");
+        let mut base = Text::default();
+        for action in base.edit(subject, predicate, "Buy milk") {
+            base.apply(subject, predicate, &action);
+        }
+
+        let (mut laptop, mut phone) = (base.clone(), base.clone());
+        let on_laptop = laptop.edit(subject, predicate, "Buy oat milk");
+        let on_phone = phone.edit(subject, predicate, "Buy milk and eggs");
+        let on_phone_later = {
+            let mut phone = phone.clone();
+            on_phone
+                .iter()
+                .for_each(|a| phone.apply(subject, predicate, a));
+            phone.edit(subject, predicate, "Buy milk, eggs")
+        };
+        for action in on_laptop.iter().chain(&on_phone).chain(&on_phone_later) {
+            laptop.apply(subject, predicate, action);
+        }
+        for action in on_phone.iter().chain(&on_phone_later).chain(&on_laptop) {
+            phone.apply(subject, predicate, action);
+        }
+        assert_eq!(laptop, phone);
+        assert_eq!(laptop.to_string(), "Buy oat milk, eggs");
+
+        // Typing at the same place on both keeps both, in the same order.
+        let (mut left, mut right) = (laptop.clone(), laptop.clone());
+        let a = left.edit(subject, predicate, "Buy oat milk, eggs!");
+        let b = right.edit(subject, predicate, "Buy oat milk, eggs?");
+        a.iter()
+            .chain(&b)
+            .for_each(|x| left.apply(subject, predicate, x));
+        b.iter()
+            .chain(&a)
+            .for_each(|x| right.apply(subject, predicate, x));
+        assert_eq!(left.to_string(), right.to_string());
+        assert_eq!(left.to_string().len(), "Buy oat milk, eggs!?".len());
+    }
+
+    #[test]
+    fn inserts_past_the_last_offset_are_ignored() {
+        let mut text = Text::default();
+        let id = CharId {
+            seq: 1,
+            tag: Uuid::nil(),
+            offset: u32::MAX - 1,
+        };
+        text.insert(None, id, "abc");
+        assert!(text.is_empty());
+        text.insert(None, id, "ab");
+        assert_eq!(text.to_string(), "ab");
+    }
+}
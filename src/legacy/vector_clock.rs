@@ -0,0 +1,121 @@
+//! Vector clocks, recording which events an event had seen.
+//!
+//! Timestamps order events but don't tell whether one replica had seen the
+//! other's edit when it made its own. With causal metadata turned on, see
+//! [`crate::legacy::storage::StorageConfig::causal`], each event carries the
+//! clock of its creator in its [`metadata::CLOCK`]: for every actor, the
+//! sequence number of the last of its events seen, its own included. An
+//! event happened before another if the other had seen it, and two events
+//! neither of which had seen the other are concurrent.
+
+use crate::legacy::storage::{metadata, Event};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VectorClock(BTreeMap<Uuid, u64>);
+
+impl VectorClock {
+    /// The sequence number of the last event of `actor` seen.
+    pub fn get(&self, actor: &Uuid) -> u64 {
+        self.0.get(actor).copied().unwrap_or(0)
+    }
+
+    /// Counts a new event of `actor`, returning its sequence number.
+    pub fn tick(&mut self, actor: Uuid) -> u64 {
+        let seq = self.0.entry(actor).or_insert(0);
+        *seq += 1;
+        *seq
+    }
+
+    /// Takes in everything `other` had seen.
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (actor, seq) in &other.0 {
+            let mine = self.0.entry(*actor).or_insert(0);
+            *mine = (*mine).max(*seq);
+        }
+    }
+}
+
+/// As `actor=seq` pairs separated by commas.
+impl Display for VectorClock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, (actor, seq)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", actor.simple(), seq)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for VectorClock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut clock = VectorClock::default();
+        for pair in s.split(',').filter(|p| !p.is_empty()) {
+            let (actor, seq) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid vector clock entry: {}", pair))?;
+            let actor = Uuid::parse_str(actor).map_err(|e| e.to_string())?;
+            let seq = seq.parse().map_err(|e| format!("{}: {}", pair, e))?;
+            clock.0.insert(actor, seq);
+        }
+        Ok(clock)
+    }
+}
+
+/// The clock `event` was created with, if it has one.
+pub fn clock(event: &Event) -> Option<VectorClock> {
+    event.metadata().get(metadata::CLOCK)?.parse().ok()
+}
+
+/// Whether `a` happened before `b`, `None` if either has no clock.
+pub fn happened_before(a: &Event, b: &Event) -> Option<bool> {
+    let (a_clock, b_clock) = (clock(a)?, clock(b)?);
+    let seq = a_clock.get(&a.actor());
+    Some(seq > 0 && b_clock.get(&a.actor()) >= seq && a.id() != b.id())
+}
+
+/// Whether neither of `a` and `b` had seen the other, `None` if either has
+/// no clock.
+pub fn concurrent(a: &Event, b: &Event) -> Option<bool> {
+    Some(!happened_before(a, b)? && !happened_before(b, a)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::{Action, EventCreator};
+
+    #[test]
+    fn clocks_tell_concurrent_events() {
+        let create = || Action::CreateEntity { id: Uuid::new_v4() };
+        let mut alice = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let mut bob = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        alice.set_clock(VectorClock::default());
+        bob.set_clock(VectorClock::default());
+
+        let first = alice.create(create());
+        bob.observe(&first);
+        let reply = bob.create(create());
+        let second = alice.create(create());
+        assert_eq!(happened_before(&first, &reply), Some(true));
+        assert_eq!(happened_before(&reply, &first), Some(false));
+        assert_eq!(concurrent(&reply, &second), Some(true));
+        assert_eq!(concurrent(&first, &second), Some(false));
+
+        let clock = clock(&reply).unwrap();
+        assert_eq!(clock.get(&alice.actor()), 1);
+        assert_eq!(clock.get(&bob.actor()), 1);
+        assert_eq!(clock.to_string().parse(), Ok(clock));
+
+        let mut plain = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        assert_eq!(concurrent(&first, &plain.create(create())), None);
+    }
+}
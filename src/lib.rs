@@ -1,2 +1,62 @@
+//! Graphite, the knowledge graph editor.
+//!
+//! The engine, the event log and the graph projected from it, is in
+//! [`core`] for tools that embed it. The editor is behind the default `gui`
+//! feature, so `default-features = false` builds without iced.
+
+pub mod api;
+pub mod archive;
+pub mod backup;
+pub mod board;
+pub mod calendar;
+pub mod chart;
+pub mod clipboard;
+pub mod clipper;
+pub mod config;
+pub mod conflicts;
+pub mod core;
+pub mod crdt;
+pub mod dashboard;
+pub mod dedupe;
+#[cfg(feature = "gui")]
 pub mod editor;
+pub mod export;
+pub mod feeds;
+pub mod folder;
+pub mod graph;
+pub mod graphql;
+pub mod hygiene;
+pub mod i18n;
+pub mod import;
+pub mod journal;
+pub mod lan;
 pub mod legacy;
+pub mod location;
+pub mod lock;
+pub mod logging;
+pub mod maintenance;
+pub mod merge;
+pub mod plugin;
+pub mod progress;
+pub mod provenance;
+pub mod query;
+pub mod recurrence;
+pub mod relay;
+pub mod replication;
+pub mod rollup;
+pub mod scheduler;
+pub mod script;
+pub mod search;
+pub mod selection;
+pub mod shell;
+pub mod shortcuts;
+pub mod simulation;
+pub mod staging;
+pub mod table;
+pub mod tasks;
+pub mod template;
+pub mod theme;
+pub mod thumbnail;
+pub mod timer;
+pub mod validation;
+pub mod wasm;
@@ -0,0 +1,305 @@
+//! Where Graphite keeps its files: the config, the default database, the
+//! plugins, the shell history and the keyring.
+//!
+//! By default they are in the platform's directories, e.g.
+//! `~/.config/graphite` on Linux. They all live in one data directory
+//! instead if
+//!
+//! 1. one is given with `--data-dir`,
+//! 2. `GRAPHITE_DATA_DIR` is set,
+//! 3. there is a file named `portable` next to the executable, which keeps
+//!    them in `data` next to it, e.g. on a USB stick, or
+//! 4. one was chosen before, see [`choose`].
+//!
+//! The identity of the local actor is kept in the database, so it moves
+//! with it.
+
+use crate::config::Config;
+use crate::legacy::storage::EventStorage;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// The environment variable that sets the data directory.
+pub const VARIABLE: &str = "GRAPHITE_DATA_DIR";
+
+/// The file next to the executable that turns on portable mode.
+const PORTABLE: &str = "portable";
+/// The file in the platform's config directory naming a chosen data
+/// directory.
+const CHOSEN: &str = "location";
+const CONFIG: &str = "config.toml";
+const DATABASE: &str = "graphite.db";
+const PLUGINS: &str = "plugins";
+const SHELL_HISTORY: &str = "shell_history";
+const FILES: &str = "files";
+const KEYRING: &str = "keyring.json";
+const SLOW_QUERIES: &str = "slow_queries.log";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// The platform's directories.
+    Platform,
+    /// One directory for everything, and why it is used.
+    Dir(PathBuf, Source),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Flag,
+    Environment,
+    Portable,
+    Chosen,
+}
+
+impl Location {
+    /// The location in use, given the `--data-dir` flag.
+    pub fn resolve(flag: Option<PathBuf>) -> Location {
+        if let Some(dir) = flag {
+            return Location::Dir(dir, Source::Flag);
+        }
+        if let Some(dir) = std::env::var_os(VARIABLE).filter(|dir| !dir.is_empty()) {
+            return Location::Dir(dir.into(), Source::Environment);
+        }
+        if let Some(dir) = portable_dir().filter(|_| is_portable()) {
+            return Location::Dir(dir, Source::Portable);
+        }
+        match chosen() {
+            Some(dir) => Location::Dir(dir, Source::Chosen),
+            None => Location::Platform,
+        }
+    }
+
+    /// The data directory, unless the platform's directories are used.
+    pub fn dir(&self) -> Option<&Path> {
+        match self {
+            Location::Platform => None,
+            Location::Dir(dir, _) => Some(dir),
+        }
+    }
+
+    /// Where the config is kept, if there is a place for it.
+    pub fn config(&self) -> Option<PathBuf> {
+        match self {
+            Location::Platform => Config::path(),
+            Location::Dir(dir, _) => Some(dir.join(CONFIG)),
+        }
+    }
+
+    /// The database at `configured`, which is relative to the data
+    /// directory if there is one.
+    pub fn database(&self, configured: &Path) -> PathBuf {
+        match self.dir() {
+            Some(dir) => dir.join(configured),
+            None => configured.to_path_buf(),
+        }
+    }
+
+    pub fn plugins(&self) -> Option<PathBuf> {
+        Some(self.config()?.parent()?.join(PLUGINS))
+    }
+
+    pub fn shell_history(&self) -> Option<PathBuf> {
+        match self {
+            Location::Platform => {
+                dirs::data_dir().map(|dir| dir.join("graphite").join(SHELL_HISTORY))
+            }
+            Location::Dir(dir, _) => Some(dir.join(SHELL_HISTORY)),
+        }
+    }
+
+    /// The keys of the graph, see [`crate::backup::keyring`].
+    pub fn keyring(&self) -> Option<PathBuf> {
+        match self {
+            Location::Platform => dirs::data_dir().map(|dir| dir.join("graphite").join(KEYRING)),
+            Location::Dir(dir, _) => Some(dir.join(KEYRING)),
+        }
+    }
+
+    /// The slow-query log, see [`crate::legacy::storage::profile`].
+    pub fn slow_queries(&self) -> Option<PathBuf> {
+        match self {
+            Location::Platform => {
+                dirs::data_dir().map(|dir| dir.join("graphite").join(SLOW_QUERIES))
+            }
+            Location::Dir(dir, _) => Some(dir.join(SLOW_QUERIES)),
+        }
+    }
+
+    /// Where files that came with an imported graph are kept, see
+    /// [`crate::archive`].
+    pub fn files(&self) -> Option<PathBuf> {
+        match self {
+            Location::Platform => dirs::data_dir().map(|dir| dir.join("graphite").join(FILES)),
+            Location::Dir(dir, _) => Some(dir.join(FILES)),
+        }
+    }
+
+    /// Whether Graphite hasn't saved a config here yet, as on its first run.
+    pub fn is_new(&self) -> bool {
+        self.config().is_none_or(|config| !config.exists())
+    }
+}
+
+/// The data directory of portable mode, `data` next to the executable.
+pub fn portable_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join("data"))
+}
+
+/// The file that turns on portable mode, next to the executable.
+fn portable_marker() -> Option<PathBuf> {
+    Some(std::env::current_exe().ok()?.parent()?.join(PORTABLE))
+}
+
+fn is_portable() -> bool {
+    portable_marker().is_some_and(|marker| marker.exists())
+}
+
+/// The file naming the chosen data directory.
+fn chosen_path() -> Option<PathBuf> {
+    Config::path()?.parent().map(|dir| dir.join(CHOSEN))
+}
+
+fn chosen() -> Option<PathBuf> {
+    let text = std::fs::read_to_string(chosen_path()?).ok()?;
+    let dir = text.trim();
+    (!dir.is_empty()).then(|| PathBuf::from(dir))
+}
+
+/// Uses `dir` as the data directory from the next start on: portable mode if
+/// it is [`portable_dir`], the platform's directories if it is `None`.
+pub fn choose(dir: Option<&Path>) -> Result<()> {
+    if let Some(marker) = portable_marker() {
+        if dir.is_some() && dir.map(Path::to_path_buf) == portable_dir() {
+            return std::fs::write(&marker, "")
+                .with_context(|| format!("Failed to write {}", marker.display()));
+        }
+        if marker.exists() {
+            std::fs::remove_file(&marker)
+                .with_context(|| format!("Failed to remove {}", marker.display()))?;
+        }
+    }
+    let Some(path) = chosen_path() else {
+        bail!("There is no config directory to remember the data directory in");
+    };
+    match dir {
+        Some(dir) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            let dir = std::path::absolute(dir)
+                .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+            std::fs::write(&path, dir.to_string_lossy().as_bytes())
+                .with_context(|| format!("Failed to write {}", path.display()))
+        }
+        None => match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Copies the config, the database of `storage`, the plugins, the shell
+/// history and the keyring from `from` into the new data directory `to`, and returns what
+/// was copied. Nothing is removed from `from`.
+///
+/// The copied config names the copied database, so that it is opened from
+/// `to`.
+pub fn migrate(
+    from: &Location,
+    config: &Config,
+    storage: &EventStorage,
+    to: &Path,
+) -> Result<Vec<PathBuf>> {
+    for name in [CONFIG, DATABASE] {
+        if to.join(name).exists() {
+            bail!("{} already has a {}", to.display(), name);
+        }
+    }
+    std::fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    let mut copied = Vec::new();
+
+    storage.copy_to(&to.join(DATABASE))?;
+    copied.push(to.join(DATABASE));
+    let config = Config {
+        database: PathBuf::from(DATABASE),
+        ..config.clone()
+    };
+    config.save(&to.join(CONFIG))?;
+    copied.push(to.join(CONFIG));
+
+    if let Some(plugins) = from.plugins().filter(|dir| dir.is_dir()) {
+        copy_dir(&plugins, &to.join(PLUGINS))?;
+        copied.push(to.join(PLUGINS));
+    }
+    for (file, name) in [
+        (from.shell_history(), SHELL_HISTORY),
+        (from.keyring(), KEYRING),
+    ] {
+        if let Some(file) = file.filter(|file| file.is_file()) {
+            std::fs::copy(&file, to.join(name))
+                .with_context(|| format!("Failed to copy {}", file.display()))?;
+            copied.push(to.join(name));
+        }
+    }
+    Ok(copied)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    for entry in
+        std::fs::read_dir(from).with_context(|| format!("Failed to read {}", from.display()))?
+    {
+        let entry = entry.with_context(|| format!("Failed to read {}", from.display()))?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+    use uuid::Uuid;
+
+    #[test]
+    fn migrated_data_opens_from_the_new_directory() {
+        let root = std::env::temp_dir().join(format!("graphite-location-{}", Uuid::new_v4()));
+        let from = Location::Dir(root.join("old"), Source::Flag);
+        std::fs::create_dir_all(root.join("old").join(PLUGINS)).unwrap();
+        std::fs::write(root.join("old").join(PLUGINS).join("a.toml"), "").unwrap();
+
+        let mut storage = EventStorage::open(":memory:").unwrap();
+        let mut creator = storage.creator().unwrap();
+        let event = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+        storage.record_batch(vec![event]).unwrap();
+        let config = Config {
+            theme: "Nord".to_string(),
+            ..Config::default()
+        };
+
+        let to = Location::Dir(root.join("new"), Source::Chosen);
+        let copied = migrate(&from, &config, &storage, to.dir().unwrap()).unwrap();
+        assert_eq!(copied.len(), 3);
+        let moved = Config::load(&to.config().unwrap()).unwrap();
+        assert_eq!(moved.theme, "Nord");
+        let database = EventStorage::open(to.database(&moved.database)).unwrap();
+        assert_eq!(database.count().unwrap(), 1);
+        assert_eq!(
+            database.local_actor().unwrap(),
+            storage.local_actor().unwrap()
+        );
+        assert!(to.plugins().unwrap().join("a.toml").exists());
+        assert!(migrate(&from, &config, &storage, to.dir().unwrap()).is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
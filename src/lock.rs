@@ -0,0 +1,156 @@
+//! Advisory edit locks, for saying "I'm restructuring this, hands off" while
+//! collaborating.
+//!
+//! A lock is two facts on the locked entity: `locked_by`, a link to the
+//! actor holding it, and `locked_until`, when it expires. It covers the
+//! entity and its descendants through the hierarchy predicate. Locks are
+//! ordinary facts, so they sync like everything else, and they never block
+//! a write: the editor only warns before an edit to an entity someone else
+//! has locked.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+pub const LOCKED_BY: &str = "locked_by";
+pub const LOCKED_UNTIL: &str = "locked_until";
+
+/// How long a lock lasts unless given otherwise, in seconds.
+pub const DEFAULT_DURATION: i64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lock {
+    /// The locked entity, which may be an ancestor of the one asked about.
+    pub entity: Uuid,
+    pub owner: Uuid,
+    /// When the lock expires, as a Unix timestamp.
+    pub until: i64,
+}
+
+/// The actions that lock `entity` for `owner` until `until`, replacing any
+/// lock it has.
+pub fn lock(entity: Uuid, owner: Uuid, until: i64) -> Vec<Action> {
+    let mut actions = unlock(entity);
+    actions.push(Action::AddFact {
+        subject: entity,
+        predicate: LOCKED_BY.to_string(),
+        datum: Datum::Entity(owner),
+    });
+    actions.push(Action::AddFact {
+        subject: entity,
+        predicate: LOCKED_UNTIL.to_string(),
+        datum: Datum::DateTime(until),
+    });
+    actions
+}
+
+pub fn unlock(entity: Uuid) -> Vec<Action> {
+    [LOCKED_BY, LOCKED_UNTIL]
+        .into_iter()
+        .map(|predicate| Action::RemoveFact {
+            subject: entity,
+            predicate: predicate.to_string(),
+        })
+        .collect()
+}
+
+/// The lock on `entity` itself, if it hasn't expired at `now`.
+pub fn of(projection: &Projection, entity: Uuid, now: i64) -> Option<Lock> {
+    let facts = projection.entity(&entity)?;
+    let (Some(Datum::Entity(owner)), Some(Datum::DateTime(until))) =
+        (facts.value(LOCKED_BY), facts.value(LOCKED_UNTIL))
+    else {
+        return None;
+    };
+    (*until > now).then_some(Lock {
+        entity,
+        owner: *owner,
+        until: *until,
+    })
+}
+
+/// The lock covering `entity` at `now`: its own, or the nearest one of its
+/// ancestors through `hierarchy`.
+pub fn covering(projection: &Projection, hierarchy: &str, entity: Uuid, now: i64) -> Option<Lock> {
+    let mut seen = HashSet::new();
+    let mut current = Some(entity);
+    while let Some(id) = current.filter(|id| seen.insert(*id)) {
+        if let Some(lock) = of(projection, id, now) {
+            return Some(lock);
+        }
+        current = match projection.entity(&id)?.value(hierarchy) {
+            Some(Datum::Entity(parent)) => Some(*parent),
+            _ => None,
+        };
+    }
+    None
+}
+
+/// The locks held by others than `actor` on the entities `actions` change.
+/// Taking or releasing a lock doesn't count as a change.
+pub fn conflicts(
+    projection: &Projection,
+    hierarchy: &str,
+    actions: &[Action],
+    actor: Uuid,
+    now: i64,
+) -> Vec<Lock> {
+    let mut locks = Vec::new();
+    for action in actions {
+        for (subject, predicate) in action.touches() {
+            if matches!(predicate, Some(LOCKED_BY | LOCKED_UNTIL)) {
+                continue;
+            }
+            let Some(lock) = covering(projection, hierarchy, subject, now) else {
+                continue;
+            };
+            if lock.owner != actor && !locks.contains(&lock) {
+                locks.push(lock);
+            }
+        }
+    }
+    locks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_cover_descendants_of_others_until_they_expire() {
+        let (me, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let (root, child) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut projection = Projection::new();
+        projection.apply(&Action::CreateEntity { id: root });
+        projection.apply(&Action::CreateEntity { id: child });
+        projection.apply(&Action::AddFact {
+            subject: child,
+            predicate: "parent".to_string(),
+            datum: Datum::Entity(root),
+        });
+        for action in lock(root, other, 100) {
+            projection.apply(&action);
+        }
+
+        let rename = [Action::AddFact {
+            subject: child,
+            predicate: "name".to_string(),
+            datum: Datum::String("Renamed".to_string()),
+        }];
+        let held = Lock {
+            entity: root,
+            owner: other,
+            until: 100,
+        };
+        assert_eq!(conflicts(&projection, "parent", &rename, me, 50), [held]);
+        assert!(conflicts(&projection, "parent", &rename, other, 50).is_empty());
+        assert!(conflicts(&projection, "parent", &rename, me, 100).is_empty());
+        assert!(conflicts(&projection, "parent", &unlock(root), me, 50).is_empty());
+
+        for action in unlock(root) {
+            projection.apply(&action);
+        }
+        assert_eq!(covering(&projection, "parent", child, 50), None);
+    }
+}
@@ -0,0 +1,230 @@
+//! Logging of the `tracing` events and spans of the app to stderr, and to a
+//! file if asked for.
+//!
+//! Each line names the spans it was logged in, e.g.
+//! `12:00:01.250  WARN update{message="Backups"}: Failed to back up to NAS`.
+//! The most recent lines are kept for the debug console, see [`recent`].
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// How many lines [`recent`] keeps.
+pub const RECENT: usize = 500;
+
+static LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+thread_local! {
+    /// The spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The most recent lines logged, oldest first.
+pub fn recent() -> Vec<String> {
+    LINES.lock().unwrap().iter().cloned().collect()
+}
+
+/// Logs what is at `level` or more severe, to `file` as well if given.
+pub fn init(level: LevelFilter, file: Option<&Path>) -> Result<()> {
+    let logger = Logger::new(level, file)?;
+    tracing::subscriber::set_global_default(logger).context("Failed to set up logging")
+}
+
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+    spans: Mutex<HashMap<Id, Span>>,
+    next: AtomicU64,
+}
+
+struct Span {
+    name: &'static str,
+    fields: String,
+    /// The handles to the span, it is closed when the last one is dropped.
+    references: usize,
+}
+
+impl Logger {
+    fn new(level: LevelFilter, file: Option<&Path>) -> Result<Logger> {
+        let file = match file {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?,
+            ),
+            None => None,
+        };
+        Ok(Logger {
+            level,
+            file: file.map(Mutex::new),
+            spans: Mutex::default(),
+            next: AtomicU64::new(1),
+        })
+    }
+
+    fn line(&self, event: &Event) -> String {
+        let now = time::OffsetDateTime::now_utc();
+        let mut line = format!(
+            "{:02}:{:02}:{:02}.{:03} {:>5} ",
+            now.hour(),
+            now.minute(),
+            now.second(),
+            now.millisecond(),
+            event.metadata().level()
+        );
+        let spans = self.spans.lock().unwrap();
+        ENTERED.with(|entered| {
+            for span in entered.borrow().iter().filter_map(|id| spans.get(id)) {
+                line.push_str(span.name);
+                if !span.fields.is_empty() {
+                    let _ = write!(line, "{{{}}}", span.fields);
+                }
+                line.push_str(": ");
+            }
+        });
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        line.push_str(&fields.message);
+        if !fields.other.is_empty() {
+            let _ = write!(line, " {}", fields.other);
+        }
+        line
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.level
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.level)
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = Id::from_u64(self.next.fetch_add(1, Ordering::Relaxed));
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+        let span = Span {
+            name: attributes.metadata().name(),
+            fields: fields.other,
+            references: 1,
+        };
+        self.spans.lock().unwrap().insert(id.clone(), span);
+        id
+    }
+
+    fn record(&self, id: &Id, values: &Record<'_>) {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(id) {
+            let mut fields = Fields {
+                other: std::mem::take(&mut span.fields),
+                ..Fields::default()
+            };
+            values.record(&mut fields);
+            span.fields = fields.other;
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let line = self.line(event);
+        eprintln!("{}", line);
+        if let Some(file) = &self.file {
+            // Logging must not fail what is being logged.
+            let _ = writeln!(file.lock().unwrap(), "{}", line);
+        }
+        let mut lines = LINES.lock().unwrap();
+        if lines.len() == RECENT {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    fn enter(&self, id: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(id.clone()));
+    }
+
+    fn exit(&self, id: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(index) = entered.iter().rposition(|e| e == id) {
+                entered.remove(index);
+            }
+        });
+    }
+
+    fn clone_span(&self, id: &Id) -> Id {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(id) {
+            span.references += 1;
+        }
+        id.clone()
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        match spans.get_mut(&id) {
+            Some(span) if span.references > 1 => {
+                span.references -= 1;
+                false
+            }
+            Some(_) => {
+                spans.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The message of an event and its other fields as `name=value`.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    other: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+            return;
+        }
+        if !self.other.is_empty() {
+            self.other.push(' ');
+        }
+        let _ = write!(self.other, "{}={:?}", field.name(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_name_their_spans() {
+        let logger = Logger::new(LevelFilter::INFO, None).unwrap();
+        tracing::subscriber::with_default(logger, || {
+            let _load = tracing::info_span!("load", events = 3).entered();
+            tracing::info!(entities = 2, "Loaded the graph");
+            tracing::debug!("Too verbose");
+        });
+        let lines = recent();
+        assert!(lines
+            .iter()
+            .any(|line| line.ends_with(" INFO load{events=3}: Loaded the graph entities=2")));
+        assert!(!lines.iter().any(|line| line.contains("Too verbose")));
+    }
+}
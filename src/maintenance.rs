@@ -0,0 +1,188 @@
+//! Periodic upkeep of the database, run by the editor in the background and
+//! by `graphite maintain`:
+//!
+//! 1. tags a snapshot of the graph as a checkpoint named
+//!    `maintenance-<time>`, keeping the newest `keep_snapshots`,
+//! 2. updates the statistics of the query planner and, with `vacuum` on,
+//!    returns the space of deleted rows to the file system,
+//! 3. deletes the events quarantined by `verify --repair` more than
+//!    `quarantine_days` ago,
+//! 4. deletes the backups, local and on targets, their retention no longer
+//!    keeps, see [`crate::backup`].
+//!
+//! ```toml
+//! [maintenance]
+//! interval = 24
+//! keep_snapshots = 7
+//! quarantine_days = 30
+//! vacuum = true
+//! ```
+
+use crate::backup;
+use crate::export::iso_date_time;
+use crate::legacy::storage::EventStorage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::{info, instrument};
+
+/// The prefix of the checkpoints tagged by maintenance.
+const PREFIX: &str = "maintenance-";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Hours between runs in the editor, 0 for none.
+    pub interval: u64,
+    /// How many snapshots are kept, 0 to take none.
+    pub keep_snapshots: usize,
+    pub quarantine_days: u64,
+    pub vacuum: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            interval: 24,
+            keep_snapshots: 7,
+            quarantine_days: 30,
+            vacuum: true,
+        }
+    }
+}
+
+/// What a run did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The checkpoint tagged, if any.
+    pub snapshot: Option<String>,
+    /// The older snapshots untagged.
+    pub untagged: Vec<String>,
+    /// The bytes returned to the file system.
+    pub freed: u64,
+    /// The number of quarantined events deleted.
+    pub pruned: usize,
+    /// The backups deleted.
+    pub rotated: Vec<String>,
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(snapshot) = &self.snapshot {
+            writeln!(f, "Tagged {}", snapshot)?;
+        }
+        for name in &self.untagged {
+            writeln!(f, "Untagged {}", name)?;
+        }
+        writeln!(f, "Freed {} bytes", self.freed)?;
+        writeln!(f, "Pruned {} quarantined events", self.pruned)?;
+        for name in &self.rotated {
+            writeln!(f, "Deleted the backup {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Maintains the database of `storage` at `now` and rotates the backups of
+/// `backups`.
+pub fn run(
+    storage: &EventStorage,
+    settings: &Settings,
+    backups: &backup::Settings,
+    now: OffsetDateTime,
+) -> Result<Report> {
+    let mut report = database(storage, settings, now)?;
+    report.rotated = rotate(backups, now)?;
+    Ok(report)
+}
+
+/// Snapshots, optimizes and prunes the database of `storage` at `now`.
+#[instrument(skip_all)]
+pub fn database(
+    storage: &EventStorage,
+    settings: &Settings,
+    now: OffsetDateTime,
+) -> Result<Report> {
+    let mut report = Report::default();
+    if settings.keep_snapshots > 0 {
+        let time = iso_date_time(now.unix_timestamp()).context("Invalid time")?;
+        let name = format!("{}{}", PREFIX, time);
+        storage.tag(&name)?;
+        report.snapshot = Some(name);
+        let mut snapshots: Vec<String> = storage
+            .checkpoints()?
+            .into_iter()
+            .map(|c| c.name)
+            .filter(|name| name.starts_with(PREFIX))
+            .collect();
+        // Named by time, so they sort oldest first.
+        snapshots.sort();
+        let old = snapshots.len().saturating_sub(settings.keep_snapshots);
+        for name in snapshots.into_iter().take(old) {
+            storage.untag(&name)?;
+            report.untagged.push(name);
+        }
+    }
+    storage.analyze()?;
+    if settings.vacuum {
+        report.freed = storage.vacuum()?;
+    }
+    let days = settings.quarantine_days.saturating_mul(24 * 60 * 60);
+    let before = now.unix_timestamp().saturating_sub_unsigned(days);
+    report.pruned = storage.prune_quarantine(before)?;
+    info!(
+        freed = report.freed,
+        pruned = report.pruned,
+        "Maintained the database"
+    );
+    Ok(report)
+}
+
+/// Deletes the backups, local and on targets, their retention doesn't keep
+/// at `now`, and returns their names.
+#[instrument(skip_all)]
+pub fn rotate(backups: &backup::Settings, now: OffsetDateTime) -> Result<Vec<String>> {
+    let mut rotated = backup::local::rotate(&backups.local)?;
+    for target in &backups.targets {
+        rotated.extend(backup::rotate(target, now, backups.retention)?);
+    }
+    info!(rotated = rotated.len(), "Rotated the backups");
+    Ok(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn keeps_the_newest_snapshots() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        storage.tag("release").unwrap();
+        let settings = Settings {
+            keep_snapshots: 2,
+            ..Settings::default()
+        };
+        let backups = backup::Settings::default();
+        for day in 1..=3 {
+            let now = datetime!(2024-07-01 03:00 UTC) + time::Duration::days(day);
+            let report = run(&storage, &settings, &backups, now).unwrap();
+            assert_eq!(report.pruned, 0);
+            assert!(report.rotated.is_empty());
+        }
+        let names: Vec<String> = storage
+            .checkpoints()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "maintenance-2024-07-03T03:00:00Z",
+                "maintenance-2024-07-04T03:00:00Z",
+                "release"
+            ]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Merging duplicate entities into one.
+//!
+//! Merging `duplicate` into `keep` points every link to the duplicate at
+//! `keep`, copies the facts of predicates `keep` lacks and deletes the
+//! duplicate, which leaves it in the trash. Where both have a predicate,
+//! the values of `keep` win and those of the duplicate are dropped; the
+//! [`Preview`] lists them so nothing is lost unnoticed. The actions are
+//! recorded as one transaction, so the merge syncs and reverts as one step.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use uuid::Uuid;
+
+/// What merging would do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preview {
+    /// The facts copied to the entity kept.
+    pub copied: Vec<(String, Datum)>,
+    /// The predicates both have with different values, with the values of
+    /// the duplicate that are dropped.
+    pub conflicts: Vec<(String, Vec<Datum>)>,
+    /// The entities linking to the duplicate, with the predicate, which
+    /// will link to the entity kept instead.
+    pub references: Vec<(Uuid, String)>,
+}
+
+pub fn preview(projection: &Projection, keep: Uuid, duplicate: Uuid) -> Preview {
+    let mut preview = Preview::default();
+    let (Some(kept), Some(merged)) = (projection.entity(&keep), projection.entity(&duplicate))
+    else {
+        return preview;
+    };
+    for (predicate, values) in merged.facts() {
+        let values = values.iter().map(|datum| repoint(datum, keep, duplicate));
+        if kept.values(predicate).is_empty() {
+            preview
+                .copied
+                .extend(values.map(|datum| (predicate.to_string(), datum)));
+            continue;
+        }
+        let dropped: Vec<Datum> = values
+            .filter(|datum| !kept.values(predicate).contains(datum))
+            .collect();
+        if !dropped.is_empty() {
+            preview.conflicts.push((predicate.to_string(), dropped));
+        }
+    }
+    for (id, entity) in projection.entities() {
+        if *id == duplicate {
+            continue;
+        }
+        for (predicate, values) in entity.facts() {
+            if values.contains(&Datum::Entity(duplicate)) {
+                preview.references.push((*id, predicate.to_string()));
+            }
+        }
+    }
+    preview.references.sort();
+    preview
+}
+
+/// The actions that merge `duplicate` into `keep`.
+pub fn merge(projection: &Projection, keep: Uuid, duplicate: Uuid) -> Vec<Action> {
+    if keep == duplicate || !projection.contains(&keep) || !projection.contains(&duplicate) {
+        return Vec::new();
+    }
+    let preview = preview(projection, keep, duplicate);
+    let mut actions = Vec::new();
+    for (id, predicate) in &preview.references {
+        let Some(entity) = projection.entity(id) else {
+            continue;
+        };
+        actions.push(Action::RemoveFact {
+            subject: *id,
+            predicate: predicate.clone(),
+        });
+        let mut values: Vec<Datum> = Vec::new();
+        for datum in entity.values(predicate) {
+            let datum = repoint(datum, keep, duplicate);
+            if !values.contains(&datum) {
+                values.push(datum);
+            }
+        }
+        actions.extend(values.into_iter().map(|datum| Action::AddFact {
+            subject: *id,
+            predicate: predicate.clone(),
+            datum,
+        }));
+    }
+    actions.extend(
+        preview
+            .copied
+            .into_iter()
+            .map(|(predicate, datum)| Action::AddFact {
+                subject: keep,
+                predicate,
+                datum,
+            }),
+    );
+    actions.push(Action::DeleteEntity { id: duplicate });
+    actions
+}
+
+/// `datum`, linking to `keep` if it linked to `duplicate`.
+fn repoint(datum: &Datum, keep: Uuid, duplicate: Uuid) -> Datum {
+    match datum {
+        Datum::Entity(id) if *id == duplicate => Datum::Entity(keep),
+        datum => datum.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(subject: Uuid, predicate: &str, datum: Datum) -> Action {
+        Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        }
+    }
+
+    #[test]
+    fn merges_facts_and_links_into_the_entity_kept() {
+        let [keep, duplicate, fan] = [(); 3].map(|_| Uuid::new_v4());
+        let mut projection = Projection::new();
+        for id in [keep, duplicate, fan] {
+            projection.apply(&Action::CreateEntity { id });
+        }
+        projection.apply(&add(keep, "name", Datum::String("Ada Lovelace".into())));
+        projection.apply(&add(duplicate, "name", Datum::String("Ada".into())));
+        projection.apply(&add(duplicate, "born", Datum::Integer(1815)));
+        projection.apply(&add(fan, "likes", Datum::Entity(duplicate)));
+        projection.apply(&add(fan, "likes", Datum::Entity(keep)));
+
+        let preview = preview(&projection, keep, duplicate);
+        assert_eq!(preview.copied, [("born".to_string(), Datum::Integer(1815))]);
+        assert_eq!(
+            preview.conflicts,
+            [("name".to_string(), vec![Datum::String("Ada".into())])]
+        );
+        assert_eq!(preview.references, [(fan, "likes".to_string())]);
+
+        projection.apply(&Action::Transaction {
+            actions: merge(&projection, keep, duplicate),
+        });
+        assert!(!projection.contains(&duplicate));
+        let kept = projection.entity(&keep).unwrap();
+        assert_eq!(
+            kept.value("name"),
+            Some(&Datum::String("Ada Lovelace".into()))
+        );
+        assert_eq!(kept.value("born"), Some(&Datum::Integer(1815)));
+        assert_eq!(
+            projection.entity(&fan).unwrap().values("likes"),
+            [Datum::Entity(keep)]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Plugins contribute parts of the editor's interface without code: side
+//! panels, sections of the inspector and view types for dashboard widgets,
+//! each a declarative layout bound to queries.
+//!
+//! A plugin is a TOML file in the `plugins` directory next to the config:
+//!
+//! ```toml
+//! name = "Citations"
+//!
+//! [[sections]]
+//! title = "Cited by"
+//! when = "type=paper"
+//! layout = { type = "list", query = "cites=$this" }
+//!
+//! [[panels]]
+//! title = "Reading list"
+//! layout = { type = "table", query = "type=paper status=unread", columns = ["name", "year"] }
+//!
+//! [[views]]
+//! name = "papers"
+//! layout = { type = "list", query = "type=paper tag={topic}" }
+//! ```
+//!
+//! In queries and texts, `$this` is the id of the entity a layout is shown
+//! for, i.e. the inspected entity or the dashboard widget, and `{predicate}`
+//! its value of a predicate. A dashboard widget of kind `view` shows the view
+//! named by its `view` fact, as `plugin/view`.
+//!
+//! A plugin may also bring a WebAssembly module in its `[wasm]` table, see
+//! [`crate::wasm`].
+
+use crate::dashboard::{describe, name};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use crate::query::Query;
+use crate::wasm;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plugin {
+    pub name: String,
+    #[serde(default)]
+    pub panels: Vec<Panel>,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    #[serde(default)]
+    pub views: Vec<View>,
+    pub wasm: Option<wasm::Module>,
+}
+
+/// A panel beside the main window's content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Panel {
+    pub title: String,
+    pub layout: Layout,
+}
+
+/// A section of the inspector, for the entities matching `when`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub title: String,
+    #[serde(default)]
+    pub when: String,
+    pub layout: Layout,
+}
+
+/// A view type of dashboard widgets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    pub name: String,
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Layout {
+    Text {
+        text: String,
+    },
+    /// The entities matching a query, by name.
+    List {
+        query: String,
+        limit: Option<usize>,
+    },
+    Count {
+        query: String,
+    },
+    /// The entities matching a query with their values of `columns`.
+    Table {
+        query: String,
+        columns: Vec<String>,
+    },
+    /// The facts of the entity shown, or only of `predicates`.
+    Facts {
+        #[serde(default)]
+        predicates: Vec<String>,
+    },
+    Column {
+        children: Vec<Layout>,
+    },
+    Row {
+        children: Vec<Layout>,
+    },
+}
+
+/// A layout with its queries run, ready to be drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Text(String),
+    List(Vec<Uuid>),
+    Count(usize),
+    Table {
+        columns: Vec<String>,
+        rows: Vec<(Uuid, Vec<String>)>,
+    },
+    Facts(Vec<(String, Datum)>),
+    Column(Vec<Block>),
+    Row(Vec<Block>),
+    Error(String),
+}
+
+impl Plugin {
+    pub fn from_toml(text: &str) -> Result<Plugin> {
+        let plugin: Plugin = toml::from_str(text)?;
+        let queries = plugin
+            .panels
+            .iter()
+            .map(|p| &p.layout)
+            .chain(plugin.sections.iter().map(|s| &s.layout))
+            .chain(plugin.views.iter().map(|v| &v.layout));
+        for layout in queries {
+            layout.check()?;
+        }
+        for section in &plugin.sections {
+            section.when.parse::<Query>()?;
+        }
+        Ok(plugin)
+    }
+
+    /// The view named `name`.
+    pub fn view(&self, name: &str) -> Option<&View> {
+        self.views.iter().find(|v| v.name == name)
+    }
+}
+
+impl Section {
+    pub fn shows(&self, projection: &Projection, entity: Uuid) -> bool {
+        let Ok(query) = self.when.parse::<Query>() else {
+            return false;
+        };
+        projection
+            .entity(&entity)
+            .is_some_and(|e| query.matches(projection, e))
+    }
+}
+
+impl Layout {
+    /// Fails on queries that can't be parsed, whatever entity they are for.
+    fn check(&self) -> Result<()> {
+        match self {
+            Layout::List { query, .. } | Layout::Count { query } | Layout::Table { query, .. } => {
+                fill(query, &Projection::new(), None)
+                    .parse::<Query>()
+                    .with_context(|| format!("Invalid query {}", query))?;
+            }
+            Layout::Column { children } | Layout::Row { children } => {
+                children.iter().try_for_each(Layout::check)?
+            }
+            Layout::Text { .. } | Layout::Facts { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Runs the queries of the layout for the entity `this`.
+    pub fn render(&self, projection: &Projection, this: Option<Uuid>) -> Block {
+        let run = |query: &str| -> Result<Vec<Uuid>, Block> {
+            match fill(query, projection, this).parse::<Query>() {
+                Ok(query) => {
+                    let mut ids = query.run(projection);
+                    ids.sort_by_cached_key(|id| (name(projection, id), *id));
+                    Ok(ids)
+                }
+                Err(e) => Err(Block::Error(format!("{:#}", e))),
+            }
+        };
+        let result = match self {
+            Layout::Text { text } => Ok(Block::Text(fill(text, projection, this))),
+            Layout::List { query, limit } => run(query).map(|mut ids| {
+                ids.truncate(limit.unwrap_or(usize::MAX));
+                Block::List(ids)
+            }),
+            Layout::Count { query } => run(query).map(|ids| Block::Count(ids.len())),
+            Layout::Table { query, columns } => run(query).map(|ids| Block::Table {
+                columns: columns.clone(),
+                rows: ids
+                    .into_iter()
+                    .map(|id| (id, cells(projection, id, columns)))
+                    .collect(),
+            }),
+            Layout::Facts { predicates } => {
+                let entity = this.and_then(|id| projection.entity(&id));
+                let facts = entity.into_iter().flat_map(|e| e.facts());
+                Ok(Block::Facts(
+                    facts
+                        .filter(|(p, _)| predicates.is_empty() || predicates.iter().any(|q| q == p))
+                        .flat_map(|(p, values)| values.iter().map(|d| (p.to_string(), d.clone())))
+                        .collect(),
+                ))
+            }
+            Layout::Column { children } => Ok(Block::Column(
+                children
+                    .iter()
+                    .map(|c| c.render(projection, this))
+                    .collect(),
+            )),
+            Layout::Row { children } => Ok(Block::Row(
+                children
+                    .iter()
+                    .map(|c| c.render(projection, this))
+                    .collect(),
+            )),
+        };
+        result.unwrap_or_else(|error| error)
+    }
+}
+
+/// Every plugin in `dir`, sorted by file name, and the errors of the files
+/// that aren't valid plugins.
+pub fn load(dir: &Path) -> (Vec<Plugin>, Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "toml"))
+        .collect();
+    paths.sort();
+    let (mut plugins, mut errors) = (Vec::new(), Vec::new());
+    for path in paths {
+        let plugin = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Plugin::from_toml(&text))
+            .with_context(|| format!("Invalid plugin {}", path.display()));
+        match plugin {
+            Ok(mut plugin) => {
+                if let Some(module) = &mut plugin.wasm {
+                    module.module = dir.join(&module.module);
+                }
+                plugins.push(plugin)
+            }
+            Err(e) => errors.push(format!("{:#}", e)),
+        }
+    }
+    (plugins, errors)
+}
+
+/// Replaces `$this` and `{predicate}` in `template` with the id and the
+/// values of `this`.
+fn fill(template: &str, projection: &Projection, this: Option<Uuid>) -> String {
+    let entity = this.and_then(|id| projection.entity(&id));
+    let mut rest = template.replace("$this", &this.unwrap_or_default().to_string());
+    let mut filled = String::new();
+    while let Some((before, after)) = rest.split_once('{') {
+        let Some((predicate, after)) = after.split_once('}') else {
+            break;
+        };
+        filled.push_str(before);
+        if let Some(datum) = entity.and_then(|e| e.value(predicate)) {
+            filled.push_str(&describe(projection, datum));
+        }
+        rest = after.to_string();
+    }
+    filled.push_str(&rest);
+    filled
+}
+
+fn cells(projection: &Projection, id: Uuid, columns: &[String]) -> Vec<String> {
+    let entity = projection.entity(&id);
+    columns
+        .iter()
+        .map(|column| {
+            let values = entity.map_or(&[][..], |e| e.values(column));
+            let described: Vec<String> = values.iter().map(|d| describe(projection, d)).collect();
+            described.join(", ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn renders_layouts_for_an_entity() {
+        let plugin = Plugin::from_toml(
+            r#"
+            name = "Citations"
+
+            [[sections]]
+            title = "Cited by"
+            when = "type=paper"
+            layout = { type = "column", children = [
+                { type = "text", text = "Papers citing {name}" },
+                { type = "list", query = "cites=$this" },
+            ] }
+
+            [[views]]
+            name = "tagged"
+            layout = { type = "table", query = "tag={topic}", columns = ["name", "cites"] }
+            "#,
+        )
+        .unwrap();
+        let mut projection = Projection::new();
+        let (cited, citing, widget) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let fact = |subject, predicate: &str, datum| Action::AddFact {
+            subject,
+            predicate: predicate.into(),
+            datum,
+        };
+        let text = |s: &str| Datum::String(s.into());
+        for action in [
+            Action::CreateEntity { id: cited },
+            Action::CreateEntity { id: citing },
+            Action::CreateEntity { id: widget },
+            fact(cited, "type", text("paper")),
+            fact(cited, "name", text("Origins")),
+            fact(citing, "name", text("Sequel")),
+            fact(citing, "cites", Datum::Entity(cited)),
+            fact(citing, "tag", text("ml")),
+            fact(widget, "topic", text("ml")),
+        ] {
+            projection.apply(&action);
+        }
+
+        let section = &plugin.sections[0];
+        assert!(section.shows(&projection, cited));
+        assert!(!section.shows(&projection, citing));
+        assert_eq!(
+            section.layout.render(&projection, Some(cited)),
+            Block::Column(vec![
+                Block::Text("Papers citing Origins".into()),
+                Block::List(vec![citing]),
+            ])
+        );
+        assert_eq!(
+            plugin
+                .view("tagged")
+                .unwrap()
+                .layout
+                .render(&projection, Some(widget)),
+            Block::Table {
+                columns: vec!["name".into(), "cites".into()],
+                rows: vec![(citing, vec!["Sequel".into(), "Origins".into()])],
+            }
+        );
+
+        let invalid = "name = \"x\"\n[[panels]]\ntitle = \"t\"\nlayout = { type = \"count\", query = \"a<b\" }";
+        assert!(Plugin::from_toml(invalid).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Progress reporting and cancellation for operations that take a while, such
+//! as replaying the event log or importing a large file.
+//!
+//! [`task`] returns the two ends of a task: the operation gets the
+//! [`Reporter`], advances it as it goes and stops with [`Cancelled`] once
+//! [`Reporter::check`] fails. Whoever started it keeps the [`Handle`] to
+//! read the progress from and to cancel the operation. The task is finished
+//! when every clone of its reporter was dropped.
+
+use crate::legacy::error::GraphiteError;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// The total of a task whose total isn't known (yet).
+const UNKNOWN: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub done: u64,
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// How much of the task is done, from 0 to 1, if its total is known.
+    pub fn fraction(&self) -> Option<f32> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.done as f64 / total as f64).min(1.0) as f32),
+            None => None,
+        }
+    }
+}
+
+/// The error of an operation that was cancelled through its handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Whether `error` is, or was caused by, a cancellation.
+pub fn is_cancelled(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| {
+        e.is::<Cancelled>()
+            || matches!(
+                e.downcast_ref::<GraphiteError>(),
+                Some(GraphiteError::Cancelled(_))
+            )
+    })
+}
+
+struct Shared {
+    done: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+/// Starts a task, returning the end that reports and the end that watches.
+pub fn task() -> (Reporter, Handle) {
+    let shared = Arc::new(Shared {
+        done: AtomicU64::new(0),
+        total: AtomicU64::new(UNKNOWN),
+        cancelled: AtomicBool::new(false),
+    });
+    (Reporter(shared.clone()), Handle(shared))
+}
+
+/// The end of a task given to the operation.
+#[derive(Clone)]
+pub struct Reporter(Arc<Shared>);
+
+impl Reporter {
+    /// A reporter nobody watches, for callers that don't show progress.
+    pub fn none() -> Reporter {
+        task().0
+    }
+
+    pub fn set_total(&self, total: u64) {
+        self.0.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Adds `total` to the total, e.g. for work found along the way.
+    pub fn add_total(&self, total: u64) {
+        let _ = self
+            .0
+            .total
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                Some(match current {
+                    UNKNOWN => total,
+                    current => current.saturating_add(total),
+                })
+            });
+    }
+
+    pub fn advance(&self, done: u64) {
+        self.0.done.fetch_add(done, Ordering::Relaxed);
+    }
+
+    /// Fails with [`Cancelled`] once the task was cancelled.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The end of a task kept by whoever started it.
+pub struct Handle(Arc<Shared>);
+
+impl Handle {
+    pub fn progress(&self) -> Progress {
+        let total = self.0.total.load(Ordering::Relaxed);
+        Progress {
+            done: self.0.done.load(Ordering::Relaxed),
+            total: (total != UNKNOWN).then_some(total),
+        }
+    }
+
+    /// Asks the operation to stop at its next check.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Whether the operation dropped its reporter, done or not.
+    pub fn is_finished(&self) -> bool {
+        // Only reporters are cloned, so the handle is the last one left.
+        Arc::strong_count(&self.0) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_progress_until_cancelled() {
+        let (reporter, handle) = task();
+        assert_eq!(handle.progress().fraction(), None);
+        reporter.set_total(4);
+        reporter.clone().advance(1);
+        assert_eq!(handle.progress().fraction(), Some(0.25));
+        reporter.add_total(4);
+        assert_eq!(
+            handle.progress(),
+            Progress {
+                done: 1,
+                total: Some(8)
+            }
+        );
+
+        assert!(reporter.check().is_ok());
+        handle.cancel();
+        let error = anyhow::Error::from(reporter.check().unwrap_err()).context("Failed to load");
+        assert!(is_cancelled(&error));
+        assert!(!handle.is_finished());
+        drop(reporter);
+        assert!(handle.is_finished());
+    }
+}
@@ -0,0 +1,145 @@
+//! Where facts came from.
+//!
+//! A fact cites its sources in a meta-fact about the same entity, whose
+//! predicate is the fact's followed by [`SUFFIX`]: the sources of `born` are
+//! the values of `born@source`. A source is a URL, a link to a document
+//! entity, or an import batch, stored as a `urn:uuid:` URL. The sources of a
+//! predicate apply to all of its values.
+//!
+//! Meta-facts are ordinary facts, so they sync and revert with the facts they
+//! cite, and queries filter by them, e.g. `born@source=https://example.com`,
+//! or `@source=…` for entities with any fact from a source, see
+//! [`crate::query`].
+
+use crate::legacy::projection::Entity;
+use crate::legacy::storage::{Action, Datum};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// What the predicates of meta-facts end with.
+pub const SUFFIX: &str = "@source";
+
+const BATCH_PREFIX: &str = "urn:uuid:";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Source {
+    Url(String),
+    /// An entity standing for a document, e.g. a paper or a book.
+    Document(Uuid),
+    /// The facts imported together, e.g. from one file.
+    Batch(Uuid),
+}
+
+impl Source {
+    /// A batch for facts about to be imported.
+    pub fn new_batch() -> Source {
+        Source::Batch(Uuid::new_v4())
+    }
+
+    pub fn to_datum(&self) -> Datum {
+        match self {
+            Source::Url(url) => Datum::String(url.clone()),
+            Source::Document(id) => Datum::Entity(*id),
+            Source::Batch(id) => Datum::String(format!("{}{}", BATCH_PREFIX, id)),
+        }
+    }
+
+    pub fn from_datum(datum: &Datum) -> Option<Source> {
+        match datum {
+            Datum::String(s) => Some(
+                s.strip_prefix(BATCH_PREFIX)
+                    .and_then(|id| id.parse().ok())
+                    .map_or_else(|| Source::Url(s.clone()), Source::Batch),
+            ),
+            Datum::Entity(id) => Some(Source::Document(*id)),
+            _ => None,
+        }
+    }
+}
+
+/// The predicate of the meta-fact citing the sources of `predicate`.
+pub fn meta_predicate(predicate: &str) -> String {
+    format!("{}{}", predicate, SUFFIX)
+}
+
+/// Whether `predicate` is that of a meta-fact.
+pub fn is_meta(predicate: &str) -> bool {
+    predicate.ends_with(SUFFIX)
+}
+
+/// The sources cited for the facts of `predicate`.
+pub fn sources(entity: &Entity, predicate: &str) -> Vec<Source> {
+    entity
+        .values(&meta_predicate(predicate))
+        .iter()
+        .filter_map(Source::from_datum)
+        .collect()
+}
+
+/// `actions` with meta-facts citing `source` for the facts they add.
+pub fn cite(actions: Vec<Action>, source: &Source) -> Vec<Action> {
+    let mut cited = HashSet::new();
+    let mut result = Vec::with_capacity(actions.len());
+    for action in actions {
+        let citation = match &action {
+            Action::AddFact {
+                subject, predicate, ..
+            } if !is_meta(predicate) && cited.insert((*subject, predicate.clone())) => {
+                Some(Action::AddFact {
+                    subject: *subject,
+                    predicate: meta_predicate(predicate),
+                    datum: source.to_datum(),
+                })
+            }
+            _ => None,
+        };
+        result.push(action);
+        result.extend(citation);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::projection::Projection;
+    use crate::query::Query;
+
+    #[test]
+    fn cited_sources_are_listed_and_queried() {
+        let (ada, paper) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut projection = Projection::new();
+        projection.apply(&Action::CreateEntity { id: ada });
+        projection.apply(&Action::CreateEntity { id: paper });
+        let fact = |predicate: &str, datum| Action::AddFact {
+            subject: ada,
+            predicate: predicate.to_string(),
+            datum,
+        };
+        let batch = Source::new_batch();
+        let actions = cite(
+            vec![
+                fact("name", Datum::String("Ada".into())),
+                fact("name", Datum::String("Ada Lovelace".into())),
+            ],
+            &batch,
+        );
+        assert_eq!(actions.len(), 3);
+        let actions = actions.into_iter().chain(cite(
+            vec![fact("born", Datum::Integer(1815))],
+            &Source::Document(paper),
+        ));
+        actions.for_each(|action| projection.apply(&action));
+
+        let entity = projection.entity(&ada).unwrap();
+        assert_eq!(sources(entity, "name"), vec![batch.clone()]);
+        assert_eq!(sources(entity, "born"), [Source::Document(paper)]);
+        let Datum::String(urn) = batch.to_datum() else {
+            unreachable!()
+        };
+        let run = |query: &str| query.parse::<Query>().unwrap().run(&projection);
+        assert_eq!(run(&format!("name@source={}", urn)), [ada]);
+        assert_eq!(run(&format!("@source={}", paper)), [ada]);
+        assert!(run("@source=https://example.com").is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+//! Queries select entities by their facts, e.g.
+//! `type=task status!=done due<2024-07-01`.
+//!
+//! A query is a list of clauses separated by spaces, all of which must hold:
+//!
+//! - `predicate` and `!predicate`: the entity has, or lacks, the predicate
+//! - `predicate=value` and `predicate!=value`: some value, or none, is `value`
+//! - `predicate<value` and `predicate>value`: some value is less, or more,
+//!   than the number or date `value`
+//!
+//! A predicate starting with `@` stands for every predicate ending with it,
+//! e.g. `@source=…` for the sources cited by any fact, see
+//! [`crate::provenance`].
+//!
+//! Values with spaces are quoted, as in `name="Big project"`. A link equals
+//! the id or the name of the entity it links to. The empty query matches
+//! every entity.
+
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::Datum;
+use anyhow::{anyhow, bail, Result};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use time::format_description::well_known::Iso8601;
+use time::Date;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    clauses: Vec<Clause>,
+    source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Clause {
+    Has(String),
+    Lacks(String),
+    Equals(String, String),
+    Differs(String, String),
+    Less(String, f64),
+    More(String, f64),
+}
+
+impl Query {
+    /// Whether `entity` satisfies every clause.
+    pub fn matches(&self, projection: &Projection, entity: &Entity) -> bool {
+        self.clauses.iter().all(|clause| match clause {
+            Clause::Has(predicate) => values(entity, predicate).next().is_some(),
+            Clause::Lacks(predicate) => values(entity, predicate).next().is_none(),
+            Clause::Equals(predicate, value) => {
+                values(entity, predicate).any(|datum| equals(projection, datum, value))
+            }
+            Clause::Differs(predicate, value) => {
+                !values(entity, predicate).any(|datum| equals(projection, datum, value))
+            }
+            Clause::Less(predicate, bound) => values(entity, predicate)
+                .any(|datum| compare(datum, *bound) == Some(Ordering::Less)),
+            Clause::More(predicate, bound) => values(entity, predicate)
+                .any(|datum| compare(datum, *bound) == Some(Ordering::Greater)),
+        })
+    }
+
+    /// The ids of the matching entities, in no particular order.
+    pub fn run(&self, projection: &Projection) -> Vec<Uuid> {
+        projection
+            .entities()
+            .filter(|(_, entity)| self.matches(projection, entity))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Query> {
+        let clauses = tokens(s)?
+            .into_iter()
+            .map(|token| clause(&token))
+            .collect::<Result<_>>()?;
+        Ok(Query {
+            clauses,
+            source: s.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Splits a query at spaces outside quotes, removing the quotes.
+fn tokens(s: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if quoted {
+        bail!("Unclosed quote in query {}", s);
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn clause(token: &str) -> Result<Clause> {
+    let bound = |value: &str| {
+        number(value).ok_or_else(|| anyhow!("{} is neither a number nor a date", value))
+    };
+    let clause = if let Some((predicate, value)) = token.split_once("!=") {
+        Clause::Differs(predicate.to_string(), value.to_string())
+    } else if let Some((predicate, value)) = token.split_once('=') {
+        Clause::Equals(predicate.to_string(), value.to_string())
+    } else if let Some((predicate, value)) = token.split_once('<') {
+        Clause::Less(predicate.to_string(), bound(value)?)
+    } else if let Some((predicate, value)) = token.split_once('>') {
+        Clause::More(predicate.to_string(), bound(value)?)
+    } else if let Some(predicate) = token.strip_prefix('!') {
+        Clause::Lacks(predicate.to_string())
+    } else {
+        Clause::Has(token.to_string())
+    };
+    match &clause {
+        Clause::Has(p) | Clause::Lacks(p) if p.is_empty() => {
+            bail!("Missing predicate in {}", token)
+        }
+        Clause::Equals(p, _) | Clause::Differs(p, _) | Clause::Less(p, _) | Clause::More(p, _)
+            if p.is_empty() =>
+        {
+            bail!("Missing predicate in {}", token)
+        }
+        _ => Ok(clause),
+    }
+}
+
+/// A number, or a date as the Unix time of its start in UTC.
+fn number(value: &str) -> Option<f64> {
+    if let Ok(n) = value.parse::<f64>() {
+        return Some(n);
+    }
+    let date = Date::parse(value, &Iso8601::DEFAULT).ok()?;
+    Some(date.midnight().assume_utc().unix_timestamp() as f64)
+}
+
+/// The values of `predicate`, or for a predicate starting with `@`, of all
+/// predicates ending with it.
+fn values<'a>(entity: &'a Entity, predicate: &'a str) -> Box<dyn Iterator<Item = &'a Datum> + 'a> {
+    if !predicate.starts_with('@') {
+        return Box::new(entity.values(predicate).iter());
+    }
+    Box::new(
+        entity
+            .facts()
+            .filter(move |(p, _)| p.ends_with(predicate))
+            .flat_map(|(_, values)| values),
+    )
+}
+
+fn equals(projection: &Projection, datum: &Datum, value: &str) -> bool {
+    match datum {
+        Datum::String(s) => s == value,
+        Datum::Integer(n) => value.parse() == Ok(*n),
+        Datum::Float(n) => value.parse() == Ok(*n),
+        Datum::Boolean(b) => value.parse() == Ok(*b),
+        Datum::DateTime(t) => number(value) == Some(*t as f64),
+        Datum::Entity(id) => {
+            id.to_string() == value
+                || projection
+                    .entity(id)
+                    .and_then(|e| e.value("name"))
+                    .is_some_and(|name| name == &Datum::String(value.to_string()))
+        }
+    }
+}
+
+fn compare(datum: &Datum, bound: f64) -> Option<Ordering> {
+    let n = match datum {
+        Datum::Integer(n) => *n as f64,
+        Datum::Float(n) => *n,
+        Datum::DateTime(t) => *t as f64,
+        _ => return None,
+    };
+    n.partial_cmp(&bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn matches_clauses() {
+        let mut projection = Projection::new();
+        let (project, task) = (Uuid::new_v4(), Uuid::new_v4());
+        for id in [project, task] {
+            projection.apply(&Action::CreateEntity { id });
+        }
+        let facts = [
+            (project, "name", Datum::String("Big project".into())),
+            (task, "type", Datum::String("task".into())),
+            (task, "status", Datum::String("todo".into())),
+            (task, "estimate", Datum::Integer(3)),
+            (task, "parent", Datum::Entity(project)),
+            (task, "due", Datum::DateTime(1_719_792_000)), // 2024-07-01
+        ];
+        for (subject, predicate, datum) in facts {
+            projection.apply(&Action::AddFact {
+                subject,
+                predicate: predicate.into(),
+                datum,
+            });
+        }
+        let run = |query: &str| query.parse::<Query>().unwrap().run(&projection);
+
+        assert_eq!(run("").len(), 2);
+        assert_eq!(run("type=task status!=done"), vec![task]);
+        assert_eq!(run("parent=\"Big project\" estimate>2"), vec![task]);
+        assert_eq!(run("due<2024-07-02 due>2024-06-30"), vec![task]);
+        assert_eq!(run("!type"), vec![project]);
+        assert!(run("estimate<3").is_empty());
+        assert!("name=\"open".parse::<Query>().is_err());
+        assert!("due<soon".parse::<Query>().is_err());
+        assert!("=x".parse::<Query>().is_err());
+    }
+}
@@ -0,0 +1,534 @@
+//! Recurring entities.
+//!
+//! An entity recurs when it has an `rrule` fact holding an iCalendar style
+//! rule such as `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;COUNT=10`, and a
+//! `dtstart` for its first occurrence. Occurrences are whole days.
+//!
+//! Exceptions are ordinary facts: a skipped occurrence is an `exdate`, and a
+//! moved occurrence is an `exdate` for the original day plus an `rdate` for
+//! the new one. The first occurrence from today on is kept in the derived
+//! `next_occurrence` fact so it can be queried like any other.
+
+use crate::import::upsert;
+use crate::legacy::async_storage::AsyncStorage;
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{Action, Datum, EventCreator};
+use crate::scheduler::Scheduler;
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use time::macros::format_description;
+use time::{Date, Duration, Month, OffsetDateTime, UtcOffset, Weekday};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A recurrence rule, the subset of RFC 5545 that applies to whole days.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub frequency: Frequency,
+    /// Every how many days, weeks, months or years.
+    pub interval: u32,
+    /// The number of occurrences, counted from the start.
+    pub count: Option<u32>,
+    /// The last day an occurrence may fall on.
+    pub until: Option<Date>,
+    pub by_day: Vec<Weekday>,
+    /// Days of the month, negative days count from its end.
+    pub by_month_day: Vec<i8>,
+}
+
+impl Rule {
+    pub fn new(frequency: Frequency) -> Rule {
+        Rule {
+            frequency,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+        }
+    }
+
+    /// The occurrences of the rule starting at `start`, before exceptions,
+    /// that fall before `end`.
+    pub fn expand(&self, start: Date, end: Date) -> Vec<Date> {
+        let mut dates = Vec::new();
+        let interval = i64::from(self.interval.max(1));
+        for period in 0.. {
+            let Some(candidates) = self.period(start, period * interval) else {
+                break;
+            };
+            if candidates.first >= end {
+                break;
+            }
+            for date in candidates.dates {
+                if date < start || date >= end || self.until.is_some_and(|u| date > u) {
+                    continue;
+                }
+                if self.count.is_some_and(|c| dates.len() >= c as usize) {
+                    return dates;
+                }
+                dates.push(date);
+            }
+            if self.until.is_some_and(|until| candidates.first > until) {
+                break;
+            }
+        }
+        dates
+    }
+
+    /// The candidate days of the period `offset` days, weeks, months or years
+    /// after the one `start` is in, or `None` past the supported dates.
+    fn period(&self, start: Date, offset: i64) -> Option<Period> {
+        let weekdays = |dates: Vec<Date>| -> Vec<Date> {
+            if self.by_day.is_empty() {
+                dates
+            } else {
+                dates
+                    .into_iter()
+                    .filter(|d| self.by_day.contains(&d.weekday()))
+                    .collect()
+            }
+        };
+        let period = match self.frequency {
+            Frequency::Daily => {
+                let day = start.checked_add(Duration::days(offset))?;
+                Period {
+                    first: day,
+                    dates: weekdays(vec![day]),
+                }
+            }
+            Frequency::Weekly => {
+                let monday = start
+                    .checked_sub(Duration::days(
+                        start.weekday().number_days_from_monday().into(),
+                    ))?
+                    .checked_add(Duration::weeks(offset))?;
+                let week: Vec<Date> = (0..7)
+                    .filter_map(|d| monday.checked_add(Duration::days(d)))
+                    .collect();
+                let dates = if self.by_day.is_empty() {
+                    week.into_iter()
+                        .filter(|d| d.weekday() == start.weekday())
+                        .collect()
+                } else {
+                    weekdays(week)
+                };
+                Period {
+                    first: monday,
+                    dates,
+                }
+            }
+            Frequency::Monthly => {
+                let months = i64::from(start.month() as u8 - 1) + offset;
+                let year = start.year() + i32::try_from(months.div_euclid(12)).ok()?;
+                let month = Month::try_from(u8::try_from(months.rem_euclid(12) + 1).ok()?).ok()?;
+                let first = Date::from_calendar_date(year, month, 1).ok()?;
+                let length = time::util::days_in_year_month(year, month);
+                let days: Vec<Date> = (1..=length)
+                    .filter_map(|d| first.replace_day(d).ok())
+                    .collect();
+                let dates = if !self.by_month_day.is_empty() {
+                    let mut dates: Vec<Date> = self
+                        .by_month_day
+                        .iter()
+                        .filter_map(|&d| {
+                            let day = if d < 0 { length as i8 + d + 1 } else { d };
+                            first.replace_day(u8::try_from(day).ok()?).ok()
+                        })
+                        .collect();
+                    dates.sort();
+                    dates.dedup();
+                    weekdays(dates)
+                } else if !self.by_day.is_empty() {
+                    weekdays(days)
+                } else {
+                    first.replace_day(start.day()).ok().into_iter().collect()
+                };
+                Period { first, dates }
+            }
+            Frequency::Yearly => {
+                let year = start.year().checked_add(i32::try_from(offset).ok()?)?;
+                let first = Date::from_calendar_date(year, Month::January, 1).ok()?;
+                Period {
+                    first,
+                    dates: start.replace_year(year).ok().into_iter().collect(),
+                }
+            }
+        };
+        Some(period)
+    }
+}
+
+struct Period {
+    /// The first day of the period.
+    first: Date,
+    dates: Vec<Date>,
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Monday),
+    ("TU", Weekday::Tuesday),
+    ("WE", Weekday::Wednesday),
+    ("TH", Weekday::Thursday),
+    ("FR", Weekday::Friday),
+    ("SA", Weekday::Saturday),
+    ("SU", Weekday::Sunday),
+];
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Rule> {
+        let s = s.trim();
+        let s = s.strip_prefix("RRULE:").unwrap_or(s);
+        let mut frequency = None;
+        let mut rule = Rule::new(Frequency::Daily);
+        for part in s.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid rule part {}", part))?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => bail!("Unsupported frequency {}", value),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value.parse().context("Invalid INTERVAL")?;
+                    if rule.interval == 0 {
+                        bail!("INTERVAL must be positive");
+                    }
+                }
+                "COUNT" => rule.count = Some(value.parse().context("Invalid COUNT")?),
+                "UNTIL" => {
+                    // Only the date of a date-time is used.
+                    let date = value.get(..8).unwrap_or(value);
+                    let format = format_description!("[year][month][day]");
+                    rule.until = Some(Date::parse(date, format).context("Invalid UNTIL")?);
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let day = day.to_ascii_uppercase();
+                        let weekday = WEEKDAYS
+                            .iter()
+                            .find(|(name, _)| *name == day)
+                            .map(|(_, weekday)| *weekday)
+                            .ok_or_else(|| anyhow!("Unsupported BYDAY {}", day))?;
+                        rule.by_day.push(weekday);
+                    }
+                }
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        let day: i8 = day.parse().context("Invalid BYMONTHDAY")?;
+                        if day == 0 || !(-31..=31).contains(&day) {
+                            bail!("Invalid BYMONTHDAY {}", day);
+                        }
+                        rule.by_month_day.push(day);
+                    }
+                }
+                _ => bail!("Unsupported rule part {}", key),
+            }
+        }
+        rule.frequency = frequency.ok_or_else(|| anyhow!("A rule needs a FREQ"))?;
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={}", frequency)?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = self.until {
+            write!(
+                f,
+                ";UNTIL={:04}{:02}{:02}",
+                until.year(),
+                until.month() as u8,
+                until.day()
+            )?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self
+                .by_day
+                .iter()
+                .filter_map(|d| WEEKDAYS.iter().find(|(_, w)| w == d).map(|(n, _)| *n))
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if !self.by_month_day.is_empty() {
+            let days: Vec<String> = self.by_month_day.iter().map(i8::to_string).collect();
+            write!(f, ";BYMONTHDAY={}", days.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Days are stored as midnight UTC, so every device agrees on them.
+fn day_datum(date: Date) -> Datum {
+    Datum::DateTime(
+        date.midnight()
+            .assume_offset(UtcOffset::UTC)
+            .unix_timestamp(),
+    )
+}
+
+fn dates(entity: &Entity, predicate: &str) -> BTreeSet<Date> {
+    entity
+        .values(predicate)
+        .iter()
+        .filter_map(|datum| match datum {
+            Datum::DateTime(seconds) => OffsetDateTime::from_unix_timestamp(*seconds)
+                .ok()
+                .map(|d| d.date()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The rule of a recurring entity, or `None` if it does not recur or its
+/// rule is invalid.
+pub fn rule(entity: &Entity) -> Option<Rule> {
+    match entity.value("rrule") {
+        Some(Datum::String(rule)) => rule.parse().ok(),
+        _ => None,
+    }
+}
+
+/// The occurrences of `entity` from `start` until before `end`, with
+/// skipped and moved occurrences applied.
+pub fn occurrences(entity: &Entity, start: Date, end: Date) -> Vec<Date> {
+    let (Some(rule), Some(&dtstart)) = (rule(entity), dates(entity, "dtstart").first()) else {
+        return Vec::new();
+    };
+    let excluded = dates(entity, "exdate");
+    let mut occurrences: BTreeSet<Date> = rule
+        .expand(dtstart, end)
+        .into_iter()
+        .filter(|d| *d >= start && !excluded.contains(d))
+        .collect();
+    occurrences.extend(
+        dates(entity, "rdate")
+            .into_iter()
+            .filter(|d| (start..end).contains(d) && !excluded.contains(d)),
+    );
+    occurrences.into_iter().collect()
+}
+
+/// The first occurrence of `entity` on or after `from`, looking at most ten
+/// years ahead.
+pub fn next_occurrence(entity: &Entity, from: Date) -> Option<Date> {
+    let end = from
+        .replace_year(from.year() + 10)
+        .unwrap_or(from + Duration::weeks(520));
+    occurrences(entity, from, end).into_iter().next()
+}
+
+/// The recurring entities that occur on each day from `start` until before
+/// `end`.
+pub fn by_day(projection: &Projection, start: Date, end: Date) -> BTreeMap<Date, Vec<Uuid>> {
+    let mut days: BTreeMap<Date, Vec<Uuid>> = BTreeMap::new();
+    for (id, entity) in projection.entities() {
+        for date in occurrences(entity, start, end) {
+            days.entry(date).or_default().push(*id);
+        }
+    }
+    days
+}
+
+/// Returns the actions that make `id` recur by `rule` from `start`.
+pub fn set_rule(projection: &Projection, id: Uuid, rule: &Rule, start: Date) -> Vec<Action> {
+    let mut facts = BTreeMap::new();
+    facts.insert("rrule".to_string(), vec![Datum::String(rule.to_string())]);
+    facts.insert("dtstart".to_string(), vec![day_datum(start)]);
+    upsert(projection, id, &facts)
+}
+
+/// Returns the action that skips the occurrence of `id` on `date`.
+pub fn skip(id: Uuid, date: Date) -> Action {
+    Action::AddFact {
+        subject: id,
+        predicate: "exdate".to_string(),
+        datum: day_datum(date),
+    }
+}
+
+/// Returns the actions that move the occurrence of `id` on `from` to `to`.
+pub fn reschedule(id: Uuid, from: Date, to: Date) -> Vec<Action> {
+    vec![
+        skip(id, from),
+        Action::AddFact {
+            subject: id,
+            predicate: "rdate".to_string(),
+            datum: day_datum(to),
+        },
+    ]
+}
+
+/// Returns the actions that bring the `next_occurrence` of every recurring
+/// entity up to date as of `today`.
+pub fn materialize(projection: &Projection, today: Date) -> Vec<Action> {
+    projection
+        .entities()
+        .filter(|(_, entity)| entity.value("rrule").is_some())
+        .flat_map(|(id, entity)| {
+            let next = next_occurrence(entity, today);
+            let mut facts = BTreeMap::new();
+            facts.insert(
+                "next_occurrence".to_string(),
+                next.map(day_datum).into_iter().collect(),
+            );
+            upsert(projection, *id, &facts)
+        })
+        .collect()
+}
+
+/// How often the `next_occurrence` facts are brought up to date.
+pub const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Adds a job to `scheduler` that keeps the `next_occurrence` facts current.
+pub fn schedule(
+    scheduler: Scheduler,
+    storage: AsyncStorage,
+    mut creator: EventCreator,
+) -> Scheduler {
+    scheduler.every("recurrence", REFRESH_INTERVAL, move || {
+        let projection = futures::executor::block_on(storage.call(|s| Projection::load(s)))?;
+        let actions = materialize(&projection, crate::journal::today());
+        if actions.is_empty() {
+            return Ok(());
+        }
+        let event = creator.create(Action::Transaction { actions });
+        Ok(futures::executor::block_on(storage.record(event))?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    fn recurring(rule: &str, start: Date) -> (Projection, Uuid) {
+        let mut projection = Projection::new();
+        let id = Uuid::new_v4();
+        set_rule(&projection, id, &rule.parse().unwrap(), start)
+            .iter()
+            .for_each(|a| projection.apply(a));
+        (projection, id)
+    }
+
+    #[test]
+    fn rules_round_trip() {
+        let rule = "FREQ=WEEKLY;INTERVAL=2;COUNT=5;UNTIL=20241231;BYDAY=MO,TH";
+        assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        assert_eq!(
+            "RRULE:FREQ=MONTHLY;BYMONTHDAY=-1;UNTIL=20240101T000000Z"
+                .parse::<Rule>()
+                .unwrap()
+                .until,
+            Some(date!(2024 - 01 - 01))
+        );
+        assert!("INTERVAL=2".parse::<Rule>().is_err());
+        assert!("FREQ=HOURLY".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn expands_rules() {
+        // A Monday.
+        let start = date!(2024 - 01 - 01);
+        let end = date!(2025 - 01 - 01);
+        let expand = |rule: &str| rule.parse::<Rule>().unwrap().expand(start, end);
+        assert_eq!(
+            expand("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE;COUNT=3"),
+            [
+                date!(2024 - 01 - 01),
+                date!(2024 - 01 - 03),
+                date!(2024 - 01 - 15)
+            ]
+        );
+        assert_eq!(
+            expand("FREQ=MONTHLY;BYMONTHDAY=-1;UNTIL=20240331"),
+            [
+                date!(2024 - 01 - 31),
+                date!(2024 - 02 - 29),
+                date!(2024 - 03 - 31)
+            ]
+        );
+        assert_eq!(expand("FREQ=DAILY;BYDAY=SA,SU").len(), 104);
+        assert_eq!(
+            "FREQ=YEARLY"
+                .parse::<Rule>()
+                .unwrap()
+                .expand(date!(2024 - 02 - 29), date!(2029 - 01 - 01)),
+            [date!(2024 - 02 - 29), date!(2028 - 02 - 29)]
+        );
+    }
+
+    #[test]
+    fn exceptions_skip_and_move_occurrences() {
+        let (mut projection, id) = recurring("FREQ=WEEKLY", date!(2024 - 01 - 01));
+        let mut actions = vec![skip(id, date!(2024 - 01 - 08))];
+        actions.extend(reschedule(id, date!(2024 - 01 - 15), date!(2024 - 01 - 17)));
+        actions.iter().for_each(|a| projection.apply(a));
+
+        let entity = projection.entity(&id).unwrap();
+        assert_eq!(
+            occurrences(entity, date!(2024 - 01 - 01), date!(2024 - 01 - 23)),
+            [
+                date!(2024 - 01 - 01),
+                date!(2024 - 01 - 17),
+                date!(2024 - 01 - 22)
+            ]
+        );
+        assert_eq!(
+            by_day(&projection, date!(2024 - 01 - 16), date!(2024 - 01 - 18)),
+            BTreeMap::from([(date!(2024 - 01 - 17), vec![id])])
+        );
+    }
+
+    #[test]
+    fn materializes_the_next_occurrence() {
+        let (mut projection, id) = recurring("FREQ=DAILY;COUNT=3", date!(2024 - 01 - 01));
+        let next = |projection: &Projection| {
+            projection
+                .entity(&id)
+                .unwrap()
+                .value("next_occurrence")
+                .cloned()
+        };
+
+        materialize(&projection, date!(2024 - 01 - 02))
+            .iter()
+            .for_each(|a| projection.apply(a));
+        assert_eq!(next(&projection), Some(day_datum(date!(2024 - 01 - 02))));
+        assert!(materialize(&projection, date!(2024 - 01 - 02)).is_empty());
+
+        materialize(&projection, date!(2024 - 01 - 04))
+            .iter()
+            .for_each(|a| projection.apply(a));
+        assert_eq!(next(&projection), None);
+    }
+}
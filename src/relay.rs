@@ -0,0 +1,580 @@
+//! Store-and-forward sync through a relay, for devices that are never online
+//! at the same time.
+//!
+//! The relay, run with the `graphite-relay` binary, keeps the batches of
+//! events devices send it by graph id, without reading them: batches are
+//! sealed like backups, see [`crate::backup::Secret`]. It stamps each batch
+//! with its own hybrid logical clock as it arrives, so a device asks for the
+//! batches after the stamp of the last one it got, its watermark, and never
+//! misses one sent late by a device whose clock is behind.
+//!
+//! - `POST /graphs/<graph>/batches` stores the body and answers its stamp,
+//! - `GET /graphs/<graph>/batches?seconds=…&logical=…&limit=…` answers the
+//!   batches after the stamp, oldest first.
+//!
+//! Syncing pulls the batches after the watermark and merges their events,
+//! see [`crate::conflicts::merge`], then pushes the events recorded since
+//! the last push.
+
+use crate::backup::Secret;
+use crate::conflicts;
+use crate::legacy::hlc::{self, HLTimestamp};
+use crate::legacy::storage::{metadata, Event, EventStorage};
+use crate::replication::{self, Subscription};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tiny_http::{Request, Response, Server};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+/// The environment variable that sets the token of the relay.
+pub const TOKEN_VARIABLE: &str = "GRAPHITE_RELAY_TOKEN";
+/// The largest batch the relay accepts.
+pub const MAX_BATCH: u64 = 64 * 1024 * 1024;
+/// The most batches answered at once.
+const PAGE: usize = 100;
+/// The most events sent in one batch.
+const EVENTS_PER_BATCH: usize = 1000;
+/// The change pushed last, see [`EventStorage::poll_changes`].
+const PUSHED: &str = "relay_pushed";
+/// The stamp of the last batch pulled.
+const WATERMARK: &str = "relay_watermark";
+/// The subscription the events before the watermark were pulled with.
+const SUBSCRIPTION: &str = "relay_subscription";
+
+/// Where and as which graph a device syncs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The relay, e.g. `https://relay.example.com`.
+    pub url: Option<String>,
+    /// The id the devices of the graph share on the relay.
+    pub graph: Option<Uuid>,
+    /// The token of the relay, unless `GRAPHITE_RELAY_TOKEN` is set.
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Batch {
+    pub stamp: HLTimestamp,
+    #[serde(with = "base64_bytes")]
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Page {
+    batches: Vec<Batch>,
+}
+
+/// The batches kept by the relay.
+pub struct Store {
+    conn: Connection,
+    clock: hlc::State<fn() -> i64>,
+}
+
+impl Store {
+    pub fn open(path: impl AsRef<Path>) -> Result<Store> {
+        let path = path.as_ref();
+        let conn =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = FULL;
+            CREATE TABLE IF NOT EXISTS batches (
+                graph BLOB NOT NULL, -- UUID as BLOB
+                seconds INTEGER NOT NULL,
+                logical INTEGER NOT NULL,
+                payload BLOB NOT NULL,
+                PRIMARY KEY (graph, seconds, logical)
+            );",
+        )
+        .context("Failed to create the batches table")?;
+        let mut clock = hlc::State::new();
+        let latest: Option<(i64, u16)> = conn
+            .query_row(
+                "SELECT seconds, logical FROM batches
+                ORDER BY seconds DESC, logical DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .context("Failed to read the latest stamp")?;
+        if let Some((seconds, logical)) = latest {
+            clock.update(HLTimestamp::new(seconds, logical));
+        }
+        Ok(Store { conn, clock })
+    }
+
+    /// Keeps `payload` for `graph` and returns its stamp.
+    pub fn append(&mut self, graph: Uuid, payload: &[u8]) -> Result<HLTimestamp> {
+        let stamp = self.clock.get_time();
+        self.conn
+            .execute(
+                "INSERT INTO batches (graph, seconds, logical, payload) VALUES (?, ?, ?, ?)",
+                rusqlite::params![graph, stamp.seconds(), stamp.logical(), payload],
+            )
+            .context("Failed to store the batch")?;
+        Ok(stamp)
+    }
+
+    /// The batches of `graph` stamped after `after`, oldest first.
+    pub fn since(&self, graph: Uuid, after: HLTimestamp, limit: usize) -> Result<Vec<Batch>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT seconds, logical, payload FROM batches
+                WHERE graph = ?1 AND (seconds > ?2 OR (seconds = ?2 AND logical > ?3))
+                ORDER BY seconds, logical LIMIT ?4",
+            )
+            .context("Failed to prepare SQL statement to read batches")?;
+        let batches = stmt
+            .query_map(
+                rusqlite::params![graph, after.seconds(), after.logical(), limit as i64],
+                |row| {
+                    Ok(Batch {
+                        stamp: HLTimestamp::new(row.get(0)?, row.get(1)?),
+                        payload: row.get(2)?,
+                    })
+                },
+            )
+            .context("Failed to read batches")?
+            .collect::<rusqlite::Result<_>>()
+            .context("Failed to read batches")?;
+        Ok(batches)
+    }
+}
+
+/// Answers the requests of devices from a [`Store`].
+pub struct Relay {
+    store: Store,
+    token: Option<String>,
+}
+
+impl Relay {
+    /// A relay that requires `token`, if given, from devices.
+    pub fn new(store: Store, token: Option<String>) -> Relay {
+        Relay { store, token }
+    }
+
+    /// Returns the status code and body to answer a request with.
+    pub fn handle(
+        &mut self,
+        method: &str,
+        url: &str,
+        authorization: Option<&str>,
+        body: &[u8],
+    ) -> (u16, Vec<u8>) {
+        let text = |status, message: &str| (status, message.as_bytes().to_vec());
+        if let Some(token) = &self.token {
+            let given = authorization.and_then(|a| a.strip_prefix("Bearer "));
+            if !given.is_some_and(|t| constant_time_eq(t.as_bytes(), token.as_bytes())) {
+                return text(401, "Invalid token");
+            }
+        }
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let graph = match path
+            .strip_prefix("/graphs/")
+            .and_then(|rest| rest.strip_suffix("/batches"))
+            .map(Uuid::parse_str)
+        {
+            Some(Ok(graph)) => graph,
+            Some(Err(_)) => return text(400, "Invalid graph id"),
+            None => return text(404, "Not found"),
+        };
+        let answer = match method {
+            "POST" => self
+                .store
+                .append(graph, body)
+                .map(|stamp| (201, serde_json::to_vec(&stamp))),
+            "GET" => {
+                let param = |name: &str| {
+                    query
+                        .split('&')
+                        .filter_map(|pair| pair.split_once('='))
+                        .find(|(key, _)| *key == name)
+                        .map(|(_, value)| value)
+                };
+                let (Ok(seconds), Ok(logical), Ok(limit)) = (
+                    param("seconds").unwrap_or("0").parse(),
+                    param("logical").unwrap_or("0").parse(),
+                    param("limit").map_or(Ok(PAGE), str::parse::<usize>),
+                ) else {
+                    return text(400, "Invalid watermark");
+                };
+                let after = HLTimestamp::new(seconds, logical);
+                self.store
+                    .since(graph, after, limit.min(PAGE))
+                    .map(|batches| (200, serde_json::to_vec(&Page { batches })))
+            }
+            _ => return text(405, "Only GET and POST are allowed"),
+        };
+        match answer {
+            Ok((status, Ok(body))) => (status, body),
+            Ok((_, Err(e))) => text(500, &e.to_string()),
+            Err(e) => text(500, &format!("{:#}", e)),
+        }
+    }
+
+    fn answer(&mut self, request: &mut Request) -> (u16, Vec<u8>) {
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .map(|h| h.value.to_string());
+        let mut body = Vec::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BATCH + 1)
+            .read_to_end(&mut body);
+        if read.is_err() {
+            return (400, b"Failed to read the body".to_vec());
+        }
+        if body.len() as u64 > MAX_BATCH {
+            return (413, b"The batch is too large".to_vec());
+        }
+        self.handle(
+            request.method().as_str(),
+            request.url(),
+            authorization.as_deref(),
+            &body,
+        )
+    }
+
+    /// Serves requests on `address` from a background thread.
+    pub fn serve(mut self, address: impl ToSocketAddrs) -> Result<RelayServer> {
+        let server = Server::http(address).map_err(|e| anyhow!("Failed to listen: {}", e))?;
+        let server = Arc::new(server);
+        let address = server
+            .server_addr()
+            .to_ip()
+            .context("The relay is not listening on an IP address")?;
+        let listener = server.clone();
+        let thread = thread::Builder::new()
+            .name("graphite-relay".into())
+            .spawn(move || {
+                for mut request in listener.incoming_requests() {
+                    let (status, body) = self.answer(&mut request);
+                    let _ = request.respond(Response::from_data(body).with_status_code(status));
+                }
+            })
+            .context("Failed to spawn the relay thread")?;
+        info!(%address, "Relaying");
+        Ok(RelayServer {
+            server,
+            address,
+            thread: Some(thread),
+        })
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// A running relay, stopped when dropped.
+pub struct RelayServer {
+    server: Arc<Server>,
+    address: SocketAddr,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RelayServer {
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Serves until the process ends.
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RelayServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Talks to a relay.
+pub struct Client {
+    url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    pub fn new(settings: &Settings) -> Result<Client> {
+        let Some(url) = &settings.url else {
+            bail!("Set the url of a relay in the config");
+        };
+        Ok(Client {
+            url: url.trim_end_matches('/').to_string(),
+            token: std::env::var(TOKEN_VARIABLE)
+                .ok()
+                .or_else(|| settings.token.clone())
+                .filter(|token| !token.is_empty()),
+        })
+    }
+
+    fn request(&self, method: &str, graph: Uuid) -> ureq::Request {
+        let request = ureq::request(method, &format!("{}/graphs/{}/batches", self.url, graph));
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    pub fn push(&self, graph: Uuid, payload: &[u8]) -> Result<HLTimestamp> {
+        let response = crate::backup::send(self.request("POST", graph), payload)
+            .with_context(|| format!("Failed to push to {}", self.url))?;
+        Ok(serde_json::from_reader(response.into_reader())?)
+    }
+
+    pub fn pull(&self, graph: Uuid, after: HLTimestamp) -> Result<Vec<Batch>> {
+        let request = self
+            .request("GET", graph)
+            .query("seconds", &after.seconds().to_string())
+            .query("logical", &after.logical().to_string());
+        let response = crate::backup::send(request, &[])
+            .with_context(|| format!("Failed to pull from {}", self.url))?;
+        let page: Page = serde_json::from_reader(response.into_reader())?;
+        Ok(page.batches)
+    }
+}
+
+/// What syncing did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Synced {
+    pub pulled: usize,
+    pub pushed: usize,
+    pub conflicts: usize,
+}
+
+/// Merges the batches of the graph on the relay that `storage` hasn't seen,
+/// restricted to `subscription`, then pushes the events recorded since the
+/// last sync.
+#[instrument(skip_all)]
+pub fn sync(
+    storage: &mut EventStorage,
+    settings: &Settings,
+    secret: &Secret,
+    subscription: &Subscription,
+) -> Result<Synced> {
+    let client = Client::new(settings)?;
+    let Some(graph) = settings.graph else {
+        bail!("Set the graph id shared by the devices in the config");
+    };
+    let origin = format!("relay/{}", graph);
+    let mut synced = Synced::default();
+
+    let mut watermark = match storage.meta_value(WATERMARK)? {
+        // Pull again what another subscription left out.
+        Some(_) if replication::read_with(storage, SUBSCRIPTION)? != *subscription => {
+            HLTimestamp::new(0, 0)
+        }
+        Some(stamp) => stamp
+            .parse()
+            .map_err(|e| anyhow!("Invalid relay watermark {}: {}", stamp, e))?,
+        None => HLTimestamp::new(0, 0),
+    };
+    let mut pulled = Vec::new();
+    loop {
+        let batches = client.pull(graph, watermark)?;
+        if batches.is_empty() {
+            break;
+        }
+        for batch in batches {
+            pulled.extend(decode(&secret.open(&batch.payload)?)?);
+            watermark = batch.stamp;
+        }
+    }
+    let pulled: Vec<Event> = pulled.into_iter().map(|e| e.synced_from(&origin)).collect();
+    let pulled = replication::select_for(storage, subscription, pulled)?;
+    (synced.pulled, synced.conflicts) = conflicts::merge(storage, pulled)?;
+    storage.set_meta_value(WATERMARK, &watermark.to_string())?;
+    replication::set_read_with(storage, SUBSCRIPTION, subscription)?;
+
+    // Everything the first time, then what was recorded since.
+    let (events, last) = match storage.meta_value(PUSHED)? {
+        Some(since) => {
+            let since = since.parse().context("Invalid relay change")?;
+            let changes = storage.poll_changes(since)?;
+            (changes.events, changes.last)
+        }
+        None => {
+            let last = storage.last_change()?;
+            let mut events = Vec::new();
+            storage.play(|event| {
+                events.push(event);
+                Ok(())
+            })?;
+            (events, last)
+        }
+    };
+    let events: Vec<Event> = events
+        .into_iter()
+        .filter(|e| e.metadata().get(metadata::SYNC_ORIGIN) != Some(&origin))
+        .collect();
+    for chunk in events.chunks(EVENTS_PER_BATCH) {
+        client.push(graph, &secret.seal(&encode(chunk)?)?)?;
+        synced.pushed += chunk.len();
+    }
+    storage.set_meta_value(PUSHED, &last.to_string())?;
+    info!(
+        pulled = synced.pulled,
+        pushed = synced.pushed,
+        conflicts = synced.conflicts,
+        "Synced"
+    );
+    Ok(synced)
+}
+
+/// Events as gzipped JSON lines.
+fn encode(events: &[Event]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    for event in events {
+        serde_json::to_writer(&mut encoder, event)?;
+        encoder.write_all(b"\n")?;
+    }
+    Ok(encoder.finish()?)
+}
+
+fn decode(payload: &[u8]) -> Result<Vec<Event>> {
+    BufReader::new(GzDecoder::new(payload))
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+        .map(|line| {
+            Ok(serde_json::from_str(
+                &line.context("The batch is corrupt")?,
+            )?)
+        })
+        .collect()
+}
+
+mod base64_bytes {
+    use super::{Engine, STANDARD};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    #[test]
+    fn devices_converge_through_the_relay() {
+        let path = std::env::temp_dir().join(format!("graphite-relay-{}.db", Uuid::new_v4()));
+        let relay = Relay::new(Store::open(&path).unwrap(), Some("token".into()))
+            .serve("127.0.0.1:0")
+            .unwrap();
+        let mut settings = Settings {
+            url: Some(format!("http://{}", relay.address())),
+            graph: Some(Uuid::new_v4()),
+            token: Some("wrong".into()),
+        };
+        let secret = Secret::Passphrase("secret".into());
+        let everything = Subscription::default();
+        let record = |storage: &mut EventStorage| {
+            let mut creator = storage.creator().unwrap();
+            let event = creator.create(Action::CreateEntity { id: Uuid::new_v4() });
+            storage.record_batch(vec![event]).unwrap();
+        };
+        let [mut laptop, mut phone] = [(); 2].map(|_| EventStorage::open(":memory:").unwrap());
+        record(&mut laptop);
+        assert!(sync(&mut laptop, &settings, &secret, &everything).is_err());
+
+        settings.token = Some("token".into());
+        let synced = sync(&mut laptop, &settings, &secret, &everything).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (0, 1));
+        // The phone was offline meanwhile.
+        record(&mut phone);
+        let synced = sync(&mut phone, &settings, &secret, &everything).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (1, 1));
+        let synced = sync(&mut laptop, &settings, &secret, &everything).unwrap();
+        assert_eq!(synced.pulled, 1);
+        // Nothing new either way.
+        let synced = sync(&mut phone, &settings, &secret, &everything).unwrap();
+        assert_eq!((synced.pulled, synced.pushed), (0, 0));
+
+        let events = |storage: &EventStorage| {
+            let mut ids = Vec::new();
+            storage
+                .play(|event| {
+                    ids.push(event.id());
+                    Ok(())
+                })
+                .unwrap();
+            ids.sort();
+            ids
+        };
+        assert_eq!(events(&laptop), events(&phone));
+        assert_eq!(events(&laptop).len(), 2);
+        drop(relay);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn widening_the_subscription_pulls_what_it_adds() {
+        let path = std::env::temp_dir().join(format!("graphite-relay-{}.db", Uuid::new_v4()));
+        let relay = Relay::new(Store::open(&path).unwrap(), None)
+            .serve("127.0.0.1:0")
+            .unwrap();
+        let settings = Settings {
+            url: Some(format!("http://{}", relay.address())),
+            graph: Some(Uuid::new_v4()),
+            token: None,
+        };
+        let secret = Secret::Passphrase("secret".into());
+        let [mut laptop, mut phone] = [(); 2].map(|_| EventStorage::open(":memory:").unwrap());
+        let mut creator = laptop.creator().unwrap();
+        let mut events = Vec::new();
+        for name in ["Graphite", "Chores"] {
+            let id = Uuid::new_v4();
+            events.push(creator.create(Action::CreateEntity { id }));
+            events.push(creator.create(Action::AddFact {
+                subject: id,
+                predicate: "project".to_string(),
+                datum: crate::legacy::storage::Datum::String(name.to_string()),
+            }));
+        }
+        laptop.record_batch(events).unwrap();
+        sync(&mut laptop, &settings, &secret, &Subscription::default()).unwrap();
+
+        let mut subscription = Subscription {
+            queries: vec!["project=Graphite".to_string()],
+            depth: 0,
+        };
+        let synced = sync(&mut phone, &settings, &secret, &subscription).unwrap();
+        assert_eq!(synced.pulled, 2);
+        let synced = sync(&mut phone, &settings, &secret, &subscription).unwrap();
+        assert_eq!(synced.pulled, 0);
+        subscription.queries.push("project=Chores".to_string());
+        let synced = sync(&mut phone, &settings, &secret, &subscription).unwrap();
+        assert_eq!(synced.pulled, 2);
+        drop(relay);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,264 @@
+//! Partial replication: merging only part of the graph of a backup.
+//!
+//! A [`Subscription`] selects the entities matching any of its queries, in
+//! the graph merged or in the local one, and their neighbors up to `depth`
+//! links away in either direction. The events touching them are merged
+//! whole, so transactions stay atomic, along with every chunk of a chunked
+//! transaction. The entities they link to beyond that, the boundary, only
+//! get the events creating, deleting or naming them, so links show a label
+//! rather than a dangling id.
+//!
+//! Matching the local graph too keeps entities that leave the subset up to
+//! date. Merging records the events missing locally, so once the
+//! subscription is widened, the next merge brings in the whole history of
+//! the entities it adds. Syncs that only read what is new since the last
+//! one store the subscription they read with, see [`read_with`], and read
+//! everything again when it changes.
+
+use crate::dedupe::KEYS;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum, Event, EventStorage};
+use crate::query::Query;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscription {
+    /// The queries selecting the entities replicated, see [`crate::query`].
+    /// None replicates everything.
+    pub queries: Vec<String>,
+    /// How many links away from a selected entity its neighbors are
+    /// replicated too.
+    pub depth: usize,
+}
+
+impl Subscription {
+    pub fn is_everything(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Selection {
+    /// The entities replicated whole.
+    pub selected: HashSet<Uuid>,
+    /// The entities linked to from the selected ones, replicated with their
+    /// names only.
+    pub boundary: HashSet<Uuid>,
+}
+
+impl Selection {
+    /// Whether `event` is merged.
+    fn keeps(&self, event: &Event) -> bool {
+        event.action().touches().iter().any(|(subject, predicate)| {
+            self.selected.contains(subject)
+                || (self.boundary.contains(subject) && predicate.is_none_or(is_key))
+        })
+    }
+}
+
+fn is_key(predicate: &str) -> bool {
+    KEYS.contains(&predicate)
+}
+
+/// The entities `subscription` selects in the `local` graph and the
+/// `remote` one.
+pub fn selection(
+    subscription: &Subscription,
+    local: &Projection,
+    remote: &Projection,
+) -> Result<Selection> {
+    let queries = subscription
+        .queries
+        .iter()
+        .map(|q| {
+            q.parse::<Query>()
+                .with_context(|| format!("Failed to parse the subscription query {}", q))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let graphs = [local, remote];
+
+    let mut selected: HashSet<Uuid> = HashSet::new();
+    for graph in graphs {
+        for (id, entity) in graph.entities() {
+            if queries.iter().any(|q| q.matches(graph, entity)) {
+                selected.insert(*id);
+            }
+        }
+    }
+
+    // Links in both directions, in either graph.
+    let mut links: HashMap<Uuid, HashSet<Uuid>> = HashMap::new();
+    for graph in graphs {
+        for (id, entity) in graph.entities() {
+            for (_, values) in entity.facts() {
+                for datum in values {
+                    if let Datum::Entity(target) = datum {
+                        links.entry(*id).or_default().insert(*target);
+                        links.entry(*target).or_default().insert(*id);
+                    }
+                }
+            }
+        }
+    }
+    let mut frontier: Vec<Uuid> = selected.iter().copied().collect();
+    for _ in 0..subscription.depth {
+        frontier = frontier
+            .iter()
+            .flat_map(|id| links.get(id).into_iter().flatten())
+            .filter(|neighbor| selected.insert(**neighbor))
+            .copied()
+            .collect();
+    }
+
+    let mut boundary = HashSet::new();
+    for graph in graphs {
+        for id in &selected {
+            let Some(entity) = graph.entity(id) else {
+                continue;
+            };
+            for (_, values) in entity.facts() {
+                for datum in values {
+                    if let Datum::Entity(target) = datum {
+                        if !selected.contains(target) {
+                            boundary.insert(*target);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(Selection { selected, boundary })
+}
+
+/// The `events` of another replica that `subscription` selects, given the
+/// `local` graph.
+pub fn select(
+    subscription: &Subscription,
+    local: &Projection,
+    events: Vec<Event>,
+) -> Result<Vec<Event>> {
+    if subscription.is_everything() {
+        return Ok(events);
+    }
+    let mut remote = Projection::new();
+    events.iter().for_each(|e| remote.apply_event(e));
+    let selection = selection(subscription, local, &remote)?;
+
+    let chunked = |event: &Event| match event.action() {
+        Action::Chunk { transaction, .. } => Some(*transaction),
+        _ => None,
+    };
+    let transactions: HashSet<Uuid> = events
+        .iter()
+        .filter(|e| selection.keeps(e))
+        .filter_map(chunked)
+        .collect();
+    Ok(events
+        .into_iter()
+        .filter(|e| selection.keeps(e) || chunked(e).is_some_and(|t| transactions.contains(&t)))
+        .collect())
+}
+
+/// The subscription events were last read with, stored under `key` in the
+/// metadata of `storage`, everything if none was.
+pub fn read_with(storage: &EventStorage, key: &str) -> Result<Subscription> {
+    match storage.meta_value(key)? {
+        Some(json) => {
+            serde_json::from_str(&json).with_context(|| format!("Invalid subscription {}", json))
+        }
+        None => Ok(Subscription::default()),
+    }
+}
+
+/// Stores `subscription` under `key` in the metadata of `storage`, as the
+/// one events were read with.
+pub fn set_read_with(storage: &EventStorage, key: &str, subscription: &Subscription) -> Result<()> {
+    storage.set_meta_value(key, &serde_json::to_string(subscription)?)?;
+    Ok(())
+}
+
+/// Like [`select`], with the graph of `storage`.
+pub fn select_for(
+    storage: &EventStorage,
+    subscription: &Subscription,
+    events: Vec<Event>,
+) -> Result<Vec<Event>> {
+    if subscription.is_everything() {
+        return Ok(events);
+    }
+    let local = Projection::load(storage).context("Failed to load the local graph")?;
+    select(subscription, &local, events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::EventCreator;
+
+    #[test]
+    fn subscriptions_select_a_subgraph() {
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(0, 0));
+        let [project, other, task, person, team, chore] = [(); 6].map(|_| Uuid::new_v4());
+        let fact = |subject: Uuid, predicate: &str, datum: Datum| Action::AddFact {
+            subject,
+            predicate: predicate.to_string(),
+            datum,
+        };
+        let text = |s: &str| Datum::String(s.to_string());
+        let mut events = Vec::new();
+        for (id, name) in [
+            (project, "Graphite"),
+            (other, "Garden"),
+            (person, "Ada"),
+            (team, "Core"),
+        ] {
+            events.push(creator.create(Action::Transaction {
+                actions: vec![Action::CreateEntity { id }, fact(id, "name", text(name))],
+            }));
+        }
+        events.push(creator.create(fact(project, "status", text("active"))));
+        events.push(creator.create(fact(person, "team", Datum::Entity(team))));
+        for (id, parent) in [(task, project), (chore, other)] {
+            events.push(creator.create(Action::CreateEntity { id }));
+            events.push(creator.create(fact(id, "project", Datum::Entity(parent))));
+        }
+        events.push(creator.create(fact(task, "assignee", Datum::Entity(person))));
+
+        let mut subscription = Subscription {
+            queries: vec!["project=Graphite".to_string()],
+            depth: 0,
+        };
+        let local = Projection::new();
+        let mut merged = Projection::new();
+        select(&subscription, &local, events.clone())
+            .unwrap()
+            .iter()
+            .for_each(|e| merged.apply_event(e));
+        assert_eq!(merged.entity(&task).unwrap().values("assignee").len(), 1);
+        // The boundary, named but without its other facts.
+        assert_eq!(
+            merged.entity(&project).unwrap().value("name"),
+            Some(&text("Graphite"))
+        );
+        assert!(merged.entity(&project).unwrap().value("status").is_none());
+        assert!(merged.entity(&person).unwrap().value("team").is_none());
+        for id in [other, team, chore] {
+            assert!(!merged.contains(&id));
+        }
+
+        // Widened, the neighbors come in whole and the boundary moves out.
+        subscription.depth = 1;
+        let selection = selection(&subscription, &merged, &merged).unwrap();
+        assert_eq!(selection.selected, HashSet::from([task, project, person]));
+        let widened = select(&subscription, &merged, events).unwrap();
+        widened.iter().for_each(|e| merged.apply_event(e));
+        assert!(merged.entity(&project).unwrap().value("status").is_some());
+        assert!(merged.contains(&team));
+        assert!(!merged.contains(&chore));
+    }
+}
@@ -0,0 +1,326 @@
+//! Rollups aggregate facts of an entity's descendants, such as the share of
+//! tasks in a project that are done or its earliest due date.
+//!
+//! The hierarchy is given by a predicate that links a child to its parent,
+//! `parent` by default. Rollups are derived facts: they are computed from the
+//! projection and never recorded. [`Rollups::update`] only recomputes the
+//! ancestors of the entities that changed.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "aggregate", rename_all = "snake_case")]
+pub enum Aggregate {
+    /// The number of descendants with the predicate.
+    Count,
+    Sum,
+    Min,
+    Max,
+    /// The percentage of descendants with the predicate whose value is the
+    /// string `equals`.
+    Percent {
+        equals: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Definition {
+    /// The name of the derived fact.
+    pub name: String,
+    /// The predicate of the descendants that is aggregated.
+    pub predicate: String,
+    #[serde(flatten)]
+    pub aggregate: Aggregate,
+}
+
+impl Definition {
+    /// The percentage of tasks done, the sum of estimates and the earliest
+    /// due date.
+    pub fn defaults() -> Vec<Definition> {
+        vec![
+            Definition {
+                name: "done".to_string(),
+                predicate: "status".to_string(),
+                aggregate: Aggregate::Percent {
+                    equals: "done".to_string(),
+                },
+            },
+            Definition {
+                name: "estimate".to_string(),
+                predicate: "estimate".to_string(),
+                aggregate: Aggregate::Sum,
+            },
+            Definition {
+                name: "earliest due".to_string(),
+                predicate: "due".to_string(),
+                aggregate: Aggregate::Min,
+            },
+        ]
+    }
+
+    fn evaluate<'a>(&self, values: impl Iterator<Item = &'a Datum>) -> Option<Datum> {
+        match &self.aggregate {
+            Aggregate::Count => Some(Datum::Integer(values.count() as i64)),
+            Aggregate::Sum => values.fold(None, |sum, datum| match (sum, datum) {
+                (None, Datum::Integer(_) | Datum::Float(_)) => Some(datum.clone()),
+                (Some(Datum::Integer(a)), Datum::Integer(b)) => Some(Datum::Integer(a + b)),
+                (Some(Datum::Integer(a)), Datum::Float(b)) => Some(Datum::Float(a as f64 + b)),
+                (Some(Datum::Float(a)), Datum::Integer(b)) => Some(Datum::Float(a + *b as f64)),
+                (Some(Datum::Float(a)), Datum::Float(b)) => Some(Datum::Float(a + b)),
+                (sum, _) => sum,
+            }),
+            Aggregate::Min => extreme(values, Ordering::Less),
+            Aggregate::Max => extreme(values, Ordering::Greater),
+            Aggregate::Percent { equals } => {
+                let (mut matching, mut total) = (0, 0);
+                for datum in values {
+                    total += 1;
+                    if matches!(datum, Datum::String(s) if s == equals) {
+                        matching += 1;
+                    }
+                }
+                (total > 0).then(|| Datum::Integer(matching * 100 / total))
+            }
+        }
+    }
+}
+
+/// Orders data of the same kind.
+fn compare(a: &Datum, b: &Datum) -> Option<Ordering> {
+    match (a, b) {
+        (Datum::Integer(a), Datum::Integer(b)) | (Datum::DateTime(a), Datum::DateTime(b)) => {
+            Some(a.cmp(b))
+        }
+        (Datum::Float(a), Datum::Float(b)) => a.partial_cmp(b),
+        (Datum::String(a), Datum::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// The value that orders `wanted` against all others of its kind. Values of
+/// another kind than the first are ignored.
+fn extreme<'a>(values: impl Iterator<Item = &'a Datum>, wanted: Ordering) -> Option<Datum> {
+    values
+        .fold(None, |best: Option<&Datum>, datum| match best {
+            None if compare(datum, datum).is_some() => Some(datum),
+            Some(b) if compare(datum, b) == Some(wanted) => Some(datum),
+            best => best,
+        })
+        .cloned()
+}
+
+/// The rollups of every entity with descendants.
+pub struct Rollups {
+    hierarchy: String,
+    definitions: Vec<Definition>,
+    /// The parent of every entity that has one.
+    parents: HashMap<Uuid, Uuid>,
+    children: HashMap<Uuid, HashSet<Uuid>>,
+    values: HashMap<Uuid, BTreeMap<String, Datum>>,
+}
+
+impl Rollups {
+    pub fn new(hierarchy: &str, definitions: Vec<Definition>) -> Rollups {
+        Rollups {
+            hierarchy: hierarchy.to_string(),
+            definitions,
+            parents: HashMap::new(),
+            children: HashMap::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Recomputes every rollup from scratch.
+    pub fn rebuild(&mut self, projection: &Projection) {
+        self.parents.clear();
+        self.children.clear();
+        self.values.clear();
+        let ids: Vec<Uuid> = projection.entities().map(|(id, _)| *id).collect();
+        self.update(projection, &ids);
+    }
+
+    /// Brings the rollups up to date after the entities in `changed` were
+    /// changed, created or deleted in `projection`.
+    pub fn update(&mut self, projection: &Projection, changed: &[Uuid]) {
+        let mut stale = HashSet::new();
+        for id in changed {
+            // Both the old and the new ancestors of a moved entity change.
+            self.ancestors(*id, &mut stale);
+            self.relink(projection, *id);
+            self.ancestors(*id, &mut stale);
+        }
+        for id in stale {
+            self.recompute(projection, id);
+        }
+    }
+
+    /// Adds the ancestors of `id` to `into`, stopping at cycles.
+    fn ancestors(&self, id: Uuid, into: &mut HashSet<Uuid>) {
+        let mut current = id;
+        while let Some(parent) = self.parents.get(&current) {
+            if !into.insert(*parent) {
+                break;
+            }
+            current = *parent;
+        }
+    }
+
+    fn relink(&mut self, projection: &Projection, id: Uuid) {
+        if let Some(old) = self.parents.remove(&id) {
+            if let Some(siblings) = self.children.get_mut(&old) {
+                siblings.remove(&id);
+            }
+        }
+        let parent = projection
+            .entity(&id)
+            .and_then(|e| match e.value(&self.hierarchy) {
+                Some(Datum::Entity(parent)) if *parent != id => Some(*parent),
+                _ => None,
+            });
+        if let Some(parent) = parent {
+            self.parents.insert(id, parent);
+            self.children.entry(parent).or_default().insert(id);
+        }
+    }
+
+    fn recompute(&mut self, projection: &Projection, id: Uuid) {
+        let mut descendants = Vec::new();
+        let mut seen = HashSet::from([id]);
+        let mut queue = vec![id];
+        while let Some(current) = queue.pop() {
+            for child in self.children.get(&current).into_iter().flatten() {
+                if seen.insert(*child) {
+                    descendants.push(*child);
+                    queue.push(*child);
+                }
+            }
+        }
+
+        let values: BTreeMap<String, Datum> = self
+            .definitions
+            .iter()
+            .filter_map(|definition| {
+                let data = descendants
+                    .iter()
+                    .filter_map(|d| projection.entity(d))
+                    .filter_map(|e| e.value(&definition.predicate));
+                Some((definition.name.clone(), definition.evaluate(data)?))
+            })
+            .collect();
+        if values.is_empty() {
+            self.values.remove(&id);
+        } else {
+            self.values.insert(id, values);
+        }
+    }
+
+    /// The rollups of `id` by definition name, if it has descendants with
+    /// any of the aggregated predicates.
+    pub fn of(&self, id: &Uuid) -> Option<&BTreeMap<String, Datum>> {
+        self.values.get(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    fn entity(projection: &mut Projection, facts: &[(&str, Datum)]) -> Uuid {
+        let id = Uuid::new_v4();
+        projection.apply(&Action::CreateEntity { id });
+        for (predicate, datum) in facts {
+            projection.apply(&Action::AddFact {
+                subject: id,
+                predicate: predicate.to_string(),
+                datum: datum.clone(),
+            });
+        }
+        id
+    }
+
+    fn status(s: &str) -> Datum {
+        Datum::String(s.to_string())
+    }
+
+    #[test]
+    fn rolls_up_descendants() {
+        let mut projection = Projection::new();
+        let project = entity(&mut projection, &[]);
+        let phase = entity(
+            &mut projection,
+            &[
+                ("parent", Datum::Entity(project)),
+                ("estimate", Datum::Integer(2)),
+            ],
+        );
+        entity(
+            &mut projection,
+            &[
+                ("parent", Datum::Entity(phase)),
+                ("status", status("done")),
+                ("estimate", Datum::Float(1.5)),
+                ("due", Datum::DateTime(200)),
+            ],
+        );
+        let open = entity(
+            &mut projection,
+            &[
+                ("parent", Datum::Entity(project)),
+                ("status", status("todo")),
+                ("due", Datum::DateTime(100)),
+            ],
+        );
+        let mut rollups = Rollups::new("parent", Definition::defaults());
+        rollups.rebuild(&projection);
+
+        let of_project = rollups.of(&project).unwrap();
+        assert_eq!(of_project["done"], Datum::Integer(50));
+        assert_eq!(of_project["estimate"], Datum::Float(3.5));
+        assert_eq!(of_project["earliest due"], Datum::DateTime(100));
+        assert_eq!(rollups.of(&phase).unwrap()["done"], Datum::Integer(100));
+        assert_eq!(rollups.of(&open), None);
+
+        // Moving the open task under the phase updates both.
+        projection.apply(&Action::RemoveFact {
+            subject: open,
+            predicate: "parent".into(),
+        });
+        projection.apply(&Action::AddFact {
+            subject: open,
+            predicate: "parent".into(),
+            datum: Datum::Entity(phase),
+        });
+        rollups.update(&projection, &[open]);
+        assert_eq!(rollups.of(&phase).unwrap()["done"], Datum::Integer(50));
+        assert_eq!(rollups.of(&project).unwrap()["done"], Datum::Integer(50));
+
+        projection.apply(&Action::DeleteEntity { id: open });
+        rollups.update(&projection, &[open]);
+        assert_eq!(rollups.of(&project).unwrap()["done"], Datum::Integer(100));
+    }
+
+    #[test]
+    fn survives_cycles() {
+        let mut projection = Projection::new();
+        let a = entity(&mut projection, &[("status", status("done"))]);
+        let b = entity(
+            &mut projection,
+            &[("parent", Datum::Entity(a)), ("status", status("todo"))],
+        );
+        projection.apply(&Action::AddFact {
+            subject: a,
+            predicate: "parent".into(),
+            datum: Datum::Entity(b),
+        });
+        let mut rollups = Rollups::new("parent", Definition::defaults());
+        rollups.rebuild(&projection);
+        assert_eq!(rollups.of(&a).unwrap()["done"], Datum::Integer(0));
+        assert_eq!(rollups.of(&b).unwrap()["done"], Datum::Integer(100));
+    }
+}
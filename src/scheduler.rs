@@ -0,0 +1,154 @@
+//! Runs background jobs at fixed intervals.
+//!
+//! Jobs run one at a time on a single thread, in the order they become due.
+//! A job that fails is logged and tried again at its next interval.
+
+use anyhow::{Context, Result};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+type Run = Box<dyn FnMut() -> Result<()> + Send>;
+
+struct Job {
+    name: String,
+    interval: Duration,
+    next: Instant,
+    run: Run,
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Adds a job that runs as soon as the scheduler starts and then every
+    /// `interval`.
+    pub fn every(
+        mut self,
+        name: &str,
+        interval: Duration,
+        run: impl FnMut() -> Result<()> + Send + 'static,
+    ) -> Scheduler {
+        self.jobs.push(Job {
+            name: name.to_string(),
+            interval,
+            next: Instant::now(),
+            run: Box::new(run),
+        });
+        self
+    }
+
+    /// Runs every job that is due at `now` and returns the name and outcome
+    /// of each.
+    pub fn run_due(&mut self, now: Instant) -> Vec<(String, Result<()>)> {
+        let mut due: Vec<&mut Job> = self.jobs.iter_mut().filter(|j| j.next <= now).collect();
+        due.sort_by_key(|j| j.next);
+        due.into_iter()
+            .map(|job| {
+                let result = (job.run)();
+                job.next = now + job.interval;
+                (job.name.clone(), result)
+            })
+            .collect()
+    }
+
+    /// When the next job is due, if there are any jobs.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.jobs.iter().map(|j| j.next).min()
+    }
+
+    /// Runs the jobs on a background thread until the handle is dropped.
+    pub fn spawn(mut self) -> Result<SchedulerHandle> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("graphite-scheduler".into())
+            .spawn(move || loop {
+                for (name, result) in self.run_due(Instant::now()) {
+                    if let Err(e) = result {
+                        tracing::warn!(job = name, "Job failed: {:#}", e);
+                    }
+                }
+                let wait = self
+                    .next_due()
+                    .map(|next| next.saturating_duration_since(Instant::now()))
+                    .unwrap_or(Duration::MAX);
+                match stopped.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            })
+            .context("Failed to spawn the scheduler thread")?;
+        Ok(SchedulerHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Stops the scheduler when dropped, waiting for a running job to finish.
+pub struct SchedulerHandle {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up.
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn runs_jobs_when_due() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let (a, b) = (runs.clone(), runs.clone());
+        let mut scheduler = Scheduler::new()
+            .every("fast", Duration::from_secs(1), move || {
+                a.lock().unwrap().push("fast");
+                Ok(())
+            })
+            .every("slow", Duration::from_secs(10), move || {
+                b.lock().unwrap().push("slow");
+                Err(anyhow!("failed"))
+            });
+
+        let start = Instant::now();
+        let outcomes = scheduler.run_due(start);
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[1].1.is_err());
+
+        scheduler.run_due(start + Duration::from_secs(2));
+        assert_eq!(*runs.lock().unwrap(), vec!["fast", "slow", "fast"]);
+        assert_eq!(scheduler.next_due(), Some(start + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn spawned_scheduler_stops_on_drop() {
+        let (sender, receiver) = mpsc::channel();
+        let handle = Scheduler::new()
+            .every("ping", Duration::from_secs(60), move || {
+                sender.send(()).unwrap();
+                Ok(())
+            })
+            .spawn()
+            .unwrap();
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        drop(handle);
+        assert!(receiver.recv().is_err());
+    }
+}
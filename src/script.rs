@@ -0,0 +1,226 @@
+//! Scripts run lines of the shell's language against the graph, see
+//! [`crate::shell`], e.g. to rename a predicate everywhere or print a report.
+//!
+//! A script is a `.graphite` file in the `scripts` directory next to the
+//! config, starting with comments that name it, its parameters and the keys
+//! that run it, if any:
+//!
+//! ```text
+//! # name: Rename predicate
+//! # params: from to
+//! # key: Ctrl+Alt+R
+//! # Moves the first value of $from to $to on every entity that has one.
+//! each $from do add $this $to {$from}
+//! each $from do remove $this $from
+//! ```
+//!
+//! `$param` is replaced by the value given for a parameter before a line
+//! runs. Scripts run in a sandbox: they see a copy of the graph, and their
+//! edits are recorded together as one event once every line succeeded, so a
+//! failing script changes nothing. The scripts in `scripts/` of the
+//! repository ship with the editor.
+//!
+//! Every script is a command of the editor: its keys, like those of
+//! [`crate::shortcuts`], run it, and so does choosing it after typing `>` in
+//! the jump to entity dialog. Commands bound to the same keys win.
+
+use crate::crdt;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Action;
+use crate::shell::{Outcome, Shell};
+use crate::shortcuts::Binding;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// The scripts shipped with the editor, as file names and contents.
+const BUILT_IN: [(&str, &str); 2] = [
+    (
+        "rename-predicate.graphite",
+        include_str!("../scripts/rename-predicate.graphite"),
+    ),
+    (
+        "task-report.graphite",
+        include_str!("../scripts/task-report.graphite"),
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    pub name: String,
+    pub description: String,
+    pub params: Vec<String>,
+    /// The keys that run the script.
+    pub key: Option<Binding>,
+    body: String,
+}
+
+/// What a script printed and the edits it made, to be recorded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ran {
+    pub output: String,
+    pub actions: Vec<Action>,
+}
+
+impl Script {
+    /// Reads the script in `text`, named after `file` unless it says
+    /// otherwise.
+    pub fn parse(file: &str, text: &str) -> Result<Script> {
+        let mut script = Script {
+            name: file.trim_end_matches(".graphite").to_string(),
+            description: String::new(),
+            params: Vec::new(),
+            key: None,
+            body: text.to_string(),
+        };
+        let mut description = Vec::new();
+        for line in text.lines().map(str::trim) {
+            let Some(comment) = line.strip_prefix('#') else {
+                break;
+            };
+            let comment = comment.trim();
+            if let Some(name) = comment.strip_prefix("name:") {
+                script.name = name.trim().to_string();
+            } else if let Some(params) = comment.strip_prefix("params:") {
+                script.params = params.split_whitespace().map(str::to_string).collect();
+            } else if let Some(key) = comment.strip_prefix("key:") {
+                script.key = Some(key.trim().parse()?);
+            } else {
+                description.push(comment);
+            }
+        }
+        script.description = description.join(" ");
+        Ok(script)
+    }
+
+    /// Runs the script on a copy of `projection`, with `args` the values of
+    /// its parameters in order and `types` the predicates declared in
+    /// [`crate::crdt`].
+    pub fn run(
+        &self,
+        args: &[String],
+        projection: &Projection,
+        types: &crdt::Types,
+    ) -> Result<Ran> {
+        if args.len() != self.params.len() {
+            bail!(
+                "{} takes {} parameters, not {}",
+                self.name,
+                self.params.len(),
+                args.len()
+            );
+        }
+        // Longer names first, so `$from` doesn't replace part of `$fromage`.
+        let mut params: Vec<(&String, &String)> = self.params.iter().zip(args).collect();
+        params.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+        let mut shell = Shell::sandbox(projection)?.with_crdt(types.clone());
+        let mut output = Vec::new();
+        for (number, line) in self.body.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let line = params.iter().fold(line.to_string(), |line, (param, arg)| {
+                line.replace(&format!("${}", param), arg)
+            });
+            match shell
+                .eval(&line)
+                .with_context(|| format!("Failed on line {} of {}", number + 1, self.name))?
+            {
+                Outcome::Print(printed) if printed.is_empty() => {}
+                Outcome::Print(printed) => output.push(printed),
+                Outcome::Exit => break,
+            }
+        }
+        let edits = shell.into_edits();
+        Ok(Ran {
+            output: output.join("\n"),
+            actions: match edits.is_empty() {
+                true => Vec::new(),
+                false => vec![Action::Transaction { actions: edits }],
+            },
+        })
+    }
+}
+
+/// The scripts shipped with the editor and those in `dir`, by name, and why
+/// the files that couldn't be read were skipped.
+pub fn load(dir: &Path) -> (Vec<Script>, Vec<String>) {
+    let mut scripts: Vec<Script> = BUILT_IN
+        .iter()
+        .map(|(file, text)| Script::parse(file, text).expect("shipped scripts are valid"))
+        .collect();
+    let mut errors = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == "graphite"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            let script = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Script::parse(&file, &text));
+            match script {
+                Ok(script) => {
+                    // Scripts of the same name replace those shipped.
+                    scripts.retain(|s| s.name != script.name);
+                    scripts.push(script);
+                }
+                Err(e) => errors.push(format!("Invalid script {}: {:#}", path.display(), e)),
+            }
+        }
+    }
+    scripts.sort_by(|a, b| a.name.cmp(&b.name));
+    (scripts, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Datum;
+    use uuid::Uuid;
+
+    #[test]
+    fn shipped_scripts_rename_and_report() {
+        let (scripts, errors) = load(Path::new("no such directory"));
+        assert!(errors.is_empty());
+        let script = |name: &str| scripts.iter().find(|s| s.name == name).unwrap();
+
+        let mut projection = Projection::new();
+        let fact = |subject, predicate: &str, value: &str| Action::AddFact {
+            subject,
+            predicate: predicate.into(),
+            datum: Datum::String(value.into()),
+        };
+        let (task, done) = (Uuid::new_v4(), Uuid::new_v4());
+        for (id, name, status) in [(task, "Write docs", "open"), (done, "Ship", "done")] {
+            projection.apply(&Action::CreateEntity { id });
+            projection.apply(&fact(id, "type", "task"));
+            projection.apply(&fact(id, "name", name));
+            projection.apply(&fact(id, "state", status));
+        }
+        projection.apply(&Action::AddFact {
+            subject: task,
+            predicate: "due".into(),
+            datum: Datum::DateTime(1719792000),
+        });
+
+        let rename = script("Rename predicate");
+        assert_eq!(rename.params, ["from", "to"]);
+        assert!(rename.run(&[], &projection, &crdt::Types::new()).is_err());
+        let args = ["state".to_string(), "status".to_string()];
+        let ran = rename.run(&args, &projection, &crdt::Types::new()).unwrap();
+        assert_eq!(ran.actions.len(), 1);
+        ran.actions.iter().for_each(|a| projection.apply(a));
+        let entity = projection.entity(&done).unwrap();
+        assert_eq!(entity.value("status"), Some(&Datum::String("done".into())));
+        assert_eq!(entity.value("state"), None);
+
+        let report = script("Task report");
+        assert_eq!(report.key, Some("Ctrl+Alt+T".parse().unwrap()));
+        assert!(Script::parse("bound.graphite", "# key: Ctrl+Hyper+K").is_err());
+        let ran = report.run(&[], &projection, &crdt::Types::new()).unwrap();
+        assert_eq!(ran.output, "Open tasks:\n- Write docs due 2024-07-01");
+        assert!(ran.actions.is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+//! Jumping to an entity by typing part of its name.
+//!
+//! Every string value is searched, ignoring case, and a match is scored by
+//! its quality: an equal value scores highest, then a value starting with the
+//! query, one with a word starting with it, one containing it, and one
+//! containing its characters in order. Matches in a [`KEYS`] value count
+//! double. Among matches, entities edited recently and those with many links
+//! rank higher.
+
+use crate::dedupe::KEYS;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Datum, Event};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// How much an edit now adds to the score, halving every [`HALF_LIFE`].
+const RECENCY: f64 = 0.3;
+/// How much the most linked entity adds to the score.
+const DEGREE: f64 = 0.2;
+const HALF_LIFE: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+
+/// When each entity was last edited, from the event log.
+#[derive(Debug, Clone, Default)]
+pub struct Recency {
+    edited: HashMap<Uuid, i64>,
+}
+
+impl Recency {
+    pub fn record(&mut self, event: &Event) {
+        let seconds = event.hlc().seconds();
+        for id in event.action().subjects() {
+            let edited = self.edited.entry(id).or_default();
+            *edited = seconds.max(*edited);
+        }
+    }
+
+    /// The Unix timestamp of the last edit of `id`.
+    pub fn edited(&self, id: &Uuid) -> Option<i64> {
+        self.edited.get(id).copied()
+    }
+
+    /// The entities edited most recently, up to `limit`.
+    pub fn latest(&self, projection: &Projection, limit: usize) -> Vec<Uuid> {
+        let mut ids: Vec<(i64, Uuid)> = self
+            .edited
+            .iter()
+            .filter(|(id, _)| projection.contains(id))
+            .map(|(id, edited)| (*edited, *id))
+            .collect();
+        ids.sort_by(|a, b| b.cmp(a));
+        ids.into_iter().take(limit).map(|(_, id)| id).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub id: Uuid,
+    pub score: f64,
+    /// The value that matched best.
+    pub matched: String,
+}
+
+/// The entities matching `query`, best first, up to `limit`.
+pub fn search(
+    projection: &Projection,
+    recency: &Recency,
+    query: &str,
+    now: i64,
+    limit: usize,
+) -> Vec<Hit> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut degrees: HashMap<Uuid, usize> = HashMap::new();
+    for (id, entity) in projection.entities() {
+        for (_, values) in entity.facts() {
+            for datum in values {
+                if let Datum::Entity(target) = datum {
+                    *degrees.entry(*id).or_default() += 1;
+                    *degrees.entry(*target).or_default() += 1;
+                }
+            }
+        }
+    }
+    let most = degrees.values().copied().max().unwrap_or_default();
+
+    let mut hits = Vec::new();
+    for (id, entity) in projection.entities() {
+        let mut best: Option<(f64, &str)> = None;
+        for (predicate, values) in entity.facts() {
+            let weight = if KEYS.contains(&predicate) { 1.0 } else { 0.5 };
+            for datum in values {
+                let Datum::String(value) = datum else {
+                    continue;
+                };
+                let quality = weight * quality(&value.to_lowercase(), &query);
+                if quality > best.map_or(0.0, |(q, _)| q) {
+                    best = Some((quality, value));
+                }
+            }
+        }
+        let Some((quality, matched)) = best else {
+            continue;
+        };
+        let recency = recency.edited(id).map_or(0.0, |edited| {
+            0.5_f64.powf((now - edited).max(0) as f64 / HALF_LIFE)
+        });
+        let degree = match degrees.get(id) {
+            Some(degree) => (*degree as f64).ln_1p() / (most as f64).ln_1p(),
+            None => 0.0,
+        };
+        hits.push(Hit {
+            id: *id,
+            score: quality + RECENCY * recency + DEGREE * degree,
+            matched: matched.to_string(),
+        });
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.id.cmp(&b.id)));
+    hits.truncate(limit);
+    hits
+}
+
+/// How well `value` matches `query`, both lowercase, from 0 for no match to
+/// 1 for equal.
+fn quality(value: &str, query: &str) -> f64 {
+    if value == query {
+        1.0
+    } else if value.starts_with(query) {
+        0.8
+    } else if value
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.starts_with(query))
+    {
+        0.6
+    } else if value.contains(query) {
+        0.4
+    } else {
+        let mut chars = value.chars();
+        if query.chars().all(|q| chars.any(|c| c == q)) {
+            0.2
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::{Action, EventCreator};
+
+    #[test]
+    fn matches_are_ranked() {
+        let mut projection = Projection::new();
+        let mut recency = Recency::default();
+        let [notes, rust, rusty, trust] = [(); 4].map(|_| Uuid::new_v4());
+        let now = 1_700_000_000;
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(now, 0));
+        for (id, name) in [
+            (notes, "Notes on rust"),
+            (rust, "Rust"),
+            (rusty, "Rusty"),
+            (trust, "Trust"),
+        ] {
+            for event in creator.transaction(vec![
+                Action::CreateEntity { id },
+                Action::AddFact {
+                    subject: id,
+                    predicate: "name".to_string(),
+                    datum: Datum::String(name.to_string()),
+                },
+            ]) {
+                projection.apply_event(&event);
+                if id != trust {
+                    recency.record(&event);
+                }
+            }
+        }
+        let ids = |hits: Vec<Hit>| hits.into_iter().map(|h| h.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(search(&projection, &recency, "rust", now, 10)),
+            [rust, rusty, notes, trust]
+        );
+        assert_eq!(ids(search(&projection, &recency, "rsy", now, 10)), [rusty]);
+        assert_eq!(search(&projection, &recency, " ", now, 10), []);
+        assert_eq!(recency.latest(&projection, 10).len(), 3);
+
+        let score = |projection: &Projection| {
+            search(projection, &recency, "rusty", now, 10)
+                .into_iter()
+                .find(|h| h.id == rusty)
+                .map(|h| h.score)
+        };
+        let unlinked = score(&projection);
+        projection.apply(&Action::AddFact {
+            subject: notes,
+            predicate: "about".to_string(),
+            datum: Datum::Entity(rusty),
+        });
+        assert!(score(&projection) > unlinked);
+    }
+}
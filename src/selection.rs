@@ -0,0 +1,383 @@
+//! Selecting several entities and editing them at once.
+//!
+//! A [`Selection`] follows the usual conventions of lists and canvases: a
+//! click selects one entity, Ctrl+click toggles one, Shift+click selects the
+//! range from the last clicked entity and dragging a rectangle selects what
+//! it encloses. Bulk operations return the actions for the whole selection,
+//! to be recorded as a single transaction that is synced and reverted as
+//! one step.
+//!
+//! Canvas positions are the `x` and `y` facts of an entity, in logical
+//! pixels.
+
+use crate::graph::layout;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// In the order they were selected.
+    ids: Vec<Uuid>,
+    /// The entity Shift+click ranges start from.
+    anchor: Option<Uuid>,
+}
+
+impl Selection {
+    pub fn ids(&self) -> &[Uuid] {
+        &self.ids
+    }
+
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.ids.contains(id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// The entity clicked last, if it is still selected.
+    pub fn anchor(&self) -> Option<Uuid> {
+        self.anchor.filter(|id| self.contains(id))
+    }
+
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.anchor = None;
+    }
+
+    /// Selects only `id`.
+    pub fn click(&mut self, id: Uuid) {
+        self.ids = vec![id];
+        self.anchor = Some(id);
+    }
+
+    /// Adds `id` to the selection, or removes it if it was selected.
+    pub fn toggle(&mut self, id: Uuid) {
+        match self.ids.iter().position(|selected| *selected == id) {
+            Some(n) => {
+                self.ids.remove(n);
+            }
+            None => self.ids.push(id),
+        }
+        self.anchor = Some(id);
+    }
+
+    /// Selects the entities from the anchor to `id` in the order they are
+    /// shown, or only `id` without an anchor. The anchor stays, so another
+    /// Shift+click changes the range.
+    pub fn extend(&mut self, id: Uuid, order: &[Uuid]) {
+        let position = |id| order.iter().position(|shown| *shown == id);
+        let (Some(anchor), Some(to)) = (self.anchor.and_then(position), position(id)) else {
+            return self.click(id);
+        };
+        let range = if anchor <= to {
+            &order[anchor..=to]
+        } else {
+            &order[to..=anchor]
+        };
+        self.ids = range.to_vec();
+    }
+
+    /// Selects the entities whose position is inside the rectangle between
+    /// two corners, adding them to the selection if `add`.
+    pub fn select_rect(
+        &mut self,
+        from: (f32, f32),
+        to: (f32, f32),
+        positions: &HashMap<Uuid, (f32, f32)>,
+        add: bool,
+    ) {
+        let (left, right) = (from.0.min(to.0), from.0.max(to.0));
+        let (top, bottom) = (from.1.min(to.1), from.1.max(to.1));
+        let mut inside: Vec<Uuid> = positions
+            .iter()
+            .filter(|(_, (x, y))| (left..=right).contains(x) && (top..=bottom).contains(y))
+            .map(|(id, _)| *id)
+            .collect();
+        inside.sort();
+        if !add {
+            self.ids.clear();
+        }
+        for id in inside {
+            if !self.contains(&id) {
+                self.ids.push(id);
+            }
+        }
+    }
+
+    /// Keeps the selected entities for which `keep` is true, e.g. those that
+    /// were not deleted.
+    pub fn retain(&mut self, keep: impl FnMut(&Uuid) -> bool) {
+        self.ids.retain(keep);
+    }
+
+    /// Selects exactly `ids`, such as entities that were just pasted.
+    pub fn set(&mut self, ids: Vec<Uuid>) {
+        self.anchor = ids.first().copied();
+        self.ids = ids;
+    }
+}
+
+/// The canvas positions of the entities that have one.
+pub fn positions(projection: &Projection, ids: &[Uuid]) -> HashMap<Uuid, (f32, f32)> {
+    let coordinate = |id: &Uuid, predicate| match projection.entity(id)?.value(predicate)? {
+        Datum::Float(n) => Some(*n as f32),
+        Datum::Integer(n) => Some(*n as f32),
+        _ => None,
+    };
+    ids.iter()
+        .filter_map(|id| Some((*id, (coordinate(id, "x")?, coordinate(id, "y")?))))
+        .collect()
+}
+
+/// Deletes the selected entities.
+pub fn delete(selection: &Selection) -> Vec<Action> {
+    selection
+        .ids()
+        .iter()
+        .map(|id| Action::DeleteEntity { id: *id })
+        .collect()
+}
+
+/// Adds the fact `tag` = `value` to the selected entities that lack it.
+pub fn tag(projection: &Projection, selection: &Selection, value: &str) -> Vec<Action> {
+    let datum = Datum::String(value.to_string());
+    selection
+        .ids()
+        .iter()
+        .filter(|id| {
+            projection
+                .entity(id)
+                .is_some_and(|e| !e.values("tag").contains(&datum))
+        })
+        .map(|id| Action::AddFact {
+            subject: *id,
+            predicate: "tag".to_string(),
+            datum: datum.clone(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+impl Axis {
+    fn predicate(self) -> &'static str {
+        match self {
+            Axis::Horizontal => "x",
+            Axis::Vertical => "y",
+        }
+    }
+
+    fn of(self, (x, y): (f32, f32)) -> f32 {
+        match self {
+            Axis::Horizontal => x,
+            Axis::Vertical => y,
+        }
+    }
+}
+
+/// Lines the selected entities up along `axis` at the smallest coordinate,
+/// i.e. on their left or top edge. Entities without a position are left as
+/// they are.
+pub fn align(projection: &Projection, selection: &Selection, axis: Axis) -> Vec<Action> {
+    let positions = positions(projection, selection.ids());
+    let Some(edge) = positions
+        .values()
+        .map(|p| axis.of(*p))
+        .min_by(f32::total_cmp)
+    else {
+        return Vec::new();
+    };
+    move_to(
+        axis,
+        positions
+            .iter()
+            .filter(|(_, p)| axis.of(**p) != edge)
+            .map(|(id, _)| (*id, edge)),
+    )
+}
+
+/// Spaces the selected entities evenly along `axis`, between the first and
+/// the last of them.
+pub fn distribute(projection: &Projection, selection: &Selection, axis: Axis) -> Vec<Action> {
+    let mut placed: Vec<(f32, Uuid)> = positions(projection, selection.ids())
+        .into_iter()
+        .map(|(id, p)| (axis.of(p), id))
+        .collect();
+    placed.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    if placed.len() < 3 {
+        return Vec::new();
+    }
+    let first = placed[0].0;
+    let step = (placed[placed.len() - 1].0 - first) / (placed.len() - 1) as f32;
+    move_to(
+        axis,
+        placed
+            .iter()
+            .enumerate()
+            .map(|(n, (_, id))| (*id, first + step * n as f32)),
+    )
+}
+
+/// The shapes the selected entities can be arranged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Circle,
+    Grid,
+}
+
+/// Arranges the selected entities in `shape` around the center of those
+/// with a position, keeping their order: clockwise around the center for a
+/// circle, in rows for a grid. Entities without a position come last.
+pub fn arrange(projection: &Projection, selection: &Selection, shape: Shape) -> Vec<Action> {
+    if selection.len() < 2 {
+        return Vec::new();
+    }
+    let positions = positions(projection, selection.ids());
+    let (x, y) = positions
+        .values()
+        .fold((0.0, 0.0), |(x, y), p| (x + p.0, y + p.1));
+    let center = match positions.len() {
+        0 => (0.0, 0.0),
+        n => (x / n as f32, y / n as f32),
+    };
+    let order = |id: &Uuid| match positions.get(id) {
+        None => (1, 0.0, 0.0),
+        Some((x, y)) => match shape {
+            Shape::Circle => (0, (x - center.0).atan2(center.1 - y), 0.0),
+            Shape::Grid => (0, *y, *x),
+        },
+    };
+    let by = |a: &Uuid, b: &Uuid| {
+        let (a, b) = (order(a), order(b));
+        a.0.cmp(&b.0)
+            .then(a.1.total_cmp(&b.1))
+            .then(a.2.total_cmp(&b.2))
+    };
+    let mut ids = selection.ids().to_vec();
+    ids.sort_by(by);
+    let points = match shape {
+        Shape::Circle => layout::circle(ids.len()),
+        Shape::Grid => {
+            // Rows of the entities closest in height, each left to right.
+            let points = layout::grid(ids.len());
+            let columns = points.iter().filter(|p| p.1 == points[0].1).count();
+            for row in ids.chunks_mut(columns) {
+                row.sort_by(|a, b| {
+                    let (a, b) = (order(a), order(b));
+                    a.0.cmp(&b.0).then(a.2.total_cmp(&b.2))
+                });
+            }
+            points
+        }
+    };
+    let placed: Vec<(Uuid, (f32, f32))> = ids
+        .iter()
+        .zip(points)
+        .map(|(id, (x, y))| (*id, (center.0 + x, center.1 + y)))
+        .collect();
+    let mut actions = move_to(Axis::Horizontal, placed.iter().map(|(id, p)| (*id, p.0)));
+    actions.extend(move_to(
+        Axis::Vertical,
+        placed.iter().map(|(id, p)| (*id, p.1)),
+    ));
+    actions
+}
+
+fn move_to(axis: Axis, moves: impl Iterator<Item = (Uuid, f32)>) -> Vec<Action> {
+    let mut actions = Vec::new();
+    for (id, coordinate) in moves {
+        actions.push(Action::RemoveFact {
+            subject: id,
+            predicate: axis.predicate().to_string(),
+        });
+        actions.push(Action::AddFact {
+            subject: id,
+            predicate: axis.predicate().to_string(),
+            datum: Datum::Float(coordinate as f64),
+        });
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicks_select_ranges() {
+        let order: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+        let mut selection = Selection::default();
+        selection.click(order[3]);
+        selection.extend(order[1], &order);
+        assert_eq!(selection.ids(), &order[1..=3]);
+        selection.extend(order[4], &order);
+        assert_eq!(selection.ids(), &order[3..=4]);
+        selection.toggle(order[0]);
+        selection.toggle(order[3]);
+        assert_eq!(selection.ids(), &[order[4], order[0]]);
+        assert_eq!(selection.anchor(), None);
+
+        let positions = HashMap::from([(order[0], (0.0, 0.0)), (order[1], (50.0, 50.0))]);
+        selection.select_rect((60.0, 60.0), (40.0, 40.0), &positions, false);
+        assert_eq!(selection.ids(), &[order[1]]);
+    }
+
+    #[test]
+    fn bulk_operations_edit_the_selection() {
+        let mut projection = Projection::new();
+        let mut selection = Selection::default();
+        for (x, y) in [(10.0, 0.0), (40.0, 5.0), (20.0, 9.0)] {
+            let id = Uuid::new_v4();
+            projection.apply(&Action::CreateEntity { id });
+            for (predicate, value) in [("x", x), ("y", y)] {
+                projection.apply(&Action::AddFact {
+                    subject: id,
+                    predicate: predicate.into(),
+                    datum: Datum::Float(value),
+                });
+            }
+            selection.toggle(id);
+        }
+        let apply = |projection: &mut Projection, actions| {
+            projection.apply(&Action::Transaction { actions });
+        };
+        let xs = |projection: &Projection| {
+            let positions = positions(projection, selection.ids());
+            let mut xs: Vec<f32> = selection.ids().iter().map(|id| positions[id].0).collect();
+            xs.sort_by(f32::total_cmp);
+            xs
+        };
+
+        let spread = distribute(&projection, &selection, Axis::Horizontal);
+        apply(&mut projection, spread);
+        assert_eq!(xs(&projection), [10.0, 25.0, 40.0]);
+        let aligned = align(&projection, &selection, Axis::Horizontal);
+        // Only the two entities off the edge move.
+        assert_eq!(aligned.len(), 4);
+        apply(&mut projection, aligned);
+        assert_eq!(xs(&projection), [10.0, 10.0, 10.0]);
+        let grid = arrange(&projection, &selection, Shape::Grid);
+        apply(&mut projection, grid);
+        assert_eq!(xs(&projection), [-50.0, -50.0, 70.0]);
+
+        let tagged = tag(&projection, &selection, "draft");
+        apply(&mut projection, tagged);
+        assert!(tag(&projection, &selection, "draft").is_empty());
+
+        apply(&mut projection, delete(&selection));
+        assert!(projection.is_empty());
+        selection.retain(|id| projection.contains(id));
+        assert!(selection.is_empty());
+    }
+}
@@ -0,0 +1,670 @@
+//! An interactive shell to explore and edit the graph without the editor.
+//!
+//! Each line is a command, e.g. `find type=task status!=done` with the query
+//! language of [`crate::query`], `show "Big project"` or
+//! `add "Big project" due 2024-07-01`. Entities are named by their id or
+//! their name. Edits are recorded right away, unless a transaction was
+//! started with `begin`: then they are collected until `commit` records them
+//! as one event, or `rollback` drops them. Predicates declared in
+//! [`crate::crdt`] are edited so that they merge.
+//!
+//! `each <query> do <command>` runs a command for every entity matching the
+//! query, with `$this` its id and `{predicate}` its first value, e.g.
+//! `each type=task do add $this title {name}`. The same language runs
+//! scripts, see [`crate::script`].
+
+mod line;
+
+use crate::crdt;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Datum, EventCreator, EventStorage};
+use crate::query::Query;
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt::Write;
+use std::path::PathBuf;
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::{Date, OffsetDateTime, PrimitiveDateTime};
+use uuid::Uuid;
+
+const COMMANDS: [&str; 14] = [
+    "add",
+    "begin",
+    "commit",
+    "create",
+    "delete",
+    "each",
+    "exit",
+    "find",
+    "help",
+    "increment",
+    "print",
+    "remove",
+    "rollback",
+    "show",
+];
+
+const HELP: &str = "\
+find <query>                  list the entities matching a query
+show <entity>                 print the facts of an entity and its backlinks
+create [name]                 create an entity
+add <entity> <predicate> <value>
+                              add a fact; values are numbers, true or false,
+                              dates, @entity for links, or text
+remove <entity> <predicate>   remove all values of a predicate
+increment <entity> <predicate> [amount]
+                              add to a counter, by 1 unless given
+delete <entity>               delete an entity
+each <query> do <command>     run a command for every entity matching a
+                              query, with $this its id and {predicate} its
+                              first value
+print <text>                  print a line of text
+begin                         collect the following edits in a transaction
+commit                        record the transaction as one event
+rollback                      drop the transaction
+exit                          leave the shell";
+
+pub struct Shell {
+    storage: EventStorage,
+    creator: EventCreator,
+    projection: Projection,
+    /// The projection before the open transaction and its actions so far.
+    transaction: Option<(Projection, Vec<Action>)>,
+    crdt: crdt::Types,
+    /// Whether this runs a script, which can't record events itself.
+    sandboxed: bool,
+}
+
+/// What a line did.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Print(String),
+    Exit,
+}
+
+impl Shell {
+    pub fn new(storage: EventStorage) -> Result<Shell> {
+        let creator = storage.creator()?;
+        let projection = Projection::load(&storage)?;
+        Ok(Shell {
+            storage,
+            creator,
+            projection,
+            transaction: None,
+            crdt: crdt::Types::new(),
+            sandboxed: false,
+        })
+    }
+
+    /// A shell for scripts, editing a copy of `projection`. It is backed by
+    /// an empty database in memory, and its edits are only collected, for
+    /// [`Shell::into_edits`] to hand to whoever runs the script.
+    pub fn sandbox(projection: &Projection) -> Result<Shell> {
+        let storage = EventStorage::open(":memory:")?;
+        Ok(Shell {
+            creator: storage.creator()?,
+            storage,
+            projection: projection.clone(),
+            transaction: Some((projection.clone(), Vec::new())),
+            crdt: crdt::Types::new(),
+            sandboxed: true,
+        })
+    }
+
+    /// The edits made in the open transaction.
+    pub fn into_edits(self) -> Vec<Action> {
+        self.transaction
+            .map(|(_, actions)| actions)
+            .unwrap_or_default()
+    }
+
+    /// Edits the predicates of `types` so that they merge.
+    pub fn with_crdt(mut self, types: crdt::Types) -> Shell {
+        self.crdt = types;
+        self
+    }
+
+    pub fn prompt(&self) -> String {
+        match &self.transaction {
+            Some((_, actions)) => format!("graphite [{}]> ", actions.len()),
+            None => "graphite> ".to_string(),
+        }
+    }
+
+    /// Runs one line.
+    pub fn eval(&mut self, line: &str) -> Result<Outcome> {
+        let words = words(line)?;
+        let Some((command, args)) = words.split_first() else {
+            return Ok(Outcome::Print(String::new()));
+        };
+        let args: Vec<&Word> = args.iter().collect();
+        if self.sandboxed && ["begin", "commit", "rollback", "exit"].contains(&&*command.text) {
+            bail!("Scripts can't {}", command.text);
+        }
+        let output = match (command.text.as_str(), args.as_slice()) {
+            ("help", []) => HELP.to_string(),
+            ("print", _) => {
+                let words: Vec<&str> = args.iter().map(|w| w.text.as_str()).collect();
+                words.join(" ")
+            }
+            ("each", _) => {
+                let rest = line.trim_start()[command.text.len()..].trim();
+                let (query, template) = rest
+                    .split_once(" do ")
+                    .ok_or_else(|| anyhow!("Write each <query> do <command>"))?;
+                let query: Query = query.parse()?;
+                let mut matching: Vec<(String, Uuid)> = query
+                    .run(&self.projection)
+                    .into_iter()
+                    .map(|id| (self.label(&id), id))
+                    .collect();
+                matching.sort();
+                let mut outputs = Vec::new();
+                for (_, id) in matching {
+                    let line = self.fill(template, id);
+                    match self.eval(&line)? {
+                        Outcome::Print(output) if output.is_empty() => {}
+                        Outcome::Print(output) => outputs.push(output),
+                        Outcome::Exit => bail!("Can't exit from each"),
+                    }
+                }
+                outputs.join("\n")
+            }
+            ("exit" | "quit", []) => return self.exit(),
+            ("find", _) => {
+                let query: Query = line.trim_start()[command.text.len()..].parse()?;
+                self.find(&query)
+            }
+            ("show", [entity]) => self.show(self.entity(entity)?),
+            ("create", []) => self.create(None)?,
+            ("create", [name]) => self.create(Some(&name.text))?,
+            ("add", [entity, predicate, value]) => {
+                let subject = self.entity(entity)?;
+                let datum = self.datum(value)?;
+                let action = match self.crdt.get(&predicate.text) {
+                    Some(kind) => {
+                        let entity = self.projection.entity(&subject);
+                        kind.add(entity, subject, &predicate.text, datum)?
+                    }
+                    None => Action::AddFact {
+                        subject,
+                        predicate: predicate.text.clone(),
+                        datum,
+                    },
+                };
+                self.edit(vec![action])?
+            }
+            ("remove", [entity, predicate]) => {
+                let subject = self.entity(entity)?;
+                let action = match self.crdt.get(&predicate.text) {
+                    Some(kind) => {
+                        let entity = self.projection.entity(&subject);
+                        kind.remove(entity, subject, &predicate.text, None)?
+                    }
+                    None => Action::RemoveFact {
+                        subject,
+                        predicate: predicate.text.clone(),
+                    },
+                };
+                self.edit(vec![action])?
+            }
+            ("increment", [entity, predicate, amount @ ..]) if amount.len() <= 1 => {
+                let subject = self.entity(entity)?;
+                let amount = match amount.first() {
+                    Some(word) => word
+                        .text
+                        .parse()
+                        .with_context(|| format!("{} isn't an integer", word.text))?,
+                    None => 1,
+                };
+                let kind = self.crdt.get(&predicate.text).copied();
+                let kind = kind.with_context(|| format!("{} isn't a counter", predicate.text))?;
+                self.edit(vec![kind.increment(subject, &predicate.text, amount)?])?
+            }
+            ("delete", [entity]) => {
+                let id = self.entity(entity)?;
+                self.edit(vec![Action::DeleteEntity { id }])?
+            }
+            ("begin", []) => match self.transaction {
+                Some(_) => bail!("A transaction is already open"),
+                None => {
+                    self.transaction = Some((self.projection.clone(), Vec::new()));
+                    "Started a transaction".to_string()
+                }
+            },
+            ("commit", []) => {
+                let Some((_, actions)) = &self.transaction else {
+                    bail!("There is no transaction to commit");
+                };
+                let count = actions.len();
+                if count > 0 {
+                    // Recorded while still open, as it is applied already.
+                    self.record(Action::Transaction {
+                        actions: actions.clone(),
+                    })?;
+                }
+                self.transaction = None;
+                format!("Committed {} edits", count)
+            }
+            ("rollback", []) => {
+                let (before, actions) = self
+                    .transaction
+                    .take()
+                    .ok_or_else(|| anyhow!("There is no transaction to roll back"))?;
+                self.projection = before;
+                format!("Dropped {} edits", actions.len())
+            }
+            (name, _) if COMMANDS.contains(&name) || name == "quit" => {
+                bail!("Wrong arguments for {}, see help", name)
+            }
+            (name, _) => bail!("Unknown command {}, see help", name),
+        };
+        Ok(Outcome::Print(output))
+    }
+
+    fn exit(&mut self) -> Result<Outcome> {
+        if let Some((_, actions)) = &self.transaction {
+            bail!(
+                "Commit or roll back the transaction of {} edits first",
+                actions.len()
+            );
+        }
+        Ok(Outcome::Exit)
+    }
+
+    fn find(&self, query: &Query) -> String {
+        let mut found: Vec<(String, Uuid)> = query
+            .run(&self.projection)
+            .into_iter()
+            .map(|id| (self.label(&id), id))
+            .collect();
+        found.sort();
+        let width = found.iter().map(|(label, _)| label.chars().count()).max();
+        let mut output = String::new();
+        for (label, id) in &found {
+            let _ = writeln!(
+                output,
+                "{:width$}  {}",
+                label,
+                id,
+                width = width.unwrap_or(0)
+            );
+        }
+        let _ = write!(output, "{} entities", found.len());
+        output
+    }
+
+    fn show(&self, id: Uuid) -> String {
+        let mut output = format!("{}  {}\n", self.label(&id), id);
+        let entity = self
+            .projection
+            .entity(&id)
+            .expect("resolved entities exist");
+        let facts: Vec<(&str, &[Datum])> = entity.facts().collect();
+        let width = facts.iter().map(|(p, _)| p.chars().count()).max();
+        for (predicate, values) in facts {
+            for datum in values {
+                let _ = writeln!(
+                    output,
+                    "  {:width$}  {}",
+                    predicate,
+                    self.format(datum),
+                    width = width.unwrap_or(0)
+                );
+            }
+        }
+        let mut backlinks = Vec::new();
+        for (source, entity) in self.projection.entities() {
+            for (predicate, values) in entity.facts() {
+                if values.contains(&Datum::Entity(id)) {
+                    backlinks.push(format!("  @{} {}", quote(&self.label(source)), predicate));
+                }
+            }
+        }
+        if !backlinks.is_empty() {
+            backlinks.sort();
+            output.push_str("linked from\n");
+            output.push_str(&backlinks.join("\n"));
+        }
+        output.trim_end().to_string()
+    }
+
+    fn create(&mut self, name: Option<&str>) -> Result<String> {
+        let id = Uuid::new_v4();
+        let mut actions = vec![Action::CreateEntity { id }];
+        if let Some(name) = name {
+            actions.push(Action::AddFact {
+                subject: id,
+                predicate: "name".to_string(),
+                datum: Datum::String(name.to_string()),
+            });
+        }
+        self.edit(actions)?;
+        Ok(id.to_string())
+    }
+
+    /// Records `actions`, or adds them to the open transaction.
+    fn edit(&mut self, actions: Vec<Action>) -> Result<String> {
+        match &mut self.transaction {
+            Some((_, pending)) => {
+                for action in actions {
+                    self.projection.apply(&action);
+                    pending.push(action);
+                }
+                Ok(String::new())
+            }
+            None => {
+                self.record(Action::Transaction { actions })?;
+                Ok(String::new())
+            }
+        }
+    }
+
+    /// Records `action` as an event. Unlike those of transactions, which are
+    /// applied as they are made, it is applied once recorded.
+    fn record(&mut self, action: Action) -> Result<()> {
+        let event = self.creator.create(action);
+        self.storage.record(event.clone())?;
+        if self.transaction.is_none() {
+            self.projection.apply_event(&event);
+        }
+        Ok(())
+    }
+
+    /// The entity with the id or the name `word`.
+    fn entity(&self, word: &Word) -> Result<Uuid> {
+        let text = word.text.strip_prefix('@').unwrap_or(&word.text);
+        if let Ok(id) = text.parse::<Uuid>() {
+            if self.projection.contains(&id) {
+                return Ok(id);
+            }
+            bail!("There is no entity {}", id);
+        }
+        let named: Vec<Uuid> = self
+            .projection
+            .entities()
+            .filter(|(id, _)| self.label(id) == text)
+            .map(|(id, _)| *id)
+            .collect();
+        match named.as_slice() {
+            [id] => Ok(*id),
+            [] => bail!("There is no entity named {}", text),
+            _ => bail!("Several entities are named {}, use an id", text),
+        }
+    }
+
+    /// The value written as `word`. Quoted words are text, unless they are
+    /// links such as `@"Big project"`.
+    fn datum(&self, word: &Word) -> Result<Datum> {
+        let text = word.text.as_str();
+        if text.starts_with('@') {
+            return Ok(Datum::Entity(self.entity(word)?));
+        }
+        if word.quoted {
+            return Ok(Datum::String(text.to_string()));
+        }
+        if let Ok(n) = text.parse() {
+            return Ok(Datum::Integer(n));
+        }
+        if let Ok(n) = text.parse() {
+            return Ok(Datum::Float(n));
+        }
+        if let Ok(b) = text.parse() {
+            return Ok(Datum::Boolean(b));
+        }
+        if let Some(time) = date_time(text) {
+            return Ok(Datum::DateTime(time));
+        }
+        Ok(Datum::String(text.to_string()))
+    }
+
+    fn format(&self, datum: &Datum) -> String {
+        match datum {
+            Datum::String(s) => format!("{:?}", s),
+            Datum::Integer(n) => n.to_string(),
+            Datum::Float(n) => format!("{:?}", n),
+            Datum::Boolean(b) => b.to_string(),
+            Datum::DateTime(t) => match OffsetDateTime::from_unix_timestamp(*t) {
+                Ok(time) if time.time() == time::Time::MIDNIGHT => time.date().to_string(),
+                Ok(time) => format!(
+                    "{}T{:02}:{:02}:{:02}Z",
+                    time.date(),
+                    time.hour(),
+                    time.minute(),
+                    time.second()
+                ),
+                Err(_) => t.to_string(),
+            },
+            Datum::Entity(id) => format!("@{}", quote(&self.label(id))),
+        }
+    }
+
+    /// Replaces `$this` and `{predicate}` in `template` with the id and the
+    /// first values of `id`, written as the shell reads them.
+    fn fill(&self, template: &str, id: Uuid) -> String {
+        let entity = self.projection.entity(&id);
+        let mut rest = template.replace("$this", &id.to_string());
+        let mut filled = String::new();
+        while let Some((before, after)) = rest.split_once('{') {
+            let Some((predicate, after)) = after.split_once('}') else {
+                break;
+            };
+            filled.push_str(before);
+            filled.push_str(&match entity.and_then(|e| e.value(predicate)) {
+                Some(Datum::String(s)) => format!("\"{}\"", s),
+                Some(Datum::Entity(id)) => format!("@{}", id),
+                Some(datum) => self.format(datum),
+                None => "\"\"".to_string(),
+            });
+            rest = after.to_string();
+        }
+        filled.push_str(&rest);
+        filled
+    }
+
+    fn label(&self, id: &Uuid) -> String {
+        match self.projection.entity(id).and_then(|e| e.value("name")) {
+            Some(Datum::String(name)) => name.clone(),
+            _ => id.to_string(),
+        }
+    }
+
+    /// Completions of the word before the end of `line`: the byte offset the
+    /// word starts at and the words it may become.
+    pub fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        let start = word_start(line);
+        let partial = line[start..].trim_start_matches(['"', '@']);
+        let before = words(&line[..start]).unwrap_or_default();
+        let command = before.first().map(|w| w.text.as_str());
+        let names = || {
+            self.projection
+                .entities()
+                .filter_map(|(id, e)| e.value("name").map(|_| self.label(id)))
+                .collect::<Vec<String>>()
+        };
+        let candidates: Vec<String> = match (command, before.len()) {
+            (None, _) => COMMANDS.iter().map(|c| c.to_string()).collect(),
+            (Some("show" | "add" | "remove" | "delete"), 1) => names(),
+            (Some("add" | "remove"), 2) | (Some("find"), _) => self.predicates(),
+            (Some("add"), 3) if line[start..].starts_with('@') => names(),
+            _ => Vec::new(),
+        };
+        let prefix = &line[start..line.len() - partial.len()];
+        let mut completions: Vec<String> = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(partial))
+            .map(|c| match prefix.trim_end_matches('"') {
+                at if c.contains(' ') || prefix.ends_with('"') => format!("{}{}", at, quote(&c)),
+                at => format!("{}{}", at, c),
+            })
+            .collect();
+        completions.sort();
+        completions.dedup();
+        (start, completions)
+    }
+
+    fn predicates(&self) -> Vec<String> {
+        self.projection
+            .entities()
+            .flat_map(|(_, e)| e.facts().map(|(p, _)| p.to_string()))
+            .collect()
+    }
+}
+
+/// Reads and runs lines until `exit` or the end of input, keeping the
+/// history of earlier sessions in `history`.
+pub fn run(storage: EventStorage, history: Option<PathBuf>, types: crdt::Types) -> Result<()> {
+    let mut shell = Shell::new(storage)?.with_crdt(types);
+    let mut editor = line::Editor::new(history.as_deref());
+    while let Some(input) = editor.read(&shell.prompt(), |line| shell.complete(line))? {
+        match shell.eval(&input) {
+            Ok(Outcome::Print(output)) if output.is_empty() => {}
+            Ok(Outcome::Print(output)) => println!("{}", output),
+            Ok(Outcome::Exit) => break,
+            Err(e) => eprintln!("{:#}", e),
+        }
+    }
+    if let Some(path) = history {
+        editor
+            .save(&path)
+            .with_context(|| format!("Failed to save the shell history to {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Word {
+    text: String,
+    quoted: bool,
+}
+
+/// Splits a line at spaces outside quotes.
+fn words(line: &str) -> Result<Vec<Word>> {
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(Word::default).quoted = true;
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(Word::default).text.push(c),
+        }
+    }
+    if quoted {
+        bail!("Unclosed quote");
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Where the last word of `line` starts, inside an open quote or not.
+fn word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => start = i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    start
+}
+
+fn quote(name: &str) -> String {
+    if name.contains(' ') {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// A date, or a date and time, as Unix seconds.
+pub(crate) fn date_time(text: &str) -> Option<i64> {
+    if let Ok(time) = OffsetDateTime::parse(text, &Rfc3339) {
+        return Some(time.unix_timestamp());
+    }
+    if let Ok(time) = PrimitiveDateTime::parse(text, &Iso8601::DEFAULT) {
+        return Some(time.assume_utc().unix_timestamp());
+    }
+    let date = Date::parse(text, &Iso8601::DEFAULT).ok()?;
+    Some(date.midnight().assume_utc().unix_timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(shell: &mut Shell, line: &str) -> String {
+        match shell.eval(line).unwrap() {
+            Outcome::Print(output) => output,
+            Outcome::Exit => panic!("{} exited", line),
+        }
+    }
+
+    #[test]
+    fn edits_in_transactions() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        let mut shell = Shell::new(storage).unwrap();
+        print(&mut shell, "create \"Big project\"");
+        print(&mut shell, "begin");
+        print(&mut shell, "create Roof");
+        print(&mut shell, "add Roof parent @\"Big project\"");
+        print(&mut shell, "add Roof estimate 3");
+        print(&mut shell, "add Roof note \"3\"");
+        assert_eq!(shell.prompt(), "graphite [5]> ");
+        assert!(shell.eval("exit").is_err());
+        print(&mut shell, "commit");
+        assert_eq!(shell.storage.stats().unwrap().total, 2);
+
+        assert_eq!(
+            print(&mut shell, "show Roof")
+                .lines()
+                .skip(1)
+                .collect::<Vec<_>>(),
+            [
+                "  estimate  3",
+                "  name      \"Roof\"",
+                "  note      \"3\"",
+                "  parent    @\"Big project\"",
+            ]
+        );
+        assert!(print(&mut shell, "show \"Big project\"").ends_with("  @Roof parent"));
+        assert!(print(&mut shell, "find estimate>2").ends_with("1 entities"));
+
+        print(&mut shell, "begin");
+        print(&mut shell, "delete Roof");
+        print(&mut shell, "rollback");
+        assert!(print(&mut shell, "find parent").starts_with("Roof"));
+        assert!(shell.eval("show Nothing").is_err());
+        assert_eq!(shell.eval("exit").unwrap(), Outcome::Exit);
+    }
+
+    #[test]
+    fn completes_commands_names_and_predicates() {
+        let storage = EventStorage::open(":memory:").unwrap();
+        let mut shell = Shell::new(storage).unwrap();
+        print(&mut shell, "create \"Big project\"");
+        print(&mut shell, "add \"Big project\" estimate 3");
+
+        assert_eq!(shell.complete("re"), (0, vec!["remove".to_string()]));
+        assert_eq!(
+            shell.complete("show Bi"),
+            (5, vec!["\"Big project\"".to_string()])
+        );
+        assert_eq!(
+            shell.complete("add \"Big project\" es"),
+            (18, vec!["estimate".to_string()])
+        );
+        assert_eq!(
+            shell.complete("add Roof parent @B"),
+            (16, vec!["@\"Big project\"".to_string()])
+        );
+        assert_eq!(
+            shell.complete("find name=x est"),
+            (12, vec!["estimate".to_string()])
+        );
+    }
+}
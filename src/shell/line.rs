@@ -0,0 +1,303 @@
+//! A small line editor for the shell: moving the cursor, history with the
+//! arrow keys and tab completion.
+//!
+//! Terminals are put in raw mode to read single key presses. When the input
+//! is not a terminal, such as a script piped into the shell, or on
+//! platforms without termios, lines are read as they come.
+
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+/// How many lines of history are kept.
+const HISTORY: usize = 1000;
+
+pub struct Editor {
+    history: Vec<String>,
+}
+
+impl Editor {
+    /// An editor with the history saved at `path`, if any.
+    pub fn new(path: Option<&Path>) -> Editor {
+        let history = path
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        Editor { history }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let start = self.history.len().saturating_sub(HISTORY);
+        let mut text = self.history[start..].join("\n");
+        text.push('\n');
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Reads a line, or `None` at the end of input. `complete` returns where
+    /// the word being completed starts and its completions.
+    pub fn read(
+        &mut self,
+        prompt: &str,
+        complete: impl FnMut(&str) -> (usize, Vec<String>),
+    ) -> Result<Option<String>> {
+        let line = match raw::Mode::enable() {
+            Some(_raw) => self.edit(prompt, complete)?,
+            None => {
+                let mut line = String::new();
+                if io::stdin().lock().read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                Some(line.trim_end_matches(['\r', '\n']).to_string())
+            }
+        };
+        if let Some(line) = &line {
+            if !line.trim().is_empty() && self.history.last() != Some(line) {
+                self.history.push(line.clone());
+            }
+        }
+        Ok(line)
+    }
+
+    fn edit(
+        &mut self,
+        prompt: &str,
+        mut complete: impl FnMut(&str) -> (usize, Vec<String>),
+    ) -> Result<Option<String>> {
+        let mut out = io::stdout().lock();
+        let mut line = Line::default();
+        // Where in the history the line is, the line being typed at the end.
+        let mut recalled = self.history.len();
+        let mut typed = String::new();
+        line.draw(&mut out, prompt)?;
+        loop {
+            match key()? {
+                Key::Enter => {
+                    write!(out, "\r\n")?;
+                    return Ok(Some(line.text()));
+                }
+                Key::Interrupt => {
+                    write!(out, "^C\r\n")?;
+                    line = Line::default();
+                    recalled = self.history.len();
+                }
+                Key::Eof if line.chars.is_empty() => {
+                    write!(out, "\r\n")?;
+                    return Ok(None);
+                }
+                Key::Eof => {}
+                Key::Char(c) => line.insert(c),
+                Key::Backspace => line.backspace(),
+                Key::Left => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                Key::Home => line.cursor = 0,
+                Key::LineEnd => line.cursor = line.chars.len(),
+                Key::Up if recalled > 0 => {
+                    if recalled == self.history.len() {
+                        typed = line.text();
+                    }
+                    recalled -= 1;
+                    line = Line::new(&self.history[recalled]);
+                }
+                Key::Down if recalled < self.history.len() => {
+                    recalled += 1;
+                    line = Line::new(self.history.get(recalled).unwrap_or(&typed));
+                }
+                Key::Up | Key::Down => {}
+                Key::Tab => {
+                    let before: String = line.chars[..line.cursor].iter().collect();
+                    let (start, completions) = complete(&before);
+                    let common = common_prefix(&completions);
+                    let word = &before[start..];
+                    if common.len() > word.len() {
+                        line.replace(before[..start].chars().count(), &common);
+                    } else if completions.len() > 1 {
+                        write!(out, "\r\n{}\r\n", completions.join("  "))?;
+                    }
+                }
+            }
+            line.draw(&mut out, prompt)?;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Line {
+    chars: Vec<char>,
+    /// The position of the cursor, in chars.
+    cursor: usize,
+}
+
+impl Line {
+    fn new(text: &str) -> Line {
+        let chars: Vec<char> = text.chars().collect();
+        Line {
+            cursor: chars.len(),
+            chars,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Replaces the text from `start` to the cursor.
+    fn replace(&mut self, start: usize, text: &str) {
+        self.chars.splice(start..self.cursor, text.chars());
+        self.cursor = start + text.chars().count();
+    }
+
+    fn draw(&self, out: &mut impl Write, prompt: &str) -> Result<()> {
+        write!(out, "\r\x1b[K{}{}", prompt, self.text())?;
+        let after = self.chars.len() - self.cursor;
+        if after > 0 {
+            write!(out, "\x1b[{}D", after)?;
+        }
+        out.flush().context("Failed to write to the terminal")
+    }
+}
+
+enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    LineEnd,
+    /// Ctrl+C
+    Interrupt,
+    /// Ctrl+D
+    Eof,
+}
+
+/// Reads a key press from the terminal.
+fn key() -> Result<Key> {
+    let mut stdin = io::stdin().lock();
+    let mut byte = || -> Result<u8> {
+        let mut buffer = [0];
+        if stdin.read(&mut buffer)? == 0 {
+            return Ok(4);
+        }
+        Ok(buffer[0])
+    };
+    loop {
+        let key = match byte()? {
+            b'\r' | b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            127 | 8 => Key::Backspace,
+            1 => Key::Home,
+            3 => Key::Interrupt,
+            4 => Key::Eof,
+            5 => Key::LineEnd,
+            0x1b => match (byte()?, byte()?) {
+                (b'[', b'A') => Key::Up,
+                (b'[', b'B') => Key::Down,
+                (b'[', b'C') => Key::Right,
+                (b'[', b'D') => Key::Left,
+                (b'[', b'H') => Key::Home,
+                (b'[', b'F') => Key::LineEnd,
+                _ => continue,
+            },
+            first if first >= 0x20 => {
+                // The rest of a UTF-8 sequence follows its first byte.
+                let mut bytes = vec![first];
+                for _ in 1..first.leading_ones().max(1) {
+                    bytes.push(byte()?);
+                }
+                match std::str::from_utf8(&bytes)
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                {
+                    Some(c) => Key::Char(c),
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        return Ok(key);
+    }
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let Some((first, rest)) = words.split_first() else {
+        return String::new();
+    };
+    let mut prefix = first.clone();
+    for word in rest {
+        while !word.starts_with(&prefix) {
+            prefix.pop();
+        }
+    }
+    prefix
+}
+
+#[cfg(unix)]
+mod raw {
+    /// Raw mode of the terminal on stdin, until dropped.
+    pub struct Mode(libc::termios);
+
+    impl Mode {
+        /// Enables raw mode, or returns `None` if stdin is not a terminal.
+        pub fn enable() -> Option<Mode> {
+            // SAFETY: termios is plain data filled in by tcgetattr, and the
+            // calls only read or set the attributes of stdin.
+            unsafe {
+                if libc::isatty(libc::STDIN_FILENO) != 1 {
+                    return None;
+                }
+                let mut original: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                    return None;
+                }
+                let mut raw = original;
+                raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+                raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+                raw.c_cc[libc::VMIN] = 1;
+                raw.c_cc[libc::VTIME] = 0;
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) != 0 {
+                    return None;
+                }
+                Some(Mode(original))
+            }
+        }
+    }
+
+    impl Drop for Mode {
+        fn drop(&mut self) {
+            // SAFETY: restores the attributes read in `enable`.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.0);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod raw {
+    pub struct Mode;
+
+    impl Mode {
+        pub fn enable() -> Option<Mode> {
+            None
+        }
+    }
+}
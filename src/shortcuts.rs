@@ -0,0 +1,312 @@
+//! Keyboard shortcuts: the editor's commands and the key chords they are
+//! bound to.
+//!
+//! The bindings are kept in the `[keybindings]` table of the config, where
+//! each command can be remapped. Commands without a binding in the file get
+//! their default. Two commands bound to the same chord are a conflict,
+//! reported by [`conflicts`]; the first of them in [`Command::ALL`] wins.
+
+use anyhow::{anyhow, bail, Result};
+#[cfg(feature = "gui")]
+use iced::keyboard::{Key, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// A command that can be bound to a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    JournalToday,
+    Diagnostics,
+    Settings,
+    Checkpoints,
+    Backups,
+    Inspector,
+    /// Show or hide the list of shortcuts.
+    Help,
+    /// Show or hide the recent log lines.
+    Console,
+    /// Report entities and links left behind.
+    Hygiene,
+    /// List the deleted entities that can be restored.
+    Trash,
+    /// Suggest entities to merge.
+    Duplicates,
+    /// Create an entity from a template.
+    NewEntity,
+    /// Edit entities in a table.
+    Table,
+    /// Move entities between the values of a predicate.
+    Board,
+    /// Show the entities with a date on a calendar.
+    Calendar,
+    /// Show the graph on a canvas.
+    Canvas,
+    /// Jump to an entity by typing part of its name.
+    Search,
+    /// Hold edits back from the event log until they are committed.
+    Staging,
+    /// Resolve conflicts between concurrent edits.
+    Conflicts,
+    /// Sync with other instances on the local network.
+    Nearby,
+    /// Run a script on the graph.
+    Scripts,
+}
+
+impl Command {
+    pub const ALL: [Command; 21] = [
+        Command::JournalToday,
+        Command::Diagnostics,
+        Command::Settings,
+        Command::Checkpoints,
+        Command::Backups,
+        Command::Inspector,
+        Command::Help,
+        Command::Console,
+        Command::Hygiene,
+        Command::Trash,
+        Command::Duplicates,
+        Command::NewEntity,
+        Command::Table,
+        Command::Board,
+        Command::Calendar,
+        Command::Canvas,
+        Command::Search,
+        Command::Staging,
+        Command::Conflicts,
+        Command::Nearby,
+        Command::Scripts,
+    ];
+
+    pub fn default_binding(self) -> Binding {
+        let binding = match self {
+            Command::JournalToday => "Ctrl+J",
+            Command::Diagnostics => "Ctrl+Shift+D",
+            Command::Settings => "Ctrl+,",
+            Command::Checkpoints => "Ctrl+Shift+T",
+            Command::Backups => "Ctrl+Shift+B",
+            Command::Inspector => "Ctrl+I",
+            Command::Help => "?",
+            Command::Console => "Ctrl+Shift+L",
+            Command::Hygiene => "Ctrl+Shift+H",
+            Command::Trash => "Ctrl+Shift+X",
+            Command::Duplicates => "Ctrl+Shift+M",
+            Command::NewEntity => "Ctrl+N",
+            Command::Table => "Ctrl+T",
+            Command::Board => "Ctrl+Shift+K",
+            Command::Calendar => "Ctrl+Shift+C",
+            Command::Canvas => "Ctrl+G",
+            Command::Search => "Ctrl+O",
+            Command::Staging => "Ctrl+Shift+S",
+            Command::Conflicts => "Ctrl+Shift+R",
+            Command::Nearby => "Ctrl+Shift+N",
+            Command::Scripts => "Ctrl+Shift+P",
+        };
+        binding.parse().expect("default bindings are valid")
+    }
+
+    /// The id of the command's label in the translations, see
+    /// [`crate::i18n`].
+    pub fn message_id(self) -> &'static str {
+        match self {
+            Command::JournalToday => "command-journal-today",
+            Command::Diagnostics => "command-diagnostics",
+            Command::Settings => "command-settings",
+            Command::Checkpoints => "command-checkpoints",
+            Command::Backups => "command-backups",
+            Command::Inspector => "command-inspector",
+            Command::Help => "command-help",
+            Command::Console => "command-console",
+            Command::Hygiene => "command-hygiene",
+            Command::Trash => "command-trash",
+            Command::Duplicates => "command-duplicates",
+            Command::NewEntity => "command-new-entity",
+            Command::Table => "command-table",
+            Command::Board => "command-board",
+            Command::Calendar => "command-calendar",
+            Command::Canvas => "command-canvas",
+            Command::Search => "command-search",
+            Command::Staging => "command-staging",
+            Command::Conflicts => "command-conflicts",
+            Command::Nearby => "command-nearby",
+            Command::Scripts => "command-scripts",
+        }
+    }
+}
+
+/// Every command with its default binding.
+pub fn defaults() -> BTreeMap<Command, Binding> {
+    Command::ALL
+        .into_iter()
+        .map(|command| (command, command.default_binding()))
+        .collect()
+}
+
+/// The command bound to a key press, if any.
+#[cfg(feature = "gui")]
+pub fn command(
+    bindings: &BTreeMap<Command, Binding>,
+    key: &Key,
+    modifiers: Modifiers,
+) -> Option<Command> {
+    bindings
+        .iter()
+        .find(|(_, binding)| binding.matches(key, modifiers))
+        .map(|(command, _)| *command)
+}
+
+/// The chords bound to more than one command, with those commands.
+pub fn conflicts(bindings: &BTreeMap<Command, Binding>) -> Vec<(Binding, Vec<Command>)> {
+    let mut conflicts: Vec<(Binding, Vec<Command>)> = Vec::new();
+    for (command, binding) in bindings {
+        match conflicts.iter_mut().find(|(b, _)| b == binding) {
+            Some((_, commands)) => commands.push(*command),
+            None => conflicts.push((binding.clone(), vec![*command])),
+        }
+    }
+    conflicts.retain(|(_, commands)| commands.len() > 1);
+    conflicts
+}
+
+/// A key together with the modifiers that must be held, e.g. `Ctrl+Shift+D`.
+///
+/// `Ctrl` is the platform's command key, so it is `⌘` on macOS. Shift is
+/// only checked for letters, or if the binding asks for it, as other keys
+/// such as `?` need it on some keyboard layouts and not on others.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Binding {
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+    /// The lower-cased character of the key.
+    key: String,
+}
+
+impl Binding {
+    #[cfg(feature = "gui")]
+    pub fn matches(&self, key: &Key, modifiers: Modifiers) -> bool {
+        let Key::Character(character) = key else {
+            return false;
+        };
+        let letter = self.key.chars().all(char::is_alphabetic);
+        character.to_lowercase() == self.key
+            && modifiers.command() == self.ctrl
+            && (modifiers.shift() == self.shift || !(letter || self.shift))
+            && modifiers.alt() == self.alt
+    }
+}
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Binding> {
+        let mut binding = Binding {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            key: String::new(),
+        };
+        // Split from the end so that `Ctrl++` binds the plus key.
+        let (modifiers, key) = match s.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier.trim().to_lowercase().as_str() {
+                "ctrl" | "cmd" | "command" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "alt" | "option" => binding.alt = true,
+                _ => bail!("Unknown modifier {} in {}", modifier, s),
+            }
+        }
+        let key = key.trim().to_lowercase();
+        if key.chars().count() != 1 {
+            return Err(anyhow!("Bindings need a single character key, got {}", s));
+        }
+        binding.key = key;
+        Ok(binding)
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Binding> {
+        s.parse()
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> String {
+        binding.to_string()
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.shift, "Shift+"),
+            (self.alt, "Alt+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        f.write_str(&self.key.to_uppercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn bindings_match_key_presses() {
+        let bindings = defaults();
+        let ctrl_shift = Modifiers::COMMAND | Modifiers::SHIFT;
+        assert_eq!(
+            command(&bindings, &Key::Character("D".into()), ctrl_shift),
+            Some(Command::Diagnostics)
+        );
+        assert_eq!(
+            command(&bindings, &Key::Character("d".into()), Modifiers::COMMAND),
+            None
+        );
+        // `?` is typed with Shift on some layouts only.
+        for modifiers in [Modifiers::SHIFT, Modifiers::empty()] {
+            assert_eq!(
+                command(&bindings, &Key::Character("?".into()), modifiers),
+                Some(Command::Help)
+            );
+        }
+        assert_eq!("ctrl++".parse::<Binding>().unwrap().to_string(), "Ctrl++");
+        assert!("Ctrl+Hyper+K".parse::<Binding>().is_err());
+        assert!("Ctrl+Enter".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn finds_conflicts() {
+        let mut bindings = defaults();
+        assert!(conflicts(&bindings).is_empty());
+        bindings.insert(Command::Inspector, "ctrl+j".parse().unwrap());
+        assert_eq!(
+            conflicts(&bindings),
+            vec![(
+                "Ctrl+J".parse().unwrap(),
+                vec![Command::JournalToday, Command::Inspector]
+            )]
+        );
+        // The first command in order wins.
+        #[cfg(feature = "gui")]
+        assert_eq!(
+            command(&bindings, &Key::Character("j".into()), Modifiers::COMMAND),
+            Some(Command::JournalToday)
+        );
+    }
+}
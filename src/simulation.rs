@@ -0,0 +1,477 @@
+//! Deterministic simulation of replicas syncing, to test that they converge.
+//!
+//! A simulation runs `replicas` in-memory databases for `ticks` simulated
+//! seconds. Each tick, replicas edit the graph at random, with the actions the
+//! editor records, and send the events the other replica hasn't received yet
+//! to a random peer. Messages arrive after a random latency, in any order, and
+//! are dropped while the two replicas are on different sides of a partition.
+//! Every replica's clock is skewed by up to `max_skew` seconds, and its hybrid
+//! logical clock observes the events it receives, as on a device.
+//!
+//! At the end, partitions heal and replicas sync until nothing new arrives,
+//! then every replica must have the same events and the same graph when they
+//! are replayed. Everything random comes from `seed`, so a failing run is
+//! reproduced by running its seed again.
+
+use crate::legacy::hlc::{self, HLTimestamp};
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::metadata::Metadata;
+use crate::legacy::storage::{Action, Datum, Event, EventStorage};
+use anyhow::{bail, Context, Result};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+use uuid::Uuid;
+
+/// When simulated time starts, in unix seconds.
+const EPOCH: i64 = 1_700_000_000;
+
+/// The predicates edited, by the kind of edit.
+const PREDICATES: [&str; 3] = ["name", "status", "tag"];
+const WORDS: [&str; 6] = ["alpha", "beta", "gamma", "delta", "open", "done"];
+
+/// How many rounds of syncing every pair at the end may take.
+const MAX_ROUNDS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub seed: u64,
+    pub replicas: usize,
+    /// Simulated seconds, one step each.
+    pub ticks: u64,
+    /// The chance that a replica edits the graph in a tick.
+    pub edit_chance: f64,
+    /// The chance that a replica sends its events to a peer in a tick.
+    pub sync_chance: f64,
+    /// The fewest and most ticks a message takes to arrive.
+    pub latency: (u64, u64),
+    /// The chance that a partition starts in a tick, if there is none.
+    pub partition_chance: f64,
+    /// The most ticks a partition lasts.
+    pub partition_ticks: u64,
+    /// The most seconds a replica's clock is ahead or behind.
+    pub max_skew: i64,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            seed: 0,
+            replicas: 4,
+            ticks: 300,
+            edit_chance: 0.5,
+            sync_chance: 0.2,
+            latency: (0, 5),
+            partition_chance: 0.02,
+            partition_ticks: 40,
+            max_skew: 120,
+        }
+    }
+}
+
+/// What a simulation did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// The events every replica ended with.
+    pub events: u64,
+    pub messages: u64,
+    /// The messages lost to partitions.
+    pub dropped: u64,
+    /// The rounds of syncing at the end.
+    pub rounds: usize,
+}
+
+/// A pseudo-random generator, SplitMix64, so runs only depend on the seed.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, `n` more than 0.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn between(&mut self, low: u64, high: u64) -> u64 {
+        low + self.below(high.saturating_sub(low) + 1)
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        match items.len() {
+            0 => None,
+            n => items.get(self.below(n as u64) as usize),
+        }
+    }
+
+    fn uuid(&mut self) -> Uuid {
+        Uuid::from_u64_pair(self.next(), self.next())
+    }
+}
+
+struct Replica {
+    storage: EventStorage,
+    actor: Uuid,
+    clock: hlc::State<Box<dyn FnMut() -> i64>>,
+    /// The graph as this replica sees it, to pick what to edit.
+    projection: Projection,
+}
+
+struct Message {
+    arrives: u64,
+    from: usize,
+    to: usize,
+    events: Vec<Event>,
+}
+
+/// Runs the simulation of `config` and checks that the replicas converge.
+pub fn run(config: &Config) -> Result<Outcome> {
+    if config.replicas < 2 {
+        bail!("A simulation needs at least two replicas");
+    }
+    let mut rng = Rng(config.seed);
+    let now = Rc::new(Cell::new(EPOCH));
+    let mut replicas = Vec::new();
+    for _ in 0..config.replicas {
+        let skew = rng.between(0, 2 * config.max_skew.max(0) as u64) as i64 - config.max_skew;
+        let now = now.clone();
+        replicas.push(Replica {
+            storage: EventStorage::open(":memory:")?,
+            actor: rng.uuid(),
+            clock: hlc::State::new_with(Box::new(move || now.get() + skew)),
+            projection: Projection::new(),
+        });
+    }
+
+    let mut outcome = Outcome::default();
+    let mut in_flight: Vec<Message> = Vec::new();
+    // The events each replica knows the other has, by sender and receiver.
+    let mut delivered: BTreeMap<(usize, usize), HashSet<Uuid>> = BTreeMap::new();
+    // The side of each replica and when the partition heals.
+    let mut partition: Option<(Vec<bool>, u64)> = None;
+    let split = |partition: &Option<(Vec<bool>, u64)>, a: usize, b: usize| {
+        partition
+            .as_ref()
+            .is_some_and(|(sides, _)| sides[a] != sides[b])
+    };
+
+    for tick in 0..config.ticks {
+        now.set(EPOCH + tick as i64);
+        match &partition {
+            Some((_, heals)) if *heals <= tick => partition = None,
+            None if rng.chance(config.partition_chance) => {
+                let sides = (0..config.replicas).map(|_| rng.chance(0.5)).collect();
+                partition = Some((sides, tick + rng.between(1, config.partition_ticks)));
+            }
+            _ => {}
+        }
+
+        for replica in replicas.iter_mut() {
+            if rng.chance(config.edit_chance) {
+                edit(replica, &mut rng, now.get())?;
+            }
+        }
+
+        for (from, replica) in replicas.iter().enumerate() {
+            if !rng.chance(config.sync_chance) {
+                continue;
+            }
+            let to = (from + 1 + rng.below(config.replicas as u64 - 1) as usize) % config.replicas;
+            let known = delivered.entry((from, to)).or_default();
+            let events = unsent(&replica.storage, known)?;
+            if events.is_empty() {
+                continue;
+            }
+            outcome.messages += 1;
+            if split(&partition, from, to) {
+                outcome.dropped += 1;
+                continue;
+            }
+            in_flight.push(Message {
+                arrives: tick + rng.between(config.latency.0, config.latency.1),
+                from,
+                to,
+                events,
+            });
+        }
+
+        // Messages arriving in the same tick do so in a random order.
+        let mut arriving = Vec::new();
+        let mut i = 0;
+        while i < in_flight.len() {
+            if in_flight[i].arrives <= tick {
+                arriving.push(in_flight.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        while !arriving.is_empty() {
+            let message = arriving.swap_remove(rng.below(arriving.len() as u64) as usize);
+            if split(&partition, message.from, message.to) {
+                outcome.dropped += 1;
+                continue;
+            }
+            let known = delivered.entry((message.from, message.to)).or_default();
+            known.extend(message.events.iter().map(Event::id));
+            receive(&mut replicas[message.to], message.events)?;
+        }
+    }
+
+    // Every partition heals and every message in flight arrives.
+    now.set(EPOCH + config.ticks as i64);
+    in_flight.sort_by_key(|m| m.arrives);
+    for message in in_flight {
+        receive(&mut replicas[message.to], message.events)?;
+    }
+    loop {
+        outcome.rounds += 1;
+        let mut merged = 0;
+        for from in 0..config.replicas {
+            for to in 0..config.replicas {
+                if from != to {
+                    let events = unsent(&replicas[from].storage, &HashSet::new())?;
+                    merged += receive(&mut replicas[to], events)?;
+                }
+            }
+        }
+        if merged == 0 {
+            break;
+        }
+        if outcome.rounds >= MAX_ROUNDS {
+            bail!(
+                "Seed {}: replicas still merge events after {} rounds",
+                config.seed,
+                MAX_ROUNDS
+            );
+        }
+    }
+
+    let first = Projection::load(&replicas[0].storage)?;
+    outcome.events = replicas[0].storage.count()?;
+    for (i, replica) in replicas.iter().enumerate().skip(1) {
+        let events = replica.storage.count()?;
+        if events != outcome.events {
+            bail!(
+                "Seed {}: replica {} has {} events, replica 0 has {}",
+                config.seed,
+                i,
+                events,
+                outcome.events
+            );
+        }
+        let projection = Projection::load(&replica.storage)?;
+        if projection != first {
+            bail!(
+                "Seed {}: replica {} diverged from replica 0: {:?}",
+                config.seed,
+                i,
+                first.diff(&projection)
+            );
+        }
+    }
+    Ok(outcome)
+}
+
+/// The events of `storage` not in `known`.
+fn unsent(storage: &EventStorage, known: &HashSet<Uuid>) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    storage.play(|event| {
+        if !known.contains(&event.id()) {
+            events.push(event);
+        }
+        Ok(())
+    })?;
+    Ok(events)
+}
+
+/// Merges `events` into `replica`, as syncing does, and returns how many
+/// were new.
+fn receive(replica: &mut Replica, events: Vec<Event>) -> Result<usize> {
+    if let Some(latest) = events.iter().map(Event::hlc).max() {
+        replica.clock.update(latest);
+    }
+    let merged = replica.storage.merge(events)?;
+    if merged > 0 {
+        // Merged events may sort before those applied, so the graph is
+        // replayed, as the editor does.
+        replica.projection = Projection::load(&replica.storage)?;
+    }
+    Ok(merged)
+}
+
+/// Records a random edit of the graph `replica` sees at `now`.
+fn edit(replica: &mut Replica, rng: &mut Rng, now: i64) -> Result<()> {
+    let mut ids: Vec<Uuid> = replica.projection.entities().map(|(id, _)| *id).collect();
+    ids.sort();
+    let action = match rng.pick(&ids).copied() {
+        Some(subject) if !rng.chance(0.15) => change(replica, rng, subject, now),
+        _ => {
+            let id = rng.uuid();
+            Action::Transaction {
+                actions: vec![
+                    Action::CreateEntity { id },
+                    Action::AddFact {
+                        subject: id,
+                        predicate: "name".to_string(),
+                        datum: word(rng),
+                    },
+                ],
+            }
+        }
+    };
+    let hlc: HLTimestamp = replica.clock.get_time();
+    let version = action.version();
+    let event = Event::new_from_parts(
+        rng.uuid(),
+        hlc,
+        action,
+        replica.actor,
+        version,
+        Metadata::new(),
+    )?;
+    replica.projection.apply_event(&event);
+    replica
+        .storage
+        .record(event)
+        .context("Failed to record an edit")?;
+    Ok(())
+}
+
+/// A random change of the entity `subject`.
+fn change(replica: &Replica, rng: &mut Rng, subject: Uuid, now: i64) -> Action {
+    let entity = replica.projection.entity(&subject);
+    let predicate = rng.pick(&PREDICATES).unwrap_or(&"name").to_string();
+    let tags = |predicate: &str| -> Vec<Uuid> {
+        entity
+            .map(|e| e.tagged(predicate).iter().map(|(tag, _)| *tag).collect())
+            .unwrap_or_default()
+    };
+    match rng.below(10) {
+        0 => Action::AddFact {
+            subject,
+            predicate,
+            datum: word(rng),
+        },
+        1 => Action::RemoveFact { subject, predicate },
+        2 => Action::Transaction {
+            actions: vec![
+                Action::RemoveFact {
+                    subject,
+                    predicate: predicate.clone(),
+                },
+                Action::AddFact {
+                    subject,
+                    predicate,
+                    datum: word(rng),
+                },
+            ],
+        },
+        3 => Action::Increment {
+            subject,
+            predicate: "count".to_string(),
+            amount: rng.between(1, 5) as i64,
+        },
+        4 => Action::AddElement {
+            subject,
+            predicate: "labels".to_string(),
+            datum: word(rng),
+            tag: rng.uuid(),
+        },
+        5 => {
+            let mut tags = tags("labels");
+            tags.retain(|_| rng.chance(0.5));
+            Action::RemoveElements {
+                subject,
+                predicate: "labels".to_string(),
+                tags,
+            }
+        }
+        6 => Action::Assign {
+            subject,
+            predicate: "owner".to_string(),
+            datum: word(rng),
+            tag: rng.uuid(),
+            replaces: tags("owner"),
+        },
+        7 => Action::AppendString {
+            subject,
+            predicate: "notes".to_string(),
+            text: format!(" {}", WORDS[rng.below(WORDS.len() as u64) as usize]),
+        },
+        8 => {
+            let text = entity
+                .and_then(|e| e.text("body"))
+                .cloned()
+                .unwrap_or_default();
+            let mut chars: Vec<char> = text.to_string().chars().collect();
+            let at = rng.below(chars.len() as u64 + 1) as usize;
+            if rng.chance(0.3) && at < chars.len() {
+                let end = (at + rng.between(1, 4) as usize).min(chars.len());
+                chars.drain(at..end);
+            } else {
+                let word = WORDS[rng.below(WORDS.len() as u64) as usize];
+                chars.splice(at..at, word.chars());
+            }
+            let new: String = chars.into_iter().collect();
+            let mut actions = text.edit(subject, "body", &new);
+            // Tagged from the seed rather than at random.
+            for action in &mut actions {
+                if let Action::InsertText { id, .. } = action {
+                    id.tag = rng.uuid();
+                }
+            }
+            Action::Transaction { actions }
+        }
+        _ => match rng.chance(0.5) {
+            true => Action::DeleteEntity { id: subject },
+            false => {
+                let mut trashed: Vec<Uuid> =
+                    replica.projection.trash(now).map(|(id, _)| *id).collect();
+                trashed.sort();
+                match rng.pick(&trashed) {
+                    Some(id) => Action::RestoreEntity { id: *id },
+                    None => Action::DeleteEntity { id: subject },
+                }
+            }
+        },
+    }
+}
+
+fn word(rng: &mut Rng) -> Datum {
+    Datum::String(WORDS[rng.below(WORDS.len() as u64) as usize].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_converge() {
+        for seed in 0..4 {
+            let outcome = run(&Config {
+                seed,
+                ..Config::default()
+            })
+            .unwrap();
+            assert!(outcome.events > 0);
+            assert!(outcome.messages > 0);
+        }
+        // Two runs of a seed do the same.
+        let config = Config {
+            seed: 7,
+            ticks: 100,
+            ..Config::default()
+        };
+        assert_eq!(run(&config).unwrap(), run(&config).unwrap());
+    }
+}
@@ -0,0 +1,121 @@
+//! Staged edits: actions held back from the event log until they are
+//! committed, so a sketch can be thrown away without leaving any history.
+//!
+//! The graph shown includes the staged actions. The graph without them is
+//! kept aside and receives the events recorded meanwhile, and it is shown
+//! again once the staged actions are committed or discarded.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::{Action, Event};
+use uuid::Uuid;
+
+#[derive(Debug, Default)]
+pub struct Stage {
+    pending: Vec<Action>,
+    /// The graph without the pending actions, while there are any.
+    committed: Option<Projection>,
+}
+
+impl Stage {
+    /// How many actions are staged.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Stages `actions`, applying them to the graph `shown`.
+    pub fn stage(&mut self, shown: &mut Projection, actions: Vec<Action>) {
+        if self.committed.is_none() {
+            self.committed = Some(shown.clone());
+        }
+        actions.iter().for_each(|a| shown.apply(a));
+        self.pending.extend(actions);
+    }
+
+    /// Applies an event recorded meanwhile to the graph without the staged
+    /// actions. The graph shown receives it too.
+    pub fn apply_event(&mut self, event: &Event) {
+        if let Some(committed) = &mut self.committed {
+            committed.apply_event(event);
+        }
+    }
+
+    /// Stages the pending actions again on top of `shown`, the graph just
+    /// reloaded.
+    pub fn reload(&mut self, shown: &mut Projection) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.committed = Some(shown.clone());
+        self.pending.iter().for_each(|a| shown.apply(a));
+    }
+
+    /// Whether `id` was created, deleted or restored by a staged action, or
+    /// if `predicate` is given, had that fact changed.
+    pub fn touches(&self, id: &Uuid, predicate: Option<&str>) -> bool {
+        self.pending
+            .iter()
+            .flat_map(Action::touches)
+            .any(|(subject, touched)| {
+                subject == *id && (touched.is_none() || predicate.is_none() || touched == predicate)
+            })
+    }
+
+    /// Takes the staged actions to be recorded or dropped, putting `shown`
+    /// back to the graph without them.
+    pub fn take(&mut self, shown: &mut Projection) -> Vec<Action> {
+        if let Some(committed) = self.committed.take() {
+            *shown = committed;
+        }
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::hlc::HLTimestamp;
+    use crate::legacy::storage::{Datum, EventCreator};
+
+    #[test]
+    fn staged_actions_are_held_back() {
+        let mut shown = Projection::new();
+        let mut stage = Stage::default();
+        let [sketch, other] = [(); 2].map(|_| Uuid::new_v4());
+        stage.stage(
+            &mut shown,
+            vec![
+                Action::CreateEntity { id: sketch },
+                Action::AddFact {
+                    subject: sketch,
+                    predicate: "name".to_string(),
+                    datum: Datum::String("Sketch".to_string()),
+                },
+            ],
+        );
+        assert!(shown.contains(&sketch));
+        assert!(stage.touches(&sketch, Some("name")));
+        assert!(!stage.touches(&other, None));
+        assert_eq!(stage.len(), 2);
+
+        // Recorded meanwhile, by this or another replica.
+        let mut creator = EventCreator::new(Uuid::new_v4(), HLTimestamp::new(1, 0));
+        let event = creator.create(Action::CreateEntity { id: other });
+        shown.apply_event(&event);
+        stage.apply_event(&event);
+
+        let mut reloaded = Projection::new();
+        reloaded.apply_event(&event);
+        stage.reload(&mut reloaded);
+        assert_eq!(reloaded, shown);
+
+        let actions = stage.take(&mut shown);
+        assert_eq!(actions.len(), 2);
+        assert!(!shown.contains(&sketch));
+        assert!(shown.contains(&other));
+        assert!(stage.is_empty());
+    }
+}
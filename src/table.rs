@@ -0,0 +1,203 @@
+//! A table of the entities matching a query, with a column per predicate,
+//! for reading and editing many entities at once.
+//!
+//! A cell holding one value, or none, is edited as text: its value is
+//! replaced by the number, boolean or text typed, see [`infer`], or removed
+//! if the text is empty. Cells with several values or a link are read-only
+//! here, as text can't tell which value was meant.
+
+use crate::import::infer;
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{Action, Datum};
+use crate::provenance;
+use crate::query::Query;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Sort {
+        Sort {
+            column: "name".to_string(),
+            descending: false,
+        }
+    }
+}
+
+/// The entities matching `query`, sorted by `sort`, with a column for each
+/// of their predicates and each of `extra`.
+pub fn table(projection: &Projection, query: &Query, extra: &[String], sort: &Sort) -> Table {
+    let mut rows = query.run(projection);
+    let mut columns: BTreeSet<&str> = BTreeSet::new();
+    for id in &rows {
+        if let Some(entity) = projection.entity(id) {
+            columns.extend(
+                entity
+                    .facts()
+                    .map(|(predicate, _)| predicate)
+                    .filter(|predicate| !provenance::is_meta(predicate)),
+            );
+        }
+    }
+    // The name comes first, the columns added last.
+    let mut columns: Vec<String> = columns.into_iter().map(str::to_string).collect();
+    if let Some(i) = columns.iter().position(|c| c == "name") {
+        let name = columns.remove(i);
+        columns.insert(0, name);
+    }
+    for column in extra {
+        if !columns.contains(column) {
+            columns.push(column.clone());
+        }
+    }
+
+    let value = |id: &Uuid| projection.entity(id).and_then(|e| e.value(&sort.column));
+    rows.sort_by(|a, b| {
+        let order = match (value(a), value(b)) {
+            (Some(x), Some(y)) => compare(x, y),
+            // Empty cells last either way.
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        let order = if sort.descending {
+            order.reverse()
+        } else {
+            order
+        };
+        order.then_with(|| a.cmp(b))
+    });
+    Table { columns, rows }
+}
+
+/// Whether the cell of `predicate` can be edited as text.
+pub fn editable(entity: &Entity, predicate: &str) -> bool {
+    match entity.values(predicate) {
+        [] => true,
+        [datum] => !matches!(datum, Datum::Entity(_)),
+        _ => false,
+    }
+}
+
+/// The actions that set the cell of `predicate` to the value typed.
+pub fn edit(projection: &Projection, id: Uuid, predicate: &str, input: &str) -> Vec<Action> {
+    let Some(entity) = projection.entity(&id) else {
+        return Vec::new();
+    };
+    if !editable(entity, predicate) {
+        return Vec::new();
+    }
+    let input = input.trim();
+    let datum = (!input.is_empty()).then(|| infer(input));
+    if entity.value(predicate) == datum.as_ref() {
+        return Vec::new();
+    }
+    let mut actions = Vec::new();
+    if entity.value(predicate).is_some() {
+        actions.push(Action::RemoveFact {
+            subject: id,
+            predicate: predicate.to_string(),
+        });
+    }
+    actions.extend(datum.map(|datum| Action::AddFact {
+        subject: id,
+        predicate: predicate.to_string(),
+        datum,
+    }));
+    actions
+}
+
+/// Numbers and dates by value, before text and the rest by their text.
+pub(crate) fn compare(a: &Datum, b: &Datum) -> Ordering {
+    let number = |datum: &Datum| match datum {
+        Datum::Integer(n) => Some(*n as f64),
+        Datum::Float(n) => Some(*n),
+        Datum::DateTime(t) => Some(*t as f64),
+        _ => None,
+    };
+    let text = |datum: &Datum| match datum {
+        Datum::String(s) => s.to_lowercase(),
+        Datum::Boolean(b) => b.to_string(),
+        Datum::Entity(id) => id.to_string(),
+        _ => String::new(),
+    };
+    match (number(a), number(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => text(a).cmp(&text(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_and_edits_cells() {
+        let mut projection = Projection::new();
+        let add = |projection: &mut Projection, facts: &[(&str, Datum)]| {
+            let id = Uuid::new_v4();
+            projection.apply(&Action::CreateEntity { id });
+            for (predicate, datum) in facts {
+                projection.apply(&Action::AddFact {
+                    subject: id,
+                    predicate: predicate.to_string(),
+                    datum: datum.clone(),
+                });
+            }
+            id
+        };
+        let text = |s: &str| Datum::String(s.to_string());
+        let ada = add(
+            &mut projection,
+            &[
+                ("type", text("person")),
+                ("name", text("Ada")),
+                ("born", Datum::Integer(1815)),
+            ],
+        );
+        let alan = add(
+            &mut projection,
+            &[("type", text("person")), ("name", text("Alan"))],
+        );
+        add(&mut projection, &[("type", text("place"))]);
+
+        let query: Query = "type=person".parse().unwrap();
+        let sort = Sort {
+            column: "born".to_string(),
+            descending: true,
+        };
+        let found = table(&projection, &query, &["email".to_string()], &sort);
+        assert_eq!(found.columns, ["name", "born", "type", "email"]);
+        assert_eq!(found.rows, [ada, alan]);
+
+        let edits = edit(&projection, alan, "born", "1912");
+        edits.iter().for_each(|a| projection.apply(a));
+        assert_eq!(
+            projection.entity(&alan).unwrap().value("born"),
+            Some(&Datum::Integer(1912))
+        );
+        assert_eq!(table(&projection, &query, &[], &sort).rows, [alan, ada]);
+        assert!(edit(&projection, alan, "born", "1912").is_empty());
+        assert_eq!(
+            edit(&projection, ada, "born", " "),
+            [Action::RemoveFact {
+                subject: ada,
+                predicate: "born".to_string()
+            }]
+        );
+    }
+}
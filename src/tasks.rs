@@ -0,0 +1,482 @@
+//! Tasks.
+//!
+//! A task is an entity of type `task` with a `title`, a `status` and
+//! optionally a `due` date, a `priority` from 1 (high) to 3 (low), an
+//! `assignee` and a `repeat` interval. Completing a recurring task records
+//! the completion and moves it to its next due date instead of closing it.
+//! All changes are ordinary actions, so tasks sync like everything else.
+
+use crate::import::{entity_id, upsert};
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{Action, Datum};
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeMap;
+use time::format_description::well_known::Iso8601;
+use time::{Date, Duration, Month, OffsetDateTime, UtcOffset, Weekday};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Todo,
+    Doing,
+    Done,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Todo => "todo",
+            Status::Doing => "doing",
+            Status::Done => "done",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Status> {
+        [Status::Todo, Status::Doing, Status::Done]
+            .into_iter()
+            .find(|status| status.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Repeat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Repeat::Daily => "daily",
+            Repeat::Weekly => "weekly",
+            Repeat::Monthly => "monthly",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Repeat> {
+        match s {
+            "daily" | "day" => Some(Repeat::Daily),
+            "weekly" | "week" => Some(Repeat::Weekly),
+            "monthly" | "month" => Some(Repeat::Monthly),
+            _ => None,
+        }
+    }
+
+    /// The occurrence after `date`.
+    pub fn next(self, date: Date) -> Date {
+        match self {
+            Repeat::Daily => date + Duration::DAY,
+            Repeat::Weekly => date + Duration::WEEK,
+            Repeat::Monthly => add_month(date),
+        }
+    }
+}
+
+/// The same day in the next month, or its last day if the month is shorter.
+fn add_month(date: Date) -> Date {
+    let (year, month) = match date.month() {
+        Month::December => (date.year() + 1, Month::January),
+        month => (date.year(), month.next()),
+    };
+    let day = date.day().min(time::util::days_in_year_month(year, month));
+    Date::from_calendar_date(year, month, day).unwrap_or(date)
+}
+
+/// A task parsed from a line of text, see [`parse_quick`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickTask {
+    pub title: String,
+    pub due: Option<Date>,
+    pub priority: Option<i64>,
+    pub assignee: Option<String>,
+    pub repeat: Option<Repeat>,
+}
+
+/// Parses a task from text such as `Water plants due:friday every:week !2 @sam`.
+///
+/// - `due:` takes `today`, `tomorrow`, a weekday or a `YYYY-MM-DD` date
+/// - `every:` takes `day`, `week` or `month`
+/// - `!1` to `!3` set the priority, `!high`, `!medium` and `!low` also work
+/// - `@name` assigns the task, `@me` to yourself
+///
+/// Everything else is the title.
+pub fn parse_quick(text: &str, today: Date) -> Result<QuickTask> {
+    let mut task = QuickTask {
+        title: String::new(),
+        due: None,
+        priority: None,
+        assignee: None,
+        repeat: None,
+    };
+    let mut title = Vec::new();
+    for word in text.split_whitespace() {
+        if let Some(due) = word.strip_prefix("due:") {
+            task.due = Some(parse_due(due, today)?);
+        } else if let Some(repeat) = word.strip_prefix("every:") {
+            task.repeat =
+                Some(Repeat::parse(repeat).ok_or_else(|| anyhow!("Unknown interval {}", repeat))?);
+        } else if let Some(priority) = word.strip_prefix('!').filter(|p| !p.is_empty()) {
+            task.priority = Some(match priority {
+                "1" | "high" => 1,
+                "2" | "medium" => 2,
+                "3" | "low" => 3,
+                _ => bail!("Unknown priority {}", priority),
+            });
+        } else if let Some(name) = word.strip_prefix('@').filter(|n| !n.is_empty()) {
+            task.assignee = Some(name.to_string());
+        } else {
+            title.push(word);
+        }
+    }
+    task.title = title.join(" ");
+    if task.title.is_empty() {
+        bail!("A task needs a title");
+    }
+    if task.repeat.is_some() && task.due.is_none() {
+        task.due = Some(today);
+    }
+    Ok(task)
+}
+
+fn parse_due(due: &str, today: Date) -> Result<Date> {
+    let weekday = match due {
+        "today" => return Ok(today),
+        "tomorrow" => return Ok(today + Duration::DAY),
+        "mon" | "monday" => Weekday::Monday,
+        "tue" | "tuesday" => Weekday::Tuesday,
+        "wed" | "wednesday" => Weekday::Wednesday,
+        "thu" | "thursday" => Weekday::Thursday,
+        "fri" | "friday" => Weekday::Friday,
+        "sat" | "saturday" => Weekday::Saturday,
+        "sun" | "sunday" => Weekday::Sunday,
+        date => {
+            return Date::parse(date, &Iso8601::DATE).map_err(|_| anyhow!("Unknown date {}", date))
+        }
+    };
+    // The next such weekday, a week from today if it is today.
+    let mut date = today + Duration::DAY;
+    while date.weekday() != weekday {
+        date += Duration::DAY;
+    }
+    Ok(date)
+}
+
+/// Due dates are stored as midnight UTC, so every device agrees on the day.
+fn due_datum(date: Date) -> Datum {
+    Datum::DateTime(
+        date.midnight()
+            .assume_offset(UtcOffset::UTC)
+            .unix_timestamp(),
+    )
+}
+
+pub fn due(task: &Entity) -> Option<Date> {
+    match task.value("due") {
+        Some(Datum::DateTime(seconds)) => OffsetDateTime::from_unix_timestamp(*seconds)
+            .ok()
+            .map(|d| d.date()),
+        _ => None,
+    }
+}
+
+pub fn status(task: &Entity) -> Status {
+    match task.value("status") {
+        Some(Datum::String(s)) => Status::parse(s).unwrap_or(Status::Todo),
+        _ => Status::Todo,
+    }
+}
+
+pub fn priority(task: &Entity) -> Option<i64> {
+    match task.value("priority") {
+        Some(Datum::Integer(p)) => Some(*p),
+        _ => None,
+    }
+}
+
+pub fn repeat(task: &Entity) -> Option<Repeat> {
+    match task.value("repeat") {
+        Some(Datum::String(s)) => Repeat::parse(s),
+        _ => None,
+    }
+}
+
+pub fn is_task(entity: &Entity) -> bool {
+    entity.value("type") == Some(&Datum::String("task".to_string()))
+}
+
+pub fn person_entity(name: &str) -> Uuid {
+    entity_id(&format!("person:{}", name.to_lowercase()))
+}
+
+/// The entity named `name`, ignoring case, or the actions that create a
+/// person with that name.
+fn resolve_assignee(projection: &Projection, name: &str) -> (Uuid, Vec<Action>) {
+    let existing = projection.entities().find(|(_, e)| match e.value("name") {
+        Some(Datum::String(n)) => n.eq_ignore_ascii_case(name),
+        _ => false,
+    });
+    if let Some((id, _)) = existing {
+        return (*id, Vec::new());
+    }
+    let id = person_entity(name);
+    let mut facts = BTreeMap::new();
+    facts.insert("type".to_string(), vec![Datum::String("person".into())]);
+    facts.insert("name".to_string(), vec![Datum::String(name.into())]);
+    (id, upsert(projection, id, &facts))
+}
+
+/// Returns the id of a new task parsed from `text` and the actions that
+/// create it, see [`parse_quick`]. `@me` assigns the task to `me`.
+pub fn quick_add(
+    projection: &Projection,
+    text: &str,
+    me: Uuid,
+    today: Date,
+) -> Result<(Uuid, Vec<Action>)> {
+    let quick = parse_quick(text, today)?;
+    let id = Uuid::new_v4();
+    let mut actions = vec![Action::CreateEntity { id }];
+    let mut facts = vec![
+        ("type", Datum::String("task".into())),
+        ("title", Datum::String(quick.title)),
+        ("status", Datum::String(Status::Todo.as_str().into())),
+    ];
+    if let Some(due) = quick.due {
+        facts.push(("due", due_datum(due)));
+    }
+    if let Some(priority) = quick.priority {
+        facts.push(("priority", Datum::Integer(priority)));
+    }
+    if let Some(repeat) = quick.repeat {
+        facts.push(("repeat", Datum::String(repeat.as_str().into())));
+    }
+    if let Some(name) = &quick.assignee {
+        let (assignee, create) = match name.as_str() {
+            "me" => (me, Vec::new()),
+            name => resolve_assignee(projection, name),
+        };
+        actions.extend(create);
+        facts.push(("assignee", Datum::Entity(assignee)));
+    }
+    actions.extend(facts.into_iter().map(|(predicate, datum)| Action::AddFact {
+        subject: id,
+        predicate: predicate.to_string(),
+        datum,
+    }));
+    Ok((id, actions))
+}
+
+pub fn set_status(projection: &Projection, task: Uuid, status: Status) -> Vec<Action> {
+    let mut facts = BTreeMap::new();
+    facts.insert(
+        "status".to_string(),
+        vec![Datum::String(status.as_str().into())],
+    );
+    upsert(projection, task, &facts)
+}
+
+/// Returns the actions that complete `task` at `now`.
+///
+/// Every completion is kept in `completed`. A recurring task stays open and
+/// is due at its first occurrence after today instead.
+pub fn complete(projection: &Projection, task: Uuid, now: OffsetDateTime) -> Vec<Action> {
+    let Some(entity) = projection.entity(&task) else {
+        return Vec::new();
+    };
+    let mut actions = vec![Action::AddFact {
+        subject: task,
+        predicate: "completed".to_string(),
+        datum: Datum::DateTime(now.unix_timestamp()),
+    }];
+    match (repeat(entity), due(entity)) {
+        (Some(repeat), Some(due)) => {
+            let mut next = repeat.next(due);
+            while next <= now.date() {
+                next = repeat.next(next);
+            }
+            let mut facts = BTreeMap::new();
+            facts.insert("due".to_string(), vec![due_datum(next)]);
+            facts.insert(
+                "status".to_string(),
+                vec![Datum::String(Status::Todo.as_str().into())],
+            );
+            actions.extend(upsert(projection, task, &facts));
+        }
+        _ => actions.extend(set_status(projection, task, Status::Done)),
+    }
+    actions
+}
+
+/// The groups of the "My tasks" view, in the order they are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bucket {
+    Overdue,
+    Today,
+    Tomorrow,
+    /// Due within the next week.
+    Upcoming,
+    Later,
+    NoDate,
+}
+
+impl Bucket {
+    fn of(due: Option<Date>, today: Date) -> Bucket {
+        let Some(due) = due else {
+            return Bucket::NoDate;
+        };
+        match (due - today).whole_days() {
+            ..=-1 => Bucket::Overdue,
+            0 => Bucket::Today,
+            1 => Bucket::Tomorrow,
+            2..=7 => Bucket::Upcoming,
+            _ => Bucket::Later,
+        }
+    }
+
+    /// The id of the bucket's label in the translations, see [`crate::i18n`].
+    pub fn message_id(self) -> &'static str {
+        match self {
+            Bucket::Overdue => "bucket-overdue",
+            Bucket::Today => "bucket-today",
+            Bucket::Tomorrow => "bucket-tomorrow",
+            Bucket::Upcoming => "bucket-upcoming",
+            Bucket::Later => "bucket-later",
+            Bucket::NoDate => "bucket-no-date",
+        }
+    }
+}
+
+/// The open tasks assigned to `me` or to nobody, grouped by when they are
+/// due and sorted by due date, priority and title.
+pub fn my_tasks(projection: &Projection, me: Uuid, today: Date) -> Vec<(Bucket, Vec<Uuid>)> {
+    let mut tasks: Vec<_> = projection
+        .entities()
+        .filter(|(_, e)| is_task(e) && status(e) != Status::Done)
+        .filter(|(_, e)| match e.value("assignee") {
+            Some(Datum::Entity(assignee)) => *assignee == me,
+            _ => true,
+        })
+        .map(|(id, e)| {
+            let title = match e.value("title") {
+                Some(Datum::String(title)) => title.clone(),
+                _ => String::new(),
+            };
+            let due = due(e);
+            let key = (Bucket::of(due, today), due, priority(e).unwrap_or(4), title);
+            (key, *id)
+        })
+        .collect();
+    tasks.sort();
+
+    let mut groups: Vec<(Bucket, Vec<Uuid>)> = Vec::new();
+    for ((bucket, ..), id) in tasks {
+        match groups.last_mut() {
+            Some((last, ids)) if *last == bucket => ids.push(id),
+            _ => groups.push((bucket, vec![id])),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    fn add(projection: &mut Projection, text: &str, today: Date) -> Uuid {
+        let (id, actions) = quick_add(projection, text, Uuid::nil(), today).unwrap();
+        actions.iter().for_each(|a| projection.apply(a));
+        id
+    }
+
+    #[test]
+    fn parses_quick_tasks() {
+        // A Friday.
+        let today = date!(2024 - 03 - 01);
+        let task = parse_quick("Water plants due:mon every:week !high @Sam", today).unwrap();
+        assert_eq!(
+            task,
+            QuickTask {
+                title: "Water plants".into(),
+                due: Some(date!(2024 - 03 - 04)),
+                priority: Some(1),
+                assignee: Some("Sam".into()),
+                repeat: Some(Repeat::Weekly),
+            }
+        );
+        assert_eq!(
+            parse_quick("x due:fri", today).unwrap().due,
+            Some(date!(2024 - 03 - 08))
+        );
+        assert_eq!(
+            parse_quick("x due:2024-04-02", today).unwrap().due,
+            Some(date!(2024 - 04 - 02))
+        );
+        assert!(parse_quick("due:today", today).is_err());
+        assert!(parse_quick("x !9", today).is_err());
+    }
+
+    #[test]
+    fn assignees_are_reused() {
+        let today = date!(2024 - 03 - 01);
+        let mut projection = Projection::new();
+        let a = add(&mut projection, "one @sam", today);
+        let b = add(&mut projection, "two @Sam", today);
+        let assignee = |id| projection.entity(&id).unwrap().value("assignee").cloned();
+        assert_eq!(assignee(a), Some(Datum::Entity(person_entity("sam"))));
+        assert_eq!(assignee(a), assignee(b));
+        assert_eq!(projection.len(), 3);
+    }
+
+    #[test]
+    fn completing_recurring_tasks_moves_them() {
+        let today = date!(2024 - 01 - 31);
+        let mut projection = Projection::new();
+        let once = add(&mut projection, "once", today);
+        let monthly = add(&mut projection, "pay rent every:month", today);
+
+        let now = datetime!(2024-01-31 12:00 UTC);
+        for task in [once, monthly] {
+            complete(&projection, task, now)
+                .iter()
+                .for_each(|a| projection.apply(a));
+        }
+        let once = projection.entity(&once).unwrap();
+        assert_eq!(status(once), Status::Done);
+        let monthly = projection.entity(&monthly).unwrap();
+        assert_eq!(status(monthly), Status::Todo);
+        assert_eq!(due(monthly), Some(date!(2024 - 02 - 29)));
+        assert_eq!(monthly.values("completed").len(), 1);
+    }
+
+    #[test]
+    fn my_tasks_are_grouped_by_due_date() {
+        let today = date!(2024 - 03 - 01);
+        let mut projection = Projection::new();
+        let mine = add(&mut projection, "mine @me", today);
+        let later = add(&mut projection, "later due:2024-05-01", today);
+        let urgent = add(&mut projection, "urgent due:today !1", today);
+        let trivial = add(&mut projection, "trivial due:today !3", today);
+        let undated = add(&mut projection, "someday", today);
+        let overdue = add(&mut projection, "late due:2024-02-01", today);
+        add(&mut projection, "theirs due:today @someone", today);
+        let done = add(&mut projection, "done due:today", today);
+        set_status(&projection, done, Status::Done)
+            .iter()
+            .for_each(|a| projection.apply(a));
+
+        let groups = my_tasks(&projection, Uuid::nil(), today);
+        let undated_group = groups.last().unwrap();
+        assert_eq!(undated_group.0, Bucket::NoDate);
+        assert_eq!(undated_group.1, [mine, undated]);
+        assert_eq!(
+            groups[..3],
+            [
+                (Bucket::Overdue, vec![overdue]),
+                (Bucket::Today, vec![urgent, trivial]),
+                (Bucket::Later, vec![later]),
+            ]
+        );
+    }
+}
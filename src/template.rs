@@ -0,0 +1,153 @@
+//! Templates for new entities, defined in the graph.
+//!
+//! A template is an entity with `type=template`, a `name` such as "Person",
+//! its `field` predicates in order, and optionally the `for` type given to
+//! the entities it creates. A new entity starts as a form with a value for
+//! each field; the fields filled in become its facts.
+
+use crate::import::infer;
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{Action, Datum};
+use uuid::Uuid;
+
+/// The type of template entities.
+pub const TEMPLATE: &str = "template";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Template {
+    /// The template entity, or `None` for one not yet recorded.
+    pub id: Option<Uuid>,
+    pub name: String,
+    /// The type of the entities created.
+    pub kind: Option<String>,
+    pub fields: Vec<String>,
+}
+
+impl Template {
+    fn read(id: Uuid, entity: &Entity) -> Option<Template> {
+        if entity.value("type") != Some(&Datum::String(TEMPLATE.to_string())) {
+            return None;
+        }
+        let string = |datum: &Datum| match datum {
+            Datum::String(s) => Some(s.clone()),
+            _ => None,
+        };
+        Some(Template {
+            id: Some(id),
+            name: entity.value("name").and_then(string).unwrap_or_default(),
+            kind: entity.value("for").and_then(string),
+            fields: entity.values("field").iter().filter_map(string).collect(),
+        })
+    }
+
+    /// The actions that create an entity with the `values` of the fields,
+    /// leaving out those left empty.
+    pub fn instantiate(&self, values: &[String]) -> (Uuid, Vec<Action>) {
+        let id = Uuid::new_v4();
+        let mut actions = vec![Action::CreateEntity { id }];
+        if let Some(kind) = &self.kind {
+            actions.push(Action::AddFact {
+                subject: id,
+                predicate: "type".to_string(),
+                datum: Datum::String(kind.clone()),
+            });
+        }
+        for (field, value) in self.fields.iter().zip(values) {
+            let value = value.trim();
+            if field.trim().is_empty() || value.is_empty() {
+                continue;
+            }
+            actions.push(Action::AddFact {
+                subject: id,
+                predicate: field.trim().to_string(),
+                datum: infer(value),
+            });
+        }
+        (id, actions)
+    }
+
+    /// The actions that record the template, replacing what was recorded
+    /// of it before.
+    pub fn save(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let id = match self.id {
+            Some(id) => {
+                for predicate in ["name", "for", "field"] {
+                    actions.push(Action::RemoveFact {
+                        subject: id,
+                        predicate: predicate.to_string(),
+                    });
+                }
+                id
+            }
+            None => {
+                let id = Uuid::new_v4();
+                actions.push(Action::CreateEntity { id });
+                actions.push(Action::AddFact {
+                    subject: id,
+                    predicate: "type".to_string(),
+                    datum: Datum::String(TEMPLATE.to_string()),
+                });
+                id
+            }
+        };
+        let fact = |predicate: &str, value: &str| Action::AddFact {
+            subject: id,
+            predicate: predicate.to_string(),
+            datum: Datum::String(value.trim().to_string()),
+        };
+        actions.push(fact("name", &self.name));
+        if let Some(kind) = self.kind.as_deref().filter(|k| !k.trim().is_empty()) {
+            actions.push(fact("for", kind));
+        }
+        for field in self.fields.iter().filter(|f| !f.trim().is_empty()) {
+            actions.push(fact("field", field));
+        }
+        actions
+    }
+}
+
+/// The templates in the graph, by name.
+pub fn templates(projection: &Projection) -> Vec<Template> {
+    let mut templates: Vec<Template> = projection
+        .entities()
+        .filter_map(|(id, entity)| Template::read(*id, entity))
+        .collect();
+    templates.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+    templates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_are_saved_and_instantiated() {
+        let mut projection = Projection::new();
+        let person = Template {
+            id: None,
+            name: "Person".to_string(),
+            kind: Some("person".to_string()),
+            fields: vec!["name".into(), "email".into(), "born".into()],
+        };
+        person.save().iter().for_each(|a| projection.apply(a));
+        let [saved] = &templates(&projection)[..] else {
+            panic!("expected one template");
+        };
+        assert_eq!(saved.fields, person.fields);
+
+        let mut renamed = saved.clone();
+        renamed.fields.retain(|f| f != "email");
+        renamed.save().iter().for_each(|a| projection.apply(a));
+        let [saved] = &templates(&projection)[..] else {
+            panic!("expected one template");
+        };
+        assert_eq!(saved.fields, ["name", "born"]);
+
+        let (id, actions) = saved.instantiate(&["Ada".into(), "1815".into()]);
+        actions.iter().for_each(|a| projection.apply(a));
+        let ada = projection.entity(&id).unwrap();
+        assert_eq!(ada.value("type"), Some(&Datum::String("person".into())));
+        assert_eq!(ada.value("born"), Some(&Datum::Integer(1815)));
+    }
+}
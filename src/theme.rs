@@ -0,0 +1,261 @@
+//! Themes: iced's built-in ones and custom palettes.
+//!
+//! Custom palettes are defined in the config under `[palettes.<name>]`, or in
+//! the graph as entities of type `palette` with a `name` and a fact per color.
+//! Colors are written as `#rrggbb`. Besides the colors iced needs, a palette
+//! has the colors of graph views, which default to ones derived from it.
+//!
+//! Without the `gui` feature only the palettes are read, not turned into
+//! themes.
+
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// An opaque color, `#rrggbb` in its string form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hex(pub u8, pub u8, pub u8);
+
+#[cfg(feature = "gui")]
+impl From<Hex> for iced::Color {
+    fn from(Hex(r, g, b): Hex) -> iced::Color {
+        iced::Color::from_rgb8(r, g, b)
+    }
+}
+
+impl FromStr for Hex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Hex> {
+        let digits = s.trim().strip_prefix('#').unwrap_or(s.trim());
+        let invalid = || anyhow!("Invalid color {}, expected #rrggbb", s);
+        if digits.len() != 6 || !digits.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Hex(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl TryFrom<String> for Hex {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Hex> {
+        s.parse()
+    }
+}
+
+impl From<Hex> for String {
+    fn from(hex: Hex) -> String {
+        hex.to_string()
+    }
+}
+
+impl fmt::Display for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Palette {
+    pub background: Hex,
+    pub text: Hex,
+    pub primary: Hex,
+    pub success: Hex,
+    pub danger: Hex,
+    /// The fill of nodes, the primary color by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<Hex>,
+    /// Edges between nodes, the text color by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge: Option<Hex>,
+    /// The highlight of selected items, the success color by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Hex>,
+    /// The background of graph views, the background color by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canvas: Option<Hex>,
+}
+
+/// The colors of graph views.
+#[cfg(feature = "gui")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphColors {
+    pub node: iced::Color,
+    pub edge: iced::Color,
+    pub selection: iced::Color,
+    pub canvas: iced::Color,
+}
+
+#[cfg(feature = "gui")]
+impl GraphColors {
+    /// Colors derived from one of iced's palettes.
+    pub fn derived(palette: &iced::theme::Palette) -> GraphColors {
+        GraphColors {
+            node: palette.primary,
+            edge: palette.text,
+            selection: palette.success,
+            canvas: palette.background,
+        }
+    }
+}
+
+impl Palette {
+    #[cfg(feature = "gui")]
+    pub fn theme(&self, name: &str) -> iced::Theme {
+        iced::Theme::custom(
+            name.to_string(),
+            iced::theme::Palette {
+                background: self.background.into(),
+                text: self.text.into(),
+                primary: self.primary.into(),
+                success: self.success.into(),
+                danger: self.danger.into(),
+            },
+        )
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn graph(&self) -> GraphColors {
+        let or = |color: Option<Hex>, default: Hex| iced::Color::from(color.unwrap_or(default));
+        GraphColors {
+            node: or(self.node, self.primary),
+            edge: or(self.edge, self.text),
+            selection: or(self.selection, self.success),
+            canvas: or(self.canvas, self.background),
+        }
+    }
+
+    /// Reads a palette from the facts of a `palette` entity. Colors that are
+    /// missing or invalid make the palette invalid, except for the optional
+    /// graph colors.
+    fn from_facts(entity: &crate::legacy::projection::Entity) -> Option<Palette> {
+        let color = |predicate: &str| match entity.value(predicate) {
+            Some(Datum::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+        Some(Palette {
+            background: color("background")?,
+            text: color("text")?,
+            primary: color("primary")?,
+            success: color("success")?,
+            danger: color("danger")?,
+            node: color("node"),
+            edge: color("edge"),
+            selection: color("selection"),
+            canvas: color("canvas"),
+        })
+    }
+}
+
+/// The palettes defined in the graph, by name.
+pub fn graph_palettes(projection: &Projection) -> BTreeMap<String, Palette> {
+    projection
+        .entities()
+        .filter(|(_, e)| e.value("type") == Some(&Datum::String("palette".to_string())))
+        .filter_map(|(_, e)| match e.value("name") {
+            Some(Datum::String(name)) => Some((name.clone(), Palette::from_facts(e)?)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The theme named `name` with the colors of its graph views: a built-in
+/// theme, or else a palette from the config, or else one from the graph. An
+/// unknown name gives the dark theme.
+#[cfg(feature = "gui")]
+pub fn resolve(
+    name: &str,
+    configured: &BTreeMap<String, Palette>,
+    projection: &Projection,
+) -> (iced::Theme, GraphColors) {
+    if let Some(theme) = iced::Theme::ALL.iter().find(|t| t.to_string() == name) {
+        return (theme.clone(), GraphColors::derived(&theme.palette()));
+    }
+    let palette = configured
+        .get(name)
+        .cloned()
+        .or_else(|| graph_palettes(projection).remove(name));
+    match palette {
+        Some(palette) => (palette.theme(name), palette.graph()),
+        None => {
+            let theme = iced::Theme::Dark;
+            let colors = GraphColors::derived(&theme.palette());
+            (theme, colors)
+        }
+    }
+}
+
+/// The names of every theme to choose from: the built-in ones, then the
+/// custom palettes in the config and in the graph.
+#[cfg(feature = "gui")]
+pub fn names(configured: &BTreeMap<String, Palette>, projection: &Projection) -> Vec<String> {
+    let mut names: Vec<String> = iced::Theme::ALL.iter().map(|t| t.to_string()).collect();
+    for name in configured.keys().chain(graph_palettes(projection).keys()) {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+    use uuid::Uuid;
+
+    #[test]
+    fn resolves_custom_palettes() {
+        let mut projection = Projection::new();
+        let id = Uuid::new_v4();
+        projection.apply(&Action::CreateEntity { id });
+        for (predicate, value) in [
+            ("type", "palette"),
+            ("name", "Paper"),
+            ("background", "#fafafa"),
+            ("text", "#111111"),
+            ("primary", "#3366CC"),
+            ("success", "#22aa55"),
+            ("danger", "#cc3333"),
+            ("selection", "#ffcc00"),
+        ] {
+            projection.apply(&Action::AddFact {
+                subject: id,
+                predicate: predicate.into(),
+                datum: Datum::String(value.into()),
+            });
+        }
+
+        let (theme, colors) = resolve("Paper", &BTreeMap::new(), &projection);
+        assert_eq!(theme.to_string(), "Paper");
+        assert_eq!(
+            theme.palette().primary,
+            iced::Color::from_rgb8(0x33, 0x66, 0xcc)
+        );
+        assert_eq!(colors.selection, iced::Color::from_rgb8(0xff, 0xcc, 0x00));
+        assert_eq!(colors.canvas, iced::Color::from_rgb8(0xfa, 0xfa, 0xfa));
+
+        // The config wins over the graph.
+        let mut configured = BTreeMap::new();
+        let mut palette = Palette::from_facts(projection.entity(&id).unwrap()).unwrap();
+        palette.primary = Hex(0, 0, 0);
+        configured.insert("Paper".to_string(), palette);
+        let (theme, _) = resolve("Paper", &configured, &projection);
+        assert_eq!(theme.palette().primary, iced::Color::BLACK);
+        assert_eq!(names(&configured, &projection).last().unwrap(), "Paper");
+
+        assert_eq!(
+            resolve("Nope", &configured, &projection).0,
+            iced::Theme::Dark
+        );
+        assert!("#12345".parse::<Hex>().is_err());
+        assert_eq!("#0a0B0c".parse::<Hex>().unwrap().to_string(), "#0a0b0c");
+    }
+}
@@ -0,0 +1,218 @@
+//! Thumbnails of the images entities refer to.
+//!
+//! The image of an entity is the first value of its [`PREDICATES`] that is a
+//! path or `file://` URL of a PNG, an `http(s)://` URL, or a base64 `data:`
+//! URI. Only PNGs are decoded, of at most [`MAX_BYTES`], and they are shrunk
+//! to at most [`SIZE`] cells a side, each the average of its pixels, so they
+//! can be drawn with plain widgets.
+
+use crate::legacy::projection::Entity;
+use crate::legacy::storage::Datum;
+use anyhow::{bail, ensure, Context, Result};
+use base64::Engine;
+use std::io::Read;
+
+/// The predicates whose values are images, in order of preference.
+pub const PREDICATES: [&str; 3] = ["thumbnail", "image", "photo"];
+/// The most cells on a side of a thumbnail.
+pub const SIZE: u32 = 16;
+/// The largest image read.
+pub const MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    /// The RGB colors of the cells, row by row.
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Thumbnail {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+/// Where the image of `entity` is, if it has one.
+pub fn source(entity: &Entity) -> Option<&str> {
+    PREDICATES
+        .iter()
+        .flat_map(|p| entity.values(p))
+        .find_map(|datum| match datum {
+            Datum::String(s) if is_image(s) => Some(s.as_str()),
+            _ => None,
+        })
+}
+
+fn is_image(source: &str) -> bool {
+    let lower = source.to_ascii_lowercase();
+    lower.starts_with("data:image/")
+        || lower.starts_with("http://")
+        || lower.starts_with("https://")
+        || lower.ends_with(".png")
+}
+
+/// Reads and shrinks the image at `source`.
+pub fn load(source: &str) -> Result<Thumbnail> {
+    let bytes = read(source)?;
+    decode(&bytes).with_context(|| format!("Failed to read the image {}", short(source)))
+}
+
+fn read(source: &str) -> Result<Vec<u8>> {
+    if let Some(data) = source.strip_prefix("data:") {
+        let Some((_, encoded)) = data.split_once(";base64,") else {
+            bail!("Only base64 data URIs are supported");
+        };
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .context("Failed to decode the data URI");
+    }
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let response = ureq::get(source)
+            .call()
+            .with_context(|| format!("Failed to fetch {}", source))?;
+        let mut bytes = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_BYTES + 1)
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to read {}", source))?;
+        ensure!(
+            bytes.len() as u64 <= MAX_BYTES,
+            "{} is larger than {} bytes",
+            source,
+            MAX_BYTES
+        );
+        return Ok(bytes);
+    }
+    let path = source.strip_prefix("file://").unwrap_or(source);
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read {}", path))?
+        .len();
+    ensure!(
+        size <= MAX_BYTES,
+        "{} is larger than {} bytes",
+        path,
+        MAX_BYTES
+    );
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path))
+}
+
+/// Decodes a PNG, blending transparency onto white, and averages it into
+/// cells.
+fn decode(bytes: &[u8]) -> Result<Thumbnail> {
+    let limits = png::Limits {
+        bytes: 4 * MAX_BYTES as usize,
+    };
+    let mut decoder = png::Decoder::new_with_limits(bytes, limits);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let pixels = &buffer[..info.buffer_size()];
+    let blend = |value: u8, alpha: u8| {
+        let alpha = alpha as u32;
+        ((value as u32 * alpha + 255 * (255 - alpha)) / 255) as u8
+    };
+    let rgb: Vec<[u8; 3]> = match info.color_type {
+        png::ColorType::Rgb => pixels.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+        png::ColorType::Rgba => pixels
+            .chunks_exact(4)
+            .map(|p| [blend(p[0], p[3]), blend(p[1], p[3]), blend(p[2], p[3])])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().map(|p| [*p; 3]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .map(|p| [blend(p[0], p[1]); 3])
+            .collect(),
+        png::ColorType::Indexed => bail!("Indexed colors were not expanded"),
+    };
+    ensure!(info.width > 0 && info.height > 0, "The image is empty");
+
+    let scale = info.width.max(info.height).div_ceil(SIZE);
+    let (width, height) = (info.width.div_ceil(scale), info.height.div_ceil(scale));
+    let mut cells = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0u32; 3];
+            let mut count = 0;
+            for py in y * scale..((y + 1) * scale).min(info.height) {
+                for px in x * scale..((x + 1) * scale).min(info.width) {
+                    let pixel = rgb[(py * info.width + px) as usize];
+                    for (total, value) in sum.iter_mut().zip(pixel) {
+                        *total += value as u32;
+                    }
+                    count += 1;
+                }
+            }
+            cells.push(sum.map(|total| (total / count) as u8));
+        }
+    }
+    Ok(Thumbnail {
+        width,
+        height,
+        pixels: cells,
+    })
+}
+
+/// A source short enough for messages, data URIs being long.
+fn short(source: &str) -> &str {
+    match source.find(',') {
+        Some(comma) if source.starts_with("data:") => &source[..comma],
+        _ => source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::projection::Projection;
+    use crate::legacy::storage::Action;
+    use uuid::Uuid;
+
+    #[test]
+    fn images_are_shrunk() {
+        // Red on the left half, blue on the right.
+        let (width, height) = (64, 32);
+        let mut pixels = Vec::new();
+        for _ in 0..height {
+            for x in 0..width {
+                pixels.extend(if x < width / 2 {
+                    [255, 0, 0]
+                } else {
+                    [0, 0, 255]
+                });
+            }
+        }
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        let uri = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&png)
+        );
+
+        let mut projection = Projection::new();
+        let id = Uuid::new_v4();
+        projection.apply(&Action::CreateEntity { id });
+        for value in ["notes.txt", &uri] {
+            projection.apply(&Action::AddFact {
+                subject: id,
+                predicate: "image".to_string(),
+                datum: Datum::String(value.to_string()),
+            });
+        }
+        let source = source(projection.entity(&id).unwrap()).unwrap();
+        assert_eq!(source, uri);
+
+        let thumbnail = load(source).unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (16, 8));
+        assert_eq!(thumbnail.pixel(0, 0), [255, 0, 0]);
+        assert_eq!(thumbnail.pixel(15, 7), [0, 0, 255]);
+        assert!(load("data:image/png;base64,AAAA").is_err());
+    }
+}
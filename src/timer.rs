@@ -0,0 +1,194 @@
+//! Time tracking.
+//!
+//! Starting a timer on an entity creates a `time_entry` entity with the
+//! tracked `entity`, the `actor` who tracks it and a `start` time. Stopping it
+//! adds the `end` time. Each actor runs at most one timer, so starting a
+//! timer stops the one that is running.
+
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::{Action, Datum};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// A tracked interval, in unix seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub id: Uuid,
+    pub entity: Uuid,
+    pub actor: Uuid,
+    pub start: i64,
+    /// `None` while the timer is running.
+    pub end: Option<i64>,
+}
+
+impl Entry {
+    fn from_entity(id: Uuid, entity: &Entity) -> Option<Entry> {
+        if entity.value("type") != Some(&Datum::String("time_entry".to_string())) {
+            return None;
+        }
+        let (Some(Datum::Entity(tracked)), Some(Datum::Entity(actor))) =
+            (entity.value("entity"), entity.value("actor"))
+        else {
+            return None;
+        };
+        let Some(Datum::DateTime(start)) = entity.value("start") else {
+            return None;
+        };
+        let end = match entity.value("end") {
+            Some(Datum::DateTime(end)) => Some(*end),
+            _ => None,
+        };
+        Some(Entry {
+            id,
+            entity: *tracked,
+            actor: *actor,
+            start: *start,
+            end,
+        })
+    }
+
+    /// The seconds of the entry within `from` until before `to`, counting a
+    /// running entry until `now`.
+    pub fn seconds_within(&self, from: i64, to: i64, now: i64) -> i64 {
+        let end = self.end.unwrap_or(now).min(to);
+        (end - self.start.max(from)).max(0)
+    }
+}
+
+pub fn entries(projection: &Projection) -> impl Iterator<Item = Entry> + '_ {
+    projection
+        .entities()
+        .filter_map(|(id, entity)| Entry::from_entity(*id, entity))
+}
+
+/// The timer `actor` is running, if any.
+pub fn running(projection: &Projection, actor: Uuid) -> Option<Entry> {
+    entries(projection)
+        .filter(|e| e.actor == actor && e.end.is_none())
+        .max_by_key(|e| e.start)
+}
+
+/// Returns the actions that stop the timer `actor` is running at `now`.
+pub fn stop(projection: &Projection, actor: Uuid, now: i64) -> Vec<Action> {
+    entries(projection)
+        .filter(|e| e.actor == actor && e.end.is_none())
+        .map(|e| Action::AddFact {
+            subject: e.id,
+            predicate: "end".to_string(),
+            datum: Datum::DateTime(now.max(e.start)),
+        })
+        .collect()
+}
+
+/// Returns the actions that start a timer on `entity` for `actor` at `now`,
+/// stopping the one that is running.
+pub fn start(projection: &Projection, entity: Uuid, actor: Uuid, now: i64) -> Vec<Action> {
+    let mut actions = stop(projection, actor, now);
+    let id = Uuid::new_v4();
+    actions.push(Action::CreateEntity { id });
+    for (predicate, datum) in [
+        ("type", Datum::String("time_entry".to_string())),
+        ("entity", Datum::Entity(entity)),
+        ("actor", Datum::Entity(actor)),
+        ("start", Datum::DateTime(now)),
+    ] {
+        actions.push(Action::AddFact {
+            subject: id,
+            predicate: predicate.to_string(),
+            datum,
+        });
+    }
+    actions
+}
+
+/// The seconds tracked on each entity from `from` until before `to`.
+pub fn by_entity(projection: &Projection, from: i64, to: i64, now: i64) -> BTreeMap<Uuid, i64> {
+    let mut totals = BTreeMap::new();
+    for entry in entries(projection) {
+        let seconds = entry.seconds_within(from, to, now);
+        if seconds > 0 {
+            *totals.entry(entry.entity).or_insert(0) += seconds;
+        }
+    }
+    totals
+}
+
+/// The seconds tracked on entities with each `tag` from `from` until before
+/// `to`. Time on an entity with several tags counts towards each of them.
+pub fn by_tag(projection: &Projection, from: i64, to: i64, now: i64) -> BTreeMap<String, i64> {
+    let mut totals = BTreeMap::new();
+    for (entity, seconds) in by_entity(projection, from, to, now) {
+        let tags = projection
+            .entity(&entity)
+            .map(|e| e.values("tag"))
+            .unwrap_or_default();
+        for tag in tags {
+            if let Datum::String(tag) = tag {
+                *totals.entry(tag.clone()).or_insert(0) += seconds;
+            }
+        }
+    }
+    totals
+}
+
+/// Formats seconds as `h:mm`.
+pub fn format_duration(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(projection: &mut Projection, f: impl FnOnce(&Projection) -> Vec<Action>) {
+        f(projection).iter().for_each(|a| projection.apply(a));
+    }
+
+    #[test]
+    fn starting_a_timer_stops_the_running_one() {
+        let mut projection = Projection::new();
+        let (me, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        apply(&mut projection, |p| start(p, a, me, 0));
+        apply(&mut projection, |p| start(p, a, other, 0));
+        apply(&mut projection, |p| start(p, b, me, 600));
+
+        assert_eq!(running(&projection, me).unwrap().entity, b);
+        assert_eq!(running(&projection, other).unwrap().entity, a);
+        apply(&mut projection, |p| stop(p, me, 900));
+        assert_eq!(running(&projection, me), None);
+
+        let totals = by_entity(&projection, 0, 3600, 1200);
+        // 600 seconds by me and 1200 by the still running other actor.
+        assert_eq!(totals[&a], 1800);
+        assert_eq!(totals[&b], 300);
+        // Only the part within the range counts.
+        assert_eq!(by_entity(&projection, 300, 700, 1200)[&a], 400 + 300);
+    }
+
+    #[test]
+    fn reports_time_per_tag() {
+        let mut projection = Projection::new();
+        let me = Uuid::new_v4();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        for (id, tags) in [(a, vec!["work", "rust"]), (b, vec!["work"])] {
+            projection.apply(&Action::CreateEntity { id });
+            for tag in tags {
+                projection.apply(&Action::AddFact {
+                    subject: id,
+                    predicate: "tag".into(),
+                    datum: Datum::String(tag.into()),
+                });
+            }
+        }
+        apply(&mut projection, |p| start(p, a, me, 0));
+        apply(&mut projection, |p| start(p, b, me, 60));
+        apply(&mut projection, |p| stop(p, me, 180));
+
+        let totals = by_tag(&projection, 0, 1000, 1000);
+        assert_eq!(totals["work"], 180);
+        assert_eq!(totals["rust"], 60);
+        assert_eq!(format_duration(3720), "1:02");
+    }
+}
@@ -0,0 +1,456 @@
+//! Constraints on facts, declared in the graph itself.
+//!
+//! A constraint is an entity with `type=constraint` and the `predicate` it
+//! constrains, for the entities whose `type` is its `for`, or for all of
+//! them if it has none. It holds any of
+//!
+//! - `required=true`: the predicate has a value,
+//! - `min` and `max`: its numbers and dates lie within these bounds,
+//! - `pattern`: its strings match this regular expression as a whole,
+//! - `unique=true`: no two entities share a value of it.
+//!
+//! [`validate`] lists the facts that break them. Constraints are facts like
+//! any other, so they sync with the graph and apply on every device.
+//!
+//! A [`Validator`] keeps the violations up to date as entities change,
+//! checking only the entities changed and those sharing a unique value with
+//! them. Constraints are compiled once, and again when one of them changes.
+
+use crate::legacy::projection::{Entity, Projection};
+use crate::legacy::storage::Datum;
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+/// The type of constraint entities.
+pub const CONSTRAINT: &str = "constraint";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub entity: Uuid,
+    pub predicate: String,
+    pub rule: Rule,
+    /// The constraint broken.
+    pub constraint: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Required,
+    Min(f64),
+    Max(f64),
+    Pattern(String),
+    /// Another entity has the same value.
+    Unique(Uuid),
+    /// The constraint's own `pattern` isn't a regular expression.
+    InvalidPattern(String),
+}
+
+struct Constraint {
+    id: Uuid,
+    predicate: String,
+    kind: Option<String>,
+    required: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    pattern: Option<(String, Regex)>,
+    unique: bool,
+}
+
+/// The facts breaking the constraints in the graph, by entity and predicate.
+pub fn validate(projection: &Projection) -> Vec<Violation> {
+    let mut violations: Vec<_> = Validator::new(projection)
+        .violations
+        .into_values()
+        .flatten()
+        .collect();
+    violations.sort_by(|a, b| (a.entity, &a.predicate).cmp(&(b.entity, &b.predicate)));
+    violations
+}
+
+/// The violations of a graph, kept up to date as its entities change.
+#[derive(Default)]
+pub struct Validator {
+    /// Every constraint entity, whether it declares a constraint or not.
+    declared: HashSet<Uuid>,
+    constraints: Vec<Constraint>,
+    /// Who holds each value of the predicate of each unique constraint, by
+    /// its index in `constraints`.
+    holders: Vec<HashMap<String, BTreeSet<Uuid>>>,
+    /// The values each entity holds in `holders`.
+    held: HashMap<Uuid, Vec<(usize, String)>>,
+    violations: HashMap<Uuid, Vec<Violation>>,
+}
+
+impl Validator {
+    pub fn new(projection: &Projection) -> Validator {
+        let mut validator = Validator::default();
+        let mut checked = Vec::new();
+        for (id, entity) in projection.entities() {
+            if !is_constraint(entity) {
+                checked.push(*id);
+                continue;
+            }
+            validator.declared.insert(*id);
+            match constraint(*id, entity) {
+                Ok(Some(constraint)) => validator.constraints.push(constraint),
+                Ok(None) => {}
+                Err(violation) => {
+                    validator.violations.insert(*id, vec![violation]);
+                }
+            }
+        }
+        validator.holders = vec![HashMap::new(); validator.constraints.len()];
+        for id in &checked {
+            validator.hold(projection, *id);
+        }
+        for id in checked {
+            validator.check(projection, id);
+        }
+        validator
+    }
+
+    /// The violations of each entity.
+    pub fn violations(&self) -> &HashMap<Uuid, Vec<Violation>> {
+        &self.violations
+    }
+
+    /// Checks the `changed` entities again, and those sharing a unique value
+    /// with them before or after. Changed constraints check every entity.
+    pub fn update(&mut self, projection: &Projection, changed: &[Uuid]) {
+        let constraints_changed = changed.iter().any(|id| {
+            self.declared.contains(id) || projection.entity(id).is_some_and(is_constraint)
+        });
+        if constraints_changed {
+            *self = Validator::new(projection);
+            return;
+        }
+        let mut affected: BTreeSet<Uuid> = changed.iter().copied().collect();
+        for id in changed {
+            for (i, value) in self.held.remove(id).unwrap_or_default() {
+                if let Some(holders) = self.holders[i].get_mut(&value) {
+                    holders.remove(id);
+                    affected.extend(holders.iter());
+                    if holders.is_empty() {
+                        self.holders[i].remove(&value);
+                    }
+                }
+            }
+        }
+        for id in changed {
+            self.hold(projection, *id);
+            for (i, value) in self.held.get(id).into_iter().flatten() {
+                affected.extend(self.holders[*i][value].iter());
+            }
+        }
+        for id in affected {
+            self.check(projection, id);
+        }
+    }
+
+    /// Adds the unique values of `id` to `holders`.
+    fn hold(&mut self, projection: &Projection, id: Uuid) {
+        let Some(entity) = projection.entity(&id) else {
+            return;
+        };
+        let mut held = Vec::new();
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            if !constraint.unique || !applies(constraint, entity) {
+                continue;
+            }
+            for datum in entity.values(&constraint.predicate) {
+                let value = format!("{:?}", datum);
+                self.holders[i].entry(value.clone()).or_default().insert(id);
+                held.push((i, value));
+            }
+        }
+        if !held.is_empty() {
+            self.held.insert(id, held);
+        }
+    }
+
+    /// Lists the violations of `id` again.
+    fn check(&mut self, projection: &Projection, id: Uuid) {
+        let mut violations = Vec::new();
+        if let Some(entity) = projection.entity(&id).filter(|e| !is_constraint(e)) {
+            for constraint in self.constraints.iter().filter(|c| applies(c, entity)) {
+                violations.extend(check(constraint, id, entity));
+            }
+            for (i, value) in self.held.get(&id).into_iter().flatten() {
+                let others = &self.holders[*i][value];
+                if let Some(other) = others.iter().find(|other| **other != id) {
+                    let constraint = &self.constraints[*i];
+                    violations.push(Violation {
+                        entity: id,
+                        predicate: constraint.predicate.clone(),
+                        rule: Rule::Unique(*other),
+                        constraint: constraint.id,
+                    });
+                }
+            }
+        }
+        violations.sort_by(|a, b| a.predicate.cmp(&b.predicate));
+        violations.dedup();
+        match violations.is_empty() {
+            true => self.violations.remove(&id),
+            false => self.violations.insert(id, violations),
+        };
+    }
+}
+
+/// The violations of `constraint` by `entity`, but for uniqueness.
+fn check(constraint: &Constraint, id: Uuid, entity: &Entity) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut violation = |rule| {
+        violations.push(Violation {
+            entity: id,
+            predicate: constraint.predicate.clone(),
+            rule,
+            constraint: constraint.id,
+        })
+    };
+    let values = entity.values(&constraint.predicate);
+    if constraint.required && values.is_empty() {
+        violation(Rule::Required);
+    }
+    for datum in values {
+        let n = number(datum);
+        if let (Some(n), Some(min)) = (n, constraint.min) {
+            if n < min {
+                violation(Rule::Min(min));
+            }
+        }
+        if let (Some(n), Some(max)) = (n, constraint.max) {
+            if n > max {
+                violation(Rule::Max(max));
+            }
+        }
+        if let (Datum::String(s), Some((source, regex))) = (datum, &constraint.pattern) {
+            if !regex.is_match(s) {
+                violation(Rule::Pattern(source.clone()));
+            }
+        }
+    }
+    violations
+}
+
+/// The violations of each entity.
+pub fn by_entity(violations: Vec<Violation>) -> HashMap<Uuid, Vec<Violation>> {
+    let mut grouped: HashMap<Uuid, Vec<Violation>> = HashMap::new();
+    for violation in violations {
+        grouped.entry(violation.entity).or_default().push(violation);
+    }
+    grouped
+}
+
+fn is_constraint(entity: &Entity) -> bool {
+    entity.value("type") == Some(&Datum::String(CONSTRAINT.to_string()))
+}
+
+/// The constraint declared by `entity`, if it names a predicate, or the
+/// violation of its invalid pattern.
+fn constraint(id: Uuid, entity: &Entity) -> Result<Option<Constraint>, Violation> {
+    let string = |predicate| match entity.value(predicate) {
+        Some(Datum::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+    let Some(predicate) = string("predicate") else {
+        return Ok(None);
+    };
+    let pattern = match string("pattern") {
+        Some(source) => match Regex::new(&format!("^(?:{})$", source)) {
+            Ok(regex) => Some((source, regex)),
+            Err(_) => {
+                return Err(Violation {
+                    entity: id,
+                    predicate: "pattern".to_string(),
+                    rule: Rule::InvalidPattern(source),
+                    constraint: id,
+                })
+            }
+        },
+        None => None,
+    };
+    let flag = |predicate| entity.value(predicate) == Some(&Datum::Boolean(true));
+    Ok(Some(Constraint {
+        id,
+        kind: string("for"),
+        required: flag("required"),
+        min: entity.value("min").and_then(number),
+        max: entity.value("max").and_then(number),
+        pattern,
+        unique: flag("unique"),
+        predicate,
+    }))
+}
+
+fn applies(constraint: &Constraint, entity: &Entity) -> bool {
+    match &constraint.kind {
+        Some(kind) => entity.value("type") == Some(&Datum::String(kind.clone())),
+        None => true,
+    }
+}
+
+/// A number or date as a number, dates as Unix time.
+fn number(datum: &Datum) -> Option<f64> {
+    match datum {
+        Datum::Integer(n) => Some(*n as f64),
+        Datum::Float(n) => Some(*n),
+        Datum::DateTime(t) => Some(*t as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy::storage::Action;
+
+    fn entity(projection: &mut Projection, facts: &[(&str, Datum)]) -> Uuid {
+        let id = Uuid::new_v4();
+        projection.apply(&Action::CreateEntity { id });
+        for (predicate, datum) in facts {
+            projection.apply(&Action::AddFact {
+                subject: id,
+                predicate: predicate.to_string(),
+                datum: datum.clone(),
+            });
+        }
+        id
+    }
+
+    /// The facts of a constraint on the `predicate` of persons.
+    fn rule(predicate: &str, facts: &[(&'static str, Datum)]) -> Vec<(&'static str, Datum)> {
+        let text = |s: &str| Datum::String(s.to_string());
+        let mut all = vec![
+            ("type", text(CONSTRAINT)),
+            ("for", text("person")),
+            ("predicate", text(predicate)),
+        ];
+        all.extend_from_slice(facts);
+        all
+    }
+
+    #[test]
+    fn reports_broken_constraints() {
+        let text = |s: &str| Datum::String(s.to_string());
+        let mut projection = Projection::new();
+        let person = [("type", text("person"))];
+        let required = entity(
+            &mut projection,
+            &rule("email", &[("required", Datum::Boolean(true))]),
+        );
+        let pattern = entity(
+            &mut projection,
+            &rule("email", &[("pattern", text(r"[^@\s]+@[^@\s]+"))]),
+        );
+        let range = entity(
+            &mut projection,
+            &rule(
+                "age",
+                &[("min", Datum::Integer(0)), ("max", Datum::Integer(150))],
+            ),
+        );
+        let unique = entity(
+            &mut projection,
+            &rule("email", &[("unique", Datum::Boolean(true))]),
+        );
+        let broken = entity(&mut projection, &rule("x", &[("pattern", text("("))]));
+
+        let ada = entity(
+            &mut projection,
+            &[
+                person[0].clone(),
+                ("email", text("ada@example.com")),
+                ("age", Datum::Integer(36)),
+            ],
+        );
+        let nameless = entity(&mut projection, &person);
+        let twin = entity(
+            &mut projection,
+            &[
+                person[0].clone(),
+                ("email", text("ada@example.com")),
+                ("age", Datum::Integer(200)),
+            ],
+        );
+        let typo = entity(
+            &mut projection,
+            &[person[0].clone(), ("email", text("ada at example.com"))],
+        );
+        entity(&mut projection, &[("type", text("robot"))]);
+
+        let found = by_entity(validate(&projection));
+        let rules = |id: &Uuid| -> Vec<(Rule, Uuid)> {
+            let mut rules: Vec<_> = found[id]
+                .iter()
+                .map(|v| (v.rule.clone(), v.constraint))
+                .collect();
+            rules.sort_by_key(|(_, constraint)| *constraint);
+            rules
+        };
+        assert_eq!(found.len(), 5);
+        assert_eq!(rules(&ada), [(Rule::Unique(twin), unique)]);
+        assert_eq!(rules(&nameless), [(Rule::Required, required)]);
+        let mut expected = vec![(Rule::Max(150.0), range), (Rule::Unique(ada), unique)];
+        expected.sort_by_key(|(_, constraint)| *constraint);
+        assert_eq!(rules(&twin), expected);
+        assert_eq!(
+            rules(&typo),
+            [(Rule::Pattern(r"[^@\s]+@[^@\s]+".to_string()), pattern)]
+        );
+        assert_eq!(
+            rules(&broken),
+            [(Rule::InvalidPattern("(".to_string()), broken)]
+        );
+    }
+
+    #[test]
+    fn updates_match_validating_again() {
+        let text = |s: &str| Datum::String(s.to_string());
+        let mut projection = Projection::new();
+        let unique = entity(
+            &mut projection,
+            &rule("email", &[("unique", Datum::Boolean(true))]),
+        );
+        let email = |email: &str| [("type", text("person")), ("email", text(email))];
+        let ada = entity(&mut projection, &email("ada@example.com"));
+        let twin = entity(&mut projection, &email("ada@example.com"));
+        let third = entity(&mut projection, &email("ada@example.com"));
+        let mut validator = Validator::new(&projection);
+        let mut edit = |projection: &mut Projection, actions: Vec<Action>| {
+            let changed: Vec<Uuid> = actions.iter().flat_map(Action::subjects).collect();
+            actions.iter().for_each(|a| projection.apply(a));
+            validator.update(projection, &changed);
+            assert_eq!(validator.violations(), &by_entity(validate(projection)));
+        };
+        assert_eq!(by_entity(validate(&projection)).len(), 3);
+
+        // The twin changes its email, the other two still share one.
+        let change = |id, to: &str| {
+            vec![
+                Action::RemoveFact {
+                    subject: id,
+                    predicate: "email".to_string(),
+                },
+                Action::AddFact {
+                    subject: id,
+                    predicate: "email".to_string(),
+                    datum: text(to),
+                },
+            ]
+        };
+        edit(&mut projection, change(twin, "twin@example.com"));
+        edit(&mut projection, vec![Action::DeleteEntity { id: third }]);
+        assert!(by_entity(validate(&projection)).is_empty());
+        edit(&mut projection, change(ada, "twin@example.com"));
+        // Constraints changing check everything again.
+        edit(
+            &mut projection,
+            vec![Action::RemoveFact {
+                subject: unique,
+                predicate: "unique".to_string(),
+            }],
+        );
+    }
+}
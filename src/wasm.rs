@@ -0,0 +1,486 @@
+//! Plugins compiled to WebAssembly, for what the declarative layouts of
+//! [`crate::plugin`] can't do: importing other file formats, working out how
+//! an entity is drawn, and functions of an entity.
+//!
+//! A plugin points to its module and says which interfaces it implements and
+//! what it may reach:
+//!
+//! ```toml
+//! name = "Zotero"
+//!
+//! [wasm]
+//! module = "zotero.wasm"
+//! importers = ["bib", "ris"]
+//! renderer = true
+//! functions = ["reading_time"]
+//! permissions = { graph = false, network = false, dirs = [] }
+//! ```
+//!
+//! Modules are WASI programs, run by the interpreter in [`interpreter`].
+//! Each call starts the module, gives it one JSON request as its input,
+//! tagged with its `interface`, and reads the JSON response from its output:
+//!
+//! - `import` gets the `file` name and its `text`, and answers the `records`
+//!   to import, each facts by predicate as in [`crate::import::Record`].
+//! - `render` gets the `id` and `facts` of an entity, and answers its
+//!   [`Rendered`] look, which the canvas draws the node with.
+//! - `call` gets the `function`, the `id` and `facts` of an entity if any,
+//!   and `args`, and answers a `value`, a datum or null.
+//!
+//! Modules only get what their permissions grant, which [`wasi`] enforces:
+//! with `graph` requests carry every entity in `graph`, with `network` the
+//! module may fetch URLs, and `dirs` are the directories it may read and
+//! write. Otherwise it sees no files, no network and no environment. A call
+//! running longer than the timeout or using more memory than the config
+//! allows is stopped, and its plugin never writes to the graph itself: what
+//! it answers is recorded by the editor, as any import.
+
+mod decode;
+mod interpreter;
+mod wasi;
+
+use crate::graph;
+use crate::import::Record;
+use crate::legacy::projection::Projection;
+use crate::legacy::storage::Datum;
+use anyhow::{anyhow, bail, Context, Result};
+use decode::Program;
+use interpreter::{Instance, Trap};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wasi::Wasi;
+
+/// How many bytes a module may write or fetch.
+const MAX_OUTPUT: u64 = 64 * 1024 * 1024;
+
+/// How modules are run, the `[wasm]` table of the config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Runtime {
+    /// Seconds a call may take.
+    pub timeout: u64,
+    /// Mebibytes of memory a module may use.
+    pub memory: usize,
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime {
+            timeout: 10,
+            memory: 256,
+        }
+    }
+}
+
+/// The `[wasm]` table of a plugin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Module {
+    /// The path of the module, relative to the plugin's file.
+    pub module: PathBuf,
+    /// The extensions of the files the module imports.
+    #[serde(default)]
+    pub importers: Vec<String>,
+    #[serde(default)]
+    pub renderer: bool,
+    #[serde(default)]
+    pub functions: Vec<String>,
+    #[serde(default)]
+    pub permissions: Permissions,
+}
+
+/// What a module may reach besides its request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Whether requests carry the whole graph.
+    pub graph: bool,
+    pub network: bool,
+    /// The directories the module may read and write, under the same path.
+    pub dirs: Vec<PathBuf>,
+}
+
+/// How a renderer says an entity is drawn. What is missing is drawn as
+/// usual.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rendered {
+    pub label: Option<String>,
+    /// A color such as `#ff8800`.
+    pub color: Option<String>,
+    /// A shape such as `circle`, `square` or `diamond`.
+    pub shape: Option<String>,
+    /// A short text shown on the entity, such as a count.
+    pub badge: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "interface", rename_all = "snake_case")]
+enum Request<'a> {
+    Import {
+        file: &'a str,
+        text: &'a str,
+    },
+    Render {
+        id: Uuid,
+        facts: Record,
+        graph: Option<&'a BTreeMap<Uuid, Record>>,
+    },
+    Call {
+        function: &'a str,
+        id: Option<Uuid>,
+        facts: Option<Record>,
+        args: &'a [Datum],
+        graph: Option<&'a BTreeMap<Uuid, Record>>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct Imported {
+    records: Vec<Record>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Called {
+    value: Option<Datum>,
+}
+
+impl Module {
+    /// Whether the module imports files with the extension of `path`.
+    pub fn imports(&self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        self.importers
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(extension))
+    }
+
+    /// The records the module reads from the file at `path`.
+    pub fn import(&self, runtime: &Runtime, path: &Path) -> Result<Vec<Record>> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        let request = Request::Import {
+            file: &file,
+            text: &text,
+        };
+        let imported: Imported = self
+            .call(runtime, &request)
+            .with_context(|| format!("Failed to import {}", path.display()))?;
+        Ok(imported.records)
+    }
+
+    /// How the module draws the entity `id`.
+    pub fn render(&self, runtime: &Runtime, projection: &Projection, id: Uuid) -> Result<Rendered> {
+        if !self.renderer {
+            bail!("{} doesn't render entities", self.module.display());
+        }
+        let graph = self.graph(projection);
+        let request = Request::Render {
+            id,
+            facts: facts(projection, id).with_context(|| format!("There is no entity {}", id))?,
+            graph: graph.as_ref(),
+        };
+        self.call(runtime, &request)
+    }
+
+    /// The job of drawing the entities `ids` that still exist, to run in
+    /// the background, as it loads the module once for all of them.
+    pub fn render_job(&self, projection: &Projection, ids: &[Uuid]) -> RenderJob {
+        RenderJob {
+            module: self.clone(),
+            requests: ids
+                .iter()
+                .filter_map(|id| Some((*id, facts(projection, *id)?)))
+                .collect(),
+            graph: self.graph(projection),
+        }
+    }
+
+    /// The value of `function` for the entity `id`, if any, and `args`.
+    pub fn function(
+        &self,
+        runtime: &Runtime,
+        projection: &Projection,
+        function: &str,
+        id: Option<Uuid>,
+        args: &[Datum],
+    ) -> Result<Option<Datum>> {
+        if !self.functions.iter().any(|f| f == function) {
+            bail!("{} has no function {}", self.module.display(), function);
+        }
+        let graph = self.graph(projection);
+        let request = Request::Call {
+            function,
+            id,
+            facts: id.and_then(|id| facts(projection, id)),
+            args,
+            graph: graph.as_ref(),
+        };
+        let called: Called = self.call(runtime, &request)?;
+        Ok(called.value)
+    }
+
+    /// The whole graph, if the module may see it.
+    fn graph(&self, projection: &Projection) -> Option<BTreeMap<Uuid, Record>> {
+        self.permissions.graph.then(|| {
+            projection
+                .entities()
+                .filter_map(|(id, _)| Some((*id, facts(projection, *id)?)))
+                .collect()
+        })
+    }
+
+    /// Loads the module, to run it.
+    fn load(&self) -> Result<Program> {
+        let bytes = std::fs::read(&self.module)
+            .with_context(|| format!("Failed to read {}", self.module.display()))?;
+        decode::decode(&bytes).with_context(|| format!("Failed to load {}", self.module.display()))
+    }
+
+    /// Loads and runs the module with `request`.
+    fn call<T: for<'de> Deserialize<'de>>(
+        &self,
+        runtime: &Runtime,
+        request: &Request,
+    ) -> Result<T> {
+        self.run(&self.load()?, runtime, request)
+    }
+
+    /// Runs `program`, the module, with `request` and reads its response.
+    fn run<T: for<'de> Deserialize<'de>>(
+        &self,
+        program: &Program,
+        runtime: &Runtime,
+        request: &Request,
+    ) -> Result<T> {
+        let name = self
+            .module
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let deadline = Instant::now() + Duration::from_secs(runtime.timeout);
+        let input = serde_json::to_vec(request)?;
+        let wasi = Wasi::new(program, &name, input, &self.permissions, deadline)
+            .with_context(|| format!("Failed to start {}", self.module.display()))?;
+        let mut instance = Instance::new(program, wasi, runtime.memory << 20, deadline);
+        let status = match instance.start().and_then(|()| {
+            let start = instance
+                .export("_start")
+                .ok_or_else(|| Trap::Error(anyhow!("It has no _start function")))?;
+            instance.invoke(start, &[])
+        }) {
+            Ok(_) => 0,
+            Err(Trap::Exit(status)) => status,
+            Err(Trap::Timeout) => bail!(
+                "{} took longer than {} seconds",
+                self.module.display(),
+                runtime.timeout
+            ),
+            Err(Trap::Error(error)) => {
+                let stderr = String::from_utf8_lossy(&instance.host.stderr);
+                return Err(error.context(format!(
+                    "{} failed: {}",
+                    self.module.display(),
+                    stderr.trim()
+                )));
+            }
+        };
+        if status != 0 {
+            bail!(
+                "{} failed: {}",
+                self.module.display(),
+                String::from_utf8_lossy(&instance.host.stderr).trim()
+            );
+        }
+        serde_json::from_slice(&instance.host.stdout)
+            .with_context(|| format!("Invalid response from {}", self.module.display()))
+    }
+}
+
+/// Entities for a renderer to draw, see [`Module::render_job`].
+#[derive(Debug, Clone)]
+pub struct RenderJob {
+    module: Module,
+    requests: Vec<(Uuid, Record)>,
+    graph: Option<BTreeMap<Uuid, Record>>,
+}
+
+impl RenderJob {
+    /// How the module draws each entity, failing with the first it can't.
+    pub fn run(self, runtime: &Runtime) -> Result<Vec<(Uuid, Rendered)>> {
+        if !self.module.renderer {
+            bail!("{} doesn't render entities", self.module.module.display());
+        }
+        let program = self.module.load()?;
+        let mut rendered = Vec::with_capacity(self.requests.len());
+        for (id, facts) in self.requests {
+            let request = Request::Render {
+                id,
+                facts,
+                graph: self.graph.as_ref(),
+            };
+            let look = self
+                .module
+                .run(&program, runtime, &request)
+                .with_context(|| format!("Failed to render {}", id))?;
+            rendered.push((id, look));
+        }
+        Ok(rendered)
+    }
+}
+
+impl Rendered {
+    /// Draws `node` as the module says, leaving what it doesn't say or
+    /// says wrongly as it is.
+    pub fn restyle(&self, node: &mut graph::Node) {
+        if let Some(label) = &self.label {
+            node.label.clone_from(label);
+        }
+        if let Some(color) = self.color.as_deref().and_then(|c| c.parse().ok()) {
+            node.style.color = Some(color);
+        }
+        if let Some(shape) = self.shape.as_deref().and_then(|s| s.parse().ok()) {
+            node.style.shape = shape;
+        }
+        if let Some(badge) = &self.badge {
+            node.style.icon = Some(badge.clone());
+        }
+    }
+}
+
+/// The facts of the entity `id`.
+fn facts(projection: &Projection, id: Uuid) -> Option<Record> {
+    let entity = projection.entity(&id)?;
+    Some(
+        entity
+            .facts()
+            .map(|(p, values)| (p.to_string(), values.to_vec()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WASI program writing `answer`, then looping forever if `forever`.
+    fn program(answer: &str, forever: bool) -> Vec<u8> {
+        let section = |id: u8, bytes: &[u8]| {
+            let mut section = vec![id, bytes.len() as u8];
+            section.extend_from_slice(bytes);
+            section
+        };
+        let name = |name: &str| [&[name.len() as u8], name.as_bytes()].concat();
+        // The answer is at 16, and the buffer of fd_write at 0.
+        let mut code = vec![
+            0x00, 0x41, 0x00, 0x41, 0x10, 0x36, 0x02, 0x00, 0x41, 0x04, 0x41,
+        ];
+        code.push(answer.len() as u8);
+        code.extend([0x36, 0x02, 0x00, 0x41, 0x01, 0x41, 0x00, 0x41, 0x01]);
+        code.extend([0x41, 0x08, 0x10, 0x00, 0x1a]);
+        if forever {
+            code.extend([0x03, 0x40, 0x0c, 0x00, 0x0b]);
+        }
+        code.push(0x0b);
+        let mut data = vec![0x01, 0x00, 0x41, 0x10, 0x0b, answer.len() as u8];
+        data.extend_from_slice(answer.as_bytes());
+        [
+            &b"\0asm\x01\0\0\0"[..],
+            &section(
+                1,
+                &[2, 0x60, 4, 0x7f, 0x7f, 0x7f, 0x7f, 1, 0x7f, 0x60, 0, 0],
+            ),
+            &section(
+                2,
+                &[
+                    &[1][..],
+                    &name("wasi_snapshot_preview1"),
+                    &name("fd_write"),
+                    &[0, 0],
+                ]
+                .concat(),
+            ),
+            &section(3, &[1, 1]),
+            &section(5, &[1, 0, 1]),
+            &section(7, &[&[1][..], &name("_start"), &[0, 1]].concat()),
+            &section(10, &[&[1, code.len() as u8][..], &code].concat()),
+            &section(11, &data),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn modules_run_with_only_what_they_are_granted() {
+        let dir = std::env::temp_dir().join(format!("wasm-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let answer = r#"{"records": [{"name": [{"String": "Dune"}]}]}"#;
+        std::fs::write(dir.join("zotero.wasm"), program(answer, false)).unwrap();
+        std::fs::write(dir.join("stuck.wasm"), program(answer, true)).unwrap();
+        std::fs::write(dir.join("library.bib"), "@book{dune}").unwrap();
+        let mut module = Module {
+            module: dir.join("zotero.wasm"),
+            importers: vec!["bib".to_string()],
+            renderer: false,
+            functions: Vec::new(),
+            permissions: Permissions::default(),
+        };
+        let runtime = Runtime::default();
+        let records = module.import(&runtime, &dir.join("library.bib")).unwrap();
+        assert_eq!(records[0]["name"], [Datum::String("Dune".to_string())]);
+        assert!(module.imports(Path::new("library.BIB")));
+        let projection = Projection::new();
+        assert!(module
+            .render(&runtime, &projection, Uuid::new_v4())
+            .is_err());
+
+        // A renderer's answer styles the node, but for what is invalid.
+        let look = r#"{"shape": "diamond", "badge": "3", "color": "orange"}"#;
+        std::fs::write(dir.join("look.wasm"), program(look, false)).unwrap();
+        let mut projection = Projection::new();
+        let id = Uuid::new_v4();
+        projection.apply(&crate::legacy::storage::Action::CreateEntity { id });
+        let renderer = Module {
+            module: dir.join("look.wasm"),
+            renderer: true,
+            ..module.clone()
+        };
+        let job = renderer.render_job(&projection, &[id, Uuid::new_v4()]);
+        let [(rendered_id, rendered)] = &job.run(&runtime).unwrap()[..] else {
+            panic!("expected the one entity");
+        };
+        let mut node = graph::Node {
+            id,
+            label: "Dune".to_string(),
+            x: 0.0,
+            y: 0.0,
+            style: graph::Style::default(),
+            members: 0,
+        };
+        rendered.restyle(&mut node);
+        assert_eq!(*rendered_id, id);
+        assert_eq!(node.style.shape, graph::Shape::Diamond);
+        assert_eq!(node.style.icon.as_deref(), Some("3"));
+        assert_eq!((node.label.as_str(), node.style.color), ("Dune", None));
+
+        // A module that never ends is stopped, as one needing more memory.
+        module.module = dir.join("stuck.wasm");
+        let runtime = Runtime {
+            timeout: 0,
+            ..Runtime::default()
+        };
+        let error = module.import(&runtime, &dir.join("library.bib"));
+        assert!(format!("{:#}", error.unwrap_err()).contains("took longer than 0 seconds"));
+        let runtime = Runtime {
+            memory: 0,
+            ..Runtime::default()
+        };
+        assert!(module.import(&runtime, &dir.join("library.bib")).is_err());
+
+        // Directories it isn't granted don't exist for it.
+        module.permissions.dirs = vec![dir.join("missing")];
+        assert!(module.import(&runtime, &dir.join("library.bib")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,807 @@
+//! Reads the binary format of WebAssembly modules, see
+//! <https://webassembly.github.io/spec/core/binary/>.
+//!
+//! Covers what compilers emit for `wasm32-wasip1` by default: the MVP, sign
+//! extension, saturating conversions, bulk memory, multiple values and the
+//! reference types `call_indirect` needs. Modules using SIMD, threads or
+//! exceptions are refused, as are modules importing anything but functions.
+
+use anyhow::{bail, ensure, Context, Result};
+use std::collections::HashMap;
+
+/// The most locals a function may declare, so a module can't make every
+/// call allocate gigabytes.
+const MAX_LOCALS: u32 = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+    FuncRef,
+    ExternRef,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+#[derive(Debug)]
+pub(super) struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: u32,
+}
+
+#[derive(Debug)]
+pub(super) struct Function {
+    pub ty: u32,
+    /// The locals after the parameters.
+    pub locals: u32,
+    pub code: Vec<Op>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Limits {
+    pub min: u32,
+    pub max: Option<u32>,
+}
+
+#[derive(Debug)]
+pub(super) struct Global {
+    pub mutable: bool,
+    pub init: Vec<Op>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Export {
+    Function(u32),
+    Table,
+    Memory,
+    Global,
+}
+
+#[derive(Debug)]
+pub(super) enum Mode {
+    Passive,
+    Active { index: u32, offset: Vec<Op> },
+    Declarative,
+}
+
+#[derive(Debug)]
+pub(super) struct Element {
+    pub mode: Mode,
+    /// Constant expressions giving a function reference or null.
+    pub items: Vec<Vec<Op>>,
+}
+
+#[derive(Debug)]
+pub(super) struct Data {
+    pub mode: Mode,
+    pub bytes: Vec<u8>,
+}
+
+/// A decoded module.
+#[derive(Debug, Default)]
+pub(super) struct Program {
+    pub types: Vec<FuncType>,
+    /// Imported functions, which come before the defined ones.
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    pub tables: Vec<Limits>,
+    pub memory: Option<Limits>,
+    pub globals: Vec<Global>,
+    pub exports: HashMap<String, Export>,
+    pub start: Option<u32>,
+    pub elements: Vec<Element>,
+    pub data: Vec<Data>,
+}
+
+impl Program {
+    /// The type of the function `index`, imported or defined.
+    pub fn function_type(&self, index: u32) -> Option<&FuncType> {
+        let index = index as usize;
+        let ty = match index.checked_sub(self.imports.len()) {
+            None => self.imports[index].ty,
+            Some(defined) => self.functions.get(defined)?.ty,
+        };
+        self.types.get(ty as usize)
+    }
+
+    pub fn function_count(&self) -> usize {
+        self.imports.len() + self.functions.len()
+    }
+}
+
+/// An instruction, with the targets of its blocks resolved to indices into
+/// the code of its function. Numeric instructions keep their opcode.
+#[derive(Debug, Clone)]
+pub(super) enum Op {
+    Unreachable,
+    Nop,
+    Block {
+        params: u32,
+        results: u32,
+        end: u32,
+    },
+    Loop {
+        params: u32,
+    },
+    If {
+        params: u32,
+        results: u32,
+        otherwise: u32,
+        end: u32,
+    },
+    Else {
+        end: u32,
+    },
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Box<[u32]>, u32),
+    Return,
+    Call(u32),
+    CallIndirect {
+        ty: u32,
+        table: u32,
+    },
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    TableGet(u32),
+    TableSet(u32),
+    /// A load or store opcode and its offset.
+    Load(u8, u32),
+    Store(u8, u32),
+    MemorySize,
+    MemoryGrow,
+    /// A constant of any type, as the bits of its value.
+    Const(u64),
+    /// An opcode from `i32.eqz` (0x45) to `i64.extend32_s` (0xc4).
+    Numeric(u8),
+    /// A saturating conversion, `0xfc` 0 to 7.
+    TruncSat(u8),
+    RefNull,
+    RefIsNull,
+    RefFunc(u32),
+    MemoryInit(u32),
+    DataDrop(u32),
+    MemoryCopy,
+    MemoryFill,
+    TableInit {
+        element: u32,
+        table: u32,
+    },
+    ElemDrop(u32),
+    TableCopy {
+        to: u32,
+        from: u32,
+    },
+    TableGrow(u32),
+    TableSize(u32),
+    TableFill(u32),
+}
+
+/// Decodes the module in `bytes`.
+pub(super) fn decode(bytes: &[u8]) -> Result<Program> {
+    let mut reader = Reader::new(bytes);
+    ensure!(
+        reader.bytes(4)? == b"\0asm",
+        "This is not a WebAssembly module"
+    );
+    ensure!(
+        reader.bytes(4)? == [1, 0, 0, 0],
+        "This WebAssembly module is of an unsupported version, maybe a component"
+    );
+    let mut program = Program::default();
+    let mut function_types = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.u32()? as usize;
+        let mut section = Reader::new(reader.bytes(size)?);
+        match id {
+            0 => {} // Custom sections, such as names.
+            1 => {
+                program.types = section.vec(|r| {
+                    ensure!(r.byte()? == 0x60, "Expected a function type");
+                    Ok(FuncType {
+                        params: r.vec(Reader::val_type)?,
+                        results: r.vec(Reader::val_type)?,
+                    })
+                })?
+            }
+            2 => {
+                for _ in 0..section.u32()? {
+                    let module = section.name()?;
+                    let name = section.name()?;
+                    match section.byte()? {
+                        0 => {
+                            let ty = section.u32()?;
+                            program.imports.push(Import { module, name, ty });
+                        }
+                        kind => bail!(
+                            "The module imports {} {}.{}, only functions can be imported",
+                            ["a function", "a table", "a memory", "a global"]
+                                .get(kind as usize)
+                                .unwrap_or(&"something"),
+                            module,
+                            name
+                        ),
+                    }
+                }
+            }
+            3 => function_types = section.vec(Reader::u32)?,
+            4 => {
+                program.tables = section.vec(|r| {
+                    r.ref_type()?;
+                    r.limits()
+                })?
+            }
+            5 => {
+                let memories = section.vec(Reader::limits)?;
+                ensure!(memories.len() <= 1, "The module has more than one memory");
+                program.memory = memories.into_iter().next();
+            }
+            6 => {
+                program.globals = section.vec(|r| {
+                    r.val_type()?;
+                    let mutable = r.byte()? == 1;
+                    Ok(Global {
+                        mutable,
+                        init: r.expression()?,
+                    })
+                })?
+            }
+            7 => {
+                for _ in 0..section.u32()? {
+                    let name = section.name()?;
+                    let export = match (section.byte()?, section.u32()?) {
+                        (0, index) => Export::Function(index),
+                        (1, _) => Export::Table,
+                        (2, _) => Export::Memory,
+                        (3, _) => Export::Global,
+                        (kind, _) => bail!("Unknown export kind {}", kind),
+                    };
+                    program.exports.insert(name, export);
+                }
+            }
+            8 => program.start = Some(section.u32()?),
+            9 => program.elements = section.vec(Reader::element)?,
+            10 => {
+                let bodies = section.u32()?;
+                ensure!(
+                    bodies as usize == function_types.len(),
+                    "The module has {} function bodies for {} functions",
+                    bodies,
+                    function_types.len()
+                );
+                for ty in &function_types {
+                    let size = section.u32()? as usize;
+                    let mut body = Reader::new(section.bytes(size)?);
+                    let mut locals = 0u32;
+                    for _ in 0..body.u32()? {
+                        locals = locals
+                            .checked_add(body.u32()?)
+                            .filter(|n| *n <= MAX_LOCALS)
+                            .context("A function has too many locals")?;
+                        body.val_type()?;
+                    }
+                    let code = body.code(&program.types)?;
+                    program.functions.push(Function {
+                        ty: *ty,
+                        locals,
+                        code,
+                    });
+                }
+            }
+            11 => program.data = section.vec(Reader::data)?,
+            12 => {} // The data count, only needed to validate in one pass.
+            id => bail!("Unknown section {}", id),
+        }
+        if id != 0 {
+            ensure!(
+                section.is_empty(),
+                "Section {} is longer than its contents",
+                id
+            );
+        }
+    }
+    ensure!(
+        function_types.len() == program.functions.len(),
+        "The module declares functions without bodies"
+    );
+    check(&program)?;
+    Ok(program)
+}
+
+/// Checks the indices the interpreter relies on, so running the module
+/// can't index out of bounds.
+fn check(program: &Program) -> Result<()> {
+    let types = program.types.len() as u32;
+    for import in &program.imports {
+        ensure!(import.ty < types, "{} has no type", import.name);
+    }
+    for function in &program.functions {
+        ensure!(function.ty < types, "A function has no type");
+    }
+    let functions = program.function_count() as u32;
+    if let Some(start) = program.start {
+        ensure!(start < functions, "The start function doesn't exist");
+    }
+    for export in program.exports.values() {
+        if let Export::Function(index) = export {
+            ensure!(*index < functions, "An exported function doesn't exist");
+        }
+    }
+    let globals = program.globals.len() as u32;
+    let tables = program.tables.len() as u32;
+    let elements = program.elements.len() as u32;
+    let data = program.data.len() as u32;
+    let memory = program.memory.is_some();
+    let expressions = program
+        .globals
+        .iter()
+        .map(|g| &g.init)
+        .chain(program.elements.iter().flat_map(|e| &e.items))
+        .chain(program.elements.iter().filter_map(|e| match &e.mode {
+            Mode::Active { offset, .. } => Some(offset),
+            _ => None,
+        }))
+        .chain(program.data.iter().filter_map(|d| match &d.mode {
+            Mode::Active { offset, .. } => Some(offset),
+            _ => None,
+        }));
+    let code = program.functions.iter().map(|f| &f.code);
+    for (op, locals) in expressions
+        .flat_map(|e| e.iter().map(|op| (op, None)))
+        .chain(code.zip(&program.functions).flat_map(|(code, function)| {
+            let params = program.types[function.ty as usize].params.len() as u32;
+            code.iter()
+                .map(move |op| (op, Some(params + function.locals)))
+        }))
+    {
+        let valid = match op {
+            Op::Call(f) | Op::RefFunc(f) => *f < functions,
+            Op::CallIndirect { ty, table } => *ty < types && *table < tables,
+            Op::LocalGet(l) | Op::LocalSet(l) | Op::LocalTee(l) => locals.is_some_and(|n| *l < n),
+            Op::GlobalGet(g) => *g < globals,
+            Op::GlobalSet(g) => *g < globals && program.globals[*g as usize].mutable,
+            Op::TableGet(t)
+            | Op::TableSet(t)
+            | Op::TableGrow(t)
+            | Op::TableSize(t)
+            | Op::TableFill(t) => *t < tables,
+            Op::TableInit { element, table } => *element < elements && *table < tables,
+            Op::ElemDrop(e) => *e < elements,
+            Op::TableCopy { to, from } => *to < tables && *from < tables,
+            Op::MemoryInit(d) => *d < data && memory,
+            Op::DataDrop(d) => *d < data,
+            Op::Load(..) | Op::Store(..) | Op::MemorySize | Op::MemoryGrow => memory,
+            Op::MemoryCopy | Op::MemoryFill => memory,
+            _ => true,
+        };
+        ensure!(
+            valid,
+            "The module refers to something it doesn't have: {:?}",
+            op
+        );
+    }
+    for segment in &program.data {
+        if let Mode::Active { .. } = segment.mode {
+            ensure!(memory, "A data segment has no memory to go to");
+        }
+    }
+    for element in &program.elements {
+        if let Mode::Active { index, .. } = element.mode {
+            ensure!(index < tables, "An element segment has no table to go to");
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let (&byte, rest) = self
+            .bytes
+            .split_first()
+            .context("The module ends unexpectedly")?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        ensure!(n <= self.bytes.len(), "The module ends unexpectedly");
+        let (bytes, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    /// An unsigned LEB128 number of at most `bits` bits.
+    fn unsigned(&mut self, bits: u32) -> Result<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            ensure!(shift < bits, "A number in the module is too long");
+            value |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                ensure!(
+                    bits >= 64 || value >> bits == 0,
+                    "A number in the module is too large"
+                );
+                return Ok(value);
+            }
+        }
+    }
+
+    /// A signed LEB128 number of at most `bits` bits.
+    fn signed(&mut self, bits: u32) -> Result<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            ensure!(shift < bits, "A number in the module is too long");
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(self.unsigned(32)? as u32)
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.bytes(length)?.to_vec()).context("A name in the module isn't UTF-8")
+    }
+
+    fn vec<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let count = self.u32()? as usize;
+        // Every item takes at least a byte, which bounds what is reserved.
+        let mut items = Vec::with_capacity(count.min(self.bytes.len()));
+        for _ in 0..count {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn val_type(&mut self) -> Result<ValType> {
+        Ok(match self.byte()? {
+            0x7f => ValType::I32,
+            0x7e => ValType::I64,
+            0x7d => ValType::F32,
+            0x7c => ValType::F64,
+            0x70 => ValType::FuncRef,
+            0x6f => ValType::ExternRef,
+            0x7b => bail!("The module uses SIMD, which isn't supported"),
+            byte => bail!("Unknown value type {:#x}", byte),
+        })
+    }
+
+    fn ref_type(&mut self) -> Result<ValType> {
+        match self.val_type()? {
+            ty @ (ValType::FuncRef | ValType::ExternRef) => Ok(ty),
+            ty => bail!("Expected a reference type, not {:?}", ty),
+        }
+    }
+
+    fn limits(&mut self) -> Result<Limits> {
+        match self.byte()? {
+            0 => Ok(Limits {
+                min: self.u32()?,
+                max: None,
+            }),
+            1 => Ok(Limits {
+                min: self.u32()?,
+                max: Some(self.u32()?),
+            }),
+            _ => bail!("The module uses shared or 64-bit memory, which isn't supported"),
+        }
+    }
+
+    /// A constant expression, up to its `end`.
+    fn expression(&mut self) -> Result<Vec<Op>> {
+        let mut ops = Vec::new();
+        loop {
+            let op = match self.byte()? {
+                0x0b => return Ok(ops),
+                0x23 => Op::GlobalGet(self.u32()?),
+                0x41 => Op::Const(self.signed(32)? as i32 as u32 as u64),
+                0x42 => Op::Const(self.signed(64)? as u64),
+                0x43 => Op::Const(u32::from_le_bytes(self.bytes(4)?.try_into()?) as u64),
+                0x44 => Op::Const(u64::from_le_bytes(self.bytes(8)?.try_into()?)),
+                0xd0 => {
+                    self.ref_type()?;
+                    Op::RefNull
+                }
+                0xd2 => Op::RefFunc(self.u32()?),
+                // Extended constant expressions.
+                opcode @ (0x6a | 0x6b | 0x6c | 0x7c | 0x7d | 0x7e) => Op::Numeric(opcode),
+                opcode => bail!("Unsupported instruction {:#x} in a constant", opcode),
+            };
+            ops.push(op);
+        }
+    }
+
+    fn element(&mut self) -> Result<Element> {
+        let flags = self.u32()?;
+        ensure!(flags < 8, "Unknown element segment kind {}", flags);
+        let mode = match flags & 3 {
+            0 => Mode::Active {
+                index: 0,
+                offset: self.expression()?,
+            },
+            2 => Mode::Active {
+                index: self.u32()?,
+                offset: self.expression()?,
+            },
+            1 => Mode::Passive,
+            _ => Mode::Declarative,
+        };
+        // Segments other than the first kind say what they hold: an element
+        // kind for function indices, a reference type for expressions.
+        if flags & 3 != 0 {
+            let kind = self.byte()?;
+            ensure!(
+                kind == 0x00 || kind == 0x70 || kind == 0x6f,
+                "Unknown element kind {:#x}",
+                kind
+            );
+        }
+        let items = match flags & 4 {
+            0 => self.vec(|r| Ok(vec![Op::RefFunc(r.u32()?)]))?,
+            _ => self.vec(Reader::expression)?,
+        };
+        Ok(Element { mode, items })
+    }
+
+    fn data(&mut self) -> Result<Data> {
+        let mode = match self.u32()? {
+            0 => Mode::Active {
+                index: 0,
+                offset: self.expression()?,
+            },
+            1 => Mode::Passive,
+            2 => Mode::Active {
+                index: self.u32()?,
+                offset: self.expression()?,
+            },
+            kind => bail!("Unknown data segment kind {}", kind),
+        };
+        let length = self.u32()? as usize;
+        Ok(Data {
+            mode,
+            bytes: self.bytes(length)?.to_vec(),
+        })
+    }
+
+    /// The parameters and results of a block.
+    fn block_type(&mut self, types: &[FuncType]) -> Result<(u32, u32)> {
+        match self.bytes.first() {
+            Some(0x40) => {
+                self.byte()?;
+                Ok((0, 0))
+            }
+            Some(0x7f | 0x7e | 0x7d | 0x7c | 0x70 | 0x6f | 0x7b) => {
+                self.val_type()?;
+                Ok((0, 1))
+            }
+            _ => {
+                let index = self.signed(33)?;
+                let ty = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| types.get(i))
+                    .context("A block has an unknown type")?;
+                Ok((ty.params.len() as u32, ty.results.len() as u32))
+            }
+        }
+    }
+
+    /// The instructions of a function body, up to its last `end`.
+    fn code(&mut self, types: &[FuncType]) -> Result<Vec<Op>> {
+        let mut code = Vec::new();
+        // The blocks, loops and ifs not yet ended.
+        let mut open: Vec<usize> = Vec::new();
+        loop {
+            let at = code.len() as u32;
+            let op = match self.byte()? {
+                0x00 => Op::Unreachable,
+                0x01 => Op::Nop,
+                0x02 => {
+                    let (params, results) = self.block_type(types)?;
+                    open.push(code.len());
+                    Op::Block {
+                        params,
+                        results,
+                        end: 0,
+                    }
+                }
+                0x03 => {
+                    let (params, _) = self.block_type(types)?;
+                    open.push(code.len());
+                    Op::Loop { params }
+                }
+                0x04 => {
+                    let (params, results) = self.block_type(types)?;
+                    open.push(code.len());
+                    Op::If {
+                        params,
+                        results,
+                        otherwise: 0,
+                        end: 0,
+                    }
+                }
+                0x05 => {
+                    let start = *open.last().context("else outside of an if")?;
+                    match &mut code[start] {
+                        Op::If { otherwise, .. } if *otherwise == 0 => *otherwise = at,
+                        _ => bail!("else outside of an if"),
+                    }
+                    // The else is ended with its if.
+                    open.push(code.len());
+                    Op::Else { end: 0 }
+                }
+                0x0b => match open.pop() {
+                    None => {
+                        code.push(Op::End);
+                        return Ok(code);
+                    }
+                    Some(start) => {
+                        if let Op::Else { end } = &mut code[start] {
+                            *end = at;
+                            let start = open.pop().context("else outside of an if")?;
+                            if let Op::If { end, .. } = &mut code[start] {
+                                *end = at;
+                            }
+                        } else {
+                            match &mut code[start] {
+                                Op::Block { end, .. } => *end = at,
+                                Op::If { end, otherwise, .. } => {
+                                    *end = at;
+                                    if *otherwise == 0 {
+                                        *otherwise = at;
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        Op::End
+                    }
+                },
+                0x0c => Op::Br(self.u32()?),
+                0x0d => Op::BrIf(self.u32()?),
+                0x0e => {
+                    let targets = self.vec(Reader::u32)?;
+                    Op::BrTable(targets.into_boxed_slice(), self.u32()?)
+                }
+                0x0f => Op::Return,
+                0x10 => Op::Call(self.u32()?),
+                0x11 => {
+                    let ty = self.u32()?;
+                    Op::CallIndirect {
+                        ty,
+                        table: self.u32()?,
+                    }
+                }
+                0x1a => Op::Drop,
+                0x1b => Op::Select,
+                0x1c => {
+                    self.vec(Reader::val_type)?;
+                    Op::Select
+                }
+                0x20 => Op::LocalGet(self.u32()?),
+                0x21 => Op::LocalSet(self.u32()?),
+                0x22 => Op::LocalTee(self.u32()?),
+                0x23 => Op::GlobalGet(self.u32()?),
+                0x24 => Op::GlobalSet(self.u32()?),
+                0x25 => Op::TableGet(self.u32()?),
+                0x26 => Op::TableSet(self.u32()?),
+                opcode @ 0x28..=0x3e => {
+                    let (memory, offset) = self.memarg()?;
+                    ensure!(memory == 0, "The module uses several memories");
+                    match opcode {
+                        0x28..=0x35 => Op::Load(opcode, offset),
+                        _ => Op::Store(opcode, offset),
+                    }
+                }
+                0x3f => {
+                    self.byte()?;
+                    Op::MemorySize
+                }
+                0x40 => {
+                    self.byte()?;
+                    Op::MemoryGrow
+                }
+                0x41 => Op::Const(self.signed(32)? as i32 as u32 as u64),
+                0x42 => Op::Const(self.signed(64)? as u64),
+                0x43 => Op::Const(u32::from_le_bytes(self.bytes(4)?.try_into()?) as u64),
+                0x44 => Op::Const(u64::from_le_bytes(self.bytes(8)?.try_into()?)),
+                opcode @ 0x45..=0xc4 => Op::Numeric(opcode),
+                0xd0 => {
+                    self.ref_type()?;
+                    Op::RefNull
+                }
+                0xd1 => Op::RefIsNull,
+                0xd2 => Op::RefFunc(self.u32()?),
+                0xfc => match self.u32()? {
+                    n @ 0..=7 => Op::TruncSat(n as u8),
+                    8 => {
+                        let segment = self.u32()?;
+                        self.byte()?;
+                        Op::MemoryInit(segment)
+                    }
+                    9 => Op::DataDrop(self.u32()?),
+                    10 => {
+                        self.bytes(2)?;
+                        Op::MemoryCopy
+                    }
+                    11 => {
+                        self.byte()?;
+                        Op::MemoryFill
+                    }
+                    12 => {
+                        let element = self.u32()?;
+                        Op::TableInit {
+                            element,
+                            table: self.u32()?,
+                        }
+                    }
+                    13 => Op::ElemDrop(self.u32()?),
+                    14 => {
+                        let to = self.u32()?;
+                        Op::TableCopy {
+                            to,
+                            from: self.u32()?,
+                        }
+                    }
+                    15 => Op::TableGrow(self.u32()?),
+                    16 => Op::TableSize(self.u32()?),
+                    17 => Op::TableFill(self.u32()?),
+                    n => bail!("Unsupported instruction 0xfc {}", n),
+                },
+                0xfd => bail!("The module uses SIMD, which isn't supported"),
+                0xfe => bail!("The module uses threads, which aren't supported"),
+                opcode => bail!("Unsupported instruction {:#x}", opcode),
+            };
+            code.push(op);
+        }
+    }
+
+    /// The alignment, ignored, and the memory and offset of a memory access.
+    fn memarg(&mut self) -> Result<(u32, u32)> {
+        let align = self.u32()?;
+        // Bit 6 of the alignment says a memory index follows.
+        let memory = match align & 0x40 {
+            0 => 0,
+            _ => self.u32()?,
+        };
+        Ok((memory, self.u32()?))
+    }
+}
@@ -0,0 +1,1007 @@
+//! Runs decoded modules, see
+//! <https://webassembly.github.io/spec/core/exec/>.
+//!
+//! Values are kept as the bits of their numbers, in one stack shared by all
+//! calls, and calls don't recurse on the stack of the host, so a module
+//! can't overflow it. Modules aren't validated before they run: code that
+//! wouldn't pass validation traps where it goes wrong instead.
+
+use super::decode::{Element, Export, Mode, Op, Program};
+use anyhow::anyhow;
+use std::time::Instant;
+
+/// The size of a page of memory.
+pub(super) const PAGE: usize = 64 * 1024;
+
+/// How deep calls may nest.
+const MAX_DEPTH: usize = 10_000;
+
+/// How many values and locals all calls may hold together.
+const MAX_VALUES: usize = 8 * 1024 * 1024;
+
+/// How many instructions run between checks of the deadline.
+const SLICE: u32 = 1 << 16;
+
+/// The reference no function is at.
+const NULL: u64 = u64::MAX;
+
+/// Why a module stopped before it returned.
+#[derive(Debug)]
+pub(super) enum Trap {
+    /// The module exited with a status.
+    Exit(u32),
+    /// The module ran past its deadline.
+    Timeout,
+    Error(anyhow::Error),
+}
+
+impl From<anyhow::Error> for Trap {
+    fn from(error: anyhow::Error) -> Trap {
+        Trap::Error(error)
+    }
+}
+
+fn trap(message: &str) -> Trap {
+    Trap::Error(anyhow!("The module trapped: {}", message))
+}
+
+/// The functions a module imports, called with their arguments and the
+/// memory of the module.
+pub(super) trait Host {
+    fn call(
+        &mut self,
+        import: usize,
+        args: &[u64],
+        memory: &mut Memory,
+    ) -> Result<Option<u64>, Trap>;
+}
+
+/// The linear memory of a module.
+#[derive(Debug, Default)]
+pub(super) struct Memory {
+    bytes: Vec<u8>,
+    /// The most pages it may grow to.
+    max: usize,
+}
+
+impl Memory {
+    /// The bytes at `address`, if they are all in the memory.
+    pub fn get(&self, address: u32, length: u32) -> Option<&[u8]> {
+        let start = address as usize;
+        self.bytes.get(start..start.checked_add(length as usize)?)
+    }
+
+    pub fn get_mut(&mut self, address: u32, length: u32) -> Option<&mut [u8]> {
+        let start = address as usize;
+        self.bytes
+            .get_mut(start..start.checked_add(length as usize)?)
+    }
+
+    pub fn write(&mut self, address: u32, bytes: &[u8]) -> Option<()> {
+        self.get_mut(address, bytes.len() as u32)?
+            .copy_from_slice(bytes);
+        Some(())
+    }
+
+    fn pages(&self) -> usize {
+        self.bytes.len() / PAGE
+    }
+
+    /// The `N` bytes at `address` plus `offset`.
+    fn load<const N: usize>(&self, address: u64, offset: u32) -> Result<[u8; N], Trap> {
+        let start = (address as u32 as usize) + offset as usize;
+        self.bytes
+            .get(start..start + N)
+            .map(|b| b.try_into().expect("the length is N"))
+            .ok_or_else(|| trap("out of bounds memory access"))
+    }
+
+    fn store(&mut self, address: u64, offset: u32, bytes: &[u8]) -> Result<(), Trap> {
+        let start = (address as u32 as usize) + offset as usize;
+        self.bytes
+            .get_mut(start..start + bytes.len())
+            .ok_or_else(|| trap("out of bounds memory access"))?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// The range of `length` bytes at `address`, for bulk instructions.
+    fn range(&self, address: u64, length: u64) -> Result<std::ops::Range<usize>, Trap> {
+        let (start, length) = (address as u32 as usize, length as u32 as usize);
+        if start + length > self.bytes.len() {
+            return Err(trap("out of bounds memory access"));
+        }
+        Ok(start..start + length)
+    }
+}
+
+/// An instantiated module.
+pub(super) struct Instance<'a, H> {
+    program: &'a Program,
+    pub host: H,
+    pub memory: Memory,
+    globals: Vec<u64>,
+    tables: Vec<Vec<u64>>,
+    /// The element and data segments not yet dropped.
+    elements: Vec<Option<Vec<u64>>>,
+    data: Vec<bool>,
+    stack: Vec<u64>,
+    locals: Vec<u64>,
+    labels: Vec<Label>,
+    deadline: Instant,
+}
+
+/// Where a branch out of a block goes.
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    /// The height of the stack below the block.
+    height: usize,
+    /// How many values a branch carries.
+    arity: usize,
+    /// Where a branch continues: the `end` of a block, the start of a loop.
+    target: usize,
+    looping: bool,
+}
+
+/// A call in progress.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    function: usize,
+    pc: usize,
+    /// Where its locals and labels start, and the height of the stack below
+    /// its results.
+    locals: usize,
+    labels: usize,
+    height: usize,
+    results: usize,
+}
+
+impl<'a, H: Host> Instance<'a, H> {
+    /// An instance of `program` with memory of at most `max_memory` bytes,
+    /// to be started.
+    pub fn new(program: &'a Program, host: H, max_memory: usize, deadline: Instant) -> Self {
+        Instance {
+            program,
+            host,
+            memory: Memory {
+                bytes: Vec::new(),
+                max: max_memory / PAGE,
+            },
+            globals: Vec::new(),
+            tables: Vec::new(),
+            elements: Vec::new(),
+            data: vec![true; program.data.len()],
+            stack: Vec::new(),
+            locals: Vec::new(),
+            labels: Vec::new(),
+            deadline,
+        }
+    }
+
+    /// Sets up the memory, globals and tables, and runs the start function.
+    pub fn start(&mut self) -> Result<(), Trap> {
+        let program = self.program;
+        if let Some(limits) = &program.memory {
+            if let Some(max) = limits.max {
+                self.memory.max = self.memory.max.min(max as usize);
+            }
+            if limits.min as usize > self.memory.max {
+                return Err(Trap::Error(anyhow!(
+                    "It needs {} MiB of memory, more than the {} MiB it may use",
+                    (limits.min as usize * PAGE) >> 20,
+                    (self.memory.max * PAGE) >> 20
+                )));
+            }
+            self.memory.bytes = vec![0; limits.min as usize * PAGE];
+        }
+        self.tables = program
+            .tables
+            .iter()
+            .map(|t| vec![NULL; t.min.min(MAX_VALUES as u32) as usize])
+            .collect();
+        for global in &program.globals {
+            let value = self.constant(&global.init)?;
+            self.globals.push(value);
+        }
+        for element in &program.elements {
+            let items = element
+                .items
+                .iter()
+                .map(|item| self.constant(item))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.elements.push(Some(items));
+        }
+        for (i, element) in program.elements.iter().enumerate() {
+            self.initialize_table(i, element)?;
+        }
+        for (i, segment) in program.data.iter().enumerate() {
+            if let Mode::Active { offset, .. } = &segment.mode {
+                let offset = self.constant(offset)?;
+                let range = self.memory.range(offset, segment.bytes.len() as u64)?;
+                self.memory.bytes[range].copy_from_slice(&segment.bytes);
+                self.data[i] = false;
+            }
+        }
+        if let Some(start) = program.start {
+            self.invoke(start as usize, &[])?;
+        }
+        Ok(())
+    }
+
+    fn initialize_table(&mut self, index: usize, element: &Element) -> Result<(), Trap> {
+        match &element.mode {
+            Mode::Passive => {}
+            Mode::Declarative => self.elements[index] = None,
+            Mode::Active {
+                index: table,
+                offset,
+            } => {
+                let offset = self.constant(offset)? as u32 as usize;
+                let items = self.elements[index].take().unwrap_or_default();
+                let table = &mut self.tables[*table as usize];
+                table
+                    .get_mut(offset..offset + items.len())
+                    .ok_or_else(|| trap("out of bounds table access"))?
+                    .copy_from_slice(&items);
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of a constant expression.
+    fn constant(&self, ops: &[Op]) -> Result<u64, Trap> {
+        let mut stack: Vec<u64> = Vec::new();
+        for op in ops {
+            let value = match op {
+                Op::Const(value) => *value,
+                Op::GlobalGet(g) => *self
+                    .globals
+                    .get(*g as usize)
+                    .ok_or_else(|| trap("a global refers to a later one"))?,
+                Op::RefNull => NULL,
+                Op::RefFunc(f) => *f as u64,
+                Op::Numeric(opcode) => {
+                    let (b, a) = (stack.pop(), stack.pop());
+                    let (Some(a), Some(b)) = (a, b) else {
+                        return Err(trap("invalid constant"));
+                    };
+                    match opcode {
+                        0x6a => (a as u32).wrapping_add(b as u32) as u64,
+                        0x6b => (a as u32).wrapping_sub(b as u32) as u64,
+                        0x6c => (a as u32).wrapping_mul(b as u32) as u64,
+                        0x7c => a.wrapping_add(b),
+                        0x7d => a.wrapping_sub(b),
+                        _ => a.wrapping_mul(b),
+                    }
+                }
+                _ => return Err(trap("invalid constant")),
+            };
+            stack.push(value);
+        }
+        stack.pop().ok_or_else(|| trap("invalid constant"))
+    }
+
+    /// The function exported as `name`.
+    pub fn export(&self, name: &str) -> Option<usize> {
+        match self.program.exports.get(name)? {
+            Export::Function(index) => Some(*index as usize),
+            _ => None,
+        }
+    }
+
+    /// Calls the function `index` with `args`, returning its results.
+    pub fn invoke(&mut self, index: usize, args: &[u64]) -> Result<Vec<u64>, Trap> {
+        self.stack.clear();
+        self.locals.clear();
+        self.labels.clear();
+        self.stack.extend_from_slice(args);
+        let mut frames = Vec::new();
+        let Some(mut frame) = self.enter(index, &mut frames)? else {
+            return Ok(std::mem::take(&mut self.stack));
+        };
+        let mut budget = SLICE;
+        let program = self.program;
+        loop {
+            budget -= 1;
+            if budget == 0 {
+                budget = SLICE;
+                if Instant::now() > self.deadline {
+                    return Err(Trap::Timeout);
+                }
+                if self.stack.len() + self.locals.len() > MAX_VALUES {
+                    return Err(trap("stack overflow"));
+                }
+            }
+            let code = &program.functions[frame.function].code;
+            let op = code.get(frame.pc).ok_or_else(|| trap("ran past the end"))?;
+            frame.pc += 1;
+            match op {
+                Op::Unreachable => return Err(trap("unreachable")),
+                Op::Nop => {}
+                Op::Block {
+                    params,
+                    results,
+                    end,
+                } => self.push_label(*params, *results as usize, *end as usize, false)?,
+                Op::Loop { params } => {
+                    self.push_label(*params, *params as usize, frame.pc, true)?;
+                }
+                Op::If {
+                    params,
+                    results,
+                    otherwise,
+                    end,
+                } => {
+                    let condition = self.pop()? as u32;
+                    self.push_label(*params, *results as usize, *end as usize, false)?;
+                    if condition == 0 {
+                        // Into the else, or onto the end if there is none.
+                        frame.pc = match otherwise == end {
+                            true => *end as usize,
+                            false => *otherwise as usize + 1,
+                        };
+                    }
+                }
+                // The end of the then branch.
+                Op::Else { end } => frame.pc = *end as usize,
+                Op::End => {
+                    if self.labels.len() > frame.labels {
+                        self.labels.pop();
+                    } else {
+                        match self.leave(&frame, &mut frames)? {
+                            Some(caller) => frame = caller,
+                            None => break,
+                        }
+                    }
+                }
+                Op::Br(depth) => {
+                    if !self.branch(*depth as usize, &mut frame)? {
+                        match self.leave(&frame, &mut frames)? {
+                            Some(caller) => frame = caller,
+                            None => break,
+                        }
+                    }
+                }
+                Op::BrIf(depth) => {
+                    if self.pop()? as u32 != 0 && !self.branch(*depth as usize, &mut frame)? {
+                        match self.leave(&frame, &mut frames)? {
+                            Some(caller) => frame = caller,
+                            None => break,
+                        }
+                    }
+                }
+                Op::BrTable(targets, default) => {
+                    let i = self.pop()? as u32 as usize;
+                    let depth = *targets.get(i).unwrap_or(default) as usize;
+                    if !self.branch(depth, &mut frame)? {
+                        match self.leave(&frame, &mut frames)? {
+                            Some(caller) => frame = caller,
+                            None => break,
+                        }
+                    }
+                }
+                Op::Return => match self.leave(&frame, &mut frames)? {
+                    Some(caller) => frame = caller,
+                    None => break,
+                },
+                Op::Call(f) => {
+                    frames.push(frame);
+                    if let Some(callee) = self.enter(*f as usize, &mut frames)? {
+                        frame = callee;
+                    } else {
+                        frame = frames.pop().expect("the caller was pushed");
+                    }
+                }
+                Op::CallIndirect { ty, table } => {
+                    let i = self.pop()? as u32 as usize;
+                    let f = *self.tables[*table as usize]
+                        .get(i)
+                        .ok_or_else(|| trap("undefined element"))?;
+                    if f == NULL {
+                        return Err(trap("uninitialized element"));
+                    }
+                    let expected = &program.types[*ty as usize];
+                    if program.function_type(f as u32) != Some(expected) {
+                        return Err(trap("indirect call type mismatch"));
+                    }
+                    frames.push(frame);
+                    if let Some(callee) = self.enter(f as usize, &mut frames)? {
+                        frame = callee;
+                    } else {
+                        frame = frames.pop().expect("the caller was pushed");
+                    }
+                }
+                Op::Drop => {
+                    self.pop()?;
+                }
+                Op::Select => {
+                    let condition = self.pop()? as u32;
+                    let b = self.pop()?;
+                    let a = self.pop()?;
+                    self.stack.push(if condition != 0 { a } else { b });
+                }
+                Op::LocalGet(l) => {
+                    let value = self.locals[frame.locals + *l as usize];
+                    self.stack.push(value);
+                }
+                Op::LocalSet(l) => {
+                    let value = self.pop()?;
+                    self.locals[frame.locals + *l as usize] = value;
+                }
+                Op::LocalTee(l) => {
+                    let value = *self.stack.last().ok_or_else(|| trap("stack underflow"))?;
+                    self.locals[frame.locals + *l as usize] = value;
+                }
+                Op::GlobalGet(g) => self.stack.push(self.globals[*g as usize]),
+                Op::GlobalSet(g) => self.globals[*g as usize] = self.pop()?,
+                Op::TableGet(t) => {
+                    let i = self.pop()? as u32 as usize;
+                    let value = *self.tables[*t as usize]
+                        .get(i)
+                        .ok_or_else(|| trap("out of bounds table access"))?;
+                    self.stack.push(value);
+                }
+                Op::TableSet(t) => {
+                    let value = self.pop()?;
+                    let i = self.pop()? as u32 as usize;
+                    *self.tables[*t as usize]
+                        .get_mut(i)
+                        .ok_or_else(|| trap("out of bounds table access"))? = value;
+                }
+                Op::Load(opcode, offset) => {
+                    let address = self.pop()?;
+                    let value = self.load(*opcode, address, *offset)?;
+                    self.stack.push(value);
+                }
+                Op::Store(opcode, offset) => {
+                    let value = self.pop()?;
+                    let address = self.pop()?;
+                    self.store(*opcode, address, *offset, value)?;
+                }
+                Op::MemorySize => self.stack.push(self.memory.pages() as u64),
+                Op::MemoryGrow => {
+                    let delta = self.pop()? as u32 as usize;
+                    let pages = self.memory.pages();
+                    if pages + delta > self.memory.max {
+                        self.stack.push(u32::MAX as u64);
+                    } else {
+                        self.memory.bytes.resize((pages + delta) * PAGE, 0);
+                        self.stack.push(pages as u64);
+                    }
+                }
+                Op::Const(value) => self.stack.push(*value),
+                Op::Numeric(opcode) => self.numeric(*opcode)?,
+                Op::TruncSat(kind) => {
+                    let value = self.pop()?;
+                    let float = match kind {
+                        0 | 1 | 4 | 5 => f32::from_bits(value as u32) as f64,
+                        _ => f64::from_bits(value),
+                    };
+                    // Conversions with `as` saturate, as these do.
+                    self.stack.push(match kind {
+                        0 | 2 => float as i32 as u32 as u64,
+                        1 | 3 => float as u32 as u64,
+                        4 | 6 => float as i64 as u64,
+                        _ => float as u64,
+                    });
+                }
+                Op::RefNull => self.stack.push(NULL),
+                Op::RefIsNull => {
+                    let value = self.pop()?;
+                    self.stack.push((value == NULL) as u64);
+                }
+                Op::RefFunc(f) => self.stack.push(*f as u64),
+                Op::MemoryInit(segment) => {
+                    let length = self.pop()?;
+                    let offset = self.pop()? as u32 as usize;
+                    let address = self.pop()?;
+                    let bytes: &[u8] = match self.data[*segment as usize] {
+                        true => &program.data[*segment as usize].bytes,
+                        false => &[],
+                    };
+                    let source = bytes
+                        .get(offset..offset + length as u32 as usize)
+                        .ok_or_else(|| trap("out of bounds memory access"))?;
+                    let range = self.memory.range(address, length)?;
+                    self.memory.bytes[range].copy_from_slice(source);
+                }
+                Op::DataDrop(segment) => self.data[*segment as usize] = false,
+                Op::MemoryCopy => {
+                    let length = self.pop()?;
+                    let source = self.pop()?;
+                    let destination = self.pop()?;
+                    let from = self.memory.range(source, length)?;
+                    let to = self.memory.range(destination, length)?;
+                    self.memory.bytes.copy_within(from, to.start);
+                }
+                Op::MemoryFill => {
+                    let length = self.pop()?;
+                    let value = self.pop()? as u8;
+                    let address = self.pop()?;
+                    let range = self.memory.range(address, length)?;
+                    self.memory.bytes[range].fill(value);
+                }
+                Op::TableInit { element, table } => {
+                    let length = self.pop()? as u32 as usize;
+                    let offset = self.pop()? as u32 as usize;
+                    let index = self.pop()? as u32 as usize;
+                    let items = self.elements[*element as usize].as_deref().unwrap_or(&[]);
+                    let source = items
+                        .get(offset..offset + length)
+                        .ok_or_else(|| trap("out of bounds table access"))?;
+                    self.tables[*table as usize]
+                        .get_mut(index..index + length)
+                        .ok_or_else(|| trap("out of bounds table access"))?
+                        .copy_from_slice(source);
+                }
+                Op::ElemDrop(element) => self.elements[*element as usize] = None,
+                Op::TableCopy { to, from } => {
+                    let length = self.pop()? as u32 as usize;
+                    let source = self.pop()? as u32 as usize;
+                    let destination = self.pop()? as u32 as usize;
+                    let items = self.tables[*from as usize]
+                        .get(source..source + length)
+                        .ok_or_else(|| trap("out of bounds table access"))?
+                        .to_vec();
+                    self.tables[*to as usize]
+                        .get_mut(destination..destination + length)
+                        .ok_or_else(|| trap("out of bounds table access"))?
+                        .copy_from_slice(&items);
+                }
+                Op::TableGrow(t) => {
+                    let delta = self.pop()? as u32 as usize;
+                    let value = self.pop()?;
+                    let table = &mut self.tables[*t as usize];
+                    let size = table.len();
+                    let max = program.tables[*t as usize]
+                        .max
+                        .map_or(MAX_VALUES, |m| (m as usize).min(MAX_VALUES));
+                    if size + delta > max {
+                        self.stack.push(u32::MAX as u64);
+                    } else {
+                        table.resize(size + delta, value);
+                        self.stack.push(size as u64);
+                    }
+                }
+                Op::TableSize(t) => self.stack.push(self.tables[*t as usize].len() as u64),
+                Op::TableFill(t) => {
+                    let length = self.pop()? as u32 as usize;
+                    let value = self.pop()?;
+                    let index = self.pop()? as u32 as usize;
+                    self.tables[*t as usize]
+                        .get_mut(index..index + length)
+                        .ok_or_else(|| trap("out of bounds table access"))?
+                        .fill(value);
+                }
+            }
+        }
+        Ok(std::mem::take(&mut self.stack))
+    }
+
+    fn pop(&mut self) -> Result<u64, Trap> {
+        self.stack.pop().ok_or_else(|| trap("stack underflow"))
+    }
+
+    fn push_label(
+        &mut self,
+        params: u32,
+        arity: usize,
+        target: usize,
+        looping: bool,
+    ) -> Result<(), Trap> {
+        let height = self
+            .stack
+            .len()
+            .checked_sub(params as usize)
+            .ok_or_else(|| trap("stack underflow"))?;
+        self.labels.push(Label {
+            height,
+            arity,
+            target,
+            looping,
+        });
+        Ok(())
+    }
+
+    /// Keeps the top `arity` values of the stack and drops those down to
+    /// `height`.
+    fn unwind(&mut self, height: usize, arity: usize) -> Result<(), Trap> {
+        let top = self
+            .stack
+            .len()
+            .checked_sub(arity)
+            .filter(|top| *top >= height)
+            .ok_or_else(|| trap("stack underflow"))?;
+        self.stack.copy_within(top.., height);
+        self.stack.truncate(height + arity);
+        Ok(())
+    }
+
+    /// Branches out of `depth` blocks, or returns false if that leaves the
+    /// function.
+    fn branch(&mut self, depth: usize, frame: &mut Frame) -> Result<bool, Trap> {
+        let Some(index) = (self.labels.len() - frame.labels)
+            .checked_sub(depth + 1)
+            .map(|i| frame.labels + i)
+        else {
+            return Ok(false);
+        };
+        let label = self.labels[index];
+        self.unwind(label.height, label.arity)?;
+        if label.looping {
+            self.labels.truncate(index + 1);
+            frame.pc = label.target;
+        } else {
+            // Onto the end, past which the block is left.
+            self.labels.truncate(index);
+            frame.pc = label.target + 1;
+        }
+        Ok(true)
+    }
+
+    /// Calls the function `index` with its arguments on the stack. Imported
+    /// functions are called at once, others give the frame to run.
+    fn enter(&mut self, index: usize, frames: &mut Vec<Frame>) -> Result<Option<Frame>, Trap> {
+        let program = self.program;
+        let ty = program
+            .function_type(index as u32)
+            .ok_or_else(|| trap("call to an unknown function"))?;
+        let params = ty.params.len();
+        let height = self
+            .stack
+            .len()
+            .checked_sub(params)
+            .ok_or_else(|| trap("stack underflow"))?;
+        let Some(function) = index
+            .checked_sub(program.imports.len())
+            .map(|i| &program.functions[i])
+        else {
+            let result = self
+                .host
+                .call(index, &self.stack[height..], &mut self.memory)?;
+            self.stack.truncate(height);
+            self.stack.extend(result);
+            return Ok(None);
+        };
+        if frames.len() >= MAX_DEPTH
+            || self.stack.len() + self.locals.len() + function.locals as usize > MAX_VALUES
+        {
+            return Err(trap("call stack exhausted"));
+        }
+        let locals = self.locals.len();
+        self.locals.extend(self.stack.drain(height..));
+        self.locals
+            .resize(self.locals.len() + function.locals as usize, 0);
+        Ok(Some(Frame {
+            function: index - program.imports.len(),
+            pc: 0,
+            locals,
+            labels: self.labels.len(),
+            height,
+            results: ty.results.len(),
+        }))
+    }
+
+    /// Returns from `frame`, giving the caller's frame if any.
+    fn leave(&mut self, frame: &Frame, frames: &mut Vec<Frame>) -> Result<Option<Frame>, Trap> {
+        self.unwind(frame.height, frame.results)?;
+        self.locals.truncate(frame.locals);
+        self.labels.truncate(frame.labels);
+        Ok(frames.pop())
+    }
+
+    fn load(&self, opcode: u8, address: u64, offset: u32) -> Result<u64, Trap> {
+        let memory = &self.memory;
+        Ok(match opcode {
+            0x28 | 0x2a => u32::from_le_bytes(memory.load(address, offset)?) as u64,
+            0x29 | 0x2b => u64::from_le_bytes(memory.load(address, offset)?),
+            0x2c => i8::from_le_bytes(memory.load(address, offset)?) as u32 as u64,
+            0x2d | 0x31 => u8::from_le_bytes(memory.load(address, offset)?) as u64,
+            0x2e => i16::from_le_bytes(memory.load(address, offset)?) as u32 as u64,
+            0x2f | 0x33 => u16::from_le_bytes(memory.load(address, offset)?) as u64,
+            0x30 => i8::from_le_bytes(memory.load(address, offset)?) as u64,
+            0x32 => i16::from_le_bytes(memory.load(address, offset)?) as u64,
+            0x34 => i32::from_le_bytes(memory.load(address, offset)?) as u64,
+            _ => u32::from_le_bytes(memory.load(address, offset)?) as u64,
+        })
+    }
+
+    fn store(&mut self, opcode: u8, address: u64, offset: u32, value: u64) -> Result<(), Trap> {
+        let bytes = value.to_le_bytes();
+        let length = match opcode {
+            0x36 | 0x38 | 0x3e => 4,
+            0x37 | 0x39 => 8,
+            0x3a | 0x3c => 1,
+            _ => 2,
+        };
+        self.memory.store(address, offset, &bytes[..length])
+    }
+
+    fn numeric(&mut self, opcode: u8) -> Result<(), Trap> {
+        let value = match opcode {
+            // Tests and conversions, with one operand.
+            0x45 | 0x50 | 0x67..=0x69 | 0x79..=0x7b | 0x8b..=0x91 | 0x99..=0x9f | 0xa7.. => {
+                let a = self.pop()?;
+                unary(opcode, a)?
+            }
+            _ => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                binary(opcode, a, b)?
+            }
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+}
+
+fn f32_(bits: u64) -> f32 {
+    f32::from_bits(bits as u32)
+}
+
+fn f64_(bits: u64) -> f64 {
+    f64::from_bits(bits)
+}
+
+fn from_f32(value: f32) -> u64 {
+    value.to_bits() as u64
+}
+
+fn from_f64(value: f64) -> u64 {
+    value.to_bits()
+}
+
+/// The lesser of `a` and `b`, NaN if either is. Floats of both widths go
+/// through this, as `f32` converts to `f64` and back exactly.
+fn min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        // Of zeros of both signs, the negative one.
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+fn max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
+
+/// Truncates `value` towards zero, trapping unless the result is in
+/// `low..high`, excluding the bounds.
+fn truncate(value: f64, low: f64, high: f64) -> Result<f64, Trap> {
+    if value.is_nan() {
+        return Err(trap("invalid conversion to integer"));
+    }
+    let value = value.trunc();
+    if value <= low || value >= high {
+        return Err(trap("integer overflow"));
+    }
+    Ok(value)
+}
+
+fn unary(opcode: u8, a: u64) -> Result<u64, Trap> {
+    let (x, y) = (a as u32, a);
+    Ok(match opcode {
+        0x45 => (x == 0) as u64,
+        0x50 => (y == 0) as u64,
+        0x67 => x.leading_zeros() as u64,
+        0x68 => x.trailing_zeros() as u64,
+        0x69 => x.count_ones() as u64,
+        0x79 => y.leading_zeros() as u64,
+        0x7a => y.trailing_zeros() as u64,
+        0x7b => y.count_ones() as u64,
+        0x8b => from_f32(f32_(a).abs()),
+        0x8c => from_f32(-f32_(a)),
+        0x8d => from_f32(f32_(a).ceil()),
+        0x8e => from_f32(f32_(a).floor()),
+        0x8f => from_f32(f32_(a).trunc()),
+        0x90 => from_f32(f32_(a).round_ties_even()),
+        0x91 => from_f32(f32_(a).sqrt()),
+        0x99 => from_f64(f64_(a).abs()),
+        0x9a => from_f64(-f64_(a)),
+        0x9b => from_f64(f64_(a).ceil()),
+        0x9c => from_f64(f64_(a).floor()),
+        0x9d => from_f64(f64_(a).trunc()),
+        0x9e => from_f64(f64_(a).round_ties_even()),
+        0x9f => from_f64(f64_(a).sqrt()),
+        0xa7 => x as u64,
+        0xa8 => truncate(f32_(a) as f64, -2147483649.0, 2147483648.0)? as i32 as u32 as u64,
+        0xa9 => truncate(f32_(a) as f64, -1.0, 4294967296.0)? as u32 as u64,
+        0xaa => truncate(f64_(a), -2147483649.0, 2147483648.0)? as i32 as u32 as u64,
+        0xab => truncate(f64_(a), -1.0, 4294967296.0)? as u32 as u64,
+        0xac => x as i32 as i64 as u64,
+        0xad => x as u64,
+        0xae => truncate(
+            f32_(a) as f64,
+            -9223372036854777856.0,
+            9223372036854775808.0,
+        )? as i64 as u64,
+        0xaf => truncate(f32_(a) as f64, -1.0, 18446744073709551616.0)? as u64,
+        0xb0 => truncate(f64_(a), -9223372036854777856.0, 9223372036854775808.0)? as i64 as u64,
+        0xb1 => truncate(f64_(a), -1.0, 18446744073709551616.0)? as u64,
+        0xb2 => from_f32(x as i32 as f32),
+        0xb3 => from_f32(x as f32),
+        0xb4 => from_f32(y as i64 as f32),
+        0xb5 => from_f32(y as f32),
+        0xb6 => from_f32(f64_(a) as f32),
+        0xb7 => from_f64(x as i32 as f64),
+        0xb8 => from_f64(x as f64),
+        0xb9 => from_f64(y as i64 as f64),
+        0xba => from_f64(y as f64),
+        0xbb => from_f64(f32_(a) as f64),
+        // Reinterpretations keep the bits.
+        0xbc => x as u64,
+        0xbd..=0xbf => a,
+        0xc0 => x as i8 as i32 as u32 as u64,
+        0xc1 => x as i16 as i32 as u32 as u64,
+        0xc2 => y as i8 as i64 as u64,
+        0xc3 => y as i16 as i64 as u64,
+        _ => y as i32 as i64 as u64,
+    })
+}
+
+fn binary(opcode: u8, a: u64, b: u64) -> Result<u64, Trap> {
+    let (x, y) = (a as u32, b as u32);
+    let (fa, fb) = (f32_(a), f32_(b));
+    let (da, db) = (f64_(a), f64_(b));
+    Ok(match opcode {
+        0x46 => (x == y) as u64,
+        0x47 => (x != y) as u64,
+        0x48 => ((x as i32) < (y as i32)) as u64,
+        0x49 => (x < y) as u64,
+        0x4a => (x as i32 > y as i32) as u64,
+        0x4b => (x > y) as u64,
+        0x4c => (x as i32 <= y as i32) as u64,
+        0x4d => (x <= y) as u64,
+        0x4e => (x as i32 >= y as i32) as u64,
+        0x4f => (x >= y) as u64,
+        0x51 => (a == b) as u64,
+        0x52 => (a != b) as u64,
+        0x53 => ((a as i64) < (b as i64)) as u64,
+        0x54 => (a < b) as u64,
+        0x55 => (a as i64 > b as i64) as u64,
+        0x56 => (a > b) as u64,
+        0x57 => (a as i64 <= b as i64) as u64,
+        0x58 => (a <= b) as u64,
+        0x59 => (a as i64 >= b as i64) as u64,
+        0x5a => (a >= b) as u64,
+        0x5b => (fa == fb) as u64,
+        0x5c => (fa != fb) as u64,
+        0x5d => (fa < fb) as u64,
+        0x5e => (fa > fb) as u64,
+        0x5f => (fa <= fb) as u64,
+        0x60 => (fa >= fb) as u64,
+        0x61 => (da == db) as u64,
+        0x62 => (da != db) as u64,
+        0x63 => (da < db) as u64,
+        0x64 => (da > db) as u64,
+        0x65 => (da <= db) as u64,
+        0x66 => (da >= db) as u64,
+        0x6a => x.wrapping_add(y) as u64,
+        0x6b => x.wrapping_sub(y) as u64,
+        0x6c => x.wrapping_mul(y) as u64,
+        0x6d => match (x as i32).checked_div(y as i32) {
+            Some(q) => q as u32 as u64,
+            None if y == 0 => return Err(trap("integer divide by zero")),
+            None => return Err(trap("integer overflow")),
+        },
+        0x6e => x
+            .checked_div(y)
+            .ok_or_else(|| trap("integer divide by zero"))? as u64,
+        0x6f => match y {
+            0 => return Err(trap("integer divide by zero")),
+            _ => (x as i32).wrapping_rem(y as i32) as u32 as u64,
+        },
+        0x70 => x
+            .checked_rem(y)
+            .ok_or_else(|| trap("integer divide by zero"))? as u64,
+        0x71 => (x & y) as u64,
+        0x72 => (x | y) as u64,
+        0x73 => (x ^ y) as u64,
+        0x74 => x.wrapping_shl(y) as u64,
+        0x75 => (x as i32).wrapping_shr(y) as u32 as u64,
+        0x76 => x.wrapping_shr(y) as u64,
+        0x77 => x.rotate_left(y) as u64,
+        0x78 => x.rotate_right(y) as u64,
+        0x7c => a.wrapping_add(b),
+        0x7d => a.wrapping_sub(b),
+        0x7e => a.wrapping_mul(b),
+        0x7f => match (a as i64).checked_div(b as i64) {
+            Some(q) => q as u64,
+            None if b == 0 => return Err(trap("integer divide by zero")),
+            None => return Err(trap("integer overflow")),
+        },
+        0x80 => a
+            .checked_div(b)
+            .ok_or_else(|| trap("integer divide by zero"))?,
+        0x81 => match b {
+            0 => return Err(trap("integer divide by zero")),
+            _ => (a as i64).wrapping_rem(b as i64) as u64,
+        },
+        0x82 => a
+            .checked_rem(b)
+            .ok_or_else(|| trap("integer divide by zero"))?,
+        0x83 => a & b,
+        0x84 => a | b,
+        0x85 => a ^ b,
+        0x86 => a.wrapping_shl(b as u32),
+        0x87 => (a as i64).wrapping_shr(b as u32) as u64,
+        0x88 => a.wrapping_shr(b as u32),
+        0x89 => a.rotate_left((b % 64) as u32),
+        0x8a => a.rotate_right((b % 64) as u32),
+        0x92 => from_f32(fa + fb),
+        0x93 => from_f32(fa - fb),
+        0x94 => from_f32(fa * fb),
+        0x95 => from_f32(fa / fb),
+        0x96 => from_f32(min(fa as f64, fb as f64) as f32),
+        0x97 => from_f32(max(fa as f64, fb as f64) as f32),
+        0x98 => from_f32(fa.copysign(fb)),
+        0xa0 => from_f64(da + db),
+        0xa1 => from_f64(da - db),
+        0xa2 => from_f64(da * db),
+        0xa3 => from_f64(da / db),
+        0xa4 => from_f64(min(da, db)),
+        0xa5 => from_f64(max(da, db)),
+        0xa6 => from_f64(da.copysign(db)),
+        _ => return Err(trap("unknown instruction")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::decode::decode;
+
+    struct NoHost;
+
+    impl Host for NoHost {
+        fn call(&mut self, _: usize, _: &[u64], _: &mut Memory) -> Result<Option<u64>, Trap> {
+            Err(trap("no imports"))
+        }
+    }
+
+    #[test]
+    fn modules_trap_instead_of_failing_the_host() {
+        let section = |id: u8, bytes: &[u8]| [&[id, bytes.len() as u8][..], bytes].concat();
+        // `div` divides two i32s, `deep` calls itself forever.
+        let bytes = [
+            &b"\0asm\x01\0\0\0"[..],
+            &section(1, &[2, 0x60, 2, 0x7f, 0x7f, 1, 0x7f, 0x60, 0, 0]),
+            &section(3, &[2, 0, 1]),
+            &section(
+                7,
+                &[
+                    2, 3, b'd', b'i', b'v', 0, 0, 4, b'd', b'e', b'e', b'p', 0, 1,
+                ],
+            ),
+            &section(
+                10,
+                &[2, 7, 0, 0x20, 0, 0x20, 1, 0x6d, 0x0b, 4, 0, 0x10, 1, 0x0b],
+            ),
+        ]
+        .concat();
+        let program = decode(&bytes).unwrap();
+        let deadline = Instant::now() + std::time::Duration::from_secs(10);
+        let mut instance = Instance::new(&program, NoHost, 0, deadline);
+        instance.start().unwrap();
+        let div = instance.export("div").unwrap();
+        let i32 = |n: i32| n as u32 as u64;
+        assert_eq!(instance.invoke(div, &[i32(-7), i32(2)]).unwrap(), [i32(-3)]);
+        for (a, b, error) in [(7, 0, "divide by zero"), (i32::MIN, -1, "overflow")] {
+            let Err(Trap::Error(e)) = instance.invoke(div, &[i32(a), i32(b)]) else {
+                panic!("expected {} to trap", error);
+            };
+            assert!(e.to_string().contains(error));
+        }
+        let deep = instance.export("deep").unwrap();
+        assert!(matches!(instance.invoke(deep, &[]), Err(Trap::Error(_))));
+    }
+}
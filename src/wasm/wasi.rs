@@ -0,0 +1,660 @@
+//! The system interface modules run with, the functions of WASI preview 1
+//! (<https://github.com/WebAssembly/WASI/blob/main/legacy/preview1/docs.md>)
+//! and `graphite.fetch`, limited to what the permissions of a module grant.
+//!
+//! The input of a module is its request and its output is kept for the
+//! response. It has no environment and sees only the directories it may
+//! reach, under their own paths; paths leading out of them, also through
+//! symbolic links, are refused. Unless it may reach the network, `fetch`
+//! refuses every URL. Functions of WASI not listed here, such as sockets,
+//! fail with `ENOSYS`.
+
+use super::decode::Program;
+use super::interpreter::{Host, Memory, Trap};
+use super::{Permissions, MAX_OUTPUT};
+use anyhow::{bail, Context, Result};
+use ring::rand::SecureRandom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The error numbers of WASI.
+type Errno = u16;
+const ACCES: Errno = 2;
+const BADF: Errno = 8;
+const EXIST: Errno = 20;
+const FAULT: Errno = 21;
+const INVAL: Errno = 28;
+const IO: Errno = 29;
+const ISDIR: Errno = 31;
+const NOENT: Errno = 44;
+const NOSYS: Errno = 52;
+const NOTDIR: Errno = 54;
+const NOTEMPTY: Errno = 55;
+const SPIPE: Errno = 70;
+const NOTCAPABLE: Errno = 76;
+
+/// The functions provided and how many parameters they take.
+const FUNCTIONS: &[(&str, usize)] = &[
+    ("args_get", 2),
+    ("args_sizes_get", 2),
+    ("environ_get", 2),
+    ("environ_sizes_get", 2),
+    ("clock_res_get", 2),
+    ("clock_time_get", 3),
+    ("fd_close", 1),
+    ("fd_datasync", 1),
+    ("fd_fdstat_get", 2),
+    ("fd_filestat_get", 2),
+    ("fd_filestat_set_size", 2),
+    ("fd_prestat_get", 2),
+    ("fd_prestat_dir_name", 3),
+    ("fd_read", 4),
+    ("fd_readdir", 5),
+    ("fd_seek", 4),
+    ("fd_sync", 1),
+    ("fd_tell", 2),
+    ("fd_write", 4),
+    ("path_create_directory", 3),
+    ("path_filestat_get", 5),
+    ("path_open", 9),
+    ("path_remove_directory", 3),
+    ("path_rename", 6),
+    ("path_unlink_file", 3),
+    ("proc_exit", 1),
+    ("random_get", 2),
+    ("sched_yield", 0),
+];
+
+/// The file types of WASI.
+const CHARACTER_DEVICE: u8 = 2;
+const DIRECTORY: u8 = 3;
+const REGULAR_FILE: u8 = 4;
+
+enum Descriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    /// A directory, under the directory the module may reach, by the name
+    /// the module knows it if it is that directory.
+    Dir {
+        path: PathBuf,
+        root: PathBuf,
+        preopened: Option<String>,
+    },
+    File(File),
+}
+
+/// What an imported function is.
+#[derive(Clone, Copy)]
+enum Import {
+    Wasi(&'static str),
+    Fetch,
+    /// A function of WASI that isn't provided.
+    Missing,
+}
+
+pub(super) struct Wasi {
+    imports: Vec<Import>,
+    name: String,
+    input: Vec<u8>,
+    read: usize,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    descriptors: Vec<Option<Descriptor>>,
+    network: bool,
+    /// The last response fetched, so a module can fetch it again with room
+    /// for all of it.
+    fetched: Option<(String, Vec<u8>)>,
+    started: Instant,
+    deadline: Instant,
+}
+
+impl Wasi {
+    /// The system of the module `name` reading `input`, with what
+    /// `permissions` grant.
+    pub fn new(
+        program: &Program,
+        name: &str,
+        input: Vec<u8>,
+        permissions: &Permissions,
+        deadline: Instant,
+    ) -> Result<Wasi> {
+        let mut imports = Vec::new();
+        for import in &program.imports {
+            let ty = &program.types[import.ty as usize];
+            let (function, params, results) = match import.module.as_str() {
+                "wasi_snapshot_preview1" => {
+                    match FUNCTIONS.iter().find(|(name, _)| *name == import.name) {
+                        Some(&(name, params)) => {
+                            (Import::Wasi(name), params, (name != "proc_exit") as usize)
+                        }
+                        None => (Import::Missing, ty.params.len(), 1),
+                    }
+                }
+                "graphite" if import.name == "fetch" => (Import::Fetch, 4, 1),
+                _ => bail!(
+                    "The module imports {}.{}, which Graphite doesn't provide",
+                    import.module,
+                    import.name
+                ),
+            };
+            if ty.params.len() != params || ty.results.len() != results {
+                bail!(
+                    "The module imports {}.{} of the wrong type",
+                    import.module,
+                    import.name
+                );
+            }
+            imports.push(function);
+        }
+        let mut descriptors = vec![
+            Some(Descriptor::Stdin),
+            Some(Descriptor::Stdout),
+            Some(Descriptor::Stderr),
+        ];
+        for dir in &permissions.dirs {
+            let root = fs::canonicalize(dir)
+                .with_context(|| format!("Failed to open {}", dir.display()))?;
+            descriptors.push(Some(Descriptor::Dir {
+                path: root.clone(),
+                root,
+                preopened: Some(dir.to_string_lossy().into_owned()),
+            }));
+        }
+        Ok(Wasi {
+            imports,
+            name: name.to_string(),
+            input,
+            read: 0,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            descriptors,
+            network: permissions.network,
+            fetched: None,
+            started: Instant::now(),
+            deadline,
+        })
+    }
+
+    fn descriptor(&mut self, fd: u64) -> Result<&mut Descriptor, Errno> {
+        lookup(&mut self.descriptors, fd)
+    }
+
+    fn file(&mut self, fd: u64) -> Result<&mut File, Errno> {
+        match self.descriptor(fd)? {
+            Descriptor::File(file) => Ok(file),
+            Descriptor::Dir { .. } => Err(ISDIR),
+            _ => Err(SPIPE),
+        }
+    }
+
+    /// The host path of `path` in the directory `fd`, see [`confine`].
+    fn resolve(
+        &mut self,
+        fd: u64,
+        memory: &Memory,
+        path: u64,
+        length: u64,
+    ) -> Result<PathBuf, Errno> {
+        let path = memory.get(path as u32, length as u32).ok_or(FAULT)?;
+        let path = std::str::from_utf8(path).map_err(|_| INVAL)?;
+        let Descriptor::Dir {
+            path: base, root, ..
+        } = self.descriptor(fd)?
+        else {
+            return Err(NOTDIR);
+        };
+        confine(base, root, path)
+    }
+
+    fn call_wasi(&mut self, function: &str, a: &[u64], memory: &mut Memory) -> Result<(), Errno> {
+        match function {
+            "args_sizes_get" => {
+                write_u32(memory, a[0], 1)?;
+                write_u32(memory, a[1], self.name.len() as u32 + 1)
+            }
+            "args_get" => {
+                write_u32(memory, a[0], a[1] as u32)?;
+                let mut name = self.name.clone().into_bytes();
+                name.push(0);
+                write(memory, a[1], &name)
+            }
+            "environ_sizes_get" => {
+                write_u32(memory, a[0], 0)?;
+                write_u32(memory, a[1], 0)
+            }
+            "environ_get" => Ok(()),
+            "clock_res_get" => write_u64(memory, a[1], 1000),
+            "clock_time_get" => {
+                let time = match a[0] as u32 {
+                    0 => SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default(),
+                    _ => self.started.elapsed(),
+                };
+                write_u64(memory, a[2], time.as_nanos() as u64)
+            }
+            "fd_close" => {
+                self.descriptor(a[0])?;
+                self.descriptors[a[0] as u32 as usize] = None;
+                Ok(())
+            }
+            "fd_sync" | "fd_datasync" => match self.descriptor(a[0])? {
+                Descriptor::File(file) => file.sync_all().map_err(errno),
+                _ => Ok(()),
+            },
+            "fd_fdstat_get" => {
+                let filetype = match self.descriptor(a[0])? {
+                    Descriptor::Dir { .. } => DIRECTORY,
+                    Descriptor::File(_) => REGULAR_FILE,
+                    _ => CHARACTER_DEVICE,
+                };
+                let mut fdstat = [0; 24];
+                fdstat[0] = filetype;
+                fdstat[8..24].fill(0xff);
+                write(memory, a[1], &fdstat)
+            }
+            "fd_filestat_get" => {
+                let metadata = match self.descriptor(a[0])? {
+                    Descriptor::Dir { path, .. } => Some(fs::metadata(path).map_err(errno)?),
+                    Descriptor::File(file) => Some(file.metadata().map_err(errno)?),
+                    _ => None,
+                };
+                write(memory, a[1], &filestat(metadata.as_ref()))
+            }
+            "fd_filestat_set_size" => self.file(a[0])?.set_len(a[1]).map_err(errno),
+            "fd_prestat_get" => match self.descriptor(a[0])? {
+                Descriptor::Dir {
+                    preopened: Some(name),
+                    ..
+                } => {
+                    let mut prestat = [0; 8];
+                    prestat[4..].copy_from_slice(&(name.len() as u32).to_le_bytes());
+                    write(memory, a[1], &prestat)
+                }
+                _ => Err(BADF),
+            },
+            "fd_prestat_dir_name" => match self.descriptor(a[0])? {
+                Descriptor::Dir {
+                    preopened: Some(name),
+                    ..
+                } => {
+                    let name = name.as_bytes();
+                    write(memory, a[1], &name[..name.len().min(a[2] as u32 as usize)])
+                }
+                _ => Err(BADF),
+            },
+            "fd_read" => {
+                let mut total = 0;
+                for (buffer, length) in iovecs(memory, a[1], a[2])? {
+                    let target = memory.get_mut(buffer, length).ok_or(FAULT)?;
+                    let read = match lookup(&mut self.descriptors, a[0])? {
+                        Descriptor::Stdin => {
+                            let rest = &self.input[self.read..];
+                            let n = rest.len().min(target.len());
+                            target[..n].copy_from_slice(&rest[..n]);
+                            self.read += n;
+                            n
+                        }
+                        Descriptor::File(file) => file.read(target).map_err(errno)?,
+                        Descriptor::Dir { .. } => return Err(ISDIR),
+                        _ => return Err(BADF),
+                    };
+                    total += read;
+                    if read < length as usize {
+                        break;
+                    }
+                }
+                write_u32(memory, a[3], total as u32)
+            }
+            "fd_write" => {
+                let mut total = 0;
+                for (buffer, length) in iovecs(memory, a[1], a[2])? {
+                    let bytes = memory.get(buffer, length).ok_or(FAULT)?;
+                    match lookup(&mut self.descriptors, a[0])? {
+                        Descriptor::Stdout => append(&mut self.stdout, bytes)?,
+                        Descriptor::Stderr => append(&mut self.stderr, bytes)?,
+                        Descriptor::File(file) => file.write_all(bytes).map_err(errno)?,
+                        Descriptor::Dir { .. } => return Err(ISDIR),
+                        Descriptor::Stdin => return Err(BADF),
+                    }
+                    total += bytes.len();
+                }
+                write_u32(memory, a[3], total as u32)
+            }
+            "fd_seek" => {
+                let position = match a[2] as u8 {
+                    0 => SeekFrom::Start(a[1]),
+                    1 => SeekFrom::Current(a[1] as i64),
+                    2 => SeekFrom::End(a[1] as i64),
+                    _ => return Err(INVAL),
+                };
+                let offset = self.file(a[0])?.seek(position).map_err(errno)?;
+                write_u64(memory, a[3], offset)
+            }
+            "fd_tell" => {
+                let offset = self.file(a[0])?.stream_position().map_err(errno)?;
+                write_u64(memory, a[1], offset)
+            }
+            "fd_readdir" => {
+                let Descriptor::Dir { path, .. } = self.descriptor(a[0])? else {
+                    return Err(NOTDIR);
+                };
+                let mut names = fs::read_dir(path)
+                    .map_err(errno)?
+                    .map(|entry| {
+                        let entry = entry.map_err(errno)?;
+                        let filetype = match entry.file_type().map_err(errno)? {
+                            t if t.is_dir() => DIRECTORY,
+                            t if t.is_file() => REGULAR_FILE,
+                            _ => 0,
+                        };
+                        Ok((entry.file_name().to_string_lossy().into_owned(), filetype))
+                    })
+                    .collect::<Result<Vec<_>, Errno>>()?;
+                names.sort();
+                // Entries as far as they fit, the last one cut off when it
+                // doesn't, which tells the module to read again.
+                let mut entries = Vec::new();
+                for (i, (name, filetype)) in names.iter().enumerate().skip(a[3] as usize) {
+                    entries.extend_from_slice(&(i as u64 + 1).to_le_bytes());
+                    entries.extend_from_slice(&0u64.to_le_bytes());
+                    entries.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                    entries.extend_from_slice(&[*filetype, 0, 0, 0]);
+                    entries.extend_from_slice(name.as_bytes());
+                    if entries.len() >= a[2] as u32 as usize {
+                        break;
+                    }
+                }
+                entries.truncate(a[2] as u32 as usize);
+                write(memory, a[1], &entries)?;
+                write_u32(memory, a[4], entries.len() as u32)
+            }
+            "path_create_directory" => {
+                let path = self.resolve(a[0], memory, a[1], a[2])?;
+                fs::create_dir(path).map_err(errno)
+            }
+            "path_remove_directory" => {
+                let path = self.resolve(a[0], memory, a[1], a[2])?;
+                fs::remove_dir(path).map_err(errno)
+            }
+            "path_unlink_file" => {
+                let path = self.resolve(a[0], memory, a[1], a[2])?;
+                fs::remove_file(path).map_err(errno)
+            }
+            "path_rename" => {
+                let from = self.resolve(a[0], memory, a[1], a[2])?;
+                let to = self.resolve(a[3], memory, a[4], a[5])?;
+                fs::rename(from, to).map_err(errno)
+            }
+            "path_filestat_get" => {
+                let path = self.resolve(a[0], memory, a[2], a[3])?;
+                let metadata = fs::metadata(path).map_err(errno)?;
+                write(memory, a[4], &filestat(Some(&metadata)))
+            }
+            "path_open" => {
+                let path = self.resolve(a[0], memory, a[2], a[3])?;
+                let descriptor = open(&path, a[4] as u16, a[5], a[7] as u16)?;
+                let descriptor = match descriptor {
+                    Some(file) => Descriptor::File(file),
+                    None => {
+                        let Descriptor::Dir { root, .. } = self.descriptor(a[0])? else {
+                            return Err(NOTDIR);
+                        };
+                        Descriptor::Dir {
+                            path,
+                            root: root.clone(),
+                            preopened: None,
+                        }
+                    }
+                };
+                let fd = match self.descriptors.iter().position(Option::is_none) {
+                    Some(fd) => {
+                        self.descriptors[fd] = Some(descriptor);
+                        fd
+                    }
+                    None => {
+                        self.descriptors.push(Some(descriptor));
+                        self.descriptors.len() - 1
+                    }
+                };
+                write_u32(memory, a[8], fd as u32)
+            }
+            "random_get" => {
+                let buffer = memory.get_mut(a[0] as u32, a[1] as u32).ok_or(FAULT)?;
+                ring::rand::SystemRandom::new().fill(buffer).map_err(|_| IO)
+            }
+            "sched_yield" => Ok(()),
+            _ => Err(NOSYS),
+        }
+    }
+
+    /// Fetches the URL at `url`, copying what fits of the body into
+    /// `buffer`, and gives the length of all of it.
+    fn fetch(&mut self, a: &[u64], memory: &mut Memory) -> Result<u64, Errno> {
+        if !self.network {
+            return Err(NOTCAPABLE);
+        }
+        let url = memory.get(a[0] as u32, a[1] as u32).ok_or(FAULT)?;
+        let url = String::from_utf8(url.to_vec()).map_err(|_| INVAL)?;
+        if self.fetched.as_ref().map(|(u, _)| u) != Some(&url) {
+            let body = get(&url, self.deadline).map_err(|error| {
+                let _ = append(&mut self.stderr, format!("{:#}\n", error).as_bytes());
+                IO
+            })?;
+            self.fetched = Some((url, body));
+        }
+        let (_, body) = self.fetched.as_ref().expect("the body was fetched");
+        let length = body.len().min(a[3] as u32 as usize);
+        write(memory, a[2], &body[..length])?;
+        Ok(body.len() as u64)
+    }
+}
+
+impl Host for Wasi {
+    fn call(
+        &mut self,
+        import: usize,
+        args: &[u64],
+        memory: &mut Memory,
+    ) -> Result<Option<u64>, Trap> {
+        let result = match self.imports[import] {
+            Import::Wasi("proc_exit") => return Err(Trap::Exit(args[0] as u32)),
+            Import::Wasi(function) => self.call_wasi(function, args, memory),
+            Import::Fetch => {
+                // The length of the body, or the negated error.
+                let result = self.fetch(args, memory);
+                return Ok(Some(result.unwrap_or_else(|e| -i64::from(e) as u64)));
+            }
+            Import::Missing => Err(NOSYS),
+        };
+        Ok(Some(result.err().unwrap_or(0) as u64))
+    }
+}
+
+/// Opens the file at `path`, or gives `None` for a directory.
+/// The host path of `path` in the directory `base`, if it doesn't lead out
+/// of `root`, the directory the module may reach.
+fn confine(base: &Path, root: &Path, path: &str) -> Result<PathBuf, Errno> {
+    let mut resolved = base.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::CurDir => {}
+            Component::ParentDir if resolved != root => {
+                resolved.pop();
+            }
+            _ => return Err(NOTCAPABLE),
+        }
+    }
+    // Symbolic links may not lead out either, checked on the part of the
+    // path that exists.
+    let mut existing = resolved.as_path();
+    while existing.symlink_metadata().is_err() {
+        existing = existing.parent().ok_or(NOTCAPABLE)?;
+    }
+    let real = existing.canonicalize().map_err(errno)?;
+    if !real.starts_with(root) {
+        return Err(NOTCAPABLE);
+    }
+    Ok(resolved)
+}
+
+fn open(path: &Path, flags: u16, rights: u64, fdflags: u16) -> Result<Option<File>, Errno> {
+    const CREATE: u16 = 1;
+    const DIRECTORY_ONLY: u16 = 2;
+    const EXCLUSIVE: u16 = 4;
+    const TRUNCATE: u16 = 8;
+    const APPEND: u16 = 1;
+    const READ: u64 = 1 << 1;
+    const WRITE: u64 = 1 << 6;
+    if path.is_dir() {
+        return match flags & (CREATE | EXCLUSIVE) {
+            0 => Ok(None),
+            _ => Err(EXIST),
+        };
+    }
+    if flags & DIRECTORY_ONLY != 0 {
+        return Err(if path.exists() { NOTDIR } else { NOENT });
+    }
+    let append = fdflags & APPEND != 0;
+    let write = rights & WRITE != 0 || flags & (CREATE | TRUNCATE) != 0;
+    let file = OpenOptions::new()
+        .read(rights & READ != 0 || !write)
+        .write(write && !append)
+        .append(append)
+        .create(flags & CREATE != 0)
+        .create_new(flags & EXCLUSIVE != 0)
+        .truncate(flags & TRUNCATE != 0)
+        .open(path)
+        .map_err(errno)?;
+    Ok(Some(file))
+}
+
+/// Gets `url`, giving up at `deadline`.
+fn get(url: &str, deadline: Instant) -> Result<Vec<u8>> {
+    let response = ureq::get(url)
+        .timeout(deadline.saturating_duration_since(Instant::now()))
+        .call()
+        .with_context(|| format!("Failed to fetch {}", url))?;
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_OUTPUT)
+        .read_to_end(&mut body)
+        .with_context(|| format!("Failed to read {}", url))?;
+    Ok(body)
+}
+
+/// Adds `bytes` to an output, up to [`MAX_OUTPUT`].
+fn append(output: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Errno> {
+    if (output.len() + bytes.len()) as u64 > MAX_OUTPUT {
+        return Err(IO);
+    }
+    output.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// The buffers of a list of `count` I/O vectors at `address`.
+fn iovecs(memory: &Memory, address: u64, count: u64) -> Result<Vec<(u32, u32)>, Errno> {
+    let bytes = memory
+        .get(address as u32, (count as u32).checked_mul(8).ok_or(FAULT)?)
+        .ok_or(FAULT)?;
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|v| {
+            let field = |i: usize| u32::from_le_bytes(v[i..i + 4].try_into().expect("4 bytes"));
+            (field(0), field(4))
+        })
+        .collect())
+}
+
+fn filestat(metadata: Option<&fs::Metadata>) -> [u8; 64] {
+    let mut filestat = [0; 64];
+    let Some(metadata) = metadata else {
+        filestat[16] = CHARACTER_DEVICE;
+        return filestat;
+    };
+    filestat[16] = if metadata.is_dir() {
+        DIRECTORY
+    } else {
+        REGULAR_FILE
+    };
+    let nanos = |time: io::Result<SystemTime>| {
+        time.ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as u64)
+    };
+    let fields = [
+        1,
+        metadata.len(),
+        nanos(metadata.accessed()),
+        nanos(metadata.modified()),
+        nanos(metadata.modified()),
+    ];
+    for (i, field) in fields.into_iter().enumerate() {
+        filestat[24 + i * 8..32 + i * 8].copy_from_slice(&field.to_le_bytes());
+    }
+    filestat
+}
+
+fn lookup(descriptors: &mut [Option<Descriptor>], fd: u64) -> Result<&mut Descriptor, Errno> {
+    descriptors
+        .get_mut(fd as u32 as usize)
+        .and_then(Option::as_mut)
+        .ok_or(BADF)
+}
+
+fn write(memory: &mut Memory, address: u64, bytes: &[u8]) -> Result<(), Errno> {
+    memory.write(address as u32, bytes).ok_or(FAULT)
+}
+
+fn write_u32(memory: &mut Memory, address: u64, value: u32) -> Result<(), Errno> {
+    write(memory, address, &value.to_le_bytes())
+}
+
+fn write_u64(memory: &mut Memory, address: u64, value: u64) -> Result<(), Errno> {
+    write(memory, address, &value.to_le_bytes())
+}
+
+fn errno(error: io::Error) -> Errno {
+    match error.kind() {
+        io::ErrorKind::NotFound => NOENT,
+        io::ErrorKind::PermissionDenied => ACCES,
+        io::ErrorKind::AlreadyExists => EXIST,
+        io::ErrorKind::NotADirectory => NOTDIR,
+        io::ErrorKind::IsADirectory => ISDIR,
+        io::ErrorKind::DirectoryNotEmpty => NOTEMPTY,
+        io::ErrorKind::InvalidInput => INVAL,
+        _ => IO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_stay_in_the_directories_granted() {
+        let dir = std::env::temp_dir().join(format!("wasi-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("granted/notes")).unwrap();
+        let root = fs::canonicalize(dir.join("granted")).unwrap();
+        let notes = root.join("notes");
+        assert_eq!(confine(&notes, &root, "../a.txt"), Ok(root.join("a.txt")));
+        assert_eq!(
+            confine(&root, &root, "notes/./new/b.txt"),
+            Ok(notes.join("new/b.txt"))
+        );
+        assert_eq!(confine(&root, &root, ".."), Err(NOTCAPABLE));
+        assert_eq!(confine(&notes, &root, "../../x"), Err(NOTCAPABLE));
+        assert_eq!(confine(&root, &root, "/etc/passwd"), Err(NOTCAPABLE));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, root.join("out")).unwrap();
+            assert_eq!(confine(&root, &root, "out/secret"), Err(NOTCAPABLE));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}